@@ -0,0 +1,235 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Measures the performance of routing messages between programs.
+//!
+//! Two scenarios are measured:
+//!
+//! - A native emitter sends messages to a WASM process that echoes them back. Each round-trip is
+//! timed individually, which gives latency percentiles.
+//! - A WASM process sends messages to a native program that echoes them back. Only the total
+//! throughput is measured.
+//!
+//! Run with `cargo bench`.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::scheduler::{Core, CoreRunOutcome};
+use redshirt_core::system::{SystemBuilder, SystemRunOutcome};
+use redshirt_core::{Encode as _, EncodedMessage, InterfaceHash, MessageId, Module, Pid};
+use std::{
+    sync::atomic,
+    time::{Duration, Instant},
+};
+
+/// Number of round-trips to perform for each scenario.
+const NUM_MESSAGES: usize = 10_000;
+
+/// Interface the echo programs register. Must match the data segment of
+/// [`WASM_EMITTER`].
+const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([b'B'; 32]);
+
+/// WASM program that answers every interface message it receives with an empty message.
+///
+/// The `MessageId` is read straight from the encoded `Message::Interface`, where it is located
+/// 34 bytes after the start of the message.
+const WASM_ECHO: &str = r#"(module
+    (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+    (import "redshirt" "emit_answer" (func $emit_answer (param i32 i32 i32)))
+    (memory (export "memory") 1)
+    (func $_start (result i32)
+        (loop $next
+            (i64.store (i32.const 0) (i64.const 1))
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 64) (i32.const 1024) (i32.const 1)))
+            (call $emit_answer (i32.const 98) (i32.const 0) (i32.const 0))
+            (br $next))
+        (i32.const 0))
+    (export "_start" (func $_start)))
+"#;
+
+/// WASM program that emits `NUM_MESSAGES` messages on `INTERFACE`, waiting for the answer to
+/// each of them before emitting the next one.
+const WASM_EMITTER: &str = r#"(module
+    (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 0) "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB")
+    (data (i32.const 32) "\40\00\00\00\04\00\00\00")
+    (data (i32.const 64) "ping")
+    (func $_start (result i32)
+        (local $remaining i32)
+        (local.set $remaining (i32.const 10000))
+        (loop $next
+            (drop (call $emit_message (i32.const 0) (i32.const 32) (i32.const 1) (i32.const 1) (i32.const 1) (i32.const 72)))
+            (i64.store (i32.const 80) (i64.load (i32.const 72)))
+            (drop (call $next_message (i32.const 80) (i32.const 1) (i32.const 128) (i32.const 1024) (i32.const 1)))
+            (local.set $remaining (i32.sub (local.get $remaining) (i32.const 1)))
+            (br_if $next (local.get $remaining)))
+        (i32.const 0))
+    (export "_start" (func $_start)))
+"#;
+
+fn main() {
+    wasm_echo();
+    native_echo();
+}
+
+/// Native emitter, WASM echo.
+fn wasm_echo() {
+    let module = Module::from_bytes(&wat::parse_str(WASM_ECHO).unwrap()).unwrap();
+
+    let mut builder = Core::new();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    let echo = core.execute(&module).unwrap().pid();
+    core.set_interface_handler(INTERFACE, echo).unwrap();
+
+    let mut latencies = Vec::with_capacity(NUM_MESSAGES);
+    let total_start = Instant::now();
+
+    for _ in 0..NUM_MESSAGES {
+        let start = Instant::now();
+        let expected = core.emit_interface_message_answer(
+            emitter,
+            INTERFACE,
+            EncodedMessage(b"ping".to_vec()),
+        );
+        loop {
+            match core.run() {
+                CoreRunOutcome::MessageResponse { message_id, .. } if message_id == expected => {
+                    break
+                }
+                CoreRunOutcome::Idle => panic!("echo process stopped answering"),
+                _ => {}
+            }
+        }
+        latencies.push(start.elapsed());
+    }
+
+    report(
+        "native -> wasm echo",
+        total_start.elapsed(),
+        Some(latencies),
+    );
+}
+
+/// WASM emitter, native echo.
+fn native_echo() {
+    let module = Module::from_bytes(&wat::parse_str(WASM_EMITTER).unwrap()).unwrap();
+
+    let mut system = SystemBuilder::new()
+        .with_native_program(NativeEcho::new())
+        .with_startup_process(module)
+        .build();
+
+    let total_start = Instant::now();
+    loop {
+        match futures::executor::block_on(system.run()) {
+            SystemRunOutcome::ProgramFinished { outcome, .. } => {
+                outcome.unwrap();
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    report("wasm -> native echo", total_start.elapsed(), None);
+}
+
+/// Prints the results of a scenario.
+fn report(name: &str, total: Duration, latencies: Option<Vec<Duration>>) {
+    let per_sec = NUM_MESSAGES as f64 / total.as_secs_f64();
+    println!(
+        "{}: {} round-trips in {:?} ({:.0} msg/s)",
+        name, NUM_MESSAGES, total, per_sec
+    );
+
+    if let Some(mut latencies) = latencies {
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        println!(
+            "{}: latency p50 = {:?}, p90 = {:?}, p99 = {:?}, max = {:?}",
+            name,
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            percentile(100)
+        );
+    }
+}
+
+/// Native program that answers every message on `INTERFACE` with an empty message.
+struct NativeEcho {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Sending side of `to_answer`.
+    to_answer_tx: mpsc::UnboundedSender<MessageId>,
+    /// Messages that must be answered.
+    to_answer: Mutex<mpsc::UnboundedReceiver<MessageId>>,
+}
+
+impl NativeEcho {
+    fn new() -> Self {
+        let (to_answer_tx, to_answer) = mpsc::unbounded();
+        NativeEcho {
+            registered: atomic::AtomicBool::new(false),
+            to_answer_tx,
+            to_answer: Mutex::new(to_answer),
+        }
+    }
+}
+
+impl NativeProgram for NativeEcho {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let message_id = self.to_answer.lock().await.next().await.unwrap();
+            NativeProgramEvent::Answer {
+                message_id,
+                answer: Ok(EncodedMessage(Vec::new())),
+            }
+        })
+    }
+
+    fn interface_message(
+        &self,
+        _: InterfaceHash,
+        message_id: Option<MessageId>,
+        _: Pid,
+        _: EncodedMessage,
+    ) {
+        if let Some(message_id) = message_id {
+            self.to_answer_tx.unbounded_send(message_id).unwrap();
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {}
+}
@@ -31,7 +31,7 @@
 //! constructed.
 //!
 //! - *Native* programs. They are directly written in Rust. In order to start a native program,
-//! you must pass an object that implements the [`NativeProgramRef`](native::NativeProgramRef)
+//! you must pass an object that implements the [`NativeProgram`](native::NativeProgram)
 //! trait to [`SystemBuilder::with_native_program`] when building the [`System`].
 //!
 //! Each program within a [`System`] gets attributed a single [`Pid`] that identifies it.
@@ -61,6 +61,8 @@
 //! the registered program will be in charge of treating the message.
 //! - `threads`. The interface named `threads` provides a few utilities related to multithreading
 //! (TODO: this isn't really done yet)
+//! - `metrics`. The interface named `metrics` lets programs query counters about the system,
+//! such as the number of processes or of messages routed.
 //!
 //! > **Note**: A very common workflow for a program is, immediately after it starts, to emit a
 //! >           message on the `interface` interface in order to register itself as the handler of
@@ -119,7 +121,7 @@
 extern crate alloc;
 
 pub use self::module::Module;
-pub use self::system::{System, SystemBuilder, SystemRunOutcome};
+pub use self::system::{PowerAction, StartupProgram, System, SystemBuilder, SystemRunOutcome};
 pub use redshirt_syscalls_interface::{
     Decode, Encode, EncodedMessage, InterfaceHash, MessageId, Pid, ThreadId,
 };
@@ -14,7 +14,16 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use core::fmt;
-use sha2::Digest as _;
+
+pub use self::hash::{HashAlgorithm, ModuleHash, ParseHashError};
+pub use self::limits::{LimitExceeded, ModuleLimits};
+pub use self::metadata::ModuleMetadata;
+
+pub mod metadata;
+
+mod hash;
+mod limits;
+mod sections;
 
 /// Represents a successfully-parsed binary.
 ///
@@ -23,27 +32,64 @@ use sha2::Digest as _;
 pub struct Module {
     inner: wasmi::Module,
     hash: ModuleHash,
+    metadata: Option<ModuleMetadata>,
 }
 
-/// Hash of a module.
-#[derive(Clone, PartialEq, Eq, Hash)]
-pub struct ModuleHash([u8; 32]);
-
 /// Error that can happen when calling `from_bytes`.
 #[derive(Debug)]
-pub struct FromBytesError {}
+pub enum FromBytesError {
+    /// The module isn't a valid WASM binary.
+    Invalid,
+    /// One of the [`ModuleLimits`] has been exceeded.
+    LimitExceeded(LimitExceeded),
+    /// The `redshirt-meta` custom section is invalid.
+    InvalidMetadata,
+}
 
 impl Module {
-    /// Parses a module from WASM bytes.
+    /// Parses a module from WASM bytes, enforcing the default [`ModuleLimits`].
+    ///
+    /// Returns an error if the module contains a `redshirt-meta` custom section whose content
+    /// is invalid.
     pub fn from_bytes(buffer: impl AsRef<[u8]>) -> Result<Self, FromBytesError> {
-        let inner = wasmi::Module::from_buffer(buffer.as_ref()).map_err(|_| FromBytesError {})?;
+        Self::from_bytes_with_limits(buffer, &ModuleLimits::default())
+    }
+
+    /// Parses a module from WASM bytes, enforcing the given limits.
+    ///
+    /// The limits are checked before the module is parsed by the interpreter. Memories and
+    /// tables that don't declare a maximum size are given one equal to the corresponding limit.
+    /// The hash of the module is calculated over the original bytes.
+    pub fn from_bytes_with_limits(
+        buffer: impl AsRef<[u8]>,
+        limits: &ModuleLimits,
+    ) -> Result<Self, FromBytesError> {
+        let capped = limits
+            .apply(buffer.as_ref())
+            .map_err(|()| FromBytesError::Invalid)?
+            .map_err(FromBytesError::LimitExceeded)?;
+        let inner = wasmi::Module::from_buffer(&capped).map_err(|_| FromBytesError::Invalid)?;
+        let metadata =
+            metadata::from_wasm(buffer.as_ref()).map_err(|()| FromBytesError::InvalidMetadata)?;
         let hash = ModuleHash::from_bytes(buffer);
 
-        Ok(Module { inner, hash })
+        Ok(Module {
+            inner,
+            hash,
+            metadata,
+        })
     }
 
     /// Turns some WASM text source into a `Module`.
-    #[cfg(test)] // TODO: is `#[cfg(test)]` a good idea?
+    ///
+    /// Only available if the `wat` feature is enabled. Intended for tests, in order to be able
+    /// to embed small readable programs.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the WASM text is valid but the resulting binary isn't accepted by
+    /// [`Module::from_bytes`].
+    #[cfg(any(test, feature = "wat"))]
     pub fn from_wat(source: impl AsRef<[u8]>) -> Result<Self, wat::Error> {
         let wasm = wat::parse_bytes(source.as_ref())?;
         Ok(Self::from_bytes(wasm).unwrap())
@@ -60,36 +106,30 @@ impl Module {
     pub fn hash(&self) -> &ModuleHash {
         &self.hash
     }
-}
 
-impl From<[u8; 32]> for ModuleHash {
-    fn from(hash: [u8; 32]) -> ModuleHash {
-        ModuleHash(hash)
+    /// Returns the metadata found in the `redshirt-meta` custom section of the module, if any.
+    pub fn metadata(&self) -> Option<&ModuleMetadata> {
+        self.metadata.as_ref()
     }
 }
 
 impl fmt::Debug for Module {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Module({})", bs58::encode(&self.hash.0).into_string())
-    }
-}
-
-impl ModuleHash {
-    /// Returns the hash of the given bytes.
-    pub fn from_bytes(buffer: impl AsRef<[u8]>) -> Self {
-        ModuleHash(sha2::Sha256::digest(buffer.as_ref()).into())
-    }
-}
-
-impl fmt::Debug for ModuleHash {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ModuleHash({})", bs58::encode(&self.0).into_string())
+        if let Some(metadata) = &self.metadata {
+            write!(f, "Module({}, {:?})", self.hash, metadata.name)
+        } else {
+            write!(f, "Module({})", self.hash)
+        }
     }
 }
 
 impl fmt::Display for FromBytesError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "FromBytesError")
+        match self {
+            FromBytesError::Invalid => write!(f, "Invalid WASM module"),
+            FromBytesError::LimitExceeded(limit) => write!(f, "{}", limit),
+            FromBytesError::InvalidMetadata => write!(f, "Invalid redshirt-meta section"),
+        }
     }
 }
 
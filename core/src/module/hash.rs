@@ -0,0 +1,259 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Identity of modules.
+//!
+//! Modules are content-addressed: a module is designated by the hash of its binary
+//! representation. In order to be able to switch to a different hashing algorithm in the
+//! future, hashes are represented using the [multihash](https://multiformats.io/multihash/)
+//! format, where the digest is prefixed with the code of the algorithm and the length of the
+//! digest.
+//!
+//! The textual representation of a [`ModuleHash`] is the base58 encoding of the multihash.
+
+use alloc::vec::Vec;
+use core::{fmt, hash, str::FromStr};
+use sha2::Digest as _;
+
+/// Hash of a module.
+#[derive(Clone)]
+pub struct ModuleHash {
+    algorithm: HashAlgorithm,
+    digest: [u8; 32],
+}
+
+/// Hashing algorithm used to build a [`ModuleHash`].
+// TODO: add blake3 (multihash code 0x1e)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// SHA2 with a 256 bits output. Multihash code `0x12`.
+    Sha2_256,
+}
+
+/// Error that can happen when parsing a [`ModuleHash`].
+#[derive(Debug)]
+pub enum ParseHashError {
+    /// The string isn't valid base58.
+    Base58,
+    /// The multihash prefix is malformed.
+    InvalidPrefix,
+    /// The algorithm code is unknown or not supported.
+    UnknownAlgorithm(u64),
+    /// The length of the digest doesn't match the algorithm.
+    BadLength,
+}
+
+impl HashAlgorithm {
+    /// Returns the multihash code of this algorithm.
+    pub fn multihash_code(&self) -> u64 {
+        match self {
+            HashAlgorithm::Sha2_256 => 0x12,
+        }
+    }
+
+    /// Returns the algorithm corresponding to the given multihash code, if supported.
+    pub fn from_multihash_code(code: u64) -> Option<Self> {
+        match code {
+            0x12 => Some(HashAlgorithm::Sha2_256),
+            _ => None,
+        }
+    }
+}
+
+impl ModuleHash {
+    /// Returns the hash of the given bytes, using the default algorithm.
+    pub fn from_bytes(buffer: impl AsRef<[u8]>) -> Self {
+        ModuleHash {
+            algorithm: HashAlgorithm::Sha2_256,
+            digest: sha2::Sha256::digest(buffer.as_ref()).into(),
+        }
+    }
+
+    /// Returns the algorithm that was used to build this hash.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the raw digest, without the multihash prefix.
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    /// Returns true if `buffer` hashes to this value.
+    pub fn matches(&self, buffer: impl AsRef<[u8]>) -> bool {
+        match self.algorithm {
+            HashAlgorithm::Sha2_256 => *self == ModuleHash::from_bytes(buffer),
+        }
+    }
+
+    /// Decodes a hash from its multihash binary representation.
+    pub fn from_multihash(bytes: &[u8]) -> Result<Self, ParseHashError> {
+        let mut cursor = bytes;
+        let code = read_varint(&mut cursor).ok_or(ParseHashError::InvalidPrefix)?;
+        let len = read_varint(&mut cursor).ok_or(ParseHashError::InvalidPrefix)?;
+        let algorithm = HashAlgorithm::from_multihash_code(code)
+            .ok_or(ParseHashError::UnknownAlgorithm(code))?;
+
+        if len != 32 || cursor.len() != 32 {
+            return Err(ParseHashError::BadLength);
+        }
+
+        let mut digest = [0; 32];
+        digest.copy_from_slice(cursor);
+        Ok(ModuleHash { algorithm, digest })
+    }
+
+    /// Encodes the hash into its multihash binary representation.
+    pub fn to_multihash(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(34);
+        write_varint(&mut out, self.algorithm.multihash_code());
+        write_varint(&mut out, self.digest.len() as u64);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+}
+
+/// Builds a SHA2-256 hash from its raw digest.
+impl From<[u8; 32]> for ModuleHash {
+    fn from(digest: [u8; 32]) -> ModuleHash {
+        ModuleHash {
+            algorithm: HashAlgorithm::Sha2_256,
+            digest,
+        }
+    }
+}
+
+impl PartialEq for ModuleHash {
+    fn eq(&self, other: &ModuleHash) -> bool {
+        // Constant-time comparison of the digests, in order to not leak timing information.
+        let diff = self
+            .digest
+            .iter()
+            .zip(other.digest.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+        (self.algorithm == other.algorithm) & (diff == 0)
+    }
+}
+
+impl Eq for ModuleHash {}
+
+impl hash::Hash for ModuleHash {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        hash::Hash::hash(&self.algorithm, state);
+        hash::Hash::hash(&self.digest, state);
+    }
+}
+
+impl FromStr for ModuleHash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|_| ParseHashError::Base58)?;
+        ModuleHash::from_multihash(&bytes)
+    }
+}
+
+impl fmt::Debug for ModuleHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ModuleHash({})", self)
+    }
+}
+
+impl fmt::Display for ModuleHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", bs58::encode(self.to_multihash()).into_string())
+    }
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseHashError::Base58 => write!(f, "Invalid base58 encoding"),
+            ParseHashError::InvalidPrefix => write!(f, "Invalid multihash prefix"),
+            ParseHashError::UnknownAlgorithm(code) => {
+                write!(f, "Unknown hash algorithm: 0x{:x}", code)
+            }
+            ParseHashError::BadLength => write!(f, "Bad digest length"),
+        }
+    }
+}
+
+/// Reads an unsigned varint, as defined by the multiformats specifications.
+fn read_varint(buffer: &mut &[u8]) -> Option<u64> {
+    let mut result: u64 = 0;
+    for n in 0..9 {
+        let byte = *buffer.get(n)?;
+        result |= u64::from(byte & 0x7f) << (7 * n);
+        if byte & 0x80 == 0 {
+            *buffer = &buffer[n + 1..];
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// Writes an unsigned varint, as defined by the multiformats specifications.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashAlgorithm, ModuleHash, ParseHashError};
+    use alloc::string::ToString as _;
+
+    #[test]
+    fn multihash_prefix() {
+        let hash = ModuleHash::from_bytes(b"hello world");
+        let multihash = hash.to_multihash();
+        assert_eq!(&multihash[..2], &[0x12, 0x20]);
+        assert_eq!(&multihash[2..], &hash.digest()[..]);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let hash = ModuleHash::from_bytes(b"hello world");
+        let parsed: ModuleHash = hash.to_string().parse().unwrap();
+        assert_eq!(parsed, hash);
+        assert_eq!(parsed.algorithm(), HashAlgorithm::Sha2_256);
+    }
+
+    #[test]
+    fn unknown_algorithm() {
+        let mut multihash = ModuleHash::from_bytes(b"hello world").to_multihash();
+        multihash[0] = 0x1e;
+        match ModuleHash::from_multihash(&multihash) {
+            Err(ParseHashError::UnknownAlgorithm(0x1e)) => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn matches() {
+        let hash = ModuleHash::from_bytes(b"hello world");
+        assert!(hash.matches(b"hello world"));
+        assert!(!hash.matches(b"hello world!"));
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Limits enforced when parsing a module.
+//!
+//! Modules can come from untrusted sources, such as the network through the loader. These
+//! limits are checked before the module is handed over to the interpreter, so that a malicious
+//! module can't make the kernel exhaust its memory before it is even validated.
+//!
+//! Memories and tables that don't declare a maximum size could otherwise grow without bound
+//! while the program runs. Before the module is handed over to the interpreter, every memory and
+//! table without a maximum, whether defined or imported, is therefore given a maximum equal to
+//! the corresponding limit.
+
+use super::sections::{read_leb128_u32, read_limits, write_leb128_u32, Sections};
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+/// Maximum number of pages of a memory allowed by the WASM specification.
+const SPEC_MAX_MEMORY_PAGES: u32 = 65536;
+
+/// Limits to enforce when parsing a [`Module`](super::Module).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleLimits {
+    /// Maximum size, in bytes, of the binary representation of the module.
+    pub max_size: usize,
+    /// Maximum number of functions defined by the module.
+    pub max_functions: u32,
+    /// Maximum number of elements in a table, either initially or after growing.
+    pub max_table_elements: u32,
+    /// Maximum number of 64kiB pages of a memory, either initially or after growing.
+    pub max_memory_pages: u32,
+}
+
+/// Limit that has been exceeded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// See [`ModuleLimits::max_size`].
+    Size,
+    /// See [`ModuleLimits::max_functions`].
+    Functions,
+    /// See [`ModuleLimits::max_table_elements`].
+    TableElements,
+    /// See [`ModuleLimits::max_memory_pages`].
+    MemoryPages,
+}
+
+impl Default for ModuleLimits {
+    fn default() -> Self {
+        ModuleLimits {
+            max_size: 64 * 1024 * 1024,
+            max_functions: 100_000,
+            max_table_elements: 100_000,
+            // 1 GiB.
+            max_memory_pages: 16 * 1024,
+        }
+    }
+}
+
+impl ModuleLimits {
+    /// Returns limits that never trigger.
+    pub fn unlimited() -> Self {
+        ModuleLimits {
+            max_size: usize::max_value(),
+            max_functions: u32::max_value(),
+            max_table_elements: u32::max_value(),
+            max_memory_pages: u32::max_value(),
+        }
+    }
+
+    /// Checks whether the given WASM binary respects the limits, and gives a maximum to the
+    /// memories and tables that don't have one.
+    ///
+    /// On success, returns the binary to hand over to the interpreter. It is identical to
+    /// `wasm`, except for the limits of its memories and tables.
+    ///
+    /// Returns `Ok(Err(_))` if a limit has been exceeded, and `Err(())` if the module is
+    /// malformed.
+    pub(super) fn apply<'a>(
+        &self,
+        wasm: &'a [u8],
+    ) -> Result<Result<Cow<'a, [u8]>, LimitExceeded>, ()> {
+        if wasm.len() > self.max_size {
+            return Ok(Err(LimitExceeded::Size));
+        }
+
+        let sections = Sections::new(wasm)?;
+        let mut out = Vec::with_capacity(wasm.len());
+        out.extend_from_slice(&wasm[..8]);
+        let mut modified = false;
+
+        for section in sections {
+            let (id, original) = section?;
+            let mut content = original;
+            let mut new_content = Vec::with_capacity(original.len());
+
+            match id {
+                // Import section.
+                2 => {
+                    let num_imports = read_leb128_u32(&mut content)?;
+                    write_leb128_u32(&mut new_content, num_imports);
+                    for _ in 0..num_imports {
+                        // Module name, then field name.
+                        for _ in 0..2 {
+                            let len = read_leb128_u32(&mut content)?;
+                            let name = content.get(..len as usize).ok_or(())?;
+                            write_leb128_u32(&mut new_content, len);
+                            new_content.extend_from_slice(name);
+                            content = &content[name.len()..];
+                        }
+
+                        let kind = *content.get(0).ok_or(())?;
+                        new_content.push(kind);
+                        content = &content[1..];
+                        let result = match kind {
+                            // Function.
+                            0 => {
+                                let type_index = read_leb128_u32(&mut content)?;
+                                write_leb128_u32(&mut new_content, type_index);
+                                Ok(())
+                            }
+                            // Table.
+                            1 => {
+                                new_content.push(*content.get(0).ok_or(())?);
+                                content = &content[1..];
+                                self.cap_table(&mut content, &mut new_content)?
+                            }
+                            // Memory.
+                            2 => self.cap_memory(&mut content, &mut new_content)?,
+                            // Global.
+                            3 => {
+                                new_content.extend_from_slice(content.get(..2).ok_or(())?);
+                                content = &content[2..];
+                                Ok(())
+                            }
+                            _ => return Err(()),
+                        };
+                        if let Err(err) = result {
+                            return Ok(Err(err));
+                        }
+                    }
+                }
+                // Function section.
+                3 => {
+                    if read_leb128_u32(&mut content)? > self.max_functions {
+                        return Ok(Err(LimitExceeded::Functions));
+                    }
+                    content = original;
+                }
+                // Table section.
+                4 => {
+                    let num_tables = read_leb128_u32(&mut content)?;
+                    write_leb128_u32(&mut new_content, num_tables);
+                    for _ in 0..num_tables {
+                        // Element type.
+                        new_content.push(*content.get(0).ok_or(())?);
+                        content = &content[1..];
+                        if let Err(err) = self.cap_table(&mut content, &mut new_content)? {
+                            return Ok(Err(err));
+                        }
+                    }
+                }
+                // Memory section.
+                5 => {
+                    let num_memories = read_leb128_u32(&mut content)?;
+                    write_leb128_u32(&mut new_content, num_memories);
+                    for _ in 0..num_memories {
+                        if let Err(err) = self.cap_memory(&mut content, &mut new_content)? {
+                            return Ok(Err(err));
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            // What hasn't been parsed above is copied as is.
+            new_content.extend_from_slice(content);
+            if new_content[..] != *original {
+                modified = true;
+            }
+
+            out.push(id);
+            write_leb128_u32(&mut out, new_content.len() as u32);
+            out.extend_from_slice(&new_content);
+        }
+
+        if modified {
+            Ok(Ok(Cow::Owned(out)))
+        } else {
+            Ok(Ok(Cow::Borrowed(wasm)))
+        }
+    }
+
+    /// Reads the limits of a table from `content`, checks them, and writes them to `out`.
+    fn cap_table(
+        &self,
+        content: &mut &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<Result<(), LimitExceeded>, ()> {
+        let (min, max) = read_limits(content)?;
+        match cap(min, max, self.max_table_elements, u32::max_value()) {
+            Some(max) => {
+                write_limits(out, min, max);
+                Ok(Ok(()))
+            }
+            None => Ok(Err(LimitExceeded::TableElements)),
+        }
+    }
+
+    /// Reads the limits of a memory from `content`, checks them, and writes them to `out`.
+    fn cap_memory(
+        &self,
+        content: &mut &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<Result<(), LimitExceeded>, ()> {
+        let (min, max) = read_limits(content)?;
+        match cap(min, max, self.max_memory_pages, SPEC_MAX_MEMORY_PAGES) {
+            Some(max) => {
+                write_limits(out, min, max);
+                Ok(Ok(()))
+            }
+            None => Ok(Err(LimitExceeded::MemoryPages)),
+        }
+    }
+}
+
+/// Returns the maximum to write in the module, or `None` if the limit is exceeded.
+///
+/// A missing maximum is replaced with `limit`, unless `limit` is at least as large as what the
+/// specification allows, in which case there is nothing to enforce.
+fn cap(min: u32, max: Option<u32>, limit: u32, spec_max: u32) -> Option<Option<u32>> {
+    if min > limit || max.map_or(false, |m| m > limit) {
+        return None;
+    }
+
+    match max {
+        Some(max) => Some(Some(max)),
+        None if limit < spec_max => Some(Some(limit)),
+        None => Some(None),
+    }
+}
+
+/// Writes a `limits` structure, as found in tables and memories.
+fn write_limits(out: &mut Vec<u8>, min: u32, max: Option<u32>) {
+    match max {
+        Some(max) => {
+            out.push(0x01);
+            write_leb128_u32(out, min);
+            write_leb128_u32(out, max);
+        }
+        None => {
+            out.push(0x00);
+            write_leb128_u32(out, min);
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Size => write!(f, "Module is too large"),
+            LimitExceeded::Functions => write!(f, "Module defines too many functions"),
+            LimitExceeded::TableElements => write!(f, "Table of the module is too large"),
+            LimitExceeded::MemoryPages => write!(f, "Memory of the module is too large"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LimitExceeded, ModuleLimits};
+    use alloc::borrow::Cow;
+
+    #[test]
+    fn empty_module_passes() {
+        let wasm = wat::parse_str("(module)").unwrap();
+        assert_eq!(
+            ModuleLimits::default().apply(&wasm),
+            Ok(Ok(Cow::Borrowed(&wasm[..])))
+        );
+    }
+
+    #[test]
+    fn size_exceeded() {
+        let wasm = wat::parse_str("(module)").unwrap();
+        let limits = ModuleLimits {
+            max_size: 4,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Err(LimitExceeded::Size)));
+    }
+
+    #[test]
+    fn memory_exceeded() {
+        let wasm = wat::parse_str("(module (memory 1 20))").unwrap();
+        let limits = ModuleLimits {
+            max_memory_pages: 10,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Err(LimitExceeded::MemoryPages)));
+    }
+
+    #[test]
+    fn functions_exceeded() {
+        let wasm = wat::parse_str("(module (func) (func))").unwrap();
+        let limits = ModuleLimits {
+            max_functions: 1,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Err(LimitExceeded::Functions)));
+    }
+
+    #[test]
+    fn missing_maximum_capped() {
+        let wasm = wat::parse_str("(module (memory 1) (table 2 funcref) (func))").unwrap();
+        let expected = wat::parse_str("(module (memory 1 10) (table 2 5 funcref) (func))").unwrap();
+        let limits = ModuleLimits {
+            max_memory_pages: 10,
+            max_table_elements: 5,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Ok(Cow::Owned(expected))));
+    }
+
+    #[test]
+    fn missing_maximum_unlimited() {
+        let wasm = wat::parse_str("(module (memory 1))").unwrap();
+        assert_eq!(
+            ModuleLimits::unlimited().apply(&wasm),
+            Ok(Ok(Cow::Borrowed(&wasm[..])))
+        );
+    }
+
+    #[test]
+    fn imported_memory_exceeded() {
+        let wasm = wat::parse_str(r#"(module (import "foo" "mem" (memory 1 20)))"#).unwrap();
+        let limits = ModuleLimits {
+            max_memory_pages: 10,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Err(LimitExceeded::MemoryPages)));
+    }
+
+    #[test]
+    fn imported_table_capped() {
+        let wasm = wat::parse_str(
+            r#"(module (import "foo" "f" (func)) (import "foo" "tbl" (table 1 funcref)))"#,
+        )
+        .unwrap();
+        let expected = wat::parse_str(
+            r#"(module (import "foo" "f" (func)) (import "foo" "tbl" (table 1 5 funcref)))"#,
+        )
+        .unwrap();
+        let limits = ModuleLimits {
+            max_table_elements: 5,
+            ..ModuleLimits::default()
+        };
+        assert_eq!(limits.apply(&wasm), Ok(Ok(Cow::Owned(expected))));
+    }
+}
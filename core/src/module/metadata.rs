@@ -0,0 +1,137 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Metadata embedded within a module.
+//!
+//! A module can optionally contain a [custom section](https://webassembly.github.io/spec/core/binary/modules.html#custom-section)
+//! named `redshirt-meta`. The content of this section is a SCALE-encoded [`ModuleMetadata`].
+//!
+//! The metadata is purely declarative. It is up to the [`System`](crate::System) to decide
+//! what to do with it, such as displaying the name of the program or refusing to let it register
+//! interfaces that it didn't declare.
+
+use super::sections::{read_leb128_u32, Sections};
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, DecodeAll as _, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+/// Name of the custom section containing the metadata.
+pub const SECTION_NAME: &str = "redshirt-meta";
+
+/// Metadata of a module, as found in its `redshirt-meta` custom section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ModuleMetadata {
+    /// Human-readable name of the program.
+    pub name: String,
+    /// Version of the program. Free-form string.
+    pub version: String,
+    /// List of interfaces that the program intends to register.
+    pub registered_interfaces: Vec<InterfaceHash>,
+    /// List of interfaces that the program intends to emit messages on.
+    pub used_interfaces: Vec<InterfaceHash>,
+}
+
+impl ModuleMetadata {
+    /// Encodes the metadata into the format expected in the custom section.
+    pub fn to_section_content(&self) -> Vec<u8> {
+        self.encode()
+    }
+}
+
+/// Looks for the `redshirt-meta` section in the given WASM binary and decodes it.
+///
+/// Returns `Ok(None)` if there is no such section. If there are multiple sections with that
+/// name, only the first one is taken into account.
+pub(super) fn from_wasm(wasm: &[u8]) -> Result<Option<ModuleMetadata>, ()> {
+    let content = match find_custom_section(wasm, SECTION_NAME)? {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+
+    ModuleMetadata::decode_all(content)
+        .map(Some)
+        .map_err(|_| ())
+}
+
+/// Returns the content of the first custom section with the given name.
+fn find_custom_section<'a>(wasm: &'a [u8], name: &str) -> Result<Option<&'a [u8]>, ()> {
+    for section in Sections::new(wasm)? {
+        let (section_id, mut section) = section?;
+
+        // Custom sections have an ID of 0.
+        if section_id != 0 {
+            continue;
+        }
+
+        let name_len = read_leb128_u32(&mut section)? as usize;
+        if section.len() < name_len {
+            return Err(());
+        }
+        let (section_name, content) = section.split_at(name_len);
+        if section_name == name.as_bytes() {
+            return Ok(Some(content));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_wasm, ModuleMetadata};
+    use alloc::vec;
+    use redshirt_syscalls_interface::InterfaceHash;
+
+    #[test]
+    fn no_section() {
+        let wasm = wat::parse_str("(module)").unwrap();
+        assert!(from_wasm(&wasm).unwrap().is_none());
+    }
+
+    #[test]
+    fn decodes_section() {
+        let metadata = ModuleMetadata {
+            name: "foo".into(),
+            version: "1.2.3".into(),
+            registered_interfaces: vec![InterfaceHash::from_raw_hash([1; 32])],
+            used_interfaces: vec![InterfaceHash::from_raw_hash([2; 32])],
+        };
+
+        let mut wasm = wat::parse_str("(module)").unwrap();
+        let content = metadata.to_section_content();
+        let name = super::SECTION_NAME.as_bytes();
+        let section_len = 1 + name.len() + content.len();
+        assert!(section_len < 128 && content.len() < 128);
+        wasm.push(0);
+        wasm.push(section_len as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name);
+        wasm.extend_from_slice(&content);
+
+        assert_eq!(from_wasm(&wasm).unwrap(), Some(metadata));
+    }
+
+    #[test]
+    fn invalid_section() {
+        let mut wasm = wat::parse_str("(module)").unwrap();
+        let name = super::SECTION_NAME.as_bytes();
+        wasm.push(0);
+        wasm.push((1 + name.len() + 1) as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name);
+        wasm.push(0xff);
+        assert!(from_wasm(&wasm).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Minimal parsing of the binary format of WASM modules.
+//!
+//! The actual parsing and validation is performed by the interpreter. This module only walks
+//! over the top-level sections, which lets us extract information that the interpreter doesn't
+//! expose, or perform checks before handing over the module to the interpreter.
+
+use alloc::vec::Vec;
+
+/// Iterator over the sections of a WASM module. Yields the section ID and its content.
+///
+/// Yields an error and stops if the module is malformed.
+pub(super) struct Sections<'a> {
+    cursor: &'a [u8],
+    errored: bool,
+}
+
+impl<'a> Sections<'a> {
+    /// Starts iterating over the sections of the given module.
+    ///
+    /// Returns an error if the module doesn't start with the WASM magic number and version.
+    pub(super) fn new(wasm: &'a [u8]) -> Result<Self, ()> {
+        if wasm.len() < 8 || &wasm[..4] != b"\0asm" {
+            return Err(());
+        }
+
+        Ok(Sections {
+            cursor: &wasm[8..],
+            errored: false,
+        })
+    }
+}
+
+impl<'a> Iterator for Sections<'a> {
+    type Item = Result<(u8, &'a [u8]), ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.errored || self.cursor.is_empty() {
+            return None;
+        }
+
+        let section_id = self.cursor[0];
+        self.cursor = &self.cursor[1..];
+        let section_len = match read_leb128_u32(&mut self.cursor) {
+            Ok(l) => l as usize,
+            Err(()) => {
+                self.errored = true;
+                return Some(Err(()));
+            }
+        };
+        if self.cursor.len() < section_len {
+            self.errored = true;
+            return Some(Err(()));
+        }
+
+        let (section, rest) = self.cursor.split_at(section_len);
+        self.cursor = rest;
+        Some(Ok((section_id, section)))
+    }
+}
+
+/// Reads an unsigned LEB128-encoded number from the buffer, and advances it.
+pub(super) fn read_leb128_u32(buffer: &mut &[u8]) -> Result<u32, ()> {
+    let mut result: u32 = 0;
+    for n in 0..5 {
+        let byte = *buffer.get(n).ok_or(())?;
+        // The fifth byte only holds the 4 highest bits of the number. Any other bit set means
+        // that the encoding is too long or that the number overflows.
+        if n == 4 && byte & 0xf0 != 0 {
+            return Err(());
+        }
+        result |= u32::from(byte & 0x7f) << (7 * n as u32);
+        if byte & 0x80 == 0 {
+            *buffer = &buffer[n + 1..];
+            return Ok(result);
+        }
+    }
+    Err(())
+}
+
+/// Writes an unsigned LEB128-encoded number at the end of the buffer.
+pub(super) fn write_leb128_u32(buffer: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+/// Reads a `limits` structure, as found in tables and memories, and advances the buffer.
+///
+/// Returns the minimum and the optional maximum.
+pub(super) fn read_limits(buffer: &mut &[u8]) -> Result<(u32, Option<u32>), ()> {
+    let flag = *buffer.get(0).ok_or(())?;
+    *buffer = &buffer[1..];
+    let min = read_leb128_u32(buffer)?;
+    match flag {
+        0x00 => Ok((min, None)),
+        0x01 => Ok((min, Some(read_leb128_u32(buffer)?))),
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_leb128_u32, write_leb128_u32};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn leb128_round_trip() {
+        for value in &[0, 1, 0x7f, 0x80, 0x3fff, 0x4000, 0xffff_ffff] {
+            let mut encoded = Vec::new();
+            write_leb128_u32(&mut encoded, *value);
+            let mut buffer = &encoded[..];
+            assert_eq!(read_leb128_u32(&mut buffer), Ok(*value));
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn leb128_advances_buffer() {
+        let mut buffer = &[0xe5, 0x8e, 0x26, 0xaa][..];
+        assert_eq!(read_leb128_u32(&mut buffer), Ok(624_485));
+        assert_eq!(buffer, [0xaa]);
+    }
+
+    #[test]
+    fn leb128_overflow_rejected() {
+        let mut buffer = &[0xff, 0xff, 0xff, 0xff, 0x7f][..];
+        assert!(read_leb128_u32(&mut buffer).is_err());
+        let mut buffer = &[0x80, 0x80, 0x80, 0x80, 0x10][..];
+        assert!(read_leb128_u32(&mut buffer).is_err());
+        // Continuation bit on the fifth byte.
+        let mut buffer = &[0xff, 0xff, 0xff, 0xff, 0x8f, 0x00][..];
+        assert!(read_leb128_u32(&mut buffer).is_err());
+        let mut buffer = &[0xff, 0xff, 0xff, 0xff, 0x0f][..];
+        assert_eq!(read_leb128_u32(&mut buffer), Ok(0xffff_ffff));
+    }
+
+    #[test]
+    fn leb128_truncated() {
+        let mut buffer = &[0x80, 0x80][..];
+        assert!(read_leb128_u32(&mut buffer).is_err());
+    }
+}
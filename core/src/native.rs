@@ -16,9 +16,12 @@
 pub use self::collection::{
     NativeProgramsCollection, NativeProgramsCollectionEvent, NativeProgramsCollectionMessageIdWrite,
 };
+pub use self::tasks::NativeTaskSpawner;
 pub use self::traits::{
-    DummyMessageIdWrite, NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef,
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+    NativeProgramMessageIdWrite,
 };
 
 mod collection;
+mod tasks;
 mod traits;
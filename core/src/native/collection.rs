@@ -13,7 +13,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::native::traits::{NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef};
+use crate::native::tasks::{NativeTaskSpawner, NativeTasks};
+use crate::native::traits::{NativeProgram, NativeProgramEvent, NativeProgramMessageIdWrite};
 
 use alloc::{boxed::Box, vec::Vec};
 use core::{mem, task::Context, task::Poll};
@@ -27,6 +28,9 @@ use spin::Mutex;
 pub struct NativeProgramsCollection<'ext> {
     /// Collection of processes and their `Pid`.
     processes: Vec<(Pid, Box<dyn AdapterAbstract + Send + 'ext>)>,
+
+    /// Background tasks spawned by the programs.
+    tasks: NativeTasks,
 }
 
 /// Event generated by a [`NativeProgram`].
@@ -45,6 +49,9 @@ pub enum NativeProgramsCollectionEvent<'col> {
     },
     /// Request to cancel a previously-emitted message.
     CancelMessage {
+        /// Pid of the program that cancels the message. Same as a value that was passed to
+        /// [`push`](NativeProgramsCollection::push).
+        emitter_pid: Pid,
         /// Message to cancel.
         message_id: MessageId,
     },
@@ -56,6 +63,14 @@ pub enum NativeProgramsCollectionEvent<'col> {
         /// The produced answer, or an `Err` if the message is invalid.
         answer: Result<EncodedMessage, ()>,
     },
+    /// Request to send a partial answer to a message received with
+    /// [`interface_message`](NativeProgramsCollection::interface_message).
+    PartialAnswer {
+        /// Message to answer.
+        message_id: MessageId,
+        /// The produced partial answer.
+        answer: EncodedMessage,
+    },
 }
 
 /// Allows writing back a [`MessageId`] when a message is emitted.
@@ -89,7 +104,18 @@ trait AdapterAbstract {
         message_id: MessageId,
         response: Result<EncodedMessage, ()>,
     ) -> Result<(), Result<EncodedMessage, ()>>;
+    fn deliver_partial_response(
+        &self,
+        message_id: MessageId,
+        response: EncodedMessage,
+    ) -> Result<(), EncodedMessage>;
+    fn deliver_message_cancelled(
+        &self,
+        interface: &InterfaceHash,
+        message_id: MessageId,
+    ) -> Result<(), ()>;
     fn process_destroyed(&self, pid: Pid);
+    fn shutdown(&self);
 }
 
 trait AbstractMessageIdWrite {
@@ -108,9 +134,16 @@ impl<'ext> NativeProgramsCollection<'ext> {
     pub fn new() -> Self {
         NativeProgramsCollection {
             processes: Vec::new(),
+            tasks: NativeTasks::new(),
         }
     }
 
+    /// Returns a handle that allows spawning background tasks. These tasks are polled as part of
+    /// [`next_event`](NativeProgramsCollection::next_event).
+    pub fn task_spawner(&self) -> NativeTaskSpawner {
+        self.tasks.spawner()
+    }
+
     /// Adds a program to the collection.
     ///
     /// # Panic
@@ -119,8 +152,7 @@ impl<'ext> NativeProgramsCollection<'ext> {
     ///
     pub fn push<T>(&mut self, pid: Pid, program: T)
     where
-        T: Send + 'ext,
-        for<'r> &'r T: NativeProgramRef<'r>,
+        T: NativeProgram + Send + 'ext,
     {
         let adapter = Box::new(Adapter {
             inner: program,
@@ -138,11 +170,18 @@ impl<'ext> NativeProgramsCollection<'ext> {
         self.processes.shrink_to_fit();
     }
 
+    /// Returns true if the given [`Pid`] belongs to a program of this collection.
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.processes.iter().any(|(p, _)| *p == pid)
+    }
+
     /// Returns a `Future` that yields the next event generated by one of the programs.
     pub fn next_event<'collec>(
         &'collec self,
     ) -> impl Future<Output = NativeProgramsCollectionEvent<'collec>> + 'collec {
         future::poll_fn(move |cx| {
+            self.tasks.poll(cx);
+
             for (pid, process) in self.processes.iter() {
                 match process.poll_next_event(cx) {
                     Poll::Pending => {}
@@ -161,6 +200,7 @@ impl<'ext> NativeProgramsCollection<'ext> {
                     }
                     Poll::Ready(NativeProgramEvent::CancelMessage { message_id }) => {
                         return Poll::Ready(NativeProgramsCollectionEvent::CancelMessage {
+                            emitter_pid: *pid,
                             message_id,
                         })
                     }
@@ -170,6 +210,12 @@ impl<'ext> NativeProgramsCollection<'ext> {
                             answer,
                         })
                     }
+                    Poll::Ready(NativeProgramEvent::PartialAnswer { message_id, answer }) => {
+                        return Poll::Ready(NativeProgramsCollectionEvent::PartialAnswer {
+                            message_id,
+                            answer,
+                        })
+                    }
                 }
             }
 
@@ -198,6 +244,20 @@ impl<'ext> NativeProgramsCollection<'ext> {
         panic!() // TODO: what to do here?
     }
 
+    /// Notify the [`NativeProgram`] that has registered the given interface that a message
+    /// previously passed to [`interface_message`](NativeProgramsCollection::interface_message)
+    /// has been cancelled by its emitter.
+    pub fn message_cancelled(&self, interface: InterfaceHash, message_id: MessageId) {
+        for (_, process) in &self.processes {
+            if process
+                .deliver_message_cancelled(&interface, message_id)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
     /// Notify the [`NativeProgram`]s that the program with the given [`Pid`] has terminated.
     pub fn process_destroyed(&mut self, pid: Pid) {
         for (_, process) in &self.processes {
@@ -205,6 +265,16 @@ impl<'ext> NativeProgramsCollection<'ext> {
         }
     }
 
+    /// Notify the [`NativeProgram`]s that the system is shutting down.
+    ///
+    /// Programs are notified in the reverse order of their insertion, since programs inserted
+    /// later might depend on programs inserted earlier.
+    pub fn shutdown(&self) {
+        for (_, process) in self.processes.iter().rev() {
+            process.shutdown();
+        }
+    }
+
     /// Notify the appropriate [`NativeProgram`] of a response to a message that it has previously
     /// emitted.
     pub fn message_response(
@@ -220,19 +290,33 @@ impl<'ext> NativeProgramsCollection<'ext> {
             }
         }
 
-        panic!() // TODO: what to do here?
+        // The message might have been cancelled by its emitter before the response arrived.
+    }
+
+    /// Notify the appropriate [`NativeProgram`] of a partial response to a message that it has
+    /// previously emitted.
+    pub fn message_partial_response(&self, message_id: MessageId, mut response: EncodedMessage) {
+        for (_, process) in &self.processes {
+            let msg = mem::replace(&mut response, EncodedMessage(Vec::new()));
+            match process.deliver_partial_response(message_id, msg) {
+                Ok(_) => return,
+                Err(msg) => response = msg,
+            }
+        }
+
+        // The message might have been cancelled by its emitter before the response arrived.
     }
 }
 
 impl<T> AdapterAbstract for Adapter<T>
 where
-    for<'r> &'r T: NativeProgramRef<'r>,
+    T: NativeProgram,
 {
     fn poll_next_event<'col>(
         &'col self,
         cx: &mut Context,
     ) -> Poll<NativeProgramEvent<Box<dyn AbstractMessageIdWrite + 'col>>> {
-        let future = (&self.inner).next_event();
+        let future = self.inner.next_event();
         futures::pin_mut!(future);
         match future.poll(cx) {
             Poll::Ready(NativeProgramEvent::Emit {
@@ -242,10 +326,15 @@ where
             }) => {
                 if interface == redshirt_interface_interface::ffi::INTERFACE {
                     // TODO: check whether registration succeeds, but hard if `message_id_write` is `None
-                    if let Ok(msg) = InterfaceMessage::decode(message.clone()) {
-                        let InterfaceMessage::Register(to_reg) = msg;
-                        let mut registered_interfaces = self.registered_interfaces.lock();
-                        registered_interfaces.insert(to_reg);
+                    match InterfaceMessage::decode(message.clone()) {
+                        Ok(InterfaceMessage::Register(to_reg))
+                        | Ok(InterfaceMessage::RegisterVersions(to_reg, _)) => {
+                            let mut registered_interfaces = self.registered_interfaces.lock();
+                            registered_interfaces.insert(to_reg);
+                        }
+                        Ok(InterfaceMessage::QueryVersions(_))
+                        | Ok(InterfaceMessage::WaitAvailabilityChange(..))
+                        | Err(_) => {}
                     }
                 }
 
@@ -263,11 +352,15 @@ where
                 })
             }
             Poll::Ready(NativeProgramEvent::CancelMessage { message_id }) => {
+                self.expected_responses.lock().remove(&message_id);
                 Poll::Ready(NativeProgramEvent::CancelMessage { message_id })
             }
             Poll::Ready(NativeProgramEvent::Answer { message_id, answer }) => {
                 Poll::Ready(NativeProgramEvent::Answer { message_id, answer })
             }
+            Poll::Ready(NativeProgramEvent::PartialAnswer { message_id, answer }) => {
+                Poll::Ready(NativeProgramEvent::PartialAnswer { message_id, answer })
+            }
             Poll::Pending => Poll::Pending,
         }
     }
@@ -303,9 +396,41 @@ where
         }
     }
 
+    fn deliver_partial_response(
+        &self,
+        message_id: MessageId,
+        response: EncodedMessage,
+    ) -> Result<(), EncodedMessage> {
+        let expected_responses = self.expected_responses.lock();
+        if expected_responses.contains(&message_id) {
+            self.inner.message_partial_response(message_id, response);
+            Ok(())
+        } else {
+            Err(response)
+        }
+    }
+
+    fn deliver_message_cancelled(
+        &self,
+        interface: &InterfaceHash,
+        message_id: MessageId,
+    ) -> Result<(), ()> {
+        let registered_interfaces = self.registered_interfaces.lock();
+        if registered_interfaces.contains(interface) {
+            self.inner.message_cancelled(message_id);
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
     fn process_destroyed(&self, pid: Pid) {
         self.inner.process_destroyed(pid);
     }
+
+    fn shutdown(&self) {
+        self.inner.shutdown();
+    }
 }
 
 impl<'col, T> AbstractMessageIdWrite for MessageIdWriteAdapter<'col, T>
@@ -0,0 +1,111 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, future::Future, mem, pin::Pin, task::Context};
+use futures::task::AtomicWaker;
+use spin::Mutex;
+
+/// Task spawned through a [`NativeTaskSpawner`].
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Handle that allows native programs to spawn background tasks.
+///
+/// The tasks are executed by the [`System`](crate::System), alongside the
+/// [`next_event`](crate::native::NativeProgram::next_event) futures of the native programs.
+/// Tasks are expected to communicate their results back to the native program that has spawned
+/// them, for example through a channel, which then generates the corresponding events.
+///
+/// Can be obtained through
+/// [`SystemBuilder::native_task_spawner`](crate::system::SystemBuilder::native_task_spawner).
+#[derive(Clone)]
+pub struct NativeTaskSpawner {
+    inner: Arc<SpawnerInner>,
+}
+
+struct SpawnerInner {
+    /// Tasks that have been spawned but not polled yet.
+    queue: Mutex<Vec<Task>>,
+    /// Waken up when a new task is spawned.
+    waker: AtomicWaker,
+}
+
+/// Collection of tasks spawned by the native programs.
+pub(super) struct NativeTasks {
+    /// Spawner whose tasks are moved to `running`.
+    spawner: NativeTaskSpawner,
+    /// Tasks that have been polled at least once and haven't finished yet.
+    running: Mutex<Vec<Task>>,
+}
+
+impl NativeTaskSpawner {
+    /// Spawns a task that will run in the background.
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.inner.queue.lock().push(Box::pin(task));
+        self.inner.waker.wake();
+    }
+}
+
+impl fmt::Debug for NativeTaskSpawner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("NativeTaskSpawner").finish()
+    }
+}
+
+impl NativeTasks {
+    /// Builds an empty collection.
+    pub(super) fn new() -> Self {
+        NativeTasks {
+            spawner: NativeTaskSpawner {
+                inner: Arc::new(SpawnerInner {
+                    queue: Mutex::new(Vec::new()),
+                    waker: AtomicWaker::new(),
+                }),
+            },
+            running: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a handle that spawns tasks in this collection.
+    pub(super) fn spawner(&self) -> NativeTaskSpawner {
+        self.spawner.clone()
+    }
+
+    /// Polls all the tasks, and removes the ones that have finished.
+    // TODO: only poll the tasks that have been woken up
+    pub(super) fn poll(&self, cx: &mut Context) {
+        self.spawner.inner.waker.register(cx.waker());
+
+        let mut running = self.running.lock();
+        loop {
+            let new_tasks = mem::replace(&mut *self.spawner.inner.queue.lock(), Vec::new());
+            running.extend(new_tasks);
+
+            let mut n = 0;
+            while n < running.len() {
+                if running[n].as_mut().poll(cx).is_ready() {
+                    running.swap_remove(n);
+                } else {
+                    n += 1;
+                }
+            }
+
+            // Tasks might have spawned other tasks while being polled.
+            if self.spawner.inner.queue.lock().is_empty() {
+                break;
+            }
+        }
+    }
+}
@@ -13,31 +13,45 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use core::future::Future;
+use alloc::boxed::Box;
+use core::{future::Future, pin::Pin};
 use redshirt_syscalls_interface::{EncodedMessage, InterfaceHash, MessageId, Pid};
 
-/// Reference to a native program.
+/// `Future` returned by [`NativeProgram::next_event`].
 ///
-/// This trait is not meant to be implemented on types `T`. Instead, if `T` represents the state
-/// of the native program, then the [`NativeProgramRef`] trait should be implemented on `&'a T`
-/// instead (where `'a` is the lifetime parameter of the trait). This design is necessary due to
-/// the lack of HRTBs in the Rust language.
-pub trait NativeProgramRef<'a>: Clone {
-    /// Future resolving to the next event the [`NativeProgram`] emits.
-    ///
-    /// Typically set to `Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>`.
-    type Future: Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a;
+/// The future is boxed so that implementations can simply write
+/// `Box::pin(async move { ... })` and borrow `self` for the duration of the future.
+pub type NativeProgramFuture<'a, TMsgIdWrite> =
+    Pin<Box<dyn Future<Output = NativeProgramEvent<TMsgIdWrite>> + Send + 'a>>;
+
+/// Program written in Rust and directly executed by the kernel.
+///
+/// Implement this trait on the type that holds the state of the native program, then pass an
+/// instance of that type to
+/// [`SystemBuilder::with_native_program`](crate::system::SystemBuilder::with_native_program).
+///
+/// All the methods of this trait take `&self`. Since [`next_event`](NativeProgram::next_event)
+/// and the notification methods can be called concurrently, the state that is shared between
+/// them should be protected, for example with a `Mutex` or a channel.
+///
+/// Work that can progress independently from the events can be spawned as background tasks with
+/// a [`NativeTaskSpawner`](crate::native::NativeTaskSpawner), instead of being multiplexed
+/// inside of [`next_event`](NativeProgram::next_event).
+pub trait NativeProgram {
     /// When the [`NativeProgram`] emits a message, this item is used by the caller to notify of
     /// the [`MessageId`] that has been emitted.
     type MessageIdWrite: NativeProgramMessageIdWrite;
 
     /// Returns a `Future` resolving to when the [`NativeProgram`] wants to do something.
-    fn next_event(self) -> Self::Future;
+    ///
+    /// The returned `Future` can be dropped before it finishes, in which case this method will
+    /// be called again later.
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite>;
 
     /// Notify the [`NativeProgram`] that a message has arrived on one of the interface that it
     /// has registered.
     fn interface_message(
-        self,
+        &self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
         emitter_pid: Pid,
@@ -45,10 +59,33 @@ pub trait NativeProgramRef<'a>: Clone {
     );
 
     /// Notify the [`NativeProgram`] that the program with the given [`Pid`] has terminated.
-    fn process_destroyed(self, pid: Pid);
+    fn process_destroyed(&self, pid: Pid);
 
     /// Notify the [`NativeProgram`] of a response to a message that it has previously emitted.
-    fn message_response(self, message_id: MessageId, response: Result<EncodedMessage, ()>);
+    fn message_response(&self, message_id: MessageId, response: Result<EncodedMessage, ()>);
+
+    /// Notify the [`NativeProgram`] of a partial response to a message that it has previously
+    /// emitted. More responses will follow, the last one being passed to
+    /// [`message_response`](NativeProgram::message_response).
+    ///
+    /// Answers larger than `MAX_MESSAGE_SIZE` are also delivered as a sequence of partial
+    /// responses. Their concatenation, followed by the final response, forms the full answer.
+    ///
+    /// The default implementation does nothing.
+    fn message_partial_response(&self, _message_id: MessageId, _response: EncodedMessage) {}
+
+    /// Notify the [`NativeProgram`] that a message received with
+    /// [`interface_message`](NativeProgram::interface_message) has been cancelled by its emitter.
+    /// The message no longer needs to be answered, and answering it has no effect.
+    ///
+    /// The default implementation does nothing.
+    fn message_cancelled(&self, _message_id: MessageId) {}
+
+    /// Notify the [`NativeProgram`] that the system is shutting down. All the WASM programs have
+    /// been stopped or given up on at this point.
+    ///
+    /// The default implementation does nothing.
+    fn shutdown(&self) {}
 }
 
 /// Event generated by a [`NativeProgram`].
@@ -60,25 +97,37 @@ pub enum NativeProgramEvent<TMsgIdWrite> {
         /// Interface to emit the message on.
         interface: InterfaceHash,
         /// If we expect an answer, contains an object that allows indicating to the
-        /// [`NativeProgramRef`] which `MessageId` has been attributed.
+        /// [`NativeProgram`] which `MessageId` has been attributed.
         ///
-        /// `None` if the [`NativeProgramRef`] doesn't expect an answer for this message.
+        /// `None` if the [`NativeProgram`] doesn't expect an answer for this message.
         message_id_write: Option<TMsgIdWrite>,
         /// Message to send.
         message: EncodedMessage,
     },
     /// Request to cancel a previously-emitted message.
+    ///
+    /// No response will be delivered with
+    /// [`message_response`](NativeProgram::message_response) for this message.
     CancelMessage {
         /// Message to cancel.
         message_id: MessageId,
     },
-    /// Answer a message previously received with [`NativeProgramRef::interface_message`].
+    /// Answer a message previously received with [`NativeProgram::interface_message`].
     Answer {
         /// Message to answer.
         message_id: MessageId,
         /// Answer to the message. Can be an error if the message is invalid.
         answer: Result<EncodedMessage, ()>,
     },
+    /// Send a partial answer to a message previously received with
+    /// [`NativeProgram::interface_message`]. The message must later be answered with
+    /// [`NativeProgramEvent::Answer`], unless it gets cancelled.
+    PartialAnswer {
+        /// Message to answer.
+        message_id: MessageId,
+        /// Partial answer to the message.
+        answer: EncodedMessage,
+    },
 }
 
 /// Trait used to write back the [`MessageId`] when the program emits a message.
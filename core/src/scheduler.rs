@@ -14,10 +14,18 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 mod extrinsics;
+mod host_function;
 mod ipc;
+mod middleware;
 mod processes;
 mod tests;
 mod vm;
 
+pub use self::host_function::{HostFunction, HostFunctionContext};
 // TODO: move definition?
-pub use self::ipc::{Core, CoreBuilder, CoreProcess, CoreRunOutcome, CoreThread};
+pub use self::ipc::{
+    Core, CoreBuilder, CoreProcess, CoreRunOutcome, CoreThread, InterfaceStatistics, OrphanPolicy,
+    ProcessSummary, SetInterfaceHandlerError,
+};
+pub use self::middleware::{Middleware, Verdict};
+pub use self::vm::{ExecError, NewErr};
@@ -13,15 +13,19 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::id_pool::IdPool;
 use crate::module::Module;
+use crate::scheduler::host_function::{HostFunction, HostFunctionContext};
 use crate::scheduler::{processes, vm};
 use crate::sig;
+use crate::signature::Signature;
 use crate::{InterfaceHash, MessageId};
 
-use alloc::{vec, vec::Vec};
+use alloc::{borrow::Cow, boxed::Box, vec, vec::Vec};
 use byteorder::{ByteOrder as _, LittleEndian};
 use core::{convert::TryFrom as _, fmt, mem};
-use redshirt_syscalls_interface::{EncodedMessage, Pid, ThreadId};
+use hashbrown::{HashMap, HashSet};
+use redshirt_syscalls_interface::{BlobHandle, EncodedMessage, Pid, ThreadId};
 
 /// Wrapper around [`ProcessesCollection`](processes::ProcessesCollection), but that interprets
 /// the extrinsic calls and keeps track of the state in which pending threads are in.
@@ -31,6 +35,15 @@ use redshirt_syscalls_interface::{EncodedMessage, Pid, ThreadId};
 /// or a thread.
 pub struct ProcessesCollectionExtrinsics<TPud, TTud> {
     inner: processes::ProcessesCollection<Extrinsic, TPud, LocalThreadUserData<TTud>>,
+
+    /// Pool of identifiers for blobs.
+    blob_id_pool: IdPool,
+
+    /// List of blobs that have been created by processes.
+    blobs: HashMap<BlobHandle, Blob>,
+
+    /// List of processes that have called `accept_directed_messages`.
+    directed_messages_accepted: HashSet<Pid>,
 }
 
 /// Prototype for a `ProcessesCollectionExtrinsics` under construction.
@@ -50,6 +63,7 @@ pub enum ProcessesCollectionExtrinsicsThread<'a, TPud, TTud> {
     Regular(ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>),
     EmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage<'a, TPud, TTud>),
     WaitMessage(ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TTud>),
+    Sleep(ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>),
 }
 
 /// Access to a thread within the collection.
@@ -73,6 +87,28 @@ pub struct ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TTud> {
     inner: processes::ProcessesCollectionThread<'a, TPud, LocalThreadUserData<TTud>>,
 }
 
+/// Access to a thread within the collection.
+///
+/// Implements the [`ProcessesCollectionExtrinsicsThreadAccess`] trait.
+pub struct ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud> {
+    inner: processes::ProcessesCollectionThread<'a, TPud, LocalThreadUserData<TTud>>,
+}
+
+/// Access to a thread within the collection that has called `process_info`.
+///
+/// Contrary to the other accessors, this one can't be retrieved with
+/// [`thread_by_id`](ProcessesCollectionExtrinsics::thread_by_id), and the thread must be resumed
+/// immediately.
+///
+/// Implements the [`ProcessesCollectionExtrinsicsThreadAccess`] trait.
+pub struct ProcessesCollectionExtrinsicsThreadProcessInfo<'a, TPud, TTud> {
+    inner: processes::ProcessesCollectionThread<'a, TPud, LocalThreadUserData<TTud>>,
+    /// Offset within the memory of the process where to write the information.
+    out_pointer: u32,
+    /// Size of the memory of the process dedicated to receiving the information.
+    out_size: u32,
+}
+
 /// Common trait amongst all the thread accessor structs.
 pub trait ProcessesCollectionExtrinsicsThreadAccess<'a> {
     type ProcessUserData;
@@ -104,13 +140,31 @@ pub trait ProcessesCollectionExtrinsicsThreadAccess<'a> {
 }
 
 /// Possible function available to processes.
-#[derive(Debug, Clone, PartialEq, Eq)]
 enum Extrinsic {
     NextMessage,
+    NextMessages,
     EmitMessage,
+    EmitMessageWithDeadline,
+    TryEmitMessage,
+    EmitMessageChunk,
     EmitMessageError,
     EmitAnswer,
+    EmitAnswerPartial,
+    EmitAnswerChunk,
     CancelMessage,
+    SleepUntil,
+    BlobCreate,
+    BlobShare,
+    BlobRead,
+    BlobRelease,
+    AcceptDirectedMessages,
+    EmitDirectedMessage,
+    EmitDirectedMessageWithDeadline,
+    EmitDirectedMessageChunk,
+    ProcessInfo,
+    /// Function registered with
+    /// [`add_host_function`](ProcessesCollectionExtrinsicsBuilder::add_host_function).
+    Host(Box<dyn HostFunction>),
 }
 
 /// Structure passed to the underlying [`processes::ProcessesCollection`] that tracks the state
@@ -135,6 +189,10 @@ enum LocalThreadState {
 
     /// The thread called `emit_message` and wants to emit a message on an interface.
     EmitMessage(EmitMessage),
+
+    /// The thread called `sleep_until` and is sleeping until the monotonic clock reaches the
+    /// given value, in nanoseconds.
+    Sleep(u64),
 }
 
 /// How a process is waiting for messages.
@@ -151,6 +209,9 @@ struct MessageWait {
     out_size: u32,
     /// Whether to block the thread if no message is available.
     block: bool,
+    /// If `Some`, the thread has called `next_messages` and accepts up to this number of
+    /// messages at once, encoded as a list.
+    max_messages: Option<u32>,
 }
 
 /// How a process is emitting a message.
@@ -165,6 +226,30 @@ struct EmitMessage {
     /// True if we're allowed to block the thread to wait for an interface handler to be
     /// available.
     allow_delay: bool,
+    /// True if the message is only a chunk, and must be concatenated with the next message
+    /// emitted by the same process on the same interface.
+    chunk: bool,
+    /// True if the emission must fail immediately if the interface has no handler or if the
+    /// queue of the handler is full.
+    no_block: bool,
+    /// Value of the monotonic clock, in nanoseconds, after which the message must be answered
+    /// with an error if no answer has been sent.
+    deadline: Option<u64>,
+    /// If `Some`, the message is directly destined to the given process rather than to the
+    /// handler of `interface`.
+    target: Option<Pid>,
+}
+
+/// Blob created by a process.
+#[derive(Debug)]
+struct Blob {
+    /// Process that has created the blob. Only this process can share or release it, and the
+    /// blob is destroyed when this process terminates.
+    owner: Pid,
+    /// Processes other than the owner that are allowed to read the blob.
+    readers: HashSet<Pid>,
+    /// Content of the blob.
+    data: Vec<u8>,
 }
 
 /// How a process is emitting a response.
@@ -195,7 +280,7 @@ pub enum RunOneOutcome<'a, TPud, TTud> {
         dead_threads: Vec<(ThreadId, TTud)>,
 
         /// Value returned by the main thread that has finished, or error that happened.
-        outcome: Result<Option<wasmi::RuntimeValue>, wasmi::Trap>,
+        outcome: Result<Option<wasmi::RuntimeValue>, vm::ExecError>,
     },
 
     /// A thread in a process has finished.
@@ -216,6 +301,12 @@ pub enum RunOneOutcome<'a, TPud, TTud> {
     /// A thread in a process is waiting for an incoming message.
     ThreadWaitMessage(ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TTud>),
 
+    /// A thread in a process wants to sleep until the monotonic clock reaches a certain value.
+    ThreadSleep(ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>),
+
+    /// A thread in a process wants to know information about its own process.
+    ThreadProcessInfo(ProcessesCollectionExtrinsicsThreadProcessInfo<'a, TPud, TTud>),
+
     /// A thread in a process wants to answer a message.
     ThreadEmitAnswer {
         /// Thread that wants to emit an answer.
@@ -228,6 +319,30 @@ pub enum RunOneOutcome<'a, TPud, TTud> {
         response: EncodedMessage,
     },
 
+    /// A thread in a process wants to send a partial answer to a message.
+    ThreadEmitPartialAnswer {
+        /// Thread that wants to emit a partial answer.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+
+        /// Message to answer.
+        message_id: MessageId,
+
+        /// The partial answer.
+        response: EncodedMessage,
+    },
+
+    /// A thread in a process wants to send a chunk of an answer to a message.
+    ThreadEmitAnswerChunk {
+        /// Thread that wants to emit a chunk.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+
+        /// Message to answer.
+        message_id: MessageId,
+
+        /// The chunk.
+        response: EncodedMessage,
+    },
+
     /// A thread in a process wants to notify that a message is erroneous.
     ThreadEmitMessageError {
         /// Thread that wants to emit a message error.
@@ -237,6 +352,38 @@ pub enum RunOneOutcome<'a, TPud, TTud> {
         message_id: MessageId,
     },
 
+    /// A thread in a process is no longer interested in the answer to a message that it has
+    /// emitted.
+    ThreadCancelMessage {
+        /// Thread that wants to cancel a message.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+
+        /// Message to cancel.
+        message_id: MessageId,
+    },
+
+    /// A thread in a process has created, shared, read or released a blob. The call has been
+    /// processed and the thread resumed.
+    BlobCalled {
+        /// Thread that has called the function.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+    },
+
+    /// A thread in a process has called `accept_directed_messages`. The call has been processed
+    /// and the thread resumed.
+    AcceptDirectedMessagesCalled {
+        /// Thread that has called the function.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+    },
+
+    /// A thread in a process has called a function registered with
+    /// [`add_host_function`](ProcessesCollectionExtrinsicsBuilder::add_host_function). The
+    /// function has been called and the thread resumed.
+    HostFunctionCalled {
+        /// Thread that has called the function.
+        thread: ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud>,
+    },
+
     /// No thread is ready to run. Nothing was done.
     Idle,
 }
@@ -308,7 +455,23 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
                 debug_assert!(thread.user_data().state.is_ready_to_run());
                 let next_msg = match parse_extrinsic_next_message(&mut thread, params) {
                     Ok(m) => m,
-                    Err(_) => panic!(), // TODO:
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::MessageWait(next_msg);
+                RunOneOutcome::ThreadWaitMessage(ProcessesCollectionExtrinsicsThreadWaitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::NextMessages,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let next_msg = match parse_extrinsic_next_messages(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
                 };
                 thread.user_data().state = LocalThreadState::MessageWait(next_msg);
                 RunOneOutcome::ThreadWaitMessage(ProcessesCollectionExtrinsicsThreadWaitMessage {
@@ -324,7 +487,56 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
                 debug_assert!(thread.user_data().state.is_ready_to_run());
                 let emit_msg = match parse_extrinsic_emit_message(&mut thread, params) {
                     Ok(m) => m,
-                    Err(_) => panic!(), // TODO:
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitMessageWithDeadline,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg = match parse_extrinsic_emit_message_with_deadline(&mut thread, params)
+                {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::TryEmitMessage,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg = match parse_extrinsic_try_emit_message(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitMessageChunk,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg = match parse_extrinsic_emit_message_chunk(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
                 };
                 thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
                 RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
@@ -340,7 +552,7 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
                 debug_assert!(thread.user_data().state.is_ready_to_run());
                 let emit_resp = match parse_extrinsic_emit_answer(&mut thread, params) {
                     Ok(m) => m,
-                    Err(_) => panic!(), // TODO:
+                    Err(()) => return abort_invalid_call(thread),
                 };
                 thread.resume(None);
                 RunOneOutcome::ThreadEmitAnswer {
@@ -350,6 +562,42 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
                 }
             }
 
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitAnswerPartial,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_resp = match parse_extrinsic_emit_answer(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.resume(None);
+                RunOneOutcome::ThreadEmitPartialAnswer {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                    message_id: emit_resp.message_id,
+                    response: emit_resp.response,
+                }
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitAnswerChunk,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_resp = match parse_extrinsic_emit_answer(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.resume(None);
+                RunOneOutcome::ThreadEmitAnswerChunk {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                    message_id: emit_resp.message_id,
+                    response: emit_resp.response,
+                }
+            }
+
             processes::RunOneOutcome::Interrupted {
                 mut thread,
                 id: Extrinsic::EmitMessageError,
@@ -358,7 +606,7 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
                 debug_assert!(thread.user_data().state.is_ready_to_run());
                 let emit_msg_error = match parse_extrinsic_emit_message_error(&mut thread, params) {
                     Ok(m) => m,
-                    Err(_) => panic!(), // TODO:
+                    Err(()) => return abort_invalid_call(thread),
                 };
                 thread.resume(None);
                 RunOneOutcome::ThreadEmitMessageError {
@@ -368,74 +616,433 @@ impl<TPud, TTud> ProcessesCollectionExtrinsics<TPud, TTud> {
             }
 
             processes::RunOneOutcome::Interrupted {
-                thread,
+                mut thread,
                 id: Extrinsic::CancelMessage,
                 params,
-            } => unimplemented!(),
-        }
-    }
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let message_id = match parse_extrinsic_cancel_message(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.resume(None);
+                RunOneOutcome::ThreadCancelMessage {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                    message_id,
+                }
+            }
 
-    /// Returns an iterator to all the processes that exist in the collection.
-    pub fn pids<'a>(&'a self) -> impl ExactSizeIterator<Item = Pid> + 'a {
-        self.inner.pids()
-    }
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::SleepUntil,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let deadline = match parse_extrinsic_sleep_until(params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::Sleep(deadline);
+                RunOneOutcome::ThreadSleep(ProcessesCollectionExtrinsicsThreadSleep {
+                    inner: thread,
+                })
+            }
 
-    /// Returns a process by its [`Pid`], if it exists.
-    pub fn process_by_id(
-        &mut self,
-        pid: Pid,
-    ) -> Option<ProcessesCollectionExtrinsicsProc<TPud, TTud>> {
-        let inner = self.inner.process_by_id(pid)?;
-        Some(ProcessesCollectionExtrinsicsProc { inner })
-    }
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::BlobCreate,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let (data, handle_out) = match parse_extrinsic_blob_create(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                let ret = match data {
+                    Some(data) => {
+                        let handle: BlobHandle = self.blob_id_pool.assign();
+                        let mut handle_buf = [0; 8];
+                        LittleEndian::write_u64(&mut handle_buf, u64::from(handle));
+                        match thread.write_memory(handle_out, &handle_buf) {
+                            Ok(()) => {}
+                            Err(()) => return abort_invalid_call(thread),
+                        };
+                        let blob = Blob {
+                            owner: thread.pid(),
+                            readers: HashSet::default(),
+                            data,
+                        };
+                        self.blobs.insert(handle, blob);
+                        0
+                    }
+                    None => 1,
+                };
+                thread.resume(Some(wasmi::RuntimeValue::I32(ret)));
+                RunOneOutcome::BlobCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
 
-    /// Returns a thread by its [`ThreadId`], if it exists.
-    pub fn thread_by_id(
-        &mut self,
-        id: ThreadId,
-    ) -> Option<ProcessesCollectionExtrinsicsThread<TPud, TTud>> {
-        let inner = self.inner.thread_by_id(id)?;
-        Some(ProcessesCollectionExtrinsicsThread::from_inner(inner))
-    }
-}
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::BlobShare,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let (handle, reader) = match parse_extrinsic_blob_share(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                let pid = thread.pid();
+                let ret = match self.blobs.get_mut(&handle) {
+                    Some(blob) if blob.owner == pid => {
+                        blob.readers.insert(reader);
+                        0
+                    }
+                    _ => 1,
+                };
+                thread.resume(Some(wasmi::RuntimeValue::I32(ret)));
+                RunOneOutcome::BlobCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
 
-impl Default for ProcessesCollectionExtrinsicsBuilder {
-    fn default() -> ProcessesCollectionExtrinsicsBuilder {
-        let inner = processes::ProcessesCollectionBuilder::default()
-            .with_extrinsic(
-                "redshirt",
-                "next_message",
-                sig!((I32, I32, I32, I32, I32) -> I32),
-                Extrinsic::NextMessage,
-            )
-            .with_extrinsic(
-                "redshirt",
-                "emit_message",
-                sig!((I32, I32, I32, I32, I32, I32) -> I32),
-                Extrinsic::EmitMessage,
-            )
-            .with_extrinsic(
-                "redshirt",
-                "emit_message_error",
-                sig!((I32)),
-                Extrinsic::EmitMessageError,
-            )
-            .with_extrinsic(
-                "redshirt",
-                "emit_answer",
-                sig!((I32, I32, I32)),
-                Extrinsic::EmitAnswer,
-            )
-            .with_extrinsic(
-                "redshirt",
-                "cancel_message",
-                sig!((I32)),
-                Extrinsic::CancelMessage,
-            );
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::BlobRead,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let (handle, out_pointer, out_size) =
+                    match parse_extrinsic_blob_read(&mut thread, params) {
+                        Ok(m) => m,
+                        Err(()) => return abort_invalid_call(thread),
+                    };
+                let pid = thread.pid();
+                let ret = match self.blobs.get(&handle) {
+                    Some(blob) if blob.owner == pid || blob.readers.contains(&pid) => {
+                        let size = u32::try_from(blob.data.len()).unwrap();
+                        if size <= out_size {
+                            match thread.write_memory(out_pointer, &blob.data) {
+                                Ok(()) => {}
+                                Err(()) => return abort_invalid_call(thread),
+                            };
+                        }
+                        size
+                    }
+                    _ => 0xffffffff,
+                };
+                thread.resume(Some(wasmi::RuntimeValue::I32(ret as i32)));
+                RunOneOutcome::BlobCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
 
-        ProcessesCollectionExtrinsicsBuilder { inner }
-    }
-}
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::BlobRelease,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let handle = match parse_extrinsic_blob_release(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                let pid = thread.pid();
+                if self
+                    .blobs
+                    .get(&handle)
+                    .map_or(false, |blob| blob.owner == pid)
+                {
+                    self.blobs.remove(&handle);
+                }
+                thread.resume(None);
+                RunOneOutcome::BlobCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::AcceptDirectedMessages,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                debug_assert!(params.is_empty());
+                self.directed_messages_accepted.insert(thread.pid());
+                thread.resume(None);
+                RunOneOutcome::AcceptDirectedMessagesCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitDirectedMessage,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg = match parse_extrinsic_emit_directed_message(&mut thread, params) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitDirectedMessageWithDeadline,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg = match parse_extrinsic_emit_directed_message_with_deadline(
+                    &mut thread,
+                    params,
+                ) {
+                    Ok(m) => m,
+                    Err(()) => return abort_invalid_call(thread),
+                };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::EmitDirectedMessageChunk,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let emit_msg =
+                    match parse_extrinsic_emit_directed_message_chunk(&mut thread, params) {
+                        Ok(m) => m,
+                        Err(()) => return abort_invalid_call(thread),
+                    };
+                thread.user_data().state = LocalThreadState::EmitMessage(emit_msg);
+                RunOneOutcome::ThreadEmitMessage(ProcessesCollectionExtrinsicsThreadEmitMessage {
+                    inner: thread,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::ProcessInfo,
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let (out_pointer, out_size) =
+                    match parse_extrinsic_process_info(&mut thread, params) {
+                        Ok(m) => m,
+                        Err(()) => return abort_invalid_call(thread),
+                    };
+                RunOneOutcome::ThreadProcessInfo(ProcessesCollectionExtrinsicsThreadProcessInfo {
+                    inner: thread,
+                    out_pointer,
+                    out_size,
+                })
+            }
+
+            processes::RunOneOutcome::Interrupted {
+                mut thread,
+                id: Extrinsic::Host(function),
+                params,
+            } => {
+                debug_assert!(thread.user_data().state.is_ready_to_run());
+                let pid = thread.pid();
+                let value = function.call(&mut HostFunctionContext::new(pid, &mut thread), &params);
+                thread.resume(value);
+                RunOneOutcome::HostFunctionCalled {
+                    thread: ProcessesCollectionExtrinsicsThreadRegular { inner: thread },
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator to all the processes that exist in the collection.
+    pub fn pids<'a>(&'a self) -> impl ExactSizeIterator<Item = Pid> + 'a {
+        self.inner.pids()
+    }
+
+    /// Returns the total size, in bytes, of the memory of all the processes.
+    pub fn memory_usage(&self) -> u64 {
+        self.inner.memory_usage()
+    }
+
+    /// Destroys all the blobs that have been created by the given process.
+    ///
+    /// Must be called when a process terminates.
+    pub fn release_blobs(&mut self, pid: Pid) {
+        self.blobs.retain(|_, blob| blob.owner != pid);
+    }
+
+    /// Returns true if the given process has called `accept_directed_messages`.
+    pub fn accepts_directed_messages(&self, pid: Pid) -> bool {
+        self.directed_messages_accepted.contains(&pid)
+    }
+
+    /// Forgets that the given process accepts directed messages.
+    ///
+    /// Must be called when a process terminates.
+    pub fn stop_accepting_directed_messages(&mut self, pid: Pid) {
+        self.directed_messages_accepted.remove(&pid);
+    }
+
+    /// Returns a process by its [`Pid`], if it exists.
+    pub fn process_by_id(
+        &mut self,
+        pid: Pid,
+    ) -> Option<ProcessesCollectionExtrinsicsProc<TPud, TTud>> {
+        let inner = self.inner.process_by_id(pid)?;
+        Some(ProcessesCollectionExtrinsicsProc { inner })
+    }
+
+    /// Returns a thread by its [`ThreadId`], if it exists.
+    pub fn thread_by_id(
+        &mut self,
+        id: ThreadId,
+    ) -> Option<ProcessesCollectionExtrinsicsThread<TPud, TTud>> {
+        let inner = self.inner.thread_by_id(id)?;
+        Some(ProcessesCollectionExtrinsicsThread::from_inner(inner))
+    }
+}
+
+impl Default for ProcessesCollectionExtrinsicsBuilder {
+    fn default() -> ProcessesCollectionExtrinsicsBuilder {
+        let inner = processes::ProcessesCollectionBuilder::default()
+            .with_extrinsic(
+                "redshirt",
+                "next_message",
+                sig!((I32, I32, I32, I32, I32) -> I32),
+                Extrinsic::NextMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "next_messages",
+                sig!((I32, I32, I32, I32, I32, I32) -> I32),
+                Extrinsic::NextMessages,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_message",
+                sig!((I32, I32, I32, I32, I32, I32) -> I32),
+                Extrinsic::EmitMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_message_with_deadline",
+                sig!((I32, I32, I32, I32, I64, I32) -> I32),
+                Extrinsic::EmitMessageWithDeadline,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "try_emit_message",
+                sig!((I32, I32, I32, I32, I32) -> I32),
+                Extrinsic::TryEmitMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_message_chunk",
+                sig!((I32, I32, I32, I32) -> I32),
+                Extrinsic::EmitMessageChunk,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_message_error",
+                sig!((I32)),
+                Extrinsic::EmitMessageError,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_answer",
+                sig!((I32, I32, I32)),
+                Extrinsic::EmitAnswer,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_answer_partial",
+                sig!((I32, I32, I32)),
+                Extrinsic::EmitAnswerPartial,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_answer_chunk",
+                sig!((I32, I32, I32)),
+                Extrinsic::EmitAnswerChunk,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "cancel_message",
+                sig!((I32)),
+                Extrinsic::CancelMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "sleep_until",
+                sig!((I64)),
+                Extrinsic::SleepUntil,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "accept_directed_messages",
+                sig!(()),
+                Extrinsic::AcceptDirectedMessages,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_directed_message",
+                sig!((I32, I32, I32, I32, I32) -> I32),
+                Extrinsic::EmitDirectedMessage,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_directed_message_with_deadline",
+                sig!((I32, I32, I32, I64, I32) -> I32),
+                Extrinsic::EmitDirectedMessageWithDeadline,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "emit_directed_message_chunk",
+                sig!((I32, I32, I32) -> I32),
+                Extrinsic::EmitDirectedMessageChunk,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "process_info",
+                sig!((I32, I32) -> I32),
+                Extrinsic::ProcessInfo,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "blob_create",
+                sig!((I32, I32, I32) -> I32),
+                Extrinsic::BlobCreate,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "blob_share",
+                sig!((I32, I32) -> I32),
+                Extrinsic::BlobShare,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "blob_read",
+                sig!((I32, I32, I32) -> I32),
+                Extrinsic::BlobRead,
+            )
+            .with_extrinsic(
+                "redshirt",
+                "blob_release",
+                sig!((I32)),
+                Extrinsic::BlobRelease,
+            );
+
+        ProcessesCollectionExtrinsicsBuilder { inner }
+    }
+}
 
 impl ProcessesCollectionExtrinsicsBuilder {
     /// Allocates a `Pid` that will not be used by any process.
@@ -448,10 +1055,37 @@ impl ProcessesCollectionExtrinsicsBuilder {
         self.inner.reserve_pid()
     }
 
+    /// Registers a function that WASM modules can import under the given module and function
+    /// name.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a function with this module/name combination has already been registered,
+    /// including the functions of the `redshirt` module.
+    ///
+    pub fn add_host_function(
+        &mut self,
+        interface: impl Into<Cow<'static, str>>,
+        f_name: impl Into<Cow<'static, str>>,
+        signature: Signature,
+        function: impl HostFunction + 'static,
+    ) {
+        let inner = mem::replace(&mut self.inner, Default::default());
+        self.inner = inner.with_extrinsic(
+            interface,
+            f_name,
+            signature,
+            Extrinsic::Host(Box::new(function)),
+        );
+    }
+
     /// Turns the builder into a [`ProcessesCollectionExtrinsics`].
     pub fn build<TPud, TTud>(self) -> ProcessesCollectionExtrinsics<TPud, TTud> {
         ProcessesCollectionExtrinsics {
             inner: self.inner.build(),
+            blob_id_pool: IdPool::new(),
+            blobs: HashMap::default(),
+            directed_messages_accepted: HashSet::default(),
         }
     }
 }
@@ -468,6 +1102,11 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsProc<'a, TPud, TTud> {
         self.inner.user_data()
     }
 
+    /// Returns the size, in bytes, of the memory of the process.
+    pub fn memory_size(&self) -> usize {
+        self.inner.memory_size()
+    }
+
     /// Adds a new thread to the process, starting the function with the given index and passing
     /// the given parameters.
     ///
@@ -505,8 +1144,12 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsProc<'a, TPud, TTud> {
 
     /// Aborts the process and returns the associated user data.
     pub fn abort(self) -> (TPud, Vec<(ThreadId, TTud)>) {
-        //self.inner.abort()
-        unimplemented!() // TODO:
+        let (user_data, dead_threads) = self.inner.abort();
+        let dead_threads = dead_threads
+            .into_iter()
+            .map(|(id, state)| (id, state.external_user_data))
+            .collect();
+        (user_data, dead_threads)
     }
 }
 
@@ -528,18 +1171,21 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThread<'a, TPud, TTud> {
             Regular,
             Emit,
             Wait,
+            Sleep,
         }
 
         let ty = match inner.user_data().state {
             LocalThreadState::ReadyToRun => Ty::Regular,
             LocalThreadState::EmitMessage(_) => Ty::Emit,
             LocalThreadState::MessageWait(_) => Ty::Wait,
+            LocalThreadState::Sleep(_) => Ty::Sleep,
         };
 
         match ty {
             Ty::Regular => From::from(ProcessesCollectionExtrinsicsThreadRegular { inner }),
             Ty::Emit => From::from(ProcessesCollectionExtrinsicsThreadEmitMessage { inner }),
             Ty::Wait => From::from(ProcessesCollectionExtrinsicsThreadWaitMessage { inner }),
+            Ty::Sleep => From::from(ProcessesCollectionExtrinsicsThreadSleep { inner }),
         }
     }
 }
@@ -568,6 +1214,14 @@ impl<'a, TPud, TTud> From<ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPu
     }
 }
 
+impl<'a, TPud, TTud> From<ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>>
+    for ProcessesCollectionExtrinsicsThread<'a, TPud, TTud>
+{
+    fn from(thread: ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>) -> Self {
+        ProcessesCollectionExtrinsicsThread::Sleep(thread)
+    }
+}
+
 impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
     for ProcessesCollectionExtrinsicsThread<'a, TPud, TTud>
 {
@@ -579,6 +1233,7 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
             ProcessesCollectionExtrinsicsThread::Regular(t) => t.tid(),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => t.tid(),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => t.tid(),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => t.tid(),
         }
     }
 
@@ -587,6 +1242,7 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
             ProcessesCollectionExtrinsicsThread::Regular(t) => t.pid(),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => t.pid(),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => t.pid(),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => t.pid(),
         }
     }
 
@@ -595,6 +1251,7 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
             ProcessesCollectionExtrinsicsThread::Regular(t) => t.next_thread(),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => t.next_thread(),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => t.next_thread(),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => t.next_thread(),
         }
     }
 
@@ -603,6 +1260,7 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
             ProcessesCollectionExtrinsicsThread::Regular(t) => t.process_user_data(),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => t.process_user_data(),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => t.process_user_data(),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => t.process_user_data(),
         }
     }
 
@@ -611,6 +1269,7 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
             ProcessesCollectionExtrinsicsThread::Regular(t) => t.user_data(),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => t.user_data(),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => t.user_data(),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => t.user_data(),
         }
     }
 }
@@ -625,6 +1284,7 @@ where
             ProcessesCollectionExtrinsicsThread::Regular(t) => fmt::Debug::fmt(t, f),
             ProcessesCollectionExtrinsicsThread::EmitMessage(t) => fmt::Debug::fmt(t, f),
             ProcessesCollectionExtrinsicsThread::WaitMessage(t) => fmt::Debug::fmt(t, f),
+            ProcessesCollectionExtrinsicsThread::Sleep(t) => fmt::Debug::fmt(t, f),
         }
     }
 }
@@ -696,6 +1356,56 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadEmitMessage<'a, TPud, TT
         }
     }
 
+    /// Returns the value of the monotonic clock, in nanoseconds, after which the message must
+    /// be answered with an error, if any.
+    pub fn deadline(&mut self) -> Option<u64> {
+        if let LocalThreadState::EmitMessage(ref emit) = self.inner.user_data().state {
+            emit.deadline
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Returns the process the message is directly destined to, if it has been emitted with
+    /// `emit_directed_message`.
+    pub fn target(&mut self) -> Option<Pid> {
+        if let LocalThreadState::EmitMessage(ref emit) = self.inner.user_data().state {
+            emit.target
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// True if the message is only a chunk, and must be concatenated with the next message
+    /// emitted by the same process on the same interface.
+    pub fn is_chunk(&mut self) -> bool {
+        if let LocalThreadState::EmitMessage(ref emit) = self.inner.user_data().state {
+            emit.chunk
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// True if the emission must fail immediately, rather than being delayed or queued, if the
+    /// interface has no handler or if the queue of the handler is full.
+    pub fn is_no_block(&mut self) -> bool {
+        if let LocalThreadState::EmitMessage(ref emit) = self.inner.user_data().state {
+            emit.no_block
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Returns the message that the thread wants to emit, so that it can be inspected or
+    /// modified before being accepted.
+    pub fn message_mut(&mut self) -> &mut EncodedMessage {
+        if let LocalThreadState::EmitMessage(ref mut emit) = self.inner.user_data().state {
+            &mut emit.message
+        } else {
+            unreachable!()
+        }
+    }
+
     /// Returns the message to emit and resumes the thread.
     ///
     /// # Panic
@@ -722,7 +1432,11 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadEmitMessage<'a, TPud, TT
 
             let mut buf = [0; 8];
             LittleEndian::write_u64(&mut buf, From::from(message_id));
-            self.inner.write_memory(message_id_write, &buf).unwrap();
+            // The range has been checked when parsing the extrinsic call.
+            match self.inner.write_memory(message_id_write, &buf) {
+                Ok(()) => {}
+                Err(()) => unreachable!(),
+            }
         } else {
             assert!(message_id.is_none());
         }
@@ -735,6 +1449,14 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadEmitMessage<'a, TPud, TT
     pub fn refuse_emit(mut self) {
         self.inner.resume(Some(wasmi::RuntimeValue::I32(1)));
     }
+
+    /// Resumes the thread, signalling that the message couldn't be emitted without blocking.
+    ///
+    /// See [`is_no_block`](ProcessesCollectionExtrinsicsThreadEmitMessage::is_no_block).
+    pub fn refuse_emit_would_block(mut self) {
+        self.inner.user_data().state = LocalThreadState::ReadyToRun;
+        self.inner.resume(Some(wasmi::RuntimeValue::I32(2)));
+    }
 }
 
 impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
@@ -804,20 +1526,92 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TT
         }
     }
 
-    /// Resume the thread, sending back a message.
+    /// Returns the maximum number of messages that the thread accepts at once if it has called
+    /// `next_messages`, or `None` if it has called `next_message`.
     ///
-    /// `index` must be the index within the list returned by [`message_ids_iter`].
+    /// If `Some`, the thread must be resumed with
+    /// [`resume_messages`](ProcessesCollectionExtrinsicsThreadWaitMessage::resume_messages)
+    /// rather than
+    /// [`resume_message`](ProcessesCollectionExtrinsicsThreadWaitMessage::resume_message).
+    pub fn max_messages(&mut self) -> Option<u32> {
+        if let LocalThreadState::MessageWait(ref wait) = self.inner.user_data().state {
+            wait.max_messages
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Resume the thread, sending back a list of messages.
+    ///
+    /// `indices` must contain, for each message, the index within the list returned by
+    /// [`message_ids_iter`]. `messages` must be the encoded list of messages.
     ///
     /// # Panic
     ///
-    /// - Panics if the message is too large. You should make sure this is not the case before
-    /// calling this function.
-    /// - Panics if `index` is too large.
+    /// - Panics if the encoded list is too large. You should make sure this is not the case
+    /// before calling this function.
+    /// - Panics if one of the indices is too large.
     ///
-    pub fn resume_message(
+    pub fn resume_messages(
         mut self,
-        index: usize,
-        message: EncodedMessage,
+        indices: &[usize],
+        messages: EncodedMessage,
+    ) -> ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud> {
+        let wait = {
+            match mem::replace(
+                &mut self.inner.user_data().state,
+                LocalThreadState::ReadyToRun,
+            ) {
+                LocalThreadState::MessageWait(wait) => wait,
+                _ => unreachable!(),
+            }
+        };
+
+        debug_assert!(wait.max_messages.is_some());
+        let messages_size_u32 = u32::try_from(messages.0.len()).unwrap();
+        assert!(wait.out_size >= messages_size_u32);
+
+        // Write the messages in the process's memory. The range has been checked when parsing
+        // the extrinsic call.
+        match self.inner.write_memory(wait.out_pointer, &messages.0) {
+            Ok(()) => {}
+            Err(()) => unreachable!(),
+        };
+
+        // Zero the corresponding entries in the messages to wait upon.
+        for index in indices {
+            assert!(*index < wait.msg_ids.len());
+            match self.inner.write_memory(
+                wait.msg_ids_ptr + u32::try_from(*index).unwrap() * 8,
+                &[0; 8],
+            ) {
+                Ok(()) => {}
+                // The list of message ids has been successfully read when parsing the call.
+                Err(()) => unreachable!(),
+            };
+        }
+
+        self.inner.resume(Some(wasmi::RuntimeValue::I32(
+            i32::try_from(messages_size_u32).unwrap(),
+        )));
+
+        ProcessesCollectionExtrinsicsThreadRegular { inner: self.inner }
+    }
+
+    /// Resume the thread, sending back a message.
+    ///
+    /// `index` must be the index within the list returned by [`message_ids_iter`].
+    ///
+    /// # Panic
+    ///
+    /// - Panics if the message is too large. You should make sure this is not the case before
+    /// calling this function.
+    /// - Panics if `index` is too large.
+    ///
+    pub fn resume_message(
+        mut self,
+        index: usize,
+        message: EncodedMessage,
     ) -> ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud> {
         let wait = {
             match mem::replace(
@@ -833,10 +1627,11 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TT
         let message_size_u32 = u32::try_from(message.0.len()).unwrap();
         assert!(wait.out_size >= message_size_u32);
 
-        // Write the message in the process's memory.
+        // Write the message in the process's memory. The range has been checked when parsing
+        // the extrinsic call.
         match self.inner.write_memory(wait.out_pointer, &message.0) {
             Ok(()) => {}
-            Err(_) => panic!(), // TODO: can legit happen
+            Err(()) => unreachable!(),
         };
 
         // Zero the corresponding entry in the messages to wait upon.
@@ -845,7 +1640,8 @@ impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadWaitMessage<'a, TPud, TT
             &[0; 8],
         ) {
             Ok(()) => {}
-            Err(_) => panic!(), // TODO: can legit happen
+            // The list of message ids has been successfully read when parsing the call.
+            Err(()) => unreachable!(),
         };
 
         self.inner.user_data().state = LocalThreadState::ReadyToRun;
@@ -931,6 +1727,124 @@ where
     }
 }
 
+impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud> {
+    /// Returns the value of the monotonic clock, in nanoseconds, until which the thread wants
+    /// to sleep.
+    pub fn deadline(&mut self) -> u64 {
+        if let LocalThreadState::Sleep(deadline) = self.inner.user_data().state {
+            deadline
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Wakes up the thread and resumes its execution.
+    pub fn resume(mut self) -> ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud> {
+        self.inner.user_data().state = LocalThreadState::ReadyToRun;
+        self.inner.resume(None);
+        ProcessesCollectionExtrinsicsThreadRegular { inner: self.inner }
+    }
+}
+
+impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
+    for ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>
+{
+    type ProcessUserData = TPud;
+    type ThreadUserData = TTud;
+
+    fn tid(&mut self) -> ThreadId {
+        self.inner.tid()
+    }
+
+    fn pid(&self) -> Pid {
+        self.inner.pid()
+    }
+
+    fn next_thread(self) -> Option<ProcessesCollectionExtrinsicsThread<'a, TPud, TTud>> {
+        self.inner
+            .next_thread()
+            .map(ProcessesCollectionExtrinsicsThread::from_inner)
+    }
+
+    fn process_user_data(&mut self) -> &mut TPud {
+        self.inner.process_user_data()
+    }
+
+    fn user_data(&mut self) -> &mut TTud {
+        &mut self.inner.user_data().external_user_data
+    }
+}
+
+impl<'a, TPud, TTud> fmt::Debug for ProcessesCollectionExtrinsicsThreadSleep<'a, TPud, TTud>
+where
+    TPud: fmt::Debug,
+    TTud: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadProcessInfo<'a, TPud, TTud> {
+    /// Writes the SCALE-encoded information in the memory of the process, if it fits, and
+    /// resumes the thread. The thread is resumed with the size of the information.
+    pub fn resume(
+        mut self,
+        info: &[u8],
+    ) -> ProcessesCollectionExtrinsicsThreadRegular<'a, TPud, TTud> {
+        let size = u32::try_from(info.len()).unwrap();
+        if size <= self.out_size {
+            // The range has been checked when parsing the extrinsic call.
+            match self.inner.write_memory(self.out_pointer, info) {
+                Ok(()) => {}
+                Err(()) => unreachable!(),
+            };
+        }
+        self.inner
+            .resume(Some(wasmi::RuntimeValue::I32(size as i32)));
+        ProcessesCollectionExtrinsicsThreadRegular { inner: self.inner }
+    }
+}
+
+impl<'a, TPud, TTud> ProcessesCollectionExtrinsicsThreadAccess<'a>
+    for ProcessesCollectionExtrinsicsThreadProcessInfo<'a, TPud, TTud>
+{
+    type ProcessUserData = TPud;
+    type ThreadUserData = TTud;
+
+    fn tid(&mut self) -> ThreadId {
+        self.inner.tid()
+    }
+
+    fn pid(&self) -> Pid {
+        self.inner.pid()
+    }
+
+    fn next_thread(self) -> Option<ProcessesCollectionExtrinsicsThread<'a, TPud, TTud>> {
+        self.inner
+            .next_thread()
+            .map(ProcessesCollectionExtrinsicsThread::from_inner)
+    }
+
+    fn process_user_data(&mut self) -> &mut TPud {
+        self.inner.process_user_data()
+    }
+
+    fn user_data(&mut self) -> &mut TTud {
+        &mut self.inner.user_data().external_user_data
+    }
+}
+
+impl<'a, TPud, TTud> fmt::Debug for ProcessesCollectionExtrinsicsThreadProcessInfo<'a, TPud, TTud>
+where
+    TPud: fmt::Debug,
+    TTud: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
 impl LocalThreadState {
     /// True if `self` is equal to [`LocalThreadState::ReadyToRun`].
     fn is_ready_to_run(&self) -> bool {
@@ -941,6 +1855,39 @@ impl LocalThreadState {
     }
 }
 
+/// Aborts the process of a thread that has called an extrinsic with invalid parameters.
+fn abort_invalid_call<'a, TPud, TTud>(
+    thread: processes::ProcessesCollectionThread<'a, TPud, LocalThreadUserData<TTud>>,
+) -> RunOneOutcome<'a, TPud, TTud> {
+    let (pid, user_data, dead_threads) = thread.abort();
+    RunOneOutcome::ProcessFinished {
+        pid,
+        user_data,
+        dead_threads: dead_threads
+            .into_iter()
+            .map(|(id, state)| (id, state.external_user_data))
+            .collect(),
+        outcome: Err(vm::ExecError::InvalidExtrinsicCall),
+    }
+}
+
+/// Returns an error if the given range isn't entirely within the memory of the process.
+///
+/// The memory of a process can only grow. A range that passes this check can therefore later be
+/// written to without failing.
+fn check_memory_range<TPud, TTud>(
+    thread: &processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    offset: u32,
+    size: u32,
+) -> Result<(), ()> {
+    let end = offset.checked_add(size).ok_or(())?;
+    if usize::try_from(end).map_err(|_| ())? <= thread.memory_size() {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
 /// Analyzes a call to `next_message` made by the given thread.
 ///
 /// The `thread` parameter is only used in order to read memory from the process. This function
@@ -974,6 +1921,7 @@ fn parse_extrinsic_next_message<TPud, TTud>(
 
     let out_pointer = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
     let out_size = u32::try_from(params[3].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+    check_memory_range(thread, out_pointer, out_size)?;
     let block = params[4].try_into::<i32>().ok_or(())? != 0;
 
     Ok(MessageWait {
@@ -982,9 +1930,37 @@ fn parse_extrinsic_next_message<TPud, TTud>(
         out_pointer,
         out_size,
         block,
+        max_messages: None,
     })
 }
 
+/// Analyzes a call to `next_messages` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_next_messages<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    mut params: Vec<wasmi::RuntimeValue>,
+) -> Result<MessageWait, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 6);
+
+    let max_messages = {
+        let param = params.pop().unwrap();
+        u32::try_from(param.try_into::<i32>().ok_or(())?).map_err(|_| ())?
+    };
+    if max_messages == 0 {
+        return Err(());
+    }
+
+    let mut wait = parse_extrinsic_next_message(thread, params)?;
+    wait.max_messages = Some(max_messages);
+    Ok(wait)
+}
+
 /// Analyzes a call to `emit_message` made by the given thread.
 ///
 /// The `thread` parameter is only used in order to read memory from the process. This function
@@ -1009,32 +1985,15 @@ fn parse_extrinsic_emit_message<TPud, TTud>(
     let message = {
         let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
         let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
-        let mut out_msg = Vec::new();
-        for buf_n in 0..num_bufs {
-            let sub_buf_ptr = thread.read_memory(addr + 8 * buf_n, 4).map_err(|_| ())?;
-            let sub_buf_ptr = LittleEndian::read_u32(&sub_buf_ptr);
-            let sub_buf_sz = thread
-                .read_memory(addr + 8 * buf_n + 4, 4)
-                .map_err(|_| ())?;
-            let sub_buf_sz = LittleEndian::read_u32(&sub_buf_sz);
-            if out_msg.len() + usize::try_from(sub_buf_sz).map_err(|_| ())? >= 16 * 1024 * 1024 {
-                // TODO: arbitrary maximum message length
-                panic!("Max message length reached");
-                //return Err(());
-            }
-            out_msg.extend_from_slice(
-                &thread
-                    .read_memory(sub_buf_ptr, sub_buf_sz)
-                    .map_err(|_| ())?,
-            );
-        }
-        EncodedMessage(out_msg)
+        read_message_buffers(thread, addr, num_bufs)?
     };
 
     let needs_answer = params[3].try_into::<i32>().ok_or(())? != 0;
     let allow_delay = params[4].try_into::<i32>().ok_or(())? != 0;
     let message_id_write = if needs_answer {
-        Some(u32::try_from(params[5].try_into::<i32>().ok_or(())?).map_err(|_| ())?)
+        let addr = u32::try_from(params[5].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        check_memory_range(thread, addr, 8)?;
+        Some(addr)
     } else {
         None
     };
@@ -1044,10 +2003,188 @@ fn parse_extrinsic_emit_message<TPud, TTud>(
         message_id_write,
         message,
         allow_delay,
+        chunk: false,
+        no_block: false,
+        deadline: None,
+        target: None,
+    })
+}
+
+/// Analyzes a call to `emit_message_with_deadline` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_emit_message_with_deadline<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 6);
+
+    let interface: InterfaceHash = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        InterfaceHash::from(
+            <[u8; 32]>::try_from(&thread.read_memory(addr, 32)?[..]).map_err(|_| ())?,
+        )
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    let allow_delay = params[3].try_into::<i32>().ok_or(())? != 0;
+    let deadline = params[4].try_into::<i64>().ok_or(())? as u64;
+    let message_id_write = {
+        let addr = u32::try_from(params[5].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        check_memory_range(thread, addr, 8)?;
+        Some(addr)
+    };
+
+    Ok(EmitMessage {
+        interface,
+        message_id_write,
+        message,
+        allow_delay,
+        chunk: false,
+        no_block: false,
+        deadline: Some(deadline),
+        target: None,
     })
 }
 
-/// Analyzes a call to `emit_answer` made by the given thread.
+/// Analyzes a call to `try_emit_message` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_try_emit_message<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 5);
+
+    let interface: InterfaceHash = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        InterfaceHash::from(
+            <[u8; 32]>::try_from(&thread.read_memory(addr, 32)?[..]).map_err(|_| ())?,
+        )
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    let needs_answer = params[3].try_into::<i32>().ok_or(())? != 0;
+    let message_id_write = if needs_answer {
+        let addr = u32::try_from(params[4].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        check_memory_range(thread, addr, 8)?;
+        Some(addr)
+    } else {
+        None
+    };
+
+    Ok(EmitMessage {
+        interface,
+        message_id_write,
+        message,
+        allow_delay: false,
+        chunk: false,
+        no_block: true,
+        deadline: None,
+        target: None,
+    })
+}
+
+/// Analyzes a call to `emit_message_chunk` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_emit_message_chunk<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 4);
+
+    let interface: InterfaceHash = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        InterfaceHash::from(
+            <[u8; 32]>::try_from(&thread.read_memory(addr, 32)?[..]).map_err(|_| ())?,
+        )
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    let allow_delay = params[3].try_into::<i32>().ok_or(())? != 0;
+
+    Ok(EmitMessage {
+        interface,
+        message_id_write: None,
+        message,
+        allow_delay,
+        chunk: true,
+        no_block: false,
+        deadline: None,
+        target: None,
+    })
+}
+
+/// Reads the body of a message from a list of `num_bufs` buffers whose pointers and sizes are
+/// located at `addr` in the memory of the process.
+///
+/// Returns an error if the memory is out of range or if the total size of the message is larger
+/// than [`MAX_MESSAGE_SIZE`](redshirt_syscalls_interface::MAX_MESSAGE_SIZE). The size is checked
+/// before anything is copied.
+fn read_message_buffers<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    addr: u32,
+    num_bufs: u32,
+) -> Result<EncodedMessage, ()> {
+    let mut bufs = Vec::new();
+    let mut total_size = 0usize;
+    for buf_n in 0..num_bufs {
+        let pair_addr = buf_n
+            .checked_mul(8)
+            .and_then(|offset| addr.checked_add(offset))
+            .ok_or(())?;
+        let pair = thread.read_memory(pair_addr, 8)?;
+        let sub_buf_ptr = LittleEndian::read_u32(&pair[0..4]);
+        let sub_buf_sz = LittleEndian::read_u32(&pair[4..8]);
+        total_size = total_size
+            .checked_add(usize::try_from(sub_buf_sz).map_err(|_| ())?)
+            .ok_or(())?;
+        if total_size > redshirt_syscalls_interface::MAX_MESSAGE_SIZE {
+            return Err(());
+        }
+        bufs.push((sub_buf_ptr, sub_buf_sz));
+    }
+
+    let mut out_msg = Vec::with_capacity(total_size);
+    for (sub_buf_ptr, sub_buf_sz) in bufs {
+        out_msg.extend_from_slice(&thread.read_memory(sub_buf_ptr, sub_buf_sz)?);
+    }
+    Ok(EncodedMessage(out_msg))
+}
+
+/// Analyzes a call to `emit_answer`, `emit_answer_partial` or `emit_answer_chunk` made by the
+/// given thread.
 ///
 /// The `thread` parameter is only used in order to read memory from the process. This function
 /// has no side effect.
@@ -1070,6 +2207,9 @@ fn parse_extrinsic_emit_answer<TPud, TTud>(
     let response = {
         let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
         let sz = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        if usize::try_from(sz).map_err(|_| ())? > redshirt_syscalls_interface::MAX_MESSAGE_SIZE {
+            return Err(());
+        }
         EncodedMessage(thread.read_memory(addr, sz)?)
     };
 
@@ -1102,3 +2242,303 @@ fn parse_extrinsic_emit_message_error<TPud, TTud>(
 
     Ok(msg_id)
 }
+
+/// Analyzes a call to `cancel_message` made by the given thread.
+/// Returns the message to cancel.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_cancel_message<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<MessageId, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 1);
+
+    let msg_id = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        MessageId::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    Ok(msg_id)
+}
+
+/// Analyzes a call to `emit_directed_message` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_emit_directed_message<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 5);
+
+    let target = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        Pid::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    let needs_answer = params[3].try_into::<i32>().ok_or(())? != 0;
+    let message_id_write = if needs_answer {
+        let addr = u32::try_from(params[4].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        check_memory_range(thread, addr, 8)?;
+        Some(addr)
+    } else {
+        None
+    };
+
+    Ok(EmitMessage {
+        interface: redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE,
+        message_id_write,
+        message,
+        allow_delay: false,
+        chunk: false,
+        no_block: false,
+        deadline: None,
+        target: Some(target),
+    })
+}
+
+/// Analyzes a call to `emit_directed_message_with_deadline` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_emit_directed_message_with_deadline<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 5);
+
+    let target = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        Pid::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    let deadline = params[3].try_into::<i64>().ok_or(())? as u64;
+    let message_id_write = {
+        let addr = u32::try_from(params[4].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        check_memory_range(thread, addr, 8)?;
+        Some(addr)
+    };
+
+    Ok(EmitMessage {
+        interface: redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE,
+        message_id_write,
+        message,
+        allow_delay: false,
+        chunk: false,
+        no_block: false,
+        deadline: Some(deadline),
+        target: Some(target),
+    })
+}
+
+/// Analyzes a call to `emit_directed_message_chunk` made by the given thread.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_emit_directed_message_chunk<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<EmitMessage, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 3);
+
+    let target = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        Pid::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    let message = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let num_bufs = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        read_message_buffers(thread, addr, num_bufs)?
+    };
+
+    Ok(EmitMessage {
+        interface: redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE,
+        message_id_write: None,
+        message,
+        allow_delay: false,
+        chunk: true,
+        no_block: false,
+        deadline: None,
+        target: Some(target),
+    })
+}
+
+/// Analyzes a call to `process_info` made by a thread.
+/// Returns the offset and size of the buffer where to write the information.
+///
+/// The `thread` parameter is only used in order to check the memory range. This function has no
+/// side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_process_info<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<(u32, u32), ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 2);
+
+    let out_pointer = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+    let out_size = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+    check_memory_range(thread, out_pointer, out_size)?;
+    Ok((out_pointer, out_size))
+}
+
+/// Analyzes a call to `sleep_until` made by the given thread.
+/// Returns the value of the monotonic clock until which to sleep.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_sleep_until(params: Vec<wasmi::RuntimeValue>) -> Result<u64, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 1);
+
+    let deadline = params[0].try_into::<i64>().ok_or(())?;
+    Ok(deadline as u64)
+}
+
+/// Analyzes a call to `blob_create` made by the given thread.
+/// Returns the content of the blob to create, or `None` if it is larger than
+/// [`MAX_BLOB_SIZE`](redshirt_syscalls_interface::MAX_BLOB_SIZE), and where to write its handle.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_blob_create<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<(Option<Vec<u8>>, u32), ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 3);
+
+    let data = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let sz = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        if usize::try_from(sz).map_err(|_| ())? > redshirt_syscalls_interface::MAX_BLOB_SIZE {
+            None
+        } else {
+            Some(thread.read_memory(addr, sz)?)
+        }
+    };
+
+    let handle_out = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+
+    Ok((data, handle_out))
+}
+
+/// Analyzes a call to `blob_share` made by the given thread.
+/// Returns the blob to share, and the process to share it with.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_blob_share<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<(BlobHandle, Pid), ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 2);
+
+    let handle = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        BlobHandle::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    let reader = {
+        let addr = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        Pid::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    Ok((handle, reader))
+}
+
+/// Analyzes a call to `blob_read` made by the given thread.
+/// Returns the blob to read, and the location and size of the output buffer.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_blob_read<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<(BlobHandle, u32, u32), ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 3);
+
+    let handle = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        BlobHandle::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    let out_pointer = u32::try_from(params[1].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+    let out_size = u32::try_from(params[2].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+
+    Ok((handle, out_pointer, out_size))
+}
+
+/// Analyzes a call to `blob_release` made by the given thread.
+/// Returns the blob to release.
+///
+/// The `thread` parameter is only used in order to read memory from the process. This function
+/// has no side effect.
+///
+/// Returns an error if the call is invalid.
+fn parse_extrinsic_blob_release<TPud, TTud>(
+    thread: &mut processes::ProcessesCollectionThread<TPud, LocalThreadUserData<TTud>>,
+    params: Vec<wasmi::RuntimeValue>,
+) -> Result<BlobHandle, ()> {
+    // We use an assert here rather than a runtime check because the WASM VM (rather than us) is
+    // supposed to check the function signature.
+    assert_eq!(params.len(), 1);
+
+    let handle = {
+        let addr = u32::try_from(params[0].try_into::<i32>().ok_or(())?).map_err(|_| ())?;
+        let buf = thread.read_memory(addr, 8)?;
+        BlobHandle::from(byteorder::LittleEndian::read_u64(&buf))
+    };
+
+    Ok(handle)
+}
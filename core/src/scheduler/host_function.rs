@@ -0,0 +1,107 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Functions provided by the embedder that WASM modules can import directly.
+//!
+//! The normal way for a program to interact with the outside is to emit messages on interfaces.
+//! In some situations, for example platform-specific fast paths, the overhead of messages isn't
+//! acceptable. A [`HostFunction`] is a function that WASM modules can import and call
+//! synchronously, in the same way as the functions of the `redshirt` module.
+//!
+//! The function is called while the thread is paused, and its return value is passed back to
+//! the thread when it resumes.
+
+use crate::scheduler::processes;
+use crate::Pid;
+
+use alloc::vec::Vec;
+
+/// Function that WASM modules can import.
+pub trait HostFunction: Send {
+    /// Called when a thread calls the function.
+    ///
+    /// The returned value must match the return type of the signature the function has been
+    /// registered with.
+    fn call(
+        &mut self,
+        context: &mut HostFunctionContext,
+        params: &[wasmi::RuntimeValue],
+    ) -> Option<wasmi::RuntimeValue>;
+}
+
+/// Access to the process that has called a [`HostFunction`].
+pub struct HostFunctionContext<'a> {
+    /// Process that has called the function.
+    pid: Pid,
+    /// Access to the memory of the process.
+    memory: &'a mut dyn ThreadMemory,
+}
+
+/// Abstraction over the memory of a thread, in order to not expose the generic parameters of
+/// the processes collection.
+pub(super) trait ThreadMemory {
+    fn read_memory(&mut self, offset: u32, size: u32) -> Result<Vec<u8>, ()>;
+    fn write_memory(&mut self, offset: u32, value: &[u8]) -> Result<(), ()>;
+}
+
+impl<'a> HostFunctionContext<'a> {
+    /// Builds a new context.
+    pub(super) fn new(pid: Pid, memory: &'a mut dyn ThreadMemory) -> Self {
+        HostFunctionContext { pid, memory }
+    }
+
+    /// Returns the [`Pid`] of the process that has called the function.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Reads the memory of the process at the given location.
+    ///
+    /// Returns an error if the range is invalid or out of range.
+    pub fn read_memory(&mut self, offset: u32, size: u32) -> Result<Vec<u8>, ()> {
+        self.memory.read_memory(offset, size)
+    }
+
+    /// Writes the data at the given location in the memory of the process.
+    ///
+    /// Returns an error if the range is invalid or out of range.
+    pub fn write_memory(&mut self, offset: u32, value: &[u8]) -> Result<(), ()> {
+        self.memory.write_memory(offset, value)
+    }
+}
+
+impl<'a, TPud, TTud> ThreadMemory for processes::ProcessesCollectionThread<'a, TPud, TTud> {
+    fn read_memory(&mut self, offset: u32, size: u32) -> Result<Vec<u8>, ()> {
+        processes::ProcessesCollectionThread::read_memory(self, offset, size)
+    }
+
+    fn write_memory(&mut self, offset: u32, value: &[u8]) -> Result<(), ()> {
+        processes::ProcessesCollectionThread::write_memory(self, offset, value)
+    }
+}
+
+impl<F> HostFunction for F
+where
+    F: FnMut(&mut HostFunctionContext, &[wasmi::RuntimeValue]) -> Option<wasmi::RuntimeValue>
+        + Send,
+{
+    fn call(
+        &mut self,
+        context: &mut HostFunctionContext,
+        params: &[wasmi::RuntimeValue],
+    ) -> Option<wasmi::RuntimeValue> {
+        (self)(context, params)
+    }
+}
@@ -17,17 +17,28 @@ use crate::id_pool::IdPool;
 use crate::module::Module;
 use crate::scheduler::{
     extrinsics::{self, ProcessesCollectionExtrinsicsThreadAccess as _},
+    host_function::HostFunction,
+    middleware::{Middleware, Verdict},
     vm,
 };
+use crate::signature::Signature;
 use crate::InterfaceHash;
 
-use alloc::{collections::VecDeque, vec::Vec};
-use core::{convert::TryFrom, iter, mem};
+use alloc::{borrow::Cow, boxed::Box, collections::VecDeque, string::String, vec::Vec};
+use core::{convert::TryFrom, fmt, iter, mem};
 use crossbeam_queue::SegQueue;
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
 use redshirt_syscalls_interface::{Encode, EncodedMessage, MessageId, Pid, ThreadId};
 use smallvec::SmallVec;
 
+/// Number of messages in the queue of a process above which messages emitted with
+/// `try_emit_message` are refused.
+const MAX_QUEUED_MESSAGES: usize = 512;
+
+/// Maximum size, in bytes, of a message or answer split into chunks and destined to a reserved
+/// `Pid`. Chunks beyond this size are discarded, and the message or answer is dropped.
+const MAX_CHUNKED_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
 /// Handles scheduling processes and inter-process communications.
 pub struct Core {
     /// Queue of events to return in priority when `run` is called.
@@ -48,9 +59,50 @@ pub struct Core {
     message_id_pool: IdPool,
 
     /// List of messages that have been emitted by a process and that are waiting for a response.
+    /// Contains the emitter of the message and the interface it has been emitted on.
     // TODO: doc about hash safety
     // TODO: call shrink_to from time to time
-    messages_to_answer: HashMap<MessageId, Pid>,
+    messages_to_answer: HashMap<MessageId, (Pid, InterfaceHash)>,
+
+    /// Total number of interface messages that have been emitted since the creation.
+    messages_routed: u64,
+
+    /// For each interface, statistics about the messages that have been emitted on it.
+    interface_statistics: HashMap<InterfaceHash, InterfaceStatistics>,
+
+    /// For each interface, list of middlewares to pass the messages through, in order.
+    middlewares: HashMap<InterfaceHash, Vec<Box<dyn Middleware>>>,
+
+    /// For messages split into chunks and destined to a reserved `Pid`, the concatenation of the
+    /// chunks received so far, grouped by emitter and interface. Contains `None` if the chunks
+    /// have exceeded [`MAX_CHUNKED_MESSAGE_SIZE`].
+    chunked_messages: HashMap<(Pid, InterfaceHash), Option<Vec<u8>>>,
+
+    /// For answers split into chunks and destined to a reserved `Pid`, the concatenation of the
+    /// chunks received so far. Contains `None` if the chunks have exceeded
+    /// [`MAX_CHUNKED_MESSAGE_SIZE`].
+    chunked_answers: HashMap<MessageId, Option<Vec<u8>>>,
+
+    /// List of threads that have called `sleep_until`, with the value of the monotonic clock, in
+    /// nanoseconds, at which they must be woken up.
+    ///
+    /// Might contain threads that no longer exist.
+    sleeping_threads: Vec<(u64, ThreadId)>,
+
+    /// List of messages that have been emitted with a deadline, with the value of the monotonic
+    /// clock, in nanoseconds, after which they must be answered with an error.
+    ///
+    /// Might contain messages that have already been answered.
+    message_deadlines: Vec<(u64, MessageId)>,
+}
+
+/// Statistics about the messages emitted on an interface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceStatistics {
+    /// Total number of messages that have been emitted on the interface.
+    pub messages: u64,
+    /// Total number of bytes of all the messages that have been emitted on the interface.
+    pub bytes: u64,
 }
 
 /// Which way an interface is handled.
@@ -74,6 +126,8 @@ pub struct CoreBuilder {
     reserved_pids: HashSet<Pid>,
     /// Builder for the [`processes`][Core::processes] field in `Core`.
     inner_builder: extrinsics::ProcessesCollectionExtrinsicsBuilder,
+    /// See the corresponding field in `Core`.
+    middlewares: HashMap<InterfaceHash, Vec<Box<dyn Middleware>>>,
 }
 
 /// Outcome of calling [`run`](Core::run).
@@ -99,7 +153,7 @@ pub enum CoreRunOutcome<'a> {
         /// How the program ended. If `Ok`, it has gracefully terminated. If `Err`, something
         /// bad happened.
         // TODO: force Ok to i32?
-        outcome: Result<Option<wasmi::RuntimeValue>, wasmi::Trap>,
+        outcome: Result<Option<wasmi::RuntimeValue>, vm::ExecError>,
     },
 
     /// Thread has tried to emit a message on an interface that isn't registered. The thread is
@@ -127,6 +181,20 @@ pub enum CoreRunOutcome<'a> {
         response: Result<EncodedMessage, ()>,
     },
 
+    /// Partial response to a message emitted using [`Core::emit_interface_message_answer`].
+    /// More responses will follow, the last one being a [`CoreRunOutcome::MessageResponse`].
+    MessagePartialResponse {
+        message_id: MessageId,
+        response: EncodedMessage,
+    },
+
+    /// A message previously returned with [`CoreRunOutcome::ReservedPidInterfaceMessage`] has
+    /// been cancelled by its emitter and no longer needs to be answered.
+    ReservedPidMessageCancelled {
+        message_id: MessageId,
+        interface: InterfaceHash,
+    },
+
     /// Nothing to do. No thread is ready to run.
     Idle,
 }
@@ -140,7 +208,7 @@ enum CoreRunOutcomeInner {
         unhandled_messages: Vec<MessageId>,
         cancelled_messages: Vec<MessageId>,
         unregistered_interfaces: Vec<InterfaceHash>,
-        outcome: Result<Option<wasmi::RuntimeValue>, wasmi::Trap>,
+        outcome: Result<Option<wasmi::RuntimeValue>, vm::ExecError>,
     },
     ThreadWaitUnavailableInterface {
         thread: ThreadId,
@@ -157,6 +225,14 @@ enum CoreRunOutcomeInner {
         message_id: MessageId,
         response: Result<EncodedMessage, ()>,
     },
+    MessagePartialResponse {
+        message_id: MessageId,
+        response: EncodedMessage,
+    },
+    ReservedPidMessageCancelled {
+        message_id: MessageId,
+        interface: InterfaceHash,
+    },
     LoopAgain,
     Idle,
 }
@@ -175,6 +251,16 @@ struct Process {
     /// Interfaces that the process has registered.
     registered_interfaces: SmallVec<[InterfaceHash; 1]>,
 
+    /// Interfaces that the metadata of the module declares the process is going to register, or
+    /// `None` if the module doesn't have any metadata. The process can't register any interface
+    /// outside of this list.
+    declared_interfaces: Option<Vec<InterfaceHash>>,
+
+    /// Interfaces that the metadata of the module declares the process is going to emit messages
+    /// on, or `None` if the module doesn't have any metadata. The process can't emit messages on
+    /// any interface outside of this list.
+    declared_used_interfaces: Option<Vec<InterfaceHash>>,
+
     /// List of interfaces that this process has used. When the process dies, we notify all the
     /// handlers about it.
     used_interfaces: HashSet<InterfaceHash>,
@@ -184,6 +270,100 @@ struct Process {
 
     /// List of messages that the process is expected to answer.
     messages_to_answer: SmallVec<[MessageId; 8]>,
+
+    /// Process that has started this process, if any.
+    parent: Option<Pid>,
+
+    /// Processes that have been started by this process and that are still alive.
+    children: SmallVec<[Pid; 4]>,
+
+    /// What to do with this process when its parent terminates.
+    on_parent_exit: OrphanPolicy,
+
+    /// SHA-256 digest of the module the process has been started from.
+    module_hash: [u8; 32],
+
+    /// Name of the program, as found in the metadata of its module.
+    name: Option<String>,
+}
+
+/// What to do with a process when its parent terminates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// The process is attached to the parent of its parent, or becomes a root process if there
+    /// is none.
+    Reparent,
+    /// The process is killed as well. Its own children are then handled according to their
+    /// policy.
+    Kill,
+}
+
+/// Error used as the outcome of a process that has been killed because of
+/// [`OrphanPolicy::Kill`].
+#[derive(Debug)]
+struct ParentTerminated;
+
+impl fmt::Display for ParentTerminated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Parent process has terminated")
+    }
+}
+
+impl wasmi::HostError for ParentTerminated {}
+
+/// Error used as the outcome of a process that has been killed with [`Core::kill`].
+#[derive(Debug)]
+struct Killed;
+
+impl fmt::Display for Killed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Process has been killed")
+    }
+}
+
+impl wasmi::HostError for Killed {}
+
+/// Error that can happen when calling [`Core::set_interface_handler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetInterfaceHandlerError {
+    /// The handler is neither a running process nor a reserved `Pid`.
+    InvalidHandler,
+    /// The interface can't have a handler.
+    ReservedInterface,
+    /// There already exists a handler for this interface.
+    AlreadyRegistered,
+    /// The metadata of the module of the handler doesn't declare this interface.
+    NotDeclared,
+}
+
+impl fmt::Display for SetInterfaceHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetInterfaceHandlerError::InvalidHandler => write!(f, "Invalid handler"),
+            SetInterfaceHandlerError::ReservedInterface => write!(f, "Reserved interface"),
+            SetInterfaceHandlerError::AlreadyRegistered => {
+                write!(f, "Interface already registered")
+            }
+            SetInterfaceHandlerError::NotDeclared => {
+                write!(f, "Interface not declared in the module metadata")
+            }
+        }
+    }
+}
+
+/// Information about a running process. Returned by [`Core::processes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessSummary {
+    /// Identifier of the process.
+    pub pid: Pid,
+    /// Process that has started this process, if any.
+    pub parent: Option<Pid>,
+    /// SHA-256 digest of the module the process has been started from.
+    pub module_hash: [u8; 32],
+    /// Name of the program, as found in the metadata of its module.
+    pub name: Option<String>,
+    /// Size, in bytes, of the memory of the process.
+    pub memory_bytes: u64,
 }
 
 /// How a process is waiting for messages.
@@ -219,6 +399,7 @@ impl Core {
         CoreBuilder {
             reserved_pids: HashSet::new(),
             inner_builder: extrinsics::ProcessesCollectionExtrinsicsBuilder::default(),
+            middlewares: HashMap::default(),
         }
     }
 
@@ -271,6 +452,20 @@ impl Core {
                     message_id,
                     response,
                 },
+                CoreRunOutcomeInner::MessagePartialResponse {
+                    message_id,
+                    response,
+                } => CoreRunOutcome::MessagePartialResponse {
+                    message_id,
+                    response,
+                },
+                CoreRunOutcomeInner::ReservedPidMessageCancelled {
+                    message_id,
+                    interface,
+                } => CoreRunOutcome::ReservedPidMessageCancelled {
+                    message_id,
+                    interface,
+                },
             };
         }
     }
@@ -296,55 +491,7 @@ impl Core {
                     }
                 }
 
-                // Unregister the interfaces this program had registered.
-                let mut unregistered_interfaces = Vec::new();
-                for interface in user_data.registered_interfaces {
-                    let _interface = self.interfaces.remove(&interface);
-                    debug_assert_eq!(_interface, Some(InterfaceState::Process(pid)));
-                    unregistered_interfaces.push(interface);
-                }
-
-                // Cancelling messages that the process had emitted.
-                // TODO: this only handles messages emitted through the external API
-                let mut cancelled_messages = Vec::new();
-                for emitted_message in user_data.emitted_messages {
-                    let _emitter = self.messages_to_answer.remove(&emitted_message);
-                    debug_assert_eq!(_emitter, Some(pid));
-                    cancelled_messages.push(emitted_message);
-                }
-
-                // Notify interface handlers about the process stopping.
-                for interface in user_data.used_interfaces {
-                    match self.interfaces.get(&interface) {
-                        Some(InterfaceState::Process(p)) => {
-                            if let Some(mut process) = self.processes.process_by_id(*p) {
-                                let message =
-                                    redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(
-                                        redshirt_syscalls_interface::ffi::ProcessDestroyedMessage {
-                                            index_in_list: 0,
-                                            pid: pid.into(),
-                                        },
-                                    );
-
-                                process.user_data().messages_queue.push_back(message);
-                                try_resume_message_wait(process);
-                            } // TODO: notify externals as well?
-                        }
-                        None => unreachable!(),
-                        _ => {}
-                    }
-                }
-
-                // TODO: also, what do we do with the pending messages and all?
-
-                CoreRunOutcomeInner::ProgramFinished {
-                    pid,
-                    unregistered_interfaces,
-                    // TODO: this only handles messages emitted through the external API
-                    unhandled_messages: user_data.messages_to_answer.to_vec(), // TODO: to_vec overhead
-                    cancelled_messages,
-                    outcome,
-                }
+                self.process_destroyed(pid, user_data, outcome)
             }
 
             extrinsics::RunOneOutcome::ThreadFinished { .. } => {
@@ -357,9 +504,96 @@ impl Core {
                 CoreRunOutcomeInner::LoopAgain
             }
 
+            extrinsics::RunOneOutcome::ThreadSleep(mut thread) => {
+                let deadline = thread.deadline();
+                self.sleeping_threads.push((deadline, thread.tid()));
+                CoreRunOutcomeInner::LoopAgain
+            }
+
             extrinsics::RunOneOutcome::ThreadEmitMessage(mut thread) => {
                 let emitter_pid = thread.pid();
                 let interface = thread.emit_interface().clone();
+
+                // Messages directed to a specific process bypass the interfaces and middlewares.
+                if let Some(target) = thread.target() {
+                    // Since `thread` holds a borrow of the processes collection, we have to
+                    // release it in order to inspect the target.
+                    let thread_id = thread.tid();
+                    let accepted = self.processes.accepts_directed_messages(target);
+                    thread = match self.processes.thread_by_id(thread_id) {
+                        Some(extrinsics::ProcessesCollectionExtrinsicsThread::EmitMessage(t)) => t,
+                        _ => unreachable!(),
+                    };
+
+                    if !accepted {
+                        thread.refuse_emit();
+                        return CoreRunOutcomeInner::LoopAgain;
+                    }
+
+                    let message = accept_emit(
+                        &self.message_id_pool,
+                        &mut self.messages_to_answer,
+                        &mut self.message_deadlines,
+                        thread,
+                        &interface,
+                    );
+                    self.messages_routed += 1;
+                    let message = message.into_ffi(&interface);
+
+                    let mut process = match self.processes.process_by_id(target) {
+                        Some(p) => p,
+                        None => unreachable!(),
+                    };
+                    process.user_data().messages_queue.push_back(message);
+                    try_resume_message_wait(process);
+                    return CoreRunOutcomeInner::LoopAgain;
+                }
+
+                if !is_declared_used(thread.process_user_data(), &interface) {
+                    thread.refuse_emit();
+                    return CoreRunOutcomeInner::LoopAgain;
+                }
+
+                if let Some(middlewares) = self.middlewares.get_mut(&interface) {
+                    let verdict = apply_middlewares(
+                        middlewares,
+                        &interface,
+                        emitter_pid,
+                        thread.message_mut(),
+                    );
+                    if verdict == Verdict::Reject {
+                        thread.refuse_emit();
+                        return CoreRunOutcomeInner::LoopAgain;
+                    }
+                }
+
+                if thread.is_no_block() {
+                    // Since `thread` holds a borrow of the processes collection, we have to
+                    // release it in order to inspect the queue of the handler.
+                    let thread_id = thread.tid();
+                    let would_block = match self.interfaces.get(&interface) {
+                        Some(InterfaceState::Process(pid)) => {
+                            match self.processes.process_by_id(*pid) {
+                                Some(mut p) => {
+                                    p.user_data().messages_queue.len() >= MAX_QUEUED_MESSAGES
+                                }
+                                None => false,
+                            }
+                        }
+                        Some(InterfaceState::Requested { .. }) | None => true,
+                    };
+
+                    thread = match self.processes.thread_by_id(thread_id) {
+                        Some(extrinsics::ProcessesCollectionExtrinsicsThread::EmitMessage(t)) => t,
+                        _ => unreachable!(),
+                    };
+
+                    if would_block {
+                        thread.refuse_emit_would_block();
+                        return CoreRunOutcomeInner::LoopAgain;
+                    }
+                }
+
                 thread
                     .process_user_data()
                     .used_interfaces
@@ -367,48 +601,45 @@ impl Core {
 
                 match (self.interfaces.get_mut(&interface), thread.allow_delay()) {
                     (Some(InterfaceState::Process(pid)), _) => {
-                        let message_id = if thread.needs_answer() {
-                            Some(loop {
-                                let id: MessageId = self.message_id_pool.assign();
-                                if u64::from(id) == 0 || u64::from(id) == 1 {
-                                    continue;
-                                }
-                                match self.messages_to_answer.entry(id) {
-                                    Entry::Occupied(_) => continue,
-                                    Entry::Vacant(e) => e.insert(emitter_pid),
-                                };
-                                break id;
-                            })
-                        } else {
-                            None
-                        };
-
-                        let message = thread.accept_emit(message_id);
-
-                        if let Some(process) = self.processes.process_by_id(*pid) {
-                            let message = redshirt_syscalls_interface::ffi::Message::Interface(
-                                redshirt_syscalls_interface::ffi::InterfaceMessage {
-                                    interface: interface.into(),
-                                    index_in_list: 0,
-                                    message_id,
-                                    emitter_pid: emitter_pid.into(),
-                                    actual_data: message.0,
-                                },
-                            );
-
-                            let mut process = match self.processes.process_by_id(*pid) {
-                                Some(p) => p,
-                                None => unreachable!(),
-                            };
-                            process.user_data().messages_queue.push_back(message);
+                        let pid = *pid;
+                        let message = accept_emit(
+                            &self.message_id_pool,
+                            &mut self.messages_to_answer,
+                            &mut self.message_deadlines,
+                            thread,
+                            &interface,
+                        );
+                        self.messages_routed += 1;
+                        record_message(&mut self.interface_statistics, &interface, &message.data);
+
+                        if let Some(mut process) = self.processes.process_by_id(pid) {
+                            process
+                                .user_data()
+                                .messages_queue
+                                .push_back(message.into_ffi(&interface));
                             try_resume_message_wait(process);
                             CoreRunOutcomeInner::LoopAgain
                         } else {
-                            CoreRunOutcomeInner::ReservedPidInterfaceMessage {
-                                pid: emitter_pid,
-                                message_id,
-                                interface,
-                                message,
+                            let message_id = message.message_id;
+                            match reassemble_chunks(
+                                &mut self.chunked_messages,
+                                emitter_pid,
+                                &interface,
+                                message.data,
+                                message.chunk,
+                            ) {
+                                Reassembled::Complete(message) => {
+                                    CoreRunOutcomeInner::ReservedPidInterfaceMessage {
+                                        pid: emitter_pid,
+                                        message_id,
+                                        interface,
+                                        message,
+                                    }
+                                }
+                                Reassembled::Incomplete => CoreRunOutcomeInner::LoopAgain,
+                                Reassembled::TooLarge => message_id
+                                    .and_then(|id| self.answer_message_inner(id, Err(())))
+                                    .unwrap_or(CoreRunOutcomeInner::LoopAgain),
                             }
                         }
                     }
@@ -449,16 +680,169 @@ impl Core {
                     .unwrap_or(CoreRunOutcomeInner::LoopAgain)
             }
 
+            extrinsics::RunOneOutcome::ThreadEmitPartialAnswer {
+                message_id,
+                response,
+                ..
+            } => {
+                // TODO: check ownership of the message
+                self.answer_message_partial_inner(message_id, response)
+                    .unwrap_or(CoreRunOutcomeInner::LoopAgain)
+            }
+
+            extrinsics::RunOneOutcome::ThreadEmitAnswerChunk {
+                message_id,
+                response,
+                ..
+            } => {
+                // TODO: check ownership of the message
+                self.answer_message_chunk_inner(message_id, response);
+                CoreRunOutcomeInner::LoopAgain
+            }
+
             extrinsics::RunOneOutcome::ThreadEmitMessageError { message_id, .. } => {
                 // TODO: check ownership of the message
                 self.answer_message_inner(message_id, Err(()))
                     .unwrap_or(CoreRunOutcomeInner::LoopAgain)
             }
 
+            extrinsics::RunOneOutcome::ThreadCancelMessage { thread, message_id } => {
+                let emitter_pid = thread.pid();
+                self.cancel_message_inner(emitter_pid, message_id)
+                    .unwrap_or(CoreRunOutcomeInner::LoopAgain)
+            }
+
+            extrinsics::RunOneOutcome::BlobCalled { .. } => CoreRunOutcomeInner::LoopAgain,
+
+            extrinsics::RunOneOutcome::ThreadProcessInfo(mut thread) => {
+                let pid = thread.pid();
+                let user_data = thread.process_user_data();
+                let info = redshirt_syscalls_interface::ffi::ProcessInfo {
+                    pid,
+                    parent: user_data.parent,
+                    module_hash: user_data.module_hash,
+                    name: user_data.name.clone(),
+                    registered_interfaces: user_data
+                        .registered_interfaces
+                        .iter()
+                        .map(|i| i.clone().into())
+                        .collect(),
+                };
+                thread.resume(&info.encode().0);
+                CoreRunOutcomeInner::LoopAgain
+            }
+
+            extrinsics::RunOneOutcome::AcceptDirectedMessagesCalled { .. } => {
+                CoreRunOutcomeInner::LoopAgain
+            }
+            extrinsics::RunOneOutcome::HostFunctionCalled { .. } => CoreRunOutcomeInner::LoopAgain,
+
             extrinsics::RunOneOutcome::Idle => CoreRunOutcomeInner::Idle,
         }
     }
 
+    /// Cleans up the state after a process has been destroyed, and applies the
+    /// [`OrphanPolicy`] of its children.
+    fn process_destroyed(
+        &mut self,
+        pid: Pid,
+        user_data: Process,
+        outcome: Result<Option<wasmi::RuntimeValue>, vm::ExecError>,
+    ) -> CoreRunOutcomeInner {
+        // Remove the process from the children of its parent.
+        if let Some(parent) = user_data.parent {
+            if let Some(mut parent) = self.processes.process_by_id(parent) {
+                parent.user_data().children.retain(|c| *c != pid);
+            }
+        }
+
+        // Handle the children of the process, according to their policy.
+        for child in user_data.children.iter().cloned() {
+            let mut child_proc = match self.processes.process_by_id(child) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let policy = child_proc.user_data().on_parent_exit;
+            match policy {
+                OrphanPolicy::Reparent => {
+                    child_proc.user_data().parent = user_data.parent;
+                    if let Some(grandparent) = user_data.parent {
+                        if let Some(mut grandparent) = self.processes.process_by_id(grandparent) {
+                            grandparent.user_data().children.push(child);
+                        }
+                    }
+                }
+                OrphanPolicy::Kill => {
+                    // The child must not try to remove itself from the list of children of
+                    // the process being destroyed.
+                    child_proc.user_data().parent = None;
+                    let (child_user_data, _) = child_proc.abort();
+                    let trap = wasmi::Trap::new(wasmi::TrapKind::Host(Box::new(ParentTerminated)));
+                    let event = self.process_destroyed(child, child_user_data, Err(trap.into()));
+                    self.pending_events.push(event);
+                }
+            }
+        }
+
+        // Unregister the interfaces this program had registered.
+        let mut unregistered_interfaces = Vec::new();
+        for interface in user_data.registered_interfaces {
+            let _interface = self.interfaces.remove(&interface);
+            debug_assert_eq!(_interface, Some(InterfaceState::Process(pid)));
+            unregistered_interfaces.push(interface);
+        }
+
+        // Destroy the blobs that the process had created.
+        self.processes.release_blobs(pid);
+        self.processes.stop_accepting_directed_messages(pid);
+
+        // Discard the messages that the process was emitting in chunks.
+        self.chunked_messages
+            .retain(|(emitter, _), _| *emitter != pid);
+
+        // Cancelling messages that the process had emitted.
+        // TODO: this only handles messages emitted through the external API
+        let mut cancelled_messages = Vec::new();
+        for emitted_message in user_data.emitted_messages {
+            let _emitter = self.messages_to_answer.remove(&emitted_message);
+            debug_assert_eq!(_emitter.map(|(emitter, _)| emitter), Some(pid));
+            cancelled_messages.push(emitted_message);
+        }
+
+        // Notify interface handlers about the process stopping.
+        for interface in user_data.used_interfaces {
+            match self.interfaces.get(&interface) {
+                Some(InterfaceState::Process(p)) => {
+                    if let Some(mut process) = self.processes.process_by_id(*p) {
+                        let message = redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(
+                            redshirt_syscalls_interface::ffi::ProcessDestroyedMessage {
+                                index_in_list: 0,
+                                pid: pid.into(),
+                            },
+                        );
+
+                        process.user_data().messages_queue.push_back(message);
+                        try_resume_message_wait(process);
+                    } // TODO: notify externals as well?
+                }
+                None => unreachable!(),
+                _ => {}
+            }
+        }
+
+        // TODO: also, what do we do with the pending messages and all?
+
+        CoreRunOutcomeInner::ProgramFinished {
+            pid,
+            unregistered_interfaces,
+            // TODO: this only handles messages emitted through the external API
+            unhandled_messages: user_data.messages_to_answer.to_vec(), // TODO: to_vec overhead
+            cancelled_messages,
+            outcome,
+        }
+    }
+
     /// Returns an object granting access to a process, if it exists.
     pub fn process_by_id(&mut self, pid: Pid) -> Option<CoreProcess> {
         let p = self.processes.process_by_id(pid)?;
@@ -471,30 +855,51 @@ impl Core {
         Some(CoreThread { thread })
     }
 
+    /// Returns true if a handler has been registered for the given interface.
+    pub fn has_interface_handler(&self, interface: &InterfaceHash) -> bool {
+        match self.interfaces.get(interface) {
+            Some(InterfaceState::Process(_)) => true,
+            Some(InterfaceState::Requested { .. }) | None => false,
+        }
+    }
+
+    /// Sets the handler of the given interface.
+    ///
+    /// If the module of `process` contains metadata, the interface must be part of the
+    /// interfaces that it declares.
     // TODO: better API
     pub fn set_interface_handler(
         &mut self,
         interface: InterfaceHash,
         process: Pid,
-    ) -> Result<(), ()> {
-        if self.processes.process_by_id(process).is_none() {
-            if !self.reserved_pids.contains(&process) {
-                return Err(());
-            }
-        } else {
+    ) -> Result<(), SetInterfaceHandlerError> {
+        // Directed messages are delivered on this interface, which therefore can't have a handler.
+        if interface == redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE {
+            return Err(SetInterfaceHandlerError::ReservedInterface);
+        }
+
+        if let Some(mut p) = self.processes.process_by_id(process) {
             debug_assert!(!self.reserved_pids.contains(&process));
+            if !is_declared(p.user_data(), &interface) {
+                return Err(SetInterfaceHandlerError::NotDeclared);
+            }
+        } else if !self.reserved_pids.contains(&process) {
+            return Err(SetInterfaceHandlerError::InvalidHandler);
         }
 
         let (thread_ids, other_messages) = match self.interfaces.entry(interface.clone()) {
             Entry::Vacant(e) => {
                 e.insert(InterfaceState::Process(process));
+                if let Some(mut p) = self.processes.process_by_id(process) {
+                    p.user_data().registered_interfaces.push(interface);
+                }
                 return Ok(());
             }
             Entry::Occupied(mut e) => {
                 // Check whether interface was already registered.
                 if let InterfaceState::Requested { .. } = *e.get_mut() {
                 } else {
-                    return Err(());
+                    return Err(SetInterfaceHandlerError::AlreadyRegistered);
                 };
                 match mem::replace(e.get_mut(), InterfaceState::Process(process)) {
                     InterfaceState::Requested { threads, other } => (threads, other),
@@ -503,6 +908,10 @@ impl Core {
             }
         };
 
+        if let Some(mut p) = self.processes.process_by_id(process) {
+            p.user_data().registered_interfaces.push(interface.clone());
+        }
+
         // Send the `other_messages`.
         // TODO: should we preserve the order w.r.t. `threads`?
         for (emitter_pid, message_id, message_data) in other_messages {
@@ -513,6 +922,8 @@ impl Core {
                     message_id,
                     emitter_pid,
                     actual_data: message_data.0,
+                    chunk: false,
+                    deadline: None,
                 },
             );
 
@@ -530,49 +941,50 @@ impl Core {
             };
 
             debug_assert_eq!(*thread.emit_interface(), interface);
-            let emitter_pid = thread.pid().into();
-
-            let message_id = if thread.needs_answer() {
-                Some(loop {
-                    let id: MessageId = self.message_id_pool.assign();
-                    if u64::from(id) == 0 || u64::from(id) == 1 {
-                        continue;
-                    }
-                    match self.messages_to_answer.entry(id) {
-                        Entry::Occupied(_) => continue,
-                        Entry::Vacant(e) => e.insert(emitter_pid),
-                    };
-                    break id;
-                })
-            } else {
-                None
-            };
-
-            let message = thread.accept_emit(message_id);
+            let emitter_pid = thread.pid();
+
+            let message = accept_emit(
+                &self.message_id_pool,
+                &mut self.messages_to_answer,
+                &mut self.message_deadlines,
+                thread,
+                &interface,
+            );
+            self.messages_routed += 1;
+            record_message(&mut self.interface_statistics, &interface, &message.data);
 
             if let Some(mut interface_handler_proc) = self.processes.process_by_id(process) {
-                let message = redshirt_syscalls_interface::ffi::Message::Interface(
-                    redshirt_syscalls_interface::ffi::InterfaceMessage {
-                        interface: interface.clone().into(),
-                        index_in_list: 0,
-                        message_id,
-                        emitter_pid,
-                        actual_data: message.0,
-                    },
-                );
-
                 interface_handler_proc
                     .user_data()
                     .messages_queue
-                    .push_back(message);
+                    .push_back(message.into_ffi(&interface));
             } else {
-                self.pending_events
-                    .push(CoreRunOutcomeInner::ReservedPidInterfaceMessage {
-                        pid: emitter_pid,
-                        message_id,
-                        interface: interface.clone(),
-                        message,
-                    });
+                let message_id = message.message_id;
+                match reassemble_chunks(
+                    &mut self.chunked_messages,
+                    emitter_pid,
+                    &interface,
+                    message.data,
+                    message.chunk,
+                ) {
+                    Reassembled::Complete(message) => {
+                        self.pending_events
+                            .push(CoreRunOutcomeInner::ReservedPidInterfaceMessage {
+                                pid: emitter_pid,
+                                message_id,
+                                interface: interface.clone(),
+                                message,
+                            })
+                    }
+                    Reassembled::Incomplete => {}
+                    Reassembled::TooLarge => {
+                        if let Some(event) =
+                            message_id.and_then(|id| self.answer_message_inner(id, Err(())))
+                        {
+                            self.pending_events.push(event);
+                        }
+                    }
+                }
             }
         }
 
@@ -583,46 +995,177 @@ impl Core {
         Ok(())
     }
 
-    /// Emits a message for the handler of the given interface.
+    /// Changes the handler of an interface that has already been registered.
     ///
-    /// The message doesn't expect any answer.
-    // TODO: better API
-    pub fn emit_interface_message_no_answer<'a>(
-        &mut self,
-        emitter_pid: Pid,
-        interface: InterfaceHash,
-        message: impl Encode,
-    ) {
-        assert!(self.reserved_pids.contains(&emitter_pid));
-        let _out = self.emit_interface_message_inner(emitter_pid, interface, message, false);
-        debug_assert!(_out.is_none());
-    }
-
-    /// Emits a message for the handler of the given interface.
+    /// The messages destined to the previous handler that it hasn't retrieved yet are transferred
+    /// to the new handler. Messages that the previous handler has already received can still be
+    /// answered by it.
     ///
-    /// The message does expect an answer. The answer will be sent back as
-    /// [`MessageResponse`](CoreRunOutcome::MessageResponse) event.
-    // TODO: better API
-    pub fn emit_interface_message_answer<'a>(
+    /// > **Note**: If the previous handler is a reserved `Pid`, the messages have already been
+    /// >           delivered through [`CoreRunOutcome::ReservedPidInterfaceMessage`] and can't be
+    /// >           transferred.
+    ///
+    /// Returns an error if the interface isn't registered, if `new_handler` is neither a
+    /// running process nor a reserved `Pid`, or if the metadata of the module of `new_handler`
+    /// doesn't declare the interface.
+    pub fn reroute_interface(
         &mut self,
-        emitter_pid: Pid,
         interface: InterfaceHash,
-        message: impl Encode,
-    ) -> MessageId {
-        assert!(self.reserved_pids.contains(&emitter_pid));
-        match self.emit_interface_message_inner(emitter_pid, interface, message, true) {
-            Some(m) => m,
-            None => unreachable!(),
+        new_handler: Pid,
+    ) -> Result<(), ()> {
+        if let Some(mut p) = self.processes.process_by_id(new_handler) {
+            if !is_declared(p.user_data(), &interface) {
+                return Err(());
+            }
+        } else if !self.reserved_pids.contains(&new_handler) {
+            return Err(());
         }
-    }
 
-    fn emit_interface_message_inner<'a>(
-        &mut self,
+        let old_handler = match self.interfaces.get_mut(&interface) {
+            Some(InterfaceState::Process(pid)) => mem::replace(pid, new_handler),
+            _ => return Err(()),
+        };
+
+        if old_handler == new_handler {
+            return Ok(());
+        }
+
+        // Extract the pending messages from the queue of the previous handler.
+        let mut transferred = Vec::new();
+        if let Some(mut old) = self.processes.process_by_id(old_handler) {
+            let user_data = old.user_data();
+            user_data.registered_interfaces.retain(|i| *i != interface);
+            let mut remaining = VecDeque::with_capacity(user_data.messages_queue.len());
+            for message in user_data.messages_queue.drain(..) {
+                match message {
+                    redshirt_syscalls_interface::ffi::Message::Interface(ref msg)
+                        if interface == msg.interface =>
+                    {
+                        transferred.push(message)
+                    }
+                    other => remaining.push_back(other),
+                }
+            }
+            user_data.messages_queue = remaining;
+        }
+
+        if let Some(mut new) = self.processes.process_by_id(new_handler) {
+            new.user_data()
+                .registered_interfaces
+                .push(interface.clone());
+            new.user_data().messages_queue.extend(transferred);
+            try_resume_message_wait(new);
+        } else {
+            for message in transferred {
+                if let redshirt_syscalls_interface::ffi::Message::Interface(msg) = message {
+                    let message = reassemble_chunks(
+                        &mut self.chunked_messages,
+                        msg.emitter_pid,
+                        &interface,
+                        EncodedMessage(msg.actual_data),
+                        msg.chunk,
+                    );
+                    match message {
+                        Reassembled::Complete(message) => self.pending_events.push(
+                            CoreRunOutcomeInner::ReservedPidInterfaceMessage {
+                                pid: msg.emitter_pid,
+                                message_id: msg.message_id,
+                                interface: interface.clone(),
+                                message,
+                            },
+                        ),
+                        Reassembled::Incomplete => {}
+                        Reassembled::TooLarge => {
+                            let event = msg
+                                .message_id
+                                .and_then(|id| self.answer_message_inner(id, Err(())));
+                            if let Some(event) = event {
+                                self.pending_events.push(event);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits a message for the handler of the given interface.
+    ///
+    /// The message doesn't expect any answer.
+    // TODO: better API
+    pub fn emit_interface_message_no_answer<'a>(
+        &mut self,
+        emitter_pid: Pid,
+        interface: InterfaceHash,
+        message: impl Encode,
+    ) {
+        assert!(self.reserved_pids.contains(&emitter_pid));
+        let _out = self.emit_interface_message_inner(emitter_pid, interface, message, false, None);
+        debug_assert!(_out.is_none());
+    }
+
+    /// Emits a message for the handler of the given interface.
+    ///
+    /// The message does expect an answer. The answer will be sent back as
+    /// [`MessageResponse`](CoreRunOutcome::MessageResponse) event.
+    // TODO: better API
+    pub fn emit_interface_message_answer<'a>(
+        &mut self,
+        emitter_pid: Pid,
+        interface: InterfaceHash,
+        message: impl Encode,
+    ) -> MessageId {
+        assert!(self.reserved_pids.contains(&emitter_pid));
+        match self.emit_interface_message_inner(emitter_pid, interface, message, true, None) {
+            Some(m) => m,
+            None => unreachable!(),
+        }
+    }
+
+    /// Same as [`Core::emit_interface_message_answer`], except that the message has a deadline.
+    ///
+    /// If the message hasn't been answered when [`Core::expire_message_deadlines`] is called with
+    /// a value superior or equal to `deadline`, the handler is notified that the message has been
+    /// cancelled and the message is answered with a timeout error. See
+    /// [`Core::expire_message_deadlines`].
+    pub fn emit_interface_message_answer_with_deadline<'a>(
+        &mut self,
+        emitter_pid: Pid,
+        interface: InterfaceHash,
+        message: impl Encode,
+        deadline: u64,
+    ) -> MessageId {
+        assert!(self.reserved_pids.contains(&emitter_pid));
+        match self.emit_interface_message_inner(
+            emitter_pid,
+            interface,
+            message,
+            true,
+            Some(deadline),
+        ) {
+            Some(m) => m,
+            None => unreachable!(),
+        }
+    }
+
+    fn emit_interface_message_inner<'a>(
+        &mut self,
         emitter_pid: Pid,
         interface: InterfaceHash,
         message: impl Encode,
         needs_answer: bool,
+        deadline: Option<u64>,
     ) -> Option<MessageId> {
+        let mut message = message.encode();
+        let verdict = match self.middlewares.get_mut(&interface) {
+            Some(middlewares) => {
+                apply_middlewares(middlewares, &interface, emitter_pid, &mut message)
+            }
+            None => Verdict::Accept,
+        };
+
         let (message_id, messages_to_answer_entry) = if needs_answer {
             loop {
                 let id: MessageId = self.message_id_pool.assign();
@@ -638,6 +1181,27 @@ impl Core {
             (None, None)
         };
 
+        if verdict == Verdict::Reject {
+            if let Some(message_id) = message_id {
+                self.pending_events
+                    .push(CoreRunOutcomeInner::MessageResponse {
+                        message_id,
+                        response: Err(()),
+                    });
+            }
+            return message_id;
+        }
+
+        self.messages_routed += 1;
+        record_message(&mut self.interface_statistics, &interface, &message);
+
+        if let Some(messages_to_answer_entry) = messages_to_answer_entry {
+            messages_to_answer_entry.insert((emitter_pid, interface.clone()));
+        }
+        if let (Some(deadline), Some(message_id)) = (deadline, message_id) {
+            self.message_deadlines.push((deadline, message_id));
+        }
+
         let pid = match self.interfaces.entry(interface.clone()).or_insert_with(|| {
             InterfaceState::Requested {
                 threads: SmallVec::new(),
@@ -646,7 +1210,7 @@ impl Core {
         }) {
             InterfaceState::Process(pid) => *pid,
             InterfaceState::Requested { other, .. } => {
-                other.push((emitter_pid, message_id, message.encode()));
+                other.push((emitter_pid, message_id, message));
                 return message_id;
             }
         };
@@ -658,7 +1222,9 @@ impl Core {
                     message_id,
                     emitter_pid,
                     index_in_list: 0,
-                    actual_data: message.encode().0.to_vec(),
+                    actual_data: message.0,
+                    chunk: false,
+                    deadline,
                 },
             );
 
@@ -669,27 +1235,24 @@ impl Core {
             self.pending_events
                 .push(CoreRunOutcomeInner::ReservedPidInterfaceMessage {
                     pid: emitter_pid,
-                    message_id: None,
+                    message_id,
                     interface,
-                    message: message.encode(),
+                    message,
                 });
         };
 
-        if let Some(messages_to_answer_entry) = messages_to_answer_entry {
-            messages_to_answer_entry.insert(emitter_pid);
-        }
         message_id
     }
 
+    /// Answers a message that has been received by the handler of an interface.
     ///
-    ///
-    /// It is forbidden to answer messages created using [`emit_interface_message_answer`] or
-    /// [`emit_interface_message_no_answer`]. Only messages generated by processes can be answered
-    /// through this method.
+    /// If the message has been emitted using [`Core::emit_interface_message_answer`], the answer
+    /// is later returned as a [`CoreRunOutcome::MessageResponse`].
     // TODO: better API
     pub fn answer_message(&mut self, message_id: MessageId, response: Result<EncodedMessage, ()>) {
-        let ret = self.answer_message_inner(message_id, response);
-        assert!(ret.is_none());
+        if let Some(event) = self.answer_message_inner(message_id, response) {
+            self.pending_events.push(event);
+        }
     }
 
     // TODO: better API
@@ -698,7 +1261,9 @@ impl Core {
         message_id: MessageId,
         response: Result<EncodedMessage, ()>,
     ) -> Option<CoreRunOutcomeInner> {
-        if let Some(emitter_pid) = self.messages_to_answer.remove(&message_id) {
+        if let Some((emitter_pid, _)) = self.messages_to_answer.remove(&message_id) {
+            let chunks = self.chunked_answers.remove(&message_id);
+
             if let Some(mut process) = self.processes.process_by_id(emitter_pid) {
                 let actual_message = redshirt_syscalls_interface::ffi::Message::Response(
                     redshirt_syscalls_interface::ffi::ResponseMessage {
@@ -706,6 +1271,8 @@ impl Core {
                         // We a dummy value here and fill it up later when actually delivering the message.
                         index_in_list: 0,
                         actual_data: response.map(|r| r.0.to_vec()),
+                        partial: false,
+                        chunk: false,
                     },
                 );
 
@@ -719,12 +1286,333 @@ impl Core {
             } else {
                 Some(CoreRunOutcomeInner::MessageResponse {
                     message_id,
-                    response,
+                    response: response.and_then(|r| concat_chunks(chunks, r)),
                 })
             }
         } else {
-            // TODO: what to do here?
-            panic!("no process found with that event")
+            // The message has either been cancelled by its emitter, or is invalid.
+            // TODO: check ownership of the message and report invalid messages?
+            None
+        }
+    }
+
+    /// Sends a partial answer to a message. The message stays valid, and must later be answered
+    /// with [`Core::answer_message`].
+    ///
+    /// The same restrictions as [`Core::answer_message`] apply.
+    pub fn answer_message_partial(&mut self, message_id: MessageId, response: EncodedMessage) {
+        if let Some(event) = self.answer_message_partial_inner(message_id, response) {
+            self.pending_events.push(event);
+        }
+    }
+
+    fn answer_message_partial_inner(
+        &mut self,
+        message_id: MessageId,
+        response: EncodedMessage,
+    ) -> Option<CoreRunOutcomeInner> {
+        let emitter_pid = match self.messages_to_answer.get(&message_id) {
+            Some((emitter_pid, _)) => *emitter_pid,
+            // The message has either been cancelled by its emitter, or is invalid.
+            None => return None,
+        };
+
+        if let Some(mut process) = self.processes.process_by_id(emitter_pid) {
+            let actual_message = redshirt_syscalls_interface::ffi::Message::Response(
+                redshirt_syscalls_interface::ffi::ResponseMessage {
+                    message_id,
+                    // We a dummy value here and fill it up later when actually delivering the message.
+                    index_in_list: 0,
+                    actual_data: Ok(response.0),
+                    partial: true,
+                    chunk: false,
+                },
+            );
+
+            process.user_data().messages_queue.push_back(actual_message);
+            try_resume_message_wait(process);
+            None
+        } else {
+            let chunks = self.chunked_answers.remove(&message_id);
+            match concat_chunks(chunks, response) {
+                Ok(response) => Some(CoreRunOutcomeInner::MessagePartialResponse {
+                    message_id,
+                    response,
+                }),
+                // The handler has sent an answer that is too large. Answer with an error instead.
+                Err(()) => self.answer_message_inner(message_id, Err(())),
+            }
+        }
+    }
+
+    /// Sends a chunk of an answer to a message. The chunk is concatenated with the following
+    /// answer.
+    fn answer_message_chunk_inner(&mut self, message_id: MessageId, response: EncodedMessage) {
+        let emitter_pid = match self.messages_to_answer.get(&message_id) {
+            Some((emitter_pid, _)) => *emitter_pid,
+            // The message has either been cancelled by its emitter, or is invalid.
+            None => return,
+        };
+
+        if let Some(mut process) = self.processes.process_by_id(emitter_pid) {
+            let actual_message = redshirt_syscalls_interface::ffi::Message::Response(
+                redshirt_syscalls_interface::ffi::ResponseMessage {
+                    message_id,
+                    // We a dummy value here and fill it up later when actually delivering the message.
+                    index_in_list: 0,
+                    actual_data: Ok(response.0),
+                    partial: false,
+                    chunk: true,
+                },
+            );
+
+            process.user_data().messages_queue.push_back(actual_message);
+            try_resume_message_wait(process);
+        } else {
+            let entry = self
+                .chunked_answers
+                .entry(message_id)
+                .or_insert_with(|| Some(Vec::new()));
+            append_chunk(entry, &response.0);
+        }
+    }
+
+    /// Cancels a message emitted using [`Core::emit_interface_message_answer`]. No
+    /// [`MessageResponse`](CoreRunOutcome::MessageResponse) will be generated for this message.
+    ///
+    /// The handler of the interface is notified, unless it hasn't received the message yet, in
+    /// which case the message is simply discarded.
+    ///
+    /// Has no effect if the message has already been answered or doesn't exist.
+    pub fn cancel_message(&mut self, emitter_pid: Pid, message_id: MessageId) {
+        assert!(self.reserved_pids.contains(&emitter_pid));
+        if let Some(event) = self.cancel_message_inner(emitter_pid, message_id) {
+            self.pending_events.push(event);
+        }
+    }
+
+    fn cancel_message_inner(
+        &mut self,
+        emitter_pid: Pid,
+        message_id: MessageId,
+    ) -> Option<CoreRunOutcomeInner> {
+        // If the answer has already arrived, remove it from the queue of the emitter.
+        if let Some(mut emitter) = self.processes.process_by_id(emitter_pid) {
+            let user_data = emitter.user_data();
+            user_data.emitted_messages.retain(|m| *m != message_id);
+            user_data.messages_queue.retain(|msg| match msg {
+                redshirt_syscalls_interface::ffi::Message::Response(response) => {
+                    response.message_id != message_id
+                }
+                _ => true,
+            });
+        }
+
+        let interface = match self.messages_to_answer.entry(message_id) {
+            Entry::Occupied(e) if e.get().0 == emitter_pid => e.remove().1,
+            _ => return None,
+        };
+        self.chunked_answers.remove(&message_id);
+
+        let handler = match self.interfaces.get_mut(&interface) {
+            Some(InterfaceState::Process(pid)) => *pid,
+            Some(InterfaceState::Requested { other, .. }) => {
+                // The message is still waiting for a handler to be registered.
+                other.retain(|(_, id, _)| *id != Some(message_id));
+                return None;
+            }
+            None => return None,
+        };
+
+        if let Some(mut process) = self.processes.process_by_id(handler) {
+            let queue = &mut process.user_data().messages_queue;
+            let queue_len = queue.len();
+            queue.retain(|msg| match msg {
+                redshirt_syscalls_interface::ffi::Message::Interface(msg) => {
+                    msg.message_id != Some(message_id)
+                }
+                _ => true,
+            });
+
+            // If the handler has already pulled the message, notify it.
+            if queue.len() == queue_len {
+                let message = redshirt_syscalls_interface::ffi::Message::MessageCancelled(
+                    redshirt_syscalls_interface::ffi::MessageCancelledMessage {
+                        message_id,
+                        index_in_list: 0,
+                    },
+                );
+                queue.push_back(message);
+                try_resume_message_wait(process);
+            }
+
+            None
+        } else {
+            Some(CoreRunOutcomeInner::ReservedPidMessageCancelled {
+                message_id,
+                interface,
+            })
+        }
+    }
+
+    /// Wakes up all the threads that have called `sleep_until` with a value inferior or equal to
+    /// `now`.
+    ///
+    /// `now` must be the current value of the monotonic clock, in nanoseconds.
+    pub fn wake_up_sleeping_threads(&mut self, now: u64) {
+        let mut n = 0;
+        while n < self.sleeping_threads.len() {
+            let (deadline, tid) = self.sleeping_threads[n];
+            if deadline > now {
+                n += 1;
+                continue;
+            }
+
+            self.sleeping_threads.swap_remove(n);
+            if let Some(extrinsics::ProcessesCollectionExtrinsicsThread::Sleep(thread)) =
+                self.processes.thread_by_id(tid)
+            {
+                thread.resume();
+            }
+        }
+    }
+
+    /// Answers with an error all the messages whose deadline is inferior or equal to `now`, and
+    /// notifies their handler that they no longer need to be answered.
+    ///
+    /// The answer is a successful answer containing an encoded
+    /// `Err::<_, ErrorPayload>(ErrorPayload::new(ErrorClass::TIMED_OUT))`. This is
+    /// indistinguishable from an answer of the handler if the answers of the interface are of
+    /// type `Result<_, ErrorPayload>`, which is the recommended type for interfaces whose
+    /// messages can have a deadline.
+    ///
+    /// `now` must be the current value of the monotonic clock, in nanoseconds.
+    pub fn expire_message_deadlines(&mut self, now: u64) {
+        let mut n = 0;
+        while n < self.message_deadlines.len() {
+            let (deadline, message_id) = self.message_deadlines[n];
+            if deadline > now {
+                n += 1;
+                continue;
+            }
+
+            self.message_deadlines.swap_remove(n);
+            let emitter_pid = match self.messages_to_answer.get(&message_id) {
+                Some((emitter_pid, _)) => *emitter_pid,
+                None => continue,
+            };
+
+            // Notify the handler as if the emitter had cancelled the message.
+            if let Some(event) = self.cancel_message_inner(emitter_pid, message_id) {
+                self.pending_events.push(event);
+            }
+
+            let response = Err::<(), _>(redshirt_syscalls_interface::ErrorPayload::new(
+                redshirt_syscalls_interface::ErrorClass::TIMED_OUT,
+            ))
+            .encode();
+
+            if let Some(mut process) = self.processes.process_by_id(emitter_pid) {
+                let actual_message = redshirt_syscalls_interface::ffi::Message::Response(
+                    redshirt_syscalls_interface::ffi::ResponseMessage {
+                        message_id,
+                        // We a dummy value here and fill it up later when actually delivering the message.
+                        index_in_list: 0,
+                        actual_data: Ok(response.0),
+                        partial: false,
+                        chunk: false,
+                    },
+                );
+
+                process.user_data().messages_queue.push_back(actual_message);
+                try_resume_message_wait(process);
+            } else {
+                self.pending_events
+                    .push(CoreRunOutcomeInner::MessageResponse {
+                        message_id,
+                        response: Ok(response),
+                    });
+            }
+        }
+    }
+
+    /// Returns the value of the monotonic clock, in nanoseconds, at which the earliest sleeping
+    /// thread must be woken up or the earliest message deadline expires, or `None` if there is
+    /// none.
+    ///
+    /// [`wake_up_sleeping_threads`](Core::wake_up_sleeping_threads) and
+    /// [`expire_message_deadlines`](Core::expire_message_deadlines) should be called at this
+    /// moment.
+    pub fn next_wake_up(&self) -> Option<u64> {
+        let threads = self.sleeping_threads.iter().map(|(deadline, _)| *deadline);
+        let messages = self
+            .message_deadlines
+            .iter()
+            .filter(|(_, message_id)| self.messages_to_answer.contains_key(message_id))
+            .map(|(deadline, _)| *deadline);
+        threads.chain(messages).min()
+    }
+
+    /// Returns the number of processes currently running.
+    pub fn processes_count(&self) -> usize {
+        self.processes.pids().len()
+    }
+
+    /// Returns the total number of interface messages that have been emitted since the
+    /// creation of the [`Core`].
+    pub fn messages_routed(&self) -> u64 {
+        self.messages_routed
+    }
+
+    /// Returns, for each interface on which at least one message has been emitted, statistics
+    /// about these messages.
+    ///
+    /// Messages rejected by a middleware aren't counted.
+    pub fn interface_statistics(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&InterfaceHash, &InterfaceStatistics)> {
+        self.interface_statistics.iter()
+    }
+
+    /// Returns the total number of messages waiting in the queues of the processes.
+    pub fn queued_messages(&mut self) -> u64 {
+        let pids = self.processes.pids().collect::<Vec<_>>();
+        pids.into_iter()
+            .filter_map(|pid| {
+                self.processes
+                    .process_by_id(pid)
+                    .map(|mut p| p.user_data().messages_queue.len() as u64)
+            })
+            .sum()
+    }
+
+    /// Returns the total size, in bytes, of the memory used by the processes.
+    pub fn processes_memory_usage(&self) -> u64 {
+        self.processes.memory_usage()
+    }
+
+    /// Sends a [`Shutdown`](redshirt_syscalls_interface::ffi::Message::Shutdown) message to all
+    /// the processes that handle at least one interface.
+    pub fn broadcast_shutdown(&mut self) {
+        let mut handlers = self
+            .interfaces
+            .values()
+            .filter_map(|state| match state {
+                InterfaceState::Process(pid) => Some(*pid),
+                InterfaceState::Requested { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        handlers.sort_by_key(|pid| u64::from(*pid));
+        handlers.dedup();
+
+        for pid in handlers {
+            if let Some(mut process) = self.processes.process_by_id(pid) {
+                let message = redshirt_syscalls_interface::ffi::Message::Shutdown(
+                    redshirt_syscalls_interface::ffi::ShutdownMessage { index_in_list: 0 },
+                );
+                process.user_data().messages_queue.push_back(message);
+                try_resume_message_wait(process);
+            }
         }
     }
 
@@ -732,17 +1620,109 @@ impl Core {
     ///
     /// Each import of the [`Module`](crate::module::Module) is resolved.
     pub fn execute(&mut self, module: &Module) -> Result<CoreProcess, vm::NewErr> {
+        self.execute_inner(module, None, OrphanPolicy::Reparent)
+    }
+
+    /// Start executing the module passed as parameter, as a child of the given process.
+    ///
+    /// When `parent` terminates, the new process is handled according to `on_parent_exit`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `parent` isn't a running process.
+    ///
+    pub fn execute_child(
+        &mut self,
+        module: &Module,
+        parent: Pid,
+        on_parent_exit: OrphanPolicy,
+    ) -> Result<CoreProcess, vm::NewErr> {
+        assert!(self.processes.process_by_id(parent).is_some());
+        self.execute_inner(module, Some(parent), on_parent_exit)
+    }
+
+    fn execute_inner(
+        &mut self,
+        module: &Module,
+        parent: Option<Pid>,
+        on_parent_exit: OrphanPolicy,
+    ) -> Result<CoreProcess, vm::NewErr> {
         let proc_metadata = Process {
             messages_queue: VecDeque::new(),
             registered_interfaces: SmallVec::new(),
+            declared_interfaces: module.metadata().map(|m| m.registered_interfaces.clone()),
+            declared_used_interfaces: module.metadata().map(|m| m.used_interfaces.clone()),
             used_interfaces: HashSet::new(),
             emitted_messages: SmallVec::new(),
             messages_to_answer: SmallVec::new(),
+            parent,
+            children: SmallVec::new(),
+            on_parent_exit,
+            module_hash: *module.hash().digest(),
+            name: module.metadata().map(|m| m.name.clone()),
         };
 
-        let process = self.processes.execute(module, proc_metadata, ())?;
+        let pid = self.processes.execute(module, proc_metadata, ())?.pid();
+
+        if let Some(parent) = parent {
+            match self.processes.process_by_id(parent) {
+                Some(mut p) => p.user_data().children.push(pid),
+                None => unreachable!(),
+            }
+        }
+
+        match self.processes.process_by_id(pid) {
+            Some(process) => Ok(CoreProcess { process }),
+            None => unreachable!(),
+        }
+    }
+
+    /// Returns the process that has started the given process, or `None` if the process has been
+    /// started with [`Core::execute`] or if it doesn't exist.
+    pub fn parent_of(&mut self, pid: Pid) -> Option<Pid> {
+        self.processes.process_by_id(pid)?.user_data().parent
+    }
+
+    /// Returns the list of processes that the given process has started and that are still
+    /// alive.
+    pub fn children_of(&mut self, pid: Pid) -> Vec<Pid> {
+        match self.processes.process_by_id(pid) {
+            Some(mut p) => p.user_data().children.to_vec(),
+            None => Vec::new(),
+        }
+    }
 
-        Ok(CoreProcess { process })
+    /// Returns information about all the processes currently running.
+    pub fn processes(&mut self) -> Vec<ProcessSummary> {
+        let pids = self.processes.pids().collect::<Vec<_>>();
+        pids.into_iter()
+            .filter_map(|pid| {
+                let mut process = self.processes.process_by_id(pid)?;
+                let memory_bytes = process.memory_size() as u64;
+                let user_data = process.user_data();
+                Some(ProcessSummary {
+                    pid,
+                    parent: user_data.parent,
+                    module_hash: user_data.module_hash,
+                    name: user_data.name.clone(),
+                    memory_bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Kills a process. A [`CoreRunOutcome::ProgramFinished`] is later returned by
+    /// [`Core::run`], and the children of the process are handled according to their
+    /// [`OrphanPolicy`].
+    ///
+    /// Returns an error if the process doesn't exist.
+    pub fn kill(&mut self, pid: Pid) -> Result<(), ()> {
+        let process = self.processes.process_by_id(pid).ok_or(())?;
+        let (user_data, _) = process.abort();
+        let trap = wasmi::Trap::new(wasmi::TrapKind::Host(Box::new(Killed)));
+        let event = self.process_destroyed(pid, user_data, Err(trap.into()));
+        self.pending_events.push(event);
+        Ok(())
     }
 }
 
@@ -796,6 +1776,42 @@ impl CoreBuilder {
         pid
     }
 
+    /// Registers a function that WASM modules can import under the given module and function
+    /// name, and call directly without going through messages.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a function with this module/name combination has already been registered,
+    /// including the functions of the `redshirt` module.
+    ///
+    pub fn add_host_function(
+        &mut self,
+        interface: impl Into<Cow<'static, str>>,
+        f_name: impl Into<Cow<'static, str>>,
+        signature: Signature,
+        function: impl HostFunction + 'static,
+    ) {
+        self.inner_builder
+            .add_host_function(interface, f_name, signature, function)
+    }
+
+    /// Adds a middleware that will be called for each message emitted on the given interface,
+    /// before it is delivered to the handler.
+    ///
+    /// If multiple middlewares are added for the same interface, they are called in the order
+    /// in which they have been added. A message rejected by a middleware isn't passed to the
+    /// next ones.
+    pub fn add_middleware(
+        &mut self,
+        interface: InterfaceHash,
+        middleware: impl Middleware + 'static,
+    ) {
+        self.middlewares
+            .entry(interface)
+            .or_insert_with(Vec::new)
+            .push(Box::new(middleware));
+    }
+
     /// Turns the builder into a [`Core`].
     pub fn build(mut self) -> Core {
         self.reserved_pids.shrink_to_fit();
@@ -807,10 +1823,210 @@ impl CoreBuilder {
             reserved_pids: self.reserved_pids,
             message_id_pool: IdPool::new(),
             messages_to_answer: HashMap::default(),
+            messages_routed: 0,
+            interface_statistics: HashMap::default(),
+            middlewares: self.middlewares,
+            chunked_messages: HashMap::default(),
+            chunked_answers: HashMap::default(),
+            sleeping_threads: Vec::new(),
+            message_deadlines: Vec::new(),
+        }
+    }
+}
+
+/// Message emitted by a process and accepted by [`accept_emit`].
+struct AcceptedMessage {
+    /// Process that has emitted the message.
+    emitter_pid: Pid,
+    /// Identifier of the message, if it needs an answer.
+    message_id: Option<MessageId>,
+    /// Body of the message.
+    data: EncodedMessage,
+    /// True if the message is a chunk, to concatenate with the next message of the same emitter.
+    chunk: bool,
+    /// Value of the monotonic clock after which the message expires, if any.
+    deadline: Option<u64>,
+}
+
+impl AcceptedMessage {
+    /// Turns the message into what is pushed to the queue of the process that receives it.
+    fn into_ffi(self, interface: &InterfaceHash) -> redshirt_syscalls_interface::ffi::Message {
+        redshirt_syscalls_interface::ffi::Message::Interface(
+            redshirt_syscalls_interface::ffi::InterfaceMessage {
+                interface: interface.clone().into(),
+                index_in_list: 0,
+                message_id: self.message_id,
+                emitter_pid: self.emitter_pid.into(),
+                actual_data: self.data.0,
+                chunk: self.chunk,
+                deadline: self.deadline,
+            },
+        )
+    }
+}
+
+/// Accepts the message that `thread` is emitting on `interface`, whether it is destined to the
+/// handler of the interface or directed to a specific process.
+///
+/// If the message needs an answer, assigns it a [`MessageId`] and registers it in
+/// `messages_to_answer`, as well as in `message_deadlines` if it has a deadline.
+fn accept_emit(
+    message_id_pool: &IdPool,
+    messages_to_answer: &mut HashMap<MessageId, (Pid, InterfaceHash)>,
+    message_deadlines: &mut Vec<(u64, MessageId)>,
+    mut thread: extrinsics::ProcessesCollectionExtrinsicsThreadEmitMessage<Process, ()>,
+    interface: &InterfaceHash,
+) -> AcceptedMessage {
+    let emitter_pid = thread.pid();
+
+    let message_id = if thread.needs_answer() {
+        Some(loop {
+            let id: MessageId = message_id_pool.assign();
+            if u64::from(id) == 0 || u64::from(id) == 1 {
+                continue;
+            }
+            match messages_to_answer.entry(id) {
+                Entry::Occupied(_) => continue,
+                Entry::Vacant(e) => e.insert((emitter_pid, interface.clone())),
+            };
+            break id;
+        })
+    } else {
+        None
+    };
+
+    let chunk = thread.is_chunk();
+    let deadline = thread.deadline();
+    if let (Some(deadline), Some(message_id)) = (deadline, message_id) {
+        message_deadlines.push((deadline, message_id));
+    }
+
+    AcceptedMessage {
+        emitter_pid,
+        message_id,
+        data: thread.accept_emit(message_id),
+        chunk,
+        deadline,
+    }
+}
+
+/// Returns true if the metadata of the given process allows it to register the interface.
+fn is_declared(process: &Process, interface: &InterfaceHash) -> bool {
+    match &process.declared_interfaces {
+        Some(list) => list.contains(interface),
+        None => true,
+    }
+}
+
+/// Returns true if the metadata of the given process allows it to emit messages on the interface.
+fn is_declared_used(process: &Process, interface: &InterfaceHash) -> bool {
+    match &process.declared_used_interfaces {
+        Some(list) => list.contains(interface),
+        None => true,
+    }
+}
+
+/// Updates the statistics of the given interface after a message has been emitted on it.
+fn record_message(
+    statistics: &mut HashMap<InterfaceHash, InterfaceStatistics>,
+    interface: &InterfaceHash,
+    message: &EncodedMessage,
+) {
+    let entry = statistics.entry(interface.clone()).or_default();
+    entry.messages += 1;
+    entry.bytes += message.0.len() as u64;
+}
+
+/// Outcome of [`reassemble_chunks`].
+enum Reassembled {
+    /// More chunks are expected.
+    Incomplete,
+    /// The message is complete.
+    Complete(EncodedMessage),
+    /// The message exceeds [`MAX_CHUNKED_MESSAGE_SIZE`] and has been discarded.
+    TooLarge,
+}
+
+/// Accumulates the chunks of a message destined to a reserved `Pid`.
+///
+/// Returns [`Reassembled::Incomplete`] if `chunk` is true. Otherwise, returns the concatenation
+/// of the chunks previously received from the same emitter on the same interface and of
+/// `message`.
+fn reassemble_chunks(
+    chunked_messages: &mut HashMap<(Pid, InterfaceHash), Option<Vec<u8>>>,
+    emitter: Pid,
+    interface: &InterfaceHash,
+    message: EncodedMessage,
+    chunk: bool,
+) -> Reassembled {
+    let key = (emitter, interface.clone());
+    if chunk {
+        let entry = chunked_messages
+            .entry(key)
+            .or_insert_with(|| Some(Vec::new()));
+        append_chunk(entry, &message.0);
+        return Reassembled::Incomplete;
+    }
+
+    match concat_chunks(chunked_messages.remove(&key), message) {
+        Ok(message) => Reassembled::Complete(message),
+        Err(()) => Reassembled::TooLarge,
+    }
+}
+
+/// Appends `data` to the chunks accumulated so far. Sets `chunks` to `None` and discards the
+/// data if the total exceeds [`MAX_CHUNKED_MESSAGE_SIZE`].
+fn append_chunk(chunks: &mut Option<Vec<u8>>, data: &[u8]) {
+    let too_large = match chunks {
+        Some(buffer) if buffer.len() + data.len() > MAX_CHUNKED_MESSAGE_SIZE => true,
+        Some(buffer) => {
+            buffer.extend_from_slice(data);
+            false
+        }
+        None => false,
+    };
+
+    if too_large {
+        *chunks = None;
+    }
+}
+
+/// Concatenates the chunks accumulated so far, if any, with the last piece of a message or
+/// answer. Returns an error if the total exceeds [`MAX_CHUNKED_MESSAGE_SIZE`].
+fn concat_chunks(
+    chunks: Option<Option<Vec<u8>>>,
+    last: EncodedMessage,
+) -> Result<EncodedMessage, ()> {
+    match chunks {
+        None => Ok(last),
+        Some(None) => Err(()),
+        Some(Some(mut data)) => {
+            if data.len() + last.0.len() > MAX_CHUNKED_MESSAGE_SIZE {
+                return Err(());
+            }
+            data.extend_from_slice(&last.0);
+            Ok(EncodedMessage(data))
         }
     }
 }
 
+/// Passes the message through each middleware of the list, stopping at the first one that
+/// rejects it.
+fn apply_middlewares(
+    middlewares: &mut [Box<dyn Middleware>],
+    interface: &InterfaceHash,
+    emitter: Pid,
+    message: &mut EncodedMessage,
+) -> Verdict {
+    for middleware in middlewares {
+        if middleware.on_message(interface, emitter, message) == Verdict::Reject {
+            return Verdict::Reject;
+        }
+    }
+
+    Verdict::Accept
+}
+
 /// If any of the threads of the given process is waiting for a message to arrive, checks the
 /// queue and tries to resume said thread.
 fn try_resume_message_wait(process: extrinsics::ProcessesCollectionExtrinsicsProc<Process, ()>) {
@@ -839,6 +2055,10 @@ fn try_resume_message_wait(process: extrinsics::ProcessesCollectionExtrinsicsPro
 fn try_resume_message_wait_thread(
     mut thread: extrinsics::ProcessesCollectionExtrinsicsThreadWaitMessage<Process, ()>,
 ) -> extrinsics::ProcessesCollectionExtrinsicsThread<Process, ()> {
+    if let Some(max_messages) = thread.max_messages() {
+        return try_resume_messages_wait_thread(thread, max_messages);
+    }
+
     // Try to find a message in the queue that matches something the user is waiting for.
     let mut index_in_queue = 0;
     let index_in_msg_ids = loop {
@@ -852,14 +2072,7 @@ fn try_resume_message_wait_thread(
         }
 
         // For that message in queue, grab the value that must be in `msg_ids` in order to match.
-        let msg_id = match &thread.process_user_data().messages_queue[index_in_queue] {
-            redshirt_syscalls_interface::ffi::Message::Interface(_) => MessageId::from(1),
-            redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(_) => MessageId::from(1),
-            redshirt_syscalls_interface::ffi::Message::Response(response) => {
-                debug_assert!(u64::from(response.message_id) >= 2);
-                response.message_id
-            }
-        };
+        let msg_id = poll_id(&thread.process_user_data().messages_queue[index_in_queue]);
 
         if let Some(p) = thread.message_ids_iter().position(|id| id == msg_id.into()) {
             break p;
@@ -871,17 +2084,10 @@ fn try_resume_message_wait_thread(
     // If we reach here, we have found a message that matches what the user wants.
 
     // Adjust the `index_in_list` field of the message to match what we have.
-    match thread.process_user_data().messages_queue[index_in_queue] {
-        redshirt_syscalls_interface::ffi::Message::Response(ref mut response) => {
-            response.index_in_list = u32::try_from(index_in_msg_ids).unwrap();
-        }
-        redshirt_syscalls_interface::ffi::Message::Interface(ref mut interface) => {
-            interface.index_in_list = u32::try_from(index_in_msg_ids).unwrap();
-        }
-        redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(ref mut proc_destr) => {
-            proc_destr.index_in_list = u32::try_from(index_in_msg_ids).unwrap();
-        }
-    }
+    set_index_in_list(
+        &mut thread.process_user_data().messages_queue[index_in_queue],
+        u32::try_from(index_in_msg_ids).unwrap(),
+    );
 
     // Turn said message into bytes.
     // TODO: would be great to not do that every single time
@@ -901,3 +2107,108 @@ fn try_resume_message_wait_thread(
         From::from(thread.resume_message_too_big(msg_bytes.0.len()))
     }
 }
+
+/// Same as [`try_resume_message_wait_thread`], for a thread that has called `next_messages` and
+/// accepts up to `max_messages` messages at once.
+///
+/// Each entry of the list of messages to wait upon matches at most one message.
+fn try_resume_messages_wait_thread(
+    mut thread: extrinsics::ProcessesCollectionExtrinsicsThreadWaitMessage<Process, ()>,
+    max_messages: u32,
+) -> extrinsics::ProcessesCollectionExtrinsicsThread<Process, ()> {
+    let mut msg_ids = thread.message_ids_iter().collect::<Vec<_>>();
+    let allowed_size = thread.allowed_message_size();
+
+    // Indices within `msg_ids` of the messages that we deliver, and their encoding.
+    let mut indices = Vec::new();
+    let mut encoded = Vec::new();
+
+    let mut index_in_queue = 0;
+    while index_in_queue < thread.process_user_data().messages_queue.len()
+        && indices.len() < usize::try_from(max_messages).unwrap()
+    {
+        let msg_id = poll_id(&thread.process_user_data().messages_queue[index_in_queue]);
+        let index_in_msg_ids = match msg_ids.iter().position(|id| *id == msg_id) {
+            Some(p) => p,
+            None => {
+                index_in_queue += 1;
+                continue;
+            }
+        };
+
+        let message = &mut thread.process_user_data().messages_queue[index_in_queue];
+        set_index_in_list(message, u32::try_from(index_in_msg_ids).unwrap());
+        // TODO: would be great to not do that every single time
+        let msg_bytes = message.clone().encode();
+
+        let prefix = parity_scale_codec::Compact(u32::try_from(indices.len() + 1).unwrap());
+        let total_size =
+            parity_scale_codec::Encode::encoded_size(&prefix) + encoded.len() + msg_bytes.0.len();
+        if total_size > allowed_size {
+            if indices.is_empty() {
+                return From::from(thread.resume_message_too_big(total_size));
+            }
+            break;
+        }
+
+        // Pop the message from the queue, so that we don't deliver it twice.
+        thread
+            .process_user_data()
+            .messages_queue
+            .remove(index_in_queue);
+        msg_ids[index_in_msg_ids] = MessageId::from(0);
+        indices.push(index_in_msg_ids);
+        encoded.extend_from_slice(&msg_bytes.0);
+    }
+
+    if indices.is_empty() {
+        return if thread.block() {
+            From::from(thread)
+        } else {
+            From::from(thread.resume_no_message())
+        };
+    }
+
+    // The messages are encoded the same way as a `Vec<Message>`.
+    let mut out = parity_scale_codec::Encode::encode(&parity_scale_codec::Compact(
+        u32::try_from(indices.len()).unwrap(),
+    ));
+    out.extend_from_slice(&encoded);
+    From::from(thread.resume_messages(&indices, EncodedMessage(out)))
+}
+
+/// Returns the value that must be in the list of messages to wait upon in order for the given
+/// message to be delivered.
+fn poll_id(message: &redshirt_syscalls_interface::ffi::Message) -> MessageId {
+    match message {
+        redshirt_syscalls_interface::ffi::Message::Interface(_) => MessageId::from(1),
+        redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(_) => MessageId::from(1),
+        redshirt_syscalls_interface::ffi::Message::Shutdown(_) => MessageId::from(1),
+        redshirt_syscalls_interface::ffi::Message::MessageCancelled(_) => MessageId::from(1),
+        redshirt_syscalls_interface::ffi::Message::Response(response) => {
+            debug_assert!(u64::from(response.message_id) >= 2);
+            response.message_id
+        }
+    }
+}
+
+/// Sets the `index_in_list` field of the given message.
+fn set_index_in_list(message: &mut redshirt_syscalls_interface::ffi::Message, index: u32) {
+    match message {
+        redshirt_syscalls_interface::ffi::Message::Response(ref mut response) => {
+            response.index_in_list = index;
+        }
+        redshirt_syscalls_interface::ffi::Message::Interface(ref mut interface) => {
+            interface.index_in_list = index;
+        }
+        redshirt_syscalls_interface::ffi::Message::ProcessDestroyed(ref mut proc_destr) => {
+            proc_destr.index_in_list = index;
+        }
+        redshirt_syscalls_interface::ffi::Message::Shutdown(ref mut shutdown) => {
+            shutdown.index_in_list = index;
+        }
+        redshirt_syscalls_interface::ffi::Message::MessageCancelled(ref mut cancelled) => {
+            cancelled.index_in_list = index;
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hooks that intercept the messages emitted on an interface.
+//!
+//! A [`Middleware`] is attached to a specific interface and is called for every message emitted
+//! on that interface, before the message is delivered to the handler. It can inspect the
+//! message, modify its body, or reject it altogether.
+//!
+//! When a message is rejected, the emitter is notified the same way as if the interface wasn't
+//! available. For messages emitted by a process, the emission fails. For messages emitted
+//! through [`Core::emit_interface_message_answer`](crate::scheduler::Core::emit_interface_message_answer),
+//! an error response is produced.
+
+use crate::{EncodedMessage, InterfaceHash, Pid};
+
+/// Hook called for each message emitted on an interface.
+pub trait Middleware: Send {
+    /// Called when `emitter` emits `message` on `interface`. The message can be modified in
+    /// place.
+    fn on_message(
+        &mut self,
+        interface: &InterfaceHash,
+        emitter: Pid,
+        message: &mut EncodedMessage,
+    ) -> Verdict;
+}
+
+/// What to do with a message that has been passed to a [`Middleware`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Let the message continue its way towards the handler, or towards the next middleware.
+    Accept,
+    /// Don't deliver the message.
+    Reject,
+}
+
+impl<F> Middleware for F
+where
+    F: FnMut(&InterfaceHash, Pid, &mut EncodedMessage) -> Verdict + Send,
+{
+    fn on_message(
+        &mut self,
+        interface: &InterfaceHash,
+        emitter: Pid,
+        message: &mut EncodedMessage,
+    ) -> Verdict {
+        (self)(interface, emitter, message)
+    }
+}
@@ -124,7 +124,7 @@ pub enum RunOneOutcome<'a, TExtr, TPud, TTud> {
         dead_threads: Vec<(ThreadId, TTud)>,
 
         /// Value returned by the main thread that has finished, or error that happened.
-        outcome: Result<Option<wasmi::RuntimeValue>, wasmi::Trap>,
+        outcome: Result<Option<wasmi::RuntimeValue>, vm::ExecError>,
     },
 
     /// A thread in a process has finished.
@@ -239,6 +239,10 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
         let (mut process, inner_thread_index): (OccupiedEntry<_, _, _>, usize) = {
             let entries = self.processes.iter_mut().collect::<Vec<_>>();
             // TODO: entries.shuffle(&mut rand::thread_rng());
+            // TODO: `run` takes `&mut self` and only one CPU ever runs the kernel, so there is
+            //       no contention here yet. Once multiple workers can run processes in parallel,
+            //       replace this linear scan with per-worker deques of ready processes, with idle
+            //       workers stealing from the others, instead of a single shared queue.
             let entry = entries
                 .into_iter()
                 .filter_map(|(k, p)| {
@@ -259,6 +263,10 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
         };
 
         // Now run the thread until something happens.
+        // TODO: threads are never preempted and run until they emit an extrinsic call or finish.
+        //       Once preemption is implemented, the time slice should be configurable per
+        //       priority class through the `SystemBuilder`, and the number of involuntary
+        //       preemptions should be reported in the metrics.
         let run_outcome = {
             let mut thread = match process.get_mut().state_machine.thread(inner_thread_index) {
                 Some(t) => t,
@@ -354,6 +362,14 @@ impl<TExtr, TPud, TTud> ProcessesCollection<TExtr, TPud, TTud> {
         self.processes.keys().cloned()
     }
 
+    /// Returns the total size, in bytes, of the memory of all the processes.
+    pub fn memory_usage(&self) -> u64 {
+        self.processes
+            .values()
+            .map(|p| p.state_machine.memory_size() as u64)
+            .sum()
+    }
+
     /// Returns a process by its [`Pid`], if it exists.
     pub fn process_by_id(&mut self, pid: Pid) -> Option<ProcessesCollectionProc<TPud, TTud>> {
         match self.processes.entry(pid) {
@@ -426,6 +442,9 @@ impl<TExtr> ProcessesCollectionBuilder<TExtr> {
     ///
     /// The function signature passed as parameter is enforced when the process is created.
     ///
+    /// > **Note**: Signatures using reference types can be registered, but since the interpreter
+    /// >           doesn't support reference types yet, no module will be able to import them.
+    ///
     /// # Panic
     ///
     /// Panics if an extrinsic with this interface/name combination has already been registered.
@@ -496,6 +515,11 @@ impl<'a, TPud, TTud> ProcessesCollectionProc<'a, TPud, TTud> {
         &mut self.process.get_mut().user_data
     }
 
+    /// Returns the size, in bytes, of the memory of the process.
+    pub fn memory_size(&self) -> usize {
+        self.process.get().state_machine.memory_size()
+    }
+
     /// Adds a new thread to the process, starting the function with the given index and passing
     /// the given parameters.
     ///
@@ -660,6 +684,23 @@ impl<'a, TPud, TTud> ProcessesCollectionThread<'a, TPud, TTud> {
             .state_machine
             .write_memory(offset, value)
     }
+
+    /// Returns the size, in bytes, of the memory of the process.
+    pub fn memory_size(&self) -> usize {
+        self.process.get().state_machine.memory_size()
+    }
+
+    /// Aborts the process the thread belongs to, and returns its [`Pid`] and the associated user
+    /// data. The first element of the list of threads is the main thread's.
+    pub fn abort(self) -> (Pid, TPud, Vec<(ThreadId, TTud)>) {
+        let (pid, proc) = self.process.remove_entry();
+        let dead_threads = proc
+            .state_machine
+            .into_user_datas()
+            .map(|t| (t.thread_id, t.user_data))
+            .collect::<Vec<_>>();
+        (pid, proc.user_data, dead_threads)
+    }
 }
 
 impl<'a, TPud, TTud> fmt::Debug for ProcessesCollectionThread<'a, TPud, TTud>
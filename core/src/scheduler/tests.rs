@@ -15,12 +15,19 @@
 
 #![cfg(test)]
 
-use super::{Core, CoreRunOutcome};
+use super::{
+    Core, CoreRunOutcome, ExecError, HostFunctionContext, OrphanPolicy, SetInterfaceHandlerError,
+    Verdict,
+};
 use crate::{
-    module::Module,
+    module::{Module, ModuleMetadata},
+    sig,
     signature::{Signature, ValueType},
+    Decode, EncodedMessage, InterfaceHash, Pid,
 };
+use alloc::{format, string::String, vec, vec::Vec};
 use core::iter;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
 
 #[test]
 fn basic_module() {
@@ -75,3 +82,1268 @@ fn trapping_module() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn reroute_unregistered_interface() {
+    let mut builder = Core::new();
+    let reserved = builder.reserve_pid();
+    let mut core = builder.build();
+
+    let interface = InterfaceHash::from_raw_hash([0xaa; 32]);
+    assert!(core.reroute_interface(interface.clone(), reserved).is_err());
+    assert!(core
+        .set_interface_handler(interface.clone(), reserved)
+        .is_ok());
+    assert!(core.reroute_interface(interface, reserved).is_ok());
+}
+
+#[test]
+fn reroute_transfers_queued_messages() {
+    // Waits for an interface message and returns the first byte of its body, which is found at
+    // offset 47 of the encoded message.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "\01\00\00\00\00\00\00\00")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 64) (i32.const 256) (i32.const 1)))
+            (i32.load8_u (i32.const 111)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0xab; 32]);
+
+    let mut builder = Core::new();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    let old_handler = core.execute(&module).unwrap().pid();
+    let new_handler = core.execute(&module).unwrap().pid();
+    core.set_interface_handler(interface.clone(), old_handler)
+        .unwrap();
+
+    // The message is queued for the old handler, which hasn't had the occasion to run yet.
+    core.emit_interface_message_no_answer(emitter, interface.clone(), EncodedMessage(vec![0x42]));
+    core.reroute_interface(interface, new_handler).unwrap();
+
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                pid,
+                outcome: Ok(ret_val),
+                ..
+            } => {
+                assert_eq!(pid, new_handler);
+                assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(0x42)));
+                break;
+            }
+            CoreRunOutcome::ProgramFinished { .. } => panic!(),
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+
+    // The old handler is still waiting for a message.
+    assert_eq!(core.processes().len(), 1);
+    assert_eq!(core.processes()[0].pid, old_handler);
+}
+
+#[test]
+fn middleware_modifies_and_rejects() {
+    let interface = InterfaceHash::from_raw_hash([0xbb; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let emitter = builder.reserve_pid();
+    builder.add_middleware(
+        interface.clone(),
+        |_: &InterfaceHash, _: Pid, message: &mut EncodedMessage| {
+            if message.0.is_empty() {
+                return Verdict::Reject;
+            }
+            message.0.push(0xff);
+            Verdict::Accept
+        },
+    );
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+
+    core.emit_interface_message_no_answer(emitter, interface.clone(), EncodedMessage(vec![1]));
+    match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { message, .. } => {
+            assert_eq!(message.0, vec![1, 0xff]);
+        }
+        _ => panic!(),
+    }
+
+    let message_id =
+        core.emit_interface_message_answer(emitter, interface, EncodedMessage(Vec::new()));
+    match core.run() {
+        CoreRunOutcome::MessageResponse {
+            message_id: id,
+            response,
+        } => {
+            assert_eq!(id, message_id);
+            assert!(response.is_err());
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn cancel_message_notifies_handler() {
+    let interface = InterfaceHash::from_raw_hash([0xcc; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+
+    let message_id =
+        core.emit_interface_message_answer(emitter, interface.clone(), EncodedMessage(vec![1]));
+    match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { .. } => {}
+        _ => panic!(),
+    }
+
+    core.cancel_message(emitter, message_id);
+    match core.run() {
+        CoreRunOutcome::ReservedPidMessageCancelled {
+            message_id: id,
+            interface: iface,
+        } => {
+            assert_eq!(id, message_id);
+            assert_eq!(iface, interface);
+        }
+        _ => panic!(),
+    }
+
+    // Answering a cancelled message has no effect.
+    core.answer_message(message_id, Ok(EncodedMessage(vec![2])));
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn partial_answers_delivered_in_order() {
+    let interface = InterfaceHash::from_raw_hash([0xdd; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+
+    let message_id =
+        core.emit_interface_message_answer(emitter, interface, EncodedMessage(vec![1]));
+    match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { message_id: id, .. } => {
+            assert_eq!(id, Some(message_id))
+        }
+        _ => panic!(),
+    }
+
+    core.answer_message_partial(message_id, EncodedMessage(vec![10]));
+    core.answer_message_partial(message_id, EncodedMessage(vec![11]));
+    core.answer_message(message_id, Ok(EncodedMessage(vec![12])));
+
+    for expected in &[10, 11] {
+        match core.run() {
+            CoreRunOutcome::MessagePartialResponse {
+                message_id: id,
+                response,
+            } => {
+                assert_eq!(id, message_id);
+                assert_eq!(response.0, vec![*expected]);
+            }
+            _ => panic!(),
+        }
+    }
+
+    match core.run() {
+        CoreRunOutcome::MessageResponse {
+            message_id: id,
+            response,
+        } => {
+            assert_eq!(id, message_id);
+            assert_eq!(response.unwrap().0, vec![12]);
+        }
+        _ => panic!(),
+    }
+
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn partial_answers_received_by_process() {
+    // Emits a message on the interface whose hash is all zeroes, then waits three times for a
+    // response. Each response is a `Message::Response` of 18 bytes whose single byte of data is
+    // found at offset 15 and whose `partial` flag is found at offset 16. Returns the three
+    // `partial` flags in the lowest byte, followed with the three bytes of data.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $wait (param $out i32)
+            (i64.store (i32.const 72) (i64.load (i32.const 64)))
+            (drop (call $next_message (i32.const 72) (i32.const 1) (local.get $out) (i32.const 64) (i32.const 1))))
+        (func $_start (result i32)
+            (drop (call $emit_message (i32.const 0) (i32.const 32) (i32.const 0) (i32.const 1) (i32.const 0) (i32.const 64)))
+            (call $wait (i32.const 128))
+            (call $wait (i32.const 192))
+            (call $wait (i32.const 256))
+            (i32.or
+                (i32.or
+                    (i32.or (i32.load8_u (i32.const 144))
+                        (i32.shl (i32.load8_u (i32.const 208)) (i32.const 1)))
+                    (i32.or (i32.shl (i32.load8_u (i32.const 272)) (i32.const 2))
+                        (i32.shl (i32.load8_u (i32.const 143)) (i32.const 8))))
+                (i32.or
+                    (i32.shl (i32.load8_u (i32.const 207)) (i32.const 16))
+                    (i32.shl (i32.load8_u (i32.const 271)) (i32.const 24)))))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface, handler).unwrap();
+    core.execute(&module).unwrap();
+
+    let message_id = match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { message_id, .. } => message_id.unwrap(),
+        _ => panic!(),
+    };
+
+    core.answer_message_partial(message_id, EncodedMessage(vec![10]));
+    core.answer_message_partial(message_id, EncodedMessage(vec![11]));
+    core.answer_message(message_id, Ok(EncodedMessage(vec![12])));
+
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                outcome: Ok(ret_val),
+                ..
+            } => {
+                let expected = 0b011 | (10 << 8) | (11 << 16) | (12 << 24);
+                assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(expected)));
+                break;
+            }
+            CoreRunOutcome::ProgramFinished { .. } => panic!(),
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn chunked_message_reassembled() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "emit_message_chunk" (func $emit_message_chunk (param i32 i32 i32 i32) (result i32)))
+        (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd\dd")
+        (data (i32.const 32) "\40\00\00\00\02\00\00\00\42\00\00\00\02\00\00\00")
+        (data (i32.const 64) "abcd")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $emit_message_chunk (i32.const 0) (i32.const 32) (i32.const 1) (i32.const 0)))
+            (call $emit_message (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0xdd; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+    core.execute(&module).unwrap();
+
+    loop {
+        match core.run() {
+            CoreRunOutcome::ReservedPidInterfaceMessage {
+                interface: iface,
+                message,
+                ..
+            } => {
+                assert_eq!(iface, interface);
+                assert_eq!(message.0, b"abcd".to_vec());
+                break;
+            }
+            CoreRunOutcome::ProgramFinished { .. } => panic!(),
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn chunked_message_too_large_dropped() {
+    // Emits 17 chunks of 1 MiB, followed with a message of one byte, then emits a message of one
+    // byte. The first message exceeds the maximum size and must be dropped.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "emit_message_chunk" (func $emit_message_chunk (param i32 i32 i32 i32) (result i32)))
+        (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 17)
+        (data (i32.const 1048640) "\00\00\00\00\00\00\10\00\00\00\00\00\01\00\00\00")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (local $i i32)
+            (block
+                (loop
+                    (drop (call $emit_message_chunk (i32.const 1048576) (i32.const 1048640) (i32.const 1) (i32.const 0)))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br_if 0 (i32.lt_u (local.get $i) (i32.const 17)))))
+            (drop (call $emit_message (i32.const 1048576) (i32.const 1048648) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 0)))
+            (call $emit_message (i32.const 1048576) (i32.const 1048648) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface, handler).unwrap();
+    core.execute(&module).unwrap();
+
+    let mut received = Vec::new();
+    loop {
+        match core.run() {
+            CoreRunOutcome::ReservedPidInterfaceMessage { message, .. } => received.push(message),
+            CoreRunOutcome::ProgramFinished { outcome, .. } => {
+                assert!(outcome.is_ok());
+                break;
+            }
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].0, vec![0]);
+}
+
+#[test]
+fn invalid_extrinsic_call_aborts_process() {
+    // Passes an output buffer that is outside of the memory of the process.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $next_message (i32.const 0) (i32.const 1) (i32.const 65500) (i32.const 256) (i32.const 1)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    let pid = core.execute(&module).unwrap().pid();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            pid: p,
+            outcome: Err(ExecError::InvalidExtrinsicCall),
+            ..
+        } => assert_eq!(p, pid),
+        _ => panic!(),
+    }
+
+    assert!(core.processes().is_empty());
+}
+
+#[test]
+fn extrinsic_call_without_memory_aborts_process() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (func $_start (result i32)
+            (call $next_message (i32.const 0) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 1)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Err(ExecError::InvalidExtrinsicCall),
+            ..
+        } => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn blob_read_by_owner() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "blob_create" (func $blob_create (param i32 i32 i32) (result i32)))
+        (import "redshirt" "blob_read" (func $blob_read (param i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "hello")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $blob_create (i32.const 0) (i32.const 5) (i32.const 16)))
+            (drop (call $blob_read (i32.const 16) (i32.const 32) (i32.const 5)))
+            (i32.load8_u (i32.const 36)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                outcome: Ok(ret_val),
+                ..
+            } => {
+                assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(i32::from(b'o'))));
+                break;
+            }
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn blob_too_large_refused() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "blob_create" (func $blob_create (param i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $blob_create (i32.const 0) (i32.const 0x1000001) (i32.const 16)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(1))),
+        _ => panic!(),
+    }
+}
+
+/// Runs a process that creates a blob, shares it with the process whose PID it receives in a
+/// message, then sends the blob's handle to a second process. Returns the value returned by the
+/// second process, which is the first byte of the blob, or -1 if it couldn't read it.
+fn blob_read_by_other_process(share_with_reader: bool) -> Option<wasmi::RuntimeValue> {
+    // Waits for a message containing a PID, found at offset 47 of the encoded message, creates a
+    // blob, shares it with this PID, and emits a message containing the handle of the blob on
+    // the interface whose hash is `[0x02; 32]`. Then waits forever so that the blob stays alive.
+    let owner_module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (import "redshirt" "blob_create" (func $blob_create (param i32 i32 i32) (result i32)))
+        (import "redshirt" "blob_share" (func $blob_share (param i32 i32) (result i32)))
+        (import "redshirt" "emit_message" (func $emit_message (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "\01\00\00\00\00\00\00\00")
+        (data (i32.const 8) "hello")
+        (data (i32.const 24) "\10\00\00\00\08\00\00\00")
+        (data (i32.const 320) "\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02\02")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 64) (i32.const 256) (i32.const 1)))
+            (drop (call $blob_create (i32.const 8) (i32.const 5) (i32.const 16)))
+            (drop (call $blob_share (i32.const 16) (i32.const 111)))
+            (drop (call $emit_message (i32.const 320) (i32.const 24) (i32.const 1) (i32.const 0) (i32.const 0) (i32.const 32)))
+            (i64.store (i32.const 0) (i64.const 1))
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 64) (i32.const 256) (i32.const 1)))
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    // Waits for a message containing a blob handle, found at offset 47 of the encoded message,
+    // and returns the first byte of the blob, or -1 if the blob can't be read.
+    let reader_module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (import "redshirt" "blob_read" (func $blob_read (param i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "\01\00\00\00\00\00\00\00")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 64) (i32.const 256) (i32.const 1)))
+            (if (result i32) (i32.eq (call $blob_read (i32.const 111) (i32.const 320) (i32.const 16)) (i32.const -1))
+                (then (i32.const -1))
+                (else (i32.load8_u (i32.const 320)))))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut builder = Core::new();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    let owner = core.execute(&owner_module).unwrap().pid();
+    let reader = core.execute(&reader_module).unwrap().pid();
+    core.set_interface_handler(InterfaceHash::from_raw_hash([0x01; 32]), owner)
+        .unwrap();
+    core.set_interface_handler(InterfaceHash::from_raw_hash([0x02; 32]), reader)
+        .unwrap();
+
+    let share_with = if share_with_reader { reader } else { emitter };
+    core.emit_interface_message_no_answer(
+        emitter,
+        InterfaceHash::from_raw_hash([0x01; 32]),
+        EncodedMessage(u64::from(share_with).to_le_bytes().to_vec()),
+    );
+
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                pid,
+                outcome: Ok(ret_val),
+                ..
+            } if pid == reader => return ret_val,
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn blob_read_once_shared() {
+    assert_eq!(
+        blob_read_by_other_process(true),
+        Some(wasmi::RuntimeValue::I32(i32::from(b'h')))
+    );
+}
+
+#[test]
+fn blob_not_shared_refused() {
+    assert_eq!(
+        blob_read_by_other_process(false),
+        Some(wasmi::RuntimeValue::I32(-1))
+    );
+}
+
+#[test]
+fn sleep_until_woken_up() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "sleep_until" (func $sleep_until (param i64)))
+        (func $_start (result i32)
+            (call $sleep_until (i64.const 1000))
+            i32.const 7)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), Some(1000));
+
+    core.wake_up_sleeping_threads(999);
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+
+    core.wake_up_sleeping_threads(1000);
+    assert_eq!(core.next_wake_up(), None);
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(7)));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn try_emit_would_block_without_handler() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "try_emit_message" (func $try_emit_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $try_emit_message (i32.const 0) (i32.const 32) (i32.const 1) (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(2)));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn message_deadline_expires() {
+    // Emits a message with a deadline, then waits for the response, which is a
+    // `Message::Response` whose data starts at offset 15. Returns the first two bytes of data.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "emit_message_with_deadline" (func $emit_message_with_deadline (param i32 i32 i32 i32 i64 i32) (result i32)))
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $emit_message_with_deadline (i32.const 0) (i32.const 32) (i32.const 1) (i32.const 0) (i64.const 1000) (i32.const 64)))
+            (drop (call $next_message (i32.const 64) (i32.const 1) (i32.const 128) (i32.const 64) (i32.const 1)))
+            (i32.or (i32.load8_u (i32.const 143))
+                (i32.shl (i32.load8_u (i32.const 144)) (i32.const 8))))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0; 32]);
+
+    let mut builder = Core::new();
+    let handler = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+    core.execute(&module).unwrap();
+
+    let message_id = match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { message_id, .. } => message_id.unwrap(),
+        _ => panic!(),
+    };
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), Some(1000));
+
+    core.expire_message_deadlines(999);
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+
+    core.expire_message_deadlines(1000);
+    match core.run() {
+        CoreRunOutcome::ReservedPidMessageCancelled {
+            message_id: id,
+            interface: iface,
+        } => {
+            assert_eq!(id, message_id);
+            assert_eq!(iface, interface);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), None);
+
+    // The answer is `Err`, followed with an `ErrorPayload` whose class is `TIMED_OUT`.
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                outcome: Ok(ret_val),
+                ..
+            } => {
+                let expected = 1 | (5 << 8);
+                assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(expected)));
+                break;
+            }
+            CoreRunOutcome::Idle => panic!(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn message_deadline_expires_reserved_emitter() {
+    let interface = InterfaceHash::from_raw_hash([0; 32]);
+
+    let mut builder = Core::new();
+    let emitter = builder.reserve_pid();
+    let handler = builder.reserve_pid();
+    let mut core = builder.build();
+    core.set_interface_handler(interface.clone(), handler)
+        .unwrap();
+
+    let message_id = core.emit_interface_message_answer_with_deadline(
+        emitter,
+        interface,
+        EncodedMessage(vec![1]),
+        1000,
+    );
+    match core.run() {
+        CoreRunOutcome::ReservedPidInterfaceMessage { .. } => {}
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), Some(1000));
+
+    core.expire_message_deadlines(1000);
+    match core.run() {
+        CoreRunOutcome::ReservedPidMessageCancelled { .. } => {}
+        _ => panic!(),
+    }
+    match core.run() {
+        CoreRunOutcome::MessageResponse {
+            message_id: id,
+            response: Ok(response),
+        } => {
+            assert_eq!(id, message_id);
+            let response: Result<(), ErrorPayload> = Decode::decode(response).unwrap();
+            assert_eq!(response, Err(ErrorPayload::new(ErrorClass::TIMED_OUT)));
+        }
+        _ => panic!(),
+    }
+
+    // The handler's answer arrives too late and is ignored.
+    core.answer_message(message_id, Ok(EncodedMessage(Vec::new())));
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn directed_message_refused_without_opt_in() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "emit_directed_message" (func $emit_directed_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $emit_directed_message (i32.const 0) (i32.const 8) (i32.const 1) (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(1)));
+        }
+        _ => panic!(),
+    }
+}
+
+/// Escapes bytes so that they can be put in the string of a WAT data segment.
+fn wat_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\{:02x}", b)).collect()
+}
+
+/// Starts a process that accepts directed messages, waits for two messages, and returns the
+/// `chunk` field of the first message ORed with the one of the second message shifted by 8
+/// bits. Returns once the process is waiting for the first message.
+fn start_directed_messages_receiver(core: &mut Core) -> Pid {
+    // The `chunk` field of an interface message without message ID and with two bytes of data
+    // is at offset 49.
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "accept_directed_messages" (func $accept_directed_messages))
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $accept_directed_messages)
+            (i64.store (i32.const 0) (i64.const 1))
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 128) (i32.const 256) (i32.const 1)))
+            (i64.store (i32.const 0) (i64.const 1))
+            (drop (call $next_message (i32.const 0) (i32.const 1) (i32.const 512) (i32.const 256) (i32.const 1)))
+            (i32.or (i32.load8_u (i32.const 177))
+                (i32.shl (i32.load8_u (i32.const 561)) (i32.const 8))))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let pid = core.execute(&module).unwrap().pid();
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+    pid
+}
+
+#[test]
+fn directed_message_deadline_expires() {
+    let mut core = Core::new().build();
+    let target = start_directed_messages_receiver(&mut core);
+
+    // Emits a directed message with a deadline, then waits for the response, which is a
+    // `Message::Response` whose data starts at offset 15. Returns the first two bytes of data.
+    let module = Module::from_wat(&format!(
+        r#"(module
+        (import "redshirt" "emit_directed_message_with_deadline" (func $emit_directed_message_with_deadline (param i32 i32 i32 i64 i32) (result i32)))
+        (import "redshirt" "next_message" (func $next_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (data (i32.const 0) "{}")
+        (func $_start (result i32)
+            (drop (call $emit_directed_message_with_deadline (i32.const 0) (i32.const 32) (i32.const 1) (i64.const 1000) (i32.const 64)))
+            (drop (call $next_message (i32.const 64) (i32.const 1) (i32.const 128) (i32.const 64) (i32.const 1)))
+            (i32.or (i32.load8_u (i32.const 143))
+                (i32.shl (i32.load8_u (i32.const 144)) (i32.const 8))))
+        (export "_start" (func $_start)))
+    "#,
+        wat_bytes(&u64::from(target).to_le_bytes())
+    ))
+    .unwrap();
+
+    let emitter = core.execute(&module).unwrap().pid();
+    match core.run() {
+        CoreRunOutcome::Idle => {}
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), Some(1000));
+
+    // The answer is `Err`, followed with an `ErrorPayload` whose class is `TIMED_OUT`.
+    core.expire_message_deadlines(1000);
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            pid,
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            assert_eq!(pid, emitter);
+            let expected = 1 | (5 << 8);
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(expected)));
+        }
+        _ => panic!(),
+    }
+    assert_eq!(core.next_wake_up(), None);
+}
+
+#[test]
+fn directed_message_chunk_flagged() {
+    let mut core = Core::new().build();
+    let target = start_directed_messages_receiver(&mut core);
+
+    // Emits `ab` as a chunk, then `cd` as the last part of the message.
+    let module = Module::from_wat(&format!(
+        r#"(module
+        (import "redshirt" "emit_directed_message_chunk" (func $emit_directed_message_chunk (param i32 i32 i32) (result i32)))
+        (import "redshirt" "emit_directed_message" (func $emit_directed_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (data (i32.const 0) "{}")
+        (data (i32.const 16) "ab")
+        (data (i32.const 24) "cd")
+        (data (i32.const 32) "\10\00\00\00\02\00\00\00\18\00\00\00\02\00\00\00")
+        (func $_start (result i32)
+            (drop (call $emit_directed_message_chunk (i32.const 0) (i32.const 32) (i32.const 1)))
+            (drop (call $emit_directed_message (i32.const 0) (i32.const 40) (i32.const 1) (i32.const 0) (i32.const 0)))
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+        wat_bytes(&u64::from(target).to_le_bytes())
+    ))
+    .unwrap();
+    core.execute(&module).unwrap();
+
+    let mut target_outcome = None;
+    loop {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                pid,
+                outcome: Ok(ret_val),
+                ..
+            } if pid == target => target_outcome = Some(ret_val),
+            CoreRunOutcome::ProgramFinished { .. } => {}
+            CoreRunOutcome::Idle => break,
+            _ => panic!(),
+        }
+    }
+
+    assert_eq!(target_outcome, Some(Some(wasmi::RuntimeValue::I32(1))));
+}
+
+#[test]
+fn process_info_size() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "process_info" (func $process_info (param i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $process_info (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            // Pid, no parent, module hash, no name, and no registered interface.
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(8 + 1 + 32 + 1 + 1)));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn next_messages_pulls_multiple() {
+    let module = Module::from_wat(
+        r#"(module
+        (import "redshirt" "next_messages" (func $next_messages (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (data (i32.const 0) "\01\00\00\00\00\00\00\00\01\00\00\00\00\00\00\00")
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (drop (call $next_messages (i32.const 0) (i32.const 2) (i32.const 64) (i32.const 1024) (i32.const 1) (i32.const 8)))
+            (i32.load8_u (i32.const 64)))
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let interface = InterfaceHash::from_raw_hash([0xdd; 32]);
+
+    let mut builder = Core::new();
+    let emitter = builder.reserve_pid();
+    let mut core = builder.build();
+    let pid = core.execute(&module).unwrap().pid();
+    core.set_interface_handler(interface.clone(), pid).unwrap();
+
+    core.emit_interface_message_no_answer(emitter, interface.clone(), EncodedMessage(vec![1]));
+    core.emit_interface_message_no_answer(emitter, interface.clone(), EncodedMessage(vec![2]));
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            // SCALE-encoded length prefix of a list of two elements.
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(2 << 2)));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn host_function_called() {
+    fn double(
+        _: &mut HostFunctionContext,
+        params: &[wasmi::RuntimeValue],
+    ) -> Option<wasmi::RuntimeValue> {
+        match params {
+            [wasmi::RuntimeValue::I32(v)] => Some(wasmi::RuntimeValue::I32(v * 2)),
+            _ => panic!(),
+        }
+    }
+
+    let module = Module::from_wat(
+        r#"(module
+        (import "env" "double" (func $double (param i32) (result i32)))
+        (func $_start (result i32)
+            i32.const 21
+            call $double)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut builder = Core::new();
+    builder.add_host_function("env", "double", sig!((I32) -> I32), double);
+    let mut core = builder.build();
+    core.execute(&module).unwrap();
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            outcome: Ok(ret_val),
+            ..
+        } => {
+            assert_eq!(ret_val, Some(wasmi::RuntimeValue::I32(42)));
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn process_hierarchy() {
+    let module = Module::from_wat(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    let parent = core.execute(&module).unwrap().pid();
+    let child = core
+        .execute_child(&module, parent, OrphanPolicy::Kill)
+        .unwrap()
+        .pid();
+
+    assert_eq!(core.parent_of(parent), None);
+    assert_eq!(core.parent_of(child), Some(parent));
+    assert_eq!(core.children_of(parent), vec![child]);
+    assert!(core.children_of(child).is_empty());
+}
+
+#[test]
+fn kill_process() {
+    let module = Module::from_wat(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    let parent = core.execute(&module).unwrap().pid();
+    let child = core
+        .execute_child(&module, parent, OrphanPolicy::Reparent)
+        .unwrap()
+        .pid();
+    assert_eq!(core.processes().len(), 2);
+
+    core.kill(parent).unwrap();
+    assert!(core.kill(parent).is_err());
+
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            pid,
+            outcome: Err(_),
+            ..
+        } => assert_eq!(pid, parent),
+        _ => panic!(),
+    }
+
+    let remaining = core.processes();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].pid, child);
+    assert_eq!(remaining[0].parent, None);
+}
+
+#[test]
+fn orphan_policy_kill() {
+    let module = Module::from_wat(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    let parent = core.execute(&module).unwrap().pid();
+    let child = core
+        .execute_child(&module, parent, OrphanPolicy::Kill)
+        .unwrap()
+        .pid();
+    let grandchild = core
+        .execute_child(&module, child, OrphanPolicy::Kill)
+        .unwrap()
+        .pid();
+
+    core.kill(parent).unwrap();
+
+    let mut finished = Vec::new();
+    for _ in 0..3 {
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                pid,
+                outcome: Err(_),
+                ..
+            } => finished.push(pid),
+            _ => panic!(),
+        }
+    }
+    assert!(finished.contains(&parent));
+    assert!(finished.contains(&child));
+    assert!(finished.contains(&grandchild));
+    assert!(core.processes().is_empty());
+}
+
+#[test]
+fn orphan_policy_reparent() {
+    let module = Module::from_wat(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+    )
+    .unwrap();
+
+    let mut core = Core::new().build();
+    let grandparent = core.execute(&module).unwrap().pid();
+    let parent = core
+        .execute_child(&module, grandparent, OrphanPolicy::Reparent)
+        .unwrap()
+        .pid();
+    let child = core
+        .execute_child(&module, parent, OrphanPolicy::Reparent)
+        .unwrap()
+        .pid();
+
+    core.kill(parent).unwrap();
+    match core.run() {
+        CoreRunOutcome::ProgramFinished {
+            pid,
+            outcome: Err(_),
+            ..
+        } => assert_eq!(pid, parent),
+        _ => panic!(),
+    }
+
+    assert_eq!(core.parent_of(child), Some(grandparent));
+    assert_eq!(core.children_of(grandparent), vec![child]);
+    assert_eq!(core.processes().len(), 2);
+}
+
+/// Builds a module from the given WAT, with a `redshirt-meta` section containing `metadata`.
+fn module_with_metadata(wat: &str, metadata: &ModuleMetadata) -> Module {
+    let mut wasm = wat::parse_str(wat).unwrap();
+    let content = metadata.to_section_content();
+    let name = crate::module::metadata::SECTION_NAME.as_bytes();
+    let section_len = 1 + name.len() + content.len();
+    assert!(section_len < 128);
+    wasm.push(0);
+    wasm.push(section_len as u8);
+    wasm.push(name.len() as u8);
+    wasm.extend_from_slice(name);
+    wasm.extend_from_slice(&content);
+    Module::from_bytes(&wasm).unwrap()
+}
+
+#[test]
+fn undeclared_interface_refused() {
+    let declared = InterfaceHash::from_raw_hash([0x11; 32]);
+    let undeclared = InterfaceHash::from_raw_hash([0x22; 32]);
+
+    let metadata = ModuleMetadata {
+        name: "foo".into(),
+        version: "1.0".into(),
+        registered_interfaces: vec![declared.clone()],
+        used_interfaces: Vec::new(),
+    };
+
+    let module = module_with_metadata(
+        r#"(module
+        (func $_start (result i32)
+            i32.const 0)
+        (export "_start" (func $_start)))
+    "#,
+        &metadata,
+    );
+
+    let mut core = Core::new().build();
+    let pid = core.execute(&module).unwrap().pid();
+
+    assert_eq!(
+        core.set_interface_handler(undeclared, pid),
+        Err(SetInterfaceHandlerError::NotDeclared)
+    );
+    assert!(core.set_interface_handler(declared, pid).is_ok());
+}
+
+#[test]
+fn undeclared_used_interface_refused() {
+    // Tries to emit a message on the interface whose hash is all zeroes, for which there is no
+    // handler. Returns 1 if the emission is refused and 2 if it would block.
+    let wat = r#"(module
+        (import "redshirt" "try_emit_message" (func $try_emit_message (param i32 i32 i32 i32 i32) (result i32)))
+        (memory $mem 1)
+        (export "memory" (memory $mem))
+        (func $_start (result i32)
+            (call $try_emit_message (i32.const 0) (i32.const 32) (i32.const 1) (i32.const 0) (i32.const 0)))
+        (export "_start" (func $_start)))
+    "#;
+
+    let emit_outcome = |used_interfaces| {
+        let metadata = ModuleMetadata {
+            name: "foo".into(),
+            version: "1.0".into(),
+            registered_interfaces: Vec::new(),
+            used_interfaces,
+        };
+
+        let mut core = Core::new().build();
+        core.execute(&module_with_metadata(wat, &metadata)).unwrap();
+        match core.run() {
+            CoreRunOutcome::ProgramFinished {
+                outcome: Ok(ret_val),
+                ..
+            } => ret_val,
+            _ => panic!(),
+        }
+    };
+
+    assert_eq!(
+        emit_outcome(vec![InterfaceHash::from_raw_hash([0x11; 32])]),
+        Some(wasmi::RuntimeValue::I32(1))
+    );
+    assert_eq!(
+        emit_outcome(vec![InterfaceHash::from_raw_hash([0; 32])]),
+        Some(wasmi::RuntimeValue::I32(2))
+    );
+}
@@ -14,10 +14,12 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::module::Module;
+use crate::signature::Signature;
 use alloc::{
     borrow::{Cow, ToOwned as _},
     boxed::Box,
     format,
+    string::String,
     vec::Vec,
 };
 use core::{cell::RefCell, convert::TryInto, fmt};
@@ -174,16 +176,50 @@ pub enum ExecOutcome<'a, T> {
         thread: Thread<'a, T>,
 
         /// Error that happened.
-        // TODO: error type should change here
-        error: wasmi::Trap,
+        error: ExecError,
     },
 }
 
+/// Error that has made a thread stop.
+#[derive(Debug)]
+pub enum ExecError {
+    /// The thread has exhausted its call stack or its value stack, for example because of a
+    /// runaway recursion.
+    StackOverflow,
+    /// The thread has trapped for a different reason.
+    Trap(wasmi::Trap),
+    /// The thread has called a system function with invalid parameters, such as a pointer
+    /// outside of its memory.
+    InvalidExtrinsicCall,
+}
+
+impl From<wasmi::Trap> for ExecError {
+    fn from(trap: wasmi::Trap) -> Self {
+        match trap.kind() {
+            wasmi::TrapKind::StackOverflow => ExecError::StackOverflow,
+            _ => ExecError::Trap(trap),
+        }
+    }
+}
+
 /// Error that can happen when initializing a VM.
 #[derive(Debug)]
 pub enum NewErr {
     /// Error in the interpreter.
     Interpreter(wasmi::Error),
+    /// The module imports a function that couldn't be resolved, either because it doesn't exist
+    /// or because its signature doesn't match.
+    UnresolvedFunctionImport {
+        /// Name of the module the function is imported from.
+        module_name: String,
+        /// Name of the function.
+        function: String,
+        /// Signature of the function, as declared by the module.
+        signature: Signature,
+    },
+    /// The WASM `start` function of the module has trapped. Note that this function isn't
+    /// allowed to call imported functions.
+    StartFunctionTrapped(wasmi::Trap),
     /// The "start" symbol doesn't exist.
     StartNotFound,
     /// The "start" symbol must be a function.
@@ -229,6 +265,8 @@ impl<T> ProcessStateMachine<T> {
     ///
     /// A single main thread (whose user data is passed by parameter) is automatically created and
     /// is paused at the start of the "_start" function of the module.
+    ///
+    /// If the module has a WASM `start` function, it is executed to completion beforehand.
     pub fn new(
         module: &Module,
         main_thread_user_data: T,
@@ -236,6 +274,8 @@ impl<T> ProcessStateMachine<T> {
     ) -> Result<Self, NewErr> {
         struct ImportResolve<'a>(
             RefCell<&'a mut dyn FnMut(&str, &str, &wasmi::Signature) -> Result<usize, ()>>,
+            /// First function import that failed to resolve, if any.
+            RefCell<Option<(String, String, Signature)>>,
         );
         impl<'a> wasmi::ImportResolver for ImportResolve<'a> {
             fn resolve_func(
@@ -248,10 +288,17 @@ impl<T> ProcessStateMachine<T> {
                 let index = match closure(module_name, field_name, signature) {
                     Ok(i) => i,
                     Err(_) => {
+                        self.1.borrow_mut().get_or_insert_with(|| {
+                            (
+                                module_name.to_owned(),
+                                field_name.to_owned(),
+                                Signature::from_wasm(signature),
+                            )
+                        });
                         return Err(wasmi::Error::Instantiation(format!(
                             "Couldn't resolve `{}`:`{}`",
                             module_name, field_name
-                        )))
+                        )));
                     }
                 };
 
@@ -292,17 +339,34 @@ impl<T> ProcessStateMachine<T> {
             }
         }
 
-        let not_started =
-            wasmi::ModuleInstance::new(module.as_ref(), &ImportResolve(RefCell::new(&mut symbols)))
-                .map_err(NewErr::Interpreter)?;
+        let resolver = ImportResolve(RefCell::new(&mut symbols), RefCell::new(None));
+        // TODO: the interpreter copies the code of each function and the data segments into
+        //       every instance, even when multiple processes are started from the same module.
+        //       Sharing the code and mapping the initial memory copy-on-write requires support
+        //       from the interpreter, which wasmi doesn't provide at the moment.
+        let not_started = match wasmi::ModuleInstance::new(module.as_ref(), &resolver) {
+            Ok(m) => m,
+            Err(err) => {
+                // Report unresolved imports in a more descriptive way than the interpreter does.
+                if let Some((module_name, function, signature)) = resolver.1.into_inner() {
+                    return Err(NewErr::UnresolvedFunctionImport {
+                        module_name,
+                        function,
+                        signature,
+                    });
+                }
+                return Err(NewErr::Interpreter(err));
+            }
+        };
 
-        // TODO: WASM has a special "start" instruction that can be used to designate a function
-        // that must be executed before the module is considered initialized. It is unclear whether
-        // this is intended to be a function that for example initializes global variables, or if
-        // this is an equivalent of "_start". In practice, Rust never seems to generate such as
-        // "start" instruction, so for now we ignore it. The code below panics if there is such
-        // a "start" item, so we will fortunately not blindly run into troubles.
-        let module = not_started.assert_no_start();
+        // WASM has a special "start" function that must be executed before the module is
+        // considered initialized. Some toolchains use it for example to initialize global
+        // variables. We run it to completion before the main thread is created. Since the
+        // execution of the start function can't be interrupted, it is not allowed to call any
+        // imported function.
+        let module = not_started
+            .run_start(&mut wasmi::NopExternals)
+            .map_err(NewErr::StartFunctionTrapped)?;
 
         let memory = if let Some(mem) = module.export_by_name("memory") {
             if let Some(mem) = mem.as_memory() {
@@ -417,6 +481,8 @@ impl<T> ProcessStateMachine<T> {
 
         match self.module.export_by_name(symbol_name) {
             Some(wasmi::ExternVal::Func(f)) => {
+                // TODO: the maximum depth of the call stack and size of the value stack are
+                //       constants of the interpreter, and can't be configured per process.
                 let execution = match wasmi::FuncInstance::invoke_resumable(&f, params) {
                     Ok(e) => e,
                     Err(err) => unreachable!("{:?}", err),
@@ -465,13 +531,20 @@ impl<T> ProcessStateMachine<T> {
         self.threads.into_iter().map(|thread| thread.user_data)
     }
 
+    /// Returns the size, in bytes, of the memory of the process.
+    pub fn memory_size(&self) -> usize {
+        self.memory
+            .as_ref()
+            .map_or(0, |m| wasmi::memory_units::Bytes::from(m.current_size()).0)
+    }
+
     /// Copies the given memory range into a `Vec<u8>`.
     ///
     /// Returns an error if the range is invalid or out of range.
     pub fn read_memory(&self, offset: u32, size: u32) -> Result<Vec<u8>, ()> {
         let mem = match self.memory.as_ref() {
             Some(m) => m,
-            None => unreachable!(),
+            None => return Err(()),
         };
 
         mem.get(offset, size.try_into().map_err(|_| ())?)
@@ -484,7 +557,7 @@ impl<T> ProcessStateMachine<T> {
     pub fn write_memory(&mut self, offset: u32, value: &[u8]) -> Result<(), ()> {
         let mem = match self.memory.as_ref() {
             Some(m) => m,
-            None => unreachable!(),
+            None => return Err(()),
         };
 
         mem.set(offset, value).map_err(|_| ())
@@ -609,7 +682,7 @@ impl<'a, T> Thread<'a, T> {
                 self.vm.is_poisoned = true;
                 Ok(ExecOutcome::Errored {
                     thread: self,
-                    error: trap,
+                    error: From::from(trap),
                 })
             }
         }
@@ -647,6 +720,18 @@ impl fmt::Display for NewErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             NewErr::Interpreter(_) => write!(f, "Error in the interpreter"),
+            NewErr::UnresolvedFunctionImport {
+                module_name,
+                function,
+                signature,
+            } => write!(
+                f,
+                "Couldn't resolve imported function `{}`:`{}` with signature {}",
+                module_name, function, signature
+            ),
+            NewErr::StartFunctionTrapped(trap) => {
+                write!(f, "The WASM start function has trapped: {:?}", trap)
+            }
             NewErr::StartNotFound => write!(f, "The \"start\" symbol doesn't exist"),
             NewErr::StartIsntAFunction => write!(f, "The \"start\" symbol must be a function"),
             NewErr::MemoryIsntMemory => {
@@ -660,6 +745,16 @@ impl fmt::Display for NewErr {
     }
 }
 
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::StackOverflow => write!(f, "Stack overflow"),
+            ExecError::Trap(trap) => write!(f, "{}", trap),
+            ExecError::InvalidExtrinsicCall => write!(f, "Invalid system function call"),
+        }
+    }
+}
+
 impl fmt::Display for StartErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -685,8 +780,9 @@ impl fmt::Display for RunErr {
 
 #[cfg(test)]
 mod tests {
-    use super::{ExecOutcome, NewErr, ProcessStateMachine};
+    use super::{ExecError, ExecOutcome, NewErr, ProcessStateMachine};
     use crate::module::Module;
+    use crate::signature::Signature;
 
     #[test]
     fn starts_if_main() {
@@ -720,6 +816,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn error_if_unresolved_import() {
+        let module = Module::from_wat(
+            r#"(module
+            (import "foo" "test" (func $test (result i32)))
+            (func $_start (result i32)
+                call $test)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+
+        match ProcessStateMachine::new(&module, (), |_, _, _| Err(())) {
+            Err(NewErr::UnresolvedFunctionImport {
+                module_name,
+                function,
+                signature,
+            }) => {
+                assert_eq!(module_name, "foo");
+                assert_eq!(function, "test");
+                assert_eq!(signature, crate::sig!(() -> I32));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn start_function_runs_before_main() {
+        let module = Module::from_wat(
+            r#"(module
+            (global $val (mut i32) (i32.const 1))
+            (func $init
+                i32.const 42
+                set_global $val)
+            (func $_start (result i32)
+                get_global $val)
+            (start $init)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+
+        let mut state_machine =
+            ProcessStateMachine::new(&module, (), |_, _, _| unreachable!()).unwrap();
+        match state_machine.thread(0).unwrap().run(None) {
+            Ok(ExecOutcome::ThreadFinished {
+                return_value: Some(wasmi::RuntimeValue::I32(42)),
+                ..
+            }) => {}
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn start_function_trap_is_error() {
+        let module = Module::from_wat(
+            r#"(module
+            (func $init
+                unreachable)
+            (func $_start (result i32)
+                i32.const 5)
+            (start $init)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+
+        match ProcessStateMachine::new(&module, (), |_, _, _| unreachable!()) {
+            Err(NewErr::StartFunctionTrapped(_)) => {}
+            _ => panic!(),
+        }
+    }
+
     #[test]
     fn main_executes() {
         let module = Module::from_wat(
@@ -802,5 +971,27 @@ mod tests {
         // TODO: start running another function and check that `Poisoned` error is returned
     }
 
+    #[test]
+    fn stack_overflow() {
+        let module = Module::from_wat(
+            r#"(module
+            (func $_start
+                call $_start)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+
+        let mut state_machine =
+            ProcessStateMachine::new(&module, (), |_, _, _| unreachable!()).unwrap();
+        match state_machine.thread(0).unwrap().run(None) {
+            Ok(ExecOutcome::Errored {
+                error: ExecError::StackOverflow,
+                ..
+            }) => {}
+            _ => panic!(),
+        }
+    }
+
     // TODO: start mutiple threads
 }
@@ -13,13 +13,24 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+//! Signatures of functions.
+//!
+//! A [`Signature`] describes the parameters and return values of a function. It is used to
+//! describe the extrinsics that WASM programs are allowed to import, and is checked against the
+//! signatures found in the module when a process is spawned.
+
 use alloc::vec::Vec;
+use core::{convert::TryFrom, fmt};
 use smallvec::SmallVec;
 
+/// Signature of a function.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Signature {
+    /// Types of the parameters.
     params: SmallVec<[ValueType; 2]>,
-    ret_ty: Option<ValueType>,
+    /// Types of the return values. Contains more than one element if the function uses the
+    /// multi-value extension.
+    returns: SmallVec<[ValueType; 1]>,
 }
 
 /// Easy way to generate a [`Signature`](crate::signature::Signature).
@@ -41,59 +52,296 @@ macro_rules! sig {
         $(let params = params.chain(core::iter::once($crate::signature::ValueType::$p));)*
         $crate::signature::Signature::new(params, Some($crate::signature::ValueType::$ret))
     }};
+    (($($p:ident),*) -> ($($ret:ident),*)) => {{
+        let params = core::iter::empty();
+        $(let params = params.chain(core::iter::once($crate::signature::ValueType::$p));)*
+        let returns = core::iter::empty();
+        $(let returns = returns.chain(core::iter::once($crate::signature::ValueType::$ret));)*
+        $crate::signature::Signature::with_returns(params, returns)
+    }};
 }
 
+/// Type of a value passed as parameter or returned by a function.
 // TODO: what about U32/U64/etc.?
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ValueType {
+    /// 32-bits integer.
     I32,
+    /// 64-bits integer.
     I64,
+    /// 32-bits floating point.
     F32,
+    /// 64-bits floating point.
     F64,
+    /// Opaque reference to an object of the host.
+    ///
+    /// > **Note**: The interpreter doesn't support reference types yet. Modules can't declare
+    /// >           functions using this type.
+    ExternRef,
+    /// Reference to a function.
+    ///
+    /// > **Note**: The interpreter doesn't support reference types yet. Modules can't declare
+    /// >           functions using this type.
+    FuncRef,
+}
+
+/// Error when converting a [`Signature`] or a [`ValueType`] to the interpreter's types.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnsupportedByInterpreter {
+    /// The signature has more than one return value.
+    MultiValue,
+    /// The signature uses reference types.
+    ReferenceType,
 }
 
 impl Signature {
+    /// Builds a signature with zero or one return value.
     pub fn new(
         params: impl Iterator<Item = ValueType>,
         ret_ty: impl Into<Option<ValueType>>,
     ) -> Signature {
         Signature {
             params: params.collect(),
-            ret_ty: ret_ty.into(),
+            returns: ret_ty.into().into_iter().collect(),
+        }
+    }
+
+    /// Builds a signature with any number of return values.
+    pub fn with_returns(
+        params: impl Iterator<Item = ValueType>,
+        returns: impl Iterator<Item = ValueType>,
+    ) -> Signature {
+        Signature {
+            params: params.collect(),
+            returns: returns.collect(),
         }
     }
 
+    /// Returns the types of the parameters.
+    pub fn parameters(&self) -> impl ExactSizeIterator<Item = &ValueType> {
+        self.params.iter()
+    }
+
+    /// Returns the types of the return values.
+    pub fn return_types(&self) -> impl ExactSizeIterator<Item = &ValueType> {
+        self.returns.iter()
+    }
+
+    /// Returns the type of the return value, if the function returns exactly one value.
+    pub fn return_type(&self) -> Option<&ValueType> {
+        if self.returns.len() == 1 {
+            self.returns.get(0)
+        } else {
+            None
+        }
+    }
+
+    /// Builds a [`Signature`] from the function type used by the interpreter.
+    pub fn from_wasm(sig: &wasmi::Signature) -> Signature {
+        Signature::new(
+            sig.params().iter().cloned().map(ValueType::from),
+            sig.return_type().map(ValueType::from),
+        )
+    }
+
+    /// Returns true if a function with this signature can be used where a function with the
+    /// `expected` signature is expected.
+    ///
+    /// The parameters must be identical, while each return value must be a subtype of the
+    /// corresponding expected return value.
+    pub fn is_compatible_with(&self, expected: &Signature) -> bool {
+        self.params == expected.params
+            && self.returns.len() == expected.returns.len()
+            && self
+                .returns
+                .iter()
+                .zip(expected.returns.iter())
+                .all(|(obtained, expected)| obtained.is_subtype_of(expected))
+    }
+
     pub(crate) fn matches_wasmi(&self, sig: &wasmi::Signature) -> bool {
-        wasmi::Signature::from(self) == *sig
+        Signature::from_wasm(sig).is_compatible_with(self)
     }
 }
 
-impl<'a> From<&'a Signature> for wasmi::Signature {
-    fn from(sig: &'a Signature) -> wasmi::Signature {
-        wasmi::Signature::new(
-            sig.params
-                .iter()
-                .cloned()
-                .map(wasmi::ValueType::from)
-                .collect::<Vec<_>>(),
-            sig.ret_ty.map(wasmi::ValueType::from),
-        )
+impl ValueType {
+    /// Returns true if a value of this type can be used where a value of type `other` is
+    /// expected.
+    pub fn is_subtype_of(&self, other: &ValueType) -> bool {
+        // Numeric types don't have any subtyping relationship, and `funcref` isn't a subtype of
+        // `externref`.
+        self == other
+    }
+}
+
+impl<'a> TryFrom<&'a Signature> for wasmi::Signature {
+    type Error = UnsupportedByInterpreter;
+
+    fn try_from(sig: &'a Signature) -> Result<wasmi::Signature, Self::Error> {
+        if sig.returns.len() > 1 {
+            return Err(UnsupportedByInterpreter::MultiValue);
+        }
+
+        let params = sig
+            .params
+            .iter()
+            .cloned()
+            .map(wasmi::ValueType::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let ret_ty = match sig.returns.get(0) {
+            Some(ty) => Some(wasmi::ValueType::try_from(*ty)?),
+            None => None,
+        };
+
+        Ok(wasmi::Signature::new(params, ret_ty))
     }
 }
 
-impl From<Signature> for wasmi::Signature {
-    fn from(sig: Signature) -> wasmi::Signature {
-        wasmi::Signature::from(&sig)
+impl TryFrom<Signature> for wasmi::Signature {
+    type Error = UnsupportedByInterpreter;
+
+    fn try_from(sig: Signature) -> Result<wasmi::Signature, Self::Error> {
+        wasmi::Signature::try_from(&sig)
+    }
+}
+
+impl<'a> From<&'a wasmi::Signature> for Signature {
+    fn from(sig: &'a wasmi::Signature) -> Signature {
+        Signature::from_wasm(sig)
+    }
+}
+
+impl From<wasmi::Signature> for Signature {
+    fn from(sig: wasmi::Signature) -> Signature {
+        Signature::from(&sig)
+    }
+}
+
+/// Formats the signature as `(i32, i64) -> i32`.
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (n, param) in self.params.iter().enumerate() {
+            if n != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")?;
+
+        match self.returns.len() {
+            0 => Ok(()),
+            1 => write!(f, " -> {}", self.returns[0]),
+            _ => {
+                write!(f, " -> (")?;
+                for (n, ret) in self.returns.iter().enumerate() {
+                    if n != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", ret)?;
+                }
+                write!(f, ")")
+            }
+        }
     }
 }
 
-impl From<ValueType> for wasmi::ValueType {
-    fn from(ty: ValueType) -> wasmi::ValueType {
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::I32 => write!(f, "i32"),
+            ValueType::I64 => write!(f, "i64"),
+            ValueType::F32 => write!(f, "f32"),
+            ValueType::F64 => write!(f, "f64"),
+            ValueType::ExternRef => write!(f, "externref"),
+            ValueType::FuncRef => write!(f, "funcref"),
+        }
+    }
+}
+
+impl TryFrom<ValueType> for wasmi::ValueType {
+    type Error = UnsupportedByInterpreter;
+
+    fn try_from(ty: ValueType) -> Result<wasmi::ValueType, Self::Error> {
         match ty {
-            ValueType::I32 => wasmi::ValueType::I32,
-            ValueType::I64 => wasmi::ValueType::I64,
-            ValueType::F32 => wasmi::ValueType::F32,
-            ValueType::F64 => wasmi::ValueType::F64,
+            ValueType::I32 => Ok(wasmi::ValueType::I32),
+            ValueType::I64 => Ok(wasmi::ValueType::I64),
+            ValueType::F32 => Ok(wasmi::ValueType::F32),
+            ValueType::F64 => Ok(wasmi::ValueType::F64),
+            ValueType::ExternRef | ValueType::FuncRef => {
+                Err(UnsupportedByInterpreter::ReferenceType)
+            }
         }
     }
 }
+
+impl From<wasmi::ValueType> for ValueType {
+    fn from(ty: wasmi::ValueType) -> ValueType {
+        match ty {
+            wasmi::ValueType::I32 => ValueType::I32,
+            wasmi::ValueType::I64 => ValueType::I64,
+            wasmi::ValueType::F32 => ValueType::F32,
+            wasmi::ValueType::F64 => ValueType::F64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Signature, UnsupportedByInterpreter, ValueType};
+    use alloc::{string::ToString as _, vec, vec::Vec};
+    use core::convert::TryFrom as _;
+
+    #[test]
+    fn wasmi_round_trip() {
+        let sig = crate::sig!((I32, I64, F32) -> F64);
+        let wasmi_sig = wasmi::Signature::try_from(&sig).unwrap();
+        assert!(sig.matches_wasmi(&wasmi_sig));
+        assert_eq!(Signature::from(&wasmi_sig), sig);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(crate::sig!(()).to_string(), "()");
+        assert_eq!(
+            crate::sig!((I32, I64) -> I32).to_string(),
+            "(i32, i64) -> i32"
+        );
+        assert_eq!(
+            crate::sig!((F32) -> (F64, I32)).to_string(),
+            "(f32) -> (f64, i32)"
+        );
+    }
+
+    #[test]
+    fn compatibility() {
+        let sig = crate::sig!((I32, I64) -> I32);
+        assert!(sig.is_compatible_with(&crate::sig!((I32, I64) -> I32)));
+        assert!(!sig.is_compatible_with(&crate::sig!((I32, I64) -> I64)));
+        assert!(!sig.is_compatible_with(&crate::sig!((I32) -> I32)));
+        assert!(!sig.is_compatible_with(&crate::sig!((I32, I64))));
+    }
+
+    #[test]
+    fn reference_types() {
+        let sig = crate::sig!((ExternRef, I32) -> FuncRef);
+        assert_eq!(sig.to_string(), "(externref, i32) -> funcref");
+        assert!(!sig.is_compatible_with(&crate::sig!((FuncRef, I32) -> FuncRef)));
+        assert_eq!(
+            wasmi::Signature::try_from(&sig).unwrap_err(),
+            UnsupportedByInterpreter::ReferenceType
+        );
+    }
+
+    #[test]
+    fn multi_value() {
+        let sig = crate::sig!((I32) -> (I32, I64));
+        assert_eq!(sig.return_types().len(), 2);
+        assert!(sig.return_type().is_none());
+        assert!(wasmi::Signature::try_from(&sig).is_err());
+        assert_eq!(
+            sig.return_types().cloned().collect::<Vec<_>>(),
+            vec![ValueType::I32, ValueType::I64]
+        );
+    }
+}
@@ -13,20 +13,35 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::module::Module;
+use crate::module::{Module, ModuleHash, ModuleLimits};
 use crate::native::{self, NativeProgramMessageIdWrite as _};
-use crate::scheduler::{Core, CoreBuilder, CoreRunOutcome};
-use alloc::{vec, vec::Vec};
-use core::task::Poll;
+use crate::scheduler::{
+    Core, CoreBuilder, CoreRunOutcome, ExecError, HostFunction, HostFunctionContext, Middleware,
+    NewErr, OrphanPolicy, SetInterfaceHandlerError,
+};
+use crate::sig;
+use crate::signature::Signature;
+use alloc::{
+    borrow::Cow, boxed::Box, collections::VecDeque, format, string::String, sync::Arc, vec,
+    vec::Vec,
+};
+use core::{fmt, mem, pin::Pin, task::Poll};
 use futures::prelude::*;
-use hashbrown::{hash_map::Entry, HashMap, HashSet};
-use redshirt_syscalls_interface::{Decode, Encode, EncodedMessage, MessageId, Pid};
+use hashbrown::{hash_map::Entry, HashMap};
+use redshirt_syscalls_interface::{
+    Decode, Encode, EncodedMessage, ErrorClass, ErrorPayload, InterfaceHash, MessageId, Pid,
+};
 use smallvec::SmallVec;
 
+/// Maximum number of entries in [`System::kernel_log`].
+const MAX_KERNEL_LOG_ENTRIES: usize = 1024;
+
 /// Main struct that handles a system, including the scheduler, program loader,
 /// inter-process communication, and so on.
 ///
-/// Natively handles the "interface" and "threads" interfaces.  TODO: indicate hashes
+/// Natively handles the "interface", "threads", "metrics", "power", "process", "arguments" and
+/// "diagnostics" interfaces.
+/// TODO: indicate hashes
 pub struct System {
     /// Inner system with inter-process communications.
     core: Core,
@@ -54,13 +69,134 @@ pub struct System {
     ///
     /// Because this list is only filled at initialization, emptied at once, and then never filled
     /// again, the most straight-forward container is a `Vec`.
+    ///
+    /// The `bool` indicates whether the program is required. See [`StartupProgram::optional`].
     // TODO: add timeout for loader interface availability
-    main_programs: Vec<[u8; 32]>,
+    main_programs: Vec<(ModuleHash, bool)>,
 
-    /// Set of messages that we emitted of requests to load a program from the loader interface.
+    /// List of requests to load a program that we emitted on the loader interface, in the order
+    /// in which the programs must be started.
     /// All these messages expect a `redshirt_loader_interface::ffi::LoadResponse` as answer.
-    // TODO: call shink_to_fit from time to time
-    loading_programs: HashSet<MessageId>,
+    ///
+    /// Responses can arrive in any order. A program is only started once all the programs before
+    /// it in the list have been started.
+    loading_programs: VecDeque<LoadingProgram>,
+
+    /// List of requests to load a program that we emitted on the loader interface on behalf of
+    /// a process that has asked to spawn a program through the `process` interface, and the
+    /// message emitted on the loader interface.
+    spawning_programs: Vec<(MessageId, SpawningProgram)>,
+
+    /// Modules of the programs fetched through the loader that have at least one process alive.
+    ///
+    /// Starting a program whose module is in this list reuses the already parsed and validated
+    /// module instead of asking the loader for it again. See also [`System::process_modules`].
+    loaded_modules: HashMap<ModuleHash, LoadedModule>,
+
+    /// For each process started from an entry of [`System::loaded_modules`], the hash of its
+    /// module.
+    process_modules: HashMap<Pid, ModuleHash>,
+
+    /// "Virtual" `Pid` handling the `process` interface. Used as the emitter of the messages
+    /// sent to the loader in order to spawn programs.
+    process_interface_pid: Pid,
+
+    /// Arguments and environment variables of the processes that have been spawned through the
+    /// `process` interface, returned through the `arguments` interface.
+    process_arguments: HashMap<Pid, redshirt_arguments_interface::ffi::Arguments>,
+
+    /// Most recent entries of the kernel log, returned through the `diagnostics` interface.
+    /// Once the log contains [`MAX_KERNEL_LOG_ENTRIES`] entries, the oldest entry is discarded
+    /// whenever a new one is added.
+    kernel_log: VecDeque<redshirt_diagnostics_interface::ffi::Entry>,
+
+    /// Sequence number of the next entry of [`System::kernel_log`].
+    next_log_sequence: u64,
+
+    /// List of messages emitted on the `diagnostics` interface asking to follow the kernel log.
+    /// Contains the emitter of the message and the filter that new entries must match.
+    log_followers: Vec<(MessageId, Pid, redshirt_diagnostics_interface::ffi::Filter)>,
+
+    /// Function called when no program is ready to run and no event is pending.
+    /// See [`SystemBuilder::with_idle_hook`].
+    idle_hook: Option<Box<dyn FnMut() + Send>>,
+
+    /// Function returning the current value of the monotonic clock, in nanoseconds.
+    /// See [`SystemBuilder::with_monotonic_clock`].
+    monotonic_clock: Option<Arc<dyn Fn() -> u64 + Send + Sync>>,
+
+    /// Function returning a future that resolves once the monotonic clock reaches the given
+    /// value. See [`SystemBuilder::with_timer`].
+    timer: Option<Box<dyn Fn(u64) -> Timer + Send>>,
+
+    /// Timer created with [`System::timer`], and the value of the monotonic clock it resolves
+    /// at. Corresponds to the [`Core::next_wake_up`] of the last time we had nothing to do.
+    pending_timer: Option<(u64, Timer)>,
+
+    /// Limits enforced on the modules of the programs loaded through the loader.
+    /// See [`SystemBuilder::with_module_limits`].
+    module_limits: ModuleLimits,
+
+    /// For each interface registered through the `interface` interface, the list of versions of
+    /// the messages schema that its handler accepts. Empty if the handler doesn't use versioning.
+    interface_versions: HashMap<InterfaceHash, Vec<u32>>,
+
+    /// List of messages emitted on the `interface` interface asking to be notified when the
+    /// availability of an interface changes. Contains the interface and whether the emitter
+    /// believes that it is available.
+    availability_watchers: Vec<(MessageId, InterfaceHash, bool)>,
+
+    /// Processes started in [`SystemBuilder::build`] from the startup programs that were already
+    /// available. See [`System::startup_processes`].
+    startup_processes: Vec<Pid>,
+}
+
+/// Entry in [`System::loading_programs`].
+struct LoadingProgram {
+    /// Message emitted on the loader interface.
+    message_id: MessageId,
+    /// Hash of the module being loaded.
+    hash: ModuleHash,
+    /// If true, failing to load or start the program is fatal.
+    required: bool,
+    /// Content of the response, if it has been received.
+    response: Option<Result<Vec<u8>, ()>>,
+}
+
+/// Program requested through the `process` interface.
+struct SpawningProgram {
+    /// Hash of the module to start.
+    hash: ModuleHash,
+    /// Message emitted on the `process` interface, to answer once the program has started.
+    spawn_message_id: Option<MessageId>,
+    /// Process that has asked to spawn the program. Becomes the parent of the new process.
+    parent: Pid,
+    /// Arguments and environment variables to pass to the new process.
+    arguments: redshirt_arguments_interface::ffi::Arguments,
+}
+
+/// Entry in [`System::loaded_modules`].
+struct LoadedModule {
+    module: Module,
+    /// Number of processes started from this module that are still alive.
+    num_processes: usize,
+}
+
+/// Program to start when the [`System`] boots. Passed to
+/// [`SystemBuilder::with_startup_program`].
+pub struct StartupProgram {
+    /// Where to get the program from.
+    source: StartupProgramSource,
+    /// If true, failing to load or start the program is fatal.
+    required: bool,
+}
+
+/// Where to get a [`StartupProgram`] from.
+enum StartupProgramSource {
+    /// Program is already available.
+    Module(Module),
+    /// Program must be fetched through the `loader` interface.
+    Hash(ModuleHash),
 }
 
 /// Prototype for a [`System`].
@@ -77,13 +213,45 @@ pub struct SystemBuilder {
     /// "Virtual" Pid for handling messages on the `threads` interface.
     threads_interface_pid: Pid,
 
+    /// "Virtual" Pid for handling messages on the `metrics` interface.
+    metrics_interface_pid: Pid,
+
+    /// "Virtual" Pid for handling messages on the `power` interface.
+    power_interface_pid: Pid,
+
+    /// "Virtual" Pid for handling messages on the `process` interface.
+    process_interface_pid: Pid,
+
+    /// "Virtual" Pid for handling messages on the `arguments` interface.
+    arguments_interface_pid: Pid,
+
+    /// "Virtual" Pid for handling messages on the `diagnostics` interface.
+    diagnostics_interface_pid: Pid,
+
     /// List of programs to start executing immediately after construction.
-    startup_processes: Vec<Module>,
+    ///
+    /// The `bool` indicates whether the program is required. See [`StartupProgram::optional`].
+    startup_processes: Vec<(Module, bool)>,
 
     /// Same field as [`System::main_programs`].
-    main_programs: Vec<[u8; 32]>,
+    main_programs: Vec<(ModuleHash, bool)>,
+
+    /// Same field as [`System::idle_hook`].
+    idle_hook: Option<Box<dyn FnMut() + Send>>,
+
+    /// Same field as [`System::monotonic_clock`].
+    monotonic_clock: Option<Arc<dyn Fn() -> u64 + Send + Sync>>,
+
+    /// Same field as [`System::timer`].
+    timer: Option<Box<dyn Fn(u64) -> Timer + Send>>,
+
+    /// Same field as [`System::module_limits`].
+    module_limits: ModuleLimits,
 }
 
+/// Future returned by the function passed to [`SystemBuilder::with_timer`].
+pub type Timer = Pin<Box<dyn Future<Output = ()> + Send>>;
+
 /// Outcome of running the [`System`] once.
 #[derive(Debug)]
 pub enum SystemRunOutcome {
@@ -93,11 +261,51 @@ pub enum SystemRunOutcome {
         pid: Pid,
         /// Either `Ok(())` if the main thread has ended, or the error that happened in the
         /// process.
-        // TODO: change error type
-        outcome: Result<(), wasmi::Error>,
+        ///
+        /// A process that has exhausted its stack, for example because of a runaway recursion,
+        /// ends with [`ExecError::StackOverflow`].
+        outcome: Result<(), ExecError>,
+    },
+
+    /// A program has asked to power off or restart the machine through the `power` interface.
+    ///
+    /// The embedder is expected to call [`System::shutdown`], then perform the action in a
+    /// platform-specific way.
+    PowerRequested {
+        /// Identifier of the process that has emitted the request.
+        pid: Pid,
+        /// What to do once the [`System`] has been shut down.
+        action: PowerAction,
     },
 }
 
+/// Error that can happen when calling [`System::execute_child`].
+#[derive(Debug)]
+pub enum ExecuteChildError {
+    /// The parent isn't a running process.
+    ParentNotFound,
+    /// Failed to start the process.
+    Start(NewErr),
+}
+
+impl fmt::Display for ExecuteChildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteChildError::ParentNotFound => write!(f, "Parent process not found"),
+            ExecuteChildError::Start(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Action requested through the `power` interface. See [`SystemRunOutcome::PowerRequested`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerAction {
+    /// Power off the machine.
+    Shutdown,
+    /// Restart the machine.
+    Reboot,
+}
+
 impl System {
     /// Start executing a program.
     pub fn execute(&mut self, program: &Module) -> Pid {
@@ -107,6 +315,193 @@ impl System {
             .pid() // TODO: don't unwrap
     }
 
+    /// Start executing a program as a child of another process.
+    ///
+    /// When `parent` terminates, the new process is either killed or attached to the parent of
+    /// `parent`, depending on `on_parent_exit`. This prevents a crashed supervisor from leaving
+    /// behind orphaned workers that still hold interfaces.
+    pub fn execute_child(
+        &mut self,
+        program: &Module,
+        parent: Pid,
+        on_parent_exit: OrphanPolicy,
+    ) -> Result<Pid, ExecuteChildError> {
+        if self.core.process_by_id(parent).is_none() {
+            return Err(ExecuteChildError::ParentNotFound);
+        }
+
+        self.core
+            .execute_child(program, parent, on_parent_exit)
+            .map(|p| p.pid())
+            .map_err(ExecuteChildError::Start)
+    }
+
+    /// Returns the processes that were started when the [`System`] was built, in the order in
+    /// which their programs were passed to the [`SystemBuilder`].
+    ///
+    /// Programs that must be fetched through the `loader` interface aren't included.
+    pub fn startup_processes(&self) -> &[Pid] {
+        &self.startup_processes
+    }
+
+    /// Returns the process that has started the given process, if any.
+    pub fn parent_of(&mut self, pid: Pid) -> Option<Pid> {
+        self.core.parent_of(pid)
+    }
+
+    /// Returns the list of processes that the given process has started and that are still
+    /// alive.
+    pub fn children_of(&mut self, pid: Pid) -> Vec<Pid> {
+        self.core.children_of(pid)
+    }
+
+    /// Returns information about all the processes currently running.
+    ///
+    /// The same information is available to programs through the `process` interface.
+    pub fn processes(&mut self) -> Vec<redshirt_process_interface::ffi::ProcessInfo> {
+        self.core
+            .processes()
+            .into_iter()
+            .map(|p| redshirt_process_interface::ffi::ProcessInfo {
+                pid: p.pid,
+                parent: p.parent,
+                name: p.name,
+                module_hash: p.module_hash,
+                memory_bytes: p.memory_bytes,
+                // TODO: measure the time spent executing each process
+                cpu_time_ns: None,
+            })
+            .collect()
+    }
+
+    /// Returns a snapshot of the counters of the [`System`].
+    ///
+    /// The same information is available to programs through the `metrics` interface.
+    pub fn metrics(&mut self) -> redshirt_metrics_interface::ffi::SystemMetrics {
+        redshirt_metrics_interface::ffi::SystemMetrics {
+            processes: self.core.processes_count() as u64,
+            messages_routed: self.core.messages_routed(),
+            queued_messages: self.core.queued_messages(),
+            wasm_memory_bytes: self.core.processes_memory_usage(),
+        }
+    }
+
+    /// Returns, for each interface on which at least one message has been emitted, the number of
+    /// messages and bytes emitted on it.
+    ///
+    /// The same information is available to programs through the `metrics` interface.
+    pub fn interface_metrics(&self) -> Vec<redshirt_metrics_interface::ffi::InterfaceMetrics> {
+        self.core
+            .interface_statistics()
+            .map(
+                |(interface, stats)| redshirt_metrics_interface::ffi::InterfaceMetrics {
+                    interface: interface.clone(),
+                    messages: stats.messages,
+                    bytes: stats.bytes,
+                },
+            )
+            .collect()
+    }
+
+    /// Returns the entries of the kernel log that match the filter.
+    ///
+    /// The same information is available to programs through the `diagnostics` interface.
+    pub fn kernel_log(
+        &self,
+        filter: &redshirt_diagnostics_interface::ffi::Filter,
+    ) -> redshirt_diagnostics_interface::ffi::QueryResponse {
+        redshirt_diagnostics_interface::ffi::QueryResponse {
+            oldest: self
+                .kernel_log
+                .front()
+                .map_or(self.next_log_sequence, |e| e.sequence),
+            entries: self
+                .kernel_log
+                .iter()
+                .filter(|e| filter.matches(e))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Adds a message to the kernel log. Meant to be used by the embedder in order to report
+    /// what happens while the machine boots.
+    pub fn log_boot_message(&mut self, message: impl Into<String>) {
+        self.log(
+            redshirt_diagnostics_interface::ffi::EntryKind::Boot,
+            None,
+            message.into(),
+        );
+    }
+
+    /// Changes the handler of an interface while the [`System`] is running, for example in order
+    /// to upgrade a driver without rebooting.
+    ///
+    /// `new_handler` can be either a process or a native program. The messages that the previous
+    /// handler hasn't retrieved yet are transferred to the new handler.
+    ///
+    /// Returns an error if the interface isn't registered, or if `new_handler` doesn't exist.
+    pub fn reroute_interface(
+        &mut self,
+        interface: InterfaceHash,
+        new_handler: Pid,
+    ) -> Result<(), ()> {
+        self.core.reroute_interface(interface, new_handler)
+    }
+
+    /// Shuts down the [`System`].
+    ///
+    /// A shutdown notification is first sent to all the processes that handle an interface.
+    /// The [`System`] then continues running until either all the processes have stopped or
+    /// `grace_period` resolves, leaving processes some time to answer their pending messages and
+    /// flush their state. The processes that are still alive at the end of the grace period are
+    /// killed. Afterwards, the native programs are notified in the reverse order of their
+    /// registration.
+    ///
+    /// Returns the outcomes that happened during the shutdown, in order. This includes a
+    /// [`SystemRunOutcome::ProgramFinished`] for each process, including the ones that have been
+    /// killed.
+    pub fn shutdown<'b>(
+        &'b mut self,
+        grace_period: impl Future<Output = ()> + 'b,
+    ) -> impl Future<Output = Vec<SystemRunOutcome>> + 'b {
+        self.core.broadcast_shutdown();
+        let mut grace_period = Box::pin(grace_period);
+        let mut outcomes = Vec::new();
+
+        // TODO: We use a `poll_fn` because async/await don't work in no_std yet.
+        future::poll_fn(move |cx| {
+            loop {
+                if self.core.processes_count() == 0 {
+                    break;
+                }
+                if let Poll::Ready(()) = grace_period.as_mut().poll(cx) {
+                    break;
+                }
+
+                let run = self.run();
+                futures::pin_mut!(run);
+                match run.poll(cx) {
+                    Poll::Ready(outcome) => outcomes.push(outcome),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            for process in self.core.processes() {
+                // The process might already have been killed alongside its parent.
+                let _ = self.core.kill(process.pid);
+            }
+
+            // Report the processes that have been killed above.
+            while let Some(outcome) = self.run_once() {
+                outcomes.push(outcome);
+            }
+
+            self.native_programs.shutdown();
+            Poll::Ready(mem::replace(&mut outcomes, Vec::new()))
+        })
+    }
+
     /// Runs the [`System`] once and returns the outcome.
     ///
     /// > **Note**: For now, can block a long time because it's waiting for the native programs
@@ -116,6 +511,15 @@ impl System {
     pub fn run<'b>(&'b mut self) -> impl Future<Output = SystemRunOutcome> + 'b {
         // TODO: We use a `poll_fn` because async/await don't work in no_std yet.
         future::poll_fn(move |cx| loop {
+            // Without a clock, threads that call `sleep_until` are woken up immediately and
+            // message deadlines expire immediately.
+            let now = self
+                .monotonic_clock
+                .as_ref()
+                .map_or(u64::max_value(), |clock| clock());
+            self.core.wake_up_sleeping_threads(now);
+            self.core.expire_message_deadlines(now);
+
             if let Some(out) = self.run_once() {
                 return Poll::Ready(out);
             }
@@ -124,7 +528,35 @@ impl System {
             futures::pin_mut!(next_event);
             let event = match next_event.poll(cx) {
                 Poll::Ready(ev) => ev,
-                Poll::Pending => return Poll::Pending,
+                Poll::Pending => {
+                    if let Some(wake_up) = self.core.next_wake_up() {
+                        if let Some(timer) = self.timer.as_ref() {
+                            if self
+                                .pending_timer
+                                .as_ref()
+                                .map_or(true, |(until, _)| *until != wake_up)
+                            {
+                                self.pending_timer = Some((wake_up, timer(wake_up)));
+                            }
+                            let (_, pending_timer) = self.pending_timer.as_mut().unwrap();
+                            if let Poll::Ready(()) = pending_timer.as_mut().poll(cx) {
+                                self.pending_timer = None;
+                                continue;
+                            }
+                        } else {
+                            // Without a timer, the only way to notice that the wake-up time
+                            // has been reached is to poll again.
+                            cx.waker().wake_by_ref();
+                        }
+                    } else {
+                        self.pending_timer = None;
+                    }
+
+                    if let Some(idle_hook) = self.idle_hook.as_mut() {
+                        idle_hook();
+                    }
+                    return Poll::Pending;
+                }
             };
 
             match event {
@@ -146,24 +578,235 @@ impl System {
                             .emit_interface_message_no_answer(emitter_pid, interface, message);
                     }
                 }
-                native::NativeProgramsCollectionEvent::CancelMessage { .. } => unimplemented!(),
+                native::NativeProgramsCollectionEvent::CancelMessage {
+                    emitter_pid,
+                    message_id,
+                } => {
+                    self.core.cancel_message(emitter_pid, message_id);
+                }
                 native::NativeProgramsCollectionEvent::Answer { message_id, answer } => {
                     self.core.answer_message(message_id, answer);
                 }
+                native::NativeProgramsCollectionEvent::PartialAnswer { message_id, answer } => {
+                    self.core.answer_message_partial(message_id, answer);
+                }
             }
         })
     }
 
+    /// Adds an entry to [`System::kernel_log`] and notifies the followers whose filter matches.
+    fn log(
+        &mut self,
+        kind: redshirt_diagnostics_interface::ffi::EntryKind,
+        pid: Option<Pid>,
+        message: String,
+    ) {
+        let entry = redshirt_diagnostics_interface::ffi::Entry {
+            sequence: self.next_log_sequence,
+            timestamp_ns: self.monotonic_clock.as_ref().map(|clock| clock()),
+            kind,
+            pid,
+            message,
+        };
+        self.next_log_sequence += 1;
+
+        for (message_id, _, filter) in &self.log_followers {
+            if filter.matches(&entry) {
+                self.core
+                    .answer_message_partial(*message_id, entry.encode());
+            }
+        }
+
+        if self.kernel_log.len() >= MAX_KERNEL_LOG_ENTRIES {
+            self.kernel_log.pop_front();
+        }
+        self.kernel_log.push_back(entry);
+    }
+
+    /// Answers the entries of [`System::availability_watchers`] concerning the given interface,
+    /// now that it has become available or unavailable.
+    fn notify_availability_watchers(&mut self, interface: &InterfaceHash, available: bool) {
+        let core = &mut self.core;
+        self.availability_watchers
+            .retain(|(message_id, watched, believed_available)| {
+                if watched != interface || *believed_available == available {
+                    return true;
+                }
+
+                let response =
+                    redshirt_interface_interface::ffi::InterfaceAvailabilityResponse { available };
+                core.answer_message(*message_id, Ok(response.encode()));
+                false
+            });
+    }
+
+    /// Starts the programs at the front of [`System::loading_programs`] whose response has been
+    /// received.
+    fn start_loaded_programs(&mut self) {
+        while self
+            .loading_programs
+            .front()
+            .map_or(false, |p| p.response.is_some())
+        {
+            let loading = self.loading_programs.pop_front().unwrap();
+            let bytes = loading.response.unwrap().ok();
+            let result = self.execute_by_hash(&loading.hash, bytes.as_ref().map(|b| &b[..]), None);
+            match result {
+                Ok(pid) => self.log(
+                    redshirt_diagnostics_interface::ffi::EntryKind::ProcessStarted,
+                    Some(pid),
+                    format!("Started {}", loading.hash),
+                ),
+                Err(()) if loading.required => {
+                    panic!("Failed to load or start a required program")
+                }
+                Err(()) => self.log(
+                    redshirt_diagnostics_interface::ffi::EntryKind::Boot,
+                    None,
+                    String::from("Failed to load or start a startup program"),
+                ),
+            }
+        }
+    }
+
+    /// Starts a program requested through the `process` interface and answers the spawn
+    /// request.
+    ///
+    /// `bytes` is the module returned by the loader, or `None` if the loader has failed or if
+    /// the module is in [`System::loaded_modules`] and the loader hasn't been asked.
+    fn start_spawned_program(&mut self, spawning: SpawningProgram, bytes: Option<Vec<u8>>) {
+        // The parent might have terminated in the meanwhile, in which case nobody expects an
+        // answer.
+        if self.core.process_by_id(spawning.parent).is_none() {
+            return;
+        }
+
+        let result = self.execute_by_hash(
+            &spawning.hash,
+            bytes.as_ref().map(|b| &b[..]),
+            Some(spawning.parent),
+        );
+
+        if let Ok(pid) = result {
+            self.process_arguments.insert(pid, spawning.arguments);
+            self.log(
+                redshirt_diagnostics_interface::ffi::EntryKind::ProcessStarted,
+                Some(pid),
+                format!("Spawned by {:?}", spawning.parent),
+            );
+        }
+
+        if let Some(message_id) = spawning.spawn_message_id {
+            let response = redshirt_process_interface::ffi::SpawnResponse { result };
+            self.core.answer_message(message_id, Ok(response.encode()));
+        }
+    }
+
+    /// Starts a process from the module with the given hash, optionally as a child of `parent`.
+    ///
+    /// The module is taken from [`System::loaded_modules`] if it is there. Otherwise, it is
+    /// parsed from `bytes` and added to [`System::loaded_modules`].
+    fn execute_by_hash(
+        &mut self,
+        hash: &ModuleHash,
+        bytes: Option<&[u8]>,
+        parent: Option<Pid>,
+    ) -> Result<Pid, ErrorPayload> {
+        if !self.loaded_modules.contains_key(hash) {
+            let bytes = bytes.ok_or_else(|| {
+                ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("failed to load the module")
+            })?;
+            let module =
+                Module::from_bytes_with_limits(bytes, &self.module_limits).map_err(|err| {
+                    ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message(format!("{}", err))
+                })?;
+            let module = LoadedModule {
+                module,
+                num_processes: 0,
+            };
+            self.loaded_modules.insert(hash.clone(), module);
+        }
+
+        let loaded = self.loaded_modules.get_mut(hash).unwrap();
+        let result = match parent {
+            Some(parent) => self
+                .core
+                .execute_child(&loaded.module, parent, OrphanPolicy::Reparent),
+            None => self.core.execute(&loaded.module),
+        };
+
+        match result {
+            Ok(process) => {
+                let pid = process.pid();
+                loaded.num_processes += 1;
+                self.process_modules.insert(pid, hash.clone());
+                Ok(pid)
+            }
+            Err(err) => {
+                if loaded.num_processes == 0 {
+                    self.loaded_modules.remove(hash);
+                }
+                Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message(format!("{}", err)))
+            }
+        }
+    }
+
     fn run_once(&mut self) -> Option<SystemRunOutcome> {
         // TODO: remove loop?
         loop {
             match self.core.run() {
-                CoreRunOutcome::ProgramFinished { pid, outcome, .. } => {
+                CoreRunOutcome::ProgramFinished {
+                    pid,
+                    outcome,
+                    unregistered_interfaces,
+                    unhandled_messages,
+                    ..
+                } => {
+                    let outcome = outcome.map(|_| ());
+                    match &outcome {
+                        Ok(()) => self.log(
+                            redshirt_diagnostics_interface::ffi::EntryKind::ProcessExited,
+                            Some(pid),
+                            String::from("Exited"),
+                        ),
+                        Err(err) => self.log(
+                            redshirt_diagnostics_interface::ffi::EntryKind::ProcessCrashed,
+                            Some(pid),
+                            format!("Crashed: {}", err),
+                        ),
+                    }
+                    if !unhandled_messages.is_empty() {
+                        self.log(
+                            redshirt_diagnostics_interface::ffi::EntryKind::MessagesDropped,
+                            Some(pid),
+                            format!(
+                                "{} message(s) left unanswered by the stopped process",
+                                unhandled_messages.len()
+                            ),
+                        );
+                    }
+
+                    for interface in unregistered_interfaces {
+                        self.log(
+                            redshirt_diagnostics_interface::ffi::EntryKind::InterfaceUnregistered,
+                            Some(pid),
+                            format!("Unregistered {:?}", interface),
+                        );
+                        self.interface_versions.remove(&interface);
+                        self.notify_availability_watchers(&interface, false);
+                    }
+                    self.process_arguments.remove(&pid);
+                    if let Some(hash) = self.process_modules.remove(&pid) {
+                        if let Entry::Occupied(mut entry) = self.loaded_modules.entry(hash) {
+                            entry.get_mut().num_processes -= 1;
+                            if entry.get().num_processes == 0 {
+                                entry.remove();
+                            }
+                        }
+                    }
+                    self.log_followers.retain(|(_, emitter, _)| *emitter != pid);
                     self.native_programs.process_destroyed(pid);
-                    return Some(SystemRunOutcome::ProgramFinished {
-                        pid,
-                        outcome: outcome.map(|_| ()).map_err(|err| err.into()),
-                    });
+                    return Some(SystemRunOutcome::ProgramFinished { pid, outcome });
                 }
                 CoreRunOutcome::ThreadWaitUnavailableInterface { .. } => {} // TODO: lazy-loading
 
@@ -172,19 +815,64 @@ impl System {
                     response,
                     ..
                 } => {
-                    if self.loading_programs.remove(&message_id) {
-                        let redshirt_loader_interface::ffi::LoadResponse { result } =
-                            Decode::decode(response.unwrap()).unwrap();
-                        let module = Module::from_bytes(&result.unwrap()).unwrap();
-                        match self.core.execute(&module) {
-                            Ok(_) => {}
-                            Err(_) => panic!(),
-                        }
+                    if let Some(loading) = self
+                        .loading_programs
+                        .iter_mut()
+                        .find(|p| p.message_id == message_id)
+                    {
+                        let result = response
+                            .ok()
+                            .and_then(|r| Decode::decode(r).ok())
+                            .map(|r: redshirt_loader_interface::ffi::LoadResponse| r.result)
+                            .unwrap_or(Err(()));
+                        loading.response = Some(result);
+                        self.start_loaded_programs();
+                    } else if let Some(pos) = self
+                        .spawning_programs
+                        .iter()
+                        .position(|(id, _)| *id == message_id)
+                    {
+                        let (_, spawning) = self.spawning_programs.remove(pos);
+                        let bytes = response.ok().and_then(|r| Decode::decode(r).ok()).and_then(
+                            |r: redshirt_loader_interface::ffi::LoadResponse| r.result.ok(),
+                        );
+                        self.start_spawned_program(spawning, bytes);
                     } else {
                         self.native_programs.message_response(message_id, response);
                     }
                 }
 
+                CoreRunOutcome::MessagePartialResponse {
+                    message_id,
+                    response,
+                } => {
+                    self.native_programs
+                        .message_partial_response(message_id, response);
+                }
+
+                CoreRunOutcome::ReservedPidMessageCancelled {
+                    message_id,
+                    interface,
+                } if interface == redshirt_interface_interface::ffi::INTERFACE => {
+                    self.availability_watchers
+                        .retain(|(id, _, _)| *id != message_id);
+                }
+
+                CoreRunOutcome::ReservedPidMessageCancelled {
+                    message_id,
+                    interface,
+                } if interface == redshirt_diagnostics_interface::ffi::INTERFACE => {
+                    self.log_followers.retain(|(id, _, _)| *id != message_id);
+                }
+
+                CoreRunOutcome::ReservedPidMessageCancelled {
+                    message_id,
+                    interface,
+                } => {
+                    self.native_programs
+                        .message_cancelled(interface, message_id);
+                }
+
                 CoreRunOutcome::ReservedPidInterfaceMessage {
                     pid,
                     message_id,
@@ -239,6 +927,211 @@ impl System {
                     }
                 }
 
+                CoreRunOutcome::ReservedPidInterfaceMessage {
+                    message_id,
+                    interface,
+                    message,
+                    ..
+                } if interface == redshirt_metrics_interface::ffi::INTERFACE => {
+                    match redshirt_metrics_interface::ffi::MetricsMessage::decode(message) {
+                        Ok(redshirt_metrics_interface::ffi::MetricsMessage::GetMetrics) => {
+                            if let Some(message_id) = message_id {
+                                let metrics = self.metrics();
+                                self.core.answer_message(message_id, Ok(metrics.encode()));
+                            }
+                        }
+                        Ok(
+                            redshirt_metrics_interface::ffi::MetricsMessage::GetInterfaceMetrics,
+                        ) => {
+                            if let Some(message_id) = message_id {
+                                let metrics = self.interface_metrics();
+                                self.core.answer_message(message_id, Ok(metrics.encode()));
+                            }
+                        }
+                        Err(_) => {
+                            if let Some(message_id) = message_id {
+                                self.core.answer_message(message_id, Err(()));
+                            }
+                        }
+                    }
+                }
+
+                CoreRunOutcome::ReservedPidInterfaceMessage {
+                    pid,
+                    message_id,
+                    interface,
+                    message,
+                } if interface == redshirt_power_interface::ffi::INTERFACE => {
+                    // Requests are never answered on success, as the system stops.
+                    let action = match redshirt_power_interface::ffi::PowerMessage::decode(message)
+                    {
+                        Ok(redshirt_power_interface::ffi::PowerMessage::Shutdown) => {
+                            PowerAction::Shutdown
+                        }
+                        Ok(redshirt_power_interface::ffi::PowerMessage::Reboot) => {
+                            PowerAction::Reboot
+                        }
+                        Ok(redshirt_power_interface::ffi::PowerMessage::Suspend) => {
+                            // TODO: support suspending
+                            if let Some(message_id) = message_id {
+                                let response = redshirt_power_interface::ffi::PowerResponse {
+                                    result: Err(redshirt_syscalls_interface::ErrorPayload::new(
+                                        redshirt_syscalls_interface::ErrorClass::UNSUPPORTED,
+                                    )),
+                                };
+                                self.core.answer_message(message_id, Ok(response.encode()));
+                            }
+                            continue;
+                        }
+                        Err(_) => {
+                            if let Some(message_id) = message_id {
+                                self.core.answer_message(message_id, Err(()));
+                            }
+                            continue;
+                        }
+                    };
+
+                    return Some(SystemRunOutcome::PowerRequested { pid, action });
+                }
+
+                CoreRunOutcome::ReservedPidInterfaceMessage {
+                    pid,
+                    message_id,
+                    interface,
+                    message,
+                } if interface == redshirt_diagnostics_interface::ffi::INTERFACE => {
+                    match redshirt_diagnostics_interface::ffi::DiagnosticsMessage::decode(message) {
+                        Ok(redshirt_diagnostics_interface::ffi::DiagnosticsMessage::Query(
+                            filter,
+                        )) => {
+                            if let Some(message_id) = message_id {
+                                let response = self.kernel_log(&filter);
+                                self.core.answer_message(message_id, Ok(response.encode()));
+                            }
+                        }
+                        Ok(redshirt_diagnostics_interface::ffi::DiagnosticsMessage::Follow(
+                            filter,
+                        )) => {
+                            if let Some(message_id) = message_id {
+                                self.log_followers.push((message_id, pid, filter));
+                            }
+                        }
+                        Err(_) => {
+                            if let Some(message_id) = message_id {
+                                self.core.answer_message(message_id, Err(()));
+                            }
+                        }
+                    }
+                }
+
+                CoreRunOutcome::ReservedPidInterfaceMessage {
+                    pid,
+                    message_id,
+                    interface,
+                    message,
+                } if interface == redshirt_arguments_interface::ffi::INTERFACE => {
+                    match redshirt_arguments_interface::ffi::ArgumentsMessage::decode(message) {
+                        Ok(redshirt_arguments_interface::ffi::ArgumentsMessage::Get) => {
+                            if let Some(message_id) = message_id {
+                                let response = self
+                                    .process_arguments
+                                    .get(&pid)
+                                    .cloned()
+                                    .unwrap_or_default();
+                                self.core.answer_message(message_id, Ok(response.encode()));
+                            }
+                        }
+                        Err(_) => {
+                            if let Some(message_id) = message_id {
+                                self.core.answer_message(message_id, Err(()));
+                            }
+                        }
+                    }
+                }
+
+                CoreRunOutcome::ReservedPidInterfaceMessage {
+                    pid,
+                    message_id,
+                    interface,
+                    message,
+                } if interface == redshirt_process_interface::ffi::INTERFACE => {
+                    match redshirt_process_interface::ffi::ProcessMessage::decode(message) {
+                        Ok(redshirt_process_interface::ffi::ProcessMessage::List) => {
+                            if let Some(message_id) = message_id {
+                                let response = redshirt_process_interface::ffi::ListResponse {
+                                    processes: self.processes(),
+                                };
+                                self.core.answer_message(message_id, Ok(response.encode()));
+                            }
+                        }
+                        Ok(redshirt_process_interface::ffi::ProcessMessage::Spawn(spawn)) => {
+                            let spawning = SpawningProgram {
+                                hash: ModuleHash::from(spawn.module_hash),
+                                spawn_message_id: message_id,
+                                parent: pid,
+                                arguments: redshirt_arguments_interface::ffi::Arguments {
+                                    arguments: spawn.arguments,
+                                    environment: spawn.environment,
+                                },
+                            };
+
+                            // No need to ask the loader if a process started from the same
+                            // module is still alive.
+                            if self.loaded_modules.contains_key(&spawning.hash) {
+                                self.start_spawned_program(spawning, None);
+                            } else {
+                                let msg = redshirt_loader_interface::ffi::LoaderMessage::Load(
+                                    spawn.module_hash,
+                                );
+                                let load_message_id = self.core.emit_interface_message_answer(
+                                    self.process_interface_pid,
+                                    redshirt_loader_interface::ffi::INTERFACE,
+                                    msg,
+                                );
+                                self.spawning_programs.push((load_message_id, spawning));
+                            }
+                        }
+                        Ok(redshirt_process_interface::ffi::ProcessMessage::Kill(target)) => {
+                            // Processes can only kill themselves and their descendants. Native
+                            // programs are part of the kernel and can kill any process.
+                            // TODO: add a proper permissions system
+                            let mut allowed = self.native_programs.contains(pid);
+                            let mut ancestor = Some(target);
+                            while let Some(a) = ancestor {
+                                if a == pid {
+                                    allowed = true;
+                                    break;
+                                }
+                                ancestor = self.core.parent_of(a);
+                            }
+
+                            let result = if !allowed {
+                                Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED))
+                            } else if self.core.process_by_id(target).is_none() {
+                                Err(ErrorPayload::new(ErrorClass::NOT_FOUND))
+                            } else {
+                                Ok(())
+                            };
+
+                            // Answer before killing, as the emitter might be the target.
+                            let kill = result.is_ok();
+                            if let Some(message_id) = message_id {
+                                let response =
+                                    redshirt_process_interface::ffi::KillResponse { result };
+                                self.core.answer_message(message_id, Ok(response.encode()));
+                            }
+                            if kill {
+                                let _ = self.core.kill(target);
+                            }
+                        }
+                        Err(_) => {
+                            if let Some(message_id) = message_id {
+                                self.core.answer_message(message_id, Err(()));
+                            }
+                        }
+                    }
+                }
+
                 CoreRunOutcome::ReservedPidInterfaceMessage {
                     pid,
                     message_id,
@@ -251,33 +1144,95 @@ impl System {
                         Ok(m) => m,
                         Err(_) => panic!(), // TODO:
                     };
-                    match msg {
+                    let (interface_hash, versions) = match msg {
                         redshirt_interface_interface::ffi::InterfaceMessage::Register(
                             interface_hash,
+                        ) => (interface_hash, Vec::new()),
+                        redshirt_interface_interface::ffi::InterfaceMessage::RegisterVersions(
+                            interface_hash,
+                            versions,
+                        ) => (interface_hash, versions),
+                        redshirt_interface_interface::ffi::InterfaceMessage::QueryVersions(
+                            interface_hash,
                         ) => {
-                            let result = self.core
-                                .set_interface_handler(interface_hash.clone(), pid)
-                                .map_err(|()| redshirt_interface_interface::ffi::InterfaceRegisterError::AlreadyRegistered);
                             let response =
-                                redshirt_interface_interface::ffi::InterfaceRegisterResponse {
-                                    result,
+                                redshirt_interface_interface::ffi::InterfaceVersionsResponse {
+                                    versions: self.interface_versions.get(&interface_hash).cloned(),
                                 };
                             if let Some(message_id) = message_id {
                                 self.core.answer_message(message_id, Ok(response.encode()));
                             }
-
-                            if interface_hash == redshirt_loader_interface::ffi::INTERFACE {
-                                for hash in self.main_programs.drain(..) {
-                                    let msg =
-                                        redshirt_loader_interface::ffi::LoaderMessage::Load(hash);
-                                    let id = self.core.emit_interface_message_answer(
-                                        From::from(0), // FIXME: wrong; hacky
-                                        redshirt_loader_interface::ffi::INTERFACE,
-                                        msg,
-                                    );
-                                    self.loading_programs.insert(id);
+                            continue;
+                        }
+                        redshirt_interface_interface::ffi::InterfaceMessage::WaitAvailabilityChange(
+                            interface_hash,
+                            available,
+                        ) => {
+                            if let Some(message_id) = message_id {
+                                if self.core.has_interface_handler(&interface_hash) != available {
+                                    let response = redshirt_interface_interface::ffi::InterfaceAvailabilityResponse {
+                                        available: !available,
+                                    };
+                                    self.core.answer_message(message_id, Ok(response.encode()));
+                                } else {
+                                    self.availability_watchers.push((
+                                        message_id,
+                                        interface_hash,
+                                        available,
+                                    ));
                                 }
                             }
+                            continue;
+                        }
+                    };
+
+                    let result = self
+                        .core
+                        .set_interface_handler(interface_hash.clone(), pid)
+                        .map_err(|err| match err {
+                            SetInterfaceHandlerError::AlreadyRegistered => {
+                                redshirt_interface_interface::ffi::InterfaceRegisterError::AlreadyRegistered
+                            }
+                            SetInterfaceHandlerError::NotDeclared => {
+                                redshirt_interface_interface::ffi::InterfaceRegisterError::NotDeclared
+                            }
+                            SetInterfaceHandlerError::ReservedInterface => {
+                                redshirt_interface_interface::ffi::InterfaceRegisterError::ReservedInterface
+                            }
+                            // `pid` has just emitted the message and is therefore alive.
+                            SetInterfaceHandlerError::InvalidHandler => unreachable!(),
+                        });
+                    if result.is_ok() {
+                        self.log(
+                            redshirt_diagnostics_interface::ffi::EntryKind::InterfaceRegistered,
+                            Some(pid),
+                            format!("Registered {:?}", interface_hash),
+                        );
+                        self.interface_versions
+                            .insert(interface_hash.clone(), versions);
+                        self.notify_availability_watchers(&interface_hash, true);
+                    }
+                    let response =
+                        redshirt_interface_interface::ffi::InterfaceRegisterResponse { result };
+                    if let Some(message_id) = message_id {
+                        self.core.answer_message(message_id, Ok(response.encode()));
+                    }
+
+                    if interface_hash == redshirt_loader_interface::ffi::INTERFACE {
+                        for (hash, required) in self.main_programs.drain(..) {
+                            let msg =
+                                redshirt_loader_interface::ffi::LoaderMessage::Load(*hash.digest());
+                            let message_id = self.core.emit_interface_message_answer(
+                                From::from(0), // FIXME: wrong; hacky
+                                redshirt_loader_interface::ffi::INTERFACE,
+                                msg,
+                            );
+                            self.loading_programs.push_back(LoadingProgram {
+                                message_id,
+                                hash,
+                                required,
+                                response: None,
+                            });
                         }
                     }
                 }
@@ -305,57 +1260,198 @@ impl SystemBuilder {
         let mut core = Core::new();
         let interface_interface_pid = core.reserve_pid();
         let threads_interface_pid = core.reserve_pid();
+        let metrics_interface_pid = core.reserve_pid();
+        let power_interface_pid = core.reserve_pid();
+        let process_interface_pid = core.reserve_pid();
+        let arguments_interface_pid = core.reserve_pid();
+        let diagnostics_interface_pid = core.reserve_pid();
 
         SystemBuilder {
             core,
             interface_interface_pid,
             threads_interface_pid,
+            metrics_interface_pid,
+            power_interface_pid,
+            process_interface_pid,
+            arguments_interface_pid,
+            diagnostics_interface_pid,
             startup_processes: Vec::new(),
             main_programs: Vec::new(),
             native_programs: native::NativeProgramsCollection::new(),
+            idle_hook: None,
+            monotonic_clock: None,
+            timer: None,
+            module_limits: ModuleLimits::default(),
         }
     }
 
     /// Registers native code that can communicate with the WASM programs.
     pub fn with_native_program<T>(mut self, program: T) -> Self
     where
-        T: Send + 'static,
-        for<'r> &'r T: native::NativeProgramRef<'r>,
+        T: native::NativeProgram + Send + 'static,
     {
         self.native_programs.push(self.core.reserve_pid(), program);
         self
     }
 
-    /// Adds a process to the list of processes that the [`System`] must start as part of the
-    /// startup process.
+    /// Returns a handle that allows spawning background tasks executed by the [`System`]. Meant
+    /// to be passed to the native programs when creating them.
+    pub fn native_task_spawner(&self) -> native::NativeTaskSpawner {
+        self.native_programs.task_spawner()
+    }
+
+    /// Registers a function that WASM modules can import under the given module and function
+    /// name.
+    ///
+    /// Contrary to messages, which are the normal way for programs to interact with the outside,
+    /// calling a host function is synchronous and doesn't go through any interface handler. This
+    /// is meant for platform-specific fast paths that don't fit the message-passing model.
+    ///
+    /// # Panic
+    ///
+    /// Panics if a function with this module/name combination has already been registered,
+    /// including the functions of the `redshirt` module.
+    ///
+    pub fn with_host_function(
+        mut self,
+        interface: impl Into<Cow<'static, str>>,
+        f_name: impl Into<Cow<'static, str>>,
+        signature: Signature,
+        function: impl HostFunction + 'static,
+    ) -> Self {
+        self.core
+            .add_host_function(interface, f_name, signature, function);
+        self
+    }
+
+    /// Sets a function to call whenever the [`System`] has nothing to do: no program is ready to
+    /// run and no native program has an event to report.
+    ///
+    /// The hook is called from within [`System::run`], right before it returns `Pending`. It is
+    /// typically used to put the CPU to sleep (for example with `hlt` or `wfi`) or to park the
+    /// current thread until the next interrupt or wake-up. The hook must return as soon as
+    /// something might have happened, otherwise the [`System`] will stall.
     ///
-    /// The startup processes are started in the order in which they are added here.
+    /// Only one hook can be set. Calling this method again replaces the previous hook.
+    pub fn with_idle_hook(mut self, hook: impl FnMut() + Send + 'static) -> Self {
+        self.idle_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets the function that returns the current value of the monotonic clock, in nanoseconds.
+    ///
+    /// This clock is used to wake up the threads that have called `sleep_until` and to enforce
+    /// the deadlines of messages. If no clock is set, these threads are woken up immediately and
+    /// deadlines expire immediately.
+    ///
+    /// Programs can also read this clock synchronously by calling the `monotonic_clock` function
+    /// of the `redshirt` module, which returns 0 if no clock is set.
+    ///
+    /// A timer should also be set with [`SystemBuilder::with_timer`]. Otherwise,
+    /// [`System::run`] keeps waking itself up for as long as a thread is sleeping or a message
+    /// has a deadline.
+    pub fn with_monotonic_clock(mut self, clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        self.monotonic_clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Sets the function that returns a future resolving once the monotonic clock, as returned
+    /// by the function passed to [`SystemBuilder::with_monotonic_clock`], reaches the given
+    /// value, in nanoseconds.
     ///
-    /// By default, the list is empty. Should at least contain a process that handles the `loader`
-    /// interface.
-    pub fn with_startup_process(mut self, process: impl Into<Module>) -> Self {
-        let process = process.into();
-        self.startup_processes.push(process);
+    /// When it has nothing else to do, [`System::run`] uses this timer to be woken up when the
+    /// earliest sleeping thread must be woken up or the earliest message deadline expires.
+    pub fn with_timer(mut self, timer: impl Fn(u64) -> Timer + Send + 'static) -> Self {
+        self.timer = Some(Box::new(timer));
         self
     }
 
+    /// Sets the limits to enforce on the modules of the programs that are loaded through the
+    /// loader, whether they are main programs or spawned through the `process` interface.
+    ///
+    /// Defaults to [`ModuleLimits::default`]. The programs passed as a [`Module`] to
+    /// [`SystemBuilder::with_startup_program`] have already been parsed and aren't affected.
+    pub fn with_module_limits(mut self, limits: ModuleLimits) -> Self {
+        self.module_limits = limits;
+        self
+    }
+
+    /// Adds a middleware that is called for each message emitted on the given interface, before
+    /// the message reaches the handler. The middleware can inspect, modify or reject messages.
+    ///
+    /// Can be called multiple times, including for the same interface, in which case the
+    /// middlewares are called in the order in which they have been added.
+    pub fn with_middleware(
+        mut self,
+        interface: InterfaceHash,
+        middleware: impl Middleware + 'static,
+    ) -> Self {
+        self.core.add_middleware(interface, middleware);
+        self
+    }
+
+    /// Adds a program to the list of programs that the [`System`] must start when it boots. Can
+    /// be called multiple times to add multiple programs.
+    ///
+    /// Programs are started in two phases, regardless of how the calls to this method are
+    /// interleaved:
+    ///
+    /// - Programs passed as a [`Module`] are all started when the [`System`] is built.
+    /// - Programs passed as a [`ModuleHash`] are loaded through the `loader` interface as soon as
+    /// it has been registered by one of the programs of the first phase.
+    ///
+    /// Within each phase, programs are started in the order in which they are added here. A
+    /// [`ModuleHash`] added before a [`Module`] is therefore started after it.
+    ///
+    /// By default, the list is empty. Should at least contain a [`Module`] that handles the
+    /// `loader` interface if any program is passed by hash.
+    pub fn with_startup_program(mut self, program: impl Into<StartupProgram>) -> Self {
+        let program = program.into();
+        match program.source {
+            StartupProgramSource::Module(module) => {
+                self.startup_processes.push((module, program.required))
+            }
+            StartupProgramSource::Hash(hash) => self.main_programs.push((hash, program.required)),
+        }
+        self
+    }
+
+    /// Adds a process to the list of processes that the [`System`] must start as part of the
+    /// startup process.
+    ///
+    /// Equivalent to calling [`with_startup_program`](SystemBuilder::with_startup_program) with
+    /// a required [`Module`].
+    pub fn with_startup_process(self, process: impl Into<Module>) -> Self {
+        self.with_startup_program(process.into())
+    }
+
     /// Adds a program that the [`System`] must execute after startup. Can be called multiple times
     /// to add multiple programs.
     ///
-    /// The program will be loaded through the `loader` interface. The loading starts as soon as
-    /// the `loader` interface has been registered by one of the processes passed to
-    /// [`with_startup_process`](SystemBuilder::with_startup_process).
-    pub fn with_main_program(mut self, hash: [u8; 32]) -> Self {
-        self.main_programs.push(hash);
-        self
+    /// Equivalent to calling [`with_startup_program`](SystemBuilder::with_startup_program) with
+    /// a required [`ModuleHash`].
+    pub fn with_main_program(self, hash: [u8; 32]) -> Self {
+        self.with_startup_program(ModuleHash::from(hash))
     }
 
     /// Builds the [`System`].
     pub fn build(mut self) -> System {
+        let clock = self.monotonic_clock.clone();
+        self.core.add_host_function(
+            "redshirt",
+            "monotonic_clock",
+            sig!(() -> I64),
+            move |_: &mut HostFunctionContext, _: &[wasmi::RuntimeValue]| {
+                let now = clock.as_ref().map_or(0, |clock| clock());
+                Some(wasmi::RuntimeValue::I64(now as i64))
+            },
+        );
+
         let mut core = self.core.build();
 
-        // We ask the core to redirect messages for the `interface` and `threads` interfaces
-        // towards our "virtual" `Pid`s.
+        // We ask the core to redirect messages for the `interface`, `threads`, `metrics`,
+        // `power`, `process`, `arguments` and `diagnostics` interfaces towards our "virtual"
+        // `Pid`s.
         match core.set_interface_handler(
             redshirt_interface_interface::ffi::INTERFACE,
             self.interface_interface_pid,
@@ -370,21 +1466,86 @@ impl SystemBuilder {
             Ok(()) => {}
             Err(_) => unreachable!(),
         };
+        match core.set_interface_handler(
+            redshirt_metrics_interface::ffi::INTERFACE,
+            self.metrics_interface_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
+        match core.set_interface_handler(
+            redshirt_power_interface::ffi::INTERFACE,
+            self.power_interface_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
+        match core.set_interface_handler(
+            redshirt_process_interface::ffi::INTERFACE,
+            self.process_interface_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
+        match core.set_interface_handler(
+            redshirt_arguments_interface::ffi::INTERFACE,
+            self.arguments_interface_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
+        match core.set_interface_handler(
+            redshirt_diagnostics_interface::ffi::INTERFACE,
+            self.diagnostics_interface_pid,
+        ) {
+            Ok(()) => {}
+            Err(_) => unreachable!(),
+        };
 
-        for program in self.startup_processes {
-            core.execute(&program)
-                .expect("failed to start startup program"); // TODO:
+        let mut started = Vec::with_capacity(self.startup_processes.len());
+        for (program, required) in self.startup_processes {
+            match core.execute(&program) {
+                Ok(process) => started.push((process.pid(), program.hash().clone())),
+                Err(err) if required => panic!("Failed to start startup program: {}", err),
+                Err(_) => {}
+            }
         }
 
         self.main_programs.shrink_to_fit();
 
-        System {
+        let mut system = System {
             core,
             native_programs: self.native_programs,
             futex_waits: Default::default(),
             loading_programs: Default::default(),
+            spawning_programs: Vec::new(),
+            loaded_modules: Default::default(),
+            process_modules: Default::default(),
+            process_interface_pid: self.process_interface_pid,
+            process_arguments: Default::default(),
+            kernel_log: VecDeque::with_capacity(MAX_KERNEL_LOG_ENTRIES),
+            next_log_sequence: 0,
+            log_followers: Vec::new(),
             main_programs: self.main_programs,
+            idle_hook: self.idle_hook,
+            monotonic_clock: self.monotonic_clock,
+            timer: self.timer,
+            pending_timer: None,
+            module_limits: self.module_limits,
+            interface_versions: Default::default(),
+            availability_watchers: Vec::new(),
+            startup_processes: started.iter().map(|(pid, _)| *pid).collect(),
+        };
+
+        for (pid, hash) in started {
+            system.log(
+                redshirt_diagnostics_interface::ffi::EntryKind::ProcessStarted,
+                Some(pid),
+                format!("Started {}", hash),
+            );
         }
+
+        system
     }
 }
 
@@ -393,3 +1554,90 @@ impl Default for SystemBuilder {
         SystemBuilder::new()
     }
 }
+
+impl StartupProgram {
+    /// Marks the program as optional. If it fails to load or start, it is skipped instead of
+    /// making the [`System`] panic.
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+}
+
+impl From<Module> for StartupProgram {
+    fn from(module: Module) -> StartupProgram {
+        StartupProgram {
+            source: StartupProgramSource::Module(module),
+            required: true,
+        }
+    }
+}
+
+impl From<ModuleHash> for StartupProgram {
+    fn from(hash: ModuleHash) -> StartupProgram {
+        StartupProgram {
+            source: StartupProgramSource::Hash(hash),
+            required: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SystemBuilder;
+    use crate::module::{Module, ModuleHash};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn startup_programs_by_hash_start_after_modules() {
+        let module = Module::from_wat(
+            r#"(module
+            (func $_start (result i32)
+                i32.const 5)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+
+        let system = SystemBuilder::new()
+            .with_startup_program(ModuleHash::from([1; 32]))
+            .with_startup_program(module)
+            .with_startup_program(ModuleHash::from([2; 32]))
+            .build();
+
+        // The module is started immediately, while the hashes wait for the loader in the order
+        // in which they have been added.
+        assert_eq!(system.startup_processes().len(), 1);
+        let pending = system
+            .main_programs
+            .iter()
+            .map(|(hash, _)| *hash.digest())
+            .collect::<Vec<_>>();
+        assert_eq!(pending, [[1; 32], [2; 32]]);
+    }
+
+    #[test]
+    fn processes_share_module_by_hash() {
+        let wasm = wat::parse_str(
+            r#"(module
+            (func $_start (result i32)
+                i32.const 5)
+            (export "_start" (func $_start)))
+        "#,
+        )
+        .unwrap();
+        let hash = ModuleHash::from_bytes(&wasm);
+
+        let mut system = SystemBuilder::new().build();
+        let first = system.execute_by_hash(&hash, Some(&wasm), None).unwrap();
+        // The module is already loaded, so its bytes aren't needed anymore.
+        let second = system.execute_by_hash(&hash, None, Some(first)).unwrap();
+        assert_eq!(system.loaded_modules[&hash].num_processes, 2);
+        assert_eq!(system.process_modules[&first], hash);
+        assert_eq!(system.process_modules[&second], hash);
+
+        assert!(system
+            .execute_by_hash(&ModuleHash::from([1; 32]), None, None)
+            .is_err());
+    }
+}
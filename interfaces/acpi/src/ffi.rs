@@ -0,0 +1,188 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x08, 0x5c, 0xf3, 0x21, 0x76, 0x46, 0x50, 0x06, 0xc1, 0xc1, 0xb9, 0xbe, 0x3f, 0xa7, 0xfc, 0xb0,
+    0x94, 0x8d, 0x35, 0x8c, 0x73, 0x97, 0x53, 0xa5, 0x38, 0xc1, 0xb2, 0x43, 0x45, 0xcd, 0x28, 0x7e,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum AcpiMessage {
+    /// Returns the content of the Multiple APIC Description Table. Answered with a
+    /// [`MadtResponse`].
+    Madt,
+    /// Returns the content of the PCI Express memory mapped configuration space table.
+    /// Answered with a [`McfgResponse`].
+    Mcfg,
+    /// Returns the content of the Fixed ACPI Description Table. Answered with a
+    /// [`FadtResponse`].
+    Fadt,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct MadtResponse {
+    /// Contains an error with the `NOT_FOUND` class if the table isn't present.
+    pub result: Result<Madt, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct McfgResponse {
+    /// Contains an error with the `NOT_FOUND` class if the table isn't present.
+    pub result: Result<Mcfg, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct FadtResponse {
+    /// Contains an error with the `NOT_FOUND` class if the table isn't present.
+    pub result: Result<Fadt, ErrorPayload>,
+}
+
+/// Multiple APIC Description Table. Describes the interrupt controllers of the system.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Madt {
+    /// Physical address of the local APIC of each processor.
+    pub local_apic_address: u64,
+    /// If true, the system also has 8259 PICs, which must be disabled before using the APICs.
+    pub legacy_pics: bool,
+    pub processors: Vec<Processor>,
+    pub io_apics: Vec<IoApic>,
+    /// Differences between the ISA interrupts and the global system interrupts. ISA interrupts
+    /// that aren't in this list are identity-mapped.
+    pub interrupt_source_overrides: Vec<InterruptSourceOverride>,
+    /// Local APIC inputs connected to the non-maskable interrupt.
+    pub local_apic_nmis: Vec<LocalApicNmi>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Processor {
+    /// Identifier of the processor in the ACPI namespace.
+    pub processor_uid: u32,
+    /// Identifier of the local APIC of the processor.
+    pub apic_id: u32,
+    /// If false, the processor can't be used.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct IoApic {
+    pub id: u8,
+    /// Physical address of the registers of the I/O APIC.
+    pub address: u32,
+    /// Global system interrupt corresponding to the first input of the I/O APIC.
+    pub global_system_interrupt_base: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct InterruptSourceOverride {
+    /// Always 0, meaning ISA.
+    pub bus: u8,
+    /// ISA interrupt number.
+    pub source: u8,
+    pub global_system_interrupt: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct LocalApicNmi {
+    /// Processor whose local APIC is concerned, or `None` for all processors.
+    pub processor_uid: Option<u32>,
+    /// Input of the local APIC, either 0 or 1.
+    pub lint: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Polarity {
+    /// Conforms to the specifications of the bus.
+    SameAsBus,
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum TriggerMode {
+    /// Conforms to the specifications of the bus.
+    SameAsBus,
+    Edge,
+    Level,
+}
+
+/// PCI Express memory mapped configuration space table.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Mcfg {
+    pub entries: Vec<McfgEntry>,
+}
+
+/// Range of buses whose configuration space is accessible through the Enhanced Configuration
+/// Access Mechanism.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct McfgEntry {
+    /// Physical address of the configuration space of the bus 0 of the segment group, even if
+    /// `start_bus` isn't 0.
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// Fixed ACPI Description Table. Contains information about the power management hardware.
+///
+/// Fields that don't exist in the revision of the table provided by the firmware are set to 0.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Fadt {
+    /// Physical address of the Differentiated System Description Table.
+    pub dsdt_address: u64,
+    /// Interrupt used by the ACPI hardware to signal events.
+    pub sci_interrupt: u16,
+    /// I/O port where `acpi_enable` and `acpi_disable` must be written. 0 if the system is
+    /// always in ACPI mode.
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    /// I/O ports of the power management registers blocks.
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm_timer_block: u32,
+    /// Index of the century in the RTC memory, or 0 if not supported.
+    pub century_register: u8,
+    /// IA-PC boot architecture flags. Indicate, for example, whether there is an 8042
+    /// controller or VGA hardware.
+    pub boot_architecture_flags: u16,
+    /// Fixed feature flags.
+    pub flags: u32,
+    /// Register where `reset_value` must be written in order to reset the system, if supported.
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+/// Location of a register.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct GenericAddress {
+    /// 0 for system memory, 1 for system I/O, 2 for PCI configuration space.
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
@@ -0,0 +1,59 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! ACPI tables.
+//!
+//! The ACPI tables are provided by the firmware and describe the hardware of the machine. This
+//! interface gives access to the tables that drivers need, already parsed: the interrupt
+//! controllers with [`madt`], the PCI Express configuration space with [`mcfg`], and the power
+//! management hardware with [`fadt`].
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use futures::prelude::*;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the content of the Multiple APIC Description Table.
+pub fn madt() -> impl Future<Output = Result<ffi::Madt, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::AcpiMessage::Madt;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::MadtResponse| rep.result)
+}
+
+/// Returns the content of the PCI Express memory mapped configuration space table.
+pub fn mcfg() -> impl Future<Output = Result<ffi::Mcfg, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::AcpiMessage::Mcfg;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::McfgResponse| rep.result)
+}
+
+/// Returns the content of the Fixed ACPI Description Table.
+pub fn fadt() -> impl Future<Output = Result<ffi::Fadt, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::AcpiMessage::Fadt;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::FadtResponse| rep.result)
+}
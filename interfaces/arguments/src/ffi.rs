@@ -0,0 +1,41 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x2e, 0x02, 0x7b, 0x38, 0xa6, 0x50, 0x36, 0xc1, 0xac, 0xd8, 0x1a, 0xeb, 0x0d, 0x17, 0xa3, 0x05,
+    0x53, 0x08, 0xe4, 0x6c, 0x91, 0x6d, 0xcd, 0xaf, 0xfd, 0x98, 0x8e, 0x1d, 0x94, 0x7c, 0x46, 0x85,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum ArgumentsMessage {
+    /// Request the arguments and environment of the emitter. Must be answered with an
+    /// [`Arguments`].
+    Get,
+}
+
+/// Arguments and environment variables of a process, as passed by the process that has spawned
+/// it. Both lists are empty for processes that haven't been spawned through the `process`
+/// interface.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Arguments {
+    pub arguments: Vec<String>,
+    /// List of keys and values.
+    pub environment: Vec<(String, String)>,
+}
@@ -0,0 +1,52 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Arguments and environment variables.
+//!
+//! This interface is handled by the kernel itself. The values are set by the process that has
+//! spawned the current process through the `process` interface.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub mod ffi;
+
+/// Returns the arguments and environment variables of the current process.
+pub fn get() -> impl Future<Output = ffi::Arguments> {
+    unsafe {
+        let msg = ffi::ArgumentsMessage::Get;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Returns the arguments of the current process.
+pub fn arguments() -> impl Future<Output = Vec<String>> {
+    get().map(|args| args.arguments)
+}
+
+/// Returns the value of an environment variable of the current process.
+pub async fn var(key: &str) -> Option<String> {
+    get()
+        .await
+        .environment
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+}
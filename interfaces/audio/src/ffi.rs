@@ -0,0 +1,119 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x07, 0x3c, 0x35, 0x6f, 0x82, 0x9e, 0xd4, 0x4f, 0x0a, 0x01, 0x43, 0x13, 0x0a, 0x8c, 0x96, 0xa1,
+    0xaf, 0x25, 0x11, 0x88, 0xb9, 0x2e, 0x1b, 0x66, 0x00, 0x46, 0x8d, 0xf2, 0x32, 0x30, 0xc7, 0x56,
+]);
+
+/// Interface registered by the driver of the audio device. Accepts the same messages as
+/// [`INTERFACE`].
+///
+/// Programs aren't meant to use this interface directly. Instead, the mixer that handles
+/// [`INTERFACE`] opens a single stream on the device and mixes the streams of all the programs
+/// into it.
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const DEVICE_INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x6a, 0xfc, 0x85, 0x04, 0x84, 0x58, 0xcd, 0x86, 0xb0, 0x5b, 0xf8, 0x73, 0x46, 0xdb, 0x4b, 0x9b,
+    0xce, 0x43, 0xbf, 0x40, 0xd0, 0x9b, 0x06, 0x0a, 0xeb, 0x5f, 0x36, 0x6d, 0x46, 0x75, 0xf2, 0xa8,
+]);
+
+/// Message sent to the handler of the audio interface.
+///
+/// Streams are identified by a number chosen by the handler. A stream can only be used by the
+/// process that has opened it, and is closed automatically when that process terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum AudioMessage {
+    /// Opens a playback stream. Answered with an [`OpenStreamResponse`].
+    OpenStream(StreamFormat),
+    /// Queues samples for playback. Answered with a [`WriteResponse`] once all the samples have
+    /// been queued in the hardware buffer, which lets the sender know when to produce more.
+    Write(Write),
+    /// Queries the latency of a stream. Answered with a [`LatencyResponse`].
+    Latency(u64),
+    /// Changes the volume of a stream. Applies to the samples written afterwards. No answer is
+    /// expected.
+    SetVolume(SetVolume),
+    /// Closes a stream. Samples that haven't been played yet are discarded. No answer is
+    /// expected.
+    CloseStream(u64),
+}
+
+/// Format of the samples of a stream.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct StreamFormat {
+    /// Number of frames per second, such as 44100 or 48000.
+    pub sample_rate: u32,
+    /// Number of channels. Samples of the different channels are interleaved.
+    pub channels: u8,
+    pub sample_format: SampleFormat,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum SampleFormat {
+    /// Signed 16 bits integers, little endian.
+    S16Le,
+    /// 32 bits floating point numbers between -1.0 and 1.0, little endian.
+    F32Le,
+}
+
+impl SampleFormat {
+    /// Returns the number of bytes of one sample of one channel.
+    pub fn bytes_per_sample(&self) -> u32 {
+        match self {
+            SampleFormat::S16Le => 2,
+            SampleFormat::F32Le => 4,
+        }
+    }
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct OpenStreamResponse {
+    /// On success, contains the identifier of the new stream.
+    pub result: Result<u64, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Write {
+    pub stream: u64,
+    /// Interleaved samples, in the format of the stream. The length must be a multiple of the
+    /// size of a frame.
+    pub samples: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SetVolume {
+    pub stream: u64,
+    /// Percentage of the original amplitude of the samples, between 0 and 100. Higher values
+    /// are treated as 100. Streams are opened with a volume of 100.
+    pub volume: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct LatencyResponse {
+    /// On success, contains the number of nanoseconds between the moment samples are written
+    /// and the moment they are heard.
+    pub result: Result<u64, ErrorPayload>,
+}
@@ -0,0 +1,111 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Audio output.
+//!
+//! Programs open a [`Stream`] with the format of the samples they produce, then push samples
+//! with [`Stream::write`]. The `Future` returned by [`Stream::write`] is ready once the samples
+//! have been queued, which regulates the rate at which samples are produced.
+//!
+//! Multiple streams, possibly with different formats, can be open at the same time. They are
+//! mixed together before being sent to the audio device.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::time::Duration;
+use futures::prelude::*;
+
+pub use ffi::{SampleFormat, StreamFormat};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Playback stream. Closed when destroyed.
+pub struct Stream {
+    id: u64,
+    format: StreamFormat,
+}
+
+impl Stream {
+    /// Opens a new playback stream with the given format.
+    pub async fn open(format: StreamFormat) -> Result<Stream, ErrorPayload> {
+        let msg = ffi::AudioMessage::OpenStream(format.clone());
+        let response: ffi::OpenStreamResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        Ok(Stream {
+            id: response.result?,
+            format,
+        })
+    }
+
+    /// Returns the format passed when opening the stream.
+    pub fn format(&self) -> &StreamFormat {
+        &self.format
+    }
+
+    /// Queues samples for playback. The `Future` is ready once they have been queued.
+    ///
+    /// The samples must be interleaved and in the format of the stream.
+    pub fn write(&self, samples: Vec<u8>) -> impl Future<Output = Result<(), ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::AudioMessage::Write(ffi::Write {
+                stream: self.id,
+                samples,
+            });
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::WriteResponse| rep.result)
+    }
+
+    /// Changes the volume of the stream, as a percentage of the original amplitude of the
+    /// samples. Applies to the samples written afterwards.
+    pub fn set_volume(&self, volume: u8) {
+        unsafe {
+            let msg = ffi::AudioMessage::SetVolume(ffi::SetVolume {
+                stream: self.id,
+                volume,
+            });
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+
+    /// Returns the delay between the moment samples are written and the moment they are heard.
+    pub fn latency(&self) -> impl Future<Output = Result<Duration, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::AudioMessage::Latency(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::LatencyResponse| rep.result.map(Duration::from_nanos))
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::AudioMessage::CloseStream(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x29, 0x33, 0x18, 0x1d, 0x26, 0x3d, 0x12, 0x14, 0x35, 0x0b, 0x5f, 0x25, 0x2a, 0x24, 0x5e, 0x38,
+    0x32, 0x06, 0x11, 0x11, 0x3e, 0x2f, 0x0b, 0x57, 0x26, 0x3d, 0x2c, 0x10, 0x01, 0x07, 0x0d, 0x12,
+]);
+
+/// Message sent to the handler of the block device interface.
+///
+/// The handler can expose multiple devices, each designated by an identifier chosen by the
+/// handler. All operations are asynchronous: the answer is sent back once the operation has
+/// completed, and multiple operations can be in progress at the same time.
+#[derive(Debug, Encode, Decode)]
+pub enum BlockMessage {
+    /// Asks for the list of devices. Answered with a [`ListDevicesResponse`].
+    ListDevices,
+    /// Asks for the geometry of a device. Answered with a [`GeometryResponse`].
+    Geometry(Geometry),
+    /// Reads a range of sectors. Answered with a [`ReadResponse`].
+    Read(Read),
+    /// Writes a range of sectors. Answered with a [`WriteResponse`].
+    ///
+    /// The data is not guaranteed to be persisted until a [`BlockMessage::Flush`] completes.
+    Write(Write),
+    /// Makes sure that all the previously-completed writes are persisted. Answered with a
+    /// [`FlushResponse`].
+    Flush(Flush),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListDevicesResponse {
+    pub devices: Vec<u32>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Geometry {
+    pub device: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct GeometryResponse {
+    pub result: Result<DeviceGeometry, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DeviceGeometry {
+    /// Size of a sector, in bytes. All reads and writes are done in units of sectors.
+    pub sector_size: u32,
+    /// Total number of sectors on the device.
+    pub num_sectors: u64,
+    /// If true, writes are refused.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Read {
+    pub device: u32,
+    /// Index of the first sector to read.
+    pub first_sector: u64,
+    /// Number of sectors to read.
+    pub num_sectors: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadResponse {
+    /// On success, contains `num_sectors * sector_size` bytes.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Write {
+    pub device: u32,
+    /// Index of the first sector to write.
+    pub first_sector: u64,
+    /// Data to write. Its length must be a multiple of the sector size.
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Flush {
+    pub device: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct FlushResponse {
+    pub result: Result<(), ErrorPayload>,
+}
@@ -0,0 +1,120 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Block devices.
+//!
+//! A block device is a storage medium, such as a hard drive, that is read and written in units
+//! of sectors. This interface is meant to be registered by disk drivers and used by filesystem
+//! implementations.
+//!
+//! Use [`devices`] to obtain the list of available devices, then [`BlockDevice`] to access one.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+
+pub mod ffi;
+
+/// Returns the list of devices available.
+#[cfg(feature = "std")]
+pub async fn devices() -> Vec<BlockDevice> {
+    let rep: ffi::ListDevicesResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(
+            &ffi::INTERFACE,
+            ffi::BlockMessage::ListDevices,
+        )
+        .unwrap()
+        .await
+    };
+    rep.devices
+        .into_iter()
+        .map(|id| BlockDevice { id })
+        .collect()
+}
+
+/// Access to a block device.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDevice {
+    id: u32,
+}
+
+#[cfg(feature = "std")]
+impl BlockDevice {
+    /// Returns the identifier of the device, as assigned by the handler.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Queries the geometry of the device.
+    pub async fn geometry(&self) -> Result<ffi::DeviceGeometry, ErrorPayload> {
+        let msg = ffi::BlockMessage::Geometry(ffi::Geometry { device: self.id });
+        let rep: ffi::GeometryResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+
+    /// Reads `num_sectors` sectors starting at `first_sector`.
+    pub async fn read(&self, first_sector: u64, num_sectors: u32) -> Result<Vec<u8>, ErrorPayload> {
+        let msg = ffi::BlockMessage::Read(ffi::Read {
+            device: self.id,
+            first_sector,
+            num_sectors,
+        });
+        let rep: ffi::ReadResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+
+    /// Writes `data` starting at `first_sector`. The length of `data` must be a multiple of the
+    /// sector size.
+    pub async fn write(
+        &self,
+        first_sector: u64,
+        data: impl Into<Vec<u8>>,
+    ) -> Result<(), ErrorPayload> {
+        let msg = ffi::BlockMessage::Write(ffi::Write {
+            device: self.id,
+            first_sector,
+            data: data.into(),
+        });
+        let rep: ffi::WriteResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+
+    /// Waits until all the previous writes have been persisted.
+    pub async fn flush(&self) -> Result<(), ErrorPayload> {
+        let msg = ffi::BlockMessage::Flush(ffi::Flush { device: self.id });
+        let rep: ffi::FlushResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+}
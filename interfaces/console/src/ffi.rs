@@ -0,0 +1,53 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::string::String;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x70, 0x34, 0x1e, 0xd6, 0xa8, 0xec, 0x14, 0x20, 0x3a, 0x02, 0x90, 0x18, 0x1d, 0x29, 0x8c, 0x10,
+    0x69, 0x1c, 0xf0, 0x79, 0x2c, 0xc7, 0xa1, 0x3b, 0x53, 0x2a, 0x47, 0x35, 0xb2, 0x9a, 0xa6, 0xab,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum ConsoleMessage {
+    /// Writes text on the console. No answer is expected.
+    ///
+    /// The text can contain ANSI escape sequences, which the handler must interpret. At least
+    /// cursor movements (`CSI n A/B/C/D`, `CSI r;c H`), erasing (`CSI n J`, `CSI n K`) and
+    /// colors (`CSI n m` with the 8 standard colors) must be supported. Unsupported sequences
+    /// are ignored.
+    Write(String),
+    /// Waits for the user to enter a line of text. Answered with a [`ReadLineResponse`].
+    ReadLine,
+    /// Queries the size of the console. Answered with a [`SizeResponse`].
+    Size,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadLineResponse {
+    /// On success, the line without the trailing line break.
+    pub result: Result<String, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SizeResponse {
+    /// Number of characters per line.
+    pub columns: u16,
+    /// Number of lines.
+    pub rows: u16,
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Text console.
+//!
+//! Contrary to the stdout interface, the console can be read from, and its output supports ANSI
+//! escape sequences. This makes it possible to write interactive programs.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use futures::prelude::*;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Writes text on the console. The text can contain ANSI escape sequences.
+pub fn write(text: impl Into<String>) {
+    unsafe {
+        let msg = ffi::ConsoleMessage::Write(text.into());
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
+
+/// Waits for the user to enter a line of text, and returns it without the trailing line break.
+pub fn read_line() -> impl Future<Output = Result<String, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::ConsoleMessage::ReadLine;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ReadLineResponse| rep.result)
+}
+
+/// Returns the number of columns and rows of the console.
+pub fn size() -> impl Future<Output = (u16, u16)> {
+    let response = unsafe {
+        let msg = ffi::ConsoleMessage::Size;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::SizeResponse| (rep.columns, rep.rows))
+}
@@ -0,0 +1,108 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x6b, 0x6b, 0x8a, 0x0e, 0x5a, 0xc0, 0x4c, 0x4f, 0xc2, 0x14, 0x84, 0x70, 0x79, 0x61, 0x14, 0xcc,
+    0xf0, 0xd7, 0x9e, 0x6f, 0x93, 0xbd, 0xad, 0x71, 0xf0, 0xc9, 0xb7, 0x30, 0x2a, 0x09, 0x4f, 0x31,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum DeviceManagerMessage {
+    /// Reports that a device has appeared on a bus. Emitted by the drivers of buses. Not
+    /// answered.
+    ///
+    /// The device is removed when the emitter terminates.
+    Report(DeviceDescription),
+    /// Reports that a device previously reported by the emitter has disappeared. Not answered.
+    Unreport { bus: Bus, location: String },
+    /// Request the list of devices. Answered with a [`ListResponse`].
+    List,
+    /// Subscribe to changes in the list of devices. Answered with a [`DeviceEvent`] each time
+    /// something changes, starting with a [`DeviceEvent::Added`] for each device already present.
+    /// The message is never answered otherwise. Cancel it to unsubscribe.
+    Subscribe,
+    /// Assign a device to the emitter, which is the driver of the device. Answered with a
+    /// [`ClaimResponse`].
+    ///
+    /// The device is released when the emitter terminates.
+    Claim {
+        /// Identifier of the device, as found in [`Device::id`].
+        id: u64,
+        /// Human-readable name of the driver.
+        driver: String,
+    },
+    /// Release a device previously claimed by the emitter. Not answered.
+    Release(u64),
+}
+
+/// Bus a device is connected to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum Bus {
+    Pci,
+    Usb,
+}
+
+/// Description of a device, as reported by the driver of its bus.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DeviceDescription {
+    pub bus: Bus,
+    /// Position of the device on the bus, in a format that depends on the bus. For example
+    /// `00:1f.3` for PCI. Unique within a bus.
+    pub location: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Class of the device, such as `0x2` for PCI network controllers.
+    pub class: u8,
+    /// Category of the device within its class.
+    pub subclass: u8,
+    /// Interface or protocol of the device within its subclass.
+    pub protocol: u8,
+}
+
+/// Device known to the device manager.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Device {
+    /// Identifier assigned by the device manager. Never reused.
+    pub id: u64,
+    pub description: DeviceDescription,
+    /// Name of the driver the device is assigned to, if any.
+    pub driver: Option<String>,
+}
+
+/// Change in the list of devices.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum DeviceEvent {
+    /// A device has appeared.
+    Added(Device),
+    /// A device has disappeared. Its driver, if any, should stop using it.
+    Removed(Device),
+    /// A device has been assigned to a driver or released by its driver.
+    DriverChanged(Device),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListResponse {
+    pub devices: Vec<Device>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ClaimResponse {
+    pub result: Result<(), ErrorPayload>,
+}
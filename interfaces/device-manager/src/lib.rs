@@ -0,0 +1,128 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Device hot-plugging.
+//!
+//! The drivers of buses, such as PCI or USB, report the devices that appear and disappear with
+//! [`report`] and [`unreport`]. Device drivers and other programs can then list the devices
+//! with [`list`], or watch for changes with [`events`], in order to react to hardware being
+//! plugged in after boot.
+//!
+//! A driver that takes care of a device calls [`claim`], so that other drivers know that the
+//! device is already handled.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::{Bus, Device, DeviceDescription, DeviceEvent};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Reports that a device has appeared on a bus.
+pub fn report(description: DeviceDescription) {
+    unsafe {
+        let msg = ffi::DeviceManagerMessage::Report(description);
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
+
+/// Reports that a device previously passed to [`report`] has disappeared.
+pub fn unreport(bus: Bus, location: String) {
+    unsafe {
+        let msg = ffi::DeviceManagerMessage::Unreport { bus, location };
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
+
+/// Returns the list of devices currently present.
+pub fn list() -> impl Future<Output = Vec<Device>> {
+    unsafe {
+        let msg = ffi::DeviceManagerMessage::List;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|rep: ffi::ListResponse| rep.devices)
+    }
+}
+
+/// Assigns the device with the given identifier to the current process.
+///
+/// Returns an error if the device doesn't exist or is already assigned to another driver.
+pub fn claim(id: u64, driver: String) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::DeviceManagerMessage::Claim { id, driver };
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ClaimResponse| rep.result)
+}
+
+/// Releases a device previously claimed with [`claim`].
+pub fn release(id: u64) {
+    unsafe {
+        let msg = ffi::DeviceManagerMessage::Release(id);
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
+
+/// Returns a `Stream` of changes in the list of devices. The stream first yields a
+/// [`DeviceEvent::Added`] for each device already present. The subscription is cancelled when
+/// the [`Events`] is destroyed.
+pub fn events() -> Events {
+    let msg_id = unsafe {
+        let msg = ffi::DeviceManagerMessage::Subscribe.encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    Events {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of changes in the list of devices.
+///
+/// See [`events`].
+pub struct Events {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for Events {
+    type Item = DeviceEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{InterfaceHash, Pid};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xf9, 0x50, 0x00, 0x60, 0x1f, 0x13, 0x29, 0x87, 0x93, 0xa5, 0xe3, 0x14, 0x07, 0xd0, 0x4d, 0xf5,
+    0xc6, 0x20, 0xe7, 0xcb, 0xc0, 0x76, 0xad, 0xde, 0x5f, 0x32, 0x29, 0x99, 0xb1, 0x97, 0xeb, 0xf6,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum DiagnosticsMessage {
+    /// Queries the entries of the kernel log that match a filter. Must be answered with a
+    /// [`QueryResponse`].
+    Query(Filter),
+    /// Asks to be notified of the entries added to the kernel log that match a filter. The
+    /// handler must send a partial answer containing an [`Entry`] for each new entry, and never
+    /// sends a final answer. The message must be cancelled in order to stop the notifications.
+    Follow(Filter),
+}
+
+/// Criteria that entries must match.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Filter {
+    /// Only entries whose sequence number is superior or equal to this value match.
+    pub since: u64,
+    /// If not empty, only entries of one of these kinds match.
+    pub kinds: Vec<EntryKind>,
+    /// If `Some`, only entries concerning this process match.
+    pub pid: Option<Pid>,
+}
+
+impl Filter {
+    /// Returns true if the entry matches the filter.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        entry.sequence >= self.since
+            && (self.kinds.is_empty() || self.kinds.contains(&entry.kind))
+            && self.pid.map_or(true, |pid| entry.pid == Some(pid))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct QueryResponse {
+    /// Sequence number of the oldest entry still in the log. The log has a limited size, and
+    /// older entries have been discarded.
+    pub oldest: u64,
+    /// Entries that match the filter, from the oldest to the newest.
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Entry {
+    /// Number of entries that have been added to the log before this one.
+    pub sequence: u64,
+    /// Value of the monotonic clock when the entry has been added, in nanoseconds. `None` if
+    /// the kernel has no clock.
+    pub timestamp_ns: Option<u64>,
+    pub kind: EntryKind,
+    /// Process that the entry concerns, if any.
+    pub pid: Option<Pid>,
+    /// Human-readable description of the event.
+    pub message: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum EntryKind {
+    /// Message emitted by the kernel while starting up.
+    Boot,
+    /// A process has started.
+    ProcessStarted,
+    /// A process has stopped gracefully.
+    ProcessExited,
+    /// A process has stopped because of an error. The message contains the reason.
+    ProcessCrashed,
+    /// A process has registered an interface.
+    InterfaceRegistered,
+    /// An interface is no longer registered, because its handler has stopped.
+    InterfaceUnregistered,
+    /// Messages have been discarded without being answered, for example because their handler
+    /// has stopped.
+    MessagesDropped,
+}
@@ -0,0 +1,89 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Kernel diagnostics.
+//!
+//! This interface is handled by the kernel itself. The kernel keeps a log of the important
+//! events that happen in the system, such as the messages printed while booting, processes
+//! stopping and the reason why, or interfaces being registered. This log can be retrieved with
+//! [`query`], and new entries can be watched with [`follow`].
+//!
+//! The log has a limited size, and the oldest entries are discarded when it is full.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::{Entry, EntryKind, Filter};
+
+pub mod ffi;
+
+/// Returns the entries of the kernel log that match the filter.
+pub fn query(filter: Filter) -> impl Future<Output = ffi::QueryResponse> {
+    unsafe {
+        let msg = ffi::DiagnosticsMessage::Query(filter);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Returns a `Stream` of the entries added to the kernel log that match the filter.
+///
+/// Only the entries added after this function has been called are returned. Use [`query`] in
+/// order to retrieve the existing entries.
+pub fn follow(filter: Filter) -> Follow {
+    let msg_id = unsafe {
+        let msg = ffi::DiagnosticsMessage::Follow(filter).encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    Follow {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of new entries of the kernel log. Stops following the log when destroyed.
+///
+/// See [`follow`].
+pub struct Follow {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for Follow {
+    type Item = Entry;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Follow {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
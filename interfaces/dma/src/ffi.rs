@@ -0,0 +1,67 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xca, 0x27, 0xeb, 0x52, 0x83, 0x25, 0x3a, 0xdc, 0x95, 0x43, 0xa6, 0x1b, 0xc6, 0x88, 0xca, 0x76,
+    0xf5, 0x82, 0x47, 0xd4, 0xa9, 0xaf, 0xf1, 0xb7, 0xef, 0xe7, 0xb6, 0xf1, 0x15, 0xc3, 0x90, 0xe3,
+]);
+
+/// Message sent to the handler of the DMA interface.
+///
+/// Allocations are identified by a number chosen by the handler. An allocation can only be
+/// freed by the process that has created it, and is freed automatically when that process
+/// terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum DmaMessage {
+    /// Allocates a buffer. Answered with an [`AllocateResponse`].
+    Allocate(Allocate),
+    /// Frees a buffer previously allocated. No answer is expected.
+    Free(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Allocate {
+    /// Size of the buffer in bytes. Must be non-zero.
+    pub size: u64,
+    /// Alignment of the buffer, in bytes. Must be a power of two.
+    pub alignment: u64,
+    /// If `Some`, the last byte of the buffer, as seen by the device, must not be above this
+    /// value. Used for devices that can only address 32 bits, for example.
+    pub max_device_address: Option<u64>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct AllocateResponse {
+    pub result: Result<Allocation, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Allocation {
+    /// Identifier to pass when freeing the buffer.
+    pub id: u64,
+    /// Address of the buffer in physical memory, to use with the `hardware` interface.
+    ///
+    /// The buffer is physically contiguous and initially filled with zeroes.
+    pub physical_address: u64,
+    /// Address of the buffer to pass to devices.
+    ///
+    /// This is currently always equal to `physical_address`, but will differ once an IOMMU is
+    /// in use.
+    pub device_address: u64,
+}
@@ -0,0 +1,127 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Allocation of memory accessible by devices.
+//!
+//! Devices that perform direct memory accesses (DMA), such as network cards or disk
+//! controllers, need buffers that are physically contiguous and whose address is known. A
+//! [`DmaBuffer`] is such a buffer. Its content can be accessed through the `hardware`
+//! interface.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::convert::TryFrom as _;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Buffer in physical memory accessible by devices. Freed when destroyed.
+pub struct DmaBuffer {
+    id: u64,
+    physical_address: u64,
+    device_address: u64,
+    size: u64,
+}
+
+impl DmaBuffer {
+    /// Allocates a buffer of `size` bytes, aligned to `alignment` bytes.
+    pub async fn new(size: u64, alignment: u64) -> Result<DmaBuffer, ErrorPayload> {
+        DmaBuffer::with_max_device_address(size, alignment, None).await
+    }
+
+    /// Same as [`DmaBuffer::new`], but the buffer must be addressable by the device with
+    /// addresses inferior or equal to `max_device_address`.
+    pub async fn with_max_device_address(
+        size: u64,
+        alignment: u64,
+        max_device_address: Option<u64>,
+    ) -> Result<DmaBuffer, ErrorPayload> {
+        let msg = ffi::DmaMessage::Allocate(ffi::Allocate {
+            size,
+            alignment,
+            max_device_address,
+        });
+        let response: ffi::AllocateResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        let allocation = response.result?;
+        Ok(DmaBuffer {
+            id: allocation.id,
+            physical_address: allocation.physical_address,
+            device_address: allocation.device_address,
+            size,
+        })
+    }
+
+    /// Returns the address of the buffer in physical memory.
+    pub fn physical_address(&self) -> u64 {
+        self.physical_address
+    }
+
+    /// Returns the address of the buffer to pass to devices.
+    pub fn device_address(&self) -> u64 {
+        self.device_address
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// Writes `data` in the buffer, starting at `offset`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the data doesn't fit in the buffer.
+    ///
+    pub fn write(&self, offset: u64, data: impl Into<Vec<u8>>) {
+        let data = data.into();
+        assert!(offset + u64::try_from(data.len()).unwrap() <= self.size);
+        unsafe { redshirt_hardware_interface::write(self.physical_address + offset, data) }
+    }
+
+    /// Reads `out.len()` bytes from the buffer, starting at `offset`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the requested range doesn't fit in the buffer.
+    ///
+    pub async fn read(&self, offset: u64, mut out: &mut [u8]) {
+        assert!(offset + u64::try_from(out.len()).unwrap() <= self.size);
+        let mut builder = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+        unsafe {
+            builder.read(self.physical_address + offset, &mut out);
+        }
+        builder.send().await;
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::DmaMessage::Free(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
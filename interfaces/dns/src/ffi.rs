@@ -0,0 +1,88 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xd0, 0xf3, 0x7f, 0x05, 0x3a, 0x83, 0x27, 0xbf, 0x0c, 0xc6, 0x9d, 0x78, 0x14, 0xe1, 0x90, 0xae,
+    0x6e, 0x03, 0x85, 0xf5, 0x7c, 0x6f, 0x52, 0xdb, 0x62, 0x20, 0x2a, 0xde, 0xa4, 0x98, 0x20, 0xd4,
+]);
+
+/// Message sent to the handler of the DNS interface.
+#[derive(Debug, Encode, Decode)]
+pub enum DnsMessage {
+    /// Resolve a domain name. Answered with a [`ResolveResponse`].
+    Resolve(Resolve),
+    /// Replace the list of DNS servers that are queried. No answer is expected.
+    SetServers(Vec<Server>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Resolve {
+    /// Domain name to resolve, for example `example.com`.
+    pub name: String,
+    pub record_type: RecordType,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ResolveResponse {
+    /// On success, contains the records found in the answer. If the name is an alias, the
+    /// answer contains the [`RecordData::Cname`] records followed with the records of the
+    /// requested type.
+    ///
+    /// Contains an error with the `NOT_FOUND` class if the name doesn't exist, and with the
+    /// `TIMED_OUT` class if no server has answered.
+    pub result: Result<Vec<Record>, ErrorPayload>,
+}
+
+/// Address of a DNS server.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Server {
+    /// IPv4 addresses are represented as IPv4-mapped IPv6 addresses.
+    pub ip: [u16; 8],
+    pub port: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Ns,
+    Txt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Record {
+    /// Domain name the record concerns.
+    pub name: String,
+    /// Number of seconds during which the record can be cached.
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum RecordData {
+    A([u8; 4]),
+    Aaaa([u16; 8]),
+    Cname(String),
+    Mx { preference: u16, exchange: String },
+    Ns(String),
+    Txt(Vec<Vec<u8>>),
+}
@@ -0,0 +1,56 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Domain name resolution.
+//!
+//! Call [`resolve`] to query the records of a domain name. The handler of this interface takes
+//! care of caching the results.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::{Record, RecordData, RecordType, Server};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Resolves the records of the given type of a domain name.
+pub fn resolve(
+    name: impl Into<String>,
+    record_type: RecordType,
+) -> impl Future<Output = Result<Vec<Record>, ErrorPayload>> {
+    let msg = ffi::DnsMessage::Resolve(ffi::Resolve {
+        name: name.into(),
+        record_type,
+    });
+
+    let response = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ResolveResponse| rep.result)
+}
+
+/// Replaces the list of DNS servers used by the handler.
+pub fn set_servers(servers: Vec<Server>) {
+    unsafe {
+        let msg = ffi::DnsMessage::SetServers(servers);
+        let _ = redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+    }
+}
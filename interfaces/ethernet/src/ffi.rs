@@ -0,0 +1,55 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x5a, 0x2c, 0x80, 0xbe, 0x16, 0x48, 0xc3, 0xaf, 0xe0, 0x0d, 0xa5, 0x04, 0xb6, 0xb4, 0x9f, 0x9d,
+    0x27, 0x0c, 0x15, 0x55, 0x92, 0x10, 0xfd, 0x65, 0xf3, 0x20, 0x17, 0x97, 0x54, 0x8f, 0xc3, 0x4f,
+]);
+
+/// Message sent by a network card driver to the handler of the Ethernet interface, normally the
+/// network manager.
+///
+/// Network interfaces are identified by the PID of the driver and by an identifier chosen by the
+/// driver. They are unregistered automatically when the driver terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum NetworkMessage {
+    /// Registers a new network interface. No answer is expected.
+    RegisterInterface {
+        /// Identifier of the interface. Must be unique among the interfaces of the driver.
+        id: u64,
+        /// MAC address of the network card.
+        mac_address: [u8; 6],
+    },
+    /// Unregisters an interface previously registered. No answer is expected.
+    UnregisterInterface(u64),
+    /// Notifies of an Ethernet frame, without its CRC, received from the network. No answer is
+    /// expected.
+    InterfaceOnData {
+        /// Interface the frame has been received on.
+        id: u64,
+        /// The frame.
+        data: Vec<u8>,
+    },
+    /// Asks for the next Ethernet frame, without its CRC, to send to the network on the given
+    /// interface. Answered with a `Vec<u8>` containing the frame once one is available.
+    ///
+    /// For each interface, only one such message can exist at any given point in time.
+    InterfaceWaitData(u64),
+}
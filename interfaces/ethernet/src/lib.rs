@@ -0,0 +1,87 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Ethernet networking.
+//!
+//! This interface is used by network card drivers in order to expose their network cards to the
+//! network manager, which runs the TCP/IP stack. Drivers call [`register_interface`] for each of
+//! their cards, then forward the frames received from the network with
+//! [`NetInterfaceRegistration::packet_from_network`] and send out the frames returned by
+//! [`NetInterfaceRegistration::packet_to_send`].
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use futures::prelude::*;
+
+pub mod ffi;
+
+/// Registers a new network interface towards the network manager.
+///
+/// The interface is unregistered when the returned [`NetInterfaceRegistration`] is destroyed.
+pub async fn register_interface(mac_address: [u8; 6]) -> NetInterfaceRegistration {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    unsafe {
+        let msg = ffi::NetworkMessage::RegisterInterface { id, mac_address };
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg).unwrap();
+    }
+
+    NetInterfaceRegistration { id }
+}
+
+/// Network interface registered towards the network manager.
+pub struct NetInterfaceRegistration {
+    /// Identifier of the interface, as passed in the messages.
+    id: u64,
+}
+
+impl NetInterfaceRegistration {
+    /// Transmits to the network manager an Ethernet frame, without its CRC, that has been
+    /// received from the network.
+    pub fn packet_from_network(&self, data: Vec<u8>) {
+        unsafe {
+            let msg = ffi::NetworkMessage::InterfaceOnData { id: self.id, data };
+            redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg)
+                .unwrap();
+        }
+    }
+
+    /// Waits for the network manager to provide an Ethernet frame, without its CRC, to send to
+    /// the network.
+    ///
+    /// Only one such future can exist at any given point in time.
+    pub fn packet_to_send(&self) -> impl Future<Output = Vec<u8>> {
+        unsafe {
+            let msg = ffi::NetworkMessage::InterfaceWaitData(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        }
+    }
+}
+
+impl Drop for NetInterfaceRegistration {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::NetworkMessage::UnregisterInterface(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
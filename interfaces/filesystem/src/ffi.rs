@@ -0,0 +1,180 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x09, 0x0c, 0x20, 0x30, 0x11, 0x11, 0x07, 0x02, 0x16, 0x49, 0x05, 0x33, 0x06, 0x07, 0x26, 0x0c,
+    0x18, 0x3f, 0x11, 0x08, 0x31, 0x0b, 0x14, 0x04, 0x33, 0x2b, 0x3c, 0x3a, 0x21, 0x34, 0x20, 0x51,
+]);
+
+/// Message sent to the handler of the filesystem interface.
+///
+/// Paths are always absolute and use `/` as separator.
+///
+/// Files are accessed through handles obtained with [`FilesystemMessage::Open`]. Handles are
+/// specific to the process that opened them, and are automatically closed when that process
+/// terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum FilesystemMessage {
+    /// Opens the file at the given path. Answered with an [`OpenResponse`].
+    Open(Open),
+    /// Reads data from the current position of an open file and advances the position.
+    /// Answered with a [`ReadResponse`].
+    Read(Read),
+    /// Writes data at the current position of an open file and advances the position. The file
+    /// is extended if necessary. Answered with a [`WriteResponse`].
+    Write(Write),
+    /// Sets the current position of an open file. Answered with a [`SeekResponse`].
+    Seek(Seek),
+    /// Closes an open file. No answer is expected.
+    Close(Close),
+    /// Creates a directory. The parent must already exist. Answered with a
+    /// [`CreateDirResponse`].
+    CreateDir(CreateDir),
+    /// Lists the content of a directory. Answered with a [`ReadDirResponse`].
+    ReadDir(ReadDir),
+    /// Queries information about a file or directory. Answered with a [`MetadataResponse`].
+    Metadata(MetadataRequest),
+    /// Asks the virtual filesystem to route the requests concerning paths under a prefix to the
+    /// emitter of this message. Answered with a [`MountResponse`].
+    ///
+    /// The emitter must accept directed messages. Requests are then delivered to it as directed
+    /// messages with the same format as this enum, and paths relative to the prefix.
+    ///
+    /// Handlers other than the virtual filesystem answer with an error.
+    Mount(Mount),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Open {
+    pub path: String,
+    /// If true, the file is created if it doesn't exist.
+    pub create: bool,
+    /// If true, the content of the file is erased.
+    pub truncate: bool,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct OpenResponse {
+    /// On success, the handle of the file.
+    pub result: Result<u64, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Read {
+    pub handle: u64,
+    /// Maximum number of bytes to read.
+    pub len: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadResponse {
+    /// On success, the data that has been read. Empty if the end of the file has been reached.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Write {
+    pub handle: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Seek {
+    pub handle: u64,
+    /// New position, in bytes, from the start of the file. Can be past the end of the file, in
+    /// which case the next write fills the gap with zeroes.
+    pub position: u64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SeekResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Close {
+    pub handle: u64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateDir {
+    pub path: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateDirResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadDir {
+    pub path: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadDirResponse {
+    pub result: Result<Vec<DirEntry>, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DirEntry {
+    /// Name of the entry, without the path of the directory.
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct MetadataRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct MetadataResponse {
+    pub result: Result<Metadata, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Metadata {
+    pub kind: EntryKind,
+    /// Size of the file in bytes. Always 0 for directories.
+    pub len: u64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Mount {
+    /// Path where to mount the filesystem, for example `/mem`. Can be `/` to mount the root.
+    pub prefix: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct MountResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
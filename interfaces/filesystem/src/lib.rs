@@ -0,0 +1,190 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Accessing files.
+//!
+//! The handler of this interface is responsible for storing files and directories. Programs
+//! open files with [`File::open`] or [`File::create`], and manipulate directories with
+//! [`create_dir`], [`read_dir`] and [`metadata`].
+//!
+//! All the functions of this module return an [`ErrorPayload`] on failure. Most notably, the
+//! [`ErrorClass::NOT_FOUND`] class is used when a path doesn't exist.
+//!
+//! # Providers
+//!
+//! The handler of this interface is normally the virtual filesystem, which doesn't store
+//! anything by itself. Processes that store files call [`mount`] in order to be in charge of
+//! all the paths under a certain prefix, then receive requests as directed messages.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+
+pub mod ffi;
+
+/// Open file.
+///
+/// The file is closed when this object is destroyed.
+#[cfg(feature = "std")]
+pub struct File {
+    handle: u64,
+}
+
+#[cfg(feature = "std")]
+impl File {
+    /// Opens the existing file at the given path.
+    pub async fn open(path: impl Into<String>) -> Result<File, ErrorPayload> {
+        File::open_inner(path.into(), false, false).await
+    }
+
+    /// Opens the file at the given path, creating it if it doesn't exist, and erases its
+    /// content.
+    pub async fn create(path: impl Into<String>) -> Result<File, ErrorPayload> {
+        File::open_inner(path.into(), true, true).await
+    }
+
+    async fn open_inner(path: String, create: bool, truncate: bool) -> Result<File, ErrorPayload> {
+        let msg = ffi::FilesystemMessage::Open(ffi::Open {
+            path,
+            create,
+            truncate,
+        });
+        let rep: ffi::OpenResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        Ok(File {
+            handle: rep.result?,
+        })
+    }
+
+    /// Reads at most `len` bytes from the current position. Returns an empty buffer if the end
+    /// of the file has been reached.
+    pub async fn read(&mut self, len: u32) -> Result<Vec<u8>, ErrorPayload> {
+        let msg = ffi::FilesystemMessage::Read(ffi::Read {
+            handle: self.handle,
+            len,
+        });
+        let rep: ffi::ReadResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+
+    /// Writes `data` at the current position.
+    pub async fn write(&mut self, data: impl Into<Vec<u8>>) -> Result<(), ErrorPayload> {
+        let msg = ffi::FilesystemMessage::Write(ffi::Write {
+            handle: self.handle,
+            data: data.into(),
+        });
+        let rep: ffi::WriteResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+
+    /// Sets the current position, in bytes from the start of the file.
+    pub async fn seek(&mut self, position: u64) -> Result<(), ErrorPayload> {
+        let msg = ffi::FilesystemMessage::Seek(ffi::Seek {
+            handle: self.handle,
+            position,
+        });
+        let rep: ffi::SeekResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        rep.result
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::FilesystemMessage::Close(ffi::Close {
+                handle: self.handle,
+            });
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg);
+        }
+    }
+}
+
+/// Creates a directory at the given path. The parent directory must already exist.
+#[cfg(feature = "std")]
+pub async fn create_dir(path: impl Into<String>) -> Result<(), ErrorPayload> {
+    let msg = ffi::FilesystemMessage::CreateDir(ffi::CreateDir { path: path.into() });
+    let rep: ffi::CreateDirResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .await
+    };
+    rep.result
+}
+
+/// Returns the list of entries of the directory at the given path.
+#[cfg(feature = "std")]
+pub async fn read_dir(path: impl Into<String>) -> Result<Vec<ffi::DirEntry>, ErrorPayload> {
+    let msg = ffi::FilesystemMessage::ReadDir(ffi::ReadDir { path: path.into() });
+    let rep: ffi::ReadDirResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .await
+    };
+    rep.result
+}
+
+/// Returns information about the file or directory at the given path.
+#[cfg(feature = "std")]
+pub async fn metadata(path: impl Into<String>) -> Result<ffi::Metadata, ErrorPayload> {
+    let msg = ffi::FilesystemMessage::Metadata(ffi::MetadataRequest { path: path.into() });
+    let rep: ffi::MetadataResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .await
+    };
+    rep.result
+}
+
+/// Asks the virtual filesystem to route the requests concerning paths under `prefix` to the
+/// current process.
+///
+/// On success, the requests are received through
+/// [`next_interface_message`](redshirt_syscalls_interface::next_interface_message), as directed
+/// messages that decode to [`ffi::FilesystemMessage`]. Paths in these messages are relative to
+/// `prefix`.
+#[cfg(feature = "std")]
+pub async fn mount(prefix: impl Into<String>) -> Result<(), ErrorPayload> {
+    redshirt_syscalls_interface::accept_directed_messages();
+
+    let msg = ffi::FilesystemMessage::Mount(ffi::Mount {
+        prefix: prefix.into(),
+    });
+    let rep: ffi::MountResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .await
+    };
+    rep.result
+}
@@ -0,0 +1,114 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x36, 0xaa, 0x61, 0x5d, 0x09, 0xb6, 0x5e, 0x47, 0x9f, 0xcd, 0xb9, 0xcb, 0x25, 0xc5, 0x2e, 0x5e,
+    0xb1, 0xe7, 0xcc, 0xa6, 0xba, 0xbd, 0xc2, 0x79, 0xe4, 0x65, 0x65, 0x12, 0x49, 0xb6, 0xaf, 0x1c,
+]);
+
+/// Message sent to the handler of the firewall interface, normally the network manager.
+#[derive(Debug, Encode, Decode)]
+pub enum FirewallMessage {
+    /// Replaces the rules and resets the counters. No answer is expected.
+    SetRules(Ruleset),
+    /// Asks for the rules and their counters. Answered with a [`GetRulesResponse`].
+    GetRules,
+}
+
+/// Rules applied to the IP packets exchanged with the network.
+///
+/// Each packet is checked against the rules in order, and the action of the first rule that
+/// matches is applied. Packets that don't match any rule get the default action. Frames that
+/// don't contain an IP packet, such as ARP frames, are always allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Ruleset {
+    pub rules: Vec<Rule>,
+    pub default_action: Action,
+}
+
+/// Rule of a [`Ruleset`]. Each field that is `Some` must match for the rule to apply.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Rule {
+    pub action: Action,
+    pub direction: Option<Direction>,
+    /// MAC address of the network interface the packet goes through.
+    pub interface: Option<[u8; 6]>,
+    pub protocol: Option<Protocol>,
+    /// Port on the side of this machine. Packets without ports, such as ICMP, never match.
+    pub local_port: Option<PortRange>,
+    /// Port on the side of the other machine. Packets without ports never match.
+    pub remote_port: Option<PortRange>,
+    /// Range the address of the other machine belongs to.
+    pub remote_address: Option<AddressRange>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Direction {
+    /// Packet received from the network.
+    Inbound,
+    /// Packet sent to the network.
+    Outbound,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    /// ICMP for IPv4 packets, ICMPv6 for IPv6 packets.
+    Icmp,
+}
+
+/// Inclusive range of ports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PortRange {
+    pub first: u16,
+    pub last: u16,
+}
+
+/// Range of IP addresses, in CIDR notation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AddressRange {
+    /// IPv4 addresses are represented as IPv4-mapped IPv6 addresses.
+    pub ip: [u16; 8],
+    /// Number of leading bits of `ip` that addresses must share. For IPv4 addresses, this
+    /// includes the 96 bits of the IPv4-mapped prefix.
+    pub prefix_len: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct GetRulesResponse {
+    pub ruleset: Ruleset,
+    pub counters: Counters,
+}
+
+/// Number of packets each rule has been applied to since the rules have been set.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Counters {
+    /// Same order as [`Ruleset::rules`].
+    pub rules: Vec<u64>,
+    /// Packets that haven't matched any rule.
+    pub default_action: u64,
+}
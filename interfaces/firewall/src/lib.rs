@@ -0,0 +1,49 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Packet filtering.
+//!
+//! The handler of this interface, normally the network manager, filters the IP packets exchanged
+//! between the network cards and the TCP/IP stack. Call [`set_rules`] to allow or deny packets
+//! depending on their network interface, protocol, ports and addresses, and [`rules`] to
+//! retrieve the rules along with the number of packets that each of them has matched.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use futures::prelude::*;
+
+pub use ffi::{Action, AddressRange, Counters, Direction, PortRange, Protocol, Rule, Ruleset};
+
+pub mod ffi;
+
+/// Replaces the rules applied to the packets, and resets the counters.
+pub fn set_rules(ruleset: Ruleset) {
+    unsafe {
+        let msg = ffi::FirewallMessage::SetRules(ruleset);
+        let _ = redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+    }
+}
+
+/// Returns the rules currently applied to the packets and their counters.
+pub fn rules() -> impl Future<Output = (Ruleset, Counters)> {
+    let response = unsafe {
+        let msg = ffi::FirewallMessage::GetRules;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::GetRulesResponse| (rep.ruleset, rep.counters))
+}
@@ -0,0 +1,102 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xc9, 0x57, 0xe5, 0x7d, 0x64, 0x63, 0x0a, 0xf9, 0xc2, 0x11, 0x8b, 0xc5, 0x70, 0xf3, 0xd0, 0x96,
+    0xa6, 0x80, 0xa2, 0x7a, 0x0d, 0x6c, 0xcc, 0xfe, 0xf2, 0x1e, 0x2e, 0x5e, 0xaf, 0xff, 0x7f, 0x69,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum FramebufferMessage {
+    /// Queries the characteristics of the framebuffer. Answered with a [`FramebufferInfo`].
+    Info,
+    /// Updates parts of the screen. Answered with a [`PresentResponse`] once the new pixels are
+    /// visible.
+    Present(Present),
+    /// Asks to be notified of vertical blanks. The handler sends a partial answer containing a
+    /// [`VsyncEvent`] at each vertical blank, and never sends a final answer. The message must be
+    /// cancelled in order to stop the notifications.
+    SubscribeVsync,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct FramebufferInfo {
+    /// Width of the framebuffer, in pixels.
+    pub width: u32,
+    /// Height of the framebuffer, in pixels.
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+/// Layout of a pixel in memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PixelFormat {
+    /// Four bytes per pixel: blue, green, red, then an unused byte.
+    B8G8R8X8,
+    /// Four bytes per pixel: red, green, blue, then an unused byte.
+    R8G8B8X8,
+    /// Two bytes per pixel, little endian: 5 bits of red, 6 bits of green, 5 bits of blue,
+    /// starting from the most significant bit.
+    R5G6B5,
+}
+
+impl PixelFormat {
+    /// Returns the number of bytes of a pixel.
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::B8G8R8X8 | PixelFormat::R8G8B8X8 => 4,
+            PixelFormat::R5G6B5 => 2,
+        }
+    }
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Present {
+    /// Regions of the screen that have changed, along with their new content. Regions must not
+    /// overlap and must be within the framebuffer. The rest of the screen is left untouched.
+    pub damage: Vec<Damage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Damage {
+    pub rect: Rect,
+    /// New pixels of the region, line by line, in the format of the framebuffer. Must contain
+    /// exactly `rect.width * rect.height` pixels.
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct PresentResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct VsyncEvent {
+    /// Number of vertical blanks since the framebuffer has been initialized.
+    pub frame: u64,
+}
@@ -0,0 +1,144 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Linear framebuffer.
+//!
+//! A framebuffer is a two-dimensional array of pixels displayed on the screen. Programs call
+//! [`info`] to learn its size and pixel format, then draw in their own buffer and send the
+//! parts that have changed with [`present`] or [`present_from_buffer`]. Only sending the
+//! regions that have changed, called the damage, avoids copying the entire screen at each
+//! frame.
+//!
+//! [`vsync_events`] can be used to synchronize drawing with the refresh rate of the screen.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the characteristics of the framebuffer.
+pub fn info() -> impl Future<Output = ffi::FramebufferInfo> {
+    unsafe {
+        let msg = ffi::FramebufferMessage::Info;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Updates the given regions of the screen. The `Future` is ready once the new pixels are
+/// visible.
+pub fn present(damage: Vec<ffi::Damage>) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::FramebufferMessage::Present(ffi::Present { damage });
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::PresentResponse| rep.result)
+}
+
+/// Same as [`present`], but extracts the content of the given rectangles from `buffer`, which
+/// must contain the whole screen, line by line, in the format described by `info`.
+///
+/// # Panic
+///
+/// Panics if `buffer` is too small or if a rectangle is out of the screen.
+///
+pub fn present_from_buffer(
+    info: &ffi::FramebufferInfo,
+    buffer: &[u8],
+    rects: &[ffi::Rect],
+) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let bpp = info.format.bytes_per_pixel() as usize;
+    let stride = info.width as usize * bpp;
+    assert!(buffer.len() >= stride * info.height as usize);
+
+    let damage = rects
+        .iter()
+        .map(|rect| {
+            assert!(rect
+                .x
+                .checked_add(rect.width)
+                .map_or(false, |r| r <= info.width));
+            assert!(rect
+                .y
+                .checked_add(rect.height)
+                .map_or(false, |b| b <= info.height));
+
+            let line_len = rect.width as usize * bpp;
+            let mut pixels = Vec::with_capacity(line_len * rect.height as usize);
+            for y in rect.y..(rect.y + rect.height) {
+                let start = y as usize * stride + rect.x as usize * bpp;
+                pixels.extend_from_slice(&buffer[start..start + line_len]);
+            }
+
+            ffi::Damage {
+                rect: *rect,
+                pixels,
+            }
+        })
+        .collect();
+
+    present(damage)
+}
+
+/// Returns a `Stream` that yields at each vertical blank. The subscription is cancelled when
+/// the [`VsyncEvents`] is destroyed.
+pub fn vsync_events() -> VsyncEvents {
+    let msg_id = unsafe {
+        let msg = ffi::FramebufferMessage::SubscribeVsync.encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    VsyncEvents {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of vertical blanks.
+///
+/// See [`vsync_events`].
+pub struct VsyncEvents {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for VsyncEvents {
+    type Item = ffi::VsyncEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for VsyncEvents {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
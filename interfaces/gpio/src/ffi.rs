@@ -0,0 +1,96 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x20, 0xfd, 0x56, 0xb3, 0x32, 0xb8, 0x32, 0x12, 0xa4, 0xe7, 0x75, 0x72, 0x44, 0xb7, 0x22, 0xc6,
+    0xd2, 0x67, 0x77, 0xd0, 0xbe, 0xe4, 0x3f, 0x93, 0x61, 0x44, 0x99, 0xfb, 0xab, 0x02, 0xf7, 0x60,
+]);
+
+/// Message sent to the handler of the GPIO interface.
+///
+/// Pins are identified by their number on the GPIO controller, from 0 to the value returned by
+/// [`GpioMessage::NumPins`] excluded.
+#[derive(Debug, Encode, Decode)]
+pub enum GpioMessage {
+    /// Returns the number of pins. Answered with a `u32`.
+    NumPins,
+    /// Changes the direction and the pull resistor of a pin. Answered with a
+    /// [`ConfigureResponse`].
+    Configure(Configure),
+    /// Sets the level of an output pin. Answered with a [`WriteResponse`].
+    Write { pin: u32, high: bool },
+    /// Reads the level of a pin. Answered with a [`ReadResponse`].
+    Read(u32),
+    /// Asks to be notified of the edges detected on the given input pin. The handler sends a
+    /// partial answer containing an [`EdgeEvent`] for each edge, and never sends a final answer.
+    /// The message must be cancelled in order to stop the notifications.
+    SubscribeEdges { pin: u32, edge: Edge },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Configure {
+    pub pin: u32,
+    pub direction: Direction,
+    pub pull: Pull,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// Internal resistor connected to the pin.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Pull {
+    None,
+    Down,
+    Up,
+}
+
+/// Edges to be notified of.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ConfigureResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadResponse {
+    /// On success, true if the pin is high.
+    pub result: Result<bool, ErrorPayload>,
+}
+
+/// Edge detected on a pin.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct EdgeEvent {
+    /// True for a rising edge, false for a falling edge.
+    pub rising: bool,
+}
@@ -0,0 +1,109 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! General-purpose input/output pins.
+//!
+//! Call [`configure`] to set a pin as an input or an output, then [`read`] or [`write`] its
+//! level. [`edge_events`] can be used to be notified when the level of an input pin changes.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::{Configure, Direction, Edge, EdgeEvent, Pull};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the number of pins of the GPIO controller.
+pub fn num_pins() -> impl Future<Output = u32> {
+    unsafe {
+        let msg = ffi::GpioMessage::NumPins;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Changes the direction and the pull resistor of a pin.
+pub fn configure(config: Configure) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::GpioMessage::Configure(config);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ConfigureResponse| rep.result)
+}
+
+/// Sets the level of an output pin.
+pub fn write(pin: u32, high: bool) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::GpioMessage::Write { pin, high };
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::WriteResponse| rep.result)
+}
+
+/// Reads the level of a pin. Returns true if the pin is high.
+pub fn read(pin: u32) -> impl Future<Output = Result<bool, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::GpioMessage::Read(pin);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ReadResponse| rep.result)
+}
+
+/// Returns a `Stream` that yields the edges detected on an input pin. The subscription is
+/// cancelled when the [`EdgeEvents`] is destroyed.
+pub fn edge_events(pin: u32, edge: Edge) -> EdgeEvents {
+    let msg_id = unsafe {
+        let msg = ffi::GpioMessage::SubscribeEdges { pin, edge }.encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    EdgeEvents {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of edges.
+///
+/// See [`edge_events`].
+pub struct EdgeEvents {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for EdgeEvents {
+    type Item = EdgeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for EdgeEvents {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
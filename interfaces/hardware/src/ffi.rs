@@ -15,7 +15,7 @@
 
 use alloc::vec::Vec;
 use parity_scale_codec::{Decode, Encode};
-use redshirt_syscalls_interface::InterfaceHash;
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash, Pid};
 
 // TODO: this has been randomly generated; instead should be a hash or something
 pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
@@ -50,15 +50,57 @@ pub enum HardwareMessage {
     /// If there is at least one memory or port read, the response must be a
     /// `Vec<HardwareAccessResponse>` where each element corresponds to a read. No response is
     /// expected if there are only writes.
+    ///
+    /// If the emitter isn't allowed to perform one of the operations (see
+    /// [`HardwareMessage::Grant`]), then none of the operations are performed and an error is
+    /// returned if a response is expected.
     // TODO: should we enforce some limits in the amount of data that can be returned in a response?
     HardwareAccess(Vec<Operation>),
 
     /// Ask the handler to send back a response when the interrupt with the given number is
     /// triggered.
     ///
-    /// > **Note**: If called with a non-hardware interrupt, no response will ever come back.
-    // TODO: how to not miss any interrupt? we instead need some registration system or something
+    /// Not supported. The handler answers with an error, and the `interrupts` interface must be
+    /// used instead.
+    // TODO: remove this variant
     InterruptWait(u32),
+
+    /// Grants a process access to a range of physical memory or ports, or the right to claim
+    /// interrupts. If a response is expected, it must be a [`GrantResponse`].
+    ///
+    /// Processes can only access the ranges they have been granted, the memory they have
+    /// allocated, and their DMA buffers, and can only claim interrupts if they have been granted
+    /// [`AccessRange::Interrupts`]. Only the processes trusted by the handler, normally the bus
+    /// drivers started by the kernel, have access to all the hardware and are allowed to grant.
+    ///
+    /// Grants are revoked when the grantee terminates.
+    Grant {
+        /// Process to grant access to.
+        pid: Pid,
+        range: AccessRange,
+    },
+}
+
+/// Response to a [`HardwareMessage::Grant`].
+pub type GrantResponse = Result<(), ErrorPayload>;
+
+/// Range of physical memory or of ports.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum AccessRange {
+    Memory {
+        /// Physical address of the first byte of the range.
+        start: u64,
+        /// Number of bytes in the range.
+        len: u64,
+    },
+    Ports {
+        /// First port of the range.
+        start: u32,
+        /// Number of ports in the range.
+        len: u32,
+    },
+    /// Right to claim interrupts through the `interrupts` interface.
+    Interrupts,
 }
 
 /// Request to perform accesses to physical memory or to ports.
@@ -53,6 +53,15 @@ impl HardwareWriteOperationsBuilder {
         });
     }
 
+    /// Writes a `u16` with a single memory access, in the platform's native endianess.
+    pub unsafe fn write_one_u16(&mut self, address: u64, data: u16) {
+        self.operations
+            .push(ffi::Operation::PhysicalMemoryWriteU16 {
+                address,
+                data: vec![data],
+            });
+    }
+
     pub unsafe fn write_one_u32(&mut self, address: u64, data: u32) {
         self.operations
             .push(ffi::Operation::PhysicalMemoryWriteU32 {
@@ -89,6 +98,20 @@ impl HardwareWriteOperationsBuilder {
     }
 }
 
+/// Grants the given process access to a range of physical memory or ports, or the right to
+/// claim interrupts.
+///
+/// Fails if the current process isn't allowed to grant. See [`ffi::HardwareMessage::Grant`].
+pub fn grant(
+    pid: redshirt_syscalls_interface::Pid,
+    range: ffi::AccessRange,
+) -> impl Future<Output = ffi::GrantResponse> {
+    unsafe {
+        let msg = ffi::HardwareMessage::Grant { pid, range };
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
 /// Writes the given data to the given physical memory address location.
 pub unsafe fn write(address: u64, data: impl Into<Vec<u8>>) {
     let mut builder = HardwareWriteOperationsBuilder::with_capacity(1);
@@ -157,6 +180,15 @@ impl<'a> HardwareOperationsBuilder<'a> {
         self.out.push(Out::MemReadU8(out));
     }
 
+    pub unsafe fn read_u16(&mut self, address: u64, out: &'a mut impl AsMut<[u16]>) {
+        let out = out.as_mut();
+        self.operations.push(ffi::Operation::PhysicalMemoryReadU16 {
+            address,
+            len: out.len() as u32, // TODO: don't use `as`
+        });
+        self.out.push(Out::MemReadU16(out));
+    }
+
     pub unsafe fn read_u32(&mut self, address: u64, out: &'a mut impl AsMut<[u32]>) {
         let out = out.as_mut();
         self.operations.push(ffi::Operation::PhysicalMemoryReadU32 {
@@ -173,6 +205,15 @@ impl<'a> HardwareOperationsBuilder<'a> {
         });
     }
 
+    /// Writes a `u16` with a single memory access, in the platform's native endianess.
+    pub unsafe fn write_one_u16(&mut self, address: u64, data: u16) {
+        self.operations
+            .push(ffi::Operation::PhysicalMemoryWriteU16 {
+                address,
+                data: vec![data],
+            });
+    }
+
     pub unsafe fn write_one_u32(&mut self, address: u64, data: u32) {
         self.operations
             .push(ffi::Operation::PhysicalMemoryWriteU32 {
@@ -0,0 +1,72 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xb5, 0xdd, 0x57, 0xca, 0x94, 0xd3, 0x38, 0x0d, 0xd4, 0x6b, 0x77, 0x2f, 0xaa, 0x39, 0xef, 0x44,
+    0x5e, 0x91, 0xa5, 0x9e, 0xd9, 0x19, 0x70, 0xb2, 0x7d, 0x02, 0xf4, 0xd3, 0x6a, 0x54, 0xad, 0x40,
+]);
+
+/// Message sent to the handler of the HTTP client interface.
+///
+/// Responses are identified by a number chosen by the handler, and are closed automatically
+/// when the process that has emitted the request terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum HttpClientMessage {
+    /// Send a request. Answered with a [`RequestResponse`] once the head of the response has
+    /// been received.
+    Request(Request),
+    /// Ask for the next part of the body of a response. Answered with a [`ReadBodyResponse`].
+    /// For each response, only one read can exist at any given point in time.
+    ReadBody(u32),
+    /// Close a response, interrupting the transfer of its body. No answer is expected.
+    Close(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Request {
+    /// For example `GET` or `POST`.
+    pub method: String,
+    /// Absolute URL, starting with `http://` or `https://`.
+    pub url: String,
+    /// Headers to add to the request. The `Host`, `Content-Length` and `Connection` headers are
+    /// added automatically.
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct RequestResponse {
+    pub result: Result<ResponseHead, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ResponseHead {
+    /// Identifier to use to read the body.
+    pub response_id: u32,
+    /// Status code, for example 200.
+    pub status: u16,
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadBodyResponse {
+    /// Next part of the body. Empty if the entire body has been read.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
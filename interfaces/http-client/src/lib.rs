@@ -0,0 +1,121 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP client.
+//!
+//! Call [`request`] to send a request. Once the head of the response has been received, the
+//! body can be read piece by piece with [`Response::read_body`].
+//!
+//! Redirections aren't followed automatically.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::Request;
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Response to a request. Closed when destroyed.
+pub struct Response {
+    id: u32,
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+}
+
+/// Sends a request and waits for the head of the response.
+pub async fn request(request: Request) -> Result<Response, ErrorPayload> {
+    let msg = ffi::HttpClientMessage::Request(request);
+    let response: ffi::RequestResponse = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .await
+    };
+
+    let head = response.result?;
+    Ok(Response {
+        id: head.response_id,
+        status: head.status,
+        headers: head.headers,
+    })
+}
+
+/// Sends a `GET` request to the given URL and waits for the head of the response.
+pub async fn get(url: impl Into<String>) -> Result<Response, ErrorPayload> {
+    request(Request {
+        method: "GET".into(),
+        url: url.into(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    })
+    .await
+}
+
+impl Response {
+    /// Returns the status code of the response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the headers of the response.
+    pub fn headers(&self) -> &[(String, Vec<u8>)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header with the given name, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Reads the next part of the body. Returns an empty `Vec` once the entire body has been
+    /// read.
+    pub fn read_body(&mut self) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::HttpClientMessage::ReadBody(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::ReadBodyResponse| rep.result)
+    }
+
+    /// Reads the rest of the body.
+    pub async fn read_body_to_end(&mut self) -> Result<Vec<u8>, ErrorPayload> {
+        let mut out = Vec::new();
+        loop {
+            let data = self.read_body().await?;
+            if data.is_empty() {
+                return Ok(out);
+            }
+            out.extend_from_slice(&data);
+        }
+    }
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::HttpClientMessage::Close(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
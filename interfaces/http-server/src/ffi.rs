@@ -0,0 +1,77 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x5a, 0xfc, 0xaf, 0xaa, 0xab, 0x73, 0x47, 0x7a, 0xbc, 0x11, 0xa6, 0xe6, 0xab, 0xfe, 0xb3, 0x55,
+    0x1b, 0x88, 0xc3, 0x74, 0xb8, 0x0b, 0x98, 0xb8, 0x98, 0xd9, 0x57, 0xae, 0x25, 0x20, 0xe3, 0xb4,
+]);
+
+/// Message sent to the handler of the HTTP server interface.
+///
+/// Routes and requests are identified by numbers chosen by the handler. Routes are unregistered
+/// automatically when the process that has registered them terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum HttpServerMessage {
+    /// Register a path prefix, such as `/metrics`. Requests whose path starts with this prefix
+    /// are delivered to the emitter. If multiple prefixes match, the longest one wins.
+    /// Answered with a [`RegisterResponse`].
+    Register(String),
+    /// Unregister a route. Requests that haven't been answered yet get a 503 response. No answer
+    /// is expected.
+    Unregister(u32),
+    /// Wait for the next request on a route. Answered with a [`NextRequestResponse`]. For each
+    /// route, only one such message can exist at any given point in time.
+    NextRequest(u32),
+    /// Respond to a request. No answer is expected.
+    Respond(Response),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct RegisterResponse {
+    /// On success, the identifier of the route.
+    pub result: Result<u32, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct NextRequestResponse {
+    pub result: Result<Request, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Request {
+    /// Identifier to pass back in the [`Response`].
+    pub request_id: u64,
+    /// For example `GET` or `POST`.
+    pub method: String,
+    /// Path and query of the request, including the prefix of the route.
+    pub path: String,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Response {
+    pub request_id: u64,
+    /// Status code, for example 200.
+    pub status: u16,
+    /// Headers of the response. The `Content-Length` header is added automatically.
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
@@ -0,0 +1,146 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP server.
+//!
+//! Call [`Route::register`] to receive the requests whose path starts with a certain prefix,
+//! then [`Route::next_request`] to wait for a request. Each request must be answered with
+//! [`Request::respond`].
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Registered route. Unregistered when destroyed.
+pub struct Route {
+    id: u32,
+}
+
+impl Route {
+    /// Registers a path prefix, such as `/metrics`.
+    pub async fn register(prefix: impl Into<String>) -> Result<Route, ErrorPayload> {
+        let msg = ffi::HttpServerMessage::Register(prefix.into());
+        let response: ffi::RegisterResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        Ok(Route {
+            id: response.result?,
+        })
+    }
+
+    /// Waits for the next request on this route.
+    pub fn next_request(&mut self) -> impl Future<Output = Result<Request, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::HttpServerMessage::NextRequest(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::NextRequestResponse| {
+            rep.result.map(|inner| Request {
+                inner,
+                responded: false,
+            })
+        })
+    }
+}
+
+impl Drop for Route {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::HttpServerMessage::Unregister(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
+
+/// Request received on a route. Answered with a 500 status code if destroyed without calling
+/// [`Request::respond`].
+pub struct Request {
+    inner: ffi::Request,
+    responded: bool,
+}
+
+impl Request {
+    /// Returns the method of the request, for example `GET`.
+    pub fn method(&self) -> &str {
+        &self.inner.method
+    }
+
+    /// Returns the path and query of the request, including the prefix of the route.
+    pub fn path(&self) -> &str {
+        &self.inner.path
+    }
+
+    /// Returns the headers of the request.
+    pub fn headers(&self) -> &[(String, Vec<u8>)] {
+        &self.inner.headers
+    }
+
+    /// Returns the value of the first header with the given name, case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.inner
+            .headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Returns the body of the request.
+    pub fn body(&self) -> &[u8] {
+        &self.inner.body
+    }
+
+    /// Sends back a response.
+    pub fn respond(
+        mut self,
+        status: u16,
+        headers: Vec<(String, Vec<u8>)>,
+        body: impl Into<Vec<u8>>,
+    ) {
+        self.responded = true;
+        send_response(self.inner.request_id, status, headers, body.into());
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if !self.responded {
+            send_response(self.inner.request_id, 500, Vec::new(), Vec::new());
+        }
+    }
+}
+
+fn send_response(request_id: u64, status: u16, headers: Vec<(String, Vec<u8>)>, body: Vec<u8>) {
+    unsafe {
+        let msg = ffi::HttpServerMessage::Respond(ffi::Response {
+            request_id,
+            status,
+            headers,
+            body,
+        });
+        let _ = redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x25, 0x06, 0x6a, 0x6c, 0xc9, 0x59, 0x32, 0x4f, 0x44, 0x46, 0xf3, 0x0f, 0xf7, 0xde, 0xd7, 0x34,
+    0x2a, 0x67, 0xd8, 0x32, 0x1a, 0x8f, 0x5e, 0xa5, 0x0c, 0x8a, 0xfb, 0xee, 0xda, 0xf2, 0xbf, 0x66,
+]);
+
+/// Message sent to the handler of the I2C interface.
+///
+/// Buses are identified by a number chosen by the handler.
+#[derive(Debug, Encode, Decode)]
+pub enum I2cMessage {
+    /// Returns the list of available buses. Answered with a [`ListBusesResponse`].
+    ListBuses,
+    /// Performs a sequence of reads and writes with a device. Answered with a
+    /// [`TransactionResponse`].
+    Transaction(Transaction),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListBusesResponse {
+    pub buses: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Transaction {
+    pub bus: u32,
+    /// 7-bit address of the device.
+    pub address: u8,
+    /// Operations to perform, in order.
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Operation {
+    /// Sends the given bytes to the device.
+    Write(Vec<u8>),
+    /// Receives the given number of bytes from the device.
+    Read(u32),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TransactionResponse {
+    /// On success, contains the data received by each [`Operation::Read`], in order.
+    ///
+    /// An error of the `IO` class is returned if the device didn't acknowledge its address or
+    /// the data.
+    pub result: Result<Vec<Vec<u8>>, ErrorPayload>,
+}
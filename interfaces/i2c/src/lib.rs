@@ -0,0 +1,90 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! I²C buses.
+//!
+//! Call [`list_buses`] to obtain the available buses, then [`write`], [`read`], [`write_read`]
+//! or [`transaction`] to communicate with the devices connected to a bus.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::Operation;
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the list of available I²C buses.
+pub fn list_buses() -> impl Future<Output = Vec<u32>> {
+    let response = unsafe {
+        let msg = ffi::I2cMessage::ListBuses;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ListBusesResponse| rep.buses)
+}
+
+/// Performs a sequence of operations with the device at the given 7-bit address, and returns
+/// the data received by each [`Operation::Read`].
+pub fn transaction(
+    bus: u32,
+    address: u8,
+    operations: Vec<Operation>,
+) -> impl Future<Output = Result<Vec<Vec<u8>>, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::I2cMessage::Transaction(ffi::Transaction {
+            bus,
+            address,
+            operations,
+        });
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::TransactionResponse| rep.result)
+}
+
+/// Sends data to a device.
+pub fn write(
+    bus: u32,
+    address: u8,
+    data: impl Into<Vec<u8>>,
+) -> impl Future<Output = Result<(), ErrorPayload>> {
+    transaction(bus, address, vec![Operation::Write(data.into())]).map_ok(|_| ())
+}
+
+/// Receives `len` bytes from a device.
+pub fn read(
+    bus: u32,
+    address: u8,
+    len: u32,
+) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+    transaction(bus, address, vec![Operation::Read(len)])
+        .map_ok(|mut data| data.pop().unwrap_or_default())
+}
+
+/// Sends data to a device, then receives `len` bytes from it. Typically used to read the
+/// registers of a device, by writing the register number first.
+pub fn write_read(
+    bus: u32,
+    address: u8,
+    data: impl Into<Vec<u8>>,
+    len: u32,
+) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+    let operations = vec![Operation::Write(data.into()), Operation::Read(len)];
+    transaction(bus, address, operations).map_ok(|mut data| data.pop().unwrap_or_default())
+}
@@ -13,6 +13,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use alloc::vec::Vec;
 use parity_scale_codec::{Decode, Encode};
 use redshirt_syscalls_interface::InterfaceHash;
 
@@ -24,7 +25,22 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 
 #[derive(Debug, Encode, Decode)]
 pub enum InterfaceMessage {
+    /// Registers the emitter as the handler of the given interface. Must be answered with an
+    /// [`InterfaceRegisterResponse`].
     Register(InterfaceHash),
+    /// Same as [`InterfaceMessage::Register`], but also indicates the list of versions of the
+    /// messages schema that the handler accepts. Must be answered with an
+    /// [`InterfaceRegisterResponse`].
+    RegisterVersions(InterfaceHash, Vec<u32>),
+    /// Asks which versions of the messages schema the handler of the given interface accepts.
+    /// Must be answered with an [`InterfaceVersionsResponse`].
+    QueryVersions(InterfaceHash),
+    /// Asks to be notified when the given interface gains or loses its handler. The `bool` must
+    /// be `true` if the emitter believes that the interface currently has a handler.
+    ///
+    /// Must be answered with an [`InterfaceAvailabilityResponse`] as soon as the availability of
+    /// the interface differs from the `bool`, which can be immediately.
+    WaitAvailabilityChange(InterfaceHash, bool),
 }
 
 #[derive(Debug, Encode, Decode)]
@@ -32,8 +48,26 @@ pub struct InterfaceRegisterResponse {
     pub result: Result<(), InterfaceRegisterError>,
 }
 
+#[derive(Debug, Encode, Decode)]
+pub struct InterfaceVersionsResponse {
+    /// `None` if no handler is registered for this interface. An empty list if the handler
+    /// has been registered with [`InterfaceMessage::Register`] and doesn't use versioning.
+    pub versions: Option<Vec<u32>>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct InterfaceAvailabilityResponse {
+    /// True if the interface now has a handler.
+    pub available: bool,
+}
+
 #[derive(Debug, Encode, Decode)]
 pub enum InterfaceRegisterError {
     /// There already exists a process registered for this interface.
     AlreadyRegistered,
+    /// The interface isn't part of the interfaces that the metadata of the module of the
+    /// process declares.
+    NotDeclared,
+    /// The interface is reserved by the kernel and can't be registered.
+    ReservedInterface,
 }
@@ -18,6 +18,9 @@
 #![deny(intra_doc_link_resolution_failure)]
 #![no_std]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use futures::prelude::*;
 use redshirt_syscalls_interface::InterfaceHash;
 
@@ -42,3 +45,59 @@ pub fn register_interface(
             .map(|response: ffi::InterfaceRegisterResponse| response.result)
     }
 }
+
+/// Same as [`register_interface`], but also indicates the versions of the messages schema that
+/// the current program accepts.
+///
+/// Clients can then query these versions with [`query_versions`], and prefix their messages with
+/// the version they use with
+/// [`EncodedMessage::versioned`](redshirt_syscalls_interface::EncodedMessage::versioned).
+pub fn register_interface_versions(
+    hash: InterfaceHash,
+    versions: Vec<u32>,
+) -> impl Future<Output = Result<(), InterfaceRegisterError>> {
+    let msg = ffi::InterfaceMessage::RegisterVersions(hash, versions);
+    unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: ffi::InterfaceRegisterResponse| response.result)
+    }
+}
+
+/// Returns the versions of the messages schema that the handler of the given interface accepts.
+///
+/// Returns `None` if no handler is registered. Returns an empty list if the handler doesn't use
+/// versioning.
+pub fn query_versions(hash: InterfaceHash) -> impl Future<Output = Option<Vec<u32>>> {
+    let msg = ffi::InterfaceMessage::QueryVersions(hash);
+    unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: ffi::InterfaceVersionsResponse| response.versions)
+    }
+}
+
+/// Waits until the given interface gains a handler, if `available` is `false`, or loses its
+/// handler, if `available` is `true`.
+///
+/// Returns immediately if the availability of the interface already differs from `available`.
+/// The returned value is whether the interface now has a handler.
+pub fn wait_availability_change(
+    hash: InterfaceHash,
+    available: bool,
+) -> impl Future<Output = bool> {
+    let msg = ffi::InterfaceMessage::WaitAvailabilityChange(hash, available);
+    unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: ffi::InterfaceAvailabilityResponse| response.available)
+    }
+}
+
+/// Waits until the given interface has a handler.
+///
+/// This is useful for programs that are started before the programs they depend upon, as an
+/// alternative to retrying emitting messages in a loop.
+pub fn wait_interface_available(hash: InterfaceHash) -> impl Future<Output = ()> {
+    wait_availability_change(hash, false).map(|_| ())
+}
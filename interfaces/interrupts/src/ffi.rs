@@ -0,0 +1,96 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x16, 0xcf, 0xff, 0xa8, 0xc2, 0x2b, 0xe6, 0xc7, 0x96, 0xc2, 0x04, 0xec, 0x1f, 0x9a, 0xea, 0x42,
+    0x82, 0x37, 0x75, 0x2d, 0x9a, 0xf7, 0x4d, 0x51, 0x29, 0xfc, 0xbe, 0xda, 0x7c, 0xc1, 0x89, 0xc6,
+]);
+
+/// Message sent to the handler of the interrupts interface.
+///
+/// Claims are identified by a number chosen by the handler. A claim can only be used by the
+/// process that has created it, and is released automatically when that process terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum InterruptsMessage {
+    /// Claims an interrupt. Answered with a [`ClaimResponse`].
+    ///
+    /// Interrupt lines are initially unmasked.
+    Claim(InterruptSource),
+    /// Waits for the claimed interrupt to be triggered. Answered with a [`WaitResponse`].
+    ///
+    /// Interrupt lines are masked by the handler as soon as the interrupt is triggered, and stay
+    /// masked until an [`InterruptsMessage::Ack`] is sent. This gives the driver the
+    /// opportunity to clear the cause of the interrupt in the device. Message-signaled
+    /// interrupts can't be masked by the handler, and must be masked in the device if needed.
+    Wait(u64),
+    /// Indicates that the cause of the interrupt has been handled, and unmasks the interrupt
+    /// line. No answer is expected.
+    Ack(u64),
+    /// Releases a claim. No answer is expected.
+    Release(u64),
+}
+
+/// Interrupt to claim.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum InterruptSource {
+    /// ISA interrupt request line, between 0 and 15. Redirections indicated by the firmware
+    /// are applied automatically.
+    IsaIrq(u8),
+    /// Global system interrupt, as indicated for example by the firmware for the interrupt pins
+    /// of PCI devices.
+    Gsi {
+        gsi: u32,
+        /// PCI interrupt pins are active low.
+        active_low: bool,
+        /// PCI interrupt pins are level-triggered.
+        level_triggered: bool,
+    },
+    /// Message-signaled interrupt. The handler allocates an interrupt, and the driver must
+    /// configure the device with the [`MsiMessage`] returned in the response.
+    Msi,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ClaimResponse {
+    pub result: Result<Claim, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Claim {
+    /// Identifier to use in the other messages.
+    pub id: u64,
+    /// For message-signaled interrupts, the message that the device must write.
+    pub msi: Option<MsiMessage>,
+}
+
+/// Memory write that triggers a message-signaled interrupt.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct MsiMessage {
+    /// Physical address to write to.
+    pub address: u64,
+    /// Value to write.
+    pub data: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WaitResponse {
+    /// On success, contains the number of times the interrupt has been triggered since the
+    /// previous answer to a [`InterruptsMessage::Wait`]. Always at least 1.
+    pub result: Result<u64, ErrorPayload>,
+}
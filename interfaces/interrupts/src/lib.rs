@@ -0,0 +1,90 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hardware interrupts.
+//!
+//! Drivers [`claim`](Interrupt::claim) the interrupt of their device, then call
+//! [`Interrupt::wait`] in a loop. After an interrupt line has been triggered, it stays masked
+//! until [`Interrupt::ack`] is called, which drivers should do after having cleared the cause of
+//! the interrupt in the device.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+use futures::prelude::*;
+
+pub use ffi::{InterruptSource, MsiMessage};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Claimed interrupt. Released when destroyed.
+pub struct Interrupt {
+    id: u64,
+    msi: Option<MsiMessage>,
+}
+
+impl Interrupt {
+    /// Claims an interrupt. Fails if it doesn't exist or is already claimed.
+    pub async fn claim(source: InterruptSource) -> Result<Interrupt, ErrorPayload> {
+        let msg = ffi::InterruptsMessage::Claim(source);
+        let response: ffi::ClaimResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        let claim = response.result?;
+        Ok(Interrupt {
+            id: claim.id,
+            msi: claim.msi,
+        })
+    }
+
+    /// For message-signaled interrupts, returns the message that the device must be configured
+    /// to write.
+    pub fn msi_message(&self) -> Option<&MsiMessage> {
+        self.msi.as_ref()
+    }
+
+    /// Waits for the interrupt to be triggered. Returns the number of times it has been
+    /// triggered since the previous call.
+    pub fn wait(&self) -> impl Future<Output = Result<u64, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::InterruptsMessage::Wait(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::WaitResponse| rep.result)
+    }
+
+    /// Unmasks the interrupt line after it has been triggered.
+    pub fn ack(&self) {
+        unsafe {
+            let msg = ffi::InterruptsMessage::Ack(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
+
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::InterruptsMessage::Release(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xe9, 0x70, 0x1b, 0x91, 0x09, 0x43, 0x81, 0x6b, 0x31, 0xba, 0x44, 0x61, 0x0a, 0x0b, 0xd9, 0xe8,
+    0x19, 0xc9, 0x74, 0xb4, 0xd0, 0xde, 0xff, 0x77, 0x02, 0xb5, 0xc6, 0x5e, 0x61, 0x0c, 0xe0, 0xc3,
+]);
+
+/// Message sent to the handler of the keyboard interface.
+#[derive(Debug, Encode, Decode)]
+pub enum KeyboardMessage {
+    /// Asks to be notified of the keys being pressed and released. The handler sends a partial
+    /// answer containing a [`KeyEvent`] each time a key changes state, and never sends a final
+    /// answer. The message must be cancelled in order to stop the notifications.
+    Subscribe,
+}
+
+/// A key has been pressed or released.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct KeyEvent {
+    /// Usage ID of the key in the keyboard page (`0x07`) of the USB HID usage tables. This
+    /// identifies the physical key, independently of the keyboard layout.
+    pub usage: u16,
+    /// True if the key has been pressed, false if it has been released.
+    pub pressed: bool,
+}
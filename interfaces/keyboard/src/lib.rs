@@ -0,0 +1,73 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Keyboards.
+//!
+//! Call [`events`] to be notified of the keys being pressed and released. Keys are identified
+//! by their position rather than by the character they produce, and translating them to text
+//! according to a keyboard layout is left to the user of this interface.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::KeyEvent;
+
+pub mod ffi;
+
+/// Returns a `Stream` that yields the keys being pressed and released. The subscription is
+/// cancelled when the [`KeyEvents`] is destroyed.
+pub fn events() -> KeyEvents {
+    let msg_id = unsafe {
+        let msg = ffi::KeyboardMessage::Subscribe.encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    KeyEvents {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of key presses and releases.
+///
+/// See [`events`].
+pub struct KeyEvents {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for KeyEvents {
+    type Item = KeyEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for KeyEvents {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
@@ -25,10 +25,20 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 
 #[derive(Debug, Encode, Decode)]
 pub enum LoaderMessage {
+    /// Loads the module whose SHA-256 hash is the given value. Answered with a [`LoadResponse`].
     Load([u8; 32]),
+    /// Stores a module so that it can later be loaded. Answered with a [`StoreResponse`].
+    Store(Vec<u8>),
 }
 
 #[derive(Debug, Encode, Decode)]
 pub struct LoadResponse {
     pub result: Result<Vec<u8>, ()>,
 }
+
+#[derive(Debug, Encode, Decode)]
+pub struct StoreResponse {
+    /// On success, the SHA-256 hash of the module, which can be passed to
+    /// [`LoaderMessage::Load`].
+    pub result: Result<[u8; 32], ()>,
+}
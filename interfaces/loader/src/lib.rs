@@ -41,3 +41,17 @@ pub fn load(hash: [u8; 32]) -> impl Future<Output = Result<Vec<u8>, ()>> {
         }
     }
 }
+
+/// Stores a WASM module so that it can later be loaded with [`load`].
+///
+/// Returns the hash of the module, or an error if the loader doesn't support storing modules.
+// TODO: better error type
+pub fn store(data: Vec<u8>) -> impl Future<Output = Result<[u8; 32], ()>> {
+    unsafe {
+        let msg = ffi::LoaderMessage::Store(data);
+        match redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg) {
+            Ok(fut) => fut.map(|rep: ffi::StoreResponse| rep.result).left_future(),
+            Err(_) => future::ready(Err(())).right_future(),
+        }
+    }
+}
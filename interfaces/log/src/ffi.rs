@@ -0,0 +1,51 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xfb, 0x73, 0x52, 0x08, 0x0f, 0xb2, 0xb1, 0x76, 0xa2, 0x46, 0x2e, 0x5b, 0x72, 0x36, 0x09, 0x38,
+    0xc8, 0xd0, 0xc2, 0x98, 0x34, 0xdc, 0xf3, 0x56, 0x0d, 0x39, 0xa9, 0xad, 0xbe, 0xb8, 0x7c, 0x8b,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum LogMessage {
+    /// Record an entry in the logs. No answer is expected.
+    Log(LogRecord),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct LogRecord {
+    pub level: Level,
+    /// Part of the program the entry comes from, for example `net::dhcp`. Used for filtering.
+    pub target: String,
+    /// Human-readable message.
+    pub message: String,
+    /// Additional key-value pairs attached to the entry.
+    pub fields: Vec<(String, String)>,
+}
+
+/// Importance of a log entry. Levels are ordered from the most important to the most verbose.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
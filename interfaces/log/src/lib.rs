@@ -0,0 +1,54 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Logging.
+//!
+//! Log entries are made of a [`Level`](ffi::Level), a target, a message, and optional key-value
+//! fields. The handler of this interface decides, based on the level and the target, whether
+//! to keep the entry and where to write it.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{borrow::ToOwned as _, vec::Vec};
+
+pub use ffi::Level;
+
+pub mod ffi;
+
+/// Records an entry in the logs.
+pub fn log(level: Level, target: &str, message: &str) {
+    log_with_fields(level, target, message, &[])
+}
+
+/// Records an entry in the logs, with additional key-value fields.
+pub fn log_with_fields(level: Level, target: &str, message: &str, fields: &[(&str, &str)]) {
+    let record = ffi::LogRecord {
+        level,
+        target: target.to_owned(),
+        message: message.to_owned(),
+        fields: fields
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect::<Vec<_>>(),
+    };
+
+    unsafe {
+        let msg = ffi::LogMessage::Log(record);
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
@@ -0,0 +1,563 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Generates the boilerplate of an interface from a single declaration.
+//!
+//! Each interface crate contains an `ffi` module with the hash of the interface and an enum of
+//! all the messages that can be emitted on it, plus helper functions that emit these messages.
+//! The [`redshirt_interface!`] macro generates all of these from a list of function signatures:
+//!
+//! ```ignore
+//! redshirt_interface_macro::redshirt_interface! {
+//!     /// Time.
+//!     interface TimeMessage, TimeHandler = [
+//!         0x1a, 0xd7, 0x1f, 0x2f, 0x6f, 0x4e, 0x7f, 0x29, 0x2b, 0x2d, 0x5f, 0x91, 0x24, 0x4b,
+//!         0x50, 0x8c, 0x6c, 0x39, 0x1f, 0xf3, 0x2a, 0x43, 0x29, 0x0a, 0x6d, 0x7c, 0x42, 0x1e,
+//!         0x6d, 0xe0, 0xcd, 0xca,
+//!     ];
+//!
+//!     /// Returns the number of nanoseconds since an arbitrary point in time in the past.
+//!     fn get_monotonic() -> u128;
+//!     /// Returns a `Future` that yields when the monotonic clock reaches this value.
+//!     fn wait_monotonic(until: u128) -> ();
+//! }
+//! ```
+//!
+//! The example above generates:
+//!
+//! - A `pub const INTERFACE: InterfaceHash`.
+//! - A `TimeMessage` enum with one variant per function (`GetMonotonic` and
+//! `WaitMonotonic(u128)`), implementing `Encode` and `Decode`.
+//! - Client stubs. Functions with a return type return an `impl Future` resolving to the
+//! answer. Functions without a return type emit a message that doesn't expect any answer.
+//! - A `TimeHandler` trait with one method per function, and a `TimeMessage::dispatch` method
+//! that calls the right method of a `TimeHandler` and encodes the answer.
+//!
+//! The crate where the macro is used must depend on `redshirt-syscalls-interface` and
+//! `parity-scale-codec`.
+//!
+//! # Backward-compatible messages
+//!
+//! The `#[derive(RedshirtEncode, RedshirtDecode)]` macros implement `Encode` and `Decode` for a
+//! struct in a way that allows adding fields to it without breaking the programs that have been
+//! compiled against the previous version:
+//!
+//! ```ignore
+//! #[derive(RedshirtEncode, RedshirtDecode)]
+//! pub struct OpenFile {
+//!     #[redshirt(tag = 0)]
+//!     pub path: String,
+//!     #[redshirt(tag = 1, default)]
+//!     pub read_only: bool,
+//! }
+//! ```
+//!
+//! Each field is encoded alongside its tag. When decoding, fields whose tag is unknown are
+//! ignored, and fields marked with `default` that are missing are set to `Default::default()`.
+//! A missing field without `default` is an error. Tags must never be reused for a different
+//! field.
+//!
+//! The crate where these macros are used must depend on `parity-scale-codec` and contain
+//! `extern crate alloc`.
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Attribute, Data, DeriveInput, ExprArray, Fields, Ident, Lit, Meta, NestedMeta, Token, Type,
+};
+
+mod kw {
+    syn::custom_keyword!(interface);
+}
+
+/// Generates the hash, message enum, client stubs and handler trait of an interface.
+///
+/// See the crate-level documentation for the syntax.
+#[proc_macro]
+pub fn redshirt_interface(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let interface = parse_macro_input!(input as Interface);
+    proc_macro::TokenStream::from(interface.generate())
+}
+
+/// Implements `parity_scale_codec::Encode` for a struct whose fields are tagged.
+///
+/// See the crate-level documentation.
+#[proc_macro_derive(RedshirtEncode, attributes(redshirt))]
+pub fn derive_redshirt_encode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let output = match TaggedStruct::from_input(&input) {
+        Ok(s) => s.generate_encode(),
+        Err(err) => err.to_compile_error(),
+    };
+    proc_macro::TokenStream::from(output)
+}
+
+/// Implements `parity_scale_codec::Decode` for a struct whose fields are tagged.
+///
+/// See the crate-level documentation.
+#[proc_macro_derive(RedshirtDecode, attributes(redshirt))]
+pub fn derive_redshirt_decode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let output = match TaggedStruct::from_input(&input) {
+        Ok(s) => s.generate_decode(),
+        Err(err) => err.to_compile_error(),
+    };
+    proc_macro::TokenStream::from(output)
+}
+
+/// Parsed content of a [`redshirt_interface!`] invocation.
+struct Interface {
+    /// Attributes of the interface, applied to the generated `INTERFACE` constant.
+    attrs: Vec<Attribute>,
+    /// Name of the message enum to generate.
+    message: Ident,
+    /// Name of the handler trait to generate.
+    handler: Ident,
+    /// Hash of the interface.
+    hash: ExprArray,
+    /// List of functions of the interface.
+    functions: Vec<Function>,
+}
+
+/// Function within an [`Interface`].
+struct Function {
+    /// Attributes of the function, such as its documentation.
+    attrs: Vec<Attribute>,
+    /// Name of the function.
+    name: Ident,
+    /// Parameters of the function.
+    params: Vec<(Ident, Type)>,
+    /// Type of the answer, or `None` if the message doesn't expect any answer.
+    output: Option<Type>,
+}
+
+/// Single `name: Type` parameter of a [`Function`].
+struct Param(Ident, Type);
+
+impl Parse for Interface {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<kw::interface>()?;
+        let message = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let handler = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let hash = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        let mut functions = Vec::new();
+        while !input.is_empty() {
+            functions.push(input.parse()?);
+        }
+
+        Ok(Interface {
+            attrs,
+            message,
+            handler,
+            hash,
+            functions,
+        })
+    }
+}
+
+impl Parse for Function {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<Token![fn]>()?;
+        let name = input.parse()?;
+
+        let content;
+        parenthesized!(content in input);
+        let params = Punctuated::<Param, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .map(|Param(name, ty)| (name, ty))
+            .collect();
+
+        let output = if input.peek(Token![->]) {
+            input.parse::<Token![->]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        input.parse::<Token![;]>()?;
+
+        Ok(Function {
+            attrs,
+            name,
+            params,
+            output,
+        })
+    }
+}
+
+impl Parse for Param {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        Ok(Param(name, ty))
+    }
+}
+
+impl Interface {
+    /// Generates the code corresponding to this interface.
+    fn generate(&self) -> TokenStream {
+        let Interface {
+            attrs,
+            message,
+            handler,
+            hash,
+            ..
+        } = self;
+
+        let variants = self.functions.iter().map(|f| {
+            let attrs = &f.attrs;
+            let variant = f.variant_name();
+            if f.params.is_empty() {
+                quote! { #(#attrs)* #variant }
+            } else {
+                let tys = f.params.iter().map(|(_, ty)| ty);
+                quote! { #(#attrs)* #variant(#(#tys),*) }
+            }
+        });
+
+        let stubs = self.functions.iter().map(|f| {
+            let attrs = &f.attrs;
+            let name = &f.name;
+            let params = f.params.iter().map(|(n, ty)| quote! { #n: #ty });
+            let construct = f.construct(message);
+            match &f.output {
+                Some(output) => quote! {
+                    #(#attrs)*
+                    pub fn #name(#(#params),*) -> impl core::future::Future<Output = #output> {
+                        unsafe {
+                            let msg = #construct;
+                            redshirt_syscalls_interface::emit_message_with_response(&INTERFACE, msg)
+                                .unwrap()
+                        }
+                    }
+                },
+                None => quote! {
+                    #(#attrs)*
+                    pub fn #name(#(#params),*) {
+                        unsafe {
+                            let msg = #construct;
+                            redshirt_syscalls_interface::emit_message_without_response(&INTERFACE, msg)
+                                .unwrap();
+                        }
+                    }
+                },
+            }
+        });
+
+        let handler_methods = self.functions.iter().map(|f| {
+            let attrs = &f.attrs;
+            let name = &f.name;
+            let params = f.params.iter().map(|(n, ty)| quote! { #n: #ty });
+            let output = match &f.output {
+                Some(output) => quote! { -> #output },
+                None => quote! {},
+            };
+            quote! { #(#attrs)* fn #name(&mut self, #(#params),*) #output; }
+        });
+
+        let dispatch_arms = self.functions.iter().map(|f| {
+            let name = &f.name;
+            let pattern = f.construct(message);
+            let args = f.params.iter().map(|(n, _)| n);
+            if f.output.is_some() {
+                quote! {
+                    #pattern => Some(redshirt_syscalls_interface::Encode::encode(
+                        handler.#name(#(#args),*)
+                    )),
+                }
+            } else {
+                quote! {
+                    #pattern => {
+                        handler.#name(#(#args),*);
+                        None
+                    }
+                }
+            }
+        });
+
+        let dispatch_doc = format!(
+            "Calls the method of `handler` corresponding to this message. Returns the encoded \
+             answer, or `None` if the message doesn't expect any answer.\n\nSee [`{}`].",
+            handler
+        );
+
+        quote! {
+            #(#attrs)*
+            pub const INTERFACE: redshirt_syscalls_interface::InterfaceHash =
+                redshirt_syscalls_interface::InterfaceHash::from_raw_hash(#hash);
+
+            /// Message that can be emitted on [`INTERFACE`].
+            #[derive(Debug, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+            pub enum #message {
+                #(#variants,)*
+            }
+
+            #(#stubs)*
+
+            /// Trait to implement in order to handle the messages of [`INTERFACE`].
+            pub trait #handler {
+                #(#handler_methods)*
+            }
+
+            impl #message {
+                #[doc = #dispatch_doc]
+                pub fn dispatch(
+                    self,
+                    handler: &mut impl #handler,
+                ) -> Option<redshirt_syscalls_interface::EncodedMessage> {
+                    match self {
+                        #(#dispatch_arms)*
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Function {
+    /// Returns the name of the enum variant corresponding to this function. Converts
+    /// `snake_case` to `CamelCase`.
+    fn variant_name(&self) -> Ident {
+        let name = self
+            .name
+            .to_string()
+            .split('_')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let mut chars = s.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>();
+        Ident::new(&name, Span::call_site())
+    }
+
+    /// Returns an expression or pattern of the form `Message::Variant(param1, param2)`.
+    fn construct(&self, message: &Ident) -> TokenStream {
+        let variant = self.variant_name();
+        if self.params.is_empty() {
+            quote! { #message::#variant }
+        } else {
+            let params = self.params.iter().map(|(n, _)| n);
+            quote! { #message::#variant(#(#params),*) }
+        }
+    }
+}
+
+/// Struct on which [`RedshirtEncode`](derive_redshirt_encode) or
+/// [`RedshirtDecode`](derive_redshirt_decode) is derived.
+struct TaggedStruct<'a> {
+    /// The input of the derive.
+    input: &'a DeriveInput,
+    /// List of fields of the struct.
+    fields: Vec<TaggedField<'a>>,
+}
+
+/// Field of a [`TaggedStruct`].
+struct TaggedField<'a> {
+    /// Name of the field.
+    name: &'a Ident,
+    /// Type of the field.
+    ty: &'a Type,
+    /// Tag of the field, as found in its `#[redshirt(tag = ...)]` attribute.
+    tag: u32,
+    /// True if the field has a `#[redshirt(default)]` attribute.
+    default: bool,
+}
+
+impl<'a> TaggedStruct<'a> {
+    /// Parses the input of the derive.
+    fn from_input(input: &'a DeriveInput) -> syn::Result<Self> {
+        let fields = match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(fields) => &fields.named,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        input,
+                        "only structs with named fields are supported",
+                    ))
+                }
+            },
+            _ => return Err(syn::Error::new_spanned(input, "only structs are supported")),
+        };
+
+        let mut out = Vec::with_capacity(fields.len());
+        for field in fields {
+            let mut tag = None;
+            let mut default = false;
+
+            for attr in field.attrs.iter().filter(|a| a.path.is_ident("redshirt")) {
+                let list = match attr.parse_meta()? {
+                    Meta::List(list) => list,
+                    other => return Err(syn::Error::new_spanned(other, "expected a list")),
+                };
+
+                for item in list.nested {
+                    match item {
+                        NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("tag") => {
+                            match &nv.lit {
+                                Lit::Int(lit) => tag = Some(lit.base10_parse::<u32>()?),
+                                lit => {
+                                    return Err(syn::Error::new_spanned(lit, "expected an integer"))
+                                }
+                            }
+                        }
+                        NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("default") => {
+                            default = true;
+                        }
+                        other => return Err(syn::Error::new_spanned(other, "unknown attribute")),
+                    }
+                }
+            }
+
+            let tag = match tag {
+                Some(t) => t,
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "missing `#[redshirt(tag = ...)]` attribute",
+                    ))
+                }
+            };
+
+            if out.iter().any(|f: &TaggedField| f.tag == tag) {
+                return Err(syn::Error::new_spanned(field, "duplicate tag"));
+            }
+
+            out.push(TaggedField {
+                name: field.ident.as_ref().unwrap(),
+                ty: &field.ty,
+                tag,
+                default,
+            });
+        }
+
+        Ok(TaggedStruct { input, fields: out })
+    }
+
+    /// Generates the implementation of `Encode`.
+    ///
+    /// The struct is encoded as a list of `(tag, encoded_field)` tuples.
+    fn generate_encode(&self) -> TokenStream {
+        let name = &self.input.ident;
+        let (impl_generics, ty_generics, where_clause) = self.input.generics.split_for_impl();
+        let num_fields = self.fields.len() as u32;
+
+        let fields = self.fields.iter().map(|f| {
+            let field = f.name;
+            let tag = f.tag;
+            quote! {
+                parity_scale_codec::Encode::encode_to(&#tag, dest);
+                parity_scale_codec::Encode::encode_to(
+                    &parity_scale_codec::Encode::encode(&self.#field),
+                    dest,
+                );
+            }
+        });
+
+        quote! {
+            impl #impl_generics parity_scale_codec::Encode for #name #ty_generics #where_clause {
+                fn encode_to<W: parity_scale_codec::Output>(&self, dest: &mut W) {
+                    parity_scale_codec::Encode::encode_to(
+                        &parity_scale_codec::Compact(#num_fields),
+                        dest,
+                    );
+                    #(#fields)*
+                }
+            }
+        }
+    }
+
+    /// Generates the implementation of `Decode`.
+    fn generate_decode(&self) -> TokenStream {
+        let name = &self.input.ident;
+        let (impl_generics, ty_generics, where_clause) = self.input.generics.split_for_impl();
+
+        let vars = self
+            .fields
+            .iter()
+            .map(|f| Ident::new(&format!("field_{}", f.name), Span::call_site()))
+            .collect::<Vec<_>>();
+
+        let declarations = self.fields.iter().zip(vars.iter()).map(|(f, var)| {
+            let ty = f.ty;
+            quote! { let mut #var: Option<#ty> = None; }
+        });
+
+        let arms = self.fields.iter().zip(vars.iter()).map(|(f, var)| {
+            let ty = f.ty;
+            let tag = f.tag;
+            quote! {
+                #tag => {
+                    #var = Some(<#ty as parity_scale_codec::DecodeAll>::decode_all(&bytes)?);
+                }
+            }
+        });
+
+        let build = self.fields.iter().zip(vars.iter()).map(|(f, var)| {
+            let field = f.name;
+            if f.default {
+                quote! { #field: #var.unwrap_or_default(), }
+            } else {
+                let error = format!("missing field `{}`", f.name);
+                quote! { #field: #var.ok_or_else(|| parity_scale_codec::Error::from(#error))?, }
+            }
+        });
+
+        quote! {
+            impl #impl_generics parity_scale_codec::Decode for #name #ty_generics #where_clause {
+                fn decode<I: parity_scale_codec::Input>(
+                    input: &mut I,
+                ) -> Result<Self, parity_scale_codec::Error> {
+                    #(#declarations)*
+
+                    let num_fields =
+                        <parity_scale_codec::Compact<u32> as parity_scale_codec::Decode>::decode(
+                            input,
+                        )?
+                        .0;
+                    for _ in 0..num_fields {
+                        let tag = <u32 as parity_scale_codec::Decode>::decode(input)?;
+                        let bytes =
+                            <::alloc::vec::Vec<u8> as parity_scale_codec::Decode>::decode(input)?;
+                        match tag {
+                            #(#arms)*
+                            // Fields added by a later version of the struct are ignored.
+                            _ => {}
+                        }
+                    }
+
+                    Ok(#name {
+                        #(#build)*
+                    })
+                }
+            }
+        }
+    }
+}
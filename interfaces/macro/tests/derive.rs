@@ -0,0 +1,103 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+extern crate alloc;
+
+use parity_scale_codec::{Decode as _, Encode as _};
+use redshirt_interface_macro::{RedshirtDecode, RedshirtEncode};
+
+/// First version of the struct.
+#[derive(Debug, PartialEq, Eq, RedshirtEncode, RedshirtDecode)]
+struct OpenFileV1 {
+    #[redshirt(tag = 0)]
+    path: String,
+}
+
+/// Second version of the struct, with a field added.
+#[derive(Debug, PartialEq, Eq, RedshirtEncode, RedshirtDecode)]
+struct OpenFileV2 {
+    #[redshirt(tag = 0)]
+    path: String,
+    #[redshirt(tag = 1, default)]
+    read_only: bool,
+}
+
+/// Version of the struct with a field added but without a default.
+#[derive(Debug, PartialEq, Eq, RedshirtEncode, RedshirtDecode)]
+struct OpenFileRequiredMode {
+    #[redshirt(tag = 0)]
+    path: String,
+    #[redshirt(tag = 2)]
+    mode: u8,
+}
+
+#[test]
+fn round_trip() {
+    let original = OpenFileV2 {
+        path: "/foo".to_owned(),
+        read_only: true,
+    };
+    let decoded = OpenFileV2::decode(&mut &original.encode()[..]).unwrap();
+    assert_eq!(decoded, original);
+}
+
+#[test]
+fn old_encoding_missing_default_field() {
+    let old = OpenFileV1 {
+        path: "/foo".to_owned(),
+    };
+    let decoded = OpenFileV2::decode(&mut &old.encode()[..]).unwrap();
+    assert_eq!(
+        decoded,
+        OpenFileV2 {
+            path: "/foo".to_owned(),
+            read_only: false,
+        }
+    );
+}
+
+#[test]
+fn unknown_tags_skipped() {
+    let new = OpenFileV2 {
+        path: "/foo".to_owned(),
+        read_only: true,
+    };
+    let decoded = OpenFileV1::decode(&mut &new.encode()[..]).unwrap();
+    assert_eq!(
+        decoded,
+        OpenFileV1 {
+            path: "/foo".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn missing_field_without_default_fails() {
+    let old = OpenFileV1 {
+        path: "/foo".to_owned(),
+    };
+    assert!(OpenFileRequiredMode::decode(&mut &old.encode()[..]).is_err());
+}
+
+#[test]
+fn truncated_encoding_fails() {
+    let mut encoded = OpenFileV2 {
+        path: "/foo".to_owned(),
+        read_only: true,
+    }
+    .encode();
+    encoded.pop();
+    assert!(OpenFileV2::decode(&mut &encoded[..]).is_err());
+}
@@ -0,0 +1,102 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+extern crate alloc;
+
+use redshirt_syscalls_interface::{Decode as _, Encode as _};
+
+mod ffi {
+    redshirt_interface_macro::redshirt_interface! {
+        /// Test interface.
+        interface TestMessage, TestHandler = [
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ];
+
+        /// Adds two numbers.
+        fn add(a: u32, b: u32) -> u64;
+        /// Sets the value.
+        fn set(value: u8);
+        /// Resets the value.
+        fn reset();
+    }
+}
+
+#[derive(Default)]
+struct Handler {
+    value: Option<u8>,
+}
+
+impl ffi::TestHandler for Handler {
+    fn add(&mut self, a: u32, b: u32) -> u64 {
+        u64::from(a) + u64::from(b)
+    }
+
+    fn set(&mut self, value: u8) {
+        self.value = Some(value);
+    }
+
+    fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+#[test]
+fn interface_hash() {
+    let mut expected = [0; 32];
+    for (n, byte) in expected.iter_mut().enumerate() {
+        *byte = n as u8 + 1;
+    }
+    assert_eq!(ffi::INTERFACE, expected);
+}
+
+#[test]
+fn stubs_signatures() {
+    // The stubs emit messages and can't be called outside of a redshirt program. Only check that
+    // they have the expected signatures.
+    let _ = ffi::set as fn(u8);
+    let _ = ffi::reset as fn();
+    let _ = |a: u32, b: u32| ffi::add(a, b);
+}
+
+#[test]
+fn round_trip_with_answer() {
+    let encoded = ffi::TestMessage::Add(5, 7).encode();
+    let decoded = ffi::TestMessage::decode(encoded).unwrap();
+    match decoded {
+        ffi::TestMessage::Add(5, 7) => {}
+        ref other => panic!("{:?}", other),
+    }
+
+    let mut handler = Handler::default();
+    let answer = decoded.dispatch(&mut handler).unwrap();
+    assert_eq!(answer.decode::<u64>().unwrap(), 12);
+}
+
+#[test]
+fn round_trip_without_answer() {
+    let mut handler = Handler::default();
+
+    let encoded = ffi::TestMessage::Set(42).encode();
+    let decoded = ffi::TestMessage::decode(encoded).unwrap();
+    assert!(decoded.dispatch(&mut handler).is_none());
+    assert_eq!(handler.value, Some(42));
+
+    let encoded = ffi::TestMessage::Reset.encode();
+    let decoded = ffi::TestMessage::decode(encoded).unwrap();
+    assert!(decoded.dispatch(&mut handler).is_none());
+    assert_eq!(handler.value, None);
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x31, 0x58, 0x56, 0x47, 0xee, 0x73, 0x94, 0x54, 0xb9, 0x71, 0xe2, 0x19, 0x24, 0xc3, 0xa4, 0x5f,
+    0x8e, 0xc6, 0xad, 0xc6, 0x13, 0xc5, 0xea, 0xf7, 0x1f, 0xbc, 0xb9, 0xad, 0xef, 0x24, 0x4e, 0xcb,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum MetricsMessage {
+    /// Request the current metrics of the system. Must be answered with a [`SystemMetrics`].
+    GetMetrics,
+    /// Request statistics about the messages emitted on each interface. Must be answered with a
+    /// `Vec<InterfaceMetrics>`.
+    GetInterfaceMetrics,
+}
+
+/// Snapshot of the counters of the system.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct SystemMetrics {
+    /// Number of WASM processes currently running.
+    pub processes: u64,
+    /// Total number of messages that have been routed since the system started. Divide the
+    /// difference between two snapshots by the time elapsed to obtain a rate.
+    pub messages_routed: u64,
+    /// Total number of messages waiting in the queues of processes.
+    pub queued_messages: u64,
+    /// Total number of bytes of memory used by the WASM processes.
+    pub wasm_memory_bytes: u64,
+}
+
+/// Counters about the messages emitted on a specific interface.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct InterfaceMetrics {
+    /// Interface the counters are about.
+    pub interface: InterfaceHash,
+    /// Total number of messages emitted on this interface since the system started.
+    pub messages: u64,
+    /// Total number of bytes of the messages emitted on this interface since the system started.
+    pub bytes: u64,
+}
@@ -0,0 +1,46 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! System metrics.
+//!
+//! This interface is handled by the kernel itself, and allows monitoring tools to query
+//! counters about the system.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::future::Future;
+
+pub mod ffi;
+
+/// Returns a snapshot of the metrics of the system.
+pub fn get_metrics() -> impl Future<Output = ffi::SystemMetrics> {
+    unsafe {
+        let msg = ffi::MetricsMessage::GetMetrics;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Returns, for each interface on which at least one message has been emitted, counters about
+/// these messages.
+pub fn get_interface_metrics() -> impl Future<Output = Vec<ffi::InterfaceMetrics>> {
+    unsafe {
+        let msg = ffi::MetricsMessage::GetInterfaceMetrics;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
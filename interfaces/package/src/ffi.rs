@@ -0,0 +1,69 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xfa, 0x34, 0x61, 0xf4, 0xb7, 0x6d, 0x3a, 0x5d, 0x09, 0x9f, 0xde, 0x15, 0x16, 0xce, 0x84, 0xd4,
+    0x72, 0xa3, 0x1e, 0x49, 0xed, 0xf5, 0x2b, 0x27, 0x4c, 0xfb, 0xfc, 0x58, 0xf7, 0x0c, 0x58, 0xbf,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum PackageMessage {
+    /// Queries the list of installed packages. Answered with a [`ListResponse`].
+    List,
+    /// Installs the latest version of the package with the given name. Answered with an
+    /// [`InstallResponse`].
+    Install(String),
+    /// Installs the latest version of an installed package, if it is more recent than the
+    /// installed one. Answered with an [`UpgradeResponse`].
+    Upgrade(String),
+    /// Removes a package from the list of installed packages. Answered with a
+    /// [`RemoveResponse`].
+    Remove(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// Hash of the module of the package, which can be passed to the loader.
+    pub module_hash: [u8; 32],
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListResponse {
+    pub packages: Vec<Package>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct InstallResponse {
+    pub result: Result<Package, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UpgradeResponse {
+    /// On success, contains the new version of the package, or `None` if the installed version
+    /// is already the latest.
+    pub result: Result<Option<Package>, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct RemoveResponse {
+    pub result: Result<(), ErrorPayload>,
+}
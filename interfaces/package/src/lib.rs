@@ -0,0 +1,71 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Installing packages.
+//!
+//! A package is a module distributed along with a name and a version. The handler of this
+//! interface fetches packages from a repository, verifies them, and stores their module so that
+//! it can be loaded by its hash.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::Package;
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the list of installed packages.
+pub fn list() -> impl Future<Output = Vec<Package>> {
+    let response = unsafe {
+        let msg = ffi::PackageMessage::List;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ListResponse| rep.packages)
+}
+
+/// Installs the latest version of the package with the given name.
+pub fn install(name: impl Into<String>) -> impl Future<Output = Result<Package, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::PackageMessage::Install(name.into());
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::InstallResponse| rep.result)
+}
+
+/// Upgrades an installed package. Returns `None` if the installed version is already the latest.
+pub fn upgrade(
+    name: impl Into<String>,
+) -> impl Future<Output = Result<Option<Package>, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::PackageMessage::Upgrade(name.into());
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::UpgradeResponse| rep.result)
+}
+
+/// Removes a package from the list of installed packages.
+pub fn remove(name: impl Into<String>) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::PackageMessage::Remove(name.into());
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::RemoveResponse| rep.result)
+}
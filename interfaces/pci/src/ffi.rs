@@ -15,7 +15,7 @@
 
 use alloc::vec::Vec;
 use parity_scale_codec::{Decode, Encode};
-use redshirt_syscalls_interface::InterfaceHash;
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
 
 // TODO: this has been randomly generated; instead should be a hash or something
 pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
@@ -28,6 +28,33 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
 pub enum PciMessage {
     /// Request list of PCI devices. Answer with a [`GetDevicesListResponse`].
     GetDevicesList,
+    /// Reads a `u32` in the configuration space of a device. Answer with a `u32`.
+    ///
+    /// The offset must be a multiple of 4.
+    ReadConfigU32 {
+        location: PciDeviceLocation,
+        offset: u8,
+    },
+    /// Writes a `u32` in the configuration space of a device. No answer is expected.
+    ///
+    /// The offset must be a multiple of 4.
+    WriteConfigU32 {
+        location: PciDeviceLocation,
+        offset: u8,
+        value: u32,
+    },
+    /// Enables the decoding of accesses to the given base address register by the device, and
+    /// returns its description. Answer with a [`MapBarResponse`].
+    ///
+    /// The emitter is granted access to the range of the base address register through the
+    /// `hardware` interface.
+    MapBar {
+        location: PciDeviceLocation,
+        /// Index of the base address register, between 0 and 5.
+        bar: u8,
+    },
+    /// Allows the device to initiate DMA transfers. No answer is expected.
+    EnableBusMastering { location: PciDeviceLocation },
 }
 
 /// Response to [`PciMessage::GetDevicesList`].
@@ -37,24 +64,53 @@ pub struct GetDevicesListResponse {
     pub devices: Vec<PciDeviceInfo>,
 }
 
+/// Response to [`PciMessage::MapBar`].
+#[derive(Debug, Encode, Decode)]
+pub struct MapBarResponse {
+    pub result: Result<PciBaseAddressRegister, ErrorPayload>,
+}
+
 /// Description of a single PCI device.
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct PciDeviceInfo {
+    pub location: PciDeviceLocation,
     pub vendor_id: u16,
     pub device_id: u16,
-    pub base_address_registers: Vec<PciBaseAddressRegister>,
+    /// Base class of the device, such as `0x2` for network controllers.
+    pub class_code: u8,
+    /// Category of the device within its base class.
+    pub subclass: u8,
+    /// Register-level programming interface of the device.
+    pub prog_if: u8,
+    pub revision_id: u8,
+    /// The six base address registers of the device. Contains `None` for registers that aren't
+    /// implemented by the device, and for the upper half of 64 bits registers.
+    pub base_address_registers: Vec<Option<PciBaseAddressRegister>>,
     // TODO: add more fields
 }
 
-/// Description of a single PCI device.
-// TODO: actually figure out PCI and adjust this
-#[derive(Debug, Clone, Encode, Decode)]
+/// Position of a device on the PCI buses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct PciDeviceLocation {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+/// Description of a base address register of a PCI device.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub enum PciBaseAddressRegister {
+    /// Range of physical memory.
     Memory {
-        base_address: u32,
+        base_address: u64,
+        /// Size in bytes of the range.
+        size: u64,
         prefetchable: bool,
     },
+    /// Range of I/O ports.
     Io {
         base_address: u32,
+        /// Number of ports in the range.
+        size: u32,
     },
 }
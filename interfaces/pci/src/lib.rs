@@ -22,7 +22,8 @@
 
 extern crate alloc;
 
-pub use self::ffi::{PciBaseAddressRegister, PciDeviceInfo};
+pub use self::ffi::{PciBaseAddressRegister, PciDeviceInfo, PciDeviceLocation};
+pub use redshirt_syscalls_interface::ErrorPayload;
 
 use alloc::vec::Vec;
 use futures::prelude::*;
@@ -39,3 +40,53 @@ pub fn get_pci_devices() -> impl Future<Output = Vec<PciDeviceInfo>> {
             .map(|response: ffi::GetDevicesListResponse| response.devices)
     }
 }
+
+/// Reads a `u32` in the configuration space of the given device. The offset must be a multiple
+/// of 4.
+pub fn read_config_u32(location: PciDeviceLocation, offset: u8) -> impl Future<Output = u32> {
+    unsafe {
+        let msg = ffi::PciMessage::ReadConfigU32 { location, offset };
+        // TODO: don't unwrap?
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Writes a `u32` in the configuration space of the given device. The offset must be a multiple
+/// of 4.
+pub fn write_config_u32(location: PciDeviceLocation, offset: u8, value: u32) {
+    unsafe {
+        let msg = ffi::PciMessage::WriteConfigU32 {
+            location,
+            offset,
+            value,
+        };
+        // TODO: don't unwrap?
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg).unwrap();
+    }
+}
+
+/// Enables the decoding of accesses to the given base address register of a device, and returns
+/// its description.
+///
+/// Returns an error if the device doesn't exist or doesn't implement this register.
+pub fn map_bar(
+    location: PciDeviceLocation,
+    bar: u8,
+) -> impl Future<Output = Result<PciBaseAddressRegister, ErrorPayload>> {
+    unsafe {
+        let msg = ffi::PciMessage::MapBar { location, bar };
+        // TODO: don't unwrap?
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+            .unwrap()
+            .map(|response: ffi::MapBarResponse| response.result)
+    }
+}
+
+/// Allows the given device to initiate DMA transfers.
+pub fn enable_bus_mastering(location: PciDeviceLocation) {
+    unsafe {
+        let msg = ffi::PciMessage::EnableBusMastering { location };
+        // TODO: don't unwrap?
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x25, 0xd3, 0x15, 0xbc, 0x15, 0x11, 0xd7, 0xe4, 0x1d, 0x47, 0xfb, 0x47, 0x78, 0x66, 0x16, 0xd6,
+    0xe7, 0xaa, 0xba, 0xbb, 0xa5, 0x7d, 0xe3, 0x57, 0x59, 0xf8, 0x11, 0xa3, 0x5e, 0xf6, 0x49, 0xff,
+]);
+
+/// Message sent to the handler of the pointer interface.
+#[derive(Debug, Encode, Decode)]
+pub enum PointerMessage {
+    /// Asks to be notified of the movements of the pointing devices and of their buttons. The
+    /// handler sends a partial answer containing a [`PointerEvent`] for each change, and never
+    /// sends a final answer. The message must be cancelled in order to stop the notifications.
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PointerEvent {
+    /// The pointer has moved. The values are relative to the previous position, in units chosen
+    /// by the device. Positive values go right and down.
+    Motion { dx: i32, dy: i32 },
+    /// A button has been pressed or released.
+    Button { button: Button, pressed: bool },
+    /// The wheel has been scrolled, in number of notches. Positive values scroll down.
+    Wheel { delta: i32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+}
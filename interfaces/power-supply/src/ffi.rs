@@ -0,0 +1,68 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x8c, 0x25, 0x4f, 0x1f, 0xfa, 0x28, 0xc8, 0xa0, 0xa3, 0x30, 0x84, 0xaf, 0x2e, 0x7f, 0xe1, 0xc9,
+    0xa6, 0x1f, 0xa5, 0x0e, 0x77, 0xcb, 0x56, 0xa2, 0xda, 0x11, 0xc2, 0x95, 0x93, 0x53, 0xb3, 0xc3,
+]);
+
+/// Message sent to the handler of the power supply interface.
+#[derive(Debug, Encode, Decode)]
+pub enum PowerSupplyMessage {
+    /// Returns the current state of the power sources. Answered with a [`Status`].
+    Status,
+    /// Asks to be notified of changes of the power sources. The handler sends a partial answer
+    /// containing a [`Status`] with the current state, then another one each time the state
+    /// changes, and never sends a final answer. The message must be cancelled in order to stop
+    /// the notifications.
+    Subscribe,
+}
+
+/// State of the power sources of the machine.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Status {
+    /// True if the machine is powered by an external source, such as the mains. `None` if
+    /// unknown.
+    pub ac_online: Option<bool>,
+    /// List of batteries. Empty if the machine doesn't have any.
+    pub batteries: Vec<Battery>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Battery {
+    /// Name of the battery, chosen by the handler. Stays the same as long as the battery is
+    /// present.
+    pub name: String,
+    pub state: BatteryState,
+    /// Charge level, between 0 and 100. `None` if unknown.
+    pub charge_percent: Option<u8>,
+    /// Estimated number of seconds until the battery is empty if discharging, or full if
+    /// charging. `None` if unknown.
+    pub time_remaining_secs: Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum BatteryState {
+    Charging,
+    Discharging,
+    Full,
+    /// Connected to an external source, but not charging.
+    NotCharging,
+    Unknown,
+}
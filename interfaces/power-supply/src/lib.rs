@@ -0,0 +1,83 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Power sources of the machine.
+//!
+//! Call [`status`] to know whether the machine is connected to an external power source and
+//! how charged its batteries are, or [`events`] to be notified when that changes, for example
+//! in order to react to a low battery.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::{Battery, BatteryState, Status};
+
+pub mod ffi;
+
+/// Returns the current state of the power sources.
+pub fn status() -> impl Future<Output = Status> {
+    unsafe {
+        let msg = ffi::PowerSupplyMessage::Status;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
+
+/// Returns a `Stream` that yields the current state of the power sources, then a new state
+/// every time it changes. The subscription is cancelled when the [`StatusEvents`] is destroyed.
+pub fn events() -> StatusEvents {
+    let msg_id = unsafe {
+        let msg = ffi::PowerSupplyMessage::Subscribe.encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    StatusEvents {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of changes of the power sources.
+///
+/// See [`events`].
+pub struct StatusEvents {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for StatusEvents {
+    type Item = Status;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for StatusEvents {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
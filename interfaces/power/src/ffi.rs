@@ -0,0 +1,42 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xba, 0x5f, 0x02, 0xdd, 0xfc, 0xb8, 0x59, 0x3a, 0xac, 0x73, 0xc3, 0xc4, 0x56, 0xd5, 0x26, 0x01,
+    0x4a, 0x63, 0xb7, 0xc3, 0xed, 0x8b, 0xac, 0x8e, 0x2a, 0x16, 0xc0, 0x3e, 0x98, 0x66, 0xd8, 0x73,
+]);
+
+/// Message handled by the kernel.
+///
+/// On success, the machine stops and the message is never answered. It is answered with a
+/// [`PowerResponse`] containing an error if the request can't be fulfilled.
+#[derive(Debug, Encode, Decode)]
+pub enum PowerMessage {
+    /// Stops all the programs, then powers off the machine.
+    Shutdown,
+    /// Stops all the programs, then restarts the machine.
+    Reboot,
+    /// Puts the machine to sleep, keeping the programs in memory.
+    Suspend,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct PowerResponse {
+    pub result: Result<(), ErrorPayload>,
+}
@@ -0,0 +1,59 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Power management.
+//!
+//! This interface is handled by the kernel itself. Shutting down or rebooting first sends a
+//! shutdown notification to all the programs that handle an interface, and leaves them some
+//! time to flush their state.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+use futures::prelude::*;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Powers off the machine. The `Future` only resolves if the request has failed.
+pub fn shutdown() -> impl Future<Output = ErrorPayload> {
+    request(ffi::PowerMessage::Shutdown)
+}
+
+/// Restarts the machine. The `Future` only resolves if the request has failed.
+pub fn reboot() -> impl Future<Output = ErrorPayload> {
+    request(ffi::PowerMessage::Reboot)
+}
+
+/// Puts the machine to sleep. The `Future` only resolves if the request has failed.
+pub fn suspend() -> impl Future<Output = ErrorPayload> {
+    request(ffi::PowerMessage::Suspend)
+}
+
+fn request(msg: ffi::PowerMessage) -> impl Future<Output = ErrorPayload> {
+    let response = unsafe {
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    async move {
+        let response: ffi::PowerResponse = response.await;
+        match response.result {
+            Err(err) => err,
+            // `Ok` is never sent by the handler, as the program is stopped before it could
+            // receive it.
+            Ok(()) => future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash, Pid};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x03, 0x24, 0x28, 0xb8, 0x4b, 0xb0, 0x5d, 0x4b, 0x60, 0x28, 0x7d, 0xfb, 0x0f, 0xf4, 0xc7, 0x6e,
+    0x07, 0x78, 0x12, 0x73, 0x8d, 0xff, 0x4b, 0xfe, 0xf9, 0xd2, 0x30, 0xfc, 0xe4, 0xf1, 0x2a, 0xab,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum ProcessMessage {
+    /// Request the list of running processes. Must be answered with a [`ListResponse`].
+    List,
+    /// Load a module through the loader interface and start it as a child of the emitter. Must
+    /// be answered with a [`SpawnResponse`] once the process has started.
+    Spawn(Spawn),
+    /// Kill a process. Only the process itself, its ancestors and the programs that are part of
+    /// the kernel are allowed to kill it. Must be answered with a [`KillResponse`].
+    Kill(Pid),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ListResponse {
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Information about a running process.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ProcessInfo {
+    pub pid: Pid,
+    /// Process that has started this process, if any.
+    pub parent: Option<Pid>,
+    /// Name found in the metadata of the module, if any.
+    pub name: Option<String>,
+    /// SHA-256 digest of the module the process has been started from.
+    pub module_hash: [u8; 32],
+    /// Number of bytes of memory used by the process.
+    pub memory_bytes: u64,
+    /// Time spent executing the process, in nanoseconds, if known.
+    pub cpu_time_ns: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Spawn {
+    /// SHA-256 digest of the module to load.
+    pub module_hash: [u8; 32],
+    /// Arguments to pass to the process. Retrieved by the process through the `arguments`
+    /// interface.
+    pub arguments: Vec<String>,
+    /// Environment variables to pass to the process, as a list of keys and values. Retrieved by
+    /// the process through the `arguments` interface.
+    pub environment: Vec<(String, String)>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SpawnResponse {
+    /// On success, the identifier of the new process.
+    pub result: Result<Pid, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct KillResponse {
+    pub result: Result<(), ErrorPayload>,
+}
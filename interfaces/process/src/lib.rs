@@ -0,0 +1,70 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Process management.
+//!
+//! This interface is handled by the kernel itself, and allows listing, starting and killing
+//! processes.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::ProcessInfo;
+pub use redshirt_syscalls_interface::{ErrorPayload, Pid};
+
+pub mod ffi;
+
+/// Returns the list of running processes.
+pub fn list() -> impl Future<Output = Vec<ProcessInfo>> {
+    let response = unsafe {
+        let msg = ffi::ProcessMessage::List;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ListResponse| rep.processes)
+}
+
+/// Loads the module with the given hash and starts it as a child of the current process.
+///
+/// The new process can retrieve `arguments` and `environment` through the `arguments`
+/// interface.
+pub fn spawn(
+    module_hash: [u8; 32],
+    arguments: Vec<String>,
+    environment: Vec<(String, String)>,
+) -> impl Future<Output = Result<Pid, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::ProcessMessage::Spawn(ffi::Spawn {
+            module_hash,
+            arguments,
+            environment,
+        });
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::SpawnResponse| rep.result)
+}
+
+/// Kills a process. Only works on the current process and its descendants.
+pub fn kill(pid: Pid) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::ProcessMessage::Kill(pid);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::KillResponse| rep.result)
+}
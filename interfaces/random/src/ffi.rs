@@ -31,6 +31,9 @@ pub enum RandomMessage {
     /// errors about the length being too long to fit in memory. Call multiple times to obtain
     /// more.
     Generate { len: u16 },
+    /// Adds data coming from a source of entropy, such as a hardware random number generator,
+    /// to the state of the generator. No answer is expected.
+    AddEntropy(Vec<u8>),
 }
 
 #[derive(Debug, Encode, Decode)]
@@ -20,6 +20,7 @@
 
 extern crate alloc;
 
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 
 pub mod ffi;
@@ -35,6 +36,15 @@ pub async fn generate(len: usize) -> Vec<u8> {
     }
 }
 
+/// Passes data coming from a source of entropy, such as a hardware random number generator, to
+/// the handler, which mixes it into the state of its generator.
+pub fn add_entropy(data: Vec<u8>) {
+    unsafe {
+        let msg = ffi::RandomMessage::AddEntropy(data);
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
+    }
+}
+
 /// Fills `out` with randomly-generated data.
 #[cfg(feature = "std")]
 pub async fn generate_in(out: &mut [u8]) {
@@ -0,0 +1,61 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::InterfaceHash;
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x67, 0x76, 0xc0, 0x19, 0xee, 0x09, 0x8d, 0xd7, 0x9e, 0xb3, 0x0c, 0xd3, 0xe6, 0x09, 0xc9, 0x47,
+    0x97, 0x50, 0xc2, 0xad, 0xa2, 0xe1, 0x89, 0xe6, 0xc9, 0xf5, 0x1f, 0xb8, 0x31, 0xbf, 0x1a, 0xf6,
+]);
+
+/// Message sent to the handler of the sensors interface.
+#[derive(Debug, Encode, Decode)]
+pub enum SensorsMessage {
+    /// Returns the list of sensors of the machine and their current value. Answered with a
+    /// `Vec<Sensor>`.
+    Read,
+}
+
+/// Hardware sensor and its current value.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Sensor {
+    /// Human-readable name of the sensor, such as `CPU package`. Unique among the sensors of the
+    /// machine.
+    pub name: String,
+    pub reading: Reading,
+}
+
+/// Value measured by a sensor.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Reading {
+    Temperature {
+        /// Current temperature, in thousandths of degrees Celsius.
+        millicelsius: i32,
+        /// Temperature above which the hardware protects itself, for example by reducing its
+        /// frequency or shutting down, if known.
+        critical_millicelsius: Option<i32>,
+    },
+    FanSpeed {
+        /// Current speed of the fan, in revolutions per minute.
+        rpm: u32,
+    },
+    Voltage {
+        /// Current voltage, in millivolts.
+        millivolts: i32,
+    },
+}
@@ -0,0 +1,39 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hardware sensors.
+//!
+//! Call [`read`] to obtain the temperatures, fan speeds and voltages measured by the sensors of
+//! the machine, for example in order to monitor its thermal state.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use futures::prelude::*;
+
+pub use ffi::{Reading, Sensor};
+
+pub mod ffi;
+
+/// Returns the list of sensors of the machine and their current value.
+pub fn read() -> impl Future<Output = Vec<Sensor>> {
+    unsafe {
+        let msg = ffi::SensorsMessage::Read;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x9d, 0x6c, 0x1f, 0xc4, 0x49, 0x2b, 0xe6, 0x82, 0xbc, 0xc7, 0xc6, 0xf6, 0xc7, 0xcf, 0xae, 0x0c,
+    0xc8, 0xa4, 0x3e, 0x60, 0xa0, 0xac, 0x03, 0xbe, 0x83, 0xea, 0xda, 0x43, 0xbb, 0x24, 0xaf, 0xfe,
+]);
+
+/// Message sent to the handler of the serial interface.
+///
+/// Ports are identified by a number chosen by the handler.
+#[derive(Debug, Encode, Decode)]
+pub enum SerialMessage {
+    /// Returns the list of available ports. Answered with a [`ListPortsResponse`].
+    ListPorts,
+    /// Changes the line parameters of a port. Answered with a [`ConfigureResponse`].
+    Configure(Configure),
+    /// Sends data on a port. Answered with a [`WriteResponse`] once all the data has been handed
+    /// to the hardware.
+    Write(Write),
+    /// Waits for data to arrive on a port. Answered with a [`ReadResponse`] as soon as at least
+    /// one byte is available.
+    Read(Read),
+    /// Asks to be notified of changes of the modem status lines of the given port. The handler
+    /// sends a partial answer containing a [`ModemStatus`] with the current state, then another
+    /// one each time the state changes, and never sends a final answer. The message must be
+    /// cancelled in order to stop the notifications.
+    SubscribeModemStatus(u32),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListPortsResponse {
+    pub ports: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Configure {
+    pub port: u32,
+    /// Number of bits per second.
+    pub baud_rate: u32,
+    /// Number of data bits per character, between 5 and 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    /// Parity bit always set to 1.
+    Mark,
+    /// Parity bit always set to 0.
+    Space,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ConfigureResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Write {
+    pub port: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct WriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Read {
+    pub port: u32,
+    /// Maximum number of bytes to return.
+    pub max_len: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReadResponse {
+    /// On success, contains between 1 and `max_len` bytes.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
+
+/// State of the modem status lines.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ModemStatus {
+    /// Clear To Send.
+    pub cts: bool,
+    /// Data Set Ready.
+    pub dsr: bool,
+    /// Ring Indicator.
+    pub ring: bool,
+    /// Data Carrier Detect.
+    pub dcd: bool,
+}
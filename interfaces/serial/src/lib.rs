@@ -0,0 +1,122 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Serial ports.
+//!
+//! Call [`list_ports`] to obtain the available ports, then [`configure`] to set the line
+//! parameters of a port before using [`read`] and [`write`]. [`modem_status_events`] can be
+//! used to be notified when the other side toggles the modem status lines.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+pub use ffi::{Configure, ModemStatus, Parity, StopBits};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the list of available serial ports.
+pub fn list_ports() -> impl Future<Output = Vec<u32>> {
+    let response = unsafe {
+        let msg = ffi::SerialMessage::ListPorts;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ListPortsResponse| rep.ports)
+}
+
+/// Changes the baud rate, character size, parity and stop bits of a port.
+pub fn configure(config: Configure) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::SerialMessage::Configure(config);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ConfigureResponse| rep.result)
+}
+
+/// Sends data on a port. The `Future` is ready once all the data has been handed to the
+/// hardware.
+pub fn write(
+    port: u32,
+    data: impl Into<Vec<u8>>,
+) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::SerialMessage::Write(ffi::Write {
+            port,
+            data: data.into(),
+        });
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::WriteResponse| rep.result)
+}
+
+/// Waits for data to arrive on a port, and returns between 1 and `max_len` bytes.
+pub fn read(port: u32, max_len: u32) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::SerialMessage::Read(ffi::Read { port, max_len });
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ReadResponse| rep.result)
+}
+
+/// Returns a `Stream` that yields the state of the modem status lines of a port, then a new
+/// state every time it changes. The subscription is cancelled when the [`ModemStatusEvents`]
+/// is destroyed.
+pub fn modem_status_events(port: u32) -> ModemStatusEvents {
+    let msg_id = unsafe {
+        let msg = ffi::SerialMessage::SubscribeModemStatus(port).encode();
+        redshirt_syscalls_interface::MessageBuilder::new()
+            .add_data(&msg)
+            .emit_with_response_raw(&ffi::INTERFACE)
+            .unwrap()
+    };
+
+    ModemStatusEvents {
+        msg_id,
+        responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+    }
+}
+
+/// Stream of modem status changes.
+///
+/// See [`modem_status_events`].
+pub struct ModemStatusEvents {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for ModemStatusEvents {
+    type Item = ModemStatus;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message.decode().ok()),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for ModemStatusEvents {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x9f, 0xf0, 0xa6, 0xdc, 0x5a, 0xc6, 0xe9, 0x5a, 0x97, 0xeb, 0x12, 0x68, 0x15, 0x53, 0xf3, 0xf7,
+    0x80, 0x1b, 0xd3, 0xf6, 0xbf, 0x99, 0x25, 0x56, 0x1f, 0x9c, 0x5b, 0x74, 0x86, 0xb4, 0x54, 0x9e,
+]);
+
+/// Message sent to the handler of the SPI interface.
+///
+/// Buses are identified by a number chosen by the handler.
+#[derive(Debug, Encode, Decode)]
+pub enum SpiMessage {
+    /// Returns the list of available buses. Answered with a [`ListBusesResponse`].
+    ListBuses,
+    /// Sends and receives data. Answered with a [`TransferResponse`].
+    Transfer(Transfer),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListBusesResponse {
+    pub buses: Vec<BusInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct BusInfo {
+    pub bus: u32,
+    /// Number of chip select lines. Valid chip selects are between 0 and this value excluded.
+    pub num_chip_selects: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Transfer {
+    pub bus: u32,
+    /// Chip select line of the device. The line is asserted for the whole duration of the
+    /// transfer, and deasserted afterwards.
+    pub chip_select: u8,
+    pub mode: Mode,
+    /// Maximum frequency of the clock, in Hz. The handler picks the closest frequency that the
+    /// controller supports below this value.
+    pub clock_hz: u32,
+    /// Bytes to send. The same number of bytes are received.
+    pub data: Vec<u8>,
+}
+
+/// Clock polarity and phase.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Mode {
+    /// Clock idle low, data sampled on the rising edge.
+    Mode0,
+    /// Clock idle low, data sampled on the falling edge.
+    Mode1,
+    /// Clock idle high, data sampled on the falling edge.
+    Mode2,
+    /// Clock idle high, data sampled on the rising edge.
+    Mode3,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TransferResponse {
+    /// On success, contains the bytes received during the transfer.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
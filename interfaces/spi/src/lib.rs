@@ -0,0 +1,51 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SPI buses.
+//!
+//! Call [`list_buses`] to obtain the available buses and their number of chip select lines,
+//! then [`transfer`] to exchange data with the devices connected to a bus.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use futures::prelude::*;
+
+pub use ffi::{BusInfo, Mode, Transfer};
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Returns the list of available SPI buses.
+pub fn list_buses() -> impl Future<Output = Vec<BusInfo>> {
+    let response = unsafe {
+        let msg = ffi::SpiMessage::ListBuses;
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::ListBusesResponse| rep.buses)
+}
+
+/// Selects a device, sends the data of the transfer while receiving the same number of bytes,
+/// then deselects the device. Returns the bytes received.
+pub fn transfer(transfer: Transfer) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+    let response = unsafe {
+        let msg = ffi::SpiMessage::Transfer(transfer);
+        redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: ffi::TransferResponse| rep.result)
+}
@@ -14,20 +14,18 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use alloc::string::String;
-use parity_scale_codec::{Decode, Encode};
-use redshirt_syscalls_interface::InterfaceHash;
 
-// TODO: this has been randomly generated; instead should be a hash or something
-pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
-    0xa6, 0xbc, 0x8d, 0xc3, 0x43, 0xbd, 0xdd, 0x3b, 0x44, 0x2f, 0x06, 0x40, 0xa8, 0x40, 0xad, 0x4f,
-    0x25, 0x57, 0x22, 0x91, 0x79, 0xc8, 0x16, 0x07, 0x6f, 0xab, 0xa9, 0xd6, 0x38, 0xca, 0x01, 0x8b,
-]);
+redshirt_interface_macro::redshirt_interface! {
+    // TODO: this has been randomly generated; instead should be a hash or something
+    interface StdoutMessage, StdoutHandler = [
+        0xa6, 0xbc, 0x8d, 0xc3, 0x43, 0xbd, 0xdd, 0x3b, 0x44, 0x2f, 0x06, 0x40, 0xa8, 0x40, 0xad,
+        0x4f, 0x25, 0x57, 0x22, 0x91, 0x79, 0xc8, 0x16, 0x07, 0x6f, 0xab, 0xa9, 0xd6, 0x38, 0xca,
+        0x01, 0x8b,
+    ];
 
-#[derive(Debug, Encode, Decode)]
-pub enum StdoutMessage {
     /// Send text to print on stdout.
     ///
     /// > **Note**: There's no concept of piping, and stdout is meant to be used only for
     /// >           interfacing with the user.
-    Message(String),
+    fn message(text: String);
 }
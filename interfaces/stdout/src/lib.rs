@@ -31,8 +31,5 @@ pub mod ffi;
 /// In order to follow the Unix world, the character `\n` (LF, 0xA) means "new line". The
 /// character `\r` (CR, 0xD) is ignored.
 pub fn stdout(msg: String) {
-    unsafe {
-        let msg = ffi::StdoutMessage::Message(msg);
-        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg).unwrap();
-    }
+    ffi::message(msg)
 }
@@ -0,0 +1,117 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::Pid;
+use alloc::vec::Vec;
+use core::{convert::TryFrom as _, fmt};
+
+/// Handle to a blob.
+///
+/// A blob is an immutable buffer of at most [`MAX_BLOB_SIZE`](crate::MAX_BLOB_SIZE) bytes kept
+/// by the kernel. Handles can be passed to other processes within messages. Only the processes
+/// the blob has been explicitly shared with, using [`share_blob`], can then read it.
+#[derive(
+    Copy, Clone, PartialEq, Eq, Hash, parity_scale_codec::Encode, parity_scale_codec::Decode,
+)]
+pub struct BlobHandle(u64);
+
+impl From<u64> for BlobHandle {
+    fn from(id: u64) -> BlobHandle {
+        BlobHandle(id)
+    }
+}
+
+impl From<BlobHandle> for u64 {
+    fn from(handle: BlobHandle) -> u64 {
+        handle.0
+    }
+}
+
+impl fmt::Debug for BlobHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Creates a blob containing a copy of `data`.
+///
+/// Returns `None` if `data` is larger than [`MAX_BLOB_SIZE`](crate::MAX_BLOB_SIZE).
+///
+/// The blob is automatically destroyed when the current process terminates, or can be destroyed
+/// earlier with [`release_blob`].
+pub fn create_blob(data: &[u8]) -> Option<BlobHandle> {
+    unsafe {
+        let mut handle = 0;
+        let len = u32::try_from(data.len()).ok()?;
+        match crate::ffi::blob_create(data.as_ptr(), len, &mut handle) {
+            0 => Some(BlobHandle(handle)),
+            _ => None,
+        }
+    }
+}
+
+/// Allows the process `pid` to read the given blob.
+///
+/// Returns an error if the handle is invalid or if the blob hasn't been created by the current
+/// process.
+pub fn share_blob(handle: BlobHandle, pid: Pid) -> Result<(), ()> {
+    unsafe {
+        let pid = u64::from(pid);
+        match crate::ffi::blob_share(&handle.0, &pid) {
+            0 => Ok(()),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Returns a copy of the content of the given blob, or `None` if the handle is invalid or if the
+/// blob hasn't been shared with the current process.
+pub fn read_blob(handle: BlobHandle) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        match read_blob_into(handle, &mut out)? {
+            len if len <= out.len() => {
+                out.truncate(len);
+                return Some(out);
+            }
+            len => out.resize(len, 0),
+        }
+    }
+}
+
+/// Writes the content of the given blob at the start of `out`.
+///
+/// Returns the size of the blob, or `None` if the handle is invalid or if the blob hasn't been
+/// shared with the current process. If the returned value is larger than the length of `out`,
+/// then nothing has been written.
+pub fn read_blob_into(handle: BlobHandle, out: &mut [u8]) -> Option<usize> {
+    unsafe {
+        let out_len = u32::try_from(out.len()).unwrap();
+        match crate::ffi::blob_read(&handle.0, out.as_mut_ptr(), out_len) {
+            0xffffffff => None,
+            len => Some(usize::try_from(len).unwrap()),
+        }
+    }
+}
+
+/// Destroys the given blob.
+///
+/// Does nothing if the handle is invalid or if the blob hasn't been created by the current
+/// process.
+pub fn release_blob(handle: BlobHandle) {
+    unsafe {
+        crate::ffi::blob_release(&handle.0);
+    }
+}
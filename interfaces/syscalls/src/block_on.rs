@@ -34,7 +34,9 @@
 //!   Repeat until the `Future` has ended.
 //!
 
-use crate::{Decode, EncodedMessage, InterfaceOrDestroyed, Message, MessageId, ResponseMessage};
+use crate::{
+    Decode, EncodedMessage, InterfaceOrDestroyed, Message, MessageId, Pid, ResponseMessage,
+};
 use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use core::{
     sync::atomic::{AtomicBool, Ordering},
@@ -66,6 +68,14 @@ pub(crate) fn register_message_waker(message_id: MessageId, waker: Waker) {
 
     state.message_ids.push(From::from(message_id));
     state.wakers.push(waker);
+
+    // The lock is released before waking up the executor, in case it calls functions of this
+    // module.
+    let executor_waker = state.executor_waker.take();
+    drop(state);
+    if let Some(executor_waker) = executor_waker {
+        executor_waker.wake();
+    }
 }
 
 /// Removes one element from the global buffer of interface messages waiting to be processed.
@@ -75,9 +85,42 @@ pub(crate) fn peek_interface_message() -> Option<InterfaceOrDestroyed> {
 }
 
 /// If a response to this message ID has previously been obtained, extracts it for processing.
+///
+/// If multiple responses have been obtained (because of partial answers or chunks), they are
+/// extracted in the order in which they have been received.
 pub(crate) fn peek_response(msg_id: MessageId) -> Option<ResponseMessage> {
     let mut state = (&*STATE).lock();
-    state.pending_messages.remove(&msg_id)
+    let queue = state.pending_messages.get_mut(&msg_id)?;
+    let response = queue.pop_front();
+    if queue.is_empty() {
+        state.pending_messages.remove(&msg_id);
+    }
+    response
+}
+
+/// Returns true if the final response to this message ID has previously been obtained. If that
+/// is the case, [`peek_response`] can be called until the final response is extracted.
+pub(crate) fn is_response_complete(msg_id: MessageId) -> bool {
+    let state = (&*STATE).lock();
+    state.pending_messages.get(&msg_id).map_or(false, |queue| {
+        queue
+            .iter()
+            .any(|response| !response.partial && !response.chunk)
+    })
+}
+
+/// Adds a response obtained by calling [`next_message`] directly to the responses waiting to be
+/// processed.
+pub(crate) fn push_response(response: ResponseMessage) {
+    #[cfg(feature = "trace")]
+    crate::trace::record_response(&response);
+
+    let mut state = (&*STATE).lock();
+    state
+        .pending_messages
+        .entry(response.message_id)
+        .or_insert_with(VecDeque::new)
+        .push_back(response);
 }
 
 /// Blocks the current thread until the [`Future`](core::future::Future) passed as parameter
@@ -116,53 +159,127 @@ pub fn block_on<T>(future: impl Future<Output = T>) -> T {
             }
         }
 
-        let mut state = (&*STATE).lock();
-        debug_assert_eq!(state.message_ids.len(), state.wakers.len());
+        process_messages(true);
+    }
+}
+
+/// Pulls from the kernel the messages that the futures of this crate are waiting for, and wakes
+/// up the corresponding `Waker`s. Never blocks.
+///
+/// [`block_on`] already does this. This function only needs to be called in order to drive the
+/// futures of this crate with a different executor, for example in the main loop of a program
+/// that doesn't use [`block_on`]. After a future returns `Poll::Pending`, its `Waker` will only
+/// be invoked during a call to this function, to [`wait_messages`] or to [`block_on`].
+pub fn poll_messages() {
+    process_messages(false);
+}
+
+/// Same as [`poll_messages`], except that the current thread is blocked until at least one of
+/// the messages that the futures of this crate are waiting for is available.
+///
+/// Meant to be called by an executor that has nothing else to do. Returns immediately if no
+/// future of this crate is waiting for a message.
+pub fn wait_messages() {
+    let any_waiting = !(&*STATE).lock().message_ids.is_empty();
+    if any_waiting {
+        process_messages(true);
+    }
+}
+
+/// Registers a `Waker` that is woken up the next time one of the futures of this crate starts
+/// waiting for a message from the kernel.
+///
+/// Meant to be used by executors other than [`block_on`]. Once woken up, the executor must
+/// call [`poll_messages`] or [`wait_messages`], otherwise the future will never make progress.
+///
+/// The `Waker` is only woken up once, and must then be registered again. Registering a `Waker`
+/// replaces the one previously registered.
+pub fn register_executor_waker(waker: Waker) {
+    let mut state = (&*STATE).lock();
+    state.executor_waker = Some(waker);
+}
+
+/// Pulls the pending messages from the kernel and dispatches them.
+///
+/// If `block` is true, blocks the thread until at least one message is available.
+fn process_messages(block: bool) {
+    let mut state = (&*STATE).lock();
+    debug_assert_eq!(state.message_ids.len(), state.wakers.len());
+
+    // `block` indicates whether we should block the thread or just peek. Only ever `true` during
+    // the first iteration.
+    let mut block = block;
 
-        // `block` indicates whether we should block the thread or just peek. Always `true` during
-        // the first iteration, and `false` in further iterations.
-        let mut block = true;
+    // We process in a loop all pending messages, pulling as many of them as possible at once.
+    while let Some(msgs) = next_messages(&mut state.message_ids, block) {
+        block = false;
 
-        // We process in a loop all pending messages.
-        while let Some(msg) = next_message(&mut state.message_ids, block) {
-            block = false;
+        // The kernel has zeroed the entries corresponding to the messages. Remove them,
+        // starting from the end so that the indices stay valid.
+        let mut indices = msgs.iter().map(index_in_list).collect::<Vec<_>>();
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        for index in indices {
+            let _was_in = state.message_ids.remove(index);
+            debug_assert_eq!(_was_in, 0); // Value is zero-ed by the kernel.
+
+            let waker = state.wakers.remove(index);
+            waker.wake();
+        }
 
+        for msg in msgs {
             match msg {
                 Message::Response(msg) => {
-                    let _was_in = state.message_ids.remove(msg.index_in_list as usize);
-                    debug_assert_eq!(_was_in, 0); // Value is zero-ed by the kernel.
+                    #[cfg(feature = "trace")]
+                    crate::trace::record_response(&msg);
 
-                    let waker = state.wakers.remove(msg.index_in_list as usize);
-                    waker.wake();
-
-                    let _was_in = state.pending_messages.insert(msg.message_id, msg);
-                    debug_assert!(_was_in.is_none());
+                    state
+                        .pending_messages
+                        .entry(msg.message_id)
+                        .or_insert_with(VecDeque::new)
+                        .push_back(msg);
                 }
-                Message::Interface(msg) => {
-                    let _was_in = state.message_ids.remove(msg.index_in_list as usize);
-                    debug_assert_eq!(_was_in, 0); // Value is zero-ed by the kernel.
-
-                    let waker = state.wakers.remove(msg.index_in_list as usize);
-                    waker.wake();
+                Message::Interface(mut msg) => {
+                    // Reassemble the messages that have been split into chunks.
+                    let key = (msg.emitter_pid, msg.interface);
+                    if msg.chunk {
+                        state
+                            .interface_chunks
+                            .entry(key)
+                            .or_insert_with(Vec::new)
+                            .extend_from_slice(&msg.actual_data);
+                        continue;
+                    }
+                    if let Some(mut data) = state.interface_chunks.remove(&key) {
+                        data.extend_from_slice(&msg.actual_data);
+                        msg.actual_data = data;
+                    }
 
                     let msg = InterfaceOrDestroyed::Interface(msg);
                     state.interface_messages_queue.push_back(msg);
                 }
                 Message::ProcessDestroyed(msg) => {
-                    let _was_in = state.message_ids.remove(msg.index_in_list as usize);
-                    debug_assert_eq!(_was_in, 0); // Value is zero-ed by the kernel.
-
-                    let waker = state.wakers.remove(msg.index_in_list as usize);
-                    waker.wake();
+                    // Discard the incomplete messages of the destroyed process.
+                    let pid = msg.pid;
+                    state
+                        .interface_chunks
+                        .retain(|(emitter, _), _| *emitter != pid);
 
                     let msg = InterfaceOrDestroyed::ProcessDestroyed(msg);
                     state.interface_messages_queue.push_back(msg);
                 }
+                Message::Shutdown(msg) => {
+                    let msg = InterfaceOrDestroyed::Shutdown(msg);
+                    state.interface_messages_queue.push_back(msg);
+                }
+                Message::MessageCancelled(msg) => {
+                    let msg = InterfaceOrDestroyed::MessageCancelled(msg);
+                    state.interface_messages_queue.push_back(msg);
+                }
             };
         }
-
-        debug_assert!(!block);
     }
+
+    debug_assert!(!block);
 }
 
 lazy_static::lazy_static! {
@@ -173,7 +290,9 @@ lazy_static::lazy_static! {
             message_ids: Vec::new(),
             wakers: Vec::new(),
             pending_messages: HashMap::with_capacity(6),
+            interface_chunks: HashMap::new(),
             interface_messages_queue: VecDeque::with_capacity(2),
+            executor_waker: None,
         })
     };
 }
@@ -196,7 +315,14 @@ struct BlockOnState {
     /// > **Note**: We have to maintain this queue as a global variable rather than a per-future
     /// >           channel, otherwise dropping a `Future` would silently drop messages that have
     /// >           already been received.
-    pending_messages: HashMap<MessageId, ResponseMessage>,
+    ///
+    /// There can be more than one response per message if the handler sends partial answers or
+    /// splits its answers into chunks.
+    pending_messages: HashMap<MessageId, VecDeque<ResponseMessage>>,
+
+    /// Chunks of interface messages received so far, grouped by emitter and interface. See
+    /// [`InterfaceMessage::chunk`](crate::ffi::InterfaceMessage::chunk).
+    interface_chunks: HashMap<(Pid, [u8; 32]), Vec<u8>>,
 
     /// Queue of interface messages waiting to be delivered.
     ///
@@ -204,6 +330,55 @@ struct BlockOnState {
     /// >           channel, otherwise dropping a `Future` would silently drop messages that have
     /// >           already been received.
     interface_messages_queue: VecDeque<InterfaceOrDestroyed>,
+
+    /// `Waker` registered with [`register_executor_waker`].
+    executor_waker: Option<Waker>,
+}
+
+/// Maximum number of messages that [`next_messages`] pulls at once.
+const MAX_MESSAGES_AT_ONCE: u32 = 32;
+
+/// Same as [`next_message`], but pulls up to [`MAX_MESSAGES_AT_ONCE`] messages at once.
+///
+/// If `block` is true, then the return value is always `Some`. The returned list is never empty.
+///
+/// See the [`next_messages`](crate::ffi::next_messages) FFI function for the semantics of
+/// `to_poll`.
+fn next_messages(to_poll: &mut [u64], block: bool) -> Option<Vec<Message>> {
+    unsafe {
+        let mut out = Vec::with_capacity(256);
+        loop {
+            let ret = crate::ffi::next_messages(
+                to_poll.as_mut_ptr(),
+                to_poll.len() as u32,
+                out.as_mut_ptr(),
+                out.capacity() as u32,
+                block,
+                MAX_MESSAGES_AT_ONCE,
+            ) as usize;
+            if ret == 0 {
+                return None;
+            }
+            if ret > out.capacity() {
+                out.reserve(ret);
+                continue;
+            }
+            out.set_len(ret);
+            return Some(Decode::decode(EncodedMessage(out)).unwrap());
+        }
+    }
+}
+
+/// Returns the `index_in_list` field of the given message.
+fn index_in_list(message: &Message) -> usize {
+    let index = match message {
+        Message::Interface(msg) => msg.index_in_list,
+        Message::Response(msg) => msg.index_in_list,
+        Message::ProcessDestroyed(msg) => msg.index_in_list,
+        Message::Shutdown(msg) => msg.index_in_list,
+        Message::MessageCancelled(msg) => msg.index_in_list,
+    };
+    index as usize
 }
 
 /// Checks whether a new message arrives, optionally blocking the thread.
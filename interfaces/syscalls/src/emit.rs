@@ -13,9 +13,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{Decode, Encode, EncodedMessage, InterfaceHash, MessageId};
+use crate::{Decode, Encode, EncodedMessage, InterfaceHash, MessageId, Pid};
+use alloc::vec::Vec;
 use byteorder::{ByteOrder as _, LittleEndian};
 use core::{
+    cmp,
     convert::TryFrom as _,
     fmt,
     marker::PhantomData,
@@ -30,12 +32,21 @@ use generic_array::{
     ArrayLength, GenericArray,
 };
 
+/// Buffer where messages are encoded before being emitted, in order to avoid allocating a new
+/// buffer for each message. The kernel copies the message when it is emitted, so the buffer can
+/// be reused immediately afterwards.
+pub(crate) static ENCODE_BUFFER: spin::Mutex<Vec<u8>> = spin::Mutex::new(Vec::new());
+
 /// Prototype for a message in construction.
 ///
 /// Use this struct if you want to send out a message split between multiple slices.
 pub struct MessageBuilder<'a, TLen: ArrayLength<u8>> {
     /// Parameter for the FFI function.
     allow_delay: bool,
+    /// If true, use `try_emit_message` rather than `emit_message`.
+    no_block: bool,
+    /// If `Some`, use `emit_message_with_deadline` rather than `emit_message`.
+    deadline: Option<u64>,
     /// Array of slices, passed to the FFI function.
     array: GenericArray<u8, TLen>,
     /// Pin the lifetime. The lifetime corresponds to the lifetime of buffers pointer to
@@ -48,6 +59,8 @@ impl<'a> MessageBuilder<'a, U0> {
     pub fn new() -> Self {
         MessageBuilder {
             allow_delay: true,
+            no_block: false,
+            deadline: None,
             array: Default::default(),
             marker: PhantomData,
         }
@@ -65,11 +78,52 @@ where
         self
     }
 
+    /// If called, emitting the message will fail with [`EmitErr::WouldBlock`] if no interface
+    /// handler is available or if the handler has too many messages waiting to be processed.
+    ///
+    /// This is meant for programs, such as drivers, that prefer dropping work rather than
+    /// buffering it indefinitely.
+    ///
+    /// > **Note**: Messages larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) can't be
+    /// >           split into chunks in this mode, and are always refused with
+    /// >           [`EmitErr::WouldBlock`].
+    pub fn with_no_block(mut self) -> Self {
+        self.allow_delay = false;
+        self.no_block = true;
+        self
+    }
+
+    /// Sets the value of the monotonic clock, in nanoseconds, after which the message is
+    /// automatically answered if the handler hasn't answered it yet.
+    ///
+    /// The automatic answer is an encoded `Err` containing an [`ErrorPayload`](crate::ErrorPayload)
+    /// whose class is [`ErrorClass::TIMED_OUT`](crate::ErrorClass::TIMED_OUT), and is therefore
+    /// meant for interfaces whose answers are of type `Result<_, ErrorPayload>`.
+    ///
+    /// The deadline is also passed to the handler. It is ignored for messages that don't expect
+    /// an answer.
+    pub fn with_deadline(mut self, monotonic_ns: u64) -> Self {
+        self.deadline = Some(monotonic_ns);
+        self
+    }
+
     /// Append a slice of message data to the builder.
     ///
     /// > **Note**: This operation is cheap and doesn't perform any copy of the message data
     /// >           itself.
     pub fn add_data<TOutLen>(self, buffer: &'a EncodedMessage) -> MessageBuilder<'a, TOutLen>
+    where
+        TLen: core::ops::Add<U8, Output = TOutLen>,
+        TOutLen: ArrayLength<u8>,
+    {
+        self.add_data_raw(&buffer.0)
+    }
+
+    /// Append a slice of already-encoded message data to the builder.
+    ///
+    /// > **Note**: This operation is cheap and doesn't perform any copy of the message data
+    /// >           itself.
+    pub fn add_data_raw<TOutLen>(self, buffer: &'a [u8]) -> MessageBuilder<'a, TOutLen>
     where
         TLen: core::ops::Add<U8, Output = TOutLen>,
         TOutLen: ArrayLength<u8>,
@@ -77,12 +131,14 @@ where
         let mut new_pair = GenericArray::<u8, U8>::default();
         LittleEndian::write_u32(
             &mut new_pair[0..4],
-            u32::try_from(buffer.0.as_ptr() as usize).unwrap(),
+            u32::try_from(buffer.as_ptr() as usize).unwrap(),
         );
-        LittleEndian::write_u32(&mut new_pair[4..8], u32::try_from(buffer.0.len()).unwrap());
+        LittleEndian::write_u32(&mut new_pair[4..8], u32::try_from(buffer.len()).unwrap());
 
         MessageBuilder {
             allow_delay: self.allow_delay,
+            no_block: self.no_block,
+            deadline: self.deadline,
             array: self.array.concat(new_pair),
             marker: self.marker,
         }
@@ -105,6 +161,22 @@ where
         })
     }
 
+    /// Emit the message and returns a `Stream` that will yield all the answers, for interfaces
+    /// whose handler sends partial answers.
+    ///
+    /// The returned stream will cancel the message if it is dropped before the final answer.
+    // TODO: could we remove the error type?
+    pub unsafe fn emit_with_response_stream(
+        self,
+        interface: &InterfaceHash,
+    ) -> Result<impl Stream<Item = EncodedMessage>, EmitErr> {
+        let msg_id = self.emit_with_response_raw(interface)?;
+        Ok(EmitMessageWithResponseStream {
+            inner: crate::message_response_stream(msg_id),
+            msg_id,
+        })
+    }
+
     /// Emit the message and returns the emitted [`MessageId`].
     // TODO: could we remove the error type?
     pub unsafe fn emit_with_response_raw(
@@ -127,33 +199,239 @@ where
     ///
     /// If `needs_answer` is `true`, then on success a `Some` will always be returned.
     /// If `needs_answer` is `false`, then on success a `None` will always be returned.
+    ///
+    /// If the message is larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE), it is
+    /// split into chunks that the receiver reassembles.
     // TODO: could we remove the error type?
     pub unsafe fn emit_raw(
         self,
         interface: &InterfaceHash,
         needs_answer: bool,
     ) -> Result<Option<MessageId>, EmitErr> {
-        let mut message_id_out = MaybeUninit::uninit();
+        let total_size = self
+            .array
+            .chunks(8)
+            .map(|pair| LittleEndian::read_u32(&pair[4..8]) as usize)
+            .sum::<usize>();
+        if total_size <= crate::MAX_MESSAGE_SIZE {
+            let result = emit_bufs(
+                interface,
+                &self.array,
+                needs_answer,
+                self.allow_delay,
+                self.no_block,
+                self.deadline,
+            );
+            #[cfg(feature = "trace")]
+            trace_emit(interface, &result, total_size);
+            return result;
+        }
 
-        let ret = crate::ffi::emit_message(
-            interface as *const InterfaceHash as *const _,
-            self.array.as_ptr(),
-            u32::try_from(self.array.len() / 8).unwrap(),
+        // Emitting chunks could leave a partial message behind if the last part is refused.
+        if self.no_block {
+            return Err(EmitErr::WouldBlock);
+        }
+
+        // The message is too large. Emit everything except the last chunk with
+        // `emit_message_chunk`.
+        let current = split_into_chunks(&self.array, |chunk| {
+            crate::ffi::emit_message_chunk(
+                interface as *const InterfaceHash as *const _,
+                chunk.as_ptr(),
+                u32::try_from(chunk.len() / 8).unwrap(),
+                self.allow_delay,
+            ) == 0
+        })
+        .map_err(|()| EmitErr::BadInterface)?;
+
+        let result = emit_bufs(
+            interface,
+            &current,
             needs_answer,
             self.allow_delay,
-            message_id_out.as_mut_ptr(),
+            false,
+            self.deadline,
         );
+        #[cfg(feature = "trace")]
+        trace_emit(interface, &result, total_size);
+        result
+    }
+
+    /// Emit the message directly to the given process, which must have called
+    /// [`accept_directed_messages`].
+    ///
+    /// If `needs_answer` is `true`, then on success a `Some` will always be returned.
+    /// If `needs_answer` is `false`, then on success a `None` will always be returned.
+    ///
+    /// If the message is larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE), it is
+    /// split into chunks that the receiver reassembles. The deadline of the builder is taken
+    /// into account if an answer is needed, while the delay settings are ignored.
+    // TODO: could we remove the error type?
+    pub unsafe fn emit_directed_raw(
+        self,
+        target: Pid,
+        needs_answer: bool,
+    ) -> Result<Option<MessageId>, EmitErr> {
+        let target_raw = u64::from(target);
+        let mut message_id_out = MaybeUninit::uninit();
+
+        // Emit everything except the last chunk with `emit_directed_message_chunk` if the
+        // message is too large.
+        let last = split_into_chunks(&self.array, |chunk| {
+            crate::ffi::emit_directed_message_chunk(
+                &target_raw,
+                chunk.as_ptr(),
+                u32::try_from(chunk.len() / 8).unwrap(),
+            ) == 0
+        })
+        .map_err(|()| EmitErr::NotAccepted)?;
+
+        let ret = if let (Some(deadline), true) = (self.deadline, needs_answer) {
+            crate::ffi::emit_directed_message_with_deadline(
+                &target_raw,
+                last.as_ptr(),
+                u32::try_from(last.len() / 8).unwrap(),
+                deadline,
+                message_id_out.as_mut_ptr(),
+            )
+        } else {
+            crate::ffi::emit_directed_message(
+                &target_raw,
+                last.as_ptr(),
+                u32::try_from(last.len() / 8).unwrap(),
+                needs_answer,
+                message_id_out.as_mut_ptr(),
+            )
+        };
 
         if ret != 0 {
-            return Err(EmitErr::BadInterface);
+            return Err(EmitErr::NotAccepted);
         }
 
-        if needs_answer {
-            Ok(Some(MessageId::from(message_id_out.assume_init())))
+        let message_id = if needs_answer {
+            Some(MessageId::from(message_id_out.assume_init()))
         } else {
-            Ok(None)
+            None
+        };
+
+        #[cfg(feature = "trace")]
+        crate::trace::record(crate::trace::TraceEvent::EmitDirected {
+            target,
+            message_id,
+            size: self
+                .array
+                .chunks(8)
+                .map(|pair| LittleEndian::read_u32(&pair[4..8]))
+                .sum(),
+        });
+
+        Ok(message_id)
+    }
+}
+
+/// Records the emission of a message in the trace, if it has succeeded.
+#[cfg(feature = "trace")]
+fn trace_emit(interface: &InterfaceHash, result: &Result<Option<MessageId>, EmitErr>, size: usize) {
+    if let Ok(message_id) = result {
+        crate::trace::record(crate::trace::TraceEvent::Emit {
+            interface: interface.clone(),
+            message_id: *message_id,
+            size: u32::try_from(size).unwrap(),
+        });
+    }
+}
+
+/// Splits the list of buffers `array` into chunks of at most
+/// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) bytes, splitting the buffers if necessary.
+///
+/// All the chunks except the last one are passed to `emit_chunk`, which must return `false` if
+/// the chunk couldn't be emitted. Returns the list of buffers of the last chunk, or an error if
+/// `emit_chunk` has failed.
+fn split_into_chunks(
+    array: &[u8],
+    mut emit_chunk: impl FnMut(&[u8]) -> bool,
+) -> Result<Vec<u8>, ()> {
+    let mut current = Vec::with_capacity(array.len());
+    let mut current_size = 0;
+    for pair in array.chunks(8) {
+        let mut ptr = LittleEndian::read_u32(&pair[0..4]);
+        let mut len = LittleEndian::read_u32(&pair[4..8]) as usize;
+
+        while len != 0 {
+            if current_size == crate::MAX_MESSAGE_SIZE {
+                if !emit_chunk(&current) {
+                    return Err(());
+                }
+                current.clear();
+                current_size = 0;
+            }
+
+            let take = cmp::min(len, crate::MAX_MESSAGE_SIZE - current_size);
+            let mut new_pair = [0; 8];
+            LittleEndian::write_u32(&mut new_pair[0..4], ptr);
+            LittleEndian::write_u32(&mut new_pair[4..8], u32::try_from(take).unwrap());
+            current.extend_from_slice(&new_pair);
+            current_size += take;
+            ptr += u32::try_from(take).unwrap();
+            len -= take;
         }
     }
+
+    Ok(current)
+}
+
+/// Calls `emit_message`, `try_emit_message` if `no_block` is true, or
+/// `emit_message_with_deadline` if a deadline is passed and an answer is needed, with the given
+/// list of buffers. See [`MessageBuilder::emit_raw`].
+unsafe fn emit_bufs(
+    interface: &InterfaceHash,
+    bufs: &[u8],
+    needs_answer: bool,
+    allow_delay: bool,
+    no_block: bool,
+    deadline: Option<u64>,
+) -> Result<Option<MessageId>, EmitErr> {
+    let mut message_id_out = MaybeUninit::uninit();
+
+    let ret = if let (Some(deadline), true, false) = (deadline, needs_answer, no_block) {
+        crate::ffi::emit_message_with_deadline(
+            interface as *const InterfaceHash as *const _,
+            bufs.as_ptr(),
+            u32::try_from(bufs.len() / 8).unwrap(),
+            allow_delay,
+            deadline,
+            message_id_out.as_mut_ptr(),
+        )
+    } else if no_block {
+        crate::ffi::try_emit_message(
+            interface as *const InterfaceHash as *const _,
+            bufs.as_ptr(),
+            u32::try_from(bufs.len() / 8).unwrap(),
+            needs_answer,
+            message_id_out.as_mut_ptr(),
+        )
+    } else {
+        crate::ffi::emit_message(
+            interface as *const InterfaceHash as *const _,
+            bufs.as_ptr(),
+            u32::try_from(bufs.len() / 8).unwrap(),
+            needs_answer,
+            allow_delay,
+            message_id_out.as_mut_ptr(),
+        )
+    };
+
+    match ret {
+        0 => {}
+        2 => return Err(EmitErr::WouldBlock),
+        _ => return Err(EmitErr::BadInterface),
+    }
+
+    if needs_answer {
+        Ok(Some(MessageId::from(message_id_out.assume_init())))
+    } else {
+        Ok(None)
+    }
 }
 
 impl<'a> Default for MessageBuilder<'a, U0> {
@@ -187,9 +465,34 @@ pub unsafe fn emit_message_without_response<'a>(
     interface: &InterfaceHash,
     msg: impl Encode,
 ) -> Result<(), EmitErr> {
-    let msg = msg.encode();
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
+    MessageBuilder::new()
+        .add_data_raw(&buffer)
+        .emit_without_response(interface)
+}
+
+/// Emits a message destined to the handler of the given interface, or fails immediately with
+/// [`EmitErr::WouldBlock`] if no handler is available or if the handler is overloaded.
+///
+/// See [`MessageBuilder::with_no_block`].
+///
+/// # Safety
+///
+/// While the action of sending a message is totally safe, the message itself might instruct the
+/// environment to perform actions that would lead to unsafety.
+///
+pub unsafe fn try_emit_message_without_response(
+    interface: &InterfaceHash,
+    msg: impl Encode,
+) -> Result<(), EmitErr> {
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
     MessageBuilder::new()
-        .add_data(&msg)
+        .with_no_block()
+        .add_data_raw(&buffer)
         .emit_without_response(interface)
 }
 
@@ -211,16 +514,109 @@ pub unsafe fn emit_message_with_response<'a, T: Decode>(
     interface: &InterfaceHash,
     msg: impl Encode,
 ) -> Result<impl Future<Output = T>, EmitErr> {
-    let msg = msg.encode();
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
     MessageBuilder::new()
-        .add_data(&msg)
+        .add_data_raw(&buffer)
         .emit_with_response(interface)
 }
 
+/// Emits a message, then yields all the answers that come back.
+///
+/// Must only be used on interfaces whose handler sends partial answers. The stream yields the
+/// partial answers, then the final answer, then ends.
+///
+/// The returned stream will cancel the message if it is dropped before the final answer.
+///
+/// # Safety
+///
+/// While the action of sending a message is totally safe, the message itself might instruct the
+/// environment to perform actions that would lead to unsafety.
+///
+pub unsafe fn emit_message_with_response_stream(
+    interface: &InterfaceHash,
+    msg: impl Encode,
+) -> Result<impl Stream<Item = EncodedMessage>, EmitErr> {
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
+    MessageBuilder::new()
+        .add_data_raw(&buffer)
+        .emit_with_response_stream(interface)
+}
+
+/// Emits a message directly to the given process, which must have called
+/// [`accept_directed_messages`].
+///
+/// Returns an error if the process doesn't exist or doesn't accept directed messages.
+///
+/// # Safety
+///
+/// While the action of sending a message is totally safe, the message itself might instruct the
+/// environment to perform actions that would lead to unsafety.
+///
+pub unsafe fn emit_directed_message_without_response(
+    target: Pid,
+    msg: impl Encode,
+) -> Result<(), EmitErr> {
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
+    let out = MessageBuilder::new()
+        .add_data_raw(&buffer)
+        .emit_directed_raw(target, false)?;
+    debug_assert!(out.is_none());
+    Ok(())
+}
+
+/// Emits a message directly to the given process, which must have called
+/// [`accept_directed_messages`], then waits for a response to come back.
+///
+/// Returns an error if the process doesn't exist or doesn't accept directed messages.
+///
+/// The returned future will cancel the message if it is dropped early.
+///
+/// # Safety
+///
+/// While the action of sending a message is totally safe, the message itself might instruct the
+/// environment to perform actions that would lead to unsafety.
+///
+pub unsafe fn emit_directed_message_with_response<T: Decode>(
+    target: Pid,
+    msg: impl Encode,
+) -> Result<impl Future<Output = T>, EmitErr> {
+    let mut buffer = ENCODE_BUFFER.lock();
+    buffer.clear();
+    msg.encode_to(&mut buffer);
+    let msg_id = MessageBuilder::new()
+        .add_data_raw(&buffer)
+        .emit_directed_raw(target, true)?
+        .unwrap();
+    Ok(EmitMessageWithResponse {
+        inner: Some(crate::message_response(msg_id)),
+        msg_id,
+    })
+}
+
+/// Allows other processes to send messages to the current process with
+/// [`emit_directed_message_without_response`] and [`emit_directed_message_with_response`].
+///
+/// These messages are received with [`next_interface_message`](crate::next_interface_message),
+/// on the [`DIRECTED_MESSAGE_INTERFACE`](crate::DIRECTED_MESSAGE_INTERFACE) pseudo-interface.
+/// Their [`emitter_pid`](crate::InterfaceMessage::emitter_pid) field should be checked before
+/// acting upon them.
+pub fn accept_directed_messages() {
+    unsafe { crate::ffi::accept_directed_messages() }
+}
+
 /// Cancel the given message. No answer will be received.
 ///
 /// Has no effect if the message is invalid.
 pub fn cancel_message(message_id: MessageId) {
+    #[cfg(feature = "trace")]
+    crate::trace::record(crate::trace::TraceEvent::Cancel { message_id });
+
     unsafe { crate::ffi::cancel_message(&u64::from(message_id)) }
 }
 
@@ -229,12 +625,19 @@ pub fn cancel_message(message_id: MessageId) {
 pub enum EmitErr {
     /// The given interface has no handler.
     BadInterface,
+    /// The message couldn't be emitted without blocking. See [`MessageBuilder::with_no_block`].
+    WouldBlock,
+    /// The target process doesn't exist or doesn't accept directed messages. See
+    /// [`accept_directed_messages`].
+    NotAccepted,
 }
 
 impl fmt::Display for EmitErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             EmitErr::BadInterface => write!(f, "The given interface has no handler"),
+            EmitErr::WouldBlock => write!(f, "The message couldn't be emitted without blocking"),
+            EmitErr::NotAccepted => write!(f, "The target doesn't accept directed messages"),
         }
     }
 }
@@ -278,3 +681,26 @@ impl<T> PinnedDrop for EmitMessageWithResponse<T> {
         }
     }
 }
+
+/// Stream that drives [`emit_message_with_response_stream`] to completion.
+#[must_use]
+pub struct EmitMessageWithResponseStream {
+    inner: crate::MessageResponseStream,
+    msg_id: MessageId,
+}
+
+impl Stream for EmitMessageWithResponseStream {
+    type Item = EncodedMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Stream::poll_next(Pin::new(&mut self.inner), cx)
+    }
+}
+
+impl Drop for EmitMessageWithResponseStream {
+    fn drop(&mut self) {
+        if !self.inner.is_finished() {
+            cancel_message(self.msg_id);
+        }
+    }
+}
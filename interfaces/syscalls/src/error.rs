@@ -0,0 +1,139 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Standard format for the errors returned by interface handlers.
+//!
+//! Interfaces are free to define their answers however they want. However, answering with a
+//! `Result<_, ()>` hides the reason of the failure from the sender. Interfaces are therefore
+//! encouraged to use `Result<_, ErrorPayload>` instead.
+//!
+//! An [`ErrorPayload`] consists of:
+//!
+//! - An [`ErrorClass`], which is a number whose meaning is the same for all interfaces, and that
+//!   programs can use to decide how to react to the error without knowing the interface.
+//! - A detail code, whose meaning is specific to the interface. `0` if there is no detail.
+//! - An optional human-readable UTF-8 message, meant for logging purposes.
+
+use alloc::string::String;
+use core::fmt;
+use parity_scale_codec::{Decode, Encode};
+
+/// Error returned by an interface handler.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ErrorPayload {
+    /// General category of the error.
+    pub class: ErrorClass,
+    /// Interface-specific code giving more details about the error. `0` if not applicable.
+    pub detail: u32,
+    /// Optional human-readable description of the error.
+    pub message: Option<String>,
+}
+
+impl ErrorPayload {
+    /// Builds a new [`ErrorPayload`] of the given class, without detail or message.
+    pub fn new(class: ErrorClass) -> Self {
+        ErrorPayload {
+            class,
+            detail: 0,
+            message: None,
+        }
+    }
+
+    /// Sets the interface-specific detail code of the error.
+    pub fn with_detail(mut self, detail: u32) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    /// Sets the human-readable description of the error.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Decodes an [`ErrorPayload`] from the raw bytes of an answer.
+    ///
+    /// Returns `None` if the bytes are not a valid [`ErrorPayload`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        parity_scale_codec::DecodeAll::decode_all(bytes).ok()
+    }
+}
+
+impl From<ErrorClass> for ErrorPayload {
+    fn from(class: ErrorClass) -> ErrorPayload {
+        ErrorPayload::new(class)
+    }
+}
+
+impl fmt::Display for ErrorPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.class)?;
+        if self.detail != 0 {
+            write!(f, " (detail: {})", self.detail)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+/// General category of an [`ErrorPayload`].
+///
+/// The classes defined as constants on this type have the same meaning for all interfaces.
+/// Unknown values must be treated like [`ErrorClass::OTHER`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct ErrorClass(pub u32);
+
+impl ErrorClass {
+    /// The error doesn't fit in any other class.
+    pub const OTHER: ErrorClass = ErrorClass(0);
+    /// The message is malformed or its parameters are invalid. Retrying won't help.
+    pub const INVALID_REQUEST: ErrorClass = ErrorClass(1);
+    /// The object that the message refers to doesn't exist.
+    pub const NOT_FOUND: ErrorClass = ErrorClass(2);
+    /// The sender isn't allowed to perform this operation.
+    pub const PERMISSION_DENIED: ErrorClass = ErrorClass(3);
+    /// The handler is temporarily unable to process the message. Retrying later might succeed.
+    pub const UNAVAILABLE: ErrorClass = ErrorClass(4);
+    /// The operation took too long and has been aborted.
+    pub const TIMED_OUT: ErrorClass = ErrorClass(5);
+    /// The operation is recognized, but isn't supported by this handler.
+    pub const UNSUPPORTED: ErrorClass = ErrorClass(6);
+    /// A hardware or I/O error happened while processing the message.
+    pub const IO: ErrorClass = ErrorClass(7);
+}
+
+impl fmt::Debug for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorClass::OTHER => write!(f, "Other error"),
+            ErrorClass::INVALID_REQUEST => write!(f, "Invalid request"),
+            ErrorClass::NOT_FOUND => write!(f, "Not found"),
+            ErrorClass::PERMISSION_DENIED => write!(f, "Permission denied"),
+            ErrorClass::UNAVAILABLE => write!(f, "Unavailable"),
+            ErrorClass::TIMED_OUT => write!(f, "Timed out"),
+            ErrorClass::UNSUPPORTED => write!(f, "Unsupported"),
+            ErrorClass::IO => write!(f, "I/O error"),
+            ErrorClass(n) => write!(f, "Error class #{}", n),
+        }
+    }
+}
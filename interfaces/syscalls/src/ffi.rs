@@ -15,7 +15,7 @@
 
 use crate::{MessageId, Pid};
 
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 use parity_scale_codec::{Decode, Encode};
 
 #[link(wasm_import_module = "redshirt")]
@@ -55,6 +55,33 @@ extern "C" {
         block: bool,
     ) -> u32;
 
+    /// Same as `next_message`, but can return up to `max_messages` messages at once, in order to
+    /// reduce the number of calls for processes that receive a lot of messages.
+    ///
+    /// The messages written in `out` can be decoded into a `Vec<`[`Message`]`>`. The returned
+    /// value and the `block` parameter have the same meaning as for `next_message`. If the first
+    /// message doesn't fit in `out_len`, the function returns the size that is necessary for this
+    /// message alone, and nothing is written. Otherwise, the function writes as many messages as
+    /// possible.
+    ///
+    /// Each entry in `to_poll` matches at most one message. In other words, if multiple messages
+    /// are returned, their `index_in_list` fields are all different. Each corresponding entry in
+    /// `to_poll` is set to `0`.
+    ///
+    /// `max_messages` must not be equal to `0`.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `to_poll` and `out`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn next_messages(
+        to_poll: *mut u64,
+        to_poll_len: u32,
+        out: *mut u8,
+        out_len: u32,
+        block: bool,
+        max_messages: u32,
+    ) -> u32;
+
     /// Sends a message to the process that has registered the given interface.
     ///
     /// The memory area pointed to by `msg_bufs_ptrs` must contain a list of `msg_bufs_num` pairs
@@ -93,6 +120,61 @@ extern "C" {
         message_id_out: *mut u64,
     ) -> u32;
 
+    /// Same as `emit_message`, except that an answer is always expected and that the message
+    /// has a deadline.
+    ///
+    /// If the message hasn't been answered when the monotonic clock reaches `deadline`
+    /// nanoseconds, the kernel answers it with an encoded
+    /// `Err::<_, ErrorPayload>(ErrorPayload::new(ErrorClass::TIMED_OUT))` and notifies the
+    /// handler that the message has been cancelled. The deadline is also passed to the handler
+    /// as part of the message.
+    pub(crate) fn emit_message_with_deadline(
+        interface_hash: *const u8,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+        allow_delay: bool,
+        deadline: u64,
+        message_id_out: *mut u64,
+    ) -> u32;
+
+    /// Same as `emit_message`, except that the function fails immediately if no handler is
+    /// available for the interface, or if the handler has too many messages waiting in its
+    /// queue.
+    ///
+    /// Returns `0` on success, `1` in case of error, and `2` if the message couldn't be emitted
+    /// without blocking.
+    pub(crate) fn try_emit_message(
+        interface_hash: *const u8,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+        needs_answer: bool,
+        message_id_out: *mut u64,
+    ) -> u32;
+
+    /// Sends a chunk of a message to the process that has registered the given interface.
+    ///
+    /// The handler must concatenate the chunk with the next message that the current process
+    /// emits on the same interface, which can itself be a chunk. The parameters are the same as
+    /// for `emit_message`, except that no answer can be expected for a chunk. The last part of
+    /// the message must be emitted with `emit_message`, and indicates whether an answer is
+    /// expected.
+    ///
+    /// This function is used for messages that are larger than
+    /// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE).
+    ///
+    /// Returns `0` on success, and `1` in case of error.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `interface_hash`, `msg_bufs_ptrs`, and all the sub-buffers referred to within
+    /// `msg_bufs_ptrs`. In particular, it is invalid to modify these buffers while the function
+    /// is running.
+    pub(crate) fn emit_message_chunk(
+        interface_hash: *const u8,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+        allow_delay: bool,
+    ) -> u32;
+
     /// Sends an answer back to the emitter of given `message_id`.
     ///
     /// When this function is being called, a "lock" is being held on the memory pointed by
@@ -100,6 +182,29 @@ extern "C" {
     /// function is running.
     pub(crate) fn emit_answer(message_id: *const u64, msg: *const u8, msg_len: u32);
 
+    /// Sends a partial answer back to the emitter of given `message_id`.
+    ///
+    /// Contrary to `emit_answer`, the `message_id` stays valid after this function has been
+    /// called. Any number of partial answers can be sent, and the message must then be answered
+    /// normally using `emit_answer` or `emit_message_error`.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `message_id` and `msg`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn emit_answer_partial(message_id: *const u64, msg: *const u8, msg_len: u32);
+
+    /// Sends a chunk of an answer back to the emitter of given `message_id`.
+    ///
+    /// Answers larger than `MAX_MESSAGE_SIZE` can't be sent at once. Instead, they must be split
+    /// into chunks. All the pieces except the last one are sent using this function, and the
+    /// last piece is sent using `emit_answer` or `emit_answer_partial`. The receiver concatenates
+    /// the chunks with the following piece.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `message_id` and `msg`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn emit_answer_chunk(message_id: *const u64, msg: *const u8, msg_len: u32);
+
     /// Notifies the kernel that the given message is invalid and cannot reasonably be answered.
     ///
     /// This should be used in situations where a message we receive fails to parse or is generally
@@ -122,6 +227,146 @@ extern "C" {
     /// `message_id`. In particular, it is invalid to modify this buffer while the function is
     /// running.
     pub(crate) fn cancel_message(message_id: *const u64);
+
+    /// Puts the current thread to sleep until the monotonic clock reaches `monotonic_ns`
+    /// nanoseconds. Returns immediately if this value has already been reached.
+    ///
+    /// The monotonic clock is the same as the one of the `time` interface, but this function
+    /// doesn't require any handler for that interface to be available.
+    pub(crate) fn sleep_until(monotonic_ns: u64);
+
+    /// Returns the current value of the monotonic clock, in nanoseconds.
+    ///
+    /// This is the same clock as the one used by `sleep_until`. Returns 0 if the kernel doesn't
+    /// have a clock.
+    pub(crate) fn monotonic_clock() -> u64;
+
+    /// Allows other processes to send messages to the current process using
+    /// `emit_directed_message`. There is no way to revert this.
+    pub(crate) fn accept_directed_messages();
+
+    /// Sends a message directly to the process whose [`Pid`] is pointed by `pid`, rather than
+    /// to the handler of an interface.
+    ///
+    /// The target receives the message as an [`InterfaceMessage`] whose `interface` field is
+    /// [`DIRECTED_MESSAGE_INTERFACE`](crate::DIRECTED_MESSAGE_INTERFACE).
+    ///
+    /// The message body is passed the same way as for `emit_message`. If `needs_answer` is true,
+    /// the ID of the message is written to `message_id_out`.
+    ///
+    /// Returns `0` on success, or `1` if the target doesn't exist or hasn't called
+    /// `accept_directed_messages`.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `pid`, `msg_bufs_ptrs`, `message_id_out`, and all the sub-buffers referred to within
+    /// `msg_bufs_ptrs`. In particular, it is invalid to modify these buffers while the function is
+    /// running.
+    pub(crate) fn emit_directed_message(
+        pid: *const u64,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+        needs_answer: bool,
+        message_id_out: *mut u64,
+    ) -> u32;
+
+    /// Same as `emit_directed_message`, except that an answer is always expected and that the
+    /// message has a deadline, as for `emit_message_with_deadline`.
+    pub(crate) fn emit_directed_message_with_deadline(
+        pid: *const u64,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+        deadline: u64,
+        message_id_out: *mut u64,
+    ) -> u32;
+
+    /// Sends a chunk of a message directly to the process whose [`Pid`] is pointed by `pid`.
+    ///
+    /// The target must concatenate the chunk with the next directed message that the current
+    /// process emits towards it, as for `emit_message_chunk`. The last part of the message must
+    /// be emitted with `emit_directed_message` or `emit_directed_message_with_deadline`.
+    ///
+    /// Returns `0` on success, or `1` if the target doesn't exist or hasn't called
+    /// `accept_directed_messages`.
+    pub(crate) fn emit_directed_message_chunk(
+        pid: *const u64,
+        msg_bufs_ptrs: *const u8,
+        msg_bufs_num: u32,
+    ) -> u32;
+
+    /// Creates a blob containing a copy of the `buf_len` bytes pointed to by `buf`, and writes
+    /// a handle to this blob in the memory pointed by `handle_out`.
+    ///
+    /// Returns 0 on success, or 1 if `buf_len` is larger than [`MAX_BLOB_SIZE`], in which case
+    /// nothing has been written in `handle_out`.
+    ///
+    /// The handle can be passed to other processes as part of a message. The blob stays alive
+    /// until `blob_release` is called, or until the current process terminates.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `buf` and `handle_out`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    ///
+    /// [`MAX_BLOB_SIZE`]: crate::MAX_BLOB_SIZE
+    pub(crate) fn blob_create(buf: *const u8, buf_len: u32, handle_out: *mut u64) -> u32;
+
+    /// Allows the process whose PID is pointed to by `pid` to read the blob whose handle is
+    /// pointed to by `handle`.
+    ///
+    /// Returns 0 on success, or 1 if the handle is invalid or if the blob hasn't been created by
+    /// the current process.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle` and `pid`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn blob_share(handle: *const u64, pid: *const u64) -> u32;
+
+    /// Copies the content of the blob whose handle is pointed to by `handle` into the memory
+    /// pointed by `out`.
+    ///
+    /// Returns the size of the blob. If this value is larger than `out_len`, then nothing has
+    /// been written in `out`. Returns `0xffffffff` if the handle is invalid, or if the blob has
+    /// neither been created by nor shared with the current process.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle` and `out`. In particular, it is invalid to modify these buffers while the
+    /// function is running.
+    pub(crate) fn blob_read(handle: *const u64, out: *mut u8, out_len: u32) -> u32;
+
+    /// Destroys the blob whose handle is pointed to by `handle`.
+    ///
+    /// Only the process that has created a blob can release it. Does nothing if the handle is
+    /// invalid or if the blob belongs to a different process.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `handle`. In particular, it is invalid to modify this buffer while the function is
+    /// running.
+    pub(crate) fn blob_release(handle: *const u64);
+
+    /// Writes a SCALE-encoded [`ProcessInfo`] describing the current process into the memory
+    /// pointed by `out`.
+    ///
+    /// Returns the size of the encoded information. If this value is larger than `out_len`, then
+    /// nothing has been written in `out`.
+    ///
+    /// When this function is being called, a "lock" is being held on the memory pointed by
+    /// `out`. In particular, it is invalid to modify this buffer while the function is running.
+    pub(crate) fn process_info(out: *mut u8, out_len: u32) -> u32;
+}
+
+/// Information about a process, as returned by `process_info`.
+// TODO: add the arguments and capabilities of the process, once the kernel has these concepts
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Identifier of the process.
+    pub pid: Pid,
+    /// Process that has started this process, if any.
+    pub parent: Option<Pid>,
+    /// SHA-256 digest of the module the process has been started from.
+    pub module_hash: [u8; 32],
+    /// Name of the program, as declared in the metadata of its module, if any.
+    pub name: Option<String>,
+    /// Interfaces that the process is currently the handler of.
+    pub registered_interfaces: Vec<[u8; 32]>,
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
@@ -131,6 +376,11 @@ pub enum Message {
     /// Whenever a process that has emitted events on one of our interfaces stops, a
     /// `ProcessDestroyed` message is sent.
     ProcessDestroyed(ProcessDestroyedMessage),
+    /// Sent to interface handlers when the system is shutting down.
+    Shutdown(ShutdownMessage),
+    /// Sent to an interface handler when the emitter of a message is no longer interested in
+    /// its answer.
+    MessageCancelled(MessageCancelledMessage),
 }
 
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
@@ -147,6 +397,15 @@ pub struct InterfaceMessage {
     /// Index within the list to poll where this message was.
     pub index_in_list: u32,
     pub actual_data: Vec<u8>,
+    /// If true, this message is only a chunk of a message, and must be concatenated with the
+    /// next message received from the same emitter on the same interface.
+    ///
+    /// Chunks are reassembled by [`next_interface_message`](crate::next_interface_message),
+    /// and never returned by it.
+    pub chunk: bool,
+    /// Value of the monotonic clock, in nanoseconds, after which the emitter is no longer
+    /// interested in the answer. The handler can use this to skip work that would be too late.
+    pub deadline: Option<u64>,
 }
 
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
@@ -157,10 +416,33 @@ pub struct ProcessDestroyedMessage {
     pub index_in_list: u32,
 }
 
+/// Notification that the system is shutting down.
+///
+/// The process should stop accepting new work, flush its state, and answer the messages that it
+/// has received. The kernel kills the process after a grace period.
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct ShutdownMessage {
+    /// Index within the list to poll where this message was.
+    pub index_in_list: u32,
+}
+
+/// Notification that the emitter of a message has cancelled it.
+///
+/// The message no longer needs to be answered. Answering it anyway has no effect.
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct MessageCancelledMessage {
+    /// Identifier of the message that has been cancelled.
+    pub message_id: MessageId,
+    /// Index within the list to poll where this message was.
+    pub index_in_list: u32,
+}
+
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
 pub enum InterfaceOrDestroyed {
     Interface(InterfaceMessage),
     ProcessDestroyed(ProcessDestroyedMessage),
+    Shutdown(ShutdownMessage),
+    MessageCancelled(MessageCancelledMessage),
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
@@ -175,6 +457,17 @@ pub struct ResponseMessage {
     ///
     /// - The interface handler has crashed.
     /// - The interface handler marked our message as invalid.
+    /// - The deadline of the message has passed.
     ///
     pub actual_data: Result<Vec<u8>, ()>,
+
+    /// If true, this is a partial answer and more responses to the same message will follow.
+    /// The last response to a message always has this field set to false.
+    pub partial: bool,
+
+    /// If true, this is a chunk of an answer larger than
+    /// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE), and must be concatenated with the
+    /// following responses to the same message up to and including the first one for which this
+    /// field is false. The last response to a message always has this field set to false.
+    pub chunk: bool,
 }
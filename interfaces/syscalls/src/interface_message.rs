@@ -31,11 +31,59 @@ pub fn next_interface_message() -> InterfaceMessageFuture {
 }
 
 /// Answers the given message.
+///
+/// If the answer is larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE), it is split
+/// into chunks that the emitter reassembles.
 // TODO: move to interface interface?
 pub fn emit_answer(message_id: MessageId, msg: impl Encode) {
     unsafe {
-        let buf = msg.encode();
-        crate::ffi::emit_answer(&u64::from(message_id), buf.0.as_ptr(), buf.0.len() as u32);
+        let mut buf = crate::emit::ENCODE_BUFFER.lock();
+        buf.clear();
+        msg.encode_to(&mut buf);
+        emit_answer_chunks(message_id, &buf, crate::ffi::emit_answer);
+    }
+}
+
+/// Sends a partial answer to the given message.
+///
+/// The message must later be answered with [`emit_answer`] or [`emit_message_error`]. The emitter
+/// receives all the answers in order, for example through [`message_response_stream`].
+///
+/// > **Note**: Only use this function on interfaces whose clients expect multiple answers.
+///
+/// > **Note**: Partial answers larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) are
+/// >           split into chunks that the emitter reassembles into a single partial answer.
+///
+/// [`message_response_stream`]: crate::message_response_stream
+// TODO: move to interface interface?
+pub fn emit_answer_partial(message_id: MessageId, msg: impl Encode) {
+    unsafe {
+        let mut buf = crate::emit::ENCODE_BUFFER.lock();
+        buf.clear();
+        msg.encode_to(&mut buf);
+        emit_answer_chunks(message_id, &buf, crate::ffi::emit_answer_partial);
+    }
+}
+
+/// Sends `buf` as an answer to the given message. Every piece of
+/// [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) bytes except the last one is sent as a chunk,
+/// and the last one is sent using `emit_last`.
+unsafe fn emit_answer_chunks(
+    message_id: MessageId,
+    buf: &[u8],
+    emit_last: unsafe extern "C" fn(*const u64, *const u8, u32),
+) {
+    let message_id = u64::from(message_id);
+    let mut chunks = buf.chunks(crate::MAX_MESSAGE_SIZE).peekable();
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_some() {
+            crate::ffi::emit_answer_chunk(&message_id, chunk.as_ptr(), chunk.len() as u32);
+        } else {
+            emit_last(&message_id, chunk.as_ptr(), chunk.len() as u32);
+        }
+    }
+    if buf.is_empty() {
+        emit_last(&message_id, buf.as_ptr(), 0);
     }
 }
 
@@ -0,0 +1,355 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Human-readable introspection of messages.
+//!
+//! Messages are normally transmitted as opaque bytes, and interpreting them requires knowing
+//! the interface they have been emitted on. This module makes it possible to attach to a
+//! message a [`TypeDescription`] containing the names and types of its fields, so that a tool
+//! tracing messages can pretty-print them without linking to the crate of each interface.
+//!
+//! The descriptions assume that the message is encoded using the SCALE codec, which is the case
+//! for all the interfaces of this repository.
+//!
+//! This module is only available if the `introspection` feature is enabled.
+
+use crate::{Encode, EncodedMessage, InterfaceHash, MessageId, Pid};
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString as _},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Write as _};
+use parity_scale_codec::{Compact, Decode as _};
+
+/// Description of the type of a message or of one of its fields.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub enum TypeDescription {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    /// Array of bytes whose length is fixed.
+    ByteArray(u32),
+    /// UTF-8 string, prefixed with its length.
+    String,
+    /// List of elements of the same type, prefixed with its length.
+    Vec(Box<TypeDescription>),
+    /// Optional value.
+    Option(Box<TypeDescription>),
+    /// Either a success or an error.
+    Result(Box<TypeDescription>, Box<TypeDescription>),
+    /// Fixed list of unnamed fields.
+    Tuple(Vec<TypeDescription>),
+    /// Structure with named fields.
+    Struct {
+        name: String,
+        fields: Vec<(String, TypeDescription)>,
+    },
+    /// Enumeration. The index of each variant in the list is its discriminant. The fields of
+    /// variants with unnamed fields have an empty name.
+    Enum {
+        name: String,
+        variants: Vec<(String, Vec<(String, TypeDescription)>)>,
+    },
+}
+
+/// Types whose [`TypeDescription`] is known.
+pub trait Introspect {
+    /// Returns the description of the type.
+    fn type_description() -> TypeDescription;
+}
+
+/// Message accompanied with the description of its type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescribedMessage {
+    /// Description of the type of [`body`](DescribedMessage::body).
+    pub description: TypeDescription,
+    /// The message itself.
+    pub body: EncodedMessage,
+}
+
+/// Error that can happen when pretty-printing a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrettyPrintError {
+    /// The message doesn't match its description.
+    Mismatch,
+    /// The message contains more bytes than described.
+    TrailingData,
+}
+
+impl fmt::Display for PrettyPrintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrettyPrintError::Mismatch => write!(f, "The message doesn't match its description"),
+            PrettyPrintError::TrailingData => write!(f, "The message contains trailing data"),
+        }
+    }
+}
+
+impl DescribedMessage {
+    /// Encodes the given message and attaches its description to it.
+    pub fn new<T: Introspect + Encode>(message: T) -> Self {
+        DescribedMessage {
+            description: T::type_description(),
+            body: message.encode(),
+        }
+    }
+
+    /// Returns a human-readable representation of the message, in a syntax similar to the one
+    /// of Rust's `Debug` trait.
+    pub fn pretty_print(&self) -> Result<String, PrettyPrintError> {
+        pretty_print(&self.description, &self.body.0)
+    }
+}
+
+/// Returns a human-readable representation of the given message, in a syntax similar to the
+/// one of Rust's `Debug` trait.
+pub fn pretty_print(
+    description: &TypeDescription,
+    message: &[u8],
+) -> Result<String, PrettyPrintError> {
+    let mut input = message;
+    let mut out = String::new();
+    print_value(description, &mut input, &mut out)?;
+    if !input.is_empty() {
+        return Err(PrettyPrintError::TrailingData);
+    }
+    Ok(out)
+}
+
+/// Decodes a value of the given type from `input` and writes it in `out`.
+fn print_value(
+    description: &TypeDescription,
+    input: &mut &[u8],
+    out: &mut String,
+) -> Result<(), PrettyPrintError> {
+    fn decode<T: parity_scale_codec::Decode>(input: &mut &[u8]) -> Result<T, PrettyPrintError> {
+        T::decode(input).map_err(|_| PrettyPrintError::Mismatch)
+    }
+
+    fn decode_len(input: &mut &[u8]) -> Result<usize, PrettyPrintError> {
+        let len = decode::<Compact<u32>>(input)?.0;
+        Ok(len as usize)
+    }
+
+    fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], PrettyPrintError> {
+        if input.len() < len {
+            return Err(PrettyPrintError::Mismatch);
+        }
+        let (taken, rest) = input.split_at(len);
+        *input = rest;
+        Ok(taken)
+    }
+
+    fn print_fields(
+        fields: &[(String, TypeDescription)],
+        input: &mut &[u8],
+        out: &mut String,
+    ) -> Result<(), PrettyPrintError> {
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        let named = fields.iter().any(|(name, _)| !name.is_empty());
+        out.push_str(if named { " { " } else { "(" });
+        for (n, (name, ty)) in fields.iter().enumerate() {
+            if n != 0 {
+                out.push_str(", ");
+            }
+            if named {
+                out.push_str(name);
+                out.push_str(": ");
+            }
+            print_value(ty, input, out)?;
+        }
+        out.push_str(if named { " }" } else { ")" });
+        Ok(())
+    }
+
+    match description {
+        TypeDescription::Bool => out.push_str(&decode::<bool>(input)?.to_string()),
+        TypeDescription::U8 => out.push_str(&decode::<u8>(input)?.to_string()),
+        TypeDescription::U16 => out.push_str(&decode::<u16>(input)?.to_string()),
+        TypeDescription::U32 => out.push_str(&decode::<u32>(input)?.to_string()),
+        TypeDescription::U64 => out.push_str(&decode::<u64>(input)?.to_string()),
+        TypeDescription::U128 => out.push_str(&decode::<u128>(input)?.to_string()),
+        TypeDescription::I8 => out.push_str(&decode::<i8>(input)?.to_string()),
+        TypeDescription::I16 => out.push_str(&decode::<i16>(input)?.to_string()),
+        TypeDescription::I32 => out.push_str(&decode::<i32>(input)?.to_string()),
+        TypeDescription::I64 => out.push_str(&decode::<i64>(input)?.to_string()),
+        TypeDescription::I128 => out.push_str(&decode::<i128>(input)?.to_string()),
+        TypeDescription::ByteArray(len) => {
+            out.push_str("0x");
+            for byte in take(input, *len as usize)? {
+                let _ = write!(out, "{:02x}", byte);
+            }
+        }
+        TypeDescription::String => {
+            let len = decode_len(input)?;
+            let bytes = take(input, len)?;
+            let string = core::str::from_utf8(bytes).map_err(|_| PrettyPrintError::Mismatch)?;
+            out.push_str(&format!("{:?}", string));
+        }
+        TypeDescription::Vec(elem) if **elem == TypeDescription::U8 => {
+            let len = decode_len(input)?;
+            out.push_str("0x");
+            for byte in take(input, len)? {
+                let _ = write!(out, "{:02x}", byte);
+            }
+        }
+        TypeDescription::Vec(elem) => {
+            let len = decode_len(input)?;
+            out.push('[');
+            for n in 0..len {
+                if n != 0 {
+                    out.push_str(", ");
+                }
+                print_value(elem, input, out)?;
+            }
+            out.push(']');
+        }
+        TypeDescription::Option(inner) => match decode::<u8>(input)? {
+            0 => out.push_str("None"),
+            1 => {
+                out.push_str("Some(");
+                print_value(inner, input, out)?;
+                out.push(')');
+            }
+            _ => return Err(PrettyPrintError::Mismatch),
+        },
+        TypeDescription::Result(ok, err) => match decode::<u8>(input)? {
+            0 => {
+                out.push_str("Ok(");
+                print_value(ok, input, out)?;
+                out.push(')');
+            }
+            1 => {
+                out.push_str("Err(");
+                print_value(err, input, out)?;
+                out.push(')');
+            }
+            _ => return Err(PrettyPrintError::Mismatch),
+        },
+        TypeDescription::Tuple(elems) => {
+            out.push('(');
+            for (n, elem) in elems.iter().enumerate() {
+                if n != 0 {
+                    out.push_str(", ");
+                }
+                print_value(elem, input, out)?;
+            }
+            out.push(')');
+        }
+        TypeDescription::Struct { name, fields } => {
+            out.push_str(name);
+            print_fields(fields, input, out)?;
+        }
+        TypeDescription::Enum { name, variants } => {
+            let index = usize::from(decode::<u8>(input)?);
+            let (variant, fields) = variants.get(index).ok_or(PrettyPrintError::Mismatch)?;
+            out.push_str(name);
+            out.push_str("::");
+            out.push_str(variant);
+            print_fields(fields, input, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+macro_rules! impl_primitive {
+    ($($ty:ty => $desc:ident,)*) => {
+        $(
+            impl Introspect for $ty {
+                fn type_description() -> TypeDescription {
+                    TypeDescription::$desc
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive! {
+    bool => Bool,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    u128 => U128,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    String => String,
+    Pid => U64,
+    MessageId => U64,
+}
+
+impl Introspect for () {
+    fn type_description() -> TypeDescription {
+        TypeDescription::Tuple(Vec::new())
+    }
+}
+
+impl Introspect for [u8; 32] {
+    fn type_description() -> TypeDescription {
+        TypeDescription::ByteArray(32)
+    }
+}
+
+impl Introspect for InterfaceHash {
+    fn type_description() -> TypeDescription {
+        TypeDescription::ByteArray(32)
+    }
+}
+
+impl<T: Introspect> Introspect for Vec<T> {
+    fn type_description() -> TypeDescription {
+        TypeDescription::Vec(Box::new(T::type_description()))
+    }
+}
+
+impl<T: Introspect> Introspect for Option<T> {
+    fn type_description() -> TypeDescription {
+        TypeDescription::Option(Box::new(T::type_description()))
+    }
+}
+
+impl<T: Introspect, E: Introspect> Introspect for Result<T, E> {
+    fn type_description() -> TypeDescription {
+        TypeDescription::Result(
+            Box::new(T::type_description()),
+            Box::new(E::type_description()),
+        )
+    }
+}
+
+impl<A: Introspect, B: Introspect> Introspect for (A, B) {
+    fn type_description() -> TypeDescription {
+        TypeDescription::Tuple(vec![A::type_description(), B::type_description()])
+    }
+}
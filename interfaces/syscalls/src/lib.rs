@@ -30,12 +30,19 @@
 //! the interface handler doesn't send back a response when one is expected, then you effectively
 //! have a memory leak.
 //!
+//! Interface handlers that want to report the reason of a failure are encouraged to answer with
+//! a `Result<_, `[`ErrorPayload`]`>`.
+//!
 //! A response can also be cancelled by the sender, in which case it is as if it had decided to not
 //! expect any response.
 //!
 //! The two primary and recommended ways to emit a message are the
 //! [`emit_message_without_response`] and [`emit_message_with_response`] functions.
 //!
+//! Programs that would rather drop a message than wait for an interface handler to be available
+//! or to catch up can use [`try_emit_message_without_response`], which fails immediately with
+//! [`EmitErr::WouldBlock`] instead.
+//!
 //! # Interface handling
 //!
 //! If your program is registered as an interface handler (using the `interface` interface, not
@@ -45,10 +52,38 @@
 //! The message can later be optionally be answered using the [`emit_answer`] function. If the
 //! mesage is malformed, you can also use the [`emit_message_error`] function.
 //!
+//! Some interfaces, such as the ones reading files or receiving data from the network, can have
+//! their handler send multiple partial answers with [`emit_answer_partial`] before the final one.
+//! The sender can receive all of them using [`emit_message_with_response_stream`].
+//!
+//! If the sender cancels a message that the handler has already received, the handler receives a
+//! [`MessageCancelled`](InterfaceOrDestroyed::MessageCancelled) notification and no longer needs
+//! to answer it.
+//!
 //! There is no way for an interface handler to pro-actively send data to a process. Communication
 //! can only be done as a response to a message. This must be taken into account when designing
 //! interfaces.
 //!
+//! # Directed messages
+//!
+//! A process that calls [`accept_directed_messages`] can also receive messages sent directly to
+//! its [`Pid`] with [`emit_directed_message_without_response`] or
+//! [`emit_directed_message_with_response`], for example by its parent. This allows two processes
+//! that know each other to communicate without registering an interface. These messages are
+//! received with [`next_interface_message`], on the [`DIRECTED_MESSAGE_INTERFACE`]
+//! pseudo-interface, and are answered like any other message.
+//!
+//! # Blobs
+//!
+//! Interfaces that move large amounts of data, such as TCP or the file system, can avoid putting
+//! that data in message bodies by creating a blob with [`create_blob`], allowing the receiver to
+//! read it with [`share_blob`], and passing its [`BlobHandle`] in a message instead. The receiver
+//! then obtains a copy of the blob with [`read_blob`].
+//!
+//! Blobs are copied when they are created and when they are read. Mapping the same pages in the
+//! memory of multiple processes would avoid these copies, but isn't possible at the moment, as
+//! the WASM interpreter used by the kernel doesn't support mapping pages into a memory instance.
+//!
 //! # About threads
 //!
 //! Multithreading in WASM isn't specified yet, and Rust doesn't allow multithreaded WASM code.
@@ -78,32 +113,73 @@
 //! of [`block_on`] without having access to the internals of these `Future`s. Tying these
 //! `Future`s to the implementation of [`block_on`] is therefore the logical thing to do.
 //!
+//! Programs that would rather use a different executor can drive these `Future`s with
+//! [`poll_messages`] and [`wait_messages`] instead, which perform the same processing as
+//! [`block_on`]. The executor can register a `Waker` with [`register_executor_waker`] in order to
+//! be notified when one of these `Future`s starts waiting for a message, and therefore needs
+//! these functions to be called.
+//!
 
 #![deny(intra_doc_link_resolution_failure)]
 #![no_std]
 
 extern crate alloc;
 
-pub use block_on::block_on;
+pub use blob::{create_blob, read_blob, read_blob_into, release_blob, share_blob, BlobHandle};
+pub use block_on::{block_on, poll_messages, register_executor_waker, wait_messages};
 pub use emit::{
-    cancel_message, emit_message_with_response, emit_message_without_response, MessageBuilder,
+    accept_directed_messages, cancel_message, emit_directed_message_with_response,
+    emit_directed_message_without_response, emit_message_with_response,
+    emit_message_with_response_stream, emit_message_without_response,
+    try_emit_message_without_response, EmitErr, MessageBuilder,
 };
-pub use ffi::{InterfaceMessage, InterfaceOrDestroyed, Message, ResponseMessage};
+pub use error::{ErrorClass, ErrorPayload};
+pub use ffi::{InterfaceMessage, InterfaceOrDestroyed, Message, ProcessInfo, ResponseMessage};
 pub use interface_message::{
-    emit_answer, emit_message_error, next_interface_message, InterfaceMessageFuture,
+    emit_answer, emit_answer_partial, emit_message_error, next_interface_message,
+    InterfaceMessageFuture,
+};
+pub use process_info::process_info;
+pub use response::{
+    message_response, message_response_any, message_response_any_sync_raw, message_response_stream,
+    message_response_sync_raw, MessageResponseAnyFuture, MessageResponseFuture,
+    MessageResponseStream,
 };
-pub use response::{message_response, message_response_sync_raw, MessageResponseFuture};
-pub use traits::{Decode, Encode, EncodedMessage};
+pub use sleep::sleep_until;
+pub use traits::{Decode, DecodeRef, Encode, EncodedMessage};
 
 use core::{cmp::PartialEq, fmt};
 
+/// Maximum size, in bytes, of the body of a message or of an answer that the kernel accepts.
+///
+/// Bigger messages and answers are transparently split into chunks by the functions of this
+/// crate, and reassembled on the receiving side.
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Maximum size, in bytes, of a blob. See [`create_blob`].
+pub const MAX_BLOB_SIZE: usize = 16 * 1024 * 1024;
+
+/// Pseudo-interface on which the messages emitted with [`emit_directed_message_without_response`]
+/// and [`emit_directed_message_with_response`] are received.
+///
+/// This interface can't have a handler. See [`accept_directed_messages`].
+pub const DIRECTED_MESSAGE_INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([0xff; 32]);
+
+mod blob;
 mod block_on;
 mod emit;
+mod error;
 mod interface_message;
+mod process_info;
 mod response;
+mod sleep;
 mod traits;
 
 pub mod ffi;
+#[cfg(feature = "introspection")]
+pub mod introspection;
+#[cfg(feature = "trace")]
+pub mod trace;
 
 /// Identifier of a running process within a core.
 // TODO: move to a Pid module?
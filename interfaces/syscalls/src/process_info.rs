@@ -0,0 +1,36 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{ffi::ProcessInfo, Decode as _, EncodedMessage};
+use alloc::vec::Vec;
+use core::convert::TryFrom as _;
+
+/// Returns information about the current process, such as its [`Pid`](crate::Pid) or the hash
+/// of the module it has been started from.
+pub fn process_info() -> ProcessInfo {
+    let mut out = Vec::new();
+    loop {
+        let out_len = u32::try_from(out.len()).unwrap();
+        let len = unsafe { crate::ffi::process_info(out.as_mut_ptr(), out_len) };
+        let len = usize::try_from(len).unwrap();
+        if len <= out.len() {
+            out.truncate(len);
+            break;
+        }
+        out.resize(len, 0);
+    }
+
+    ProcessInfo::decode(EncodedMessage(out)).unwrap()
+}
@@ -15,8 +15,10 @@
 
 use crate::{ffi::Message, Decode, EncodedMessage, MessageId};
 
+use alloc::vec::Vec;
 use core::{
     marker::PhantomData,
+    mem,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -24,17 +26,15 @@ use futures::prelude::*;
 
 /// Waits until a response to the given message comes back.
 ///
-/// Returns the undecoded response.
+/// Returns the undecoded response. Partial answers are concatenated with the final answer.
 pub fn message_response_sync_raw(msg_id: MessageId) -> EncodedMessage {
-    match crate::block_on::next_message(&mut [msg_id.into()], true).unwrap() {
-        Message::Response(m) => EncodedMessage(m.actual_data.unwrap()),
-        _ => panic!(),
-    }
+    message_response_any_sync_raw(&[msg_id]).1
 }
 
 /// Returns a future that is ready when a response to the given message comes back.
 ///
-/// The return value is the type the message decodes to.
+/// The return value is the type the message decodes to. If the handler sends partial answers,
+/// they are concatenated with the final answer before being decoded.
 pub fn message_response<T: Decode>(msg_id: MessageId) -> MessageResponseFuture<T> {
     MessageResponseFuture {
         finished: false,
@@ -43,7 +43,65 @@ pub fn message_response<T: Decode>(msg_id: MessageId) -> MessageResponseFuture<T
     }
 }
 
-// TODO: add a variant of message_response but for multiple messages
+/// Waits until a response to any of the given messages comes back.
+///
+/// Returns the index within `msg_ids` of the message that has been answered, and its undecoded
+/// response. Partial answers are concatenated with the final answer. The other messages are
+/// unaffected.
+///
+/// # Panic
+///
+/// Panics if `msg_ids` is empty.
+///
+pub fn message_response_any_sync_raw(msg_ids: &[MessageId]) -> (usize, EncodedMessage) {
+    assert!(!msg_ids.is_empty());
+
+    // The response might have already been received by `block_on`.
+    if let Some(index) = msg_ids
+        .iter()
+        .position(|id| crate::block_on::is_response_complete(*id))
+    {
+        return (index, take_complete_response(msg_ids[index]));
+    }
+
+    let mut to_poll = msg_ids.iter().map(|id| u64::from(*id)).collect::<Vec<_>>();
+    loop {
+        match crate::block_on::next_message(&mut to_poll, true).unwrap() {
+            Message::Response(m) => {
+                let index = m.index_in_list as usize;
+                let is_final = !m.partial && !m.chunk;
+                // The entry has been zero-ed by the kernel.
+                to_poll[index] = u64::from(m.message_id);
+                crate::block_on::push_response(m);
+                if is_final {
+                    return (index, take_complete_response(msg_ids[index]));
+                }
+            }
+            _ => panic!(),
+        }
+    }
+}
+
+/// Returns a future that is ready when a response to any of the given messages comes back.
+///
+/// The future yields the index within `msg_ids` of the message that has been answered, and its
+/// undecoded response. The other messages are unaffected: their responses can later be obtained
+/// with [`message_response`], or they can be cancelled with
+/// [`cancel_message`](crate::cancel_message).
+///
+/// This can be used to implement timeouts, by waiting on both a message and a timer.
+///
+/// # Panic
+///
+/// Panics if `msg_ids` is empty.
+///
+pub fn message_response_any(msg_ids: Vec<MessageId>) -> MessageResponseAnyFuture {
+    assert!(!msg_ids.is_empty());
+    MessageResponseAnyFuture {
+        finished: false,
+        msg_ids,
+    }
+}
 
 /// Future that drives `message_response` to completion.
 #[must_use]
@@ -61,9 +119,10 @@ where
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         assert!(!self.finished);
-        if let Some(message) = crate::block_on::peek_response(self.msg_id) {
+        if crate::block_on::is_response_complete(self.msg_id) {
             self.finished = true;
-            Poll::Ready(Decode::decode(EncodedMessage(message.actual_data.unwrap())).unwrap())
+            let message = take_complete_response(self.msg_id);
+            Poll::Ready(Decode::decode(message).unwrap())
         } else {
             crate::block_on::register_message_waker(self.msg_id, cx.waker().clone());
             Poll::Pending
@@ -72,3 +131,108 @@ where
 }
 
 impl<T> Unpin for MessageResponseFuture<T> {}
+
+/// Returns a stream yielding all the answers to the given message, for interfaces whose handler
+/// sends partial answers.
+///
+/// The stream yields the partial answers in order, then the final answer, then ends. Answers
+/// larger than [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) are reassembled before being
+/// yielded.
+pub fn message_response_stream(msg_id: MessageId) -> MessageResponseStream {
+    MessageResponseStream {
+        finished: false,
+        msg_id,
+        chunks: Vec::new(),
+    }
+}
+
+/// Stream that drives `message_response_stream` to completion.
+#[must_use]
+pub struct MessageResponseStream {
+    msg_id: MessageId,
+    finished: bool,
+    /// Chunks of the next answer received so far.
+    chunks: Vec<u8>,
+}
+
+impl MessageResponseStream {
+    /// Returns true if the final answer has been yielded.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Stream for MessageResponseStream {
+    type Item = EncodedMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        while let Some(message) = crate::block_on::peek_response(self.msg_id) {
+            let data = message.actual_data.unwrap();
+            if message.chunk {
+                self.chunks.extend_from_slice(&data);
+                continue;
+            }
+
+            if !message.partial {
+                self.finished = true;
+            }
+            let mut answer = mem::replace(&mut self.chunks, Vec::new());
+            answer.extend_from_slice(&data);
+            return Poll::Ready(Some(EncodedMessage(answer)));
+        }
+
+        crate::block_on::register_message_waker(self.msg_id, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Future that drives `message_response_any` to completion.
+#[must_use]
+pub struct MessageResponseAnyFuture {
+    msg_ids: Vec<MessageId>,
+    finished: bool,
+}
+
+impl Future for MessageResponseAnyFuture {
+    type Output = (usize, EncodedMessage);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        assert!(!self.finished);
+
+        if let Some(index) = self
+            .msg_ids
+            .iter()
+            .position(|id| crate::block_on::is_response_complete(*id))
+        {
+            self.finished = true;
+            return Poll::Ready((index, take_complete_response(self.msg_ids[index])));
+        }
+
+        for msg_id in &self.msg_ids {
+            crate::block_on::register_message_waker(*msg_id, cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Extracts the responses to the given message that have previously been obtained, and
+/// concatenates them.
+///
+/// Must only be called if [`is_response_complete`](crate::block_on::is_response_complete)
+/// returns true.
+fn take_complete_response(msg_id: MessageId) -> EncodedMessage {
+    let mut data = Vec::new();
+    while let Some(message) = crate::block_on::peek_response(msg_id) {
+        data.extend_from_slice(&message.actual_data.unwrap());
+        if !message.partial && !message.chunk {
+            return EncodedMessage(data);
+        }
+    }
+
+    unreachable!()
+}
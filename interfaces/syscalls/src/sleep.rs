@@ -0,0 +1,26 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Puts the current thread to sleep until the monotonic clock reaches `monotonic_ns`
+/// nanoseconds.
+///
+/// Contrary to the `time` interface, this function is handled directly by the kernel and is
+/// available even if no handler for the `time` interface is loaded.
+///
+/// > **Note**: This function blocks the entire thread, including the futures being driven by
+/// >           [`block_on`](crate::block_on). Use the `time` interface for asynchronous delays.
+pub fn sleep_until(monotonic_ns: u64) {
+    unsafe { crate::ffi::sleep_until(monotonic_ns) }
+}
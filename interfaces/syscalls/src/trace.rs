@@ -0,0 +1,127 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Record of the messages emitted and received by the current program.
+//!
+//! Only available if the `trace` feature is enabled. When it is, every message emitted, every
+//! response received and every cancellation is recorded in a ring buffer holding the last
+//! [`TRACE_CAPACITY`] events, which can be retrieved with [`trace`] or [`take_trace`].
+//!
+//! Other programs can query the trace of the current program through the `trace` interface.
+
+use crate::{InterfaceHash, MessageId, Pid, ResponseMessage};
+use alloc::{collections::VecDeque, vec::Vec};
+use core::convert::TryFrom as _;
+use spin::Mutex;
+
+/// Maximum number of events kept in the trace. Older events are discarded.
+pub const TRACE_CAPACITY: usize = 1024;
+
+/// Event within the trace.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct TraceEntry {
+    /// Value of the monotonic clock, in nanoseconds, when the event has been recorded.
+    ///
+    /// This is the same clock as the one of the `time` interface. It is 0 if the kernel doesn't
+    /// have a clock.
+    pub timestamp: u64,
+    /// What happened.
+    pub event: TraceEvent,
+}
+
+/// Event that can be recorded.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub enum TraceEvent {
+    /// A message has been emitted on an interface.
+    Emit {
+        /// Interface the message has been emitted on.
+        interface: InterfaceHash,
+        /// Identifier of the message, or `None` if no answer is expected.
+        message_id: Option<MessageId>,
+        /// Size in bytes of the body of the message.
+        size: u32,
+    },
+    /// A message has been emitted directly to a process.
+    EmitDirected {
+        /// Process the message has been sent to.
+        target: Pid,
+        /// Identifier of the message, or `None` if no answer is expected.
+        message_id: Option<MessageId>,
+        /// Size in bytes of the body of the message.
+        size: u32,
+    },
+    /// A response to a message has been received.
+    Response {
+        /// Identifier of the message that has been answered.
+        message_id: MessageId,
+        /// Size in bytes of the response, or `None` if the response is an error.
+        size: Option<u32>,
+        /// True if this is a partial response.
+        partial: bool,
+    },
+    /// A message has been cancelled.
+    Cancel {
+        /// Identifier of the message that has been cancelled.
+        message_id: MessageId,
+    },
+}
+
+/// Returns a copy of the events currently in the trace, from the oldest to the most recent.
+pub fn trace() -> Vec<TraceEntry> {
+    TRACE.lock().entries.iter().cloned().collect()
+}
+
+/// Returns the events currently in the trace, from the oldest to the most recent, and clears
+/// the trace.
+pub fn take_trace() -> Vec<TraceEntry> {
+    TRACE.lock().entries.drain(..).collect()
+}
+
+/// Adds an event to the trace.
+pub(crate) fn record(event: TraceEvent) {
+    let mut trace = TRACE.lock();
+    if trace.entries.len() >= TRACE_CAPACITY {
+        trace.entries.pop_front();
+    }
+    let timestamp = unsafe { crate::ffi::monotonic_clock() };
+    trace.entries.push_back(TraceEntry { timestamp, event });
+}
+
+/// Adds a [`TraceEvent::Response`] to the trace.
+pub(crate) fn record_response(response: &ResponseMessage) {
+    record(TraceEvent::Response {
+        message_id: response.message_id,
+        size: response
+            .actual_data
+            .as_ref()
+            .ok()
+            .map(|data| u32::try_from(data.len()).unwrap()),
+        partial: response.partial,
+    });
+}
+
+lazy_static::lazy_static! {
+    static ref TRACE: Mutex<Trace> = {
+        Mutex::new(Trace {
+            entries: VecDeque::with_capacity(TRACE_CAPACITY),
+        })
+    };
+}
+
+/// Content of the trace.
+struct Trace {
+    /// Events, from the oldest to the most recent.
+    entries: VecDeque<TraceEntry>,
+}
@@ -26,6 +26,17 @@ pub struct EncodedMessage(pub Vec<u8>);
 pub trait Encode {
     /// Turn the object into bytes ready to be transmitted.
     fn encode(self) -> EncodedMessage;
+
+    /// Appends the bytes ready to be transmitted at the end of `out`.
+    ///
+    /// Contrary to [`encode`](Encode::encode), this makes it possible to reuse the same buffer
+    /// for multiple messages. The default implementation calls [`encode`](Encode::encode).
+    fn encode_to(self, out: &mut Vec<u8>)
+    where
+        Self: Sized,
+    {
+        out.extend_from_slice(&self.encode().0);
+    }
 }
 
 /// Objects that represent messages that can be unserialized.
@@ -38,16 +49,62 @@ pub trait Decode {
         Self: Sized;
 }
 
+/// Objects that represent messages that can be unserialized by borrowing from the buffer
+/// containing the message, rather than by copying its content.
+///
+/// This is typically implemented on messages containing `&'a [u8]` or `&'a str` fields.
+pub trait DecodeRef<'a>: Sized {
+    type Error: fmt::Debug;
+
+    /// Decode the raw data passed as parameter.
+    fn decode_ref(buffer: &'a [u8]) -> Result<Self, Self::Error>;
+}
+
 impl EncodedMessage {
     pub fn decode<T: Decode>(self) -> Result<T, T::Error> {
         T::decode(self)
     }
+
+    /// Decodes the message by borrowing from it. See [`DecodeRef`].
+    pub fn decode_ref<'a, T: DecodeRef<'a>>(&'a self) -> Result<T, T::Error> {
+        T::decode_ref(&self.0)
+    }
+
+    /// Builds a message whose body is prefixed with the version of the schema it is encoded
+    /// with. This allows a handler to accept multiple versions of its messages at the same time.
+    ///
+    /// The version is encoded as four bytes in little endian.
+    pub fn versioned(version: u32, body: impl Encode) -> EncodedMessage {
+        let body = body.encode();
+        let mut out = Vec::with_capacity(4 + body.0.len());
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&body.0);
+        EncodedMessage(out)
+    }
+
+    /// Splits a message built with [`EncodedMessage::versioned`] into its version and its body.
+    ///
+    /// Returns an error if the message is too short to contain a version.
+    pub fn split_version(mut self) -> Result<(u32, EncodedMessage), ()> {
+        if self.0.len() < 4 {
+            return Err(());
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(&self.0[..4]);
+        self.0.drain(..4);
+        Ok((u32::from_le_bytes(version), self))
+    }
 }
 
 impl Encode for EncodedMessage {
     fn encode(self) -> EncodedMessage {
         self
     }
+
+    fn encode_to(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
 }
 
 impl<T> Encode for T
@@ -57,6 +114,10 @@ where
     fn encode(self) -> EncodedMessage {
         EncodedMessage(parity_scale_codec::Encode::encode(&self))
     }
+
+    fn encode_to(self, out: &mut Vec<u8>) {
+        parity_scale_codec::Encode::encode_to(&self, out)
+    }
 }
 
 impl Decode for EncodedMessage {
@@ -78,6 +139,22 @@ where
     }
 }
 
+impl<'a> DecodeRef<'a> for &'a [u8] {
+    type Error = core::convert::Infallible; // TODO: `!`
+
+    fn decode_ref(buffer: &'a [u8]) -> Result<Self, Self::Error> {
+        Ok(buffer)
+    }
+}
+
+impl<'a> DecodeRef<'a> for &'a str {
+    type Error = core::str::Utf8Error;
+
+    fn decode_ref(buffer: &'a [u8]) -> Result<Self, Self::Error> {
+        core::str::from_utf8(buffer)
+    }
+}
+
 impl fmt::Debug for EncodedMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(&self.0, f)
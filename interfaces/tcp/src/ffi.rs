@@ -22,14 +22,26 @@ pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
     0x4a, 0x3c, 0x1e, 0x07, 0x18, 0x1c, 0x27, 0x11, 0x55, 0x15, 0x1d, 0x5f, 0x22, 0x5b, 0x16, 0x20,
 ]);
 
+/// Message sent to the handler of the TCP interface.
+///
+/// Sockets are identified by a number chosen by the handler, and are closed automatically when
+/// the process that has opened them terminates.
 #[derive(Debug, Encode, Decode)]
 pub enum TcpMessage {
+    /// Create a socket listening for incoming connections. Answered with a
+    /// [`TcpListenResponse`].
     Listen(TcpListen),
+    /// Wait for an incoming connection on a listening socket. Answered with a
+    /// [`TcpAcceptResponse`].
     Accept(TcpAccept),
+    /// Connect to a remote. Answered with a [`TcpOpenResponse`] once the connection has been
+    /// established.
     Open(TcpOpen),
+    /// Close a socket. No answer is expected.
     Close(TcpClose),
-    /// Ask to read data from a socket. The response contains the data. For each socket, only one
-    /// read can exist at any given point in time.
+    /// Ask to read data from a socket. The response contains the data, or is empty if the remote
+    /// has closed the connection. For each socket, only one read can exist at any given point in
+    /// time.
     Read(TcpRead),
     /// Ask to write data to a socket. A response is sent back once written. For each socket, only
     /// one write can exist at any given point in time.
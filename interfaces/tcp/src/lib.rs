@@ -77,6 +77,21 @@ impl TcpStream {
     }
 }
 
+impl TcpStream {
+    /// Returns the identifier of the socket and doesn't close it, so that it can be handed over
+    /// to another program, such as the handler of the TLS interface.
+    ///
+    /// Data that has been received but not read yet is discarded.
+    pub fn into_raw_handle(self) -> u32 {
+        let mut this = mem::ManuallyDrop::new(self);
+        // The destructor of `TcpStream` isn't going to run, so we drop the other fields manually.
+        this.read_buffer = Vec::new();
+        this.pending_read = None;
+        this.pending_write = None;
+        this.handle
+    }
+}
+
 impl AsyncRead for TcpStream {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -90,6 +105,11 @@ impl AsyncRead for TcpStream {
                     Err(_) => return Poll::Ready(Err(io::ErrorKind::Other.into())), // TODO:
                 };
                 self.pending_read = None;
+
+                // An empty response means that the remote has closed the connection.
+                if self.read_buffer.is_empty() {
+                    return Poll::Ready(Ok(0));
+                }
             }
 
             if !self.read_buffer.is_empty() {
@@ -13,14 +13,18 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{monotonic_wait_until, Instant};
-use alloc::boxed::Box;
+use crate::{ffi, Instant};
 use core::{fmt, future::Future, pin::Pin, task::Context, task::Poll, time::Duration};
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseFuture};
 
 /// Mimics the API of `futures_timer::Delay`.
+///
+/// The timer of the handler is cancelled if the `Delay` is destroyed before it fires.
 pub struct Delay {
     when: Instant,
-    inner: Pin<Box<dyn Future<Output = ()> + Send>>,
+    msg_id: MessageId,
+    inner: MessageResponseFuture<()>,
+    finished: bool,
 }
 
 impl Delay {
@@ -29,9 +33,19 @@ impl Delay {
     }
 
     pub fn new_at(at: Instant) -> Delay {
+        let msg_id = unsafe {
+            let msg = ffi::TimeMessage::WaitMonotonic(at.inner).encode();
+            redshirt_syscalls_interface::MessageBuilder::new()
+                .add_data(&msg)
+                .emit_with_response_raw(&ffi::INTERFACE)
+                .unwrap()
+        };
+
         Delay {
             when: at,
-            inner: Box::pin(monotonic_wait_until(at.inner)),
+            msg_id,
+            inner: redshirt_syscalls_interface::message_response(msg_id),
+            finished: false,
         }
     }
 
@@ -54,6 +68,18 @@ impl Future for Delay {
     type Output = ();
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        Future::poll(self.inner.as_mut(), cx)
+        let poll = Future::poll(Pin::new(&mut self.inner), cx);
+        if poll.is_ready() {
+            self.finished = true;
+        }
+        poll
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if !self.finished {
+            redshirt_syscalls_interface::cancel_message(self.msg_id);
+        }
     }
 }
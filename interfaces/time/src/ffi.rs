@@ -28,6 +28,57 @@ pub enum TimeMessage {
     GetMonotonic,
     /// Must respond with a `u128`.
     GetSystem,
+    /// Sets the value of the system clock, in nanoseconds since the Epoch. Used by programs that
+    /// obtain the time from a more accurate source, such as an SNTP client. No answer is
+    /// expected.
+    ///
+    /// The handler is free to ignore this message, for example if the system clock is managed
+    /// by the host.
+    SetSystem(u128),
     /// Send response when the monotonic clock reaches this value. Responds with nothing (`()`).
+    ///
+    /// The timer is removed if the message is cancelled.
     WaitMonotonic(u128),
+    /// Send a partial response (`()`) when the monotonic clock reaches `first`, then every
+    /// `period` nanoseconds afterwards. Ticks that are missed are skipped rather than sent in a
+    /// burst.
+    ///
+    /// The final response is never sent. The message must be cancelled in order to stop the
+    /// timer. `period` must not be 0.
+    WaitMonotonicPeriodic { first: u128, period: u128 },
+}
+
+#[cfg(feature = "introspection")]
+impl redshirt_syscalls_interface::introspection::Introspect for TimeMessage {
+    fn type_description() -> redshirt_syscalls_interface::introspection::TypeDescription {
+        use alloc::{
+            string::{String, ToString as _},
+            vec,
+            vec::Vec,
+        };
+        use redshirt_syscalls_interface::introspection::TypeDescription;
+
+        TypeDescription::Enum {
+            name: "TimeMessage".to_string(),
+            variants: vec![
+                ("GetMonotonic".to_string(), Vec::new()),
+                ("GetSystem".to_string(), Vec::new()),
+                (
+                    "SetSystem".to_string(),
+                    vec![(String::new(), TypeDescription::U128)],
+                ),
+                (
+                    "WaitMonotonic".to_string(),
+                    vec![(String::new(), TypeDescription::U128)],
+                ),
+                (
+                    "WaitMonotonicPeriodic".to_string(),
+                    vec![
+                        ("first".to_string(), TypeDescription::U128),
+                        ("period".to_string(), TypeDescription::U128),
+                    ],
+                ),
+            ],
+        }
+    }
 }
@@ -0,0 +1,64 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::ffi;
+use core::{pin::Pin, task::Context, task::Poll};
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+
+/// Stream that yields periodically.
+///
+/// See [`monotonic_interval`](crate::monotonic_interval).
+pub struct Interval {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Interval {
+    pub(crate) fn new(first: u128, period: u128) -> Interval {
+        assert_ne!(period, 0);
+
+        let msg_id = unsafe {
+            let msg = ffi::TimeMessage::WaitMonotonicPeriodic { first, period }.encode();
+            redshirt_syscalls_interface::MessageBuilder::new()
+                .add_data(&msg)
+                .emit_with_response_raw(&ffi::INTERFACE)
+                .unwrap()
+        };
+
+        Interval {
+            msg_id,
+            responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+        }
+    }
+}
+
+impl Stream for Interval {
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(_)) => Poll::Ready(Some(())),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Interval {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
@@ -25,9 +25,11 @@ use futures::prelude::*;
 
 pub use self::delay::Delay;
 pub use self::instant::Instant;
+pub use self::interval::Interval;
 
 mod delay;
 mod instant;
+mod interval;
 
 pub mod ffi;
 
@@ -47,7 +49,18 @@ pub fn system_clock() -> impl Future<Output = u128> {
     }
 }
 
+/// Sets the number of nanoseconds since the Epoch returned by [`system_clock`].
+pub fn set_system_clock(now: u128) {
+    unsafe {
+        let msg = ffi::TimeMessage::SetSystem(now);
+        redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg).unwrap();
+    }
+}
+
 /// Returns a `Future` that yields when the monotonic clock reaches this value.
+///
+/// Use [`Delay`] instead if the wait might be abandoned before it finishes, as the timer of the
+/// handler is then cancelled.
 pub fn monotonic_wait_until(until: u128) -> impl Future<Output = ()> {
     unsafe {
         let msg = ffi::TimeMessage::WaitMonotonic(until);
@@ -55,6 +68,17 @@ pub fn monotonic_wait_until(until: u128) -> impl Future<Output = ()> {
     }
 }
 
+/// Returns a `Stream` that yields when the monotonic clock reaches `first`, then every `period`
+/// nanoseconds. The timer is cancelled when the [`Interval`] is destroyed.
+///
+/// # Panic
+///
+/// Panics if `period` is 0.
+///
+pub fn monotonic_interval(first: u128, period: u128) -> Interval {
+    Interval::new(first, period)
+}
+
 /// Returns a `Future` that outputs after `duration` has elapsed.
 pub fn monotonic_wait(duration: Duration) -> impl Future<Output = ()> {
     let dur_nanos = u128::from(duration.as_secs())
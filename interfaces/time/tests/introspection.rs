@@ -0,0 +1,76 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#![cfg(feature = "introspection")]
+
+use redshirt_syscalls_interface::introspection::{
+    pretty_print, DescribedMessage, Introspect as _, PrettyPrintError,
+};
+use redshirt_syscalls_interface::Encode as _;
+use redshirt_time_interface::ffi::TimeMessage;
+
+#[test]
+fn pretty_print_matching() {
+    let message = DescribedMessage::new(TimeMessage::WaitMonotonicPeriodic {
+        first: 1000,
+        period: 50,
+    });
+    assert_eq!(
+        message.pretty_print().unwrap(),
+        "TimeMessage::WaitMonotonicPeriodic { first: 1000, period: 50 }"
+    );
+
+    let message = DescribedMessage::new(TimeMessage::SetSystem(12));
+    assert_eq!(
+        message.pretty_print().unwrap(),
+        "TimeMessage::SetSystem(12)"
+    );
+
+    let message = DescribedMessage::new(TimeMessage::GetMonotonic);
+    assert_eq!(message.pretty_print().unwrap(), "TimeMessage::GetMonotonic");
+}
+
+#[test]
+fn pretty_print_mismatching() {
+    // Unknown variant.
+    assert_eq!(
+        pretty_print(&TimeMessage::type_description(), &[5]),
+        Err(PrettyPrintError::Mismatch)
+    );
+
+    // Truncated `u128`.
+    let mut encoded = TimeMessage::WaitMonotonic(7).encode().0;
+    encoded.truncate(8);
+    assert_eq!(
+        pretty_print(&TimeMessage::type_description(), &encoded),
+        Err(PrettyPrintError::Mismatch)
+    );
+
+    // Empty message.
+    assert_eq!(
+        pretty_print(&TimeMessage::type_description(), &[]),
+        Err(PrettyPrintError::Mismatch)
+    );
+}
+
+#[test]
+fn pretty_print_trailing_data() {
+    let mut encoded = TimeMessage::GetSystem.encode().0;
+    encoded.push(0);
+    assert_eq!(
+        pretty_print(&TimeMessage::type_description(), &encoded),
+        Err(PrettyPrintError::TrailingData)
+    );
+}
@@ -0,0 +1,77 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x63, 0x1a, 0x47, 0xd8, 0xa1, 0x1b, 0x73, 0x64, 0x32, 0x97, 0x8f, 0xca, 0x5a, 0x72, 0x72, 0xb8,
+    0x8b, 0xc1, 0x89, 0x01, 0xb9, 0xc4, 0x4d, 0xb9, 0x59, 0xbb, 0x17, 0xd4, 0x7f, 0x52, 0xba, 0x17,
+]);
+
+/// Message sent to the handler of the TLS interface.
+///
+/// Sessions are identified by a number chosen by the handler, and are closed automatically when
+/// the process that has opened them terminates.
+#[derive(Debug, Encode, Decode)]
+pub enum TlsMessage {
+    /// Perform a client handshake over a TCP connection. Answered with a [`TlsConnectResponse`]
+    /// once the handshake has finished.
+    Connect(TlsConnect),
+    /// Ask to read data from a session. Answered with a [`TlsReadResponse`] once some data is
+    /// available. For each session, only one read can exist at any given point in time.
+    Read(u32),
+    /// Ask to write data to a session. Answered with a [`TlsWriteResponse`] once the data has
+    /// been written to the TCP connection.
+    Write(TlsWrite),
+    /// Close a session and its TCP connection. No answer is expected.
+    Close(u32),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TlsConnect {
+    /// Identifier of a connected socket of the TCP interface. The handler of the TLS interface
+    /// takes ownership of the socket and closes it when the session is closed.
+    // TODO: the TCP interface doesn't have a way to transfer the ownership of a socket yet
+    pub tcp_socket: u32,
+    /// Name of the server, used for the Server Name Indication extension and against which
+    /// the certificate of the server is verified.
+    pub server_name: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TlsConnectResponse {
+    /// On success, the session ID. Contains an error with the `PERMISSION_DENIED` class if the
+    /// certificate of the server couldn't be verified.
+    pub result: Result<u32, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TlsReadResponse {
+    /// Decrypted data. Empty if the remote has closed the connection.
+    pub result: Result<Vec<u8>, ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TlsWrite {
+    pub session_id: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TlsWriteResponse {
+    pub result: Result<(), ErrorPayload>,
+}
@@ -0,0 +1,92 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! TLS sessions.
+//!
+//! Open a [`TcpStream`](redshirt_tcp_interface::TcpStream), then pass it to
+//! [`TlsStream::connect`] in order to perform a handshake. Certificates are verified by the
+//! handler of this interface.
+
+#![deny(intra_doc_link_resolution_failure)]
+
+use futures::prelude::*;
+use redshirt_tcp_interface::TcpStream;
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Client-side TLS session. Closed when destroyed.
+pub struct TlsStream {
+    session_id: u32,
+}
+
+impl TlsStream {
+    /// Performs a TLS handshake over the given TCP connection, and verifies that the server has
+    /// a valid certificate for `server_name`.
+    pub async fn connect(
+        tcp: TcpStream,
+        server_name: impl Into<String>,
+    ) -> Result<TlsStream, ErrorPayload> {
+        let msg = ffi::TlsMessage::Connect(ffi::TlsConnect {
+            tcp_socket: tcp.into_raw_handle(),
+            server_name: server_name.into(),
+        });
+        let response: ffi::TlsConnectResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        Ok(TlsStream {
+            session_id: response.result?,
+        })
+    }
+
+    /// Reads some decrypted data. Returns an empty `Vec` if the remote has closed the
+    /// connection.
+    pub fn read(&mut self) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::TlsMessage::Read(self.session_id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::TlsReadResponse| rep.result)
+    }
+
+    /// Encrypts and sends data.
+    pub fn write(
+        &mut self,
+        data: impl Into<Vec<u8>>,
+    ) -> impl Future<Output = Result<(), ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::TlsMessage::Write(ffi::TlsWrite {
+                session_id: self.session_id,
+                data: data.into(),
+            });
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::TlsWriteResponse| rep.result)
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::TlsMessage::Close(self.session_id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
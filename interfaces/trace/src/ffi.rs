@@ -0,0 +1,36 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::trace::TraceEntry;
+
+/// Value of [`TraceQuery::magic`]. Distinguishes the queries from the other directed messages
+/// that a program might receive.
+pub const QUERY_MAGIC: [u8; 8] = *b"rs-trace";
+
+/// Directed message sent to a program in order to query its trace. Must be answered with a
+/// [`TraceQueryResponse`].
+#[derive(Debug, Encode, Decode)]
+pub struct TraceQuery {
+    /// Must be equal to [`QUERY_MAGIC`].
+    pub magic: [u8; 8],
+    /// If true, the trace is cleared after having been returned.
+    pub take: bool,
+}
+
+/// Answer to a [`TraceQuery`]. Contains the events of the trace, from the oldest to the most
+/// recent.
+pub type TraceQueryResponse = Vec<TraceEntry>;
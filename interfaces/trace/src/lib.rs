@@ -0,0 +1,95 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Querying the messages emitted and received by other programs.
+//!
+//! Programs that depend on this crate record the messages they emit and receive, as explained
+//! in the documentation of the `trace` module of `redshirt-syscalls-interface`. In order to make
+//! this trace available to other programs, a program must call [`enable`], then pass every
+//! message it receives with
+//! [`next_interface_message`](redshirt_syscalls_interface::next_interface_message) to
+//! [`answer_query`].
+//!
+//! Other programs can then retrieve this trace with [`query`] or [`take`]. Queries are sent as
+//! directed messages. See
+//! [`accept_directed_messages`](redshirt_syscalls_interface::accept_directed_messages).
+//!
+//! > **Note**: Any program can query the trace. It only contains the interfaces, identifiers and
+//! >           sizes of the messages, and never their content.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use futures::prelude::*;
+use parity_scale_codec::Decode as _;
+use redshirt_syscalls_interface::{trace::TraceEntry, EmitErr, InterfaceMessage, Pid};
+
+pub mod ffi;
+
+/// Allows other programs to query the trace of the current program.
+///
+/// Must be followed with calls to [`answer_query`] for each message that is received.
+pub fn enable() {
+    redshirt_syscalls_interface::accept_directed_messages();
+}
+
+/// If the message is a query for the trace of the current program, answers it and returns
+/// `true`. Otherwise, returns `false` and the message must be processed normally.
+pub fn answer_query(message: &InterfaceMessage) -> bool {
+    let directed: [u8; 32] = redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE.into();
+    if message.interface != directed {
+        return false;
+    }
+
+    let query = match ffi::TraceQuery::decode(&mut &message.actual_data[..]) {
+        Ok(query) if query.magic == ffi::QUERY_MAGIC => query,
+        _ => return false,
+    };
+
+    if let Some(message_id) = message.message_id {
+        let entries = if query.take {
+            redshirt_syscalls_interface::trace::take_trace()
+        } else {
+            redshirt_syscalls_interface::trace::trace()
+        };
+        redshirt_syscalls_interface::emit_answer(message_id, &entries);
+    }
+
+    true
+}
+
+/// Returns the events currently in the trace of the given program, from the oldest to the most
+/// recent.
+///
+/// Returns an error if the program doesn't exist or hasn't called [`enable`].
+pub fn query(pid: Pid) -> Result<impl Future<Output = Vec<TraceEntry>>, EmitErr> {
+    send_query(pid, false)
+}
+
+/// Same as [`query`], but also clears the trace of the given program.
+pub fn take(pid: Pid) -> Result<impl Future<Output = Vec<TraceEntry>>, EmitErr> {
+    send_query(pid, true)
+}
+
+fn send_query(pid: Pid, take: bool) -> Result<impl Future<Output = Vec<TraceEntry>>, EmitErr> {
+    let query = ffi::TraceQuery {
+        magic: ffi::QUERY_MAGIC,
+        take,
+    };
+    unsafe { redshirt_syscalls_interface::emit_directed_message_with_response(pid, query) }
+}
@@ -0,0 +1,79 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xb0, 0xeb, 0xf6, 0xee, 0x1e, 0x50, 0x51, 0x90, 0x6e, 0x7d, 0x7a, 0xb7, 0x73, 0x59, 0x75, 0xb7,
+    0x02, 0xc6, 0xd9, 0x1f, 0xf0, 0x7d, 0x24, 0x29, 0x58, 0x6a, 0x7c, 0xf6, 0xec, 0x67, 0xe5, 0x38,
+]);
+
+/// Message sent to the handler of the UDP interface.
+///
+/// Sockets are identified by a number chosen by the handler, and are closed automatically when
+/// the process that has bound them terminates. IPv4 addresses are represented as IPv4-mapped
+/// IPv6 addresses.
+#[derive(Debug, Encode, Decode)]
+pub enum UdpMessage {
+    /// Bind a socket to a local address. Answered with a [`UdpBindResponse`].
+    Bind(UdpBind),
+    /// Send a datagram. Answered with a [`UdpSendToResponse`] once the datagram has been sent.
+    SendTo(UdpSendTo),
+    /// Subscribe to the datagrams received on a socket. Each datagram is sent back as a partial
+    /// answer containing a [`UdpDatagram`]. Cancel the message to unsubscribe.
+    ///
+    /// Only one subscription can exist at any given point in time for each socket. Datagrams
+    /// received while there is no subscription are discarded.
+    Receive(u32),
+    /// Close a socket. No answer is expected.
+    Close(u32),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UdpBind {
+    pub local_ip: [u16; 8],
+    /// Can be 0 for auto-assign.
+    pub port: u16,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UdpBindResponse {
+    /// On success, the socket ID and the port it's bound to.
+    pub result: Result<(u32, u16), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UdpSendTo {
+    pub socket_id: u32,
+    pub ip: [u16; 8],
+    pub port: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UdpSendToResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct UdpDatagram {
+    /// Address of the sender.
+    pub ip: [u16; 8],
+    /// Port of the sender.
+    pub port: u16,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,160 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! UDP/IP.
+//!
+//! [Bind](UdpSocket::bind) a [`UdpSocket`], then use [`UdpSocket::send_to`] to send datagrams
+//! and [`UdpSocket::incoming`] to receive them.
+
+#![deny(intra_doc_link_resolution_failure)]
+
+use futures::prelude::*;
+use redshirt_syscalls_interface::{Encode as _, MessageId, MessageResponseStream};
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// UDP socket bound to a local address. Closed when destroyed.
+pub struct UdpSocket {
+    socket_id: u32,
+    local_port: u16,
+}
+
+impl UdpSocket {
+    /// Binds a socket to the given local address. If the port is 0, one is automatically
+    /// assigned.
+    pub async fn bind(local_addr: &SocketAddr) -> Result<UdpSocket, ErrorPayload> {
+        let (local_ip, port) = to_ffi_addr(local_addr);
+        let msg = ffi::UdpMessage::Bind(ffi::UdpBind { local_ip, port });
+        let response: ffi::UdpBindResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        let (socket_id, local_port) = response.result?;
+        Ok(UdpSocket {
+            socket_id,
+            local_port,
+        })
+    }
+
+    /// Returns the local port the socket is bound to.
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// Sends a datagram to the given address.
+    pub fn send_to(
+        &self,
+        data: impl Into<Vec<u8>>,
+        target: &SocketAddr,
+    ) -> impl Future<Output = Result<(), ErrorPayload>> {
+        let (ip, port) = to_ffi_addr(target);
+        let msg = ffi::UdpMessage::SendTo(ffi::UdpSendTo {
+            socket_id: self.socket_id,
+            ip,
+            port,
+            data: data.into(),
+        });
+
+        let response = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::UdpSendToResponse| rep.result)
+    }
+
+    /// Returns a stream of the datagrams received on this socket, together with the address of
+    /// their sender.
+    ///
+    /// Only one such stream can exist at any given point in time. Datagrams received while
+    /// there is no stream are discarded.
+    pub fn incoming(&self) -> Datagrams {
+        let msg_id = unsafe {
+            let msg = ffi::UdpMessage::Receive(self.socket_id).encode();
+            redshirt_syscalls_interface::MessageBuilder::new()
+                .add_data(&msg)
+                .emit_with_response_raw(&ffi::INTERFACE)
+                .unwrap()
+        };
+
+        Datagrams {
+            msg_id,
+            responses: redshirt_syscalls_interface::message_response_stream(msg_id),
+        }
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::UdpMessage::Close(self.socket_id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
+
+/// Stream of datagrams received on a socket.
+///
+/// See [`UdpSocket::incoming`].
+pub struct Datagrams {
+    msg_id: MessageId,
+    responses: MessageResponseStream,
+}
+
+impl Stream for Datagrams {
+    type Item = (SocketAddr, Vec<u8>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match Stream::poll_next(Pin::new(&mut self.responses), cx) {
+            Poll::Ready(Some(message)) => {
+                Poll::Ready(message.decode().ok().map(|dgram: ffi::UdpDatagram| {
+                    (from_ffi_addr(dgram.ip, dgram.port), dgram.data)
+                }))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Datagrams {
+    fn drop(&mut self) {
+        redshirt_syscalls_interface::cancel_message(self.msg_id);
+    }
+}
+
+fn to_ffi_addr(addr: &SocketAddr) -> ([u16; 8], u16) {
+    match addr {
+        SocketAddr::V4(addr) => (addr.ip().to_ipv6_mapped().segments(), addr.port()),
+        SocketAddr::V6(addr) => (addr.ip().segments(), addr.port()),
+    }
+}
+
+fn from_ffi_addr(ip: [u16; 8], port: u16) -> SocketAddr {
+    let ip = Ipv6Addr::from(ip);
+    // IPv4-mapped addresses are converted back to IPv4.
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0xffff, ..] => SocketAddr::new(IpAddr::V4(ip.to_ipv4().unwrap()), port),
+        _ => SocketAddr::new(IpAddr::V6(ip), port),
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0xe8, 0xda, 0x95, 0x13, 0x6b, 0x5f, 0xcb, 0xe9, 0x04, 0x08, 0xf0, 0xd7, 0x70, 0x13, 0xc4, 0x3b,
+    0x51, 0xf6, 0xb7, 0xc9, 0x27, 0x55, 0xa4, 0x88, 0xa9, 0xce, 0x0d, 0x0a, 0x3b, 0xe3, 0x67, 0x2a,
+]);
+
+#[derive(Debug, Encode, Decode)]
+pub enum WatchdogMessage {
+    /// Creates a new watchdog owned by the emitter. Answered with a [`RegisterResponse`].
+    Register(Register),
+    /// Restarts the countdown of the watchdog with the given identifier. Not answered.
+    ///
+    /// Has no effect if the watchdog doesn't exist or belongs to a different process.
+    Pet(u64),
+    /// Destroys the watchdog with the given identifier. Not answered.
+    ///
+    /// Has no effect if the watchdog doesn't exist or belongs to a different process.
+    Unregister(u64),
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Register {
+    /// Number of milliseconds after which the action fires if the watchdog hasn't been petted.
+    /// Must be non-zero.
+    pub timeout_ms: u32,
+    /// What to do when the watchdog expires.
+    pub action: Action,
+}
+
+/// Action performed when a watchdog expires.
+///
+/// Whatever the action, an entry is written in the logs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Action {
+    /// Only write an entry in the logs. The countdown then restarts.
+    Log,
+    /// Kill the process that owns the watchdog. Its parent, acting as its supervisor, is
+    /// expected to start it again. The watchdog is destroyed.
+    Restart,
+    /// Reboot the machine.
+    Reboot,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct RegisterResponse {
+    /// Identifier of the new watchdog.
+    pub result: Result<u64, ErrorPayload>,
+}
@@ -0,0 +1,79 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Watchdogs.
+//!
+//! A process that must not get stuck registers a watchdog with [`Watchdog::register`], then
+//! calls [`Watchdog::pet`] periodically. If the watchdog isn't petted before its timeout
+//! expires, the handler of this interface performs the [`Action`](ffi::Action) that was
+//! passed at registration.
+//!
+//! The handler can be backed by a hardware watchdog timer, in which case the machine is also
+//! reset if the handler itself stops running.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+use core::{convert::TryFrom as _, time::Duration};
+use futures::prelude::*;
+
+pub use ffi::Action;
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// Registered watchdog. Unregistered when destroyed.
+pub struct Watchdog {
+    id: u64,
+}
+
+impl Watchdog {
+    /// Registers a new watchdog that performs `action` if it isn't petted for longer than
+    /// `timeout`.
+    ///
+    /// `timeout` is rounded down to the millisecond.
+    pub fn register(
+        timeout: Duration,
+        action: Action,
+    ) -> impl Future<Output = Result<Watchdog, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::WatchdogMessage::Register(ffi::Register {
+                timeout_ms: u32::try_from(timeout.as_millis()).unwrap_or(u32::max_value()),
+                action,
+            });
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::RegisterResponse| rep.result.map(|id| Watchdog { id }))
+    }
+
+    /// Restarts the countdown.
+    pub fn pet(&self) {
+        unsafe {
+            let msg = ffi::WatchdogMessage::Pet(self.id);
+            redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg)
+                .unwrap();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::WatchdogMessage::Unregister(self.id);
+            redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, &msg)
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use alloc::{string::String, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use redshirt_syscalls_interface::{ErrorPayload, InterfaceHash};
+
+// TODO: this has been randomly generated; instead should be a hash or something
+pub const INTERFACE: InterfaceHash = InterfaceHash::from_raw_hash([
+    0x9f, 0x46, 0xf6, 0xe2, 0x0d, 0x25, 0x8d, 0x83, 0xc7, 0x1b, 0xbb, 0x6d, 0xb2, 0xe9, 0xf9, 0x32,
+    0x51, 0xf6, 0xe7, 0xb0, 0x69, 0xf9, 0x65, 0x38, 0x51, 0x51, 0x66, 0xbf, 0xf7, 0x78, 0x35, 0xa3,
+]);
+
+/// Message sent to the handler of the WebSocket interface.
+///
+/// Connections and listeners are identified by numbers chosen by the handler, and are closed
+/// automatically when the process that owns them terminates. Ping frames are answered by the
+/// handler and never reported.
+#[derive(Debug, Encode, Decode)]
+pub enum WebSocketMessage {
+    /// Connect to a server. Answered with a [`ConnectResponse`] once the handshake is finished.
+    Connect(Connect),
+    /// Start accepting connections on a TCP port. Answered with a [`ListenResponse`].
+    Listen(Listen),
+    /// Wait for the next connection on a listener. Answered with an [`AcceptResponse`] once the
+    /// handshake is finished. For each listener, only one such message can exist at any given
+    /// point in time.
+    Accept(u32),
+    /// Stop accepting connections. Connections already accepted are unaffected. No answer is
+    /// expected.
+    StopListening(u32),
+    /// Send a message on a connection. Answered with a [`SendResponse`] once sent.
+    Send(SendMessage),
+    /// Wait for the next message on a connection. Answered with a [`ReceiveResponse`]. For each
+    /// connection, only one such message can exist at any given point in time.
+    Receive(u32),
+    /// Send a close frame and close a connection. No answer is expected.
+    Close(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Connect {
+    /// URL starting with `ws://` or `wss://`.
+    pub url: String,
+    /// Headers to add to the handshake request, for example `Origin`.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ConnectResponse {
+    /// On success, the identifier of the connection.
+    pub result: Result<u32, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Listen {
+    /// TCP port to listen on. Can be 0 for auto-assign.
+    pub port: u16,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListenResponse {
+    /// On success, the identifier of the listener and the port it listens on.
+    pub result: Result<(u32, u16), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct AcceptResponse {
+    pub result: Result<Accepted, ErrorPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Accepted {
+    pub connection_id: u32,
+    /// Path and query of the handshake request.
+    pub path: String,
+    /// Headers of the handshake request.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SendMessage {
+    pub connection_id: u32,
+    pub message: Message,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SendResponse {
+    pub result: Result<(), ErrorPayload>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ReceiveResponse {
+    /// Next message, or `None` if the remote has closed the connection.
+    pub result: Result<Option<Message>, ErrorPayload>,
+}
+
+/// Message transmitted over a WebSocket connection.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
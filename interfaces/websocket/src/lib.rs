@@ -0,0 +1,157 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! WebSocket connections.
+//!
+//! Call [`WebSocket::connect`] to connect to a server, or [`Listener::bind`] then
+//! [`Listener::accept`] to accept connections from clients.
+
+#![deny(intra_doc_link_resolution_failure)]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use futures::prelude::*;
+
+pub use ffi::Message;
+pub use redshirt_syscalls_interface::ErrorPayload;
+
+pub mod ffi;
+
+/// WebSocket connection. Closed when destroyed.
+pub struct WebSocket {
+    id: u32,
+}
+
+impl WebSocket {
+    /// Connects to the given `ws://` or `wss://` URL.
+    pub async fn connect(url: impl Into<String>) -> Result<WebSocket, ErrorPayload> {
+        WebSocket::connect_with_headers(url, Vec::new()).await
+    }
+
+    /// Connects to the given `ws://` or `wss://` URL, adding headers to the handshake request.
+    pub async fn connect_with_headers(
+        url: impl Into<String>,
+        headers: Vec<(String, Vec<u8>)>,
+    ) -> Result<WebSocket, ErrorPayload> {
+        let msg = ffi::WebSocketMessage::Connect(ffi::Connect {
+            url: url.into(),
+            headers,
+        });
+        let response: ffi::ConnectResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        Ok(WebSocket {
+            id: response.result?,
+        })
+    }
+
+    /// Sends a message.
+    pub fn send(&mut self, message: Message) -> impl Future<Output = Result<(), ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::WebSocketMessage::Send(ffi::SendMessage {
+                connection_id: self.id,
+                message,
+            });
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::SendResponse| rep.result)
+    }
+
+    /// Waits for the next message. Returns `None` if the remote has closed the connection.
+    pub fn receive(&mut self) -> impl Future<Output = Result<Option<Message>, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::WebSocketMessage::Receive(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::ReceiveResponse| rep.result)
+    }
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::WebSocketMessage::Close(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
+
+/// Accepts WebSocket connections on a TCP port. Stops listening when destroyed.
+pub struct Listener {
+    id: u32,
+    port: u16,
+}
+
+/// Connection accepted by a [`Listener`].
+pub struct Incoming {
+    pub socket: WebSocket,
+    /// Path and query of the handshake request.
+    pub path: String,
+    /// Headers of the handshake request.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+impl Listener {
+    /// Starts listening on the given TCP port. Pass 0 to let the handler choose a port.
+    pub async fn bind(port: u16) -> Result<Listener, ErrorPayload> {
+        let msg = ffi::WebSocketMessage::Listen(ffi::Listen { port });
+        let response: ffi::ListenResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        let (id, port) = response.result?;
+        Ok(Listener { id, port })
+    }
+
+    /// Returns the TCP port the listener listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Waits for the next connection.
+    pub fn accept(&mut self) -> impl Future<Output = Result<Incoming, ErrorPayload>> {
+        let response = unsafe {
+            let msg = ffi::WebSocketMessage::Accept(self.id);
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::INTERFACE, msg).unwrap()
+        };
+        response.map(|rep: ffi::AcceptResponse| {
+            rep.result.map(|accepted| Incoming {
+                socket: WebSocket {
+                    id: accepted.connection_id,
+                },
+                path: accepted.path,
+                headers: accepted.headers,
+            })
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        unsafe {
+            let msg = ffi::WebSocketMessage::StopListening(self.id);
+            let _ =
+                redshirt_syscalls_interface::emit_message_without_response(&ffi::INTERFACE, msg);
+        }
+    }
+}
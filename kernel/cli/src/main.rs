@@ -17,7 +17,7 @@
 
 use futures::{channel::mpsc, pin_mut, prelude::*};
 use parity_scale_codec::DecodeAll;
-use std::{fs, path::PathBuf, process, sync::Arc};
+use std::{fs, path::PathBuf, process, sync::Arc, time::Duration};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -26,16 +26,28 @@ struct CliOptions {
     /// Input file.
     #[structopt(parse(from_os_str))]
     input: Option<PathBuf>,
+
+    /// Which log entries to print, for example `info,net=debug`.
+    #[structopt(long, default_value = "info")]
+    log: redshirt_log_hosted::LogFilter,
+
+    /// If set, log entries are also appended to this file.
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
 }
 
+/// Time left to the programs to stop when the system is shut down.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 fn main() {
     futures::executor::block_on(async_main());
 }
 
 async fn async_main() {
+    let cli_opts = CliOptions::from_args();
+
     let cli_requested_process = {
-        let cli_opts = CliOptions::from_args();
-        if let Some(input) = cli_opts.input {
+        if let Some(input) = &cli_opts.input {
             let file_content = fs::read(input).expect("failed to read input file");
             Some(
                 redshirt_core::module::Module::from_bytes(&file_content)
@@ -46,9 +58,29 @@ async fn async_main() {
         }
     };
 
+    let log_handler = {
+        let mut handler = redshirt_log_hosted::LogHandler::new(cli_opts.log)
+            .with_backend(redshirt_log_hosted::ConsoleBackend);
+        if let Some(log_file) = &cli_opts.log_file {
+            let backend =
+                redshirt_log_hosted::FileBackend::open(log_file).expect("failed to open log file");
+            handler = handler.with_backend(backend);
+        }
+        handler
+    };
+
     let mut system = redshirt_core::system::SystemBuilder::new()
         .with_native_program(redshirt_time_hosted::TimerHandler::new())
         .with_native_program(redshirt_stdout_hosted::StdoutHandler::new())
+        .with_native_program(redshirt_random_hosted::RandomHandler::new())
+        .with_native_program(log_handler)
+        .with_native_program(redshirt_console_hosted::ConsoleHandler::new())
+        .with_native_program(redshirt_power_supply_hosted::PowerSupplyHandler::new())
+        .with_monotonic_clock(|| redshirt_time_hosted::monotonic_clock() as u64)
+        .with_timer(|until| {
+            let until = u128::from(until);
+            Box::pin(redshirt_time_hosted::monotonic_clock_reaches(until))
+        })
         .build();
 
     let cli_pid = if let Some(cli_requested_process) = cli_requested_process {
@@ -71,7 +103,13 @@ async fn async_main() {
                     });
                 }
             }
-            _ => panic!(),
+            redshirt_core::system::SystemRunOutcome::PowerRequested { .. } => {
+                // There is no machine to power off or restart; we simply exit.
+                system
+                    .shutdown(async_std::task::sleep(SHUTDOWN_GRACE_PERIOD))
+                    .await;
+                process::exit(0);
+            }
         }
     }
 }
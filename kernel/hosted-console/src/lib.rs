@@ -0,0 +1,173 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the console interface on top of the terminal of the host.
+//!
+//! ANSI escape sequences are passed through as they are, as they are interpreted by the
+//! terminal.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use redshirt_console_interface::ffi::{ConsoleMessage, ReadLineResponse, SizeResponse, INTERFACE};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use std::{
+    io::{self, BufRead as _, Write as _},
+    sync::{atomic, mpsc as std_mpsc},
+    thread,
+};
+
+/// Native program for `console` interface messages handling.
+pub struct ConsoleHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Send on this channel the answers to emit.
+    answers_tx: mpsc::UnboundedSender<(MessageId, EncodedMessage)>,
+    /// Receiving side of [`ConsoleHandler::answers_tx`]. Accessed only by `next_event`.
+    answers_rx: Mutex<mpsc::UnboundedReceiver<(MessageId, EncodedMessage)>>,
+    /// Send on this channel the `ReadLine` messages. They are processed in order by a background
+    /// thread, as reading from stdin is blocking.
+    read_lines_tx: std::sync::Mutex<std_mpsc::Sender<MessageId>>,
+}
+
+impl ConsoleHandler {
+    /// Initializes the new state machine for the console.
+    pub fn new() -> Self {
+        let (answers_tx, answers_rx) = mpsc::unbounded();
+        let (read_lines_tx, read_lines_rx) = std_mpsc::channel();
+
+        let thread_answers_tx = answers_tx.clone();
+        thread::Builder::new()
+            .name("console-read-line".to_owned())
+            .spawn(move || read_lines_thread(read_lines_rx, thread_answers_tx))
+            .unwrap();
+
+        ConsoleHandler {
+            registered: atomic::AtomicBool::new(false),
+            answers_tx,
+            answers_rx: Mutex::new(answers_rx),
+            read_lines_tx: std::sync::Mutex::new(read_lines_tx),
+        }
+    }
+}
+
+impl NativeProgram for ConsoleHandler {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let mut answers_rx = self.answers_rx.lock().await;
+            let (message_id, answer) = answers_rx.next().await.unwrap();
+            NativeProgramEvent::Answer {
+                message_id,
+                answer: Ok(answer),
+            }
+        })
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match ConsoleMessage::decode(message) {
+            Ok(ConsoleMessage::Write(text)) => {
+                let mut stdout = io::stdout();
+                stdout.write_all(text.as_bytes()).unwrap();
+                stdout.flush().unwrap();
+            }
+            Ok(ConsoleMessage::ReadLine) => {
+                if let Some(message_id) = message_id {
+                    self.read_lines_tx.lock().unwrap().send(message_id).unwrap();
+                }
+            }
+            Ok(ConsoleMessage::Size) => {
+                if let Some(message_id) = message_id {
+                    let answer = terminal_size().encode();
+                    self.answers_tx
+                        .unbounded_send((message_id, answer))
+                        .unwrap();
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+/// Reads a line from stdin for each message received on `requests`.
+fn read_lines_thread(
+    requests: std_mpsc::Receiver<MessageId>,
+    answers: mpsc::UnboundedSender<(MessageId, EncodedMessage)>,
+) {
+    let stdin = io::stdin();
+
+    for message_id in requests {
+        let mut line = String::new();
+        let result = match stdin.lock().read_line(&mut line) {
+            Ok(0) => Err(ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("end of input")),
+            Ok(_) => {
+                let len = line.trim_end_matches(&['\r', '\n'][..]).len();
+                line.truncate(len);
+                Ok(line)
+            }
+            Err(err) => Err(ErrorPayload::new(ErrorClass::IO).with_message(err.to_string())),
+        };
+
+        let answer = ReadLineResponse { result }.encode();
+        if answers.unbounded_send((message_id, answer)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Returns the size of the terminal, as reported by the `COLUMNS` and `LINES` environment
+/// variables.
+// TODO: query the terminal itself
+fn terminal_size() -> SizeResponse {
+    let from_env = |name, default| {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+
+    SizeResponse {
+        columns: from_env("COLUMNS", 80),
+        rows: from_env("LINES", 24),
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the log interface.
+//!
+//! Entries are first passed through a [`LogFilter`], then written to each of the
+//! [`LogBackend`]s of the [`LogHandler`].
+
+use futures::prelude::*;
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_log_interface::ffi::{Level, LogMessage, LogRecord, INTERFACE};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write as _},
+    path::Path,
+    sync::{atomic, Mutex},
+};
+
+/// Native program for `log` interface messages handling.
+pub struct LogHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Which entries to keep.
+    filter: LogFilter,
+    /// Where to write the entries that pass the filter.
+    backends: Vec<Box<dyn LogBackend>>,
+}
+
+impl LogHandler {
+    /// Initializes the new state machine for logging. Entries are discarded until a backend is
+    /// added with [`LogHandler::with_backend`].
+    pub fn new(filter: LogFilter) -> Self {
+        LogHandler {
+            registered: atomic::AtomicBool::new(false),
+            filter,
+            backends: Vec::new(),
+        }
+    }
+
+    /// Adds a backend where entries are written.
+    pub fn with_backend(mut self, backend: impl LogBackend + 'static) -> Self {
+        self.backends.push(Box::new(backend));
+        self
+    }
+}
+
+impl NativeProgram for LogHandler {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            loop {
+                futures::pending!()
+            }
+        })
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        _message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match LogMessage::decode(message) {
+            Ok(LogMessage::Log(record)) => {
+                if self.filter.enabled(&record.target, record.level) {
+                    for backend in &self.backends {
+                        backend.write(emitter_pid, &record);
+                    }
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+/// Destination of log entries.
+pub trait LogBackend: Send + Sync {
+    /// Writes an entry emitted by the given process.
+    fn write(&self, emitter: Pid, record: &LogRecord);
+}
+
+/// Writes log entries on the standard error output.
+#[derive(Debug, Default)]
+pub struct ConsoleBackend;
+
+impl LogBackend for ConsoleBackend {
+    fn write(&self, emitter: Pid, record: &LogRecord) {
+        let mut stderr = io::stderr();
+        let _ = writeln!(stderr, "{}", DisplayRecord(emitter, record));
+    }
+}
+
+/// Appends log entries to a file.
+pub struct FileBackend {
+    file: Mutex<File>,
+}
+
+impl FileBackend {
+    /// Opens the file at the given path for appending, creating it if necessary.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(FileBackend {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl LogBackend for FileBackend {
+    fn write(&self, emitter: Pid, record: &LogRecord) {
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{}", DisplayRecord(emitter, record));
+    }
+}
+
+/// Formats a record as a single line.
+struct DisplayRecord<'a>(Pid, &'a LogRecord);
+
+impl<'a> fmt::Display for DisplayRecord<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.1.level {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+
+        write!(
+            f,
+            "[{:<5} {} {:?}] {}",
+            level, self.1.target, self.0, self.1.message
+        )?;
+        for (key, value) in &self.1.fields {
+            write!(f, " {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decides which log entries to keep, based on their level and target.
+///
+/// Each target can have its own maximum level. A target also applies to its children: `net`
+/// applies to `net::dhcp`, unless `net::dhcp` has its own level. Targets without any
+/// configured level use the default level.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: Level,
+    targets: Vec<(String, Level)>,
+}
+
+impl LogFilter {
+    /// Builds a filter that keeps the entries of level `default` or more important.
+    pub fn new(default: Level) -> Self {
+        LogFilter {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum level for the given target and its children.
+    pub fn with_target(mut self, target: impl Into<String>, level: Level) -> Self {
+        let target = target.into();
+        self.targets.retain(|(t, _)| *t != target);
+        self.targets.push((target, level));
+        self
+    }
+
+    /// Parses a list of comma-separated directives, such as `info,net=debug,net::dhcp=trace`.
+    /// A directive without target sets the default level.
+    pub fn parse(directives: &str) -> Result<Self, ParseFilterError> {
+        let mut filter = LogFilter::new(Level::Info);
+
+        for directive in directives.split(',').map(|d| d.trim()) {
+            if directive.is_empty() {
+                continue;
+            }
+
+            let mut iter = directive.splitn(2, '=');
+            match (iter.next(), iter.next()) {
+                (Some(level), None) => filter.default = parse_level(level)?,
+                (Some(target), Some(level)) => {
+                    filter = filter.with_target(target.trim(), parse_level(level)?)
+                }
+                (None, _) => unreachable!(),
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Returns true if an entry with the given target and level must be kept.
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let max_level = self
+            .targets
+            .iter()
+            .filter(|(t, _)| {
+                target == t
+                    || (target.starts_with(t.as_str()) && target[t.len()..].starts_with("::"))
+            })
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, l)| *l)
+            .unwrap_or(self.default);
+        level <= max_level
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter::new(Level::Info)
+    }
+}
+
+fn parse_level(level: &str) -> Result<Level, ParseFilterError> {
+    match level.trim().to_ascii_lowercase().as_str() {
+        "error" => Ok(Level::Error),
+        "warn" => Ok(Level::Warn),
+        "info" => Ok(Level::Info),
+        "debug" => Ok(Level::Debug),
+        "trace" => Ok(Level::Trace),
+        _ => Err(ParseFilterError {
+            level: level.trim().to_owned(),
+        }),
+    }
+}
+
+/// Error that can happen when parsing a [`LogFilter`].
+#[derive(Debug)]
+pub struct ParseFilterError {
+    level: String,
+}
+
+impl std::str::FromStr for LogFilter {
+    type Err = ParseFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LogFilter::parse(s)
+    }
+}
+
+impl fmt::Display for ParseFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown log level: {:?}", self.level)
+    }
+}
+
+impl std::error::Error for ParseFilterError {}
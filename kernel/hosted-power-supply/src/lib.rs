@@ -0,0 +1,258 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Implements the power supply interface by reporting the power sources of the host.
+//!
+//! On Linux, the state is read from `/sys/class/power_supply`. On other platforms, the state is
+//! reported as unknown.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use futures_timer::Delay;
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_power_supply_interface::ffi::{
+    Battery, BatteryState, PowerSupplyMessage, Status, INTERFACE,
+};
+use std::{collections::VecDeque, fs, path::Path, sync::atomic, time::Duration};
+
+/// Period at which the state of the host is read in order to detect changes.
+const POLL_PERIOD: Duration = Duration::from_secs(5);
+
+/// State machine for `power-supply` interface messages handling.
+pub struct PowerSupplyHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Accessed only by `next_event`.
+    inner: Mutex<Inner>,
+    /// Send on this channel the received interface messages and cancellations.
+    messages_tx: mpsc::UnboundedSender<ToHandler>,
+}
+
+/// Separate struct behind a mutex.
+struct Inner {
+    /// Receiving side of [`PowerSupplyHandler::messages_tx`].
+    messages_rx: mpsc::UnboundedReceiver<ToHandler>,
+    /// Processes watching for changes, and the message to answer.
+    subscribers: Vec<(Pid, MessageId)>,
+    /// State reported to the subscribers the last time.
+    last_status: Option<Status>,
+    /// Fires when the state of the host must be read again.
+    poll_timer: Delay,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+/// Message sent on [`PowerSupplyHandler::messages_tx`].
+enum ToHandler {
+    Status(MessageId),
+    Subscribe(Pid, MessageId),
+    Cancelled(MessageId),
+    ProcessDestroyed(Pid),
+}
+
+impl PowerSupplyHandler {
+    /// Initializes the new state machine for the power supply interface.
+    pub fn new() -> Self {
+        let (messages_tx, messages_rx) = mpsc::unbounded();
+
+        PowerSupplyHandler {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                messages_rx,
+                subscribers: Vec::new(),
+                last_status: None,
+                poll_timer: Delay::new(POLL_PERIOD),
+                events: VecDeque::new(),
+            }),
+            messages_tx,
+        }
+    }
+}
+
+impl NativeProgram for PowerSupplyHandler {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let mut inner = self.inner.lock().await;
+            let inner = &mut *inner;
+
+            loop {
+                if let Some(event) = inner.events.pop_front() {
+                    return event;
+                }
+
+                match future::select(inner.messages_rx.next(), &mut inner.poll_timer).await {
+                    future::Either::Left((Some(ToHandler::Status(message_id)), _)) => {
+                        return NativeProgramEvent::Answer {
+                            message_id,
+                            answer: Ok(read_status().encode()),
+                        };
+                    }
+                    future::Either::Left((Some(ToHandler::Subscribe(pid, message_id)), _)) => {
+                        inner.subscribers.push((pid, message_id));
+                        let status = read_status();
+                        inner.last_status = Some(status.clone());
+                        return NativeProgramEvent::PartialAnswer {
+                            message_id,
+                            answer: status.encode(),
+                        };
+                    }
+                    future::Either::Left((Some(ToHandler::Cancelled(message_id)), _)) => {
+                        inner.subscribers.retain(|(_, id)| *id != message_id);
+                    }
+                    future::Either::Left((Some(ToHandler::ProcessDestroyed(pid)), _)) => {
+                        inner.subscribers.retain(|(p, _)| *p != pid);
+                    }
+                    future::Either::Left((None, _)) => unreachable!(),
+                    future::Either::Right(((), _)) => {
+                        inner.poll_timer = Delay::new(POLL_PERIOD);
+                        if inner.subscribers.is_empty() {
+                            continue;
+                        }
+
+                        let status = read_status();
+                        if inner.last_status.as_ref() == Some(&status) {
+                            continue;
+                        }
+
+                        for (_, message_id) in &inner.subscribers {
+                            inner.events.push_back(NativeProgramEvent::PartialAnswer {
+                                message_id: *message_id,
+                                answer: status.encode(),
+                            });
+                        }
+                        inner.last_status = Some(status);
+                    }
+                }
+            }
+        })
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let message_id = match message_id {
+            Some(m) => m,
+            None => return,
+        };
+
+        let to_handler = match PowerSupplyMessage::decode(message) {
+            Ok(PowerSupplyMessage::Status) => ToHandler::Status(message_id),
+            Ok(PowerSupplyMessage::Subscribe) => ToHandler::Subscribe(emitter_pid, message_id),
+            Err(_) => return,
+        };
+
+        self.messages_tx.unbounded_send(to_handler).unwrap();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        self.messages_tx
+            .unbounded_send(ToHandler::ProcessDestroyed(pid))
+            .unwrap();
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        self.messages_tx
+            .unbounded_send(ToHandler::Cancelled(message_id))
+            .unwrap();
+    }
+}
+
+/// Reads the state of the power sources of the host.
+fn read_status() -> Status {
+    let mut status = Status {
+        ac_online: None,
+        batteries: Vec::new(),
+    };
+
+    let entries = match fs::read_dir("/sys/class/power_supply") {
+        Ok(e) => e,
+        Err(_) => return status,
+    };
+
+    let mut entries = entries.filter_map(|e| e.ok()).collect::<Vec<_>>();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        match read_attribute(&path, "type").as_ref().map(|s| &s[..]) {
+            Some("Mains") => {
+                let online = read_attribute(&path, "online").map_or(false, |v| v == "1");
+                status.ac_online = Some(status.ac_online.unwrap_or(false) || online);
+            }
+            Some("Battery") => {
+                if read_attribute(&path, "present").map_or(false, |v| v == "0") {
+                    continue;
+                }
+
+                let state = match read_attribute(&path, "status").as_ref().map(|s| &s[..]) {
+                    Some("Charging") => BatteryState::Charging,
+                    Some("Discharging") => BatteryState::Discharging,
+                    Some("Full") => BatteryState::Full,
+                    Some("Not charging") => BatteryState::NotCharging,
+                    _ => BatteryState::Unknown,
+                };
+
+                let time_remaining_secs = match state {
+                    BatteryState::Charging => read_attribute(&path, "time_to_full_now"),
+                    BatteryState::Discharging => read_attribute(&path, "time_to_empty_now"),
+                    _ => None,
+                };
+
+                status.batteries.push(Battery {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    state,
+                    charge_percent: read_attribute(&path, "capacity")
+                        .and_then(|v| v.parse::<u8>().ok())
+                        .map(|v| v.min(100)),
+                    time_remaining_secs: time_remaining_secs.and_then(|v| v.parse().ok()),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    status
+}
+
+/// Reads an attribute of a power supply in sysfs.
+fn read_attribute(supply: &Path, attribute: &str) -> Option<String> {
+    fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|v| v.trim().to_owned())
+}
@@ -0,0 +1,108 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the random interface by querying the random number generator of the host
+//! operating system.
+
+use futures::{channel::mpsc, lock::Mutex, prelude::*};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_random_interface::ffi::{GenerateResponse, RandomMessage, INTERFACE};
+use std::sync::atomic;
+
+/// Native program for `random` interface messages handling.
+pub struct RandomHandler {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Send on this channel the answers to emit.
+    answers_tx: mpsc::UnboundedSender<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Receiving side of [`RandomHandler::answers_tx`]. Accessed only by `next_event`.
+    answers_rx: Mutex<mpsc::UnboundedReceiver<(MessageId, Result<EncodedMessage, ()>)>>,
+}
+
+impl RandomHandler {
+    /// Initializes the new state machine for random messages handling.
+    pub fn new() -> Self {
+        let (answers_tx, answers_rx) = mpsc::unbounded();
+
+        RandomHandler {
+            registered: atomic::AtomicBool::new(false),
+            answers_tx,
+            answers_rx: Mutex::new(answers_rx),
+        }
+    }
+}
+
+impl NativeProgram for RandomHandler {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(async move {
+            if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+                return NativeProgramEvent::Emit {
+                    interface: redshirt_interface_interface::ffi::INTERFACE,
+                    message_id_write: None,
+                    message: redshirt_interface_interface::ffi::InterfaceMessage::Register(
+                        INTERFACE,
+                    )
+                    .encode(),
+                };
+            }
+
+            let mut answers_rx = self.answers_rx.lock().await;
+            let (message_id, answer) = answers_rx.next().await.unwrap();
+            NativeProgramEvent::Answer { message_id, answer }
+        })
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let message_id = match message_id {
+            Some(m) => m,
+            None => return,
+        };
+
+        let answer = match RandomMessage::decode(message) {
+            Ok(RandomMessage::Generate { len }) => {
+                let mut out = vec![0; usize::from(len)];
+                // Failing to obtain entropy from the host is not recoverable.
+                getrandom::getrandom(&mut out).expect("host random number generator failed");
+                Ok(GenerateResponse { result: out }.encode())
+            }
+            // The generator of the host gathers its own entropy.
+            Ok(RandomMessage::AddEntropy(_)) => return,
+            Err(_) => Err(()),
+        };
+
+        self.answers_tx
+            .unbounded_send((message_id, answer))
+            .unwrap();
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
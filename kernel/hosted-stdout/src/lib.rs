@@ -16,12 +16,13 @@
 //! Implements the stdout interface.
 
 use futures::prelude::*;
-use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
 use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
 use redshirt_stdout_interface::ffi::{StdoutMessage, INTERFACE};
 use std::{
     io::{self, Write as _},
-    pin::Pin,
     sync::atomic,
 };
 
@@ -40,12 +41,10 @@ impl StdoutHandler {
     }
 }
 
-impl<'a> NativeProgramRef<'a> for &'a StdoutHandler {
-    type Future =
-        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+impl NativeProgram for StdoutHandler {
     type MessageIdWrite = DummyMessageIdWrite;
 
-    fn next_event(self) -> Self::Future {
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
         Box::pin(async move {
             if !self.registered.swap(true, atomic::Ordering::Relaxed) {
                 return NativeProgramEvent::Emit {
@@ -65,7 +64,7 @@ impl<'a> NativeProgramRef<'a> for &'a StdoutHandler {
     }
 
     fn interface_message(
-        self,
+        &self,
         interface: InterfaceHash,
         _message_id: Option<MessageId>,
         _emitter_pid: Pid,
@@ -83,9 +82,9 @@ impl<'a> NativeProgramRef<'a> for &'a StdoutHandler {
         }
     }
 
-    fn process_destroyed(self, _: Pid) {}
+    fn process_destroyed(&self, _: Pid) {}
 
-    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
         unreachable!()
     }
 }
@@ -15,14 +15,22 @@
 
 //! Implements the time interface.
 
-use futures::{channel::mpsc, lock::Mutex, prelude::*, stream::FuturesUnordered};
+use futures::{
+    channel::mpsc,
+    future::{AbortHandle, Abortable, Aborted},
+    lock::Mutex,
+    prelude::*,
+    stream::FuturesUnordered,
+};
 use futures_timer::Delay;
 use redshirt_core::native::{
-    DummyMessageIdWrite, NativeProgramEvent, NativeProgramMessageIdWrite, NativeProgramRef,
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+    NativeProgramMessageIdWrite,
 };
 use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
 use redshirt_time_interface::ffi::{TimeMessage, INTERFACE};
 use std::{
+    collections::HashMap,
     convert::TryFrom,
     pin::Pin,
     sync::atomic,
@@ -35,16 +43,32 @@ pub struct TimerHandler {
     registered: atomic::AtomicBool,
     /// Accessed only by `next_event`.
     inner: Mutex<TimerHandlerInner>,
-    /// Send on this channel the received interface messages.
-    messages_tx: mpsc::UnboundedSender<(TimeMessage, MessageId)>,
+    /// Send on this channel the received interface messages and cancellations.
+    messages_tx: mpsc::UnboundedSender<ToHandler>,
 }
 
 /// Separate struct behind a mutex.
 struct TimerHandlerInner {
-    /// Stream of message IDs to answer.
-    timers: FuturesUnordered<Pin<Box<dyn Future<Output = MessageId> + Send>>>, // TODO: meh for boxing
+    /// Stream of timers that have fired, or `Err` if the timer has been cancelled.
+    timers: FuturesUnordered<Pin<Box<dyn Future<Output = Result<Timer, Aborted>> + Send>>>, // TODO: meh for boxing
+    /// For each message whose timer is in [`TimerHandlerInner::timers`], a way to cancel it.
+    abort_handles: HashMap<MessageId, AbortHandle>,
     /// Receiving side of [`TimerHandler::messages_tx`].
-    messages_rx: mpsc::UnboundedReceiver<(TimeMessage, MessageId)>,
+    messages_rx: mpsc::UnboundedReceiver<ToHandler>,
+}
+
+/// Message sent on [`TimerHandler::messages_tx`].
+enum ToHandler {
+    Message(TimeMessage, MessageId),
+    Cancelled(MessageId),
+}
+
+/// Timer that has fired.
+struct Timer {
+    message_id: MessageId,
+    /// For periodic timers, the value of the monotonic clock when it has been scheduled to fire,
+    /// and the period.
+    periodic: Option<(u128, u128)>,
 }
 
 impl TimerHandler {
@@ -56,8 +80,9 @@ impl TimerHandler {
             registered: atomic::AtomicBool::new(false),
             inner: Mutex::new(TimerHandlerInner {
                 timers: {
-                    let timers =
-                        FuturesUnordered::<Pin<Box<dyn Future<Output = MessageId> + Send>>>::new();
+                    let timers = FuturesUnordered::<
+                        Pin<Box<dyn Future<Output = Result<Timer, Aborted>> + Send>>,
+                    >::new();
                     // TODO: ugh; pushing a never-ending future, otherwise we get a permanent `None` when polling
                     timers.push(Box::pin(async move {
                         loop {
@@ -66,6 +91,7 @@ impl TimerHandler {
                     }));
                     timers
                 },
+                abort_handles: HashMap::new(),
                 messages_rx,
             }),
             messages_tx,
@@ -73,12 +99,38 @@ impl TimerHandler {
     }
 }
 
-impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
-    type Future =
-        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+impl TimerHandlerInner {
+    /// Adds a timer that fires when the monotonic clock reaches `until`.
+    fn push_timer(&mut self, message_id: MessageId, until: u128, period: Option<u128>) {
+        let dur_from_now = until.saturating_sub(monotonic_clock());
+
+        // If `dur_from_now` is larger than a `u64`, we simply don't insert any timer.
+        // We assume that we will never reach this time ever.
+        let dur = match u64::try_from(dur_from_now) {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        self.abort_handles.insert(message_id, abort_handle);
+
+        let delay = Delay::new(Duration::from_nanos(dur));
+        let timer = async move {
+            delay.await;
+            Timer {
+                message_id,
+                periodic: period.map(|p| (until, p)),
+            }
+        };
+        self.timers
+            .push(Box::pin(Abortable::new(timer, abort_registration)));
+    }
+}
+
+impl NativeProgram for TimerHandler {
     type MessageIdWrite = DummyMessageIdWrite;
 
-    fn next_event(self) -> Self::Future {
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
         Box::pin(async move {
             if !self.registered.swap(true, atomic::Ordering::Relaxed) {
                 return NativeProgramEvent::Emit {
@@ -96,49 +148,71 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
 
             loop {
                 match future::select(inner.timers.next(), inner.messages_rx.next()).await {
-                    future::Either::Left((Some(message_id), _)) => {
-                        return NativeProgramEvent::Answer {
-                            message_id,
-                            answer: Ok(().encode()),
-                        };
-                    }
-                    future::Either::Right((Some((time_message, message_id)), _)) => {
-                        match time_message {
-                            TimeMessage::GetMonotonic => {
-                                return NativeProgramEvent::Answer {
-                                    message_id,
-                                    answer: Ok(monotonic_clock().encode()),
-                                };
+                    future::Either::Left((Some(Ok(timer)), _)) => match timer.periodic {
+                        None => {
+                            inner.abort_handles.remove(&timer.message_id);
+                            return NativeProgramEvent::Answer {
+                                message_id: timer.message_id,
+                                answer: Ok(().encode()),
+                            };
+                        }
+                        Some((fired_at, period)) => {
+                            // Skip the ticks that we have missed.
+                            let now = monotonic_clock();
+                            let mut next = fired_at.saturating_add(period);
+                            if next <= now {
+                                next = next.saturating_add((now - next) / period * period + period);
                             }
-                            TimeMessage::GetSystem => {
+                            inner.push_timer(timer.message_id, next, Some(period));
+                            return NativeProgramEvent::PartialAnswer {
+                                message_id: timer.message_id,
+                                answer: ().encode(),
+                            };
+                        }
+                    },
+                    future::Either::Left((Some(Err(Aborted)), _)) => {}
+                    future::Either::Right((Some(ToHandler::Cancelled(message_id)), _)) => {
+                        if let Some(abort_handle) = inner.abort_handles.remove(&message_id) {
+                            abort_handle.abort();
+                        }
+                    }
+                    future::Either::Right((
+                        Some(ToHandler::Message(time_message, message_id)),
+                        _,
+                    )) => match time_message {
+                        TimeMessage::GetMonotonic => {
+                            return NativeProgramEvent::Answer {
+                                message_id,
+                                answer: Ok(monotonic_clock().encode()),
+                            };
+                        }
+                        TimeMessage::GetSystem => {
+                            return NativeProgramEvent::Answer {
+                                message_id,
+                                answer: Ok(system_clock().encode()),
+                            };
+                        }
+                        TimeMessage::WaitMonotonic(until) => {
+                            if until <= monotonic_clock() {
                                 return NativeProgramEvent::Answer {
                                     message_id,
-                                    answer: Ok(system_clock().encode()),
+                                    answer: Ok(().encode()),
                                 };
                             }
-                            TimeMessage::WaitMonotonic(until) => {
-                                match until.checked_sub(monotonic_clock()) {
-                                    None => {
-                                        return NativeProgramEvent::Answer {
-                                            message_id,
-                                            answer: Ok(().encode()),
-                                        }
-                                    }
-                                    Some(dur_from_now) => {
-                                        // If `dur_from_now` is larger than a `u64`, we simply don't insert any timer.
-                                        // We assume that we will never reach this time ever.
-                                        if let Ok(dur) = u64::try_from(dur_from_now) {
-                                            let delay = Delay::new(Duration::from_nanos(dur));
-                                            inner.timers.push(Box::pin(async move {
-                                                delay.await;
-                                                message_id
-                                            }));
-                                        }
-                                    }
-                                }
-                            }
+                            inner.push_timer(message_id, until, None);
                         }
-                    }
+                        TimeMessage::WaitMonotonicPeriodic { period: 0, .. } => {
+                            return NativeProgramEvent::Answer {
+                                message_id,
+                                answer: Err(()),
+                            };
+                        }
+                        TimeMessage::WaitMonotonicPeriodic { first, period } => {
+                            inner.push_timer(message_id, first, Some(period));
+                        }
+                        // Filtered out by `interface_message`.
+                        TimeMessage::SetSystem(_) => unreachable!(),
+                    },
                     future::Either::Left((None, _)) => unreachable!(),
                     future::Either::Right((None, _)) => unreachable!(),
                 }
@@ -147,7 +221,7 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
     }
 
     fn interface_message(
-        self,
+        &self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
         emitter_pid: Pid,
@@ -156,23 +230,48 @@ impl<'a> NativeProgramRef<'a> for &'a TimerHandler {
         debug_assert_eq!(interface, INTERFACE);
 
         match TimeMessage::decode(message) {
+            // The system clock is the one of the host, which we don't modify.
+            Ok(TimeMessage::SetSystem(_)) => {}
             Ok(msg) => {
                 self.messages_tx
-                    .unbounded_send((msg, message_id.unwrap()))
+                    .unbounded_send(ToHandler::Message(msg, message_id.unwrap()))
                     .unwrap();
             }
             Err(_) => {}
         }
     }
 
-    fn process_destroyed(self, _: Pid) {}
+    fn process_destroyed(&self, _: Pid) {}
 
-    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
         unreachable!()
     }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        self.messages_tx
+            .unbounded_send(ToHandler::Cancelled(message_id))
+            .unwrap();
+    }
+}
+
+/// Returns a future that resolves once [`monotonic_clock`] reaches `until`.
+pub fn monotonic_clock_reaches(until: u128) -> impl Future<Output = ()> + Send {
+    let dur_from_now = until.saturating_sub(monotonic_clock());
+    // If `dur_from_now` is larger than a `u64`, the future never resolves.
+    // We assume that we will never reach this time ever.
+    let delay = u64::try_from(dur_from_now)
+        .ok()
+        .map(|dur| Delay::new(Duration::from_nanos(dur)));
+    async move {
+        match delay {
+            Some(delay) => delay.await,
+            None => future::pending().await,
+        }
+    }
 }
 
-fn monotonic_clock() -> u128 {
+/// Returns the value of the monotonic clock reported to programs, in nanoseconds.
+pub fn monotonic_clock() -> u128 {
     lazy_static::lazy_static! {
         static ref CLOCK_START: Instant = Instant::now();
     }
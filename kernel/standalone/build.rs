@@ -60,9 +60,22 @@ fn main() {
         .arg("rustc")
         .arg("--release")
         .args(&["--target", "wasm32-unknown-unknown"])
-        .args(&["--package", "x86-stdout"])
-        .args(&["--bin", "x86-stdout"])
-        .args(&["--manifest-path", "../../modules/x86-stdout/Cargo.toml"])
+        .args(&["--package", "sdhci"])
+        .args(&["--bin", "sdhci"])
+        .args(&["--manifest-path", "../../modules/sdhci/Cargo.toml"])
+        .arg("--")
+        .args(&["-C", "link-arg=--export-table"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = Command::new("cargo")
+        .arg("rustc")
+        .arg("--release")
+        .args(&["--target", "wasm32-unknown-unknown"])
+        .args(&["--package", "device-manager"])
+        .args(&["--bin", "device-manager"])
+        .args(&["--manifest-path", "../../modules/device-manager/Cargo.toml"])
         .arg("--")
         .args(&["-C", "link-arg=--export-table"])
         .status()
@@ -82,6 +95,19 @@ fn main() {
         .unwrap();
     assert!(status.success());
 
+    let status = Command::new("cargo")
+        .arg("rustc")
+        .arg("--release")
+        .args(&["--target", "wasm32-unknown-unknown"])
+        .args(&["--package", "ps2"])
+        .args(&["--bin", "ps2"])
+        .args(&["--manifest-path", "../../modules/ps2/Cargo.toml"])
+        .arg("--")
+        .args(&["-C", "link-arg=--export-table"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
     let status = Command::new("cargo")
         .arg("rustc")
         .args(&["--target", "wasm32-unknown-unknown"])
@@ -0,0 +1,123 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `acpi` interface.
+
+use crate::acpi::tables::Tables;
+
+use alloc::boxed::Box;
+use core::{sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_acpi_interface::ffi::{
+    AcpiMessage, FadtResponse, MadtResponse, McfgResponse, INTERFACE,
+};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+
+/// State machine for `acpi` interface messages handling.
+pub struct AcpiNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Tables passed when the program has been created.
+    tables: Tables,
+    /// Message responses waiting to be emitted.
+    pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when a message is pushed to `pending_messages`.
+    waker: AtomicWaker,
+}
+
+impl AcpiNativeProgram {
+    /// Initializes the new state machine that gives access to the given tables.
+    pub fn new(tables: Tables) -> Self {
+        AcpiNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            tables,
+            pending_messages: SegQueue::new(),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for AcpiNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                Poll::Ready(NativeProgramEvent::Answer { message_id, answer })
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let message_id = match message_id {
+            Some(m) => m,
+            None => return,
+        };
+
+        let answer = match AcpiMessage::decode(message) {
+            Ok(AcpiMessage::Madt) => {
+                let result = self.tables.madt.clone().ok_or_else(not_found);
+                Ok(MadtResponse { result }.encode())
+            }
+            Ok(AcpiMessage::Mcfg) => {
+                let result = self.tables.mcfg.clone().ok_or_else(not_found);
+                Ok(McfgResponse { result }.encode())
+            }
+            Ok(AcpiMessage::Fadt) => {
+                let result = self.tables.fadt.clone().ok_or_else(not_found);
+                Ok(FadtResponse { result }.encode())
+            }
+            Err(_) => Err(()),
+        };
+
+        self.pending_messages.push((message_id, answer));
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+fn not_found() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("table not present")
+}
@@ -0,0 +1,122 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Entering ACPI sleep states.
+
+use crate::{acpi::tables, arch};
+
+use core::convert::TryFrom as _;
+use redshirt_acpi_interface::ffi;
+
+/// Bit of the PM1 control registers that indicates that the system is in ACPI mode.
+const SCI_EN: u16 = 1 << 0;
+/// Bit of the PM1 control registers that triggers the transition to the sleep state.
+const SLP_EN: u16 = 1 << 13;
+
+/// Puts the machine in the S5 ("soft off") state. Only returns if that has failed.
+///
+/// The values to write in the PM1 control registers are normally obtained by evaluating the
+/// `\_S5` object of the DSDT, which requires an AML interpreter. We instead look for the bytes
+/// that define this object and decode them, which works with the vast majority of firmwares.
+///
+/// # Safety
+///
+/// The memory containing the DSDT must be identity-mapped.
+///
+pub unsafe fn enter_s5(fadt: &ffi::Fadt) {
+    // TODO: use an AML interpreter
+    let (slp_typa, slp_typb) = match find_s5(fadt.dsdt_address) {
+        Some(v) => v,
+        None => return,
+    };
+
+    if fadt.pm1a_control_block == 0 {
+        return;
+    }
+
+    enable_acpi(fadt);
+
+    arch::write_port_u16(
+        fadt.pm1a_control_block,
+        (u16::from(slp_typa & 0b111) << 10) | SLP_EN,
+    );
+    if fadt.pm1b_control_block != 0 {
+        arch::write_port_u16(
+            fadt.pm1b_control_block,
+            (u16::from(slp_typb & 0b111) << 10) | SLP_EN,
+        );
+    }
+}
+
+/// Switches the system to ACPI mode, if it isn't already.
+unsafe fn enable_acpi(fadt: &ffi::Fadt) {
+    if arch::read_port_u16(fadt.pm1a_control_block) & SCI_EN != 0 {
+        return;
+    }
+
+    if fadt.smi_command_port == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    arch::write_port_u8(fadt.smi_command_port, fadt.acpi_enable);
+    for _ in 0..1_000_000 {
+        if arch::read_port_u16(fadt.pm1a_control_block) & SCI_EN != 0 {
+            break;
+        }
+    }
+}
+
+/// Looks for the `\_S5` package in the DSDT and returns its first two elements, which are the
+/// values of `SLP_TYPa` and `SLP_TYPb`.
+unsafe fn find_s5(dsdt_address: u64) -> Option<(u8, u8)> {
+    let dsdt = tables::read_table(usize::try_from(dsdt_address).ok()?)?;
+    let body = &dsdt[36..];
+
+    let position = body.windows(4).position(|w| w == b"_S5_")?;
+
+    // The name must be preceded by a `NameOp`, optionally followed by a root prefix.
+    let before = &body[..position];
+    if !before.ends_with(&[0x08]) && !before.ends_with(&[0x08, b'\\']) {
+        return None;
+    }
+
+    // `PackageOp`.
+    let rest = &body[position + 4..];
+    if rest.first() != Some(&0x12) {
+        return None;
+    }
+
+    // The number of additional bytes of the `PkgLength` is in bits 6 and 7 of its first byte.
+    let pkg_length_len = usize::from(rest.get(1)? >> 6) + 1;
+    // Skip the `PackageOp`, the `PkgLength` and the `NumElements`.
+    let rest = rest.get(1 + pkg_length_len + 1..)?;
+
+    let (slp_typa, rest) = aml_integer(rest)?;
+    let (slp_typb, _) = aml_integer(rest)?;
+    Some((slp_typa, slp_typb))
+}
+
+/// Decodes a small AML integer constant and returns it and the bytes that follow.
+fn aml_integer(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    match *bytes.first()? {
+        // `ZeroOp`.
+        0x00 => Some((0, &bytes[1..])),
+        // `OneOp`.
+        0x01 => Some((1, &bytes[1..])),
+        // `BytePrefix`.
+        0x0a => Some((*bytes.get(1)?, bytes.get(2..)?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parsing of the ACPI tables.
+//!
+//! The tables are read directly from physical memory, which is assumed to be identity-mapped.
+//!
+//! See https://uefi.org/specifications
+
+use alloc::vec::Vec;
+use core::{convert::TryFrom as _, slice};
+use redshirt_acpi_interface::ffi;
+
+/// Size of the header common to all the tables.
+const HEADER_LEN: usize = 36;
+
+/// Physical address of the root of the ACPI tables.
+#[derive(Debug, Copy, Clone)]
+pub enum RootTable {
+    /// Root System Description Table, containing 32 bits pointers. Used by ACPI 1.0.
+    Rsdt(usize),
+    /// Extended System Description Table, containing 64 bits pointers.
+    Xsdt(usize),
+}
+
+/// Tables found in memory. Tables that are missing or invalid are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Tables {
+    pub madt: Option<ffi::Madt>,
+    pub mcfg: Option<ffi::Mcfg>,
+    pub fadt: Option<ffi::Fadt>,
+}
+
+/// Walks through the tables referenced by the root table and parses the ones we know about.
+///
+/// # Safety
+///
+/// The root table must be at the given address, and the memory containing the tables must be
+/// identity-mapped.
+///
+pub unsafe fn parse(root: RootTable) -> Tables {
+    let mut out = Tables::default();
+
+    let (address, pointer_size) = match root {
+        RootTable::Rsdt(address) => (address, 4),
+        RootTable::Xsdt(address) => (address, 8),
+    };
+
+    let root = match read_table(address) {
+        Some(t) => t,
+        None => return out,
+    };
+
+    for pointer in root[HEADER_LEN..].chunks_exact(pointer_size) {
+        let address = match pointer_size {
+            4 => u64::from(u32::from_le_bytes([
+                pointer[0], pointer[1], pointer[2], pointer[3],
+            ])),
+            _ => u64::from_le_bytes([
+                pointer[0], pointer[1], pointer[2], pointer[3], pointer[4], pointer[5], pointer[6],
+                pointer[7],
+            ]),
+        };
+
+        let table = match usize::try_from(address).ok().and_then(|a| read_table(a)) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        match &table[0..4] {
+            b"APIC" if out.madt.is_none() => out.madt = parse_madt(table),
+            b"MCFG" if out.mcfg.is_none() => out.mcfg = parse_mcfg(table),
+            b"FACP" if out.fadt.is_none() => out.fadt = parse_fadt(table),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Returns the table at the given address, including its header, or `None` if its checksum is
+/// wrong.
+pub(super) unsafe fn read_table(address: usize) -> Option<&'static [u8]> {
+    if address == 0 {
+        return None;
+    }
+
+    let header = slice::from_raw_parts(address as *const u8, HEADER_LEN);
+    let len = usize::try_from(u32_at(header, 4)?).ok()?;
+    if len < HEADER_LEN {
+        return None;
+    }
+
+    let table = slice::from_raw_parts(address as *const u8, len);
+    if table.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+        return None;
+    }
+
+    Some(table)
+}
+
+fn parse_madt(table: &[u8]) -> Option<ffi::Madt> {
+    let mut out = ffi::Madt {
+        local_apic_address: u64::from(u32_at(table, 36)?),
+        legacy_pics: u32_at(table, 40)? & 0x1 != 0,
+        processors: Vec::new(),
+        io_apics: Vec::new(),
+        interrupt_source_overrides: Vec::new(),
+        local_apic_nmis: Vec::new(),
+    };
+
+    let mut entries = &table[44..];
+    while entries.len() >= 2 {
+        let entry_len = usize::from(entries[1]);
+        if entry_len < 2 || entry_len > entries.len() {
+            break;
+        }
+        let entry = &entries[..entry_len];
+        entries = &entries[entry_len..];
+
+        match entry[0] {
+            // Processor local APIC.
+            0 => out.processors.push(ffi::Processor {
+                processor_uid: u32::from(u8_at(entry, 2)?),
+                apic_id: u32::from(u8_at(entry, 3)?),
+                enabled: u32_at(entry, 4)? & 0x1 != 0,
+            }),
+            // I/O APIC.
+            1 => out.io_apics.push(ffi::IoApic {
+                id: u8_at(entry, 2)?,
+                address: u32_at(entry, 4)?,
+                global_system_interrupt_base: u32_at(entry, 8)?,
+            }),
+            // Interrupt source override.
+            2 => {
+                let flags = u16_at(entry, 8)?;
+                out.interrupt_source_overrides
+                    .push(ffi::InterruptSourceOverride {
+                        bus: u8_at(entry, 2)?,
+                        source: u8_at(entry, 3)?,
+                        global_system_interrupt: u32_at(entry, 4)?,
+                        polarity: polarity(flags),
+                        trigger_mode: trigger_mode(flags),
+                    })
+            }
+            // Local APIC NMI.
+            4 => {
+                let processor_uid = u8_at(entry, 2)?;
+                let flags = u16_at(entry, 3)?;
+                out.local_apic_nmis.push(ffi::LocalApicNmi {
+                    processor_uid: if processor_uid == 0xff {
+                        None
+                    } else {
+                        Some(u32::from(processor_uid))
+                    },
+                    lint: u8_at(entry, 5)?,
+                    polarity: polarity(flags),
+                    trigger_mode: trigger_mode(flags),
+                })
+            }
+            // Local APIC address override.
+            5 => out.local_apic_address = u64_at(entry, 4)?,
+            // Processor local x2APIC.
+            9 => out.processors.push(ffi::Processor {
+                processor_uid: u32_at(entry, 12)?,
+                apic_id: u32_at(entry, 4)?,
+                enabled: u32_at(entry, 8)? & 0x1 != 0,
+            }),
+            // Local x2APIC NMI.
+            0xa => {
+                let flags = u16_at(entry, 2)?;
+                let processor_uid = u32_at(entry, 4)?;
+                out.local_apic_nmis.push(ffi::LocalApicNmi {
+                    processor_uid: if processor_uid == 0xffffffff {
+                        None
+                    } else {
+                        Some(processor_uid)
+                    },
+                    lint: u8_at(entry, 8)?,
+                    polarity: polarity(flags),
+                    trigger_mode: trigger_mode(flags),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    Some(out)
+}
+
+fn parse_mcfg(table: &[u8]) -> Option<ffi::Mcfg> {
+    // The entries follow 8 reserved bytes.
+    let entries = table
+        .get(44..)?
+        .chunks_exact(16)
+        .map(|entry| {
+            Some(ffi::McfgEntry {
+                base_address: u64_at(entry, 0)?,
+                segment_group: u16_at(entry, 8)?,
+                start_bus: u8_at(entry, 10)?,
+                end_bus: u8_at(entry, 11)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(ffi::Mcfg { entries })
+}
+
+fn parse_fadt(table: &[u8]) -> Option<ffi::Fadt> {
+    // The table has grown over the revisions of the specifications. Missing fields are 0.
+    let u8_or_0 = |offset| u8_at(table, offset).unwrap_or(0);
+    let u16_or_0 = |offset| u16_at(table, offset).unwrap_or(0);
+    let u32_or_0 = |offset| u32_at(table, offset).unwrap_or(0);
+
+    let flags = u32_or_0(112);
+
+    // Bit 10 of the flags indicates whether the reset register is supported.
+    let reset_register = if flags & (1 << 10) != 0 {
+        generic_address(table, 116)
+    } else {
+        None
+    };
+
+    // The 64 bits address supersedes the 32 bits one if it is non-zero.
+    let dsdt_address = match u64_at(table, 140) {
+        Some(address) if address != 0 => address,
+        _ => u64::from(u32_or_0(40)),
+    };
+
+    Some(ffi::Fadt {
+        dsdt_address,
+        sci_interrupt: u16_at(table, 46)?,
+        smi_command_port: u32_or_0(48),
+        acpi_enable: u8_or_0(52),
+        acpi_disable: u8_or_0(53),
+        pm1a_event_block: u32_or_0(56),
+        pm1b_event_block: u32_or_0(60),
+        pm1a_control_block: u32_or_0(64),
+        pm1b_control_block: u32_or_0(68),
+        pm_timer_block: u32_or_0(76),
+        century_register: u8_or_0(108),
+        boot_architecture_flags: u16_or_0(109),
+        flags,
+        reset_value: if reset_register.is_some() {
+            u8_or_0(128)
+        } else {
+            0
+        },
+        reset_register,
+    })
+}
+
+fn generic_address(bytes: &[u8], offset: usize) -> Option<ffi::GenericAddress> {
+    Some(ffi::GenericAddress {
+        address_space: u8_at(bytes, offset)?,
+        bit_width: u8_at(bytes, offset + 1)?,
+        bit_offset: u8_at(bytes, offset + 2)?,
+        access_size: u8_at(bytes, offset + 3)?,
+        address: u64_at(bytes, offset + 4)?,
+    })
+}
+
+/// Decodes bits 0 and 1 of the MPS INTI flags.
+fn polarity(flags: u16) -> ffi::Polarity {
+    match flags & 0b11 {
+        0b01 => ffi::Polarity::ActiveHigh,
+        0b11 => ffi::Polarity::ActiveLow,
+        _ => ffi::Polarity::SameAsBus,
+    }
+}
+
+/// Decodes bits 2 and 3 of the MPS INTI flags.
+fn trigger_mode(flags: u16) -> ffi::TriggerMode {
+    match (flags >> 2) & 0b11 {
+        0b01 => ffi::TriggerMode::Edge,
+        0b11 => ffi::TriggerMode::Level,
+        _ => ffi::TriggerMode::SameAsBus,
+    }
+}
+
+fn u8_at(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let b = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    let b = bytes.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes([
+        b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+    ]))
+}
@@ -15,7 +15,10 @@
 
 #![cfg(any(target_arch = "arm", target_arch = "aarch64"))]
 
-use core::iter;
+use core::{
+    iter,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 mod misc;
 
@@ -103,6 +106,26 @@ pub fn halt() -> ! {
     }
 }
 
+/// Puts the CPU to sleep until the next interrupt, unless `woken_up` is already true.
+pub fn idle(woken_up: &AtomicBool) {
+    // Interrupts are masked while we check the atomic. `wfi` wakes up the CPU when an interrupt
+    // is pending even if interrupts are masked, and the interrupt is handled once they are
+    // unmasked.
+    unsafe {
+        asm!("cpsid i" :::: "volatile");
+        if !woken_up.load(Ordering::Acquire) {
+            asm!("wfi" :::: "volatile");
+        }
+        asm!("cpsie i" :::: "volatile");
+    }
+}
+
+/// Restarts the machine.
+pub fn reboot() -> ! {
+    // TODO: implement
+    halt()
+}
+
 pub unsafe fn write_port_u8(port: u32, data: u8) {}
 
 pub unsafe fn write_port_u16(port: u32, data: u16) {}
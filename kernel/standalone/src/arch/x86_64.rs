@@ -15,13 +15,18 @@
 
 #![cfg(target_arch = "x86_64")]
 
-use core::{convert::TryFrom as _, ops::Range};
+use core::{
+    convert::TryFrom as _,
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use x86_64::registers::model_specific::Msr;
 use x86_64::structures::port::{PortRead as _, PortWrite as _};
 
 mod acpi;
 mod boot_link;
-mod interrupts;
+pub mod interrupts;
+pub mod ioapic;
 
 /// Called by `boot.S` after basic set up has been performed.
 ///
@@ -40,11 +45,12 @@ extern "C" fn after_boot(multiboot_header: usize) -> ! {
         // TODO: panics in BOCHS
         //let acpi = acpi::load_acpi_tables(&multiboot_info);
 
-        init_pic_apic();
-        interrupts::init();
+        let apic_base_addr = init_pic_apic();
+        interrupts::init(apic_base_addr);
 
         let kernel = crate::kernel::Kernel::init(crate::kernel::KernelConfig {
             num_cpus: 1,
+            acpi_root_table: find_acpi_root_table(&multiboot_info),
             ..Default::default()
         });
 
@@ -59,6 +65,37 @@ pub fn halt() -> ! {
     }
 }
 
+/// Puts the CPU to sleep until the next interrupt, unless `woken_up` is already true.
+pub fn idle(woken_up: &AtomicBool) {
+    // Interrupts are disabled between the moment when we check the atomic and the moment when
+    // we sleep, so that an interrupt setting it to true isn't missed.
+    x86_64::instructions::interrupts::disable();
+    if woken_up.load(Ordering::Acquire) {
+        x86_64::instructions::interrupts::enable();
+        return;
+    }
+
+    // An `sti` opcode only takes effect after the *next* opcode, which is `hlt` here.
+    x86_64::instructions::interrupts::enable();
+    x86_64::instructions::hlt();
+}
+
+/// Restarts the machine.
+pub fn reboot() -> ! {
+    unsafe {
+        // Pulse the reset line of the CPU through the 8042 keyboard controller.
+        write_port_u8(0x64, 0xfe);
+
+        // If that didn't work, trigger a triple fault by loading an empty interrupts table and
+        // raising an interrupt.
+        let idt = x86_64::structures::DescriptorTablePointer { limit: 0, base: 0 };
+        x86_64::instructions::tables::lidt(&idt);
+        asm!("int3" :::: "volatile");
+    }
+
+    halt()
+}
+
 /// Reads the boot information and find the memory ranges that can be used as a heap.
 ///
 /// # Panic
@@ -119,7 +156,25 @@ fn find_free_memory_ranges<'a>(
     })
 }
 
-unsafe fn init_pic_apic() {
+/// Reads the boot information and returns the location of the root of the ACPI tables, if
+/// the bootloader has provided it.
+fn find_acpi_root_table(
+    multiboot_info: &multiboot2::BootInformation,
+) -> Option<crate::acpi::tables::RootTable> {
+    if let Some(rsdp_v2) = multiboot_info.rsdp_v2_tag() {
+        return Some(crate::acpi::tables::RootTable::Xsdt(rsdp_v2.xsdt_address()));
+    }
+
+    if let Some(rsdp_v1) = multiboot_info.rsdp_v1_tag() {
+        return Some(crate::acpi::tables::RootTable::Rsdt(rsdp_v1.rsdt_address()));
+    }
+
+    None
+}
+
+/// Disables the PIC and enables the local APIC. Returns the physical address of the registers
+/// of the local APIC.
+unsafe fn init_pic_apic() -> usize {
     // Remap and disable the PIC.
     //
     // The PIC (Programmable Interrupt Controller) is the old chip responsible for triggering
@@ -161,6 +216,8 @@ unsafe fn init_pic_apic() {
         let val = svr_addr.read_volatile();
         svr_addr.write_volatile(val | 0x100); // Enable spurious interrupts.
     }
+
+    usize::try_from(apic_base_addr).unwrap()
 }
 
 pub unsafe fn write_port_u8(port: u32, data: u8) {
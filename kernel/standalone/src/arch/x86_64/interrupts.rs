@@ -30,10 +30,24 @@
 
 // TODO: init() has to be called; this isn't great
 
-use core::task::Waker;
+use crate::arch::x86_64::ioapic;
+
+use core::{
+    sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    task::Waker,
+};
 use futures::task::AtomicWaker;
 use x86_64::structures::idt;
 
+/// Vectors below this value are reserved for exceptions and for the spurious interrupts of the
+/// disabled PIC. Hardware interrupts must use vectors between this value and 254.
+pub const FIRST_HARDWARE_VECTOR: u8 = 48;
+/// Vector of the spurious interrupts of the local APIC.
+const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// Physical address of the registers of the local APIC.
+static LOCAL_APIC_BASE: AtomicUsize = AtomicUsize::new(0);
+
 /// Registers a `Waker` to wake up when an interrupt happens.
 ///
 /// For each value of `interrupt`, only the latest registered `Waker` will be waken up.
@@ -44,13 +58,42 @@ use x86_64::structures::idt;
 pub fn set_interrupt_waker(interrupt: u8, waker: &Waker) {
     debug_assert_ne!(interrupt, 8);
     debug_assert_ne!(interrupt, 18);
-    WAKERS[usize::from(interrupt)].register(waker);
+    VECTORS[usize::from(interrupt)].waker.register(waker);
 }
 
-/// Initializes the interrupts system.
+/// Returns the number of times the given interrupt has happened since the machine has started.
+pub fn interrupt_count(interrupt: u8) -> u64 {
+    VECTORS[usize::from(interrupt)]
+        .count
+        .load(Ordering::Relaxed)
+}
+
+/// Sets the input of an I/O APIC to mask when the given interrupt happens, or `None` if
+/// nothing must be masked. The I/O APIC is designated by the physical address of its registers.
+pub fn set_mask_on_interrupt(interrupt: u8, ioapic_input: Option<(usize, u8)>) {
+    let vector = &VECTORS[usize::from(interrupt)];
+    match ioapic_input {
+        Some((address, input)) => {
+            vector.ioapic_input.store(input, Ordering::Relaxed);
+            vector.ioapic_address.store(address, Ordering::Release);
+        }
+        None => vector.ioapic_address.store(0, Ordering::Release),
+    }
+}
+
+/// Returns the identifier of the local APIC of the current processor.
+pub fn local_apic_id() -> u8 {
+    let base = LOCAL_APIC_BASE.load(Ordering::Relaxed);
+    debug_assert_ne!(base, 0);
+    unsafe { (((base + 0x20) as *const u32).read_volatile() >> 24) as u8 }
+}
+
+/// Initializes the interrupts system. `local_apic_base` is the physical address of the
+/// registers of the local APIC.
 ///
 /// Before this is called, the waker passed to [`set_interrupt_waker`] will never work.
-pub unsafe fn init() {
+pub unsafe fn init(local_apic_base: usize) {
+    LOCAL_APIC_BASE.store(local_apic_base, Ordering::Relaxed);
     IDT.load();
     x86_64::instructions::interrupts::enable();
 }
@@ -65,14 +108,14 @@ lazy_static::lazy_static! {
             }};
             ($entry:expr, $n:expr) => {{
                 extern "x86-interrupt" fn handler(_: &mut idt::InterruptStackFrame) {
-                    WAKERS[$n].wake();
+                    on_interrupt($n);
                 }
                 $entry.set_handler_fn(handler)
                     .disable_interrupts(false);
             }};
             ($entry:expr, $n:expr, with-err) => {{
                 extern "x86-interrupt" fn handler(_: &mut idt::InterruptStackFrame, _: u64) {
-                    WAKERS[$n].wake();
+                    on_interrupt($n);
                 }
                 $entry.set_handler_fn(handler)
                     .disable_interrupts(false);
@@ -87,7 +130,7 @@ lazy_static::lazy_static! {
             }};
             ($entry:expr, $n:expr, with-pf-err) => {{
                 extern "x86-interrupt" fn handler(_: &mut idt::InterruptStackFrame, _: idt::PageFaultErrorCode) {
-                    WAKERS[$n].wake();
+                    on_interrupt($n);
                 }
                 $entry.set_handler_fn(handler)
                     .disable_interrupts(false);
@@ -367,263 +410,310 @@ lazy_static::lazy_static! {
     };
 }
 
-/// For each interrupt vector, a [`Waker`](core::task::Waker) that must be waken up when that
-/// interrupt happens.
-static WAKERS: [AtomicWaker; 256] = [
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
-    AtomicWaker::new(),
+/// State of each interrupt vector.
+static VECTORS: [VectorState; 256] = [
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
+    VectorState::new(),
 ];
+
+/// State of an interrupt vector.
+struct VectorState {
+    /// Waken up when the interrupt happens.
+    waker: AtomicWaker,
+    /// Number of times the interrupt has happened.
+    count: AtomicU64,
+    /// Physical address of the I/O APIC whose input must be masked when the interrupt happens,
+    /// or 0 if none.
+    ioapic_address: AtomicUsize,
+    /// Input of the I/O APIC to mask.
+    ioapic_input: AtomicU8,
+}
+
+impl VectorState {
+    const fn new() -> Self {
+        VectorState {
+            waker: AtomicWaker::new(),
+            count: AtomicU64::new(0),
+            ioapic_address: AtomicUsize::new(0),
+            ioapic_input: AtomicU8::new(0),
+        }
+    }
+}
+
+/// Called by the interrupt handlers.
+fn on_interrupt(interrupt: usize) {
+    let vector = &VECTORS[interrupt];
+    vector.count.fetch_add(1, Ordering::Relaxed);
+
+    if interrupt >= usize::from(FIRST_HARDWARE_VECTOR) && interrupt != usize::from(SPURIOUS_VECTOR)
+    {
+        unsafe {
+            // Level-triggered interrupts would otherwise immediately happen again.
+            let ioapic_address = vector.ioapic_address.load(Ordering::Acquire);
+            if ioapic_address != 0 {
+                let input = vector.ioapic_input.load(Ordering::Relaxed);
+                ioapic::set_masked(ioapic_address, input, true);
+            }
+
+            // Signal the end of the interrupt to the local APIC.
+            let local_apic_base = LOCAL_APIC_BASE.load(Ordering::Relaxed);
+            ((local_apic_base + 0xb0) as *mut u32).write_volatile(0);
+        }
+    }
+
+    vector.waker.wake();
+}
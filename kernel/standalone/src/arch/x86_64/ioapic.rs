@@ -0,0 +1,88 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! I/O APIC handling.
+//!
+//! The I/O APIC is the chip that receives interrupts from the hardware and redirects them to
+//! the local APICs of the processors. Each I/O APIC has a certain number of inputs, and each
+//! input is configured through a redirection entry.
+//!
+//! See also the Intel 82093AA I/O APIC datasheet.
+
+/// Configures the redirection entry of the given input of the I/O APIC whose registers are at
+/// `base`.
+///
+/// # Safety
+///
+/// `base` must be the physical address of the registers of an I/O APIC, and `input` must be
+/// inferior to [`max_redirection_entries`].
+///
+pub unsafe fn set_redirection(
+    base: usize,
+    input: u8,
+    vector: u8,
+    destination_apic_id: u8,
+    active_low: bool,
+    level_triggered: bool,
+    masked: bool,
+) {
+    let low = u32::from(vector)
+        | if active_low { 1 << 13 } else { 0 }
+        | if level_triggered { 1 << 15 } else { 0 }
+        | if masked { 1 << 16 } else { 0 };
+    let high = u32::from(destination_apic_id) << 24;
+
+    // Mask the entry while we modify it, in order to not trigger a half-configured interrupt.
+    write(base, 0x10 + 2 * input, low | (1 << 16));
+    write(base, 0x11 + 2 * input, high);
+    write(base, 0x10 + 2 * input, low);
+}
+
+/// Masks or unmasks the given input of the I/O APIC whose registers are at `base`.
+///
+/// # Safety
+///
+/// Same as [`set_redirection`].
+///
+pub unsafe fn set_masked(base: usize, input: u8, masked: bool) {
+    let value = read(base, 0x10 + 2 * input);
+    let new_value = if masked {
+        value | (1 << 16)
+    } else {
+        value & !(1 << 16)
+    };
+    write(base, 0x10 + 2 * input, new_value);
+}
+
+/// Returns the number of inputs of the I/O APIC whose registers are at `base`.
+///
+/// # Safety
+///
+/// `base` must be the physical address of the registers of an I/O APIC.
+///
+pub unsafe fn max_redirection_entries(base: usize) -> u8 {
+    let version = read(base, 0x1);
+    ((version >> 16) & 0xff) as u8 + 1
+}
+
+unsafe fn read(base: usize, register: u8) -> u32 {
+    (base as *mut u32).write_volatile(u32::from(register));
+    ((base + 0x10) as *const u32).read_volatile()
+}
+
+unsafe fn write(base: usize, register: u8, value: u32) {
+    (base as *mut u32).write_volatile(u32::from(register));
+    ((base + 0x10) as *mut u32).write_volatile(value);
+}
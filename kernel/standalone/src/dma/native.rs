@@ -0,0 +1,234 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `dma` interface.
+//!
+//! Buffers are allocated with the kernel's allocator. Since the kernel runs with the memory
+//! identity-mapped, allocations are physically contiguous and their virtual address is equal to
+//! their physical address.
+
+// TODO: there is no privilege system, and any process can allocate buffers
+// TODO: once an IOMMU is in use, buffers should be mapped for the devices that use them
+
+use crate::hardware::grants::HardwareGrants;
+use alloc::{
+    alloc::{alloc_zeroed, dealloc, Layout},
+    boxed::Box,
+    sync::Arc,
+    vec::Vec,
+};
+
+use core::{convert::TryFrom as _, sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use hashbrown::HashMap;
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_dma_interface::ffi::{Allocate, AllocateResponse, Allocation, DmaMessage, INTERFACE};
+use redshirt_hardware_interface::ffi::AccessRange;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// State machine for `dma` interface messages handling.
+pub struct DmaNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// List of buffers currently allocated.
+    buffers: Mutex<Buffers>,
+    /// Message responses waiting to be emitted.
+    pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when a message is pushed to `pending_messages`.
+    waker: AtomicWaker,
+    /// Hardware that each process is allowed to access. Processes are allowed to access their
+    /// buffers.
+    grants: Arc<HardwareGrants>,
+}
+
+struct Buffers {
+    /// List of buffers, by identifier.
+    list: HashMap<u64, Buffer>,
+    /// Identifier to attribute to the next buffer.
+    next_id: u64,
+}
+
+struct Buffer {
+    /// Process that has allocated the buffer.
+    owner: Pid,
+    /// Pointer returned by the allocator.
+    ptr: *mut u8,
+    /// Layout passed to the allocator.
+    layout: Layout,
+}
+
+// Buffers are only ever accessed by the devices and through the `hardware` interface.
+unsafe impl Send for Buffer {}
+
+impl DmaNativeProgram {
+    /// Initializes the new state machine for DMA messages handling.
+    pub fn new(grants: Arc<HardwareGrants>) -> Self {
+        DmaNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            buffers: Mutex::new(Buffers {
+                list: HashMap::new(),
+                next_id: 0,
+            }),
+            pending_messages: SegQueue::new(),
+            waker: AtomicWaker::new(),
+            grants,
+        }
+    }
+
+    /// Handles a [`DmaMessage::Allocate`] message.
+    fn allocate(&self, owner: Pid, request: Allocate) -> Result<Allocation, ErrorPayload> {
+        let layout = usize::try_from(request.size)
+            .ok()
+            .filter(|s| *s != 0)
+            .and_then(|size| {
+                let align = usize::try_from(request.alignment).ok()?;
+                Layout::from_size_align(size, align).ok()
+            })
+            .ok_or_else(|| {
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                    .with_message("invalid size or alignment")
+            })?;
+
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("out of memory"));
+        }
+
+        let physical_address = u64::try_from(ptr as usize).unwrap();
+        let last_byte = physical_address + request.size - 1;
+        if request
+            .max_device_address
+            .map_or(false, |max| last_byte > max)
+        {
+            // TODO: try to allocate from a memory range that satisfies the constraint instead
+            unsafe { dealloc(ptr, layout) };
+            return Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                .with_message("no memory available below the requested address"));
+        }
+
+        let mut buffers = self.buffers.lock();
+        let id = buffers.next_id;
+        buffers.next_id += 1;
+        buffers.list.insert(id, Buffer { owner, ptr, layout });
+        self.grants.add_range(
+            owner,
+            AccessRange::Memory {
+                start: physical_address,
+                len: request.size,
+            },
+        );
+
+        Ok(Allocation {
+            id,
+            physical_address,
+            device_address: physical_address,
+        })
+    }
+}
+
+impl NativeProgram for DmaNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                Poll::Ready(NativeProgramEvent::Answer { message_id, answer })
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        match DmaMessage::decode(message) {
+            Ok(DmaMessage::Allocate(request)) => {
+                // Without a message ID, nobody would ever know about the buffer.
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                let response = AllocateResponse {
+                    result: self.allocate(emitter_pid, request),
+                };
+                self.pending_messages
+                    .push((message_id, Ok(response.encode())));
+            }
+            Ok(DmaMessage::Free(id)) => {
+                let mut buffers = self.buffers.lock();
+                if buffers
+                    .list
+                    .get(&id)
+                    .map_or(false, |b| b.owner == emitter_pid)
+                {
+                    let buffer = buffers.list.remove(&id).unwrap();
+                    self.grants.remove_memory_range(
+                        emitter_pid,
+                        u64::try_from(buffer.ptr as usize).unwrap(),
+                    );
+                    unsafe { dealloc(buffer.ptr, buffer.layout) };
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    self.pending_messages.push((message_id, Err(())));
+                }
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut buffers = self.buffers.lock();
+        let ids = buffers
+            .list
+            .iter()
+            .filter(|(_, b)| b.owner == pid)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            let buffer = buffers.list.remove(&id).unwrap();
+            unsafe { dealloc(buffer.ptr, buffer.layout) };
+        }
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
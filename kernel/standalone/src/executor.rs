@@ -36,8 +36,11 @@ pub fn block_on<R>(future: impl Future<Output = R>) -> R {
     let waker = waker(local_wake.clone());
     let mut context = Context::from_waker(&waker);
 
+    let previous = CURRENT.lock().replace(local_wake.clone());
+
     loop {
         if let Poll::Ready(val) = Future::poll(future.as_mut(), &mut context) {
+            *CURRENT.lock() = previous;
             return val;
         }
 
@@ -45,6 +48,22 @@ pub fn block_on<R>(future: impl Future<Output = R>) -> R {
     }
 }
 
+/// Puts the CPU to sleep until the next interrupt, unless the `Future` being run by
+/// [`block_on`] has already been woken up.
+///
+/// Meant to be called while a `Future` is being polled, right before it returns `Pending`.
+/// Contrary to [`block_on`], a wake-up isn't consumed by this function, and the `Future` is
+/// therefore polled again afterwards.
+pub fn idle() {
+    let local_wake = CURRENT.lock().clone();
+    if let Some(local_wake) = local_wake {
+        crate::arch::idle(&local_wake.woken_up);
+    }
+}
+
+/// Wake-up state of the `Future` currently being run by [`block_on`], if any.
+static CURRENT: spin::Mutex<Option<Arc<LocalWake>>> = spin::Mutex::new(None);
+
 struct LocalWake {
     woken_up: atomic::AtomicBool,
 }
@@ -0,0 +1,212 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the GPIO controller of the Broadcom BCM2835 family of SoCs, found in Raspberry Pi
+//! boards.
+//!
+//! See the "BCM2835 ARM Peripherals" datasheet, chapter 6.
+
+use core::sync::atomic;
+
+/// Physical address of the controller on the BCM2836 and BCM2837 (Raspberry Pi 2 and 3).
+pub const BCM2836_BASE: usize = 0x3f20_0000;
+
+/// Number of pins of the controller.
+pub const NUM_PINS: u32 = 54;
+
+// Offsets of the registers relative to the base address. Registers whose name ends with a
+// number are followed by the ones for the next pins.
+const REG_GPFSEL0: usize = 0x00;
+const REG_GPSET0: usize = 0x1c;
+const REG_GPCLR0: usize = 0x28;
+const REG_GPLEV0: usize = 0x34;
+const REG_GPEDS0: usize = 0x40;
+const REG_GPREN0: usize = 0x4c;
+const REG_GPFEN0: usize = 0x58;
+const REG_GPPUD: usize = 0x94;
+const REG_GPPUDCLK0: usize = 0x98;
+
+/// Function select values.
+const FSEL_INPUT: u32 = 0b000;
+const FSEL_OUTPUT: u32 = 0b001;
+/// Function select values of the alternate functions 0 to 5.
+const FSEL_ALT: [u32; 6] = [0b100, 0b101, 0b110, 0b111, 0b011, 0b010];
+
+/// Pull resistor of a pin.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pull {
+    None,
+    Down,
+    Up,
+}
+
+/// GPIO controller accessed through memory-mapped registers.
+pub struct Bcm2835Gpio {
+    base: usize,
+}
+
+impl Bcm2835Gpio {
+    /// Initializes the driver for the controller at the given physical address.
+    ///
+    /// # Safety
+    ///
+    /// There must be a BCM2835-compatible GPIO controller at this address, and physical memory
+    /// must be identity-mapped.
+    pub unsafe fn new(base: usize) -> Self {
+        Bcm2835Gpio { base }
+    }
+
+    /// Configures a pin as an input or an output.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range.
+    ///
+    pub fn set_output(&mut self, pin: u32, output: bool) {
+        self.set_function(pin, if output { FSEL_OUTPUT } else { FSEL_INPUT });
+    }
+
+    /// Hands over a pin to another peripheral, such as the I²C or SPI controllers. The meaning
+    /// of each alternate function depends on the pin.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range or `alt` is superior to 5.
+    ///
+    pub fn set_alternate_function(&mut self, pin: u32, alt: u8) {
+        self.set_function(pin, FSEL_ALT[usize::from(alt)]);
+    }
+
+    fn set_function(&mut self, pin: u32, fsel: u32) {
+        assert!(pin < NUM_PINS);
+        let reg = REG_GPFSEL0 + 4 * (pin / 10) as usize;
+        let shift = 3 * (pin % 10);
+        unsafe {
+            let value = self.read_reg(reg) & !(0b111 << shift);
+            self.write_reg(reg, value | (fsel << shift));
+        }
+    }
+
+    /// Connects the internal pull resistor of a pin.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range.
+    ///
+    pub fn set_pull(&mut self, pin: u32, pull: Pull) {
+        assert!(pin < NUM_PINS);
+        let control = match pull {
+            Pull::None => 0,
+            Pull::Down => 1,
+            Pull::Up => 2,
+        };
+
+        // The control signal must be set up, then clocked into the pin, with a delay of 150
+        // cycles after each step.
+        unsafe {
+            self.write_reg(REG_GPPUD, control);
+            delay_cycles(150);
+            self.write_reg(bank_reg(REG_GPPUDCLK0, pin), bank_bit(pin));
+            delay_cycles(150);
+            self.write_reg(REG_GPPUD, 0);
+            self.write_reg(bank_reg(REG_GPPUDCLK0, pin), 0);
+        }
+    }
+
+    /// Sets the level of an output pin.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range.
+    ///
+    pub fn write(&mut self, pin: u32, high: bool) {
+        assert!(pin < NUM_PINS);
+        let reg = if high { REG_GPSET0 } else { REG_GPCLR0 };
+        unsafe { self.write_reg(bank_reg(reg, pin), bank_bit(pin)) }
+    }
+
+    /// Returns true if the pin is high.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range.
+    ///
+    pub fn read(&self, pin: u32) -> bool {
+        assert!(pin < NUM_PINS);
+        unsafe { self.read_reg(bank_reg(REG_GPLEV0, pin)) & bank_bit(pin) != 0 }
+    }
+
+    /// Enables or disables the detection of rising and falling edges on a pin.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `pin` is out of range.
+    ///
+    pub fn set_edge_detection(&mut self, pin: u32, rising: bool, falling: bool) {
+        assert!(pin < NUM_PINS);
+        for (reg, enable) in &[(REG_GPREN0, rising), (REG_GPFEN0, falling)] {
+            let reg = bank_reg(*reg, pin);
+            unsafe {
+                let value = self.read_reg(reg) & !bank_bit(pin);
+                let value = if *enable {
+                    value | bank_bit(pin)
+                } else {
+                    value
+                };
+                self.write_reg(reg, value);
+            }
+        }
+    }
+
+    /// Returns the list of pins on which an edge has been detected since the last call, and
+    /// clears the detection status.
+    pub fn take_detected_edges(&mut self) -> impl Iterator<Item = u32> {
+        let mut status = [0; 2];
+        unsafe {
+            for (bank, status) in status.iter_mut().enumerate() {
+                let reg = REG_GPEDS0 + 4 * bank;
+                *status = self.read_reg(reg);
+                // Bits are cleared by writing 1.
+                self.write_reg(reg, *status);
+            }
+        }
+
+        (0..NUM_PINS).filter(move |pin| status[(pin / 32) as usize] & (1 << (pin % 32)) != 0)
+    }
+
+    unsafe fn read_reg(&self, reg: usize) -> u32 {
+        ((self.base + reg) as *const u32).read_volatile()
+    }
+
+    unsafe fn write_reg(&mut self, reg: usize, value: u32) {
+        ((self.base + reg) as *mut u32).write_volatile(value)
+    }
+}
+
+/// Returns the register of the bank containing `pin`, for registers with one bit per pin.
+fn bank_reg(reg0: usize, pin: u32) -> usize {
+    reg0 + 4 * (pin / 32) as usize
+}
+
+/// Returns the bit corresponding to `pin` within its bank.
+fn bank_bit(pin: u32) -> u32 {
+    1 << (pin % 32)
+}
+
+fn delay_cycles(cycles: u32) {
+    for _ in 0..cycles {
+        atomic::spin_loop_hint();
+    }
+}
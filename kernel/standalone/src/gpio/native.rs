@@ -0,0 +1,307 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `gpio` interface.
+
+use crate::gpio::bcm2835::{self, Bcm2835Gpio};
+
+use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use core::{sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_gpio_interface::ffi::{
+    ConfigureResponse, Direction, Edge, EdgeEvent, GpioMessage, Pull, ReadResponse, WriteResponse,
+    INTERFACE,
+};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// Pins used by the I²C controller (2 and 3), the SPI controller (7 to 11) and the UART (14 and
+/// 15), which programs aren't allowed to reconfigure.
+const RESERVED_PINS: [u32; 9] = [2, 3, 7, 8, 9, 10, 11, 14, 15];
+
+/// State machine for `gpio` interface messages handling.
+pub struct GpioNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    gpio: Bcm2835Gpio,
+    /// Direction of each pin. Pins are inputs after a reset.
+    directions: Vec<Direction>,
+    /// Edge subscriptions.
+    subscriptions: Vec<Subscription>,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+struct Subscription {
+    message_id: MessageId,
+    emitter_pid: Pid,
+    pin: u32,
+    edge: Edge,
+}
+
+impl GpioNativeProgram {
+    /// Initializes the new state machine for GPIO messages handling.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on platforms that have a BCM2835-compatible GPIO controller at
+    /// [`bcm2835::BCM2836_BASE`].
+    pub unsafe fn new() -> Self {
+        GpioNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                gpio: Bcm2835Gpio::new(bcm2835::BCM2836_BASE),
+                directions: vec![Direction::Input; bcm2835::NUM_PINS as usize],
+                subscriptions: Vec::new(),
+                events: VecDeque::new(),
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for GpioNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+
+            let mut inner = self.inner.lock();
+            inner.poll_edges();
+            if let Some(event) = inner.events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            // TODO: use interrupts instead of continuously polling the hardware
+            if !inner.subscriptions.is_empty() {
+                cx.waker().wake_by_ref();
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let mut inner = self.inner.lock();
+
+        match GpioMessage::decode(message) {
+            Ok(GpioMessage::NumPins) => {
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(bcm2835::NUM_PINS.encode()),
+                    });
+                }
+            }
+            Ok(GpioMessage::Configure(config)) => {
+                let result = check_pin(config.pin).map(|()| {
+                    let output = config.direction == Direction::Output;
+                    inner.gpio.set_output(config.pin, output);
+                    inner.gpio.set_pull(
+                        config.pin,
+                        match config.pull {
+                            Pull::None => bcm2835::Pull::None,
+                            Pull::Down => bcm2835::Pull::Down,
+                            Pull::Up => bcm2835::Pull::Up,
+                        },
+                    );
+                    inner.directions[config.pin as usize] = config.direction;
+                });
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(ConfigureResponse { result }.encode()),
+                    });
+                }
+            }
+            Ok(GpioMessage::Write { pin, high }) => {
+                let result = check_pin(pin).and_then(|()| {
+                    if inner.directions[pin as usize] != Direction::Output {
+                        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("pin isn't configured as an output"));
+                    }
+                    inner.gpio.write(pin, high);
+                    Ok(())
+                });
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(WriteResponse { result }.encode()),
+                    });
+                }
+            }
+            Ok(GpioMessage::Read(pin)) => {
+                if let Some(message_id) = message_id {
+                    let result = if pin < bcm2835::NUM_PINS {
+                        Ok(inner.gpio.read(pin))
+                    } else {
+                        Err(ErrorPayload::new(ErrorClass::NOT_FOUND))
+                    };
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(ReadResponse { result }.encode()),
+                    });
+                }
+            }
+            Ok(GpioMessage::SubscribeEdges { pin, edge }) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+                if pin < bcm2835::NUM_PINS && inner.directions[pin as usize] == Direction::Input {
+                    inner.subscriptions.push(Subscription {
+                        message_id,
+                        emitter_pid,
+                        pin,
+                        edge,
+                    });
+                    inner.update_edge_detection(pin);
+                } else {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    });
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    });
+                }
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.remove_subscriptions(|s| s.emitter_pid == pid);
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        let mut inner = self.inner.lock();
+        inner.remove_subscriptions(|s| s.message_id == message_id);
+    }
+}
+
+impl Inner {
+    /// Checks for detected edges and pushes the corresponding events to `events`.
+    fn poll_edges(&mut self) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+
+        for pin in self.gpio.take_detected_edges() {
+            // The controller doesn't report the direction of the edge. We assume that the
+            // level hasn't changed again since.
+            let rising = self.gpio.read(pin);
+            for subscription in &self.subscriptions {
+                let matches = match subscription.edge {
+                    Edge::Rising => rising,
+                    Edge::Falling => !rising,
+                    Edge::Both => true,
+                };
+                if subscription.pin == pin && matches {
+                    self.events.push_back(NativeProgramEvent::PartialAnswer {
+                        message_id: subscription.message_id,
+                        answer: EdgeEvent { rising }.encode(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Removes the subscriptions matching `filter`, and disables the edge detection that is no
+    /// longer needed.
+    fn remove_subscriptions(&mut self, mut filter: impl FnMut(&Subscription) -> bool) {
+        let mut pins = Vec::new();
+        self.subscriptions.retain(|s| {
+            if filter(s) {
+                pins.push(s.pin);
+                false
+            } else {
+                true
+            }
+        });
+
+        for pin in pins {
+            self.update_edge_detection(pin);
+        }
+    }
+
+    /// Enables the detection of the edges that the subscriptions to `pin` are interested in.
+    fn update_edge_detection(&mut self, pin: u32) {
+        let (mut rising, mut falling) = (false, false);
+        for subscription in self.subscriptions.iter().filter(|s| s.pin == pin) {
+            match subscription.edge {
+                Edge::Rising => rising = true,
+                Edge::Falling => falling = true,
+                Edge::Both => {
+                    rising = true;
+                    falling = true;
+                }
+            }
+        }
+
+        self.gpio.set_edge_detection(pin, rising, falling);
+    }
+}
+
+/// Returns an error if `pin` doesn't exist or can't be reconfigured.
+fn check_pin(pin: u32) -> Result<(), ErrorPayload> {
+    if pin >= bcm2835::NUM_PINS {
+        Err(ErrorPayload::new(ErrorClass::NOT_FOUND))
+    } else if RESERVED_PINS.contains(&pin) {
+        Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+            .with_message("pin used by another controller"))
+    } else {
+        Ok(())
+    }
+}
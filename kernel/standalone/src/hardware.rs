@@ -20,18 +20,23 @@
 
 use crate::arch;
 
-use alloc::{boxed::Box, vec::Vec};
-use core::{convert::TryFrom as _, pin::Pin, sync::atomic};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{convert::TryFrom as _, sync::atomic};
 use crossbeam_queue::SegQueue;
 use futures::prelude::*;
 use hashbrown::HashMap;
-use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
 use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
 use redshirt_hardware_interface::ffi::{
-    HardwareAccessResponse, HardwareMessage, Operation, INTERFACE,
+    AccessRange, HardwareAccessResponse, HardwareMessage, Operation, INTERFACE,
 };
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
 use spin::Mutex;
 
+pub mod grants;
+
 /// State machine for `hardware` interface messages handling.
 pub struct HardwareHandler {
     /// If true, we have sent the interface registration message.
@@ -39,27 +44,28 @@ pub struct HardwareHandler {
     /// For each PID, a list of memory allocations.
     // TODO: optimize
     allocations: Mutex<HashMap<Pid, Vec<Vec<u8>>>>,
+    /// Hardware that each process is allowed to access.
+    grants: Arc<grants::HardwareGrants>,
     /// List of messages waiting to be emitted with `next_event`.
     pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
 }
 
 impl HardwareHandler {
     /// Initializes the new state machine for hardware accesses.
-    pub fn new() -> Self {
+    pub fn new(grants: Arc<grants::HardwareGrants>) -> Self {
         HardwareHandler {
             registered: atomic::AtomicBool::new(false),
             allocations: Mutex::new(HashMap::new()),
+            grants,
             pending_messages: SegQueue::new(),
         }
     }
 }
 
-impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
-    type Future =
-        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+impl NativeProgram for HardwareHandler {
     type MessageIdWrite = DummyMessageIdWrite;
 
-    fn next_event(self) -> Self::Future {
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
         if !self.registered.swap(true, atomic::Ordering::Relaxed) {
             return Box::pin(future::ready(NativeProgramEvent::Emit {
                 interface: redshirt_interface_interface::ffi::INTERFACE,
@@ -81,7 +87,7 @@ impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
     }
 
     fn interface_message(
-        self,
+        &self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
         emitter_pid: Pid,
@@ -91,6 +97,16 @@ impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
 
         match HardwareMessage::decode(message) {
             Ok(HardwareMessage::HardwareAccess(operations)) => {
+                if !operations
+                    .iter()
+                    .all(|op| self.grants.is_operation_allowed(emitter_pid, op))
+                {
+                    if let Some(message_id) = message_id {
+                        self.pending_messages.push((message_id, Err(())));
+                    }
+                    return;
+                }
+
                 let mut response = Vec::with_capacity(operations.len());
                 for operation in operations {
                     unsafe {
@@ -124,12 +140,20 @@ impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
 
                 let mut allocations = self.allocations.lock();
                 allocations.entry(emitter_pid).or_default().push(buffer);
+                self.grants.add_range(
+                    emitter_pid,
+                    AccessRange::Memory {
+                        start: ptr,
+                        len: u64::try_from(size).unwrap(),
+                    },
+                );
 
                 if let Some(message_id) = message_id {
                     self.pending_messages.push((message_id, Ok(ptr.encode())));
                 }
             }
             Ok(HardwareMessage::Free { ptr }) => {
+                self.grants.remove_memory_range(emitter_pid, ptr);
                 if let Ok(ptr) = usize::try_from(ptr) {
                     let mut allocations = self.allocations.lock();
                     if let Some(list) = allocations.get_mut(&emitter_pid) {
@@ -140,7 +164,24 @@ impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
                     }
                 }
             }
-            Ok(HardwareMessage::InterruptWait(_int_id)) => unimplemented!(), // TODO:
+            Ok(HardwareMessage::InterruptWait(_)) => {
+                // Interrupts are delivered through the `interrupts` interface instead.
+                if let Some(message_id) = message_id {
+                    self.pending_messages.push((message_id, Err(())))
+                }
+            }
+            Ok(HardwareMessage::Grant { pid, range }) => {
+                let result = if self.grants.grant(emitter_pid, pid, range) {
+                    Ok(())
+                } else {
+                    Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED))
+                };
+
+                if let Some(message_id) = message_id {
+                    self.pending_messages
+                        .push((message_id, Ok(result.encode())));
+                }
+            }
             Err(_) => {
                 if let Some(message_id) = message_id {
                     self.pending_messages.push((message_id, Err(())))
@@ -149,11 +190,12 @@ impl<'a> NativeProgramRef<'a> for &'a HardwareHandler {
         }
     }
 
-    fn process_destroyed(self, pid: Pid) {
+    fn process_destroyed(&self, pid: Pid) {
         self.allocations.lock().remove(&pid);
+        self.grants.process_destroyed(pid);
     }
 
-    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
         unreachable!()
     }
 }
@@ -0,0 +1,231 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tracking of the hardware that each process is allowed to access.
+//!
+//! Processes can't access any hardware by default. Processes marked with
+//! [`HardwareGrants::trust`], normally the ones started by the kernel that hand out hardware to
+//! drivers, can access all the hardware and grant access to other processes. Ranges are added to the other processes either
+//! explicitly through [`HardwareGrants::grant`], or implicitly when a process allocates memory
+//! meant to be accessed by devices.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom as _;
+use hashbrown::HashMap;
+use redshirt_core::Pid;
+use redshirt_hardware_interface::ffi::{AccessRange, Operation};
+use spin::Mutex;
+
+/// Shared between the native programs that give access to hardware.
+pub struct HardwareGrants {
+    processes: Mutex<HashMap<Pid, ProcessGrants>>,
+}
+
+#[derive(Default)]
+struct ProcessGrants {
+    /// If true, the process can access all the hardware and grant access to other processes.
+    trusted: bool,
+    /// Ranges the process is allowed to access.
+    ranges: Vec<AccessRange>,
+}
+
+impl HardwareGrants {
+    /// Initializes an empty list of grants.
+    pub fn new() -> Self {
+        HardwareGrants {
+            processes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Allows `pid` to access all the hardware and to grant access to other processes.
+    pub fn trust(&self, pid: Pid) {
+        let mut processes = self.processes.lock();
+        processes.entry(pid).or_default().trusted = true;
+    }
+
+    /// Grants `grantee` access to `range` on behalf of `granter`.
+    ///
+    /// Returns `false` and does nothing if `granter` isn't trusted.
+    pub fn grant(&self, granter: Pid, grantee: Pid, range: AccessRange) -> bool {
+        let mut processes = self.processes.lock();
+        if !processes.get(&granter).map_or(false, |g| g.trusted) {
+            return false;
+        }
+
+        processes.entry(grantee).or_default().ranges.push(range);
+        true
+    }
+
+    /// Allows `pid` to access `range`.
+    pub fn add_range(&self, pid: Pid, range: AccessRange) {
+        let mut processes = self.processes.lock();
+        processes.entry(pid).or_default().ranges.push(range);
+    }
+
+    /// Removes a memory range previously passed to [`HardwareGrants::add_range`] and that
+    /// starts at the given address.
+    pub fn remove_memory_range(&self, pid: Pid, start: u64) {
+        let mut processes = self.processes.lock();
+        if let Some(grants) = processes.get_mut(&pid) {
+            let pos = grants.ranges.iter().position(|r| match r {
+                AccessRange::Memory { start: s, .. } => *s == start,
+                _ => false,
+            });
+            if let Some(pos) = pos {
+                grants.ranges.remove(pos);
+            }
+        }
+    }
+
+    /// Returns true if `pid` is allowed to perform the given operation.
+    pub fn is_operation_allowed(&self, pid: Pid, operation: &Operation) -> bool {
+        self.is_range_allowed(pid, &operation_range(operation))
+    }
+
+    /// Returns true if `pid` is allowed to access the entirety of `range`.
+    pub fn is_range_allowed(&self, pid: Pid, range: &AccessRange) -> bool {
+        let processes = self.processes.lock();
+        is_allowed(processes.get(&pid), range)
+    }
+
+    /// Removes all the grants of the given process.
+    pub fn process_destroyed(&self, pid: Pid) {
+        self.processes.lock().remove(&pid);
+    }
+}
+
+/// Returns true if a process with the given grants can access the entirety of `range`.
+fn is_allowed(grants: Option<&ProcessGrants>, range: &AccessRange) -> bool {
+    let grants = match grants {
+        Some(g) if g.trusted => return true,
+        Some(g) => g,
+        None => return false,
+    };
+
+    grants.ranges.iter().any(|granted| match (granted, range) {
+        (
+            AccessRange::Memory { start, len },
+            AccessRange::Memory {
+                start: req_start,
+                len: req_len,
+            },
+        ) => contains(
+            u128::from(*start),
+            u128::from(*len),
+            u128::from(*req_start),
+            u128::from(*req_len),
+        ),
+        (
+            AccessRange::Ports { start, len },
+            AccessRange::Ports {
+                start: req_start,
+                len: req_len,
+            },
+        ) => contains(
+            u128::from(*start),
+            u128::from(*len),
+            u128::from(*req_start),
+            u128::from(*req_len),
+        ),
+        (AccessRange::Interrupts, AccessRange::Interrupts) => true,
+        _ => false,
+    })
+}
+
+/// Returns true if `[req_start, req_start + req_len)` is within `[start, start + len)`.
+fn contains(start: u128, len: u128, req_start: u128, req_len: u128) -> bool {
+    req_start >= start && req_start + req_len <= start + len
+}
+
+/// Returns the range of memory or ports accessed by an operation.
+fn operation_range(operation: &Operation) -> AccessRange {
+    fn memory(start: u64, len: u64, elem_size: u64) -> AccessRange {
+        AccessRange::Memory {
+            start,
+            len: len.saturating_mul(elem_size),
+        }
+    }
+    let vec_len = |len: usize| u64::try_from(len).unwrap();
+
+    match operation {
+        Operation::PhysicalMemoryWriteU8 { address, data } => {
+            memory(*address, vec_len(data.len()), 1)
+        }
+        Operation::PhysicalMemoryWriteU16 { address, data } => {
+            memory(*address, vec_len(data.len()), 2)
+        }
+        Operation::PhysicalMemoryWriteU32 { address, data } => {
+            memory(*address, vec_len(data.len()), 4)
+        }
+        Operation::PhysicalMemoryReadU8 { address, len } => memory(*address, u64::from(*len), 1),
+        Operation::PhysicalMemoryReadU16 { address, len } => memory(*address, u64::from(*len), 2),
+        Operation::PhysicalMemoryReadU32 { address, len } => memory(*address, u64::from(*len), 4),
+        Operation::PortWriteU8 { port, .. } | Operation::PortReadU8 { port } => {
+            AccessRange::Ports {
+                start: *port,
+                len: 1,
+            }
+        }
+        Operation::PortWriteU16 { port, .. } | Operation::PortReadU16 { port } => {
+            AccessRange::Ports {
+                start: *port,
+                len: 2,
+            }
+        }
+        Operation::PortWriteU32 { port, .. } | Operation::PortReadU32 { port } => {
+            AccessRange::Ports {
+                start: *port,
+                len: 4,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HardwareGrants;
+    use redshirt_core::Pid;
+    use redshirt_hardware_interface::ffi::AccessRange;
+
+    #[test]
+    fn untrusted_cannot_grant() {
+        let grants = HardwareGrants::new();
+        let trusted = Pid::from(1);
+        let untrusted = Pid::from(2);
+        let grantee = Pid::from(3);
+        let range = AccessRange::Ports {
+            start: 0x60,
+            len: 1,
+        };
+
+        grants.trust(trusted);
+        grants.add_range(untrusted, range.clone());
+        assert!(grants.is_range_allowed(untrusted, &range));
+
+        // Having access to a range isn't enough to grant it.
+        assert!(!grants.grant(untrusted, grantee, range.clone()));
+        assert!(!grants.is_range_allowed(grantee, &range));
+
+        assert!(grants.grant(trusted, grantee, range.clone()));
+        assert!(grants.is_range_allowed(grantee, &range));
+        assert!(!grants.is_range_allowed(
+            grantee,
+            &AccessRange::Ports {
+                start: 0x64,
+                len: 1
+            }
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the I²C controllers, called "Broadcom Serial Controllers", of the Broadcom BCM2835
+//! family of SoCs.
+//!
+//! See the "BCM2835 ARM Peripherals" datasheet, chapter 3.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Physical address of the BSC1 controller on the BCM2836 and BCM2837 (Raspberry Pi 2 and 3),
+/// which is connected to the pins of the expansion header.
+pub const BCM2836_BSC1_BASE: usize = 0x3f80_4000;
+
+/// Frequency of the clock the controller derives the bus clock from.
+const CORE_CLOCK_HZ: u32 = 250_000_000;
+/// Frequency of the bus clock. 100kHz is the "standard mode" that all devices support.
+const BUS_CLOCK_HZ: u32 = 100_000;
+
+/// Maximum duration of a transfer before we give up.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+// Offsets of the registers relative to the base address.
+const REG_C: usize = 0x00;
+const REG_S: usize = 0x04;
+const REG_DLEN: usize = 0x08;
+const REG_A: usize = 0x0c;
+const REG_FIFO: usize = 0x10;
+const REG_DIV: usize = 0x14;
+
+const C_I2CEN: u32 = 1 << 15;
+const C_ST: u32 = 1 << 7;
+const C_CLEAR: u32 = 1 << 4;
+const C_READ: u32 = 1 << 0;
+
+const S_CLKT: u32 = 1 << 9;
+const S_ERR: u32 = 1 << 8;
+const S_RXD: u32 = 1 << 5;
+const S_TXD: u32 = 1 << 4;
+const S_DONE: u32 = 1 << 1;
+
+/// Maximum number of bytes in a single transfer.
+pub const MAX_TRANSFER_LEN: usize = 0xffff;
+
+/// Error that can happen during a transfer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The device didn't acknowledge its address or the data.
+    Nack,
+    /// The device held the clock line low for too long.
+    ClockStretchTimeout,
+    /// The transfer didn't finish in time.
+    Timeout,
+}
+
+/// I²C controller accessed through memory-mapped registers.
+pub struct Bcm2835I2c {
+    base: usize,
+}
+
+impl Bcm2835I2c {
+    /// Initializes the controller at the given physical address.
+    ///
+    /// # Safety
+    ///
+    /// There must be a BCM2835-compatible I²C controller at this address, and physical memory
+    /// must be identity-mapped.
+    pub unsafe fn new(base: usize) -> Self {
+        let mut i2c = Bcm2835I2c { base };
+        i2c.write_reg(REG_DIV, CORE_CLOCK_HZ / BUS_CLOCK_HZ);
+        i2c
+    }
+
+    /// Sends data to the device at the given 7-bit address.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `data` is longer than [`MAX_TRANSFER_LEN`].
+    ///
+    pub fn write(&mut self, address: u8, data: &[u8]) -> Result<(), Error> {
+        assert!(data.len() <= MAX_TRANSFER_LEN);
+        let deadline = crate::time::monotonic_clock() + TIMEOUT;
+        let mut data = data.iter();
+
+        unsafe {
+            self.start(address, data.len(), false);
+            loop {
+                let mut status = self.read_reg(REG_S);
+                while status & S_TXD != 0 {
+                    match data.next() {
+                        Some(byte) => self.write_reg(REG_FIFO, u32::from(*byte)),
+                        None => break,
+                    }
+                    status = self.read_reg(REG_S);
+                }
+
+                if status & (S_ERR | S_CLKT | S_DONE) != 0 {
+                    return self.finish(status);
+                }
+                if crate::time::monotonic_clock() >= deadline {
+                    self.abort();
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+    }
+
+    /// Receives `len` bytes from the device at the given 7-bit address.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `len` is superior to [`MAX_TRANSFER_LEN`].
+    ///
+    pub fn read(&mut self, address: u8, len: usize) -> Result<Vec<u8>, Error> {
+        assert!(len <= MAX_TRANSFER_LEN);
+        let deadline = crate::time::monotonic_clock() + TIMEOUT;
+        let mut out = Vec::with_capacity(len);
+
+        unsafe {
+            self.start(address, len, true);
+            loop {
+                let mut status = self.read_reg(REG_S);
+                while status & S_RXD != 0 && out.len() < len {
+                    out.push(self.read_reg(REG_FIFO) as u8);
+                    status = self.read_reg(REG_S);
+                }
+
+                if status & (S_ERR | S_CLKT) != 0 {
+                    return self.finish(status).map(|()| out);
+                }
+                // The FIFO might still contain data after the end of the transfer.
+                if status & S_DONE != 0 && status & S_RXD == 0 {
+                    return self.finish(status).map(|()| out);
+                }
+                if crate::time::monotonic_clock() >= deadline {
+                    self.abort();
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+    }
+
+    unsafe fn start(&mut self, address: u8, len: usize, read: bool) {
+        self.write_reg(REG_C, C_I2CEN | C_CLEAR);
+        self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+        self.write_reg(REG_A, u32::from(address & 0x7f));
+        self.write_reg(REG_DLEN, len as u32);
+        let read = if read { C_READ } else { 0 };
+        self.write_reg(REG_C, C_I2CEN | C_ST | read);
+    }
+
+    /// Clears the status flags and turns them into a result.
+    unsafe fn finish(&mut self, status: u32) -> Result<(), Error> {
+        self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+        if status & S_ERR != 0 {
+            Err(Error::Nack)
+        } else if status & S_CLKT != 0 {
+            Err(Error::ClockStretchTimeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn abort(&mut self) {
+        self.write_reg(REG_C, C_CLEAR);
+        self.write_reg(REG_S, S_CLKT | S_ERR | S_DONE);
+    }
+
+    unsafe fn read_reg(&self, reg: usize) -> u32 {
+        ((self.base + reg) as *const u32).read_volatile()
+    }
+
+    unsafe fn write_reg(&mut self, reg: usize, value: u32) {
+        ((self.base + reg) as *mut u32).write_volatile(value)
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `i2c` interface.
+
+use crate::gpio::bcm2835::{Bcm2835Gpio, BCM2836_BASE};
+use crate::i2c::bcm2835::{self, Bcm2835I2c};
+
+use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use core::{convert::TryFrom as _, sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_i2c_interface::ffi::{
+    I2cMessage, ListBusesResponse, Operation, Transaction, TransactionResponse, INTERFACE,
+};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// Identifier of the only bus, named after the controller.
+const BUS: u32 = 1;
+
+/// State machine for `i2c` interface messages handling.
+pub struct I2cNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    controller: Mutex<Bcm2835I2c>,
+    /// Events waiting to be returned by `next_event`.
+    events: Mutex<VecDeque<NativeProgramEvent<DummyMessageIdWrite>>>,
+    /// Waken up when an event is pushed.
+    waker: AtomicWaker,
+}
+
+impl I2cNativeProgram {
+    /// Connects the I²C controller to the pins 2 (SDA) and 3 (SCL) and initializes the new
+    /// state machine for I²C messages handling.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on BCM2836 or BCM2837 SoCs.
+    pub unsafe fn new() -> Self {
+        let mut gpio = Bcm2835Gpio::new(BCM2836_BASE);
+        gpio.set_alternate_function(2, 0);
+        gpio.set_alternate_function(3, 0);
+
+        I2cNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            controller: Mutex::new(Bcm2835I2c::new(bcm2835::BCM2836_BSC1_BASE)),
+            events: Mutex::new(VecDeque::new()),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for I2cNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            match self.events.lock().pop_front() {
+                Some(event) => Poll::Ready(event),
+                None => Poll::Pending,
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let answer = match I2cMessage::decode(message) {
+            Ok(I2cMessage::ListBuses) => Ok(ListBusesResponse { buses: vec![BUS] }.encode()),
+            Ok(I2cMessage::Transaction(transaction)) => {
+                // TODO: processes can talk to any device; there should be some access control
+                let result = self.perform(transaction);
+                Ok(TransactionResponse { result }.encode())
+            }
+            Err(_) => Err(()),
+        };
+
+        if let Some(message_id) = message_id {
+            self.events
+                .lock()
+                .push_back(NativeProgramEvent::Answer { message_id, answer });
+            self.waker.wake();
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+impl I2cNativeProgram {
+    /// Performs the operations of a transaction.
+    // TODO: the controller sends a stop condition between operations instead of a repeated start
+    // TODO: busy-waits until the transaction is over
+    fn perform(&self, transaction: Transaction) -> Result<Vec<Vec<u8>>, ErrorPayload> {
+        if transaction.bus != BUS {
+            return Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+        }
+        if transaction.address > 0x7f {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("address must be 7 bits"));
+        }
+
+        let mut controller = self.controller.lock();
+        let mut out = Vec::new();
+
+        for operation in transaction.operations {
+            let result = match operation {
+                Operation::Write(data) => {
+                    if data.len() > bcm2835::MAX_TRANSFER_LEN {
+                        return Err(too_long());
+                    }
+                    controller.write(transaction.address, &data)
+                }
+                Operation::Read(len) => {
+                    let len = usize::try_from(len)
+                        .ok()
+                        .filter(|l| *l <= bcm2835::MAX_TRANSFER_LEN)
+                        .ok_or_else(too_long)?;
+                    controller
+                        .read(transaction.address, len)
+                        .map(|data| out.push(data))
+                }
+            };
+
+            result.map_err(|err| match err {
+                bcm2835::Error::Nack => {
+                    ErrorPayload::new(ErrorClass::IO).with_message("not acknowledged")
+                }
+                bcm2835::Error::ClockStretchTimeout | bcm2835::Error::Timeout => {
+                    ErrorPayload::new(ErrorClass::TIMED_OUT)
+                }
+            })?;
+        }
+
+        Ok(out)
+    }
+}
+
+fn too_long() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("operation too long")
+}
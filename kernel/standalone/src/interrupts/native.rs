@@ -0,0 +1,427 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `interrupts` interface.
+//!
+//! Interrupts coming from the I/O APICs and message-signaled interrupts are each attributed a
+//! vector of the processor. When an interrupt happens, the interrupt handler masks the
+//! corresponding input of the I/O APIC, if any, and increments a counter that this native
+//! program compares with the value it has last reported.
+//!
+//! Processes can only claim interrupts if they are allowed to access
+//! [`AccessRange::Interrupts`] according to the [`HardwareGrants`].
+
+use crate::arch::x86_64::{interrupts, ioapic};
+use crate::hardware::grants::HardwareGrants;
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::{convert::TryFrom as _, sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use hashbrown::HashMap;
+use redshirt_acpi_interface::ffi::{Madt, Polarity, TriggerMode};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_hardware_interface::ffi::AccessRange;
+use redshirt_interrupts_interface::ffi::{
+    Claim, ClaimResponse, InterruptSource, InterruptsMessage, MsiMessage, WaitResponse, INTERFACE,
+};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// State machine for `interrupts` interface messages handling.
+pub struct InterruptsNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Interrupt controllers of the machine, or `None` if the MADT isn't available.
+    madt: Option<Madt>,
+    /// Hardware that each process is allowed to access.
+    grants: Arc<HardwareGrants>,
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    /// List of active claims, by identifier.
+    claims: HashMap<u64, ClaimState>,
+    /// Identifier to attribute to the next claim.
+    next_id: u64,
+    /// Vectors of the processor that aren't attributed to any claim.
+    free_vectors: Vec<u8>,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+struct ClaimState {
+    /// Process that has created the claim.
+    owner: Pid,
+    /// Vector of the processor the interrupt is delivered to.
+    vector: u8,
+    /// Physical address of the registers of the I/O APIC and input of this I/O APIC the
+    /// interrupt is coming from. `None` for message-signaled interrupts.
+    ioapic_input: Option<(usize, u8)>,
+    /// Value of the interrupt counter when we last answered a wait.
+    reported_count: u64,
+    /// Wait message waiting for the interrupt to happen.
+    pending_wait: Option<MessageId>,
+}
+
+impl InterruptsNativeProgram {
+    /// Initializes the new state machine for interrupts messages handling.
+    ///
+    /// Interrupts coming from the I/O APICs can only be claimed if `madt` is `Some`.
+    pub fn new(madt: Option<Madt>, grants: Arc<HardwareGrants>) -> Self {
+        InterruptsNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            madt,
+            grants,
+            inner: Mutex::new(Inner {
+                claims: HashMap::new(),
+                next_id: 0,
+                free_vectors: (interrupts::FIRST_HARDWARE_VECTOR..=254).rev().collect(),
+                events: VecDeque::new(),
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Determines the I/O APIC input, polarity and trigger mode of an ISA interrupt request
+    /// line, applying the redirections indicated by the firmware.
+    fn resolve_isa_irq(&self, irq: u8) -> Result<(usize, u8, bool, bool), ErrorPayload> {
+        if irq >= 16 {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("ISA interrupts are between 0 and 15"));
+        }
+
+        let madt = self.madt.as_ref().ok_or_else(no_madt)?;
+        let (gsi, active_low, level_triggered) = match madt
+            .interrupt_source_overrides
+            .iter()
+            .find(|o| o.bus == 0 && o.source == irq)
+        {
+            Some(over) => (
+                over.global_system_interrupt,
+                over.polarity == Polarity::ActiveLow,
+                over.trigger_mode == TriggerMode::Level,
+            ),
+            None => (u32::from(irq), false, false),
+        };
+
+        let (address, input) = self.resolve_gsi(gsi)?;
+        Ok((address, input, active_low, level_triggered))
+    }
+
+    /// Determines the I/O APIC and the input of this I/O APIC of a global system interrupt.
+    fn resolve_gsi(&self, gsi: u32) -> Result<(usize, u8), ErrorPayload> {
+        let madt = self.madt.as_ref().ok_or_else(no_madt)?;
+        for io_apic in &madt.io_apics {
+            let address = match usize::try_from(io_apic.address) {
+                Ok(a) => a,
+                Err(_) => continue,
+            };
+            let num_inputs = unsafe { ioapic::max_redirection_entries(address) };
+            let input = match gsi.checked_sub(io_apic.global_system_interrupt_base) {
+                Some(i) if i < u32::from(num_inputs) => i,
+                _ => continue,
+            };
+            return Ok((address, u8::try_from(input).unwrap()));
+        }
+
+        Err(ErrorPayload::new(ErrorClass::NOT_FOUND)
+            .with_message("no I/O APIC handles this interrupt"))
+    }
+
+    /// Handles a [`InterruptsMessage::Claim`] message.
+    fn claim(&self, inner: &mut Inner, owner: Pid, source: InterruptSource) -> ClaimResponse {
+        if !self
+            .grants
+            .is_range_allowed(owner, &AccessRange::Interrupts)
+        {
+            return ClaimResponse {
+                result: Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)),
+            };
+        }
+
+        let (ioapic_input, active_low, level_triggered) = match source {
+            InterruptSource::IsaIrq(irq) => match self.resolve_isa_irq(irq) {
+                Ok((address, input, low, level)) => (Some((address, input)), low, level),
+                Err(err) => return ClaimResponse { result: Err(err) },
+            },
+            InterruptSource::Gsi {
+                gsi,
+                active_low,
+                level_triggered,
+            } => match self.resolve_gsi(gsi) {
+                Ok(input) => (Some(input), active_low, level_triggered),
+                Err(err) => return ClaimResponse { result: Err(err) },
+            },
+            InterruptSource::Msi => (None, false, false),
+        };
+
+        if let Some(ioapic_input) = ioapic_input {
+            if inner
+                .claims
+                .values()
+                .any(|c| c.ioapic_input == Some(ioapic_input))
+            {
+                return ClaimResponse {
+                    result: Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                        .with_message("interrupt already claimed")),
+                };
+            }
+        }
+
+        let vector = match inner.free_vectors.pop() {
+            Some(v) => v,
+            None => {
+                return ClaimResponse {
+                    result: Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                        .with_message("no interrupt vector available")),
+                }
+            }
+        };
+
+        let apic_id = interrupts::local_apic_id();
+        let msi = if let Some((address, input)) = ioapic_input {
+            interrupts::set_mask_on_interrupt(vector, Some((address, input)));
+            unsafe {
+                ioapic::set_redirection(
+                    address,
+                    input,
+                    vector,
+                    apic_id,
+                    active_low,
+                    level_triggered,
+                    false,
+                );
+            }
+            None
+        } else {
+            interrupts::set_mask_on_interrupt(vector, None);
+            Some(MsiMessage {
+                address: 0xfee0_0000 | (u64::from(apic_id) << 12),
+                data: u32::from(vector),
+            })
+        };
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.claims.insert(
+            id,
+            ClaimState {
+                owner,
+                vector,
+                ioapic_input,
+                reported_count: interrupts::interrupt_count(vector),
+                pending_wait: None,
+            },
+        );
+
+        ClaimResponse {
+            result: Ok(Claim { id, msi }),
+        }
+    }
+}
+
+impl NativeProgram for InterruptsNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+
+            let mut inner = self.inner.lock();
+            let inner = &mut *inner;
+
+            for claim in inner.claims.values_mut() {
+                let message_id = match claim.pending_wait {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                // The waker must be registered before reading the counter, otherwise an
+                // interrupt happening in between would be missed.
+                interrupts::set_interrupt_waker(claim.vector, cx.waker());
+                let count = interrupts::interrupt_count(claim.vector);
+                if count == claim.reported_count {
+                    continue;
+                }
+
+                let response = WaitResponse {
+                    result: Ok(count.wrapping_sub(claim.reported_count)),
+                };
+                claim.reported_count = count;
+                claim.pending_wait = None;
+                inner.events.push_back(NativeProgramEvent::Answer {
+                    message_id,
+                    answer: Ok(response.encode()),
+                });
+            }
+
+            if let Some(event) = inner.events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let mut inner = self.inner.lock();
+
+        match InterruptsMessage::decode(message) {
+            Ok(InterruptsMessage::Claim(source)) => {
+                let response = self.claim(&mut inner, emitter_pid, source);
+                match (message_id, response.result) {
+                    (Some(message_id), result) => {
+                        inner.events.push_back(NativeProgramEvent::Answer {
+                            message_id,
+                            answer: Ok(ClaimResponse { result }.encode()),
+                        })
+                    }
+                    // Nobody will ever know about this claim.
+                    (None, Ok(claim)) => inner.release(claim.id),
+                    (None, Err(_)) => {}
+                }
+            }
+            Ok(InterruptsMessage::Wait(id)) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                let error = match inner.claims.get_mut(&id) {
+                    Some(claim) if claim.owner == emitter_pid => {
+                        if claim.pending_wait.is_none() {
+                            claim.pending_wait = Some(message_id);
+                            None
+                        } else {
+                            Some(
+                                ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                                    .with_message("already waiting for this interrupt"),
+                            )
+                        }
+                    }
+                    _ => Some(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                };
+
+                if let Some(error) = error {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(WaitResponse { result: Err(error) }.encode()),
+                    });
+                }
+            }
+            Ok(InterruptsMessage::Ack(id)) => match inner.claims.get(&id) {
+                Some(claim) if claim.owner == emitter_pid => {
+                    if let Some((address, input)) = claim.ioapic_input {
+                        x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+                            ioapic::set_masked(address, input, false)
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Ok(InterruptsMessage::Release(id)) => {
+                if inner
+                    .claims
+                    .get(&id)
+                    .map_or(false, |c| c.owner == emitter_pid)
+                {
+                    inner.release(id);
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    });
+                }
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        let ids = inner
+            .claims
+            .iter()
+            .filter(|(_, c)| c.owner == pid)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            inner.release(id);
+        }
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        let mut inner = self.inner.lock();
+        for claim in inner.claims.values_mut() {
+            if claim.pending_wait == Some(message_id) {
+                claim.pending_wait = None;
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Masks the interrupt of the given claim and removes the claim.
+    fn release(&mut self, id: u64) {
+        let claim = match self.claims.remove(&id) {
+            Some(c) => c,
+            None => return,
+        };
+
+        if let Some((address, input)) = claim.ioapic_input {
+            x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+                ioapic::set_masked(address, input, true)
+            });
+        }
+
+        interrupts::set_mask_on_interrupt(claim.vector, None);
+        self.free_vectors.push(claim.vector);
+    }
+}
+
+fn no_madt() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::UNSUPPORTED)
+        .with_message("interrupt controllers information unavailable")
+}
@@ -22,12 +22,32 @@
 //! - Share the newly-created [`Kernel`] between CPUs, and call [`Kernel::run`] once for each CPU.
 //!
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use redshirt_hardware_interface::ffi::AccessRange;
+
+/// Time left to the programs to stop when the system is shut down.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Hardware that a program embedded in the kernel is allowed to access.
+enum StartupAccess {
+    /// Only the hardware granted by a trusted process.
+    OnlyGranted,
+    /// Access to all the hardware, and permission to grant access to other processes.
+    Trusted,
+    /// Access to the given ranges only.
+    Ranges(Vec<AccessRange>),
+}
 
 /// Main struct of this crate. Runs everything.
 pub struct Kernel {
     /// If true, the kernel has started running from a different thread already.
     running: AtomicBool,
+    /// Root of the ACPI tables, if any.
+    acpi_root_table: Option<crate::acpi::tables::RootTable>,
 }
 
 /// Configuration for creating a [`Kernel`].
@@ -36,13 +56,16 @@ pub struct Kernel {
 pub struct KernelConfig {
     /// Number of times the [`Kernel::run`] function might be called.
     pub num_cpus: u32,
+    /// Physical address of the root of the ACPI tables, if the platform provides them.
+    pub acpi_root_table: Option<crate::acpi::tables::RootTable>,
 }
 
 impl Kernel {
     /// Initializes a new `Kernel`.
-    pub fn init(_cfg: KernelConfig) -> Self {
+    pub fn init(cfg: KernelConfig) -> Self {
         Kernel {
             running: AtomicBool::new(false),
+            acpi_root_table: cfg.acpi_root_table,
         }
     }
 
@@ -61,17 +84,27 @@ impl Kernel {
         .unwrap();
 
         // TODO: use a better system than cfgs
-        #[cfg(target_arch = "x86_64")]
+        #[cfg(target_arch = "arm")]
         let stdout_module = redshirt_core::module::Module::from_bytes(
             &include_bytes!(
-                "../../../modules/target/wasm32-unknown-unknown/release/x86-stdout.wasm"
+                "../../../modules/target/wasm32-unknown-unknown/release/arm-stdout.wasm"
             )[..],
         )
         .unwrap();
+
+        // TODO: use a better system than cfgs
         #[cfg(target_arch = "arm")]
-        let stdout_module = redshirt_core::module::Module::from_bytes(
+        let sdhci_module = redshirt_core::module::Module::from_bytes(
+            &include_bytes!("../../../modules/target/wasm32-unknown-unknown/release/sdhci.wasm")
+                [..],
+        )
+        .unwrap();
+
+        // TODO: use a better system than cfgs
+        #[cfg(target_arch = "x86_64")]
+        let device_manager_module = redshirt_core::module::Module::from_bytes(
             &include_bytes!(
-                "../../../modules/target/wasm32-unknown-unknown/release/arm-stdout.wasm"
+                "../../../modules/target/wasm32-unknown-unknown/release/device-manager.wasm"
             )[..],
         )
         .unwrap();
@@ -84,6 +117,13 @@ impl Kernel {
         )
         .unwrap();
 
+        // TODO: use a better system than cfgs
+        #[cfg(target_arch = "x86_64")]
+        let ps2_module = redshirt_core::module::Module::from_bytes(
+            &include_bytes!("../../../modules/target/wasm32-unknown-unknown/release/ps2.wasm")[..],
+        )
+        .unwrap();
+
         // TODO: use a better system than cfgs
         #[cfg(target_arch = "x86_64")]
         let ne2000_module = redshirt_core::module::Module::from_bytes(
@@ -91,24 +131,147 @@ impl Kernel {
         )
         .unwrap();
 
+        // TODO: use a better system than cfgs
+        #[cfg(target_arch = "x86_64")]
+        let network_manager_module = redshirt_core::module::Module::from_bytes(
+            &include_bytes!(
+                "../../../modules/target/wasm32-unknown-unknown/release/network-manager.wasm"
+            )[..],
+        )
+        .unwrap();
+
+        // TODO: use a better system than cfgs
+        #[cfg(target_arch = "x86_64")]
+        let network_binder_module = redshirt_core::module::Module::from_bytes(
+            &include_bytes!(
+                "../../../modules/target/wasm32-unknown-unknown/release/network-binder.wasm"
+            )[..],
+        )
+        .unwrap();
+
+        // Shared between the native programs that give access to the hardware.
+        let hardware_grants = Arc::new(crate::hardware::grants::HardwareGrants::new());
+
         let mut system_builder = redshirt_core::system::SystemBuilder::new()
-            .with_native_program(crate::hardware::HardwareHandler::new())
+            .with_native_program(crate::hardware::HardwareHandler::new(
+                hardware_grants.clone(),
+            ))
+            .with_native_program(crate::dma::native::DmaNativeProgram::new(
+                hardware_grants.clone(),
+            ))
             .with_native_program(crate::random::native::RandomNativeProgram::new())
-            .with_startup_process(stdout_module)
+            .with_native_program(crate::time::native::TimeNativeProgram::new())
+            .with_native_program(crate::watchdog::native::WatchdogNativeProgram::new())
+            .with_monotonic_clock(|| crate::time::monotonic_clock().as_nanos() as u64)
+            .with_timer(|until| {
+                let now = crate::time::monotonic_clock();
+                let duration = Duration::from_nanos(until)
+                    .checked_sub(now)
+                    .unwrap_or(Duration::from_secs(0));
+                Box::pin(crate::time::wait(duration))
+            })
+            .with_idle_hook(crate::executor::idle)
             .with_startup_process(hello_module);
 
+        // Access to the hardware given to each startup process, in the order in which the
+        // processes are passed to the builder.
+        let mut startup_access = vec![StartupAccess::OnlyGranted];
+
+        let acpi_tables = self
+            .acpi_root_table
+            .map(|root| unsafe { crate::acpi::tables::parse(root) });
+        if let Some(acpi_tables) = &acpi_tables {
+            system_builder = system_builder.with_native_program(
+                crate::acpi::native::AcpiNativeProgram::new(acpi_tables.clone()),
+            );
+        }
+
         // TODO: use a better system than cfgs
         #[cfg(target_arch = "x86_64")]
         {
             system_builder = system_builder
+                .with_native_program(unsafe { crate::vga::native::VgaConsoleNativeProgram::new() })
+                .with_native_program(crate::serial::native::SerialNativeProgram::new())
+                .with_native_program(crate::sensors::native::SensorsNativeProgram::new())
+                .with_native_program(crate::interrupts::native::InterruptsNativeProgram::new(
+                    acpi_tables.as_ref().and_then(|t| t.madt.clone()),
+                    hardware_grants.clone(),
+                ))
+                .with_startup_process(device_manager_module)
                 .with_startup_process(pci_module)
+                .with_startup_process(ps2_module)
                 .with_startup_process(ne2000_module)
+                .with_startup_process(network_manager_module)
+                .with_startup_process(network_binder_module);
+
+            // The PCI driver hands out the hardware of each device to the driver of that device,
+            // including the network binder that drives the network cards.
+            // The PS/2 controller isn't on the PCI bus and is given its ports directly.
+            startup_access.push(StartupAccess::Trusted);
+            startup_access.push(StartupAccess::Trusted);
+            startup_access.push(StartupAccess::Ranges(vec![
+                AccessRange::Ports {
+                    start: 0x60,
+                    len: 1,
+                },
+                AccessRange::Ports {
+                    start: 0x64,
+                    len: 1,
+                },
+                AccessRange::Interrupts,
+            ]));
+            startup_access.push(StartupAccess::OnlyGranted);
+            startup_access.push(StartupAccess::OnlyGranted);
+            startup_access.push(StartupAccess::OnlyGranted);
+        }
+
+        // TODO: use a better system than cfgs
+        // TODO: we assume that ARM means Raspberry Pi 2
+        #[cfg(target_arch = "arm")]
+        {
+            system_builder = system_builder
+                .with_native_program(unsafe { crate::gpio::native::GpioNativeProgram::new() })
+                .with_native_program(unsafe { crate::i2c::native::I2cNativeProgram::new() })
+                .with_native_program(unsafe { crate::spi::native::SpiNativeProgram::new() })
+                .with_startup_process(stdout_module)
+                .with_startup_process(sdhci_module);
+
+            // Registers of the GPIO and UART controllers, then of the SD card controller.
+            startup_access.push(StartupAccess::Ranges(vec![AccessRange::Memory {
+                start: 0x3f20_0000,
+                len: 0x2000,
+            }]));
+            startup_access.push(StartupAccess::Ranges(vec![AccessRange::Memory {
+                start: 0x3f30_0000,
+                len: 0x100,
+            }]));
         }
 
         let mut system = system_builder
             .with_main_program([0; 32]) // TODO: just a test
             .build();
 
+        // Only the programs that hand out hardware to drivers are trusted. The others can only
+        // access the hardware they need.
+        assert_eq!(system.startup_processes().len(), startup_access.len());
+        for (pid, access) in system.startup_processes().iter().zip(startup_access) {
+            match access {
+                StartupAccess::OnlyGranted => {}
+                StartupAccess::Trusted => hardware_grants.trust(*pid),
+                StartupAccess::Ranges(ranges) => {
+                    for range in ranges {
+                        hardware_grants.add_range(*pid, range);
+                    }
+                }
+            }
+        }
+
+        if acpi_tables.is_some() {
+            system.log_boot_message("ACPI tables found");
+        } else {
+            system.log_boot_message("No ACPI tables found");
+        }
+
         loop {
             // TODO: ideally the entire function would be async, and this would be an `await`,
             // but async functions don't work on no_std yet
@@ -116,7 +279,23 @@ impl Kernel {
                 redshirt_core::system::SystemRunOutcome::ProgramFinished { pid, outcome } => {
                     //console.write(&format!("Program finished {:?} => {:?}\n", pid, outcome));
                 }
-                _ => panic!(),
+                redshirt_core::system::SystemRunOutcome::PowerRequested { action, .. } => {
+                    crate::executor::block_on(
+                        system.shutdown(crate::time::wait(SHUTDOWN_GRACE_PERIOD)),
+                    );
+
+                    match action {
+                        redshirt_core::system::PowerAction::Shutdown => {
+                            let fadt = acpi_tables.as_ref().and_then(|t| t.fadt.as_ref());
+                            if let Some(fadt) = fadt {
+                                unsafe { crate::acpi::power::enter_s5(fadt) };
+                            }
+                            // Entering S5 has failed or isn't supported.
+                            crate::arch::halt();
+                        }
+                        redshirt_core::system::PowerAction::Reboot => crate::arch::reboot(),
+                    }
+                }
             }
         }
     }
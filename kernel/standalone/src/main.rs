@@ -27,14 +27,26 @@
 extern crate alloc;
 extern crate compiler_builtins;
 
+mod acpi;
 mod arch;
+mod dma;
 mod executor;
+mod gpio;
 mod hardware;
+mod i2c;
+#[cfg(target_arch = "x86_64")]
+mod interrupts;
 mod kernel;
 mod mem_alloc;
 mod panic;
 mod random;
+#[cfg(target_arch = "x86_64")]
+mod sensors;
+mod serial;
+mod spi;
 mod time;
+mod vga;
+mod watchdog;
 
 // This contains nothing. As the main entry point of the kernel is platform-specific, it is
 // located in the `arch` module rather than here.
@@ -17,12 +17,14 @@
 
 use crate::random::rng::KernelRng;
 
-use alloc::{boxed::Box, vec};
-use core::{pin::Pin, sync::atomic};
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::sync::atomic;
 use crossbeam_queue::SegQueue;
 use futures::prelude::*;
 use rand_core::RngCore as _;
-use redshirt_core::native::{DummyMessageIdWrite, NativeProgramEvent, NativeProgramRef};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
 use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
 use redshirt_random_interface::ffi::{GenerateResponse, RandomMessage, INTERFACE};
 
@@ -47,12 +49,10 @@ impl RandomNativeProgram {
     }
 }
 
-impl<'a> NativeProgramRef<'a> for &'a RandomNativeProgram {
-    type Future =
-        Pin<Box<dyn Future<Output = NativeProgramEvent<Self::MessageIdWrite>> + Send + 'a>>;
+impl NativeProgram for RandomNativeProgram {
     type MessageIdWrite = DummyMessageIdWrite;
 
-    fn next_event(self) -> Self::Future {
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
         if !self.registered.swap(true, atomic::Ordering::Relaxed) {
             return Box::pin(future::ready(NativeProgramEvent::Emit {
                 interface: redshirt_interface_interface::ffi::INTERFACE,
@@ -73,7 +73,7 @@ impl<'a> NativeProgramRef<'a> for &'a RandomNativeProgram {
     }
 
     fn interface_message(
-        self,
+        &self,
         interface: InterfaceHash,
         message_id: Option<MessageId>,
         _emitter_pid: Pid,
@@ -81,12 +81,30 @@ impl<'a> NativeProgramRef<'a> for &'a RandomNativeProgram {
     ) {
         debug_assert_eq!(interface, INTERFACE);
 
+        let message = RandomMessage::decode(message);
+
+        if let Ok(RandomMessage::AddEntropy(data)) = &message {
+            // Mix the entropy into all the generators, creating one if there's none.
+            let mut rngs = Vec::new();
+            while let Ok(rng) = self.rngs.pop() {
+                rngs.push(rng);
+            }
+            if rngs.is_empty() {
+                rngs.push(KernelRng::new());
+            }
+            for mut rng in rngs {
+                rng.add_entropy(data);
+                self.rngs.push(rng);
+            }
+            return;
+        }
+
         let message_id = match message_id {
             Some(m) => m,
             None => return,
         };
 
-        match RandomMessage::decode(message) {
+        match message {
             Ok(RandomMessage::Generate { len }) => {
                 let mut out = vec![0; usize::from(len)];
 
@@ -102,13 +120,14 @@ impl<'a> NativeProgramRef<'a> for &'a RandomNativeProgram {
                 self.pending_messages
                     .push((message_id, Ok(response.encode())));
             }
+            Ok(RandomMessage::AddEntropy(_)) => unreachable!(),
             Err(_) => self.pending_messages.push((message_id, Err(()))),
         }
     }
 
-    fn process_destroyed(self, _: Pid) {}
+    fn process_destroyed(&self, _: Pid) {}
 
-    fn message_response(self, _: MessageId, _: Result<EncodedMessage, ()>) {
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
         unreachable!()
     }
 }
@@ -34,7 +34,9 @@
 //! # Implementation in redshirt
 //!
 //! The current implementation relies on ChaCha20 seeded by a JitterRng and RdRand if it is
-//! available.
+//! available. The generator is then reseeded with the data passed through
+//! [`KernelRng::add_entropy`], which typically comes from a hardware random number generator
+//! such as a virtio-rng device.
 //!
 
 // TODO: I'm not a cryptographer nor a mathematician, but I guess that a ChaCha alone is a bit naive?
@@ -93,6 +95,22 @@ impl KernelRng {
             rng: From::from(ChaCha20Core::from_seed(chacha_seed)),
         }
     }
+
+    /// Mixes `data`, coming from an external source of entropy, into the state of the generator.
+    ///
+    /// The new state depends on both the previous state and `data`, so that untrusted data
+    /// can't reduce the quality of the output.
+    pub fn add_entropy(&mut self, data: &[u8]) {
+        let mut sha2 = Sha512Trunc256::default();
+        let mut current = [0; 32];
+        self.rng.fill_bytes(&mut current);
+        sha2.input(&current[..]);
+        sha2.input(data);
+
+        let mut chacha_seed = [0; 32];
+        chacha_seed.copy_from_slice(&sha2.fixed_result());
+        self.rng = From::from(ChaCha20Core::from_seed(chacha_seed));
+    }
 }
 
 impl RngCore for KernelRng {
@@ -0,0 +1,132 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Digital thermal sensors of Intel processors.
+//!
+//! See the "Thermal Monitoring and Protection" chapter of the Intel 64 and IA-32 Architectures
+//! Software Developer's Manual, volume 3.
+
+use core::arch::x86_64::__cpuid;
+use x86_64::registers::model_specific::Msr;
+
+const IA32_THERM_STATUS: Msr = Msr::new(0x19c);
+const IA32_TEMPERATURE_TARGET: Msr = Msr::new(0x1a2);
+const IA32_PACKAGE_THERM_STATUS: Msr = Msr::new(0x1b1);
+
+/// Bit of the thermal status registers that indicates that the digital readout is valid.
+const READING_VALID: u64 = 1 << 31;
+
+/// Temperature at which the processor starts throttling, used if the processor doesn't report it.
+const DEFAULT_TJ_MAX: u8 = 100;
+
+/// Thermal sensors of the processor we are running on.
+#[derive(Debug)]
+pub struct CoreTemp {
+    /// Temperature, in degrees Celsius, at which the processor starts throttling. The sensors
+    /// report the difference between the current temperature and this value.
+    tj_max: u8,
+    /// True if the processor has a per-core digital thermal sensor.
+    core_sensor: bool,
+    /// True if the processor has a package thermal sensor.
+    package_sensor: bool,
+}
+
+/// Temperatures read from the sensors, in degrees Celsius.
+#[derive(Debug)]
+pub struct Temperatures {
+    /// Temperature at which the processor starts throttling.
+    pub tj_max: u8,
+    /// Temperature of the core we are running on.
+    pub core: Option<i32>,
+    /// Temperature of the whole package.
+    pub package: Option<i32>,
+}
+
+impl CoreTemp {
+    /// Detects the thermal sensors of the processor. Returns `None` if the processor doesn't
+    /// have any.
+    pub fn detect() -> Option<Self> {
+        let (vendor, features, thermal) = unsafe {
+            let vendor = __cpuid(0);
+            if vendor.eax < 6 {
+                return None;
+            }
+            (vendor, __cpuid(1), __cpuid(6))
+        };
+
+        // The vendor string is spread over EBX, EDX and ECX.
+        if (vendor.ebx, vendor.edx, vendor.ecx) != (0x756e_6547, 0x4965_6e69, 0x6c65_746e) {
+            return None;
+        }
+
+        let core_sensor = thermal.eax & (1 << 0) != 0;
+        let package_sensor = thermal.eax & (1 << 6) != 0;
+        if !core_sensor && !package_sensor {
+            return None;
+        }
+
+        // The `IA32_TEMPERATURE_TARGET` register exists starting from the Nehalem
+        // micro-architecture. Reading it on older processors triggers a general protection fault.
+        let family = (features.eax >> 8) & 0xf;
+        let model = ((features.eax >> 4) & 0xf) | ((features.eax >> 12) & 0xf0);
+        let tj_max = if family == 6 && model >= 0x1a {
+            match unsafe { (IA32_TEMPERATURE_TARGET.read() >> 16) & 0xff } {
+                0 => DEFAULT_TJ_MAX,
+                v => v as u8,
+            }
+        } else {
+            // TODO: older processors use various values; see the Linux coretemp driver
+            DEFAULT_TJ_MAX
+        };
+
+        Some(CoreTemp {
+            tj_max,
+            core_sensor,
+            package_sensor,
+        })
+    }
+
+    /// Reads the current temperatures.
+    // TODO: only reads the core we are running on
+    pub fn read(&self) -> Temperatures {
+        let core = if self.core_sensor {
+            self.decode(unsafe { IA32_THERM_STATUS.read() })
+        } else {
+            None
+        };
+
+        let package = if self.package_sensor {
+            self.decode(unsafe { IA32_PACKAGE_THERM_STATUS.read() })
+        } else {
+            None
+        };
+
+        Temperatures {
+            tj_max: self.tj_max,
+            core,
+            package,
+        }
+    }
+
+    /// Turns the content of a thermal status register into a temperature.
+    fn decode(&self, status: u64) -> Option<i32> {
+        if status & READING_VALID == 0 {
+            return None;
+        }
+
+        let below_tj_max = ((status >> 16) & 0x7f) as i32;
+        Some(i32::from(self.tj_max) - below_tj_max)
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `sensors` interface.
+
+use crate::sensors::coretemp::CoreTemp;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{sync::atomic, task::Poll};
+use crossbeam_queue::SegQueue;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_sensors_interface::ffi::{Reading, Sensor, SensorsMessage, INTERFACE};
+
+/// State machine for `sensors` interface messages handling.
+pub struct SensorsNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    /// Thermal sensors of the processor, if any.
+    coretemp: Option<CoreTemp>,
+    /// Message responses waiting to be emitted.
+    pending_messages: SegQueue<(MessageId, Result<EncodedMessage, ()>)>,
+    /// Waken up when a message is pushed to `pending_messages`.
+    waker: AtomicWaker,
+}
+
+impl SensorsNativeProgram {
+    /// Initializes the new state machine and detects the sensors of the machine.
+    pub fn new() -> Self {
+        // TODO: also report the ACPI thermal zones; evaluating their `_TMP` method requires an
+        // AML interpreter
+        SensorsNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            coretemp: CoreTemp::detect(),
+            pending_messages: SegQueue::new(),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Reads the current value of all the sensors.
+    fn read(&self) -> Vec<Sensor> {
+        let mut sensors = Vec::new();
+
+        if let Some(coretemp) = &self.coretemp {
+            let temperatures = coretemp.read();
+            let critical_millicelsius = Some(i32::from(temperatures.tj_max) * 1000);
+
+            if let Some(package) = temperatures.package {
+                sensors.push(Sensor {
+                    name: String::from("CPU package"),
+                    reading: Reading::Temperature {
+                        millicelsius: package * 1000,
+                        critical_millicelsius,
+                    },
+                });
+            }
+
+            if let Some(core) = temperatures.core {
+                sensors.push(Sensor {
+                    name: String::from("CPU core"),
+                    reading: Reading::Temperature {
+                        millicelsius: core * 1000,
+                        critical_millicelsius,
+                    },
+                });
+            }
+        }
+
+        sensors
+    }
+}
+
+impl NativeProgram for SensorsNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            if let Ok((message_id, answer)) = self.pending_messages.pop() {
+                Poll::Ready(NativeProgramEvent::Answer { message_id, answer })
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let message_id = match message_id {
+            Some(m) => m,
+            None => return,
+        };
+
+        let answer = match SensorsMessage::decode(message) {
+            Ok(SensorsMessage::Read) => Ok(self.read().encode()),
+            Err(_) => Err(()),
+        };
+
+        self.pending_messages.push((message_id, answer));
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `serial` interface.
+
+use crate::serial::uart::Uart;
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::{convert::TryFrom as _, sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_serial_interface::ffi::{
+    ConfigureResponse, ListPortsResponse, ModemStatus, ReadResponse, SerialMessage, WriteResponse,
+    INTERFACE,
+};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// Standard I/O ports of the COM1 to COM4 serial ports of PC platforms.
+const BASE_PORTS: [u32; 4] = [0x3f8, 0x2f8, 0x3e8, 0x2e8];
+
+/// State machine for `serial` interface messages handling.
+pub struct SerialNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    /// List of UARTs. The identifier of a port is its index in this list.
+    uarts: Vec<Uart>,
+    /// Read messages waiting for data, in the order in which they have been received.
+    reads: Vec<PendingRead>,
+    /// Write messages whose data hasn't been entirely sent yet, in the order in which they have
+    /// been received.
+    writes: VecDeque<PendingWrite>,
+    /// Modem status subscriptions.
+    subscriptions: Vec<Subscription>,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+struct PendingRead {
+    message_id: MessageId,
+    emitter_pid: Pid,
+    port: usize,
+    max_len: usize,
+}
+
+struct PendingWrite {
+    message_id: Option<MessageId>,
+    emitter_pid: Pid,
+    port: usize,
+    data: Vec<u8>,
+    /// Number of bytes of `data` already sent.
+    offset: usize,
+}
+
+struct Subscription {
+    message_id: MessageId,
+    emitter_pid: Pid,
+    port: usize,
+    /// Modem status reported in the last partial answer. `None` if nothing has been reported
+    /// yet.
+    last_status: Option<u8>,
+}
+
+impl SerialNativeProgram {
+    /// Detects the UARTs and initializes the new state machine for serial messages handling.
+    pub fn new() -> Self {
+        let uarts = BASE_PORTS
+            .iter()
+            .filter_map(|port| unsafe { Uart::probe(*port) })
+            .collect();
+
+        SerialNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                uarts,
+                reads: Vec::new(),
+                writes: VecDeque::new(),
+                subscriptions: Vec::new(),
+                events: VecDeque::new(),
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for SerialNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+
+            let mut inner = self.inner.lock();
+            unsafe { inner.poll_hardware() };
+            if let Some(event) = inner.events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            // TODO: use interrupts instead of continuously polling the hardware
+            if !inner.reads.is_empty()
+                || !inner.writes.is_empty()
+                || !inner.subscriptions.is_empty()
+            {
+                cx.waker().wake_by_ref();
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let mut inner = self.inner.lock();
+        let num_ports = inner.uarts.len();
+        let port_index = |port: u32| {
+            usize::try_from(port)
+                .ok()
+                .filter(|p| *p < num_ports)
+                .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))
+        };
+
+        match SerialMessage::decode(message) {
+            Ok(SerialMessage::ListPorts) => {
+                if let Some(message_id) = message_id {
+                    let ports = (0..u32::try_from(num_ports).unwrap()).collect();
+                    let response = ListPortsResponse { ports };
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(response.encode()),
+                    });
+                }
+            }
+            Ok(SerialMessage::Configure(config)) => {
+                let result = port_index(config.port).and_then(|port| unsafe {
+                    inner.uarts[port]
+                        .configure(
+                            config.baud_rate,
+                            config.data_bits,
+                            config.parity,
+                            config.stop_bits,
+                        )
+                        .map_err(|()| {
+                            ErrorPayload::new(ErrorClass::UNSUPPORTED)
+                                .with_message("unsupported line parameters")
+                        })
+                });
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(ConfigureResponse { result }.encode()),
+                    });
+                }
+            }
+            Ok(SerialMessage::Write(write)) => match port_index(write.port) {
+                Ok(port) => inner.writes.push_back(PendingWrite {
+                    message_id,
+                    emitter_pid,
+                    port,
+                    data: write.data,
+                    offset: 0,
+                }),
+                Err(err) => {
+                    if let Some(message_id) = message_id {
+                        inner.events.push_back(NativeProgramEvent::Answer {
+                            message_id,
+                            answer: Ok(WriteResponse { result: Err(err) }.encode()),
+                        });
+                    }
+                }
+            },
+            Ok(SerialMessage::Read(read)) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+                let port = port_index(read.port).and_then(|port| {
+                    if read.max_len == 0 {
+                        Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("max_len must be non-zero"))
+                    } else {
+                        Ok(port)
+                    }
+                });
+                match port {
+                    Ok(port) => inner.reads.push(PendingRead {
+                        message_id,
+                        emitter_pid,
+                        port,
+                        max_len: usize::try_from(read.max_len).unwrap_or(usize::max_value()),
+                    }),
+                    Err(err) => inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(ReadResponse { result: Err(err) }.encode()),
+                    }),
+                }
+            }
+            Ok(SerialMessage::SubscribeModemStatus(port)) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+                match port_index(port) {
+                    Ok(port) => inner.subscriptions.push(Subscription {
+                        message_id,
+                        emitter_pid,
+                        port,
+                        last_status: None,
+                    }),
+                    Err(_) => inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    }),
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    });
+                }
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.reads.retain(|r| r.emitter_pid != pid);
+        inner.writes.retain(|w| w.emitter_pid != pid);
+        inner.subscriptions.retain(|s| s.emitter_pid != pid);
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        let mut inner = self.inner.lock();
+        inner.reads.retain(|r| r.message_id != message_id);
+        inner.writes.retain(|w| w.message_id != Some(message_id));
+        inner.subscriptions.retain(|s| s.message_id != message_id);
+    }
+}
+
+impl Inner {
+    /// Sends and receives data, checks the modem status lines, and pushes the corresponding
+    /// events to `events`.
+    unsafe fn poll_hardware(&mut self) {
+        // Writes to the same port are processed in order. A port is "busy" if one of its writes
+        // couldn't finish.
+        let mut busy_ports = Vec::new();
+        let mut n = 0;
+        while n < self.writes.len() {
+            let write = &mut self.writes[n];
+            if busy_ports.contains(&write.port) {
+                n += 1;
+                continue;
+            }
+
+            let uart = &self.uarts[write.port];
+            while write.offset < write.data.len() && uart.try_write(write.data[write.offset]) {
+                write.offset += 1;
+            }
+
+            if write.offset == write.data.len() {
+                let write = self.writes.remove(n).unwrap();
+                if let Some(message_id) = write.message_id {
+                    self.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(WriteResponse { result: Ok(()) }.encode()),
+                    });
+                }
+            } else {
+                busy_ports.push(write.port);
+                n += 1;
+            }
+        }
+
+        // Received data is delivered to the oldest read of each port.
+        // TODO: data received while no read is pending stays in the hardware FIFO, and is lost
+        //       if the FIFO overflows
+        for (port, uart) in self.uarts.iter().enumerate() {
+            let read_index = match self.reads.iter().position(|r| r.port == port) {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let mut data = Vec::new();
+            while data.len() < self.reads[read_index].max_len {
+                match uart.try_read() {
+                    Some(byte) => data.push(byte),
+                    None => break,
+                }
+            }
+
+            if !data.is_empty() {
+                let read = self.reads.remove(read_index);
+                self.events.push_back(NativeProgramEvent::Answer {
+                    message_id: read.message_id,
+                    answer: Ok(ReadResponse { result: Ok(data) }.encode()),
+                });
+            }
+        }
+
+        for subscription in &mut self.subscriptions {
+            let status = self.uarts[subscription.port].modem_status();
+            if subscription.last_status == Some(status) {
+                continue;
+            }
+
+            subscription.last_status = Some(status);
+            let status = ModemStatus {
+                cts: status & (1 << 4) != 0,
+                dsr: status & (1 << 5) != 0,
+                ring: status & (1 << 6) != 0,
+                dcd: status & (1 << 7) != 0,
+            };
+            self.events.push_back(NativeProgramEvent::PartialAnswer {
+                message_id: subscription.message_id,
+                answer: status.encode(),
+            });
+        }
+    }
+}
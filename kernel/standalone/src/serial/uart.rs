@@ -0,0 +1,135 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for 16550-compatible UARTs.
+//!
+//! See https://wiki.osdev.org/Serial_Ports
+
+use crate::arch;
+
+use core::convert::TryFrom as _;
+use redshirt_serial_interface::ffi::{Parity, StopBits};
+
+/// Frequency of the clock of the UART divided by 16. The baud rate is this value divided by
+/// the divisor latch.
+const BASE_BAUD_RATE: u32 = 115200;
+
+// Offsets of the registers relative to the base I/O port.
+const REG_DATA: u32 = 0;
+const REG_INTERRUPT_ENABLE: u32 = 1;
+const REG_FIFO_CONTROL: u32 = 2;
+const REG_LINE_CONTROL: u32 = 3;
+const REG_MODEM_CONTROL: u32 = 4;
+const REG_LINE_STATUS: u32 = 5;
+const REG_MODEM_STATUS: u32 = 6;
+const REG_SCRATCH: u32 = 7;
+// Accessible at `REG_DATA` and `REG_INTERRUPT_ENABLE` when `LINE_CONTROL_DLAB` is set.
+const REG_DIVISOR_LOW: u32 = 0;
+const REG_DIVISOR_HIGH: u32 = 1;
+
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_THR_EMPTY: u8 = 1 << 5;
+
+/// UART accessed through I/O ports.
+pub struct Uart {
+    base_port: u32,
+}
+
+impl Uart {
+    /// Checks whether there is a UART at the given base I/O port and, if so, configures it to
+    /// 115200 bauds, 8 data bits, no parity and one stop bit.
+    pub unsafe fn probe(base_port: u32) -> Option<Uart> {
+        // If nothing is connected, writes to the scratch register are lost.
+        arch::write_port_u8(base_port + REG_SCRATCH, 0xae);
+        if arch::read_port_u8(base_port + REG_SCRATCH) != 0xae {
+            return None;
+        }
+
+        let uart = Uart { base_port };
+        // Interrupts aren't supported; the UART is polled instead.
+        arch::write_port_u8(base_port + REG_INTERRUPT_ENABLE, 0);
+        uart.configure(BASE_BAUD_RATE, 8, Parity::None, StopBits::One)
+            .unwrap();
+        // Enable and clear the FIFOs, with a 14 bytes threshold.
+        arch::write_port_u8(base_port + REG_FIFO_CONTROL, 0xc7);
+        // Set the DTR, RTS and OUT2 lines.
+        arch::write_port_u8(base_port + REG_MODEM_CONTROL, 0x0b);
+        Some(uart)
+    }
+
+    /// Sets the line parameters. Returns an error if they aren't supported by the hardware.
+    pub unsafe fn configure(
+        &self,
+        baud_rate: u32,
+        data_bits: u8,
+        parity: Parity,
+        stop_bits: StopBits,
+    ) -> Result<(), ()> {
+        if baud_rate == 0 || BASE_BAUD_RATE % baud_rate != 0 {
+            return Err(());
+        }
+        let divisor = u16::try_from(BASE_BAUD_RATE / baud_rate).map_err(|_| ())?;
+
+        if data_bits < 5 || data_bits > 8 {
+            return Err(());
+        }
+
+        let parity = match parity {
+            Parity::None => 0b000,
+            Parity::Odd => 0b001,
+            Parity::Even => 0b011,
+            Parity::Mark => 0b101,
+            Parity::Space => 0b111,
+        };
+        let stop_bits = match stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => 1,
+        };
+        let line_control = (data_bits - 5) | (stop_bits << 2) | (parity << 3);
+
+        let [divisor_low, divisor_high] = divisor.to_le_bytes();
+        arch::write_port_u8(self.base_port + REG_LINE_CONTROL, LINE_CONTROL_DLAB);
+        arch::write_port_u8(self.base_port + REG_DIVISOR_LOW, divisor_low);
+        arch::write_port_u8(self.base_port + REG_DIVISOR_HIGH, divisor_high);
+        arch::write_port_u8(self.base_port + REG_LINE_CONTROL, line_control);
+        Ok(())
+    }
+
+    /// Returns the next received byte, if any.
+    pub unsafe fn try_read(&self) -> Option<u8> {
+        if arch::read_port_u8(self.base_port + REG_LINE_STATUS) & LINE_STATUS_DATA_READY != 0 {
+            Some(arch::read_port_u8(self.base_port + REG_DATA))
+        } else {
+            None
+        }
+    }
+
+    /// Sends a byte. Returns `false` if the UART isn't ready to accept it.
+    pub unsafe fn try_write(&self, byte: u8) -> bool {
+        if arch::read_port_u8(self.base_port + REG_LINE_STATUS) & LINE_STATUS_THR_EMPTY != 0 {
+            arch::write_port_u8(self.base_port + REG_DATA, byte);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the upper four bits of the modem status register, which contain the state of the
+    /// CTS, DSR, RI and DCD lines.
+    pub unsafe fn modem_status(&self) -> u8 {
+        arch::read_port_u8(self.base_port + REG_MODEM_STATUS) & 0xf0
+    }
+}
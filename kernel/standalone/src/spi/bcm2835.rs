@@ -0,0 +1,141 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the main SPI controller, called "SPI0", of the Broadcom BCM2835 family of SoCs.
+//!
+//! See the "BCM2835 ARM Peripherals" datasheet, chapter 10.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Physical address of the SPI0 controller on the BCM2836 and BCM2837 (Raspberry Pi 2 and 3).
+pub const BCM2836_SPI0_BASE: usize = 0x3f20_4000;
+
+/// Number of chip select lines of the controller.
+pub const NUM_CHIP_SELECTS: u8 = 2;
+
+/// Frequency of the clock the controller derives the bus clock from.
+const CORE_CLOCK_HZ: u32 = 250_000_000;
+
+/// Maximum duration of a transfer before we give up.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+// Offsets of the registers relative to the base address.
+const REG_CS: usize = 0x00;
+const REG_FIFO: usize = 0x04;
+const REG_CLK: usize = 0x08;
+
+const CS_CPHA: u32 = 1 << 2;
+const CS_CPOL: u32 = 1 << 3;
+const CS_CLEAR: u32 = 0b11 << 4;
+const CS_TA: u32 = 1 << 7;
+const CS_DONE: u32 = 1 << 16;
+const CS_RXD: u32 = 1 << 17;
+const CS_TXD: u32 = 1 << 18;
+
+/// The transfer didn't finish in time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeoutError;
+
+/// SPI controller accessed through memory-mapped registers.
+pub struct Bcm2835Spi {
+    base: usize,
+}
+
+impl Bcm2835Spi {
+    /// Initializes the driver for the controller at the given physical address.
+    ///
+    /// # Safety
+    ///
+    /// There must be a BCM2835-compatible SPI controller at this address, and physical memory
+    /// must be identity-mapped.
+    pub unsafe fn new(base: usize) -> Self {
+        Bcm2835Spi { base }
+    }
+
+    /// Asserts the given chip select line, sends `data` while receiving the same number of
+    /// bytes, then deasserts the line.
+    ///
+    /// `cpol` and `cpha` are the clock polarity and phase. The clock frequency is the highest
+    /// one that the controller supports below `max_clock_hz`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chip_select` is out of range or `max_clock_hz` is 0.
+    ///
+    pub fn transfer(
+        &mut self,
+        chip_select: u8,
+        cpol: bool,
+        cpha: bool,
+        max_clock_hz: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, TimeoutError> {
+        assert!(chip_select < NUM_CHIP_SELECTS);
+        assert_ne!(max_clock_hz, 0);
+
+        // The divider must be even. A value of 0 means 65536.
+        let max_clock_hz = max_clock_hz.min(CORE_CLOCK_HZ / 2);
+        let divider = (CORE_CLOCK_HZ + max_clock_hz - 1) / max_clock_hz;
+        let divider = ((divider + 1) & !1).max(2);
+        let divider = if divider >= 65536 { 0 } else { divider };
+
+        let mut config = u32::from(chip_select);
+        if cpol {
+            config |= CS_CPOL;
+        }
+        if cpha {
+            config |= CS_CPHA;
+        }
+
+        let deadline = crate::time::monotonic_clock() + TIMEOUT;
+        let mut out = Vec::with_capacity(data.len());
+        let mut sent = 0;
+
+        unsafe {
+            self.write_reg(REG_CLK, divider);
+            self.write_reg(REG_CS, config | CS_CLEAR);
+            self.write_reg(REG_CS, config | CS_TA);
+
+            let result = loop {
+                let status = self.read_reg(REG_CS);
+                if sent < data.len() && status & CS_TXD != 0 {
+                    self.write_reg(REG_FIFO, u32::from(data[sent]));
+                    sent += 1;
+                }
+                if out.len() < data.len() && status & CS_RXD != 0 {
+                    out.push(self.read_reg(REG_FIFO) as u8);
+                }
+                if out.len() == data.len() && status & CS_DONE != 0 {
+                    break Ok(out);
+                }
+                if crate::time::monotonic_clock() >= deadline {
+                    break Err(TimeoutError);
+                }
+            };
+
+            self.write_reg(REG_CS, config | CS_CLEAR);
+            result
+        }
+    }
+
+    unsafe fn read_reg(&self, reg: usize) -> u32 {
+        ((self.base + reg) as *const u32).read_volatile()
+    }
+
+    unsafe fn write_reg(&mut self, reg: usize, value: u32) {
+        ((self.base + reg) as *mut u32).write_volatile(value)
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `spi` interface.
+
+use crate::gpio::bcm2835::{Bcm2835Gpio, BCM2836_BASE};
+use crate::spi::bcm2835::{self, Bcm2835Spi};
+
+use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use core::{sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_spi_interface::ffi::{
+    BusInfo, ListBusesResponse, Mode, SpiMessage, Transfer, TransferResponse, INTERFACE,
+};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// Identifier of the only bus, named after the controller.
+const BUS: u32 = 0;
+
+/// State machine for `spi` interface messages handling.
+pub struct SpiNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    controller: Mutex<Bcm2835Spi>,
+    /// Events waiting to be returned by `next_event`.
+    events: Mutex<VecDeque<NativeProgramEvent<DummyMessageIdWrite>>>,
+    /// Waken up when an event is pushed.
+    waker: AtomicWaker,
+}
+
+impl SpiNativeProgram {
+    /// Connects the SPI controller to the pins 7 to 11 and initializes the new state machine for
+    /// SPI messages handling.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on BCM2836 or BCM2837 SoCs.
+    pub unsafe fn new() -> Self {
+        let mut gpio = Bcm2835Gpio::new(BCM2836_BASE);
+        for pin in 7..=11 {
+            gpio.set_alternate_function(pin, 0);
+        }
+
+        SpiNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            controller: Mutex::new(Bcm2835Spi::new(bcm2835::BCM2836_SPI0_BASE)),
+            events: Mutex::new(VecDeque::new()),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for SpiNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            match self.events.lock().pop_front() {
+                Some(event) => Poll::Ready(event),
+                None => Poll::Pending,
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let answer = match SpiMessage::decode(message) {
+            Ok(SpiMessage::ListBuses) => {
+                let buses = vec![BusInfo {
+                    bus: BUS,
+                    num_chip_selects: bcm2835::NUM_CHIP_SELECTS,
+                }];
+                Ok(ListBusesResponse { buses }.encode())
+            }
+            Ok(SpiMessage::Transfer(transfer)) => {
+                // TODO: processes can talk to any device; there should be some access control
+                let result = self.perform(transfer);
+                Ok(TransferResponse { result }.encode())
+            }
+            Err(_) => Err(()),
+        };
+
+        if let Some(message_id) = message_id {
+            self.events
+                .lock()
+                .push_back(NativeProgramEvent::Answer { message_id, answer });
+            self.waker.wake();
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
+
+impl SpiNativeProgram {
+    /// Performs a transfer.
+    // TODO: busy-waits until the transfer is over
+    fn perform(&self, transfer: Transfer) -> Result<Vec<u8>, ErrorPayload> {
+        if transfer.bus != BUS || transfer.chip_select >= bcm2835::NUM_CHIP_SELECTS {
+            return Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+        }
+        if transfer.clock_hz == 0 {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("clock frequency must be non-zero"));
+        }
+
+        let (cpol, cpha) = match transfer.mode {
+            Mode::Mode0 => (false, false),
+            Mode::Mode1 => (false, true),
+            Mode::Mode2 => (true, false),
+            Mode::Mode3 => (true, true),
+        };
+
+        self.controller
+            .lock()
+            .transfer(
+                transfer.chip_select,
+                cpol,
+                cpha,
+                transfer.clock_hz,
+                &transfer.data,
+            )
+            .map_err(|bcm2835::TimeoutError| ErrorPayload::new(ErrorClass::TIMED_OUT))
+    }
+}
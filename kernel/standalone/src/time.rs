@@ -13,7 +13,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use core::time::Duration;
+pub mod cmos;
+pub mod native;
+
+use core::{task::Poll, time::Duration};
+use futures::prelude::*;
 
 /// Returns the amount of time that has elapsed since an undeterminate moment in time.
 #[cfg(target_arch = "x86_64")]
@@ -34,3 +38,17 @@ pub fn monotonic_clock() -> Duration {
     }
     Duration::from_nanos(u64::from(reg))
 }
+
+/// Returns a `Future` that resolves once `duration` has elapsed.
+// TODO: busy-waits; use a timer interrupt instead
+pub fn wait(duration: Duration) -> impl Future<Output = ()> {
+    let until = monotonic_clock() + duration;
+    future::poll_fn(move |cx| {
+        if monotonic_clock() >= until {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+}
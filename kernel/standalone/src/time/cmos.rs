@@ -0,0 +1,139 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the real-time clock of the CMOS of PC platforms.
+//!
+//! See https://wiki.osdev.org/CMOS
+
+use crate::arch;
+
+const PORT_ADDRESS: u32 = 0x70;
+const PORT_DATA: u32 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOURS: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register for PM hours in 12 hours mode.
+const HOURS_PM: u8 = 1 << 7;
+
+/// Reads the date and time from the RTC, and returns the number of seconds since the Epoch.
+///
+/// Returns `None` if the content of the RTC is invalid, which is the case if there is no RTC.
+///
+/// The RTC is assumed to be set to UTC, and to a year between 1970 and 2069.
+// TODO: use the century register indicated by the ACPI tables
+pub unsafe fn read_unix_time() -> Option<u64> {
+    // The registers might change while we read them. We read them until we get the same values
+    // twice in a row.
+    let mut previous = read_registers()?;
+    for _ in 0..16 {
+        let current = read_registers()?;
+        if current == previous {
+            return to_unix_time(current, read_register(REG_STATUS_B));
+        }
+        previous = current;
+    }
+
+    None
+}
+
+/// Waits for the end of the update in progress, if any, then reads the seconds, minutes, hours,
+/// day, month and year registers.
+unsafe fn read_registers() -> Option<[u8; 6]> {
+    let mut attempts = 0;
+    while read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        // An update lasts for less than 2ms. Reading more than a million times means that
+        // something is wrong.
+        attempts += 1;
+        if attempts > 1_000_000 {
+            return None;
+        }
+    }
+
+    Some([
+        read_register(REG_SECONDS),
+        read_register(REG_MINUTES),
+        read_register(REG_HOURS),
+        read_register(REG_DAY),
+        read_register(REG_MONTH),
+        read_register(REG_YEAR),
+    ])
+}
+
+unsafe fn read_register(reg: u8) -> u8 {
+    arch::write_port_u8(PORT_ADDRESS, reg);
+    arch::read_port_u8(PORT_DATA)
+}
+
+fn to_unix_time(registers: [u8; 6], status_b: u8) -> Option<u64> {
+    let [seconds, minutes, hours, day, month, year] = registers;
+    let pm = hours & HOURS_PM != 0;
+    let hours = hours & !HOURS_PM;
+
+    let decode = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 {
+            u64::from(value)
+        } else {
+            u64::from(value >> 4) * 10 + u64::from(value & 0xf)
+        }
+    };
+    let (seconds, minutes, mut hours) = (decode(seconds), decode(minutes), decode(hours));
+    let (day, month, year) = (decode(day), decode(month), decode(year));
+
+    if status_b & STATUS_B_24_HOURS == 0 {
+        if hours == 0 || hours > 12 {
+            return None;
+        }
+        // 12 AM is midnight and 12 PM is noon.
+        hours %= 12;
+        if pm {
+            hours += 12;
+        }
+    }
+
+    if seconds >= 60 || minutes >= 60 || hours >= 24 {
+        return None;
+    }
+    if day == 0 || day > 31 || month == 0 || month > 12 || year >= 100 {
+        return None;
+    }
+
+    let year = if year >= 70 { 1900 + year } else { 2000 + year };
+    let days = days_since_epoch(year, month, day);
+    Some(((days * 24 + hours) * 60 + minutes) * 60 + seconds)
+}
+
+/// Returns the number of days between the 1st of January 1970 and the given date, which must be
+/// after 1970.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Years are considered to start in March, so that the leap day is at the end of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
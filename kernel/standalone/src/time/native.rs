@@ -0,0 +1,226 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `time` interface.
+//!
+//! The system clock is initialized from the CMOS real-time clock on x86_64. Platforms without
+//! a real-time clock, such as Raspberry Pi boards, start at the Epoch. In both cases, programs
+//! can then correct the system clock, for example after obtaining the time through SNTP.
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::{sync::atomic, task::Poll};
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_time_interface::ffi::{TimeMessage, INTERFACE};
+use spin::Mutex;
+
+/// State machine for `time` interface messages handling.
+pub struct TimeNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    /// Value of the system clock when it has last been set.
+    system_clock_base: u128,
+    /// Value of the monotonic clock when the system clock has last been set.
+    monotonic_clock_base: u128,
+    /// Timers that haven't fired yet.
+    timers: Vec<Timer>,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+struct Timer {
+    message_id: MessageId,
+    emitter_pid: Pid,
+    /// Value of the monotonic clock when the timer fires.
+    until: u128,
+    /// For periodic timers, the period.
+    period: Option<u128>,
+}
+
+impl TimeNativeProgram {
+    /// Reads the real-time clock, if any, and initializes the new state machine for time
+    /// messages handling.
+    pub fn new() -> Self {
+        let rtc_secs = if cfg!(target_arch = "x86_64") {
+            unsafe { crate::time::cmos::read_unix_time() }
+        } else {
+            None
+        };
+
+        TimeNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                system_clock_base: u128::from(rtc_secs.unwrap_or(0)) * 1_000_000_000,
+                monotonic_clock_base: monotonic_clock(),
+                timers: Vec::new(),
+                events: VecDeque::new(),
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for TimeNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+
+            let mut inner = self.inner.lock();
+            inner.poll_timers(monotonic_clock());
+            if let Some(event) = inner.events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            // TODO: busy-waits; use a timer interrupt instead
+            if !inner.timers.is_empty() {
+                cx.waker().wake_by_ref();
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let mut inner = self.inner.lock();
+        let now = monotonic_clock();
+
+        let answer = match TimeMessage::decode(message) {
+            Ok(TimeMessage::GetMonotonic) => Some(Ok(now.encode())),
+            Ok(TimeMessage::GetSystem) => {
+                let system = inner.system_clock_base + (now - inner.monotonic_clock_base);
+                Some(Ok(system.encode()))
+            }
+            Ok(TimeMessage::SetSystem(system)) => {
+                // TODO: any process can change the system clock; there should be some access
+                //       control
+                inner.system_clock_base = system;
+                inner.monotonic_clock_base = now;
+                None
+            }
+            Ok(TimeMessage::WaitMonotonicPeriodic { period: 0, .. }) => Some(Err(())),
+            Ok(TimeMessage::WaitMonotonic(until)) => {
+                if let Some(message_id) = message_id {
+                    inner.timers.push(Timer {
+                        message_id,
+                        emitter_pid,
+                        until,
+                        period: None,
+                    });
+                }
+                None
+            }
+            Ok(TimeMessage::WaitMonotonicPeriodic { first, period }) => {
+                if let Some(message_id) = message_id {
+                    inner.timers.push(Timer {
+                        message_id,
+                        emitter_pid,
+                        until: first,
+                        period: Some(period),
+                    });
+                }
+                None
+            }
+            Err(_) => Some(Err(())),
+        };
+
+        if let (Some(message_id), Some(answer)) = (message_id, answer) {
+            inner
+                .events
+                .push_back(NativeProgramEvent::Answer { message_id, answer });
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.timers.retain(|t| t.emitter_pid != pid);
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn message_cancelled(&self, message_id: MessageId) {
+        let mut inner = self.inner.lock();
+        inner.timers.retain(|t| t.message_id != message_id);
+    }
+}
+
+impl Inner {
+    /// Pushes to `events` the answers to the timers that have fired.
+    fn poll_timers(&mut self, now: u128) {
+        let mut n = 0;
+        while n < self.timers.len() {
+            let timer = &mut self.timers[n];
+            if timer.until > now {
+                n += 1;
+                continue;
+            }
+
+            match timer.period {
+                None => {
+                    let timer = self.timers.remove(n);
+                    self.events.push_back(NativeProgramEvent::Answer {
+                        message_id: timer.message_id,
+                        answer: Ok(().encode()),
+                    });
+                }
+                Some(period) => {
+                    // Skip the ticks that we have missed.
+                    timer.until += ((now - timer.until) / period + 1) * period;
+                    self.events.push_back(NativeProgramEvent::PartialAnswer {
+                        message_id: timer.message_id,
+                        answer: ().encode(),
+                    });
+                    n += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value of the monotonic clock reported to programs, in nanoseconds.
+fn monotonic_clock() -> u128 {
+    crate::time::monotonic_clock().as_nanos()
+}
@@ -0,0 +1,138 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Native program that handles the `console` and `stdout` interfaces by writing on the VGA
+//! text mode screen.
+
+use crate::vga::text::{TextConsole, COLUMNS, ROWS};
+
+use alloc::{boxed::Box, collections::VecDeque};
+use core::task::Poll;
+use futures::{prelude::*, task::AtomicWaker};
+use redshirt_console_interface::ffi::{ConsoleMessage, ReadLineResponse, SizeResponse};
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_stdout_interface::ffi::StdoutMessage;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use spin::Mutex;
+
+/// State machine for `console` and `stdout` interface messages handling.
+pub struct VgaConsoleNativeProgram {
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    console: TextConsole,
+    /// Events waiting to be returned by `next_event`. Initially contains the registrations of
+    /// the interfaces.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+impl VgaConsoleNativeProgram {
+    /// Clears the screen and initializes the new state machine.
+    ///
+    /// # Safety
+    ///
+    /// Assumes that the video card is in text mode.
+    ///
+    pub unsafe fn new() -> Self {
+        let events = [
+            redshirt_console_interface::ffi::INTERFACE,
+            redshirt_stdout_interface::ffi::INTERFACE,
+        ]
+        .iter()
+        .map(|interface| NativeProgramEvent::Emit {
+            interface: redshirt_interface_interface::ffi::INTERFACE,
+            message_id_write: None,
+            message: redshirt_interface_interface::ffi::InterfaceMessage::Register(*interface)
+                .encode(),
+        })
+        .collect();
+
+        VgaConsoleNativeProgram {
+            inner: Mutex::new(Inner {
+                console: TextConsole::new(),
+                events,
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for VgaConsoleNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            match self.inner.lock().events.pop_front() {
+                Some(event) => Poll::Ready(event),
+                None => Poll::Pending,
+            }
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        _emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        let mut inner = self.inner.lock();
+
+        if interface == redshirt_stdout_interface::ffi::INTERFACE {
+            if let Ok(StdoutMessage::Message(text)) = StdoutMessage::decode(message) {
+                inner.console.write_raw(&text);
+            }
+            return;
+        }
+
+        debug_assert_eq!(interface, redshirt_console_interface::ffi::INTERFACE);
+        let answer = match ConsoleMessage::decode(message) {
+            Ok(ConsoleMessage::Write(text)) => {
+                inner.console.write(&text);
+                return;
+            }
+            Ok(ConsoleMessage::ReadLine) => {
+                // TODO: read from the keyboard interface
+                let result = Err(ErrorPayload::new(ErrorClass::UNSUPPORTED));
+                Ok(ReadLineResponse { result }.encode())
+            }
+            Ok(ConsoleMessage::Size) => Ok(SizeResponse {
+                columns: u16::from(COLUMNS),
+                rows: u16::from(ROWS),
+            }
+            .encode()),
+            Err(_) => Err(()),
+        };
+
+        if let Some(message_id) = message_id {
+            inner
+                .events
+                .push_back(NativeProgramEvent::Answer { message_id, answer });
+            self.waker.wake();
+        }
+    }
+
+    fn process_destroyed(&self, _: Pid) {}
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Console on top of the 80x25 VGA text mode.
+//!
+//! The screen is a buffer of 80x25 16-bit values located at physical address `0xb8000`. The low
+//! byte of each value is a character in code page 437, and the high byte its attribute: the
+//! foreground color in the low nibble, and the background color in the high nibble.
+//!
+//! See https://wiki.osdev.org/Text_UI
+
+use crate::arch;
+
+use alloc::{vec, vec::Vec};
+use core::convert::TryFrom as _;
+
+/// Number of characters per line.
+pub const COLUMNS: u8 = 80;
+/// Number of lines.
+pub const ROWS: u8 = 25;
+
+/// Attribute byte used when no color has been set: bright light gray on black.
+const DEFAULT_ATTRIBUTE: u8 = 0xf;
+
+/// VGA color corresponding to each of the eight ANSI colors.
+const ANSI_TO_VGA_COLOR: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// State machine of the text console.
+pub struct TextConsole {
+    cursor_x: u8,
+    cursor_y: u8,
+    /// VGA attribute byte of the characters being written.
+    attribute: u8,
+    /// Escape sequence currently being parsed.
+    escape: Escape,
+}
+
+/// State of the parsing of ANSI escape sequences.
+enum Escape {
+    /// Not in an escape sequence.
+    None,
+    /// After an `ESC` character.
+    Escape,
+    /// After `ESC [`. Contains the parameters parsed so far.
+    Csi(Vec<u16>),
+}
+
+impl TextConsole {
+    /// Initializes the console and clears the screen.
+    ///
+    /// # Safety
+    ///
+    /// Assumes that the video card is in text mode, and that the video memory is mapped at its
+    /// physical address.
+    ///
+    pub unsafe fn new() -> TextConsole {
+        let mut console = TextConsole {
+            cursor_x: 0,
+            cursor_y: 0,
+            attribute: DEFAULT_ATTRIBUTE,
+            escape: Escape::None,
+        };
+
+        for y in 0..ROWS {
+            console.erase(y, 0, COLUMNS);
+        }
+        console.update_cursor();
+        console
+    }
+
+    /// Writes text on the console, ignoring escape sequences.
+    pub fn write_raw(&mut self, text: &str) {
+        for chr in text.chars() {
+            self.put_char(chr);
+        }
+        self.update_cursor();
+    }
+
+    /// Writes text on the console, interpreting ANSI escape sequences.
+    pub fn write(&mut self, text: &str) {
+        for chr in text.chars() {
+            match (&mut self.escape, chr) {
+                (Escape::None, '\x1b') => self.escape = Escape::Escape,
+                (Escape::None, chr) => self.put_char(chr),
+                (Escape::Escape, '[') => self.escape = Escape::Csi(vec![0]),
+                // Sequences other than CSI are not supported.
+                (Escape::Escape, _) => self.escape = Escape::None,
+                (Escape::Csi(params), ';') => params.push(0),
+                (Escape::Csi(params), '0'..='9') => {
+                    let last = params.last_mut().unwrap();
+                    let digit = chr.to_digit(10).unwrap() as u16;
+                    *last = last.saturating_mul(10).saturating_add(digit);
+                }
+                (Escape::Csi(_), chr) => {
+                    let params = match core::mem::replace(&mut self.escape, Escape::None) {
+                        Escape::Csi(params) => params,
+                        _ => unreachable!(),
+                    };
+                    self.apply_csi(&params, chr);
+                }
+            }
+        }
+
+        self.update_cursor();
+    }
+
+    /// Applies a CSI escape sequence. `params` always contains at least one element, where `0`
+    /// means that the parameter was omitted.
+    fn apply_csi(&mut self, params: &[u16], command: char) {
+        let count = u8::try_from(params[0].max(1)).unwrap_or(u8::max_value());
+
+        match command {
+            'A' => self.cursor_y = self.cursor_y.saturating_sub(count),
+            'B' => self.cursor_y = self.cursor_y.saturating_add(count).min(ROWS - 1),
+            'C' => self.cursor_x = self.cursor_x.saturating_add(count).min(COLUMNS - 1),
+            'D' => self.cursor_x = self.cursor_x.saturating_sub(count),
+            'H' | 'f' => {
+                let row = params[0].max(1).min(u16::from(ROWS)) - 1;
+                let col = params
+                    .get(1)
+                    .cloned()
+                    .unwrap_or(0)
+                    .max(1)
+                    .min(u16::from(COLUMNS))
+                    - 1;
+                self.cursor_y = row as u8;
+                self.cursor_x = col as u8;
+            }
+            'J' => match params[0] {
+                0 => {
+                    self.erase(self.cursor_y, self.cursor_x, COLUMNS);
+                    for y in self.cursor_y + 1..ROWS {
+                        self.erase(y, 0, COLUMNS);
+                    }
+                }
+                1 => {
+                    for y in 0..self.cursor_y {
+                        self.erase(y, 0, COLUMNS);
+                    }
+                    self.erase(self.cursor_y, 0, self.cursor_x + 1);
+                }
+                2 | 3 => {
+                    for y in 0..ROWS {
+                        self.erase(y, 0, COLUMNS);
+                    }
+                }
+                _ => {}
+            },
+            'K' => match params[0] {
+                0 => self.erase(self.cursor_y, self.cursor_x, COLUMNS),
+                1 => self.erase(self.cursor_y, 0, self.cursor_x + 1),
+                2 => self.erase(self.cursor_y, 0, COLUMNS),
+                _ => {}
+            },
+            'm' => {
+                for param in params {
+                    match *param {
+                        0 => self.attribute = DEFAULT_ATTRIBUTE,
+                        1 => self.attribute |= 0x8,
+                        22 => self.attribute &= !0x8,
+                        n @ 30..=37 => self.set_foreground(ANSI_TO_VGA_COLOR[usize::from(n - 30)]),
+                        39 => self.set_foreground(DEFAULT_ATTRIBUTE & 0xf),
+                        n @ 40..=47 => self.set_background(ANSI_TO_VGA_COLOR[usize::from(n - 40)]),
+                        49 => self.set_background(0),
+                        n @ 90..=97 => {
+                            self.set_foreground(ANSI_TO_VGA_COLOR[usize::from(n - 90)] | 0x8)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_foreground(&mut self, color: u8) {
+        self.attribute = (self.attribute & 0xf0) | color;
+    }
+
+    fn set_background(&mut self, color: u8) {
+        self.attribute = (self.attribute & 0x0f) | (color << 4);
+    }
+
+    /// Writes a single character at the cursor position and advances the cursor.
+    fn put_char(&mut self, chr: char) {
+        match chr {
+            '\n' => {
+                self.cursor_x = 0;
+                self.line_feed();
+            }
+            '\r' => self.cursor_x = 0,
+            '\x08' => self.cursor_x = self.cursor_x.saturating_sub(1),
+            '\t' => {
+                let next_stop = (self.cursor_x / 8 + 1) * 8;
+                while self.cursor_x < next_stop.min(COLUMNS) {
+                    self.put_char(' ');
+                }
+            }
+            chr if chr.is_ascii() && !chr.is_ascii_control() => self.put_byte(chr as u8),
+            // Characters that can't be represented are shown as a filled square.
+            chr if !chr.is_ascii() => self.put_byte(0xfe),
+            _ => {}
+        }
+    }
+
+    /// Writes a code page 437 character at the cursor position and advances the cursor.
+    fn put_byte(&mut self, byte: u8) {
+        self.write_cell(self.cursor_x, self.cursor_y, byte);
+        debug_assert!(self.cursor_x < COLUMNS);
+        self.cursor_x += 1;
+        if self.cursor_x == COLUMNS {
+            self.cursor_x = 0;
+            self.line_feed();
+        }
+    }
+
+    /// Moves the cursor to the next line, scrolling the screen if necessary.
+    fn line_feed(&mut self) {
+        debug_assert!(self.cursor_y < ROWS);
+        if self.cursor_y < ROWS - 1 {
+            self.cursor_y += 1;
+            return;
+        }
+
+        unsafe {
+            for y in 1..ROWS {
+                for x in 0..COLUMNS {
+                    let val = ptr_of(x, y).read_volatile();
+                    ptr_of(x, y - 1).write_volatile(val);
+                }
+            }
+        }
+        self.erase(ROWS - 1, 0, COLUMNS);
+    }
+
+    /// Replaces the characters between `x_start` (inclusive) and `x_end` (exclusive) on line `y`
+    /// with spaces.
+    fn erase(&self, y: u8, x_start: u8, x_end: u8) {
+        for x in x_start..x_end.min(COLUMNS) {
+            self.write_cell(x, y, b' ');
+        }
+    }
+
+    fn write_cell(&self, x: u8, y: u8, chr: u8) {
+        unsafe {
+            ptr_of(x, y).write_volatile(u16::from(chr) | (u16::from(self.attribute) << 8));
+        }
+    }
+
+    /// Moves the hardware cursor to the position of the cursor.
+    fn update_cursor(&self) {
+        let cursor_pos = u16::from(self.cursor_y) * u16::from(COLUMNS) + u16::from(self.cursor_x);
+        unsafe {
+            arch::write_port_u8(0x3d4, 0xf);
+            arch::write_port_u8(0x3d5, u8::try_from(cursor_pos & 0xff).unwrap());
+            arch::write_port_u8(0x3d4, 0xe);
+            arch::write_port_u8(0x3d5, u8::try_from(cursor_pos >> 8).unwrap());
+        }
+    }
+}
+
+fn ptr_of(x: u8, y: u8) -> *mut u16 {
+    assert!(x < COLUMNS);
+    assert!(y < ROWS);
+
+    unsafe {
+        let offset = isize::from(y) * isize::from(COLUMNS) + isize::from(x);
+        (0xb8000 as *mut u16).offset(offset)
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the watchdog timer of the Intel 6300ESB I/O controller hub.
+//!
+//! This is the watchdog emulated by QEMU when passing `-watchdog i6300esb`.
+//!
+//! The device counts down two stages, each of the duration written in the timer registers.
+//! The first stage raises an interrupt, which we keep disabled, and the second stage resets
+//! the machine. Reloading the timer restarts from the first stage.
+//!
+//! See the Intel 6300ESB I/O Controller Hub datasheet, section "Watchdog Timer".
+
+use crate::arch;
+
+use core::{convert::TryFrom as _, time::Duration};
+
+const VENDOR_ID: u16 = 0x8086;
+const DEVICE_ID: u16 = 0x25ab;
+
+// I/O ports of the PCI configuration mechanism #1.
+const PCI_CONFIG_ADDRESS: u32 = 0xcf8;
+const PCI_CONFIG_DATA: u32 = 0xcfc;
+
+// Registers in the PCI configuration space of the device.
+const CONFIG_BAR0: u8 = 0x10;
+const CONFIG_WDT_CONFIG: u8 = 0x60;
+const CONFIG_WDT_LOCK: u8 = 0x68;
+
+// Offsets of the memory-mapped registers relative to BAR0.
+const REG_TIMER1: usize = 0x00;
+const REG_TIMER2: usize = 0x04;
+const REG_RELOAD: usize = 0x0c;
+
+/// Value for `CONFIG_WDT_CONFIG`: output enabled, 1kHz clock, no interrupt on the first stage.
+const WDT_CONFIG_NO_INTERRUPT: u16 = 0x3;
+const WDT_LOCK_ENABLE: u8 = 1 << 1;
+const RELOAD_RELOAD: u16 = 1 << 8;
+const RELOAD_TIMEOUT: u16 = 1 << 9;
+
+/// Largest value of each stage, in seconds. The timer registers are 20 bits wide and count at
+/// roughly 1kHz, with a granularity of 2^9 ticks.
+const MAX_STAGE_SECS: u32 = 0xfffff >> 9;
+
+/// Intel 6300ESB watchdog timer.
+pub struct I6300Esb {
+    bus: u8,
+    device: u8,
+    /// Physical address of the memory-mapped registers.
+    base: usize,
+}
+
+impl I6300Esb {
+    /// Looks for the device on the PCI bus and, if found, initializes it with the timer stopped.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called once. Accesses the PCI configuration space through I/O ports, and
+    /// assumes that physical memory is identity-mapped.
+    pub unsafe fn find() -> Option<I6300Esb> {
+        for bus in 0..=255u8 {
+            for device in 0..32u8 {
+                let id = pci_config_read_u32(bus, device, 0);
+                if id as u16 != VENDOR_ID || (id >> 16) as u16 != DEVICE_ID {
+                    continue;
+                }
+
+                let base =
+                    usize::try_from(pci_config_read_u32(bus, device, CONFIG_BAR0) & !0xf).ok()?;
+                let mut wdt = I6300Esb { bus, device, base };
+                wdt.init();
+                return Some(wdt);
+            }
+        }
+
+        None
+    }
+
+    /// Starts the countdown. The machine is reset if [`I6300Esb::pet`] isn't called before
+    /// `timeout` elapses.
+    ///
+    /// `timeout` is rounded up to the second, and capped to the maximum that the device
+    /// supports.
+    pub fn start(&mut self, timeout: Duration) {
+        let mut secs = u32::try_from(timeout.as_secs()).unwrap_or(u32::max_value());
+        if timeout.subsec_nanos() != 0 {
+            secs = secs.saturating_add(1);
+        }
+        // The timeout is split between the two stages.
+        let stage = (secs / 2 + secs % 2).max(1).min(MAX_STAGE_SECS);
+
+        unsafe {
+            self.unlock_registers();
+            self.write_reg_u32(REG_TIMER1, stage << 9);
+            self.unlock_registers();
+            self.write_reg_u32(REG_TIMER2, stage << 9);
+            self.pet();
+            pci_config_write_u8(self.bus, self.device, CONFIG_WDT_LOCK, WDT_LOCK_ENABLE);
+        }
+    }
+
+    /// Restarts the countdown.
+    pub fn pet(&mut self) {
+        unsafe {
+            self.unlock_registers();
+            self.write_reg_u16(REG_RELOAD, RELOAD_RELOAD);
+        }
+    }
+
+    /// Stops the countdown.
+    pub fn stop(&mut self) {
+        unsafe {
+            self.pet();
+            pci_config_write_u8(self.bus, self.device, CONFIG_WDT_LOCK, 0);
+        }
+    }
+
+    unsafe fn init(&mut self) {
+        pci_config_write_u16(
+            self.bus,
+            self.device,
+            CONFIG_WDT_CONFIG,
+            WDT_CONFIG_NO_INTERRUPT,
+        );
+        pci_config_write_u8(self.bus, self.device, CONFIG_WDT_LOCK, 0);
+
+        // Clears the flag indicating that the previous boot has been ended by the watchdog.
+        self.unlock_registers();
+        self.write_reg_u16(REG_RELOAD, RELOAD_TIMEOUT | RELOAD_RELOAD);
+    }
+
+    /// The memory-mapped registers are write-protected. This sequence allows a single write.
+    unsafe fn unlock_registers(&mut self) {
+        self.write_reg_u16(REG_RELOAD, 0x80);
+        self.write_reg_u16(REG_RELOAD, 0x86);
+    }
+
+    unsafe fn write_reg_u16(&mut self, reg: usize, value: u16) {
+        ((self.base + reg) as *mut u16).write_volatile(value);
+    }
+
+    unsafe fn write_reg_u32(&mut self, reg: usize, value: u32) {
+        ((self.base + reg) as *mut u32).write_volatile(value);
+    }
+}
+
+fn pci_config_address(bus: u8, device: u8, offset: u8) -> u32 {
+    0x8000_0000 | (u32::from(bus) << 16) | (u32::from(device) << 11) | u32::from(offset & 0xfc)
+}
+
+unsafe fn pci_config_read_u32(bus: u8, device: u8, offset: u8) -> u32 {
+    arch::write_port_u32(PCI_CONFIG_ADDRESS, pci_config_address(bus, device, offset));
+    arch::read_port_u32(PCI_CONFIG_DATA)
+}
+
+unsafe fn pci_config_write_u16(bus: u8, device: u8, offset: u8, value: u16) {
+    arch::write_port_u32(PCI_CONFIG_ADDRESS, pci_config_address(bus, device, offset));
+    arch::write_port_u16(PCI_CONFIG_DATA + u32::from(offset & 0x2), value);
+}
+
+unsafe fn pci_config_write_u8(bus: u8, device: u8, offset: u8, value: u8) {
+    arch::write_port_u32(PCI_CONFIG_ADDRESS, pci_config_address(bus, device, offset));
+    arch::write_port_u8(PCI_CONFIG_DATA + u32::from(offset & 0x3), value);
+}
@@ -0,0 +1,304 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Native program that handles the `watchdog` interface.
+//!
+//! If an [`I6300Esb`] is present, it is armed as long as at least one watchdog is registered,
+//! and is petted by this program. If the kernel stops running this program, the machine is
+//! then reset by the hardware.
+
+use crate::watchdog::i6300esb::I6300Esb;
+
+use alloc::{boxed::Box, collections::VecDeque, format, string::String, vec, vec::Vec};
+use core::{sync::atomic, task::Poll, time::Duration};
+use futures::{prelude::*, task::AtomicWaker};
+use hashbrown::HashMap;
+use redshirt_core::native::{
+    DummyMessageIdWrite, NativeProgram, NativeProgramEvent, NativeProgramFuture,
+};
+use redshirt_core::{Decode as _, Encode as _, EncodedMessage, InterfaceHash, MessageId, Pid};
+use redshirt_log_interface::ffi::{Level, LogMessage, LogRecord};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use redshirt_watchdog_interface::ffi::{Action, RegisterResponse, WatchdogMessage, INTERFACE};
+use spin::Mutex;
+
+/// Timeout of the hardware watchdog. It is petted when half of this duration has elapsed.
+const HARDWARE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// State machine for `watchdog` interface messages handling.
+pub struct WatchdogNativeProgram {
+    /// If true, we have sent the interface registration message.
+    registered: atomic::AtomicBool,
+    inner: Mutex<Inner>,
+    /// Waken up when a message is received.
+    waker: AtomicWaker,
+}
+
+struct Inner {
+    /// List of registered watchdogs, by identifier.
+    watchdogs: HashMap<u64, Watchdog>,
+    /// Identifier to assign to the next watchdog.
+    next_id: u64,
+    /// Hardware watchdog timer, if any.
+    hardware: Option<I6300Esb>,
+    /// If `Some`, the hardware watchdog is counting down and must be petted at the given moment.
+    hardware_next_pet: Option<Duration>,
+    /// Events waiting to be returned by `next_event`.
+    events: VecDeque<NativeProgramEvent<DummyMessageIdWrite>>,
+}
+
+struct Watchdog {
+    /// Process that has registered the watchdog.
+    owner: Pid,
+    timeout: Duration,
+    /// Value of the monotonic clock after which the watchdog expires.
+    deadline: Duration,
+    action: Action,
+}
+
+impl WatchdogNativeProgram {
+    /// Detects the hardware watchdog and initializes the new state machine for watchdog
+    /// messages handling.
+    pub fn new() -> Self {
+        let hardware = if cfg!(target_arch = "x86_64") {
+            unsafe { I6300Esb::find() }
+        } else {
+            None
+        };
+
+        WatchdogNativeProgram {
+            registered: atomic::AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                watchdogs: HashMap::new(),
+                next_id: 0,
+                hardware,
+                hardware_next_pet: None,
+                events: VecDeque::new(),
+            }),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl NativeProgram for WatchdogNativeProgram {
+    type MessageIdWrite = DummyMessageIdWrite;
+
+    fn next_event<'a>(&'a self) -> NativeProgramFuture<'a, Self::MessageIdWrite> {
+        if !self.registered.swap(true, atomic::Ordering::Relaxed) {
+            return Box::pin(future::ready(NativeProgramEvent::Emit {
+                interface: redshirt_interface_interface::ffi::INTERFACE,
+                message_id_write: None,
+                message: redshirt_interface_interface::ffi::InterfaceMessage::Register(INTERFACE)
+                    .encode(),
+            }));
+        }
+
+        Box::pin(future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+
+            let mut inner = self.inner.lock();
+            inner.poll_timers(crate::time::monotonic_clock());
+            if let Some(event) = inner.events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            // TODO: busy-waits; use a timer interrupt instead
+            if !inner.watchdogs.is_empty() || inner.hardware_next_pet.is_some() {
+                cx.waker().wake_by_ref();
+            }
+
+            Poll::Pending
+        }))
+    }
+
+    fn interface_message(
+        &self,
+        interface: InterfaceHash,
+        message_id: Option<MessageId>,
+        emitter_pid: Pid,
+        message: EncodedMessage,
+    ) {
+        debug_assert_eq!(interface, INTERFACE);
+
+        let mut inner = self.inner.lock();
+        let now = crate::time::monotonic_clock();
+
+        match WatchdogMessage::decode(message) {
+            Ok(WatchdogMessage::Register(register)) => {
+                let result = if register.timeout_ms == 0 {
+                    Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("timeout must be non-zero"))
+                } else {
+                    let id = inner.next_id;
+                    inner.next_id += 1;
+                    let timeout = Duration::from_millis(u64::from(register.timeout_ms));
+                    inner.watchdogs.insert(
+                        id,
+                        Watchdog {
+                            owner: emitter_pid,
+                            timeout,
+                            deadline: now + timeout,
+                            action: register.action,
+                        },
+                    );
+                    Ok(id)
+                };
+
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Ok(RegisterResponse { result }.encode()),
+                    });
+                }
+            }
+            Ok(WatchdogMessage::Pet(id)) => {
+                if let Some(watchdog) = inner.watchdogs.get_mut(&id) {
+                    if watchdog.owner == emitter_pid {
+                        watchdog.deadline = now + watchdog.timeout;
+                    }
+                }
+            }
+            Ok(WatchdogMessage::Unregister(id)) => {
+                if inner
+                    .watchdogs
+                    .get(&id)
+                    .map_or(false, |w| w.owner == emitter_pid)
+                {
+                    inner.watchdogs.remove(&id);
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = message_id {
+                    inner.events.push_back(NativeProgramEvent::Answer {
+                        message_id,
+                        answer: Err(()),
+                    });
+                }
+            }
+        }
+
+        self.waker.wake();
+    }
+
+    fn process_destroyed(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.watchdogs.retain(|_, w| w.owner != pid);
+        self.waker.wake();
+    }
+
+    fn message_response(&self, _: MessageId, _: Result<EncodedMessage, ()>) {
+        unreachable!()
+    }
+
+    fn shutdown(&self) {
+        let mut inner = self.inner.lock();
+        inner.watchdogs.clear();
+        inner.update_hardware(crate::time::monotonic_clock());
+    }
+}
+
+impl Inner {
+    /// Performs the action of the watchdogs that have expired, arms, pets or disarms the
+    /// hardware watchdog, and pushes the corresponding events to `events`.
+    fn poll_timers(&mut self, now: Duration) {
+        let mut expired = Vec::new();
+        for (id, watchdog) in self.watchdogs.iter_mut() {
+            if watchdog.deadline > now {
+                continue;
+            }
+
+            let action = match watchdog.action {
+                Action::Log => "none",
+                Action::Restart => "restarting process",
+                Action::Reboot => "rebooting",
+            };
+            self.events.push_back(log_event(
+                format!("watchdog {} of {:?} has expired", id, watchdog.owner),
+                action,
+            ));
+
+            match watchdog.action {
+                Action::Log => watchdog.deadline = now + watchdog.timeout,
+                Action::Restart => {
+                    // The kernel lets native programs kill any process.
+                    let message =
+                        redshirt_process_interface::ffi::ProcessMessage::Kill(watchdog.owner);
+                    self.events.push_back(NativeProgramEvent::Emit {
+                        interface: redshirt_process_interface::ffi::INTERFACE,
+                        message_id_write: None,
+                        message: message.encode(),
+                    });
+                    expired.push(*id);
+                }
+                Action::Reboot => {
+                    let message = redshirt_power_interface::ffi::PowerMessage::Reboot;
+                    self.events.push_back(NativeProgramEvent::Emit {
+                        interface: redshirt_power_interface::ffi::INTERFACE,
+                        message_id_write: None,
+                        message: message.encode(),
+                    });
+                    expired.push(*id);
+                }
+            }
+        }
+
+        for id in expired {
+            self.watchdogs.remove(&id);
+        }
+
+        self.update_hardware(now);
+    }
+
+    /// Arms the hardware watchdog if there is any registered watchdog, and disarms it
+    /// otherwise. Pets it if necessary.
+    fn update_hardware(&mut self, now: Duration) {
+        let hardware = match self.hardware.as_mut() {
+            Some(h) => h,
+            None => return,
+        };
+
+        match self.hardware_next_pet {
+            None if !self.watchdogs.is_empty() => {
+                hardware.start(HARDWARE_TIMEOUT);
+                self.hardware_next_pet = Some(now + HARDWARE_TIMEOUT / 2);
+            }
+            Some(_) if self.watchdogs.is_empty() => {
+                hardware.stop();
+                self.hardware_next_pet = None;
+            }
+            Some(next_pet) if now >= next_pet => {
+                hardware.pet();
+                self.hardware_next_pet = Some(now + HARDWARE_TIMEOUT / 2);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds an event that writes an entry in the logs.
+fn log_event(message: String, action: &str) -> NativeProgramEvent<DummyMessageIdWrite> {
+    let record = LogRecord {
+        level: Level::Error,
+        target: From::from("watchdog"),
+        message,
+        fields: vec![(From::from("action"), From::from(action))],
+    };
+
+    NativeProgramEvent::Emit {
+        interface: redshirt_log_interface::ffi::INTERFACE,
+        message_id_write: None,
+        message: LogMessage::Log(record).encode(),
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for AHCI SATA controllers.
+//!
+//! This program scans the PCI space for AHCI controllers, initializes the SATA disks attached
+//! to them, and exposes these disks through the block device interface.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/AHCI
+//! - https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/serial-ata-ahci-spec-rev1-3-1.pdf
+//!
+
+mod port;
+
+use parity_scale_codec::DecodeAll;
+use redshirt_block_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId};
+use std::convert::TryFrom as _;
+
+/// Offset of the global host control register.
+const HBA_GHC: u64 = 0x04;
+/// Offset of the register indicating which ports are implemented.
+const HBA_PI: u64 = 0x0c;
+/// Bit of the global host control register enabling AHCI mode.
+const HBA_GHC_AE: u32 = 1 << 31;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut disks = Vec::new();
+
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    for device in pci_devices {
+        // Class code for mass storage controllers, subclass for SATA, and programming interface
+        // for AHCI.
+        if device.class_code != 0x1 || device.subclass != 0x6 || device.prog_if != 0x1 {
+            continue;
+        }
+
+        // The registers of the controller are always in the sixth base address register.
+        let abar = match redshirt_pci_interface::map_bar(device.location, 5).await {
+            Ok(redshirt_pci_interface::PciBaseAddressRegister::Memory { base_address, .. }) => {
+                base_address
+            }
+            _ => continue,
+        };
+        redshirt_pci_interface::enable_bus_mastering(device.location);
+
+        unsafe {
+            let ghc = port::read_u32(abar + HBA_GHC).await;
+            redshirt_hardware_interface::write_one_u32(abar + HBA_GHC, ghc | HBA_GHC_AE);
+
+            let ports_implemented = port::read_u32(abar + HBA_PI).await;
+            for port_num in 0..32 {
+                if ports_implemented & (1 << port_num) == 0 {
+                    continue;
+                }
+
+                if let Some(disk) = port::Disk::init(abar + 0x100 + port_num * 0x80).await {
+                    disks.push(disk);
+                }
+            }
+        }
+    }
+
+    if disks.is_empty() {
+        return;
+    }
+
+    // TODO: only one program can handle the block device interface at a time
+    if redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    // TODO: operations are processed one by one, while they could be queued in parallel
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::BlockMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match message {
+            ffi::BlockMessage::ListDevices => {
+                let devices = (0..u32::try_from(disks.len()).unwrap()).collect();
+                answer(msg.message_id, ffi::ListDevicesResponse { devices });
+            }
+            ffi::BlockMessage::Geometry(geometry) => {
+                let result = disk(&mut disks, geometry.device).map(|disk| ffi::DeviceGeometry {
+                    sector_size: port::SECTOR_SIZE,
+                    num_sectors: disk.num_sectors(),
+                    read_only: false,
+                });
+                answer(msg.message_id, ffi::GeometryResponse { result });
+            }
+            ffi::BlockMessage::Read(read) => {
+                let result = match disk(&mut disks, read.device) {
+                    Ok(disk) => {
+                        match check_range(disk, read.first_sector, u64::from(read.num_sectors)) {
+                            Ok(()) => read_sectors(disk, read.first_sector, read.num_sectors).await,
+                            Err(err) => Err(err),
+                        }
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadResponse { result });
+            }
+            ffi::BlockMessage::Write(write) => {
+                let result = match disk(&mut disks, write.device) {
+                    Ok(_) if write.data.len() % port::SECTOR_SIZE as usize != 0 => {
+                        Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("length isn't a multiple of the sector size"))
+                    }
+                    Ok(disk) => {
+                        let num_sectors =
+                            u64::try_from(write.data.len() / port::SECTOR_SIZE as usize).unwrap();
+                        match check_range(disk, write.first_sector, num_sectors) {
+                            Ok(()) => write_sectors(disk, write.first_sector, write.data).await,
+                            Err(err) => Err(err),
+                        }
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::WriteResponse { result });
+            }
+            ffi::BlockMessage::Flush(flush) => {
+                let result = match disk(&mut disks, flush.device) {
+                    Ok(disk) => unsafe { disk.flush().await }.map_err(|()| io_error()),
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::FlushResponse { result });
+            }
+        }
+    }
+}
+
+/// Reads a range of sectors, splitting it in multiple commands if necessary.
+async fn read_sectors(
+    disk: &mut port::Disk,
+    first_sector: u64,
+    num_sectors: u32,
+) -> Result<Vec<u8>, ErrorPayload> {
+    let mut out = Vec::with_capacity(num_sectors as usize * port::SECTOR_SIZE as usize);
+    let mut sector = first_sector;
+    let mut remaining = num_sectors;
+    while remaining != 0 {
+        let chunk = u16::try_from(remaining)
+            .unwrap_or(port::MAX_SECTORS_PER_COMMAND)
+            .min(port::MAX_SECTORS_PER_COMMAND);
+        let data = unsafe { disk.read(sector, chunk).await }.map_err(|()| io_error())?;
+        out.extend_from_slice(&data);
+        sector += u64::from(chunk);
+        remaining -= u32::from(chunk);
+    }
+    Ok(out)
+}
+
+/// Writes a range of sectors, splitting it in multiple commands if necessary.
+async fn write_sectors(
+    disk: &mut port::Disk,
+    first_sector: u64,
+    data: Vec<u8>,
+) -> Result<(), ErrorPayload> {
+    let chunk_len = usize::from(port::MAX_SECTORS_PER_COMMAND) * port::SECTOR_SIZE as usize;
+    let mut sector = first_sector;
+    for chunk in data.chunks(chunk_len) {
+        unsafe { disk.write(sector, chunk.to_vec()).await }.map_err(|()| io_error())?;
+        sector += u64::try_from(chunk.len()).unwrap() / u64::from(port::SECTOR_SIZE);
+    }
+    Ok(())
+}
+
+fn disk(disks: &mut [port::Disk], device: u32) -> Result<&mut port::Disk, ErrorPayload> {
+    usize::try_from(device)
+        .ok()
+        .and_then(move |index| disks.get_mut(index))
+        .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))
+}
+
+/// Checks that the given range of sectors is within the disk.
+fn check_range(disk: &port::Disk, first_sector: u64, num_sectors: u64) -> Result<(), ErrorPayload> {
+    match first_sector.checked_add(num_sectors) {
+        Some(end) if end <= disk.num_sectors() => Ok(()),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("range of sectors out of the disk")),
+    }
+}
+
+fn io_error() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO)
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
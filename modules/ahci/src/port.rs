@@ -0,0 +1,312 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Ports of an AHCI controller, and the SATA disks attached to them.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+/// Size of a sector. Disks with larger logical sectors aren't supported.
+pub const SECTOR_SIZE: u32 = 512;
+/// Maximum number of sectors transferred by a single command.
+pub const MAX_SECTORS_PER_COMMAND: u16 = 128;
+
+// Offsets of the registers of a port, relative to the registers of that port.
+const PX_CLB: u64 = 0x00;
+const PX_FB: u64 = 0x08;
+const PX_IS: u64 = 0x10;
+const PX_CMD: u64 = 0x18;
+const PX_TFD: u64 = 0x20;
+const PX_SIG: u64 = 0x24;
+const PX_SSTS: u64 = 0x28;
+const PX_SERR: u64 = 0x30;
+const PX_CI: u64 = 0x38;
+
+const PX_CMD_ST: u32 = 1 << 0;
+const PX_CMD_FRE: u32 = 1 << 4;
+const PX_CMD_FR: u32 = 1 << 14;
+const PX_CMD_CR: u32 = 1 << 15;
+
+/// Bit of `PxIS` indicating a task file error.
+const PX_IS_TFES: u32 = 1 << 30;
+
+/// Time after which a command that hasn't completed is aborted, in nanoseconds.
+const COMMAND_TIMEOUT_NS: u128 = 5_000_000_000;
+/// Time after which the command engine is considered stuck when starting or stopping it, in
+/// nanoseconds. The specification allows up to 500ms.
+const ENGINE_TIMEOUT_NS: u128 = 500_000_000;
+
+/// Signature of a port to which a SATA disk is attached.
+const SATA_SIGNATURE: u32 = 0x0000_0101;
+
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xea;
+const ATA_CMD_IDENTIFY: u8 = 0xec;
+
+/// SATA disk attached to a port of the controller.
+pub struct Disk {
+    /// Physical address of the registers of the port.
+    registers: u64,
+    /// Physical address of the command list. We only ever use the first slot.
+    command_list: u64,
+    /// Physical address of the command table of the first slot.
+    command_table: u64,
+    /// Physical address of the buffer used for data transfers.
+    data_buffer: u64,
+    /// Total number of sectors of the disk.
+    num_sectors: u64,
+}
+
+impl Disk {
+    /// Initializes the port whose registers are at the given physical address.
+    ///
+    /// Returns `None` if no SATA disk is attached to this port or if the disk doesn't respond.
+    pub async unsafe fn init(registers: u64) -> Option<Disk> {
+        // The `DET` field must indicate that a device is present and that communication is
+        // established, and `IPM` that the device is active.
+        let ssts = read_u32(registers + PX_SSTS).await;
+        if ssts & 0xf != 3 || (ssts >> 8) & 0xf != 1 {
+            return None;
+        }
+        if read_u32(registers + PX_SIG).await != SATA_SIGNATURE {
+            return None;
+        }
+
+        // Stop the command engine before modifying the addresses of the structures.
+        let cmd = read_u32(registers + PX_CMD).await;
+        redshirt_hardware_interface::write_one_u32(
+            registers + PX_CMD,
+            cmd & !(PX_CMD_ST | PX_CMD_FRE),
+        );
+        wait_clear(registers + PX_CMD, PX_CMD_CR | PX_CMD_FR, ENGINE_TIMEOUT_NS)
+            .await
+            .ok()?;
+
+        // The command list contains 32 headers of 32 bytes, and the received FIS area is 256
+        // bytes. The command table contains the command FIS followed, at offset 0x80, by a single
+        // physical region descriptor.
+        let command_list = malloc_aligned(1024, 1024).await;
+        let received_fis = malloc_aligned(256, 256).await;
+        let command_table = redshirt_hardware_interface::malloc::malloc(0x80 + 16, 128).await;
+        let data_buffer = redshirt_hardware_interface::malloc::malloc(
+            u64::from(MAX_SECTORS_PER_COMMAND) * u64::from(SECTOR_SIZE),
+            2,
+        )
+        .await;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(command_list, vec![0; 1024]);
+        ops.write(received_fis, vec![0; 256]);
+        // The first command header always points to our command table.
+        ops.write_one_u32(command_list + 8, command_table as u32);
+        ops.write_one_u32(command_list + 12, (command_table >> 32) as u32);
+        ops.write_one_u32(registers + PX_CLB, command_list as u32);
+        ops.write_one_u32(registers + PX_CLB + 4, (command_list >> 32) as u32);
+        ops.write_one_u32(registers + PX_FB, received_fis as u32);
+        ops.write_one_u32(registers + PX_FB + 4, (received_fis >> 32) as u32);
+        // Clear the pending errors and interrupts.
+        ops.write_one_u32(registers + PX_SERR, 0xffff_ffff);
+        ops.write_one_u32(registers + PX_IS, 0xffff_ffff);
+        ops.write_one_u32(registers + PX_CMD, (cmd & !PX_CMD_ST) | PX_CMD_FRE);
+        ops.write_one_u32(registers + PX_CMD, cmd | PX_CMD_FRE | PX_CMD_ST);
+        ops.send();
+
+        let mut disk = Disk {
+            registers,
+            command_list,
+            command_table,
+            data_buffer,
+            num_sectors: 0,
+        };
+
+        disk.command(ATA_CMD_IDENTIFY, 0, 0, SECTOR_SIZE, false)
+            .await
+            .ok()?;
+        let identify = disk.read_data_buffer(SECTOR_SIZE).await;
+        // Words 100 to 103 contain the number of sectors addressable with 48 bits LBA.
+        let mut num_sectors = [0; 8];
+        num_sectors.copy_from_slice(&identify[200..208]);
+        disk.num_sectors = u64::from_le_bytes(num_sectors);
+        if disk.num_sectors == 0 {
+            return None;
+        }
+
+        Some(disk)
+    }
+
+    /// Returns the total number of sectors of the disk.
+    pub fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+
+    /// Reads `num_sectors` sectors starting at `first_sector`.
+    ///
+    /// The range must be within the disk, and `num_sectors` no larger than
+    /// [`MAX_SECTORS_PER_COMMAND`].
+    pub async unsafe fn read(
+        &mut self,
+        first_sector: u64,
+        num_sectors: u16,
+    ) -> Result<Vec<u8>, ()> {
+        debug_assert!(num_sectors <= MAX_SECTORS_PER_COMMAND);
+        let len = u32::from(num_sectors) * SECTOR_SIZE;
+        self.command(ATA_CMD_READ_DMA_EXT, first_sector, num_sectors, len, false)
+            .await?;
+        Ok(self.read_data_buffer(len).await)
+    }
+
+    /// Writes `data` starting at `first_sector`.
+    ///
+    /// The length of `data` must be a multiple of the sector size, the range must be within the
+    /// disk, and the number of sectors no larger than [`MAX_SECTORS_PER_COMMAND`].
+    pub async unsafe fn write(&mut self, first_sector: u64, data: Vec<u8>) -> Result<(), ()> {
+        debug_assert_eq!(data.len() % SECTOR_SIZE as usize, 0);
+        let len = data.len() as u32;
+        let num_sectors = (len / SECTOR_SIZE) as u16;
+        debug_assert!(num_sectors <= MAX_SECTORS_PER_COMMAND);
+
+        redshirt_hardware_interface::write(self.data_buffer, data);
+        self.command(ATA_CMD_WRITE_DMA_EXT, first_sector, num_sectors, len, true)
+            .await
+    }
+
+    /// Flushes the write cache of the disk.
+    pub async unsafe fn flush(&mut self) -> Result<(), ()> {
+        self.command(ATA_CMD_FLUSH_CACHE_EXT, 0, 0, 0, false).await
+    }
+
+    /// Issues an ATA command in the first command slot and waits for its completion.
+    ///
+    /// `data_len` bytes are transferred between the disk and the data buffer. The completion is
+    /// detected by polling the registers of the port. If the command hasn't completed after
+    /// [`COMMAND_TIMEOUT_NS`], it is aborted and an error is returned.
+    // TODO: wait for the interrupt of the port instead of polling
+    async unsafe fn command(
+        &mut self,
+        command: u8,
+        lba: u64,
+        num_sectors: u16,
+        data_len: u32,
+        write: bool,
+    ) -> Result<(), ()> {
+        // Host to device register FIS.
+        let lba = lba.to_le_bytes();
+        let count = num_sectors.to_le_bytes();
+        let mut fis = [0u8; 20];
+        fis[0] = 0x27;
+        fis[1] = 0x80;
+        fis[2] = command;
+        fis[4..7].copy_from_slice(&lba[0..3]);
+        fis[7] = 1 << 6;
+        fis[8..11].copy_from_slice(&lba[3..6]);
+        fis[12..14].copy_from_slice(&count);
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(self.command_table, fis.to_vec());
+
+        // Command header: length of the FIS in dwords, direction, and number of physical region
+        // descriptors.
+        let num_prds = if data_len != 0 { 1 } else { 0 };
+        let mut header_dw0 = 5 | (num_prds << 16);
+        if write {
+            header_dw0 |= 1 << 6;
+        }
+        ops.write_one_u32(self.command_list, header_dw0);
+        ops.write_one_u32(self.command_list + 4, 0);
+
+        if data_len != 0 {
+            let prd = self.command_table + 0x80;
+            ops.write_one_u32(prd, self.data_buffer as u32);
+            ops.write_one_u32(prd + 4, (self.data_buffer >> 32) as u32);
+            ops.write_one_u32(prd + 8, 0);
+            ops.write_one_u32(prd + 12, data_len - 1);
+        }
+
+        ops.write_one_u32(self.registers + PX_IS, 0xffff_ffff);
+        ops.write_one_u32(self.registers + PX_CI, 1);
+        ops.send();
+
+        let start = redshirt_time_interface::monotonic_clock().await;
+        loop {
+            if read_u32(self.registers + PX_IS).await & PX_IS_TFES != 0 {
+                return Err(());
+            }
+            if read_u32(self.registers + PX_CI).await & 1 == 0 {
+                break;
+            }
+            if redshirt_time_interface::monotonic_clock().await - start > COMMAND_TIMEOUT_NS {
+                self.abort().await;
+                return Err(());
+            }
+        }
+
+        // Bit 0 of the task file data is the error bit of the status register.
+        if read_u32(self.registers + PX_TFD).await & 0x1 != 0 {
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    /// Aborts the command in progress by restarting the command engine of the port.
+    async unsafe fn abort(&mut self) {
+        let cmd = read_u32(self.registers + PX_CMD).await;
+        redshirt_hardware_interface::write_one_u32(self.registers + PX_CMD, cmd & !PX_CMD_ST);
+        // If the engine doesn't stop, the next commands will fail or time out as well.
+        let _ = wait_clear(self.registers + PX_CMD, PX_CMD_CR, ENGINE_TIMEOUT_NS).await;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u32(self.registers + PX_SERR, 0xffff_ffff);
+        ops.write_one_u32(self.registers + PX_IS, 0xffff_ffff);
+        ops.write_one_u32(self.registers + PX_CMD, cmd | PX_CMD_ST);
+        ops.send();
+    }
+
+    async unsafe fn read_data_buffer(&self, len: u32) -> Vec<u8> {
+        let mut out = vec![0; len as usize];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.data_buffer, &mut out);
+        ops.send().await;
+        out
+    }
+}
+
+pub async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
+
+/// Waits for the given bits of the register at `address` to be all cleared. Returns an error if
+/// they are still set after `timeout` nanoseconds.
+async unsafe fn wait_clear(address: u64, bits: u32, timeout: u128) -> Result<(), ()> {
+    let start = redshirt_time_interface::monotonic_clock().await;
+    while read_u32(address).await & bits != 0 {
+        if redshirt_time_interface::monotonic_clock().await - start > timeout {
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+/// Allocates physical memory aligned to `alignment`, which can be larger than the maximum
+/// alignment supported by the hardware interface.
+// TODO: the padding is wasted
+async fn malloc_aligned(size: u64, alignment: u64) -> u64 {
+    let ptr = redshirt_hardware_interface::malloc::malloc(size + alignment, 128).await;
+    (ptr + alignment - 1) / alignment * alignment
+}
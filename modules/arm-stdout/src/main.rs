@@ -32,6 +32,8 @@ async fn async_main() -> ! {
         let msg = match redshirt_syscalls_interface::next_interface_message().await {
             redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
             redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
         };
         assert_eq!(msg.interface, redshirt_stdout_interface::ffi::INTERFACE);
 
@@ -0,0 +1,292 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Software audio mixer.
+//!
+//! Implements the audio interface on top of the audio device interface registered by the
+//! driver of the audio device. A single stream is opened on the device. The streams opened by
+//! programs are converted to its format, resampled to its sample rate, and mixed together, so
+//! that multiple programs can play sound at the same time.
+
+mod stream;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_audio_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId};
+use std::pin::Pin;
+
+/// Sample rates to try when opening the stream of the device, by order of preference.
+const DEVICE_RATES: [u32; 2] = [48000, 44100];
+/// Maximum number of frames mixed and sent to the device at once.
+const CHUNK_FRAMES: usize = 480;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut device = None;
+    for rate in &DEVICE_RATES {
+        if let Ok(d) = Device::open(*rate).await {
+            device = Some(d);
+            break;
+        }
+    }
+    let device = match device {
+        Some(d) => d,
+        None => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut streams = Vec::<stream::ClientStream>::new();
+    let mut next_stream_id: u64 = 0;
+    // Write to the device currently in progress. Only one is sent at a time, so that the
+    // device regulates the rate at which we mix.
+    let mut device_write: Option<Pin<Box<dyn Future<Output = ffi::WriteResponse>>>> = None;
+
+    loop {
+        if device_write.is_none() {
+            device_write = mix(&device, &mut streams);
+        }
+
+        let event = if let Some(write) = device_write.as_mut() {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            match future::select(next_message, write).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right(_) => None,
+            }
+        } else {
+            Some(redshirt_syscalls_interface::next_interface_message().await)
+        };
+
+        let msg = match event {
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m)) => m,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                for stream in drain_filter(&mut streams, |s| s.owner == msg.pid) {
+                    stream.close();
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                for stream in &mut streams {
+                    stream.cancel_write(msg.message_id);
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_)) => continue,
+            None => {
+                // The previous write to the device has finished. Errors are ignored, as there
+                // isn't much we can do about them.
+                device_write = None;
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::AudioMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        match message {
+            ffi::AudioMessage::OpenStream(format) => {
+                let result = if format.sample_rate == 0 || format.channels == 0 {
+                    Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)
+                        .with_message("unsupported stream format"))
+                } else {
+                    let id = next_stream_id;
+                    next_stream_id = next_stream_id.wrapping_add(1);
+                    streams.push(stream::ClientStream::new(
+                        id,
+                        msg.emitter_pid,
+                        format,
+                        device.rate,
+                    ));
+                    Ok(id)
+                };
+                answer(msg.message_id, ffi::OpenStreamResponse { result });
+            }
+            ffi::AudioMessage::Write(write) => {
+                let stream = match streams
+                    .iter_mut()
+                    .find(|s| s.id == write.stream && s.owner == msg.emitter_pid)
+                {
+                    Some(s) => s,
+                    None => {
+                        let result = Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+                        answer(msg.message_id, ffi::WriteResponse { result });
+                        continue;
+                    }
+                };
+
+                if write.samples.len() % stream.frame_len() != 0 {
+                    let result = Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("length isn't a multiple of the frame size"));
+                    answer(msg.message_id, ffi::WriteResponse { result });
+                    continue;
+                }
+
+                stream.push(msg.message_id, &write.samples);
+            }
+            ffi::AudioMessage::Latency(id) => {
+                let buffered = match streams
+                    .iter()
+                    .find(|s| s.id == id && s.owner == msg.emitter_pid)
+                {
+                    Some(s) => s.buffered_duration(device.rate),
+                    None => {
+                        let result = Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+                        answer(msg.message_id, ffi::LatencyResponse { result });
+                        continue;
+                    }
+                };
+
+                let result = device
+                    .latency()
+                    .await
+                    .map(|latency| latency.saturating_add(buffered));
+                answer(msg.message_id, ffi::LatencyResponse { result });
+            }
+            ffi::AudioMessage::SetVolume(set_volume) => {
+                if let Some(stream) = streams
+                    .iter_mut()
+                    .find(|s| s.id == set_volume.stream && s.owner == msg.emitter_pid)
+                {
+                    stream.set_volume(set_volume.volume);
+                }
+            }
+            ffi::AudioMessage::CloseStream(id) => {
+                for stream in
+                    drain_filter(&mut streams, |s| s.id == id && s.owner == msg.emitter_pid)
+                {
+                    stream.close();
+                }
+            }
+        }
+    }
+}
+
+/// Stream opened on the audio device.
+struct Device {
+    id: u64,
+    /// Sample rate of the stream. Always stereo, with 16 bits samples.
+    rate: u32,
+}
+
+impl Device {
+    async fn open(rate: u32) -> Result<Device, ErrorPayload> {
+        let msg = ffi::AudioMessage::OpenStream(ffi::StreamFormat {
+            sample_rate: rate,
+            channels: 2,
+            sample_format: ffi::SampleFormat::S16Le,
+        });
+        let response: ffi::OpenStreamResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::DEVICE_INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+
+        Ok(Device {
+            id: response.result?,
+            rate,
+        })
+    }
+
+    fn write(&self, samples: Vec<u8>) -> impl Future<Output = ffi::WriteResponse> {
+        unsafe {
+            let msg = ffi::AudioMessage::Write(ffi::Write {
+                stream: self.id,
+                samples,
+            });
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::DEVICE_INTERFACE, msg)
+                .unwrap()
+        }
+    }
+
+    async fn latency(&self) -> Result<u64, ErrorPayload> {
+        let msg = ffi::AudioMessage::Latency(self.id);
+        let response: ffi::LatencyResponse = unsafe {
+            redshirt_syscalls_interface::emit_message_with_response(&ffi::DEVICE_INTERFACE, msg)
+                .unwrap()
+                .await
+        };
+        response.result
+    }
+}
+
+/// Mixes the frames buffered in the streams and sends them to the device. Returns `None` if
+/// no stream has any frame buffered.
+fn mix(
+    device: &Device,
+    streams: &mut [stream::ClientStream],
+) -> Option<Pin<Box<dyn Future<Output = ffi::WriteResponse>>>> {
+    let num_frames = streams
+        .iter()
+        .map(|s| s.buffered_frames())
+        .max()
+        .unwrap_or(0)
+        .min(CHUNK_FRAMES);
+    if num_frames == 0 {
+        return None;
+    }
+
+    let mut mixed = vec![[0.0f32; 2]; num_frames];
+    for stream in streams {
+        stream.mix_into(&mut mixed);
+    }
+
+    let samples = mixed
+        .iter()
+        .flat_map(|frame| frame.iter())
+        .flat_map(|sample| {
+            let value = (sample.max(-1.0).min(1.0) * f32::from(i16::max_value())) as i16;
+            value.to_le_bytes().to_vec()
+        })
+        .collect::<Vec<_>>();
+    debug_assert_eq!(samples.len(), num_frames * 4);
+
+    Some(Box::pin(device.write(samples)))
+}
+
+/// Removes from `streams` the streams that match `filter` and returns them.
+fn drain_filter(
+    streams: &mut Vec<stream::ClientStream>,
+    mut filter: impl FnMut(&stream::ClientStream) -> bool,
+) -> Vec<stream::ClientStream> {
+    let mut removed = Vec::new();
+    let mut n = 0;
+    while n < streams.len() {
+        if filter(&streams[n]) {
+            removed.push(streams.remove(n));
+        } else {
+            n += 1;
+        }
+    }
+    removed
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
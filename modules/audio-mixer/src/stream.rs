@@ -0,0 +1,204 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Streams opened by programs.
+
+use crate::answer;
+
+use redshirt_audio_interface::ffi;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId, Pid};
+use std::collections::VecDeque;
+
+/// Maximum number of frames, at the sample rate of the device, that can be buffered for each
+/// stream before writes stop being answered.
+const MAX_BUFFERED_FRAMES: u64 = 4800;
+
+/// Stream opened by a program.
+pub struct ClientStream {
+    pub id: u64,
+    /// Process that has opened the stream.
+    pub owner: Pid,
+    format: ffi::StreamFormat,
+    /// Factor applied to the samples, between 0.0 and 1.0.
+    volume: f32,
+    resampler: Resampler,
+    /// Stereo frames, at the sample rate of the device, waiting to be mixed.
+    buffer: VecDeque<[f32; 2]>,
+    /// Total number of frames pushed to `buffer` since the stream has been opened.
+    queued: u64,
+    /// Total number of frames removed from `buffer` since the stream has been opened.
+    consumed: u64,
+    /// Writes that haven't been answered yet, and the value of `queued` after their samples
+    /// have been pushed.
+    waiting: VecDeque<(Option<MessageId>, u64)>,
+}
+
+impl ClientStream {
+    pub fn new(id: u64, owner: Pid, format: ffi::StreamFormat, device_rate: u32) -> Self {
+        ClientStream {
+            id,
+            owner,
+            resampler: Resampler::new(format.sample_rate, device_rate),
+            format,
+            volume: 1.0,
+            buffer: VecDeque::new(),
+            queued: 0,
+            consumed: 0,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of bytes of a frame of the stream.
+    pub fn frame_len(&self) -> usize {
+        usize::from(self.format.channels) * self.format.sample_format.bytes_per_sample() as usize
+    }
+
+    /// Sets the volume, between 0 and 100, of the samples pushed afterwards.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = f32::from(volume.min(100)) / 100.0;
+    }
+
+    /// Returns the number of frames waiting to be mixed.
+    pub fn buffered_frames(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Converts the samples to the format of the device and queues them. The write is answered
+    /// once the buffer of the stream has enough space.
+    ///
+    /// The length of `samples` must be a multiple of [`ClientStream::frame_len`].
+    pub fn push(&mut self, message_id: Option<MessageId>, samples: &[u8]) {
+        debug_assert_eq!(samples.len() % self.frame_len(), 0);
+
+        let bytes_per_sample = self.format.sample_format.bytes_per_sample() as usize;
+        for frame in samples.chunks(self.frame_len()) {
+            let left = decode_sample(self.format.sample_format, &frame[..bytes_per_sample]);
+            // Mono streams are played on both channels. Channels other than the first two are
+            // ignored.
+            let right = if self.format.channels >= 2 {
+                decode_sample(
+                    self.format.sample_format,
+                    &frame[bytes_per_sample..2 * bytes_per_sample],
+                )
+            } else {
+                left
+            };
+
+            let frame = [left * self.volume, right * self.volume];
+            let buffer = &mut self.buffer;
+            let queued = &mut self.queued;
+            self.resampler.push(frame, |out| {
+                buffer.push_back(out);
+                *queued += 1;
+            });
+        }
+
+        self.waiting.push_back((message_id, self.queued));
+        self.answer_writes();
+    }
+
+    /// Adds the frames at the front of the buffer to `out`. If fewer frames than the length of
+    /// `out` are buffered, the rest is left untouched.
+    pub fn mix_into(&mut self, out: &mut [[f32; 2]]) {
+        for out in out.iter_mut() {
+            let frame = match self.buffer.pop_front() {
+                Some(f) => f,
+                None => break,
+            };
+            out[0] += frame[0];
+            out[1] += frame[1];
+            self.consumed += 1;
+        }
+
+        self.answer_writes();
+    }
+
+    /// Returns the number of nanoseconds that it takes to play the buffered frames.
+    pub fn buffered_duration(&self, device_rate: u32) -> u64 {
+        self.buffer.len() as u64 * 1_000_000_000 / u64::from(device_rate)
+    }
+
+    /// Stops waiting for the given write, if it belongs to this stream.
+    pub fn cancel_write(&mut self, message_id: MessageId) {
+        self.waiting.retain(|(id, _)| *id != Some(message_id));
+    }
+
+    /// Answers the writes that are still waiting with an error.
+    pub fn close(self) {
+        for (message_id, _) in self.waiting {
+            let result = Err(
+                ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("stream has been closed")
+            );
+            answer(message_id, ffi::WriteResponse { result });
+        }
+    }
+
+    /// Answers the writes whose samples fit within the maximum buffer size.
+    fn answer_writes(&mut self) {
+        while let Some((message_id, end)) = self.waiting.front() {
+            if *end > self.consumed + MAX_BUFFERED_FRAMES {
+                break;
+            }
+
+            answer(*message_id, ffi::WriteResponse { result: Ok(()) });
+            self.waiting.pop_front();
+        }
+    }
+}
+
+/// Converts a stream of frames from one sample rate to another using linear interpolation.
+struct Resampler {
+    /// Number of input frames per output frame.
+    step: f64,
+    /// Position of the next output frame, between the previous input frame (`0.0`) and the
+    /// next one (`1.0`).
+    phase: f64,
+    /// Last input frame.
+    previous: [f32; 2],
+}
+
+impl Resampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        Resampler {
+            step: f64::from(input_rate) / f64::from(output_rate),
+            phase: 0.0,
+            previous: [0.0; 2],
+        }
+    }
+
+    /// Processes one input frame, and calls `out` with each output frame produced.
+    fn push(&mut self, frame: [f32; 2], mut out: impl FnMut([f32; 2])) {
+        while self.phase < 1.0 {
+            let phase = self.phase as f32;
+            out([
+                self.previous[0] + (frame[0] - self.previous[0]) * phase,
+                self.previous[1] + (frame[1] - self.previous[1]) * phase,
+            ]);
+            self.phase += self.step;
+        }
+
+        self.phase -= 1.0;
+        self.previous = frame;
+    }
+}
+
+/// Decodes one sample to a value between -1.0 and 1.0.
+fn decode_sample(format: ffi::SampleFormat, bytes: &[u8]) -> f32 {
+    match format {
+        ffi::SampleFormat::S16Le => f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+        ffi::SampleFormat::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            .max(-1.0)
+            .min(1.0),
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the device-manager interface.
+//!
+//! Keeps the list of devices reported by the drivers of buses, and which driver each device is
+//! assigned to. Every change is sent to the subscribers.
+
+use parity_scale_codec::DecodeAll;
+use redshirt_device_manager_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::collections::HashMap;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut devices = Devices::default();
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                devices.process_destroyed(msg.pid);
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg) => {
+                devices.subscribers.retain(|(_, id)| *id != msg.message_id);
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::DeviceManagerMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        match message {
+            ffi::DeviceManagerMessage::Report(description) => {
+                devices.report(msg.emitter_pid, description);
+            }
+            ffi::DeviceManagerMessage::Unreport { bus, location } => {
+                devices.unreport(msg.emitter_pid, bus, &location);
+            }
+            ffi::DeviceManagerMessage::List => {
+                let mut list = devices
+                    .devices
+                    .values()
+                    .map(|d| d.device.clone())
+                    .collect::<Vec<_>>();
+                list.sort_by_key(|d| d.id);
+                answer(msg.message_id, ffi::ListResponse { devices: list });
+            }
+            ffi::DeviceManagerMessage::Subscribe => {
+                let message_id = match msg.message_id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                devices.subscribe(msg.emitter_pid, message_id);
+            }
+            ffi::DeviceManagerMessage::Claim { id, driver } => {
+                let result = devices.claim(msg.emitter_pid, id, driver);
+                answer(msg.message_id, ffi::ClaimResponse { result });
+            }
+            ffi::DeviceManagerMessage::Release(id) => {
+                devices.release(msg.emitter_pid, id);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Devices {
+    /// List of devices currently present, by identifier.
+    devices: HashMap<u64, DeviceEntry>,
+    /// Identifier to assign to the next device.
+    next_id: u64,
+    /// Processes watching for changes, and the message to answer.
+    subscribers: Vec<(Pid, MessageId)>,
+}
+
+struct DeviceEntry {
+    device: ffi::Device,
+    /// Process that has reported the device.
+    reporter: Pid,
+    /// Process that has claimed the device, if any.
+    driver: Option<Pid>,
+}
+
+impl Devices {
+    fn report(&mut self, reporter: Pid, description: ffi::DeviceDescription) {
+        // A device reported twice replaces the previous one, as it might have been swapped.
+        self.unreport(reporter, description.bus, &description.location);
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let device = ffi::Device {
+            id,
+            description,
+            driver: None,
+        };
+        self.notify(&ffi::DeviceEvent::Added(device.clone()));
+        self.devices.insert(
+            id,
+            DeviceEntry {
+                device,
+                reporter,
+                driver: None,
+            },
+        );
+    }
+
+    fn unreport(&mut self, reporter: Pid, bus: ffi::Bus, location: &str) {
+        let id = self.devices.values().find_map(|entry| {
+            if entry.reporter == reporter
+                && entry.device.description.bus == bus
+                && entry.device.description.location == location
+            {
+                Some(entry.device.id)
+            } else {
+                None
+            }
+        });
+
+        if let Some(id) = id {
+            let entry = self.devices.remove(&id).unwrap();
+            self.notify(&ffi::DeviceEvent::Removed(entry.device));
+        }
+    }
+
+    fn subscribe(&mut self, pid: Pid, message_id: MessageId) {
+        let mut existing = self.devices.values().map(|e| &e.device).collect::<Vec<_>>();
+        existing.sort_by_key(|d| d.id);
+        for device in existing {
+            redshirt_syscalls_interface::emit_answer_partial(
+                message_id,
+                ffi::DeviceEvent::Added(device.clone()),
+            );
+        }
+
+        self.subscribers.push((pid, message_id));
+    }
+
+    fn claim(&mut self, pid: Pid, id: u64, driver: String) -> Result<(), ErrorPayload> {
+        let entry = match self.devices.get_mut(&id) {
+            Some(e) => e,
+            None => return Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+        };
+
+        match entry.driver {
+            Some(p) if p == pid => return Ok(()),
+            Some(_) => {
+                return Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                    .with_message("device already claimed by another driver"))
+            }
+            None => {}
+        }
+
+        entry.driver = Some(pid);
+        entry.device.driver = Some(driver);
+        let event = ffi::DeviceEvent::DriverChanged(entry.device.clone());
+        self.notify(&event);
+        Ok(())
+    }
+
+    fn release(&mut self, pid: Pid, id: u64) {
+        let entry = match self.devices.get_mut(&id) {
+            Some(e) if e.driver == Some(pid) => e,
+            _ => return,
+        };
+
+        entry.driver = None;
+        entry.device.driver = None;
+        let event = ffi::DeviceEvent::DriverChanged(entry.device.clone());
+        self.notify(&event);
+    }
+
+    fn process_destroyed(&mut self, pid: Pid) {
+        self.subscribers.retain(|(p, _)| *p != pid);
+
+        let mut reported = self
+            .devices
+            .values()
+            .filter(|e| e.reporter == pid)
+            .map(|e| e.device.id)
+            .collect::<Vec<_>>();
+        reported.sort();
+        for id in reported {
+            let entry = self.devices.remove(&id).unwrap();
+            self.notify(&ffi::DeviceEvent::Removed(entry.device));
+        }
+
+        let mut claimed = self
+            .devices
+            .values()
+            .filter(|e| e.driver == Some(pid))
+            .map(|e| e.device.id)
+            .collect::<Vec<_>>();
+        claimed.sort();
+        for id in claimed {
+            self.release(pid, id);
+        }
+    }
+
+    /// Sends an event to all the subscribers.
+    fn notify(&self, event: &ffi::DeviceEvent) {
+        for (_, message_id) in &self.subscribers {
+            redshirt_syscalls_interface::emit_answer_partial(*message_id, event);
+        }
+    }
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DNS resolver.
+//!
+//! Registers the DNS interface and resolves names by sending queries to the configured DNS
+//! servers through the UDP interface. Results are cached for the duration indicated by the
+//! servers.
+//!
+//! Queries for the same name and record type are merged. A query that isn't answered is sent
+//! again to the next server after [`RETRY_PERIOD`], up to [`MAX_ATTEMPTS`] times.
+
+mod packet;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_dns_interface::{ffi, Record, RecordType};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId};
+use std::{
+    collections::HashMap,
+    convert::TryFrom as _,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+};
+
+/// Servers used until a [`ffi::DnsMessage::SetServers`] message is received. This is the DNS
+/// server of the user-mode network stack of QEMU.
+const DEFAULT_SERVERS: &[&str] = &["10.0.2.3:53"];
+
+/// Time after which a query that hasn't been answered is sent again, in nanoseconds.
+const RETRY_PERIOD: u128 = 2_000_000_000;
+
+/// Number of times a query is sent before reporting a timeout.
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Maximum number of entries in the cache. The cache is cleared when this limit is reached.
+const MAX_CACHE_ENTRIES: usize = 1024;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let socket = match redshirt_udp_interface::UdpSocket::bind(&"0.0.0.0:0".parse().unwrap()).await
+    {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let mut datagrams = socket.incoming();
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut resolver = Resolver {
+        servers: DEFAULT_SERVERS.iter().map(|s| s.parse().unwrap()).collect(),
+        cache: HashMap::new(),
+        queries: HashMap::new(),
+    };
+    // Timer used to retry queries. `None` if no query is in progress.
+    let mut retry_timer: Option<redshirt_time_interface::Interval> = None;
+
+    loop {
+        let event = {
+            let next_message =
+                redshirt_syscalls_interface::next_interface_message().map(Event::Message);
+            let next_datagram = datagrams.next().map(Event::Datagram);
+            let next_tick = match retry_timer.as_mut() {
+                Some(timer) => timer.next().map(|_| Event::Tick).left_future(),
+                None => future::pending().right_future(),
+            };
+            future::select(
+                future::select(next_message, next_datagram).map(|ev| ev.factor_first().0),
+                next_tick,
+            )
+            .await
+            .factor_first()
+            .0
+        };
+
+        let now = redshirt_time_interface::monotonic_clock().await;
+
+        match event {
+            Event::Message(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(msg)) => {
+                assert_eq!(msg.interface, ffi::INTERFACE);
+                match DecodeAll::decode_all(&msg.actual_data) {
+                    Ok(ffi::DnsMessage::Resolve(request)) => {
+                        if let Some(message_id) = msg.message_id {
+                            resolver.resolve(&socket, now, message_id, request).await;
+                        }
+                    }
+                    Ok(ffi::DnsMessage::SetServers(servers)) => {
+                        // TODO: there is no privilege system, and any process can change the servers
+                        resolver.servers = servers
+                            .into_iter()
+                            .map(|s| SocketAddr::new(IpAddr::V6(Ipv6Addr::from(s.ip)), s.port))
+                            .collect();
+                    }
+                    Err(_) => {
+                        if let Some(message_id) = msg.message_id {
+                            redshirt_syscalls_interface::emit_message_error(message_id);
+                        }
+                    }
+                }
+            }
+            Event::Message(
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg),
+            ) => {
+                for query in resolver.queries.values_mut() {
+                    query.waiters.retain(|m| *m != msg.message_id);
+                }
+            }
+            Event::Message(_) => {}
+            Event::Datagram(Some((_, data))) => resolver.on_response(now, &data),
+            // The UDP subscription has been interrupted.
+            Event::Datagram(None) => datagrams = socket.incoming(),
+            Event::Tick => resolver.retry(&socket, now).await,
+        }
+
+        if resolver.queries.is_empty() {
+            retry_timer = None;
+        } else if retry_timer.is_none() {
+            retry_timer = Some(redshirt_time_interface::monotonic_interval(
+                now + RETRY_PERIOD / 2,
+                RETRY_PERIOD / 2,
+            ));
+        }
+    }
+}
+
+enum Event {
+    Message(redshirt_syscalls_interface::InterfaceOrDestroyed),
+    Datagram(Option<(SocketAddr, Vec<u8>)>),
+    Tick,
+}
+
+struct Resolver {
+    /// Servers to send queries to.
+    servers: Vec<SocketAddr>,
+    /// Cached answers, and the value of the monotonic clock when they expire.
+    cache: HashMap<(String, RecordType), (Vec<Record>, u128)>,
+    /// Queries in progress, by identifier.
+    queries: HashMap<u16, Query>,
+}
+
+struct Query {
+    name: String,
+    record_type: RecordType,
+    /// Encoded query, to send again in case of timeout.
+    packet: Vec<u8>,
+    /// Messages to answer when the response arrives.
+    waiters: Vec<MessageId>,
+    /// Number of times the query has been sent.
+    attempts: u32,
+    /// Value of the monotonic clock when the query was last sent.
+    last_sent: u128,
+}
+
+impl Resolver {
+    /// Handles a [`ffi::DnsMessage::Resolve`] message.
+    async fn resolve(
+        &mut self,
+        socket: &redshirt_udp_interface::UdpSocket,
+        now: u128,
+        message_id: MessageId,
+        request: ffi::Resolve,
+    ) {
+        let name = request.name.trim_end_matches('.').to_ascii_lowercase();
+        let key = (name, request.record_type);
+
+        if let Some((records, expires)) = self.cache.get(&key) {
+            if *expires > now {
+                let response = ffi::ResolveResponse {
+                    result: Ok(records.clone()),
+                };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+                return;
+            }
+            self.cache.remove(&key);
+        }
+
+        if let Some(query) = self
+            .queries
+            .values_mut()
+            .find(|q| q.name == key.0 && q.record_type == key.1)
+        {
+            query.waiters.push(message_id);
+            return;
+        }
+
+        if self.servers.is_empty() {
+            let response = ffi::ResolveResponse {
+                result: Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                    .with_message("no DNS server configured")),
+            };
+            redshirt_syscalls_interface::emit_answer(message_id, &response);
+            return;
+        }
+
+        // Query identifiers are random in order to make spoofing responses harder.
+        let id = loop {
+            let mut id = [0; 2];
+            redshirt_random_interface::generate_in(&mut id).await;
+            let id = u16::from_ne_bytes(id);
+            if !self.queries.contains_key(&id) {
+                break id;
+            }
+        };
+
+        let packet = match packet::build_query(id, &key.0, key.1) {
+            Ok(p) => p,
+            Err(()) => {
+                let response = ffi::ResolveResponse {
+                    result: Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("invalid domain name")),
+                };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+                return;
+            }
+        };
+
+        // Errors are handled the same way as a lost datagram.
+        let _ = socket.send_to(packet.clone(), &self.servers[0]).await;
+
+        self.queries.insert(
+            id,
+            Query {
+                name: key.0,
+                record_type: key.1,
+                packet,
+                waiters: vec![message_id],
+                attempts: 1,
+                last_sent: now,
+            },
+        );
+    }
+
+    /// Handles a datagram received from a server.
+    fn on_response(&mut self, now: u128, data: &[u8]) {
+        let response = match packet::parse_response(data) {
+            Ok(r) => r,
+            Err(()) => return,
+        };
+
+        // TODO: check that the datagram comes from the server the query was sent to
+        let query = match self.queries.remove(&response.id) {
+            Some(q) => q,
+            None => return,
+        };
+
+        // TODO: retry over TCP if the response is truncated
+        let result = match response.rcode {
+            0 => {
+                let ttl = response.answers.iter().map(|r| r.ttl).min().unwrap_or(0);
+                if self.cache.len() >= MAX_CACHE_ENTRIES {
+                    self.cache.clear();
+                }
+                self.cache.insert(
+                    (query.name, query.record_type),
+                    (
+                        response.answers.clone(),
+                        now + u128::from(ttl) * 1_000_000_000,
+                    ),
+                );
+                Ok(response.answers)
+            }
+            3 => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+            rcode => Err(ErrorPayload::new(ErrorClass::OTHER)
+                .with_message(format!("DNS server returned error code {}", rcode))),
+        };
+
+        let response = ffi::ResolveResponse { result };
+        for waiter in query.waiters {
+            redshirt_syscalls_interface::emit_answer(waiter, &response);
+        }
+    }
+
+    /// Sends again the queries that haven't been answered in time, and reports a timeout for
+    /// the ones that have been sent too many times.
+    async fn retry(&mut self, socket: &redshirt_udp_interface::UdpSocket, now: u128) {
+        let expired = self
+            .queries
+            .iter()
+            .filter(|(_, q)| q.waiters.is_empty() || q.last_sent + RETRY_PERIOD <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            let query = self.queries.get_mut(&id).unwrap();
+            if query.waiters.is_empty() || query.attempts >= MAX_ATTEMPTS || self.servers.is_empty()
+            {
+                let query = self.queries.remove(&id).unwrap();
+                let response = ffi::ResolveResponse {
+                    result: Err(ErrorPayload::new(ErrorClass::TIMED_OUT)),
+                };
+                for waiter in query.waiters {
+                    redshirt_syscalls_interface::emit_answer(waiter, &response);
+                }
+                continue;
+            }
+
+            let server =
+                &self.servers[usize::try_from(query.attempts).unwrap() % self.servers.len()];
+            query.attempts += 1;
+            query.last_sent = now;
+            let _ = socket.send_to(query.packet.clone(), server).await;
+        }
+    }
+}
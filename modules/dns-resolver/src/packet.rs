@@ -0,0 +1,205 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Encoding of DNS queries and decoding of DNS responses.
+//!
+//! See RFC 1035.
+
+use redshirt_dns_interface::{Record, RecordData, RecordType};
+use std::convert::TryFrom as _;
+
+/// Decoded DNS response.
+#[derive(Debug)]
+pub struct Response {
+    /// Identifier of the query this response corresponds to.
+    pub id: u16,
+    /// Response code. 0 means success, and 3 that the name doesn't exist.
+    pub rcode: u8,
+    /// If true, the response didn't fit in a UDP datagram and is incomplete.
+    pub truncated: bool,
+    /// Records of the answer section. Records whose type isn't supported are skipped.
+    pub answers: Vec<Record>,
+}
+
+/// Builds a query for the records of type `record_type` of `name`, with recursion desired.
+///
+/// Returns an error if `name` isn't a valid domain name.
+pub fn build_query(id: u16, name: &str, record_type: RecordType) -> Result<Vec<u8>, ()> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 {
+        return Err(());
+    }
+
+    let mut out = Vec::with_capacity(18 + name.len());
+    out.extend_from_slice(&id.to_be_bytes());
+    // Flags: only "recursion desired" is set.
+    out.extend_from_slice(&[0x01, 0x00]);
+    // One question, and no answer, authority or additional record.
+    out.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(());
+        }
+        out.push(u8::try_from(label.len()).unwrap());
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+
+    out.extend_from_slice(&record_type_code(record_type).to_be_bytes());
+    // Class IN.
+    out.extend_from_slice(&[0, 1]);
+    Ok(out)
+}
+
+/// Decodes a response sent by a DNS server.
+pub fn parse_response(packet: &[u8]) -> Result<Response, ()> {
+    let id = read_u16(packet, 0)?;
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        // This is a query, not a response.
+        return Err(());
+    }
+
+    let num_questions = read_u16(packet, 4)?;
+    let num_answers = read_u16(packet, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..num_questions {
+        let (_, after_name) = read_name(packet, offset)?;
+        // Skip the type and class.
+        offset = after_name + 4;
+    }
+
+    let mut answers = Vec::with_capacity(usize::from(num_answers));
+    for _ in 0..num_answers {
+        let (name, after_name) = read_name(packet, offset)?;
+        let ty = read_u16(packet, after_name)?;
+        let class = read_u16(packet, after_name + 2)?;
+        let ttl = read_u32(packet, after_name + 4)?;
+        let rdata_len = usize::from(read_u16(packet, after_name + 8)?);
+        let rdata_start = after_name + 10;
+        let rdata = packet.get(rdata_start..rdata_start + rdata_len).ok_or(())?;
+        offset = rdata_start + rdata_len;
+
+        if class != 1 {
+            continue;
+        }
+
+        let data = match ty {
+            1 if rdata.len() == 4 => RecordData::A([rdata[0], rdata[1], rdata[2], rdata[3]]),
+            2 => RecordData::Ns(read_name(packet, rdata_start)?.0),
+            5 => RecordData::Cname(read_name(packet, rdata_start)?.0),
+            15 => RecordData::Mx {
+                preference: read_u16(packet, rdata_start)?,
+                exchange: read_name(packet, rdata_start + 2)?.0,
+            },
+            16 => RecordData::Txt(read_character_strings(rdata)?),
+            28 if rdata.len() == 16 => {
+                let mut ip = [0; 8];
+                for (n, segment) in ip.iter_mut().enumerate() {
+                    *segment = u16::from_be_bytes([rdata[2 * n], rdata[2 * n + 1]]);
+                }
+                RecordData::Aaaa(ip)
+            }
+            _ => continue,
+        };
+
+        answers.push(Record {
+            name,
+            // TTLs with the most significant bit set must be treated as 0. See RFC 2181.
+            ttl: if ttl > 0x7fff_ffff { 0 } else { ttl },
+            data,
+        });
+    }
+
+    Ok(Response {
+        id,
+        rcode: u8::try_from(flags & 0xf).unwrap(),
+        truncated: flags & 0x0200 != 0,
+        answers,
+    })
+}
+
+/// Returns the value of the `TYPE` field corresponding to a record type.
+fn record_type_code(record_type: RecordType) -> u16 {
+    match record_type {
+        RecordType::A => 1,
+        RecordType::Ns => 2,
+        RecordType::Cname => 5,
+        RecordType::Mx => 15,
+        RecordType::Txt => 16,
+        RecordType::Aaaa => 28,
+    }
+}
+
+/// Reads a domain name, possibly compressed, starting at `offset`. Returns the name and the
+/// offset of the first byte after the name.
+fn read_name(packet: &[u8], mut offset: usize) -> Result<(String, usize), ()> {
+    let mut name = String::new();
+    // Offset after the name, known once we have followed the first pointer.
+    let mut end = None;
+    // Protects against loops of pointers.
+    let mut num_jumps = 0;
+
+    loop {
+        let len = *packet.get(offset).ok_or(())?;
+        match len & 0xc0 {
+            0x00 if len == 0 => return Ok((name, end.unwrap_or(offset + 1))),
+            0x00 => {
+                let label = packet
+                    .get(offset + 1..offset + 1 + usize::from(len))
+                    .ok_or(())?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                offset += 1 + usize::from(len);
+            }
+            0xc0 => {
+                if end.is_none() {
+                    end = Some(offset + 2);
+                }
+                num_jumps += 1;
+                if num_jumps > 64 {
+                    return Err(());
+                }
+                offset = usize::from(read_u16(packet, offset)? & 0x3fff);
+            }
+            _ => return Err(()),
+        }
+    }
+}
+
+/// Reads the content of a `TXT` record, which is a list of length-prefixed strings.
+fn read_character_strings(mut data: &[u8]) -> Result<Vec<Vec<u8>>, ()> {
+    let mut out = Vec::new();
+    while let Some((len, rest)) = data.split_first() {
+        let string = rest.get(..usize::from(*len)).ok_or(())?;
+        out.push(string.to_vec());
+        data = &rest[usize::from(*len)..];
+    }
+    Ok(out)
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Result<u16, ()> {
+    let bytes = packet.get(offset..offset + 2).ok_or(())?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> Result<u32, ()> {
+    let bytes = packet.get(offset..offset + 4).ok_or(())?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -0,0 +1,272 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+// Offsets of the registers.
+const REG_CTRL: u64 = 0x0000;
+const REG_IMC: u64 = 0x00d8;
+const REG_RCTL: u64 = 0x0100;
+const REG_TCTL: u64 = 0x0400;
+const REG_TIPG: u64 = 0x0410;
+const REG_RDBAL: u64 = 0x2800;
+const REG_RDBAH: u64 = 0x2804;
+const REG_RDLEN: u64 = 0x2808;
+const REG_RDH: u64 = 0x2810;
+const REG_RDT: u64 = 0x2818;
+const REG_TDBAL: u64 = 0x3800;
+const REG_TDBAH: u64 = 0x3804;
+const REG_TDLEN: u64 = 0x3808;
+const REG_TDH: u64 = 0x3810;
+const REG_TDT: u64 = 0x3818;
+const REG_MTA: u64 = 0x5200;
+const REG_RAL0: u64 = 0x5400;
+const REG_RAH0: u64 = 0x5404;
+
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_SECRC: u32 = 1 << 26;
+
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+
+/// Bit of the status field of a descriptor indicating that the device is done with it.
+const DESC_STATUS_DD: u8 = 1 << 0;
+/// Bit of the status field of a receive descriptor indicating the end of a packet.
+const DESC_STATUS_EOP: u8 = 1 << 1;
+
+const TX_CMD_EOP: u8 = 1 << 0;
+const TX_CMD_IFCS: u8 = 1 << 1;
+const TX_CMD_RS: u8 = 1 << 3;
+
+/// Number of descriptors in each ring. Must be a multiple of 8.
+const NUM_DESCRIPTORS: u16 = 32;
+/// Size of the buffer of each descriptor. Must match the size configured in `RCTL`.
+const BUFFER_LEN: u32 = 2048;
+/// Maximum size of an Ethernet frame, without the CRC.
+const MAX_FRAME_LEN: usize = 1514;
+
+/// State of a device.
+//
+// # Implementation note
+//
+// The receive and transmit rings are allocated during initialization, and each descriptor has
+// its own buffer that is never freed. Received packets are detected by polling the status of the
+// next descriptor of the receive ring.
+//
+pub struct Device {
+    /// Physical address of the registers of the device.
+    registers: u64,
+    /// Physical address of the receive descriptors ring.
+    rx_ring: u64,
+    /// Physical address of the transmit descriptors ring.
+    tx_ring: u64,
+    /// Physical address of the buffer of each receive descriptor.
+    rx_buffers: Vec<u64>,
+    /// Physical address of the buffer of each transmit descriptor.
+    tx_buffers: Vec<u64>,
+    /// Index of the next receive descriptor that the device will fill.
+    next_rx: u16,
+    /// Index of the next transmit descriptor to fill.
+    next_tx: u16,
+    /// MAC address of the device.
+    mac_address: [u8; 6],
+}
+
+impl Device {
+    /// Assumes that an e1000 device has its registers mapped at `registers` and reinitializes
+    /// it to a starting state.
+    pub async unsafe fn reset(registers: u64) -> Self {
+        let ctrl = read_u32(registers + REG_CTRL).await;
+        redshirt_hardware_interface::write_one_u32(registers + REG_CTRL, ctrl | CTRL_RST);
+        while read_u32(registers + REG_CTRL).await & CTRL_RST != 0 {}
+
+        // Interrupts aren't supported.
+        redshirt_hardware_interface::write_one_u32(registers + REG_IMC, 0xffff_ffff);
+
+        // The MAC address is automatically loaded from the EEPROM into the first receive address
+        // registers.
+        let mac_address = {
+            let low = read_u32(registers + REG_RAL0).await.to_le_bytes();
+            let high = read_u32(registers + REG_RAH0).await.to_le_bytes();
+            [low[0], low[1], low[2], low[3], high[0], high[1]]
+        };
+
+        let ring_len = u64::from(NUM_DESCRIPTORS) * 16;
+        let rx_ring = redshirt_hardware_interface::malloc::malloc(ring_len, 128).await;
+        let tx_ring = redshirt_hardware_interface::malloc::malloc(ring_len, 128).await;
+
+        let mut rx_buffers = Vec::with_capacity(usize::from(NUM_DESCRIPTORS));
+        let mut tx_buffers = Vec::with_capacity(usize::from(NUM_DESCRIPTORS));
+        for _ in 0..NUM_DESCRIPTORS {
+            rx_buffers
+                .push(redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 16).await);
+            tx_buffers
+                .push(redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 16).await);
+        }
+
+        let mut rx_descriptors = Vec::with_capacity(ring_len as usize);
+        for buffer in &rx_buffers {
+            rx_descriptors.extend_from_slice(&buffer.to_le_bytes());
+            rx_descriptors.extend_from_slice(&[0; 8]);
+        }
+
+        // Transmit descriptors start with the `DD` bit set, to indicate that they are free.
+        let mut tx_descriptors = Vec::with_capacity(ring_len as usize);
+        for buffer in &tx_buffers {
+            tx_descriptors.extend_from_slice(&buffer.to_le_bytes());
+            tx_descriptors.extend_from_slice(&[0, 0, 0, 0, DESC_STATUS_DD, 0, 0, 0]);
+        }
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(rx_ring, rx_descriptors);
+        ops.write(tx_ring, tx_descriptors);
+
+        ops.write_one_u32(registers + REG_CTRL, CTRL_SLU);
+        for n in 0..128 {
+            ops.write_one_u32(registers + REG_MTA + 4 * n, 0);
+        }
+
+        ops.write_one_u32(registers + REG_RDBAL, rx_ring as u32);
+        ops.write_one_u32(registers + REG_RDBAH, (rx_ring >> 32) as u32);
+        ops.write_one_u32(registers + REG_RDLEN, ring_len as u32);
+        ops.write_one_u32(registers + REG_RDH, 0);
+        ops.write_one_u32(registers + REG_RDT, u32::from(NUM_DESCRIPTORS - 1));
+        // Buffers of 2048 bytes, broadcast packets accepted, and CRC stripped.
+        ops.write_one_u32(registers + REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+
+        ops.write_one_u32(registers + REG_TDBAL, tx_ring as u32);
+        ops.write_one_u32(registers + REG_TDBAH, (tx_ring >> 32) as u32);
+        ops.write_one_u32(registers + REG_TDLEN, ring_len as u32);
+        ops.write_one_u32(registers + REG_TDH, 0);
+        ops.write_one_u32(registers + REG_TDT, 0);
+        // Recommended values for the collision threshold and distance, and inter-packet gap.
+        ops.write_one_u32(
+            registers + REG_TCTL,
+            TCTL_EN | TCTL_PSP | (0x10 << 4) | (0x40 << 12),
+        );
+        ops.write_one_u32(registers + REG_TIPG, 0x0060_200a);
+        ops.send();
+
+        Device {
+            registers,
+            rx_ring,
+            tx_ring,
+            rx_buffers,
+            tx_buffers,
+            next_rx: 0,
+            next_tx: 0,
+            mac_address,
+        }
+    }
+
+    /// Returns the MAC address of the device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// Reads one Ethernet frame received by the device.
+    ///
+    /// Returns `None` if there's no frame available.
+    pub async unsafe fn read_one_incoming(&mut self) -> Option<Vec<u8>> {
+        let descriptor = self.rx_ring + u64::from(self.next_rx) * 16;
+
+        let mut status = [0u8; 8];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(descriptor + 8, &mut status);
+        ops.send().await;
+
+        if status[4] & DESC_STATUS_DD == 0 {
+            return None;
+        }
+
+        let len = u16::from_le_bytes([status[0], status[1]]);
+        // Packets larger than a buffer are split over multiple descriptors. This can't happen
+        // as long as we don't enable long packets, but we discard them for safety.
+        let complete = status[4] & DESC_STATUS_EOP != 0 && status[5] == 0;
+
+        let mut data = vec![0; usize::from(len)];
+        if complete {
+            let mut ops = HardwareOperationsBuilder::new();
+            ops.read(self.rx_buffers[usize::from(self.next_rx)], &mut data);
+            ops.send().await;
+        }
+
+        // Give the descriptor back to the device.
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(descriptor + 8, vec![0; 8]);
+        ops.write_one_u32(self.registers + REG_RDT, u32::from(self.next_rx));
+        ops.send();
+        self.next_rx = (self.next_rx + 1) % NUM_DESCRIPTORS;
+
+        if complete {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    /// Queues an Ethernet frame, without its CRC, for sending.
+    ///
+    /// Returns an error if the frame is too large or if the transmit ring is full.
+    pub async unsafe fn send_packet(&mut self, frame: &[u8]) -> Result<(), ()> {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(());
+        }
+
+        let descriptor = self.tx_ring + u64::from(self.next_tx) * 16;
+        let mut status = [0u8];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(descriptor + 12, &mut status);
+        ops.send().await;
+        if status[0] & DESC_STATUS_DD == 0 {
+            return Err(());
+        }
+
+        let buffer = self.tx_buffers[usize::from(self.next_tx)];
+        let len = (frame.len() as u16).to_le_bytes();
+        self.next_tx = (self.next_tx + 1) % NUM_DESCRIPTORS;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(buffer, frame.to_vec());
+        ops.write(
+            descriptor + 8,
+            vec![
+                len[0],
+                len[1],
+                0,
+                TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS,
+                0,
+                0,
+                0,
+                0,
+            ],
+        );
+        ops.write_one_u32(self.registers + REG_TDT, u32::from(self.next_tx));
+        ops.send();
+        Ok(())
+    }
+}
+
+async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
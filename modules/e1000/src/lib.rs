@@ -0,0 +1,117 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the Intel e1000 and e1000e network cards.
+//!
+//! Each card is registered through the Ethernet interface, after which the frames received from
+//! the network are forwarded to the handler of that interface and the frames it provides are
+//! sent out.
+//!
+//! This library is used by the `e1000` program, which drives all the cards found on the PCI
+//! bus, and by the programs that start drivers as cards appear.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/Intel_Ethernet_i217
+//! - https://www.intel.com/content/dam/doc/manual/pci-pci-x-family-gbe-controllers-software-dev-manual.pdf
+//!
+
+use futures::{lock::Mutex, prelude::*};
+use redshirt_pci_interface::PciDeviceInfo;
+use std::time::Duration;
+
+pub use self::device::Device;
+
+mod device;
+
+/// Device identifiers of the supported cards. The vendor is always Intel.
+const DEVICE_IDS: &[u16] = &[
+    0x100e, // 82540EM, emulated by QEMU by default
+    0x100f, // 82545EM
+    0x1004, // 82543GC
+    0x10d3, // 82574L
+    0x10f5, // 82567LM
+    0x153a, // I217-LM
+    0x153b, // I217-V
+];
+
+/// Interval at which the devices are checked for incoming frames.
+// TODO: use interrupts instead
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Number of times sending a frame is attempted before it is discarded.
+const SEND_ATTEMPTS: u32 = 16;
+
+/// Returns true if the given PCI device is supported by this driver.
+pub fn is_supported(device: &PciDeviceInfo) -> bool {
+    device.vendor_id == 0x8086 && DEVICE_IDS.contains(&device.device_id)
+}
+
+/// Resets and initializes the given PCI device. Returns `None` if the device isn't supported or
+/// its registers can't be accessed.
+pub async fn init(device: &PciDeviceInfo) -> Option<Device> {
+    if !is_supported(device) {
+        return None;
+    }
+
+    let registers = match redshirt_pci_interface::map_bar(device.location, 0).await {
+        Ok(redshirt_pci_interface::PciBaseAddressRegister::Memory { base_address, .. }) => {
+            base_address
+        }
+        _ => return None,
+    };
+    redshirt_pci_interface::enable_bus_mastering(device.location);
+
+    let device = unsafe { Device::reset(registers).await };
+    let mac = device.mac_address();
+    redshirt_stdout_interface::stdout(format!(
+        "Initialized e1000 with MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    ));
+    Some(device)
+}
+
+/// Registers the device through the Ethernet interface and transfers frames between the device
+/// and the handler of that interface.
+///
+/// Never finishes. The interface is unregistered when the future is destroyed.
+pub async fn run_device(device: Device) {
+    let registration = redshirt_ethernet_interface::register_interface(device.mac_address()).await;
+    let device = Mutex::new(device);
+
+    let receive = async {
+        loop {
+            let frame = unsafe { device.lock().await.read_one_incoming().await };
+            match frame {
+                Some(frame) => registration.packet_from_network(frame),
+                None => redshirt_time_interface::monotonic_wait(POLL_INTERVAL).await,
+            }
+        }
+    };
+
+    let send = async {
+        loop {
+            let frame = registration.packet_to_send().await;
+            for _ in 0..SEND_ATTEMPTS {
+                if unsafe { device.lock().await.send_packet(&frame).await }.is_ok() {
+                    break;
+                }
+                redshirt_time_interface::monotonic_wait(POLL_INTERVAL).await;
+            }
+        }
+    };
+
+    future::join(receive, send).await;
+}
@@ -0,0 +1,38 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the Intel e1000 and e1000e network cards.
+//!
+//! This program scans the PCI space for e1000 cards, and drives all the cards found. See the
+//! library of this crate for the driver itself.
+
+use futures::prelude::*;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut devices = Vec::new();
+
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    for device in pci_devices {
+        if let Some(device) = e1000::init(&device).await {
+            devices.push(device);
+        }
+    }
+
+    future::join_all(devices.into_iter().map(e1000::run_device)).await;
+}
@@ -0,0 +1,650 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Provides a filesystem mounted at `/disk0` by reading an ext2 filesystem from a block device.
+//!
+//! The filesystem is mounted read-only. Images created by ext4 are supported as long as they
+//! only use extents and 64-bit block numbers on top of the ext2 format. Journals are ignored.
+//!
+//! The first device of the block device interface is used.
+
+use parity_scale_codec::DecodeAll;
+use redshirt_block_interface::BlockDevice;
+use redshirt_filesystem_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::{collections::HashMap, convert::TryFrom as _};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let device = match redshirt_block_interface::devices().await.into_iter().next() {
+        Some(d) => d,
+        None => return,
+    };
+
+    let fs = match Ext2::mount(device).await {
+        Ok(fs) => fs,
+        Err(_) => return,
+    };
+
+    if redshirt_filesystem_interface::mount("/disk0")
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut handles = HashMap::<u64, OpenFile>::new();
+    let mut next_handle = 1;
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                handles.retain(|_, f| f.owner != msg.pid);
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(
+            msg.interface,
+            redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE
+        );
+
+        let message: ffi::FilesystemMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        let emitter = msg.emitter_pid;
+
+        match message {
+            ffi::FilesystemMessage::Open(open) => {
+                let result = if open.create || open.truncate {
+                    Err(read_only())
+                } else {
+                    match fs.lookup(&open.path).await {
+                        Ok(inode) if inode.is_directory() => {
+                            Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                                .with_message("path is a directory"))
+                        }
+                        Ok(inode) => {
+                            let handle = next_handle;
+                            next_handle += 1;
+                            handles.insert(
+                                handle,
+                                OpenFile {
+                                    owner: emitter,
+                                    inode,
+                                    position: 0,
+                                },
+                            );
+                            Ok(handle)
+                        }
+                        Err(err) => Err(err),
+                    }
+                };
+                answer(msg.message_id, ffi::OpenResponse { result });
+            }
+            ffi::FilesystemMessage::Read(read) => {
+                let result = match handle_mut(&mut handles, emitter, read.handle) {
+                    Ok(file) => {
+                        let data = fs.read(&file.inode, file.position, read.len).await;
+                        if let Ok(data) = &data {
+                            file.position += u64::try_from(data.len()).unwrap();
+                        }
+                        data
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadResponse { result });
+            }
+            ffi::FilesystemMessage::Write(_) => {
+                answer(
+                    msg.message_id,
+                    ffi::WriteResponse {
+                        result: Err(read_only()),
+                    },
+                );
+            }
+            ffi::FilesystemMessage::Seek(seek) => {
+                let result = handle_mut(&mut handles, emitter, seek.handle).map(|file| {
+                    file.position = seek.position;
+                });
+                answer(msg.message_id, ffi::SeekResponse { result });
+            }
+            ffi::FilesystemMessage::Close(close) => {
+                if handle_mut(&mut handles, emitter, close.handle).is_ok() {
+                    handles.remove(&close.handle);
+                }
+            }
+            ffi::FilesystemMessage::CreateDir(_) => {
+                answer(
+                    msg.message_id,
+                    ffi::CreateDirResponse {
+                        result: Err(read_only()),
+                    },
+                );
+            }
+            ffi::FilesystemMessage::ReadDir(read_dir) => {
+                let result = match fs.lookup(&read_dir.path).await {
+                    Ok(inode) => fs.read_dir(&inode).await.map(|entries| {
+                        entries
+                            .into_iter()
+                            .filter(|e| e.name != "." && e.name != "..")
+                            .map(|e| ffi::DirEntry {
+                                name: e.name,
+                                kind: if e.is_directory {
+                                    ffi::EntryKind::Directory
+                                } else {
+                                    ffi::EntryKind::File
+                                },
+                            })
+                            .collect()
+                    }),
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadDirResponse { result });
+            }
+            ffi::FilesystemMessage::Metadata(request) => {
+                let result = fs.lookup(&request.path).await.map(|inode| {
+                    if inode.is_directory() {
+                        ffi::Metadata {
+                            kind: ffi::EntryKind::Directory,
+                            len: 0,
+                        }
+                    } else {
+                        ffi::Metadata {
+                            kind: ffi::EntryKind::File,
+                            len: inode.size,
+                        }
+                    }
+                });
+                answer(msg.message_id, ffi::MetadataResponse { result });
+            }
+            ffi::FilesystemMessage::Mount(_) => {
+                answer(
+                    msg.message_id,
+                    ffi::MountResponse {
+                        result: Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Sends back `response` if the emitter of the message expects an answer.
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
+
+struct OpenFile {
+    /// Process that has opened the file. Other processes can't use the handle.
+    owner: Pid,
+    inode: Inode,
+    /// Current position within the file.
+    position: u64,
+}
+
+/// Returns the open file corresponding to `handle`, if it belongs to `emitter`.
+fn handle_mut(
+    handles: &mut HashMap<u64, OpenFile>,
+    emitter: Pid,
+    handle: u64,
+) -> Result<&mut OpenFile, ErrorPayload> {
+    match handles.get_mut(&handle) {
+        Some(f) if f.owner == emitter => Ok(f),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid handle")),
+    }
+}
+
+fn read_only() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::PERMISSION_DENIED).with_message("read-only filesystem")
+}
+
+fn corrupted() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message("corrupted filesystem")
+}
+
+/// Number of the inode of the root directory.
+const ROOT_INODE: u32 = 2;
+/// Value of `s_magic` in the superblock.
+const EXT2_MAGIC: u16 = 0xef53;
+/// Incompatible feature: the filesystem uses extents.
+const INCOMPAT_EXTENTS: u32 = 0x40;
+/// Incompatible feature: block numbers are 64 bits.
+const INCOMPAT_64BIT: u32 = 0x80;
+/// Incompatible features that we know how to read. We refuse to mount the filesystem if any
+/// other incompatible feature is enabled. `FILETYPE` (0x2), `RECOVER` (0x4) and `FLEX_BG`
+/// (0x200) don't affect reading.
+const INCOMPAT_SUPPORTED: u32 = 0x2 | 0x4 | INCOMPAT_EXTENTS | INCOMPAT_64BIT | 0x200;
+/// Flag of `i_flags` indicating that the inode uses extents.
+const INODE_FLAG_EXTENTS: u32 = 0x80000;
+/// Magic value at the start of each node of an extents tree.
+const EXTENT_MAGIC: u16 = 0xf30a;
+
+/// Mounted ext2 filesystem.
+struct Ext2 {
+    device: BlockDevice,
+    /// Size of a sector of the device, in bytes.
+    sector_size: u64,
+    /// Size of a block of the filesystem, in bytes.
+    block_size: u64,
+    inodes_per_group: u32,
+    /// Size of an entry of the inodes table, in bytes.
+    inode_size: u64,
+    /// Block where the table of group descriptors starts.
+    group_descriptors_block: u64,
+    /// Size of an entry of the group descriptors table, in bytes.
+    group_descriptor_size: u64,
+    /// True if block numbers in group descriptors have a high part.
+    is_64bit: bool,
+}
+
+/// Decoded inode.
+struct Inode {
+    mode: u16,
+    /// Size of the content, in bytes.
+    size: u64,
+    flags: u32,
+    /// Raw `i_block` field. Either a list of block numbers or the root of an extents tree.
+    block: [u8; 60],
+}
+
+/// Entry of a directory.
+struct RawDirEntry {
+    name: String,
+    inode: u32,
+    is_directory: bool,
+}
+
+impl Inode {
+    fn is_directory(&self) -> bool {
+        self.mode & 0xf000 == 0x4000
+    }
+}
+
+impl Ext2 {
+    /// Reads the superblock of the device and checks that we support the filesystem.
+    async fn mount(device: BlockDevice) -> Result<Ext2, ErrorPayload> {
+        let geometry = device.geometry().await?;
+        let sector_size = u64::from(geometry.sector_size);
+        if sector_size == 0 {
+            return Err(corrupted());
+        }
+
+        let mut fs = Ext2 {
+            device,
+            sector_size,
+            block_size: 1024,
+            inodes_per_group: 0,
+            inode_size: 128,
+            group_descriptors_block: 0,
+            group_descriptor_size: 32,
+            is_64bit: false,
+        };
+
+        let superblock = fs.read_bytes(1024, 1024).await?;
+        if read_u16(&superblock, 56) != EXT2_MAGIC {
+            return Err(ErrorPayload::new(ErrorClass::UNSUPPORTED).with_message("not ext2"));
+        }
+
+        let log_block_size = read_u32(&superblock, 24);
+        if log_block_size > 6 {
+            return Err(corrupted());
+        }
+        fs.block_size = 1024 << log_block_size;
+        fs.inodes_per_group = read_u32(&superblock, 40);
+        if fs.inodes_per_group == 0 {
+            return Err(corrupted());
+        }
+        fs.group_descriptors_block = u64::from(read_u32(&superblock, 20)) + 1;
+
+        // Revision 0 has fixed-size inodes and no features.
+        if read_u32(&superblock, 76) >= 1 {
+            fs.inode_size = u64::from(read_u16(&superblock, 88));
+            if fs.inode_size < 128 {
+                return Err(corrupted());
+            }
+
+            let incompat = read_u32(&superblock, 96);
+            if incompat & !INCOMPAT_SUPPORTED != 0 {
+                return Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)
+                    .with_message("unsupported filesystem features"));
+            }
+
+            if incompat & INCOMPAT_64BIT != 0 {
+                fs.is_64bit = true;
+                fs.group_descriptor_size = u64::from(read_u16(&superblock, 254));
+                if fs.group_descriptor_size < 64 {
+                    return Err(corrupted());
+                }
+            }
+        }
+
+        Ok(fs)
+    }
+
+    /// Finds the inode corresponding to the given absolute path.
+    async fn lookup(&self, path: &str) -> Result<Inode, ErrorPayload> {
+        if !path.starts_with('/') {
+            return Err(
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("relative path")
+            );
+        }
+
+        let mut inode = self.inode(ROOT_INODE).await?;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !inode.is_directory() {
+                return Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+            }
+
+            let entry = self
+                .read_dir(&inode)
+                .await?
+                .into_iter()
+                .find(|e| e.name == component)
+                .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))?;
+            inode = self.inode(entry.inode).await?;
+        }
+
+        Ok(inode)
+    }
+
+    /// Reads all the entries of a directory, including `.` and `..`.
+    async fn read_dir(&self, inode: &Inode) -> Result<Vec<RawDirEntry>, ErrorPayload> {
+        if !inode.is_directory() {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("path is not a directory"));
+        }
+
+        let content = self
+            .read(
+                inode,
+                0,
+                u32::try_from(inode.size).map_err(|_| corrupted())?,
+            )
+            .await?;
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= content.len() {
+            let entry_inode = read_u32(&content, offset);
+            let rec_len = usize::from(read_u16(&content, offset + 4));
+            let name_len = usize::from(content[offset + 6]);
+            let file_type = content[offset + 7];
+            if rec_len < 8 || offset + rec_len > content.len() || 8 + name_len > rec_len {
+                return Err(corrupted());
+            }
+
+            // An inode of 0 designates an unused entry.
+            if entry_inode != 0 {
+                let name = &content[offset + 8..offset + 8 + name_len];
+                // TODO: file_type is only valid if the FILETYPE feature is enabled
+                entries.push(RawDirEntry {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    inode: entry_inode,
+                    is_directory: file_type == 2,
+                });
+            }
+
+            offset += rec_len;
+        }
+
+        Ok(entries)
+    }
+
+    /// Reads at most `len` bytes of the content of an inode, starting at `offset`.
+    async fn read(&self, inode: &Inode, offset: u64, len: u32) -> Result<Vec<u8>, ErrorPayload> {
+        let end = offset
+            .saturating_add(u64::from(len))
+            .min(inode.size)
+            .max(offset);
+        let mut out = Vec::with_capacity(usize::try_from(end - offset).unwrap());
+
+        let mut position = offset;
+        while position < end {
+            let block_index = position / self.block_size;
+            let in_block = position % self.block_size;
+            let chunk = (self.block_size - in_block).min(end - position);
+
+            match self.block_of(inode, block_index).await? {
+                Some(block) => {
+                    let data = self
+                        .read_bytes(
+                            block * self.block_size + in_block,
+                            usize::try_from(chunk).unwrap(),
+                        )
+                        .await?;
+                    out.extend_from_slice(&data);
+                }
+                // Sparse block.
+                None => out.resize(out.len() + usize::try_from(chunk).unwrap(), 0),
+            }
+
+            position += chunk;
+        }
+
+        Ok(out)
+    }
+
+    /// Returns the block on the device corresponding to the given block index within the
+    /// inode, or `None` if the block is sparse.
+    async fn block_of(&self, inode: &Inode, index: u64) -> Result<Option<u64>, ErrorPayload> {
+        if inode.flags & INODE_FLAG_EXTENTS != 0 {
+            self.block_of_extents(inode, index).await
+        } else {
+            self.block_of_indirect(inode, index).await
+        }
+    }
+
+    async fn block_of_extents(
+        &self,
+        inode: &Inode,
+        index: u64,
+    ) -> Result<Option<u64>, ErrorPayload> {
+        let mut node = inode.block.to_vec();
+
+        loop {
+            if node.len() < 12 || read_u16(&node, 0) != EXTENT_MAGIC {
+                return Err(corrupted());
+            }
+            let num_entries = usize::from(read_u16(&node, 2));
+            let depth = read_u16(&node, 6);
+            if node.len() < 12 + num_entries * 12 {
+                return Err(corrupted());
+            }
+
+            let entries = (0..num_entries).map(|n| &node[12 + n * 12..24 + n * 12]);
+
+            if depth == 0 {
+                for entry in entries {
+                    let first = u64::from(read_u32(entry, 0));
+                    let len = read_u16(entry, 4);
+                    // Lengths above 32768 indicate uninitialized extents, which read as zeroes.
+                    let (len, initialized) = if len > 32768 {
+                        (u64::from(len - 32768), false)
+                    } else {
+                        (u64::from(len), true)
+                    };
+                    if index >= first && index < first + len {
+                        if !initialized {
+                            return Ok(None);
+                        }
+                        let start =
+                            (u64::from(read_u16(entry, 6)) << 32) | u64::from(read_u32(entry, 8));
+                        return Ok(Some(start + index - first));
+                    }
+                }
+                return Ok(None);
+            }
+
+            let child = entries
+                .take_while(|entry| u64::from(read_u32(entry, 0)) <= index)
+                .last()
+                .map(|entry| (u64::from(read_u16(entry, 8)) << 32) | u64::from(read_u32(entry, 4)));
+            match child {
+                Some(child) => node = self.read_block(child).await?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    async fn block_of_indirect(
+        &self,
+        inode: &Inode,
+        mut index: u64,
+    ) -> Result<Option<u64>, ErrorPayload> {
+        let per_block = self.block_size / 4;
+
+        // Offsets to follow, starting from `i_block`.
+        let mut path = Vec::with_capacity(4);
+        if index < 12 {
+            path.push(index);
+        } else {
+            index -= 12;
+            if index < per_block {
+                path.extend_from_slice(&[12, index]);
+            } else {
+                index -= per_block;
+                if index < per_block * per_block {
+                    path.extend_from_slice(&[13, index / per_block, index % per_block]);
+                } else {
+                    index -= per_block * per_block;
+                    if index >= per_block * per_block * per_block {
+                        return Ok(None);
+                    }
+                    path.extend_from_slice(&[
+                        14,
+                        index / (per_block * per_block),
+                        (index / per_block) % per_block,
+                        index % per_block,
+                    ]);
+                }
+            }
+        }
+
+        let mut block = u64::from(read_u32(
+            &inode.block,
+            usize::try_from(path[0]).unwrap() * 4,
+        ));
+        for offset in &path[1..] {
+            if block == 0 {
+                return Ok(None);
+            }
+            let data = self
+                .read_bytes(block * self.block_size + offset * 4, 4)
+                .await?;
+            block = u64::from(read_u32(&data, 0));
+        }
+
+        if block == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(block))
+        }
+    }
+
+    /// Reads and decodes the inode with the given number.
+    async fn inode(&self, num: u32) -> Result<Inode, ErrorPayload> {
+        if num == 0 {
+            return Err(corrupted());
+        }
+        let group = u64::from((num - 1) / self.inodes_per_group);
+        let index_in_group = u64::from((num - 1) % self.inodes_per_group);
+
+        let descriptor = self
+            .read_bytes(
+                self.group_descriptors_block * self.block_size + group * self.group_descriptor_size,
+                usize::try_from(self.group_descriptor_size).unwrap(),
+            )
+            .await?;
+        let mut inode_table = u64::from(read_u32(&descriptor, 8));
+        if self.is_64bit {
+            inode_table |= u64::from(read_u32(&descriptor, 0x28)) << 32;
+        }
+
+        let raw = self
+            .read_bytes(
+                inode_table * self.block_size + index_in_group * self.inode_size,
+                128,
+            )
+            .await?;
+
+        let mode = read_u16(&raw, 0);
+        let mut size = u64::from(read_u32(&raw, 4));
+        // For directories, this field is `i_dir_acl` in older revisions.
+        if mode & 0xf000 == 0x8000 {
+            size |= u64::from(read_u32(&raw, 108)) << 32;
+        }
+        let mut block = [0; 60];
+        block.copy_from_slice(&raw[40..100]);
+
+        Ok(Inode {
+            mode,
+            size,
+            flags: read_u32(&raw, 32),
+            block,
+        })
+    }
+
+    async fn read_block(&self, block: u64) -> Result<Vec<u8>, ErrorPayload> {
+        self.read_bytes(
+            block * self.block_size,
+            usize::try_from(self.block_size).unwrap(),
+        )
+        .await
+    }
+
+    /// Reads `len` bytes from the device starting at the given byte offset.
+    async fn read_bytes(&self, offset: u64, len: usize) -> Result<Vec<u8>, ErrorPayload> {
+        let first_sector = offset / self.sector_size;
+        let end = offset + u64::try_from(len).unwrap();
+        let num_sectors = (end + self.sector_size - 1) / self.sector_size - first_sector;
+
+        let data = self
+            .device
+            .read(
+                first_sector,
+                u32::try_from(num_sectors).map_err(|_| corrupted())?,
+            )
+            .await?;
+
+        let start = usize::try_from(offset % self.sector_size).unwrap();
+        if data.len() < start + len {
+            return Err(ErrorPayload::new(ErrorClass::IO).with_message("short read"));
+        }
+        Ok(data[start..start + len].to_vec())
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
@@ -0,0 +1,344 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Intel High Definition Audio controller and the codec attached to it.
+//!
+//! Only one output path is configured: the first digital-to-analog converter that is directly
+//! connected to an output pin. Samples are played from a cyclic buffer in physical memory, which
+//! the controller reads continuously.
+
+use core::time::Duration;
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+// Offsets of the registers of the controller.
+const REG_GCAP: u64 = 0x00;
+const REG_GCTL: u64 = 0x08;
+const REG_STATESTS: u64 = 0x0e;
+const REG_IC: u64 = 0x60;
+const REG_IR: u64 = 0x64;
+const REG_ICS: u64 = 0x68;
+
+// Offsets of the registers of a stream descriptor.
+const SD_CTL: u64 = 0x00;
+const SD_LPIB: u64 = 0x04;
+const SD_CBL: u64 = 0x08;
+const SD_LVI: u64 = 0x0c;
+const SD_FMT: u64 = 0x12;
+const SD_BDPL: u64 = 0x18;
+const SD_BDPU: u64 = 0x1c;
+
+const ICS_ICB: u16 = 1 << 0;
+const ICS_IRV: u16 = 1 << 1;
+
+const SD_CTL_SRST: u32 = 1 << 0;
+const SD_CTL_RUN: u32 = 1 << 1;
+/// Tag that identifies our stream between the controller and the codec.
+const STREAM_TAG: u32 = 1;
+
+// Verbs sent to the codec.
+const VERB_GET_PARAMETER: u32 = 0xf00;
+const VERB_GET_CONNECTION_LIST: u32 = 0xf02;
+const VERB_SET_CONNECTION_SELECT: u32 = 0x701;
+const VERB_SET_POWER_STATE: u32 = 0x705;
+const VERB_SET_STREAM_CHANNEL: u32 = 0x706;
+const VERB_SET_PIN_CONTROL: u32 = 0x707;
+const VERB_SET_EAPD: u32 = 0x70c;
+const VERB_GET_CONFIG_DEFAULT: u32 = 0xf1c;
+// Verbs with a 4 bits identifier and a 16 bits payload.
+const VERB_SET_CONVERTER_FORMAT: u32 = 0x2;
+const VERB_SET_AMP_GAIN_MUTE: u32 = 0x3;
+
+// Parameters queried with `VERB_GET_PARAMETER`.
+const PARAM_NODE_COUNT: u32 = 0x04;
+const PARAM_FUNCTION_GROUP_TYPE: u32 = 0x05;
+const PARAM_WIDGET_CAPABILITIES: u32 = 0x09;
+const PARAM_PIN_CAPABILITIES: u32 = 0x0c;
+const PARAM_CONNECTION_LIST_LENGTH: u32 = 0x0e;
+const PARAM_OUTPUT_AMP_CAPABILITIES: u32 = 0x12;
+
+/// Number of entries in the buffer descriptor list.
+const NUM_PERIODS: u32 = 4;
+/// Size in bytes of each entry of the buffer descriptor list.
+const PERIOD_LEN: u32 = 4096;
+/// Size in bytes of the cyclic buffer.
+pub const BUFFER_LEN: u32 = NUM_PERIODS * PERIOD_LEN;
+
+/// Initialized controller.
+pub struct Controller {
+    /// Physical address of the registers of the controller.
+    registers: u64,
+    /// Address of the codec on the link.
+    codec: u32,
+    /// Node of the digital-to-analog converter.
+    dac: u32,
+    /// Physical address of the registers of the output stream descriptor.
+    stream: u64,
+    /// Physical address of the cyclic buffer.
+    buffer: u64,
+}
+
+impl Controller {
+    /// Resets the controller whose registers are at the given physical address and configures
+    /// an output path.
+    pub async unsafe fn init(registers: u64) -> Result<Controller, ()> {
+        let gctl = read_u32(registers + REG_GCTL).await;
+        redshirt_hardware_interface::write_one_u32(registers + REG_GCTL, gctl & !1);
+        while read_u32(registers + REG_GCTL).await & 1 != 0 {}
+        redshirt_hardware_interface::write_one_u32(registers + REG_GCTL, gctl | 1);
+        while read_u32(registers + REG_GCTL).await & 1 == 0 {}
+
+        // Codecs have 521µs after the reset to signal their presence.
+        redshirt_time_interface::monotonic_wait(Duration::from_millis(1)).await;
+        let statests = read_u16(registers + REG_STATESTS).await;
+        let codec = (0..15)
+            .find(|n| u32::from(statests) & (1 << n) != 0)
+            .ok_or(())?;
+
+        let gcap = read_u16(registers + REG_GCAP).await;
+        let num_input_streams = u64::from((gcap >> 8) & 0xf);
+        let num_output_streams = (gcap >> 12) & 0xf;
+        if num_output_streams == 0 {
+            return Err(());
+        }
+
+        let mut controller = Controller {
+            registers,
+            codec,
+            dac: 0,
+            // Output stream descriptors come after the input stream descriptors.
+            stream: registers + 0x80 + num_input_streams * 0x20,
+            buffer: 0,
+        };
+
+        controller.configure_output_path().await?;
+
+        let bdl =
+            redshirt_hardware_interface::malloc::malloc(u64::from(NUM_PERIODS) * 16, 128).await;
+        controller.buffer =
+            redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 128).await;
+
+        let mut entries = Vec::with_capacity(NUM_PERIODS as usize * 16);
+        for period in 0..NUM_PERIODS {
+            let address = controller.buffer + u64::from(period * PERIOD_LEN);
+            entries.extend_from_slice(&address.to_le_bytes());
+            entries.extend_from_slice(&PERIOD_LEN.to_le_bytes());
+            entries.extend_from_slice(&0u32.to_le_bytes());
+        }
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(bdl, entries);
+        ops.write(controller.buffer, vec![0; BUFFER_LEN as usize]);
+        ops.write_one_u32(controller.stream + SD_BDPL, bdl as u32);
+        ops.write_one_u32(controller.stream + SD_BDPU, (bdl >> 32) as u32);
+        ops.send();
+
+        Ok(controller)
+    }
+
+    /// Finds a converter connected to an output pin, and powers up and unmutes them.
+    async unsafe fn configure_output_path(&mut self) -> Result<(), ()> {
+        let (first, count) = self.subordinate_nodes(0).await?;
+        let mut audio_function_group = None;
+        for node in first..first + count {
+            if self.parameter(node, PARAM_FUNCTION_GROUP_TYPE).await? & 0xff == 0x1 {
+                audio_function_group = Some(node);
+                break;
+            }
+        }
+        let audio_function_group = audio_function_group.ok_or(())?;
+        self.verb(audio_function_group, VERB_SET_POWER_STATE, 0)
+            .await?;
+
+        let (first, count) = self.subordinate_nodes(audio_function_group).await?;
+        let mut converters = Vec::new();
+        let mut pins = Vec::new();
+        for node in first..first + count {
+            let capabilities = self.parameter(node, PARAM_WIDGET_CAPABILITIES).await?;
+            match (capabilities >> 20) & 0xf {
+                0x0 => converters.push(node),
+                0x4 => {
+                    let pin_capabilities = self.parameter(node, PARAM_PIN_CAPABILITIES).await?;
+                    // Bits 30 and 31 of the configuration indicate whether something can be
+                    // plugged into the pin. `1` means that it can't.
+                    let config = self.verb(node, VERB_GET_CONFIG_DEFAULT, 0).await?;
+                    if pin_capabilities & (1 << 4) != 0 && (config >> 30) != 1 {
+                        pins.push(node);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for pin in pins {
+            let connections = self.connection_list(pin).await?;
+            let (index, dac) = match connections
+                .iter()
+                .enumerate()
+                .find(|(_, c)| converters.contains(*c))
+            {
+                Some((index, dac)) => (index, *dac),
+                None => continue,
+            };
+
+            self.verb(pin, VERB_SET_CONNECTION_SELECT, index as u32)
+                .await?;
+            for node in &[dac, pin] {
+                self.verb(*node, VERB_SET_POWER_STATE, 0).await?;
+                // Unmute both channels of the output amplifier, with the gain that corresponds
+                // to 0dB.
+                let amp = self.parameter(*node, PARAM_OUTPUT_AMP_CAPABILITIES).await?;
+                self.verb_long(*node, VERB_SET_AMP_GAIN_MUTE, 0xb000 | (amp & 0x7f))
+                    .await?;
+            }
+            self.verb(pin, VERB_SET_PIN_CONTROL, 0x40).await?;
+            self.verb(pin, VERB_SET_EAPD, 0x2).await?;
+
+            self.dac = dac;
+            return Ok(());
+        }
+
+        Err(())
+    }
+
+    /// Starts the DMA engine with the given value of the stream format register. The buffer is
+    /// played from its start.
+    pub async unsafe fn start(&mut self, format: u16) -> Result<(), ()> {
+        self.stop().await;
+
+        self.verb_long(self.dac, VERB_SET_CONVERTER_FORMAT, u32::from(format))
+            .await?;
+        self.verb(self.dac, VERB_SET_STREAM_CHANNEL, STREAM_TAG << 4)
+            .await?;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(self.buffer, vec![0; BUFFER_LEN as usize]);
+        ops.write_one_u32(self.stream + SD_CBL, BUFFER_LEN);
+        ops.write_one_u16(self.stream + SD_LVI, (NUM_PERIODS - 1) as u16);
+        ops.write_one_u16(self.stream + SD_FMT, format);
+        ops.write_one_u32(self.stream + SD_CTL, (STREAM_TAG << 20) | SD_CTL_RUN);
+        ops.send();
+        Ok(())
+    }
+
+    /// Stops and resets the DMA engine.
+    pub async unsafe fn stop(&mut self) {
+        redshirt_hardware_interface::write_one_u32(self.stream + SD_CTL, SD_CTL_SRST);
+        while read_u32(self.stream + SD_CTL).await & SD_CTL_SRST == 0 {}
+        redshirt_hardware_interface::write_one_u32(self.stream + SD_CTL, 0);
+        while read_u32(self.stream + SD_CTL).await & SD_CTL_SRST != 0 {}
+    }
+
+    /// Returns the offset within the cyclic buffer that the controller is currently reading.
+    pub async unsafe fn position(&self) -> u32 {
+        read_u32(self.stream + SD_LPIB).await % BUFFER_LEN
+    }
+
+    /// Writes data in the cyclic buffer, starting at the given offset and wrapping around at the
+    /// end.
+    pub unsafe fn write(&mut self, offset: u32, data: &[u8]) {
+        debug_assert!(offset < BUFFER_LEN);
+        debug_assert!(data.len() <= BUFFER_LEN as usize);
+
+        let first_len = data.len().min((BUFFER_LEN - offset) as usize);
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(self.buffer + u64::from(offset), data[..first_len].to_vec());
+        if first_len < data.len() {
+            ops.write(self.buffer, data[first_len..].to_vec());
+        }
+        ops.send();
+    }
+
+    /// Returns the first node and the number of nodes below the given one.
+    async unsafe fn subordinate_nodes(&self, node: u32) -> Result<(u32, u32), ()> {
+        let value = self.parameter(node, PARAM_NODE_COUNT).await?;
+        Ok(((value >> 16) & 0xff, value & 0xff))
+    }
+
+    async unsafe fn parameter(&self, node: u32, parameter: u32) -> Result<u32, ()> {
+        self.verb(node, VERB_GET_PARAMETER, parameter).await
+    }
+
+    /// Returns the list of nodes that can be selected as the input of the given one.
+    async unsafe fn connection_list(&self, node: u32) -> Result<Vec<u32>, ()> {
+        let length = self.parameter(node, PARAM_CONNECTION_LIST_LENGTH).await?;
+        // TODO: support the long form, where entries are 16 bits
+        if length & (1 << 7) != 0 {
+            return Ok(Vec::new());
+        }
+
+        let length = length & 0x7f;
+        let mut out = Vec::with_capacity(length as usize);
+        // Each response contains four entries.
+        for index in (0..length).step_by(4) {
+            let entries = self.verb(node, VERB_GET_CONNECTION_LIST, index).await?;
+            for n in 0..(length - index).min(4) {
+                out.push((entries >> (8 * n)) & 0xff);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Sends a verb with a 12 bits identifier and an 8 bits payload.
+    async unsafe fn verb(&self, node: u32, verb: u32, payload: u32) -> Result<u32, ()> {
+        debug_assert!(payload <= 0xff);
+        self.command((self.codec << 28) | (node << 20) | (verb << 8) | payload)
+            .await
+    }
+
+    /// Sends a verb with a 4 bits identifier and a 16 bits payload.
+    async unsafe fn verb_long(&self, node: u32, verb: u32, payload: u32) -> Result<u32, ()> {
+        debug_assert!(payload <= 0xffff);
+        self.command((self.codec << 28) | (node << 20) | (verb << 16) | payload)
+            .await
+    }
+
+    /// Sends a command to the codec through the immediate command registers, and returns the
+    /// response.
+    // TODO: use the CORB and RIRB ring buffers, as the immediate command registers are optional
+    async unsafe fn command(&self, command: u32) -> Result<u32, ()> {
+        while read_u16(self.registers + REG_ICS).await & ICS_ICB != 0 {}
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u32(self.registers + REG_IC, command);
+        // Writing 1 to `IRV` clears it.
+        ops.write_one_u16(self.registers + REG_ICS, ICS_ICB | ICS_IRV);
+        ops.send();
+
+        for _ in 0..10_000 {
+            let ics = read_u16(self.registers + REG_ICS).await;
+            if ics & ICS_ICB == 0 && ics & ICS_IRV != 0 {
+                return Ok(read_u32(self.registers + REG_IR).await);
+            }
+        }
+
+        Err(())
+    }
+}
+
+async unsafe fn read_u16(address: u64) -> u16 {
+    let mut out = [0u16];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u16(address, &mut out);
+    ops.send().await;
+    out[0]
+}
+
+async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
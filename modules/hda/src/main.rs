@@ -0,0 +1,376 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for Intel High Definition Audio controllers.
+//!
+//! This program scans the PCI space for an HD Audio controller. If it finds one, it configures
+//! an output path on the first codec and registers the audio device interface, which the mixer
+//! then uses.
+//!
+//! Only one stream can be open at a time. Samples are copied into a cyclic buffer that the
+//! controller plays continuously. The position of the controller is polled regularly in order
+//! to know which parts of the buffer can be refilled.
+//!
+//! Bibliography:
+//!
+//! - https://www.intel.com/content/www/us/en/standards/high-definition-audio-specification.html
+//! - https://wiki.osdev.org/Intel_High_Definition_Audio
+//!
+
+mod controller;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_audio_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::{collections::VecDeque, convert::TryFrom as _};
+
+/// Period at which the position of the controller is polled, in nanoseconds.
+const POLL_PERIOD: u128 = 5_000_000;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    let location = match pci_devices
+        .iter()
+        .find(|device| device.class_code == 0x04 && device.subclass == 0x03)
+    {
+        Some(device) => device.location,
+        None => return,
+    };
+
+    let registers = match redshirt_pci_interface::map_bar(location, 0).await {
+        Ok(redshirt_pci_interface::PciBaseAddressRegister::Memory { base_address, .. }) => {
+            base_address
+        }
+        _ => return,
+    };
+    redshirt_pci_interface::enable_bus_mastering(location);
+
+    let mut controller = match unsafe { controller::Controller::init(registers).await } {
+        Ok(controller) => controller,
+        Err(()) => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::DEVICE_INTERFACE)
+        .await
+        .unwrap();
+
+    let mut stream: Option<Stream> = None;
+    let mut next_stream_id: u64 = 0;
+    // Timer used to poll the position of the controller. `None` if no stream is open.
+    let mut poll_timer: Option<redshirt_time_interface::Interval> = None;
+
+    loop {
+        let event = if let Some(timer) = poll_timer.as_mut() {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            match future::select(next_message, timer.next()).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right(_) => None,
+            }
+        } else {
+            Some(redshirt_syscalls_interface::next_interface_message().await)
+        };
+
+        let msg = match event {
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m)) => m,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                if stream.as_ref().map_or(false, |s| s.owner == msg.pid) {
+                    close_stream(&mut stream, &mut controller).await;
+                    poll_timer = None;
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                if let Some(stream) = stream.as_mut() {
+                    stream
+                        .pending
+                        .retain(|w| w.message_id != Some(msg.message_id));
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_)) => continue,
+            None => {
+                if let Some(stream) = stream.as_mut() {
+                    unsafe { stream.update(&mut controller).await };
+                }
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::DEVICE_INTERFACE);
+
+        let message: ffi::AudioMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match message {
+            ffi::AudioMessage::OpenStream(format) => {
+                let result = if stream.is_some() {
+                    Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                        .with_message("a stream is already open"))
+                } else if let Some(register) = format_register(&format) {
+                    match unsafe { controller.start(register).await } {
+                        Ok(()) => {
+                            let id = next_stream_id;
+                            next_stream_id = next_stream_id.wrapping_add(1);
+                            stream = Some(Stream::new(id, msg.emitter_pid, format));
+                            let now = redshirt_time_interface::monotonic_clock().await;
+                            poll_timer = Some(redshirt_time_interface::monotonic_interval(
+                                now + POLL_PERIOD,
+                                POLL_PERIOD,
+                            ));
+                            Ok(id)
+                        }
+                        Err(()) => Err(ErrorPayload::new(ErrorClass::IO)),
+                    }
+                } else {
+                    Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)
+                        .with_message("unsupported stream format"))
+                };
+                answer(msg.message_id, ffi::OpenStreamResponse { result });
+            }
+            ffi::AudioMessage::Write(write) => {
+                let s = match stream
+                    .as_mut()
+                    .filter(|s| s.id == write.stream && s.owner == msg.emitter_pid)
+                {
+                    Some(s) => s,
+                    None => {
+                        let result = Err(ErrorPayload::new(ErrorClass::NOT_FOUND));
+                        answer(msg.message_id, ffi::WriteResponse { result });
+                        continue;
+                    }
+                };
+
+                let frame_len = usize::from(s.format.channels)
+                    * s.format.sample_format.bytes_per_sample() as usize;
+                if write.samples.len() % frame_len != 0 {
+                    let result = Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("length isn't a multiple of the frame size"));
+                    answer(msg.message_id, ffi::WriteResponse { result });
+                    continue;
+                }
+
+                s.pending.push_back(PendingWrite {
+                    message_id: msg.message_id,
+                    data: to_hardware_samples(s.format.sample_format, s.volume, write.samples),
+                    offset: 0,
+                });
+                unsafe { s.update(&mut controller).await };
+            }
+            ffi::AudioMessage::Latency(id) => {
+                let result = match stream
+                    .as_mut()
+                    .filter(|s| s.id == id && s.owner == msg.emitter_pid)
+                {
+                    Some(s) => {
+                        unsafe { s.update(&mut controller).await };
+                        let queued = u128::from(s.written - s.played);
+                        let nanos = queued * 1_000_000_000 / u128::from(s.bytes_per_second);
+                        Ok(u64::try_from(nanos).unwrap_or(u64::max_value()))
+                    }
+                    None => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                };
+                answer(msg.message_id, ffi::LatencyResponse { result });
+            }
+            ffi::AudioMessage::SetVolume(set_volume) => {
+                if let Some(s) = stream
+                    .as_mut()
+                    .filter(|s| s.id == set_volume.stream && s.owner == msg.emitter_pid)
+                {
+                    s.volume = set_volume.volume.min(100);
+                }
+            }
+            ffi::AudioMessage::CloseStream(id) => {
+                if stream
+                    .as_ref()
+                    .map_or(false, |s| s.id == id && s.owner == msg.emitter_pid)
+                {
+                    close_stream(&mut stream, &mut controller).await;
+                    poll_timer = None;
+                }
+            }
+        }
+    }
+}
+
+/// Stream currently being played.
+struct Stream {
+    id: u64,
+    /// Process that has opened the stream.
+    owner: Pid,
+    format: ffi::StreamFormat,
+    /// Volume applied to the samples, between 0 and 100.
+    volume: u8,
+    /// Number of bytes of hardware samples played per second.
+    bytes_per_second: u32,
+    /// Total number of bytes copied to the cyclic buffer since the stream has been opened.
+    written: u64,
+    /// Total number of bytes played by the controller since the stream has been opened.
+    /// Always inferior or equal to `written`, except in case of underrun.
+    played: u64,
+    /// Position of the controller within the cyclic buffer during the last update.
+    last_position: u32,
+    /// Writes that don't fit in the cyclic buffer yet.
+    pending: VecDeque<PendingWrite>,
+}
+
+struct PendingWrite {
+    /// Message to answer once all the data has been copied.
+    message_id: Option<MessageId>,
+    /// Samples in the format of the hardware.
+    data: Vec<u8>,
+    /// Number of bytes of `data` already copied.
+    offset: usize,
+}
+
+impl Stream {
+    fn new(id: u64, owner: Pid, format: ffi::StreamFormat) -> Self {
+        Stream {
+            id,
+            owner,
+            bytes_per_second: format.sample_rate * u32::from(format.channels) * 2,
+            format,
+            volume: 100,
+            written: 0,
+            played: 0,
+            last_position: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Queries the position of the controller, then copies as much pending data as possible to
+    /// the cyclic buffer.
+    async unsafe fn update(&mut self, controller: &mut controller::Controller) {
+        let position = controller.position().await;
+        let just_played =
+            (position + controller::BUFFER_LEN - self.last_position) % controller::BUFFER_LEN;
+        if just_played != 0 {
+            // Silence what has been played, so that it doesn't get played again in case of
+            // underrun.
+            controller.write(self.last_position, &vec![0; just_played as usize]);
+            self.played += u64::from(just_played);
+            self.last_position = position;
+        }
+
+        // In case of underrun, data has to be written after what is currently being played.
+        if self.written < self.played {
+            self.written = self.played;
+        }
+
+        while let Some(pending) = self.pending.front_mut() {
+            let space = u64::from(controller::BUFFER_LEN) - (self.written - self.played);
+            if space == 0 {
+                break;
+            }
+
+            let remaining = pending.data.len() - pending.offset;
+            let len = usize::try_from(space).map_or(remaining, |space| space.min(remaining));
+            let offset = u32::try_from(self.written % u64::from(controller::BUFFER_LEN)).unwrap();
+            controller.write(offset, &pending.data[pending.offset..pending.offset + len]);
+            pending.offset += len;
+            self.written += u64::try_from(len).unwrap();
+
+            if pending.offset == pending.data.len() {
+                let message_id = pending.message_id;
+                self.pending.pop_front();
+                answer(message_id, ffi::WriteResponse { result: Ok(()) });
+            }
+        }
+    }
+}
+
+/// Stops the controller and answers the writes that are still pending.
+async fn close_stream(stream: &mut Option<Stream>, controller: &mut controller::Controller) {
+    if let Some(stream) = stream.take() {
+        unsafe { controller.stop().await };
+        for pending in stream.pending {
+            let result = Err(
+                ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("stream has been closed")
+            );
+            answer(pending.message_id, ffi::WriteResponse { result });
+        }
+    }
+}
+
+/// Returns the value of the stream format register corresponding to the given format, or `None`
+/// if it isn't supported.
+///
+/// Samples are always passed to the hardware as 16 bits integers.
+fn format_register(format: &ffi::StreamFormat) -> Option<u16> {
+    // Sample rates are expressed as a base rate of 48kHz (bit 14 cleared) or 44.1kHz (bit 14
+    // set), a multiplier (bits 11 to 13) and a divisor (bits 8 to 10).
+    let rate = match format.sample_rate {
+        8000 => 0b0_000_101,
+        11025 => 0b1_000_011,
+        16000 => 0b0_000_010,
+        22050 => 0b1_000_001,
+        24000 => 0b0_000_001,
+        32000 => 0b0_001_010,
+        44100 => 0b1_000_000,
+        48000 => 0b0_000_000,
+        88200 => 0b1_001_000,
+        96000 => 0b0_001_000,
+        192000 => 0b0_011_000,
+        _ => return None,
+    };
+
+    // TODO: support more than two channels
+    if format.channels == 0 || format.channels > 2 {
+        return None;
+    }
+
+    // Bits 4 to 6 contain the number of bits per sample, where `1` means 16 bits. Bits 0 to 3
+    // contain the number of channels minus one.
+    Some((rate << 8) | (0b001 << 4) | u16::from(format.channels - 1))
+}
+
+/// Converts samples to 16 bits signed little endian integers, and applies the volume.
+fn to_hardware_samples(format: ffi::SampleFormat, volume: u8, samples: Vec<u8>) -> Vec<u8> {
+    let samples = match format {
+        ffi::SampleFormat::S16Le => samples,
+        ffi::SampleFormat::F32Le => samples
+            .chunks(4)
+            .flat_map(|sample| {
+                let value = f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                let value = (value.max(-1.0).min(1.0) * f32::from(i16::max_value())) as i16;
+                value.to_le_bytes().to_vec()
+            })
+            .collect(),
+    };
+
+    if volume >= 100 {
+        return samples;
+    }
+
+    samples
+        .chunks(2)
+        .flat_map(|sample| {
+            let value = i32::from(i16::from_le_bytes([sample[0], sample[1]]));
+            let value = (value * i32::from(volume) / 100) as i16;
+            value.to_le_bytes().to_vec()
+        })
+        .collect()
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP/1.1 client.
+//!
+//! Registers the HTTP client interface. Each request is driven by a separate task that opens a
+//! connection through the TCP interface, and through the TLS interface for `https` URLs. The
+//! task receives the messages concerning its response through a channel.
+
+mod request;
+mod url;
+
+use futures::{channel::mpsc, prelude::*};
+use parity_scale_codec::DecodeAll;
+use redshirt_http_client_interface::ffi;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, Pid};
+use std::{collections::HashMap, pin::Pin};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    // Open responses, with the process that has sent the request and the channel to their task.
+    // Destroying the sender closes the response.
+    let mut responses: HashMap<u32, (Pid, mpsc::UnboundedSender<request::Command>)> =
+        HashMap::new();
+    let mut next_response_id: u32 = 0;
+
+    // Tasks driving the requests. Each task yields the identifier of its response when it ends.
+    let mut tasks = stream::FuturesUnordered::<Pin<Box<dyn Future<Output = u32>>>>::new();
+    // `FuturesUnordered` yields `None` when empty, which we don't want.
+    tasks.push(Box::pin(future::pending()));
+
+    loop {
+        let msg = match future::select(
+            redshirt_syscalls_interface::next_interface_message(),
+            tasks.next(),
+        )
+        .await
+        {
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m),
+                _,
+            )) => m,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg),
+                _,
+            )) => {
+                responses.retain(|_, (owner, _)| *owner != msg.pid);
+                continue;
+            }
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_),
+                _,
+            )) => continue,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_),
+                _,
+            )) => continue,
+            future::Either::Right((response_id, _)) => {
+                responses.remove(&response_id.unwrap());
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::HttpClientMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        // Returns the channel to the task of a response, if it belongs to the emitter.
+        let response_commands = |response_id: u32| {
+            responses
+                .get(&response_id)
+                .filter(|(owner, _)| *owner == msg.emitter_pid)
+                .map(|(_, commands)| commands.clone())
+        };
+
+        match message {
+            ffi::HttpClientMessage::Request(req) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                let response_id = next_response_id;
+                next_response_id = next_response_id.wrapping_add(1);
+                let (tx, rx) = mpsc::unbounded();
+                responses.insert(response_id, (msg.emitter_pid, tx));
+                tasks.push(Box::pin(
+                    request::run(response_id, req, message_id, rx).map(move |()| response_id),
+                ));
+            }
+            ffi::HttpClientMessage::ReadBody(response_id) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                match response_commands(response_id) {
+                    Some(commands) => {
+                        let _ = commands.unbounded_send(request::Command::ReadBody(message_id));
+                    }
+                    None => {
+                        let response = ffi::ReadBodyResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+            }
+            ffi::HttpClientMessage::Close(response_id) => {
+                if response_commands(response_id).is_some() {
+                    responses.remove(&response_id);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sending a request and reading its response.
+
+use crate::url;
+use futures::{channel::mpsc, prelude::*};
+use redshirt_dns_interface::ffi::{RecordData, RecordType};
+use redshirt_http_client_interface::ffi;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId};
+use redshirt_tcp_interface::TcpStream;
+use redshirt_tls_interface::TlsStream;
+use std::{
+    convert::TryFrom as _,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+/// Maximum size of the head of a response.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Maximum number of headers in a response.
+const MAX_HEADERS: usize = 64;
+
+/// Message concerning a response, sent by the main task.
+pub enum Command {
+    /// A [`ffi::HttpClientMessage::ReadBody`] has been received.
+    ReadBody(MessageId),
+}
+
+/// Sends the request, answers `request_message` with the head of the response, then processes
+/// the commands until the sender of `commands` is destroyed or the connection fails.
+// TODO: connections are never reused; support keep-alive
+// TODO: support HTTP/2
+// TODO: the body of the request has to be entirely in memory; allow streaming it
+pub async fn run(
+    response_id: u32,
+    request: ffi::Request,
+    request_message: MessageId,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    let is_head = request.method.eq_ignore_ascii_case("HEAD");
+
+    let (mut connection, head) = match send_request(request).await {
+        Ok(v) => v,
+        Err(err) => {
+            let response = ffi::RequestResponse { result: Err(err) };
+            redshirt_syscalls_interface::emit_answer(request_message, &response);
+            return;
+        }
+    };
+
+    let mut body = match BodyReader::new(is_head, head.status, &head.headers) {
+        Ok(b) => b,
+        Err(err) => {
+            let response = ffi::RequestResponse { result: Err(err) };
+            redshirt_syscalls_interface::emit_answer(request_message, &response);
+            return;
+        }
+    };
+
+    let response = ffi::RequestResponse {
+        result: Ok(ffi::ResponseHead {
+            response_id,
+            status: head.status,
+            headers: head.headers,
+        }),
+    };
+    redshirt_syscalls_interface::emit_answer(request_message, &response);
+
+    while let Some(command) = commands.next().await {
+        match command {
+            Command::ReadBody(message_id) => {
+                let result = body.read(&mut connection).await;
+                let failed = result.is_err();
+                let response = ffi::ReadBodyResponse { result };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+                if failed {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Status and headers of a response.
+struct Head {
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+}
+
+/// Opens a connection, sends the request and reads the head of the response.
+async fn send_request(request: ffi::Request) -> Result<(Connection, Head), ErrorPayload> {
+    let url = url::parse(&request.url)
+        .map_err(|()| ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid URL"))?;
+    if !is_token(&request.method) {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid method"));
+    }
+
+    let ip = resolve(&url.host).await?;
+    let tcp = TcpStream::connect(&SocketAddr::new(ip, url.port))
+        .await
+        .map_err(|()| {
+            ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("connection failed")
+        })?;
+    let transport = if url.tls {
+        Transport::Tls(TlsStream::connect(tcp, url.host.clone()).await?)
+    } else {
+        Transport::Plain(tcp)
+    };
+
+    let mut connection = Connection {
+        transport,
+        buffer: Vec::new(),
+        closed: false,
+    };
+
+    let mut out = Vec::with_capacity(256 + request.body.len());
+    out.extend_from_slice(request.method.as_bytes());
+    out.push(b' ');
+    out.extend_from_slice(url.path_and_query.as_bytes());
+    out.extend_from_slice(b" HTTP/1.1\r\nHost: ");
+    out.extend_from_slice(url.authority.as_bytes());
+    out.extend_from_slice(b"\r\nConnection: close\r\n");
+    if !request.body.is_empty() || !request.method.eq_ignore_ascii_case("GET") {
+        out.extend_from_slice(format!("Content-Length: {}\r\n", request.body.len()).as_bytes());
+    }
+    for (name, value) in &request.headers {
+        if !is_token(name) || value.iter().any(|b| *b == b'\r' || *b == b'\n') {
+            return Err(
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid header")
+            );
+        }
+        if ["host", "connection", "content-length"]
+            .iter()
+            .any(|h| name.eq_ignore_ascii_case(h))
+        {
+            continue;
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(&request.body);
+    connection.transport.write(out).await?;
+
+    loop {
+        let head = connection.read_head().await?;
+        // Informational responses are followed with the actual response.
+        if head.status >= 100 && head.status < 200 && head.status != 101 {
+            continue;
+        }
+        return Ok((connection, head));
+    }
+}
+
+/// Turns a host into an IP address, asking the DNS interface if necessary.
+async fn resolve(host: &str) -> Result<IpAddr, ErrorPayload> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+
+    for record_type in &[RecordType::A, RecordType::Aaaa] {
+        let records = match redshirt_dns_interface::resolve(host, *record_type).await {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        for record in records {
+            match record.data {
+                RecordData::A(ip) => return Ok(IpAddr::from(Ipv4Addr::from(ip))),
+                RecordData::Aaaa(ip) => return Ok(IpAddr::from(Ipv6Addr::from(ip))),
+                _ => {}
+            }
+        }
+    }
+
+    Err(ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("failed to resolve host"))
+}
+
+/// Returns true if `s` is a valid method or header name.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Stream of bytes to the server.
+enum Transport {
+    Plain(TcpStream),
+    Tls(TlsStream),
+}
+
+impl Transport {
+    /// Reads some data. Returns an empty `Vec` if the server has closed the connection.
+    async fn read(&mut self) -> Result<Vec<u8>, ErrorPayload> {
+        match self {
+            Transport::Plain(tcp) => {
+                let mut buffer = vec![0; 4096];
+                let num_read = tcp.read(&mut buffer).await.map_err(io_error)?;
+                buffer.truncate(num_read);
+                Ok(buffer)
+            }
+            Transport::Tls(tls) => tls.read().await,
+        }
+    }
+
+    async fn write(&mut self, data: Vec<u8>) -> Result<(), ErrorPayload> {
+        match self {
+            Transport::Plain(tcp) => {
+                tcp.write_all(&data).await.map_err(io_error)?;
+                tcp.flush().await.map_err(io_error)
+            }
+            Transport::Tls(tls) => tls.write(data).await,
+        }
+    }
+}
+
+fn io_error(err: std::io::Error) -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message(err.to_string())
+}
+
+/// Transport plus the data received but not processed yet.
+struct Connection {
+    transport: Transport,
+    buffer: Vec<u8>,
+    /// True if the server has closed the connection.
+    closed: bool,
+}
+
+impl Connection {
+    /// Reads more data into the buffer. Returns false if the connection is closed.
+    async fn fill(&mut self) -> Result<bool, ErrorPayload> {
+        if self.closed {
+            return Ok(false);
+        }
+        let data = self.transport.read().await?;
+        if data.is_empty() {
+            self.closed = true;
+            return Ok(false);
+        }
+        self.buffer.extend_from_slice(&data);
+        Ok(true)
+    }
+
+    /// Extracts up to `max` bytes from the buffer, reading from the transport if it is empty.
+    /// Returns an empty `Vec` only if the connection is closed.
+    async fn take(&mut self, max: usize) -> Result<Vec<u8>, ErrorPayload> {
+        if self.buffer.is_empty() {
+            self.fill().await?;
+        }
+        let len = std::cmp::min(max, self.buffer.len());
+        Ok(self.buffer.drain(..len).collect())
+    }
+
+    /// Extracts a line, without its line ending.
+    async fn read_line(&mut self) -> Result<Vec<u8>, ErrorPayload> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(line);
+            }
+            if self.buffer.len() > MAX_HEAD_SIZE {
+                return Err(protocol_error("line too long"));
+            }
+            if !self.fill().await? {
+                return Err(protocol_error("connection closed unexpectedly"));
+            }
+        }
+    }
+
+    /// Reads and parses the status line and headers.
+    async fn read_head(&mut self) -> Result<Head, ErrorPayload> {
+        loop {
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut response = httparse::Response::new(&mut headers);
+            match response.parse(&self.buffer) {
+                Ok(httparse::Status::Complete(len)) => {
+                    let head = Head {
+                        status: response.code.unwrap(),
+                        headers: response
+                            .headers
+                            .iter()
+                            .map(|h| (h.name.to_owned(), h.value.to_owned()))
+                            .collect(),
+                    };
+                    self.buffer.drain(..len);
+                    return Ok(head);
+                }
+                Ok(httparse::Status::Partial) => {}
+                Err(err) => return Err(protocol_error(&err.to_string())),
+            }
+
+            if self.buffer.len() > MAX_HEAD_SIZE {
+                return Err(protocol_error("response head too large"));
+            }
+            if !self.fill().await? {
+                return Err(protocol_error("connection closed unexpectedly"));
+            }
+        }
+    }
+}
+
+fn protocol_error(msg: &str) -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message(format!("protocol error: {}", msg))
+}
+
+/// How the end of the body is delimited.
+enum BodyReader {
+    /// The given number of bytes remain.
+    Length(u64),
+    /// Chunked transfer encoding. Contains the number of bytes remaining in the current chunk,
+    /// or `None` if the size of the next chunk must be read.
+    Chunked(Option<u64>),
+    /// The body ends when the server closes the connection.
+    UntilClose,
+    /// The entire body has been read.
+    Finished,
+}
+
+impl BodyReader {
+    fn new(
+        is_head: bool,
+        status: u16,
+        headers: &[(String, Vec<u8>)],
+    ) -> Result<BodyReader, ErrorPayload> {
+        if is_head || status == 204 || status == 304 || (status >= 100 && status < 200) {
+            return Ok(BodyReader::Finished);
+        }
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v)
+        };
+
+        if let Some(encoding) = header("Transfer-Encoding") {
+            let chunked = encoding
+                .split(|b| *b == b',')
+                .last()
+                .map(|e| {
+                    String::from_utf8_lossy(e)
+                        .trim()
+                        .eq_ignore_ascii_case("chunked")
+                })
+                .unwrap_or(false);
+            return Ok(if chunked {
+                BodyReader::Chunked(None)
+            } else {
+                BodyReader::UntilClose
+            });
+        }
+
+        if let Some(length) = header("Content-Length") {
+            let length = std::str::from_utf8(length)
+                .ok()
+                .and_then(|l| l.trim().parse().ok())
+                .ok_or_else(|| protocol_error("invalid Content-Length"))?;
+            return Ok(BodyReader::Length(length));
+        }
+
+        Ok(BodyReader::UntilClose)
+    }
+
+    /// Reads the next part of the body. Returns an empty `Vec` if the body is finished.
+    async fn read(&mut self, connection: &mut Connection) -> Result<Vec<u8>, ErrorPayload> {
+        loop {
+            match self {
+                BodyReader::Finished => return Ok(Vec::new()),
+                BodyReader::Length(0) => *self = BodyReader::Finished,
+                BodyReader::Length(remaining) => {
+                    let max = usize::try_from(*remaining).unwrap_or(usize::max_value());
+                    let data = connection.take(max).await?;
+                    if data.is_empty() {
+                        return Err(protocol_error("connection closed unexpectedly"));
+                    }
+                    *remaining -= u64::try_from(data.len()).unwrap();
+                    return Ok(data);
+                }
+                BodyReader::UntilClose => {
+                    let data = connection.take(usize::max_value()).await?;
+                    if data.is_empty() {
+                        *self = BodyReader::Finished;
+                    }
+                    return Ok(data);
+                }
+                BodyReader::Chunked(None) => {
+                    let line = connection.read_line().await?;
+                    // Chunk extensions are ignored.
+                    let size = line.split(|b| *b == b';').next().unwrap();
+                    let size = std::str::from_utf8(size)
+                        .ok()
+                        .and_then(|s| u64::from_str_radix(s.trim(), 16).ok())
+                        .ok_or_else(|| protocol_error("invalid chunk size"))?;
+                    if size == 0 {
+                        // Trailers are discarded.
+                        while !connection.read_line().await?.is_empty() {}
+                        *self = BodyReader::Finished;
+                    } else {
+                        *self = BodyReader::Chunked(Some(size));
+                    }
+                }
+                BodyReader::Chunked(Some(0)) => {
+                    if !connection.read_line().await?.is_empty() {
+                        return Err(protocol_error("invalid chunk"));
+                    }
+                    *self = BodyReader::Chunked(None);
+                }
+                BodyReader::Chunked(Some(remaining)) => {
+                    let max = usize::try_from(*remaining).unwrap_or(usize::max_value());
+                    let data = connection.take(max).await?;
+                    if data.is_empty() {
+                        return Err(protocol_error("connection closed unexpectedly"));
+                    }
+                    *remaining -= u64::try_from(data.len()).unwrap();
+                    return Ok(data);
+                }
+            }
+        }
+    }
+}
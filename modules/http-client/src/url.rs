@@ -0,0 +1,86 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parsing of URLs.
+
+/// Components of an `http` or `https` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    /// True for `https`.
+    pub tls: bool,
+    /// Host name or IP address. IPv6 addresses are not surrounded by brackets.
+    pub host: String,
+    pub port: u16,
+    /// Host and port as found in the URL, to put in the `Host` header.
+    pub authority: String,
+    /// Path and query, starting with `/`.
+    pub path_and_query: String,
+}
+
+/// Parses an absolute `http` or `https` URL.
+pub fn parse(url: &str) -> Result<Url, ()> {
+    let (tls, rest) = if url.len() >= 7 && url[..7].eq_ignore_ascii_case("http://") {
+        (false, &url[7..])
+    } else if url.len() >= 8 && url[..8].eq_ignore_ascii_case("https://") {
+        (true, &url[8..])
+    } else {
+        return Err(());
+    };
+
+    // The fragment is never sent to the server.
+    let rest = rest.split('#').next().unwrap();
+    let (authority, path_and_query) = match rest.find(|c| c == '/' || c == '?') {
+        Some(pos) if rest[pos..].starts_with('/') => (&rest[..pos], rest[pos..].to_owned()),
+        Some(pos) => (&rest[..pos], format!("/{}", &rest[pos..])),
+        None => (rest, "/".to_owned()),
+    };
+
+    // Credentials aren't supported, but we strip them anyway.
+    let authority = authority.rsplit('@').next().unwrap();
+
+    let (host, port) = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(())?;
+        (&authority[1..end], &authority[end + 1..])
+    } else {
+        match authority.rfind(':') {
+            Some(pos) => (&authority[..pos], &authority[pos..]),
+            None => (authority, ""),
+        }
+    };
+
+    let port = if port.is_empty() {
+        if tls {
+            443
+        } else {
+            80
+        }
+    } else if port.starts_with(':') {
+        port[1..].parse().map_err(|_| ())?
+    } else {
+        return Err(());
+    };
+
+    if host.is_empty() {
+        return Err(());
+    }
+
+    Ok(Url {
+        tls,
+        host: host.to_owned(),
+        port,
+        authority: authority.to_owned(),
+        path_and_query,
+    })
+}
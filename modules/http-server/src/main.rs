@@ -13,67 +13,210 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use futures::{channel::mpsc, prelude::*};
+//! HTTP server listening on port 8000.
+//!
+//! Registers the HTTP server interface. Other programs register path prefixes, and the
+//! requests whose path matches one of these prefixes are delivered to them through messages.
+//! Requests that don't match any prefix get a 404 response.
+
+mod routes;
+
+use futures::{channel::mpsc, channel::oneshot, prelude::*};
+use parity_scale_codec::DecodeAll;
+use redshirt_http_server_interface::ffi;
 use std::{pin::Pin, task::Context, task::Poll};
 
+/// Maximum size of the body of a request. Larger requests get a 413 response.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
 fn main() {
-    redshirt_syscalls_interface::block_on(async move {
-        let listener = redshirt_tcp_interface::TcpListener::bind(&"0.0.0.0:8000".parse().unwrap())
-            .await
-            .unwrap();
+    redshirt_syscalls_interface::block_on(async_main());
+}
 
-        println!("Now listening on 0.0.0.0:8000");
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
 
-        let stream = stream::unfold(listener, |mut l| {
-            async move {
-                let connec = l.accept().await.0;
-                Some((connec, l))
-            }
-        });
+    let listener = redshirt_tcp_interface::TcpListener::bind(&"0.0.0.0:8000".parse().unwrap())
+        .await
+        .unwrap();
+
+    println!("Now listening on 0.0.0.0:8000");
+
+    let stream = stream::unfold(listener, |mut l| async move {
+        let connec = l.accept().await.0;
+        Some((connec, l))
+    });
+
+    let mut active_conncs = stream::FuturesUnordered::<Pin<Box<dyn Future<Output = ()>>>>::new();
+    active_conncs.push(Box::pin(future::pending()));
+    let (tx, mut rx) = mpsc::unbounded();
+    let (requests_tx, mut requests_rx) = mpsc::unbounded();
+
+    let http = hyper::server::conn::Http::new().with_executor(Executor { pusher: tx });
 
-        let mut active_conncs =
-            stream::FuturesUnordered::<Pin<Box<dyn Future<Output = ()>>>>::new();
-        active_conncs.push(Box::pin(future::pending()));
-        let (tx, mut rx) = mpsc::unbounded();
+    let mut server = hyper::server::Builder::new(
+        Accept {
+            next_connec: Box::pin(stream),
+        },
+        http,
+    )
+    .serve(hyper::service::make_service_fn(move |_| {
+        let requests_tx = requests_tx.clone();
+        async move {
+            Ok::<_, std::io::Error>(hyper::service::service_fn(move |req| {
+                handle_request(req, requests_tx.clone())
+            }))
+        }
+    }));
+
+    let mut routes = routes::Routes::new();
 
-        let http = hyper::server::conn::Http::new().with_executor(Executor { pusher: tx });
+    loop {
+        let server_event =
+            future::select(future::select(&mut server, rx.next()), active_conncs.next());
+        let other_event = future::select(
+            requests_rx.next(),
+            redshirt_syscalls_interface::next_interface_message(),
+        );
 
-        let mut server = hyper::server::Builder::new(
-            Accept {
-                next_connec: Box::pin(stream),
+        let msg = match future::select(server_event, other_event).await {
+            future::Either::Left((future::Either::Left((future::Either::Left((_, _)), _)), _)) => {
+                println!("server finished");
+                break;
+            }
+            future::Either::Left((
+                future::Either::Left((future::Either::Right((new_connec, _)), _)),
+                _,
+            )) => {
+                active_conncs.push(new_connec.unwrap());
+                continue;
+            }
+            future::Either::Left((future::Either::Right((_, _)), _)) => continue,
+            future::Either::Right((future::Either::Left((request, _)), _)) => {
+                routes.incoming(request.unwrap());
+                continue;
+            }
+            future::Either::Right((future::Either::Right((event, _)), _)) => match event {
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+                redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                    routes.process_destroyed(msg.pid);
+                    continue;
+                }
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg) => {
+                    routes.message_cancelled(msg.message_id);
+                    continue;
+                }
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
             },
-            http,
-        )
-        .serve(hyper::service::make_service_fn(|_| {
-            async {
-                Ok::<_, std::io::Error>(hyper::service::service_fn(|_req| {
-                    async {
-                        Ok::<_, std::io::Error>(hyper::Response::new(hyper::Body::from(
-                            "Hello World",
-                        )))
-                    }
-                }))
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::HttpServerMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        match message {
+            ffi::HttpServerMessage::Register(prefix) => {
+                let result = routes.register(msg.emitter_pid, prefix);
+                if let Some(message_id) = msg.message_id {
+                    let response = ffi::RegisterResponse { result };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+            }
+            ffi::HttpServerMessage::Unregister(route_id) => {
+                routes.unregister(msg.emitter_pid, route_id);
+            }
+            ffi::HttpServerMessage::NextRequest(route_id) => {
+                if let Some(message_id) = msg.message_id {
+                    routes.next_request(msg.emitter_pid, route_id, message_id);
+                }
+            }
+            ffi::HttpServerMessage::Respond(response) => {
+                routes.respond(msg.emitter_pid, response);
             }
-        }));
-
-        loop {
-            let new_connec =
-                match future::select(future::select(&mut server, rx.next()), active_conncs.next())
-                    .await
-                {
-                    future::Either::Left((future::Either::Left((_, _)), _)) => {
-                        println!("server finished");
-                        break;
-                    }
-                    future::Either::Left((future::Either::Right((new_connec, _)), _)) => {
-                        new_connec.unwrap()
-                    }
-                    future::Either::Right((_, _)) => continue,
-                };
-
-            active_conncs.push(new_connec);
         }
-    });
+    }
+}
+
+/// Reads the body of a request, then hands it to the main task and waits for the response.
+async fn handle_request(
+    req: hyper::Request<hyper::Body>,
+    requests: mpsc::UnboundedSender<routes::IncomingRequest>,
+) -> Result<hyper::Response<hyper::Body>, std::io::Error> {
+    let (parts, mut body) = req.into_parts();
+
+    let mut body_data = Vec::new();
+    loop {
+        let chunk =
+            future::poll_fn(|cx| hyper::body::Payload::poll_data(Pin::new(&mut body), cx)).await;
+        match chunk {
+            Some(Ok(chunk)) => {
+                if body_data.len() + chunk.len() > MAX_BODY_SIZE {
+                    return Ok(empty_response(413));
+                }
+                body_data.extend_from_slice(&chunk);
+            }
+            Some(Err(_)) => return Ok(empty_response(400)),
+            None => break,
+        }
+    }
+
+    let (response_tx, response_rx) = oneshot::channel();
+    let request = routes::IncomingRequest {
+        method: parts.method.as_str().to_owned(),
+        path: parts
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str().to_owned())
+            .unwrap_or_else(|| "/".to_owned()),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_vec()))
+            .collect(),
+        body: body_data,
+        response: response_tx,
+    };
+
+    if requests.unbounded_send(request).is_err() {
+        return Ok(empty_response(503));
+    }
+
+    let response = match response_rx.await {
+        Ok(r) => r,
+        Err(_) => return Ok(empty_response(503)),
+    };
+
+    let mut out = hyper::Response::new(hyper::Body::from(response.body));
+    *out.status_mut() = hyper::StatusCode::from_u16(response.status)
+        .unwrap_or(hyper::StatusCode::INTERNAL_SERVER_ERROR);
+    for (name, value) in response.headers {
+        // The length of the body is determined by hyper.
+        if name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        let name = hyper::header::HeaderName::from_bytes(name.as_bytes());
+        let value = hyper::header::HeaderValue::from_bytes(&value);
+        if let (Ok(name), Ok(value)) = (name, value) {
+            out.headers_mut().append(name, value);
+        }
+    }
+    Ok(out)
+}
+
+/// Builds a response with the given status code and an empty body.
+fn empty_response(status: u16) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::new(hyper::Body::empty());
+    *response.status_mut() = hyper::StatusCode::from_u16(status).unwrap();
+    response
 }
 
 struct Accept {
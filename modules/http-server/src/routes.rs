@@ -0,0 +1,215 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State of the routes registered through the HTTP server interface.
+
+use futures::channel::oneshot;
+use redshirt_http_server_interface::ffi;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId, Pid};
+use std::collections::{HashMap, VecDeque};
+
+/// Maximum number of requests waiting to be picked up on a route. Additional requests get a 503
+/// response.
+const MAX_QUEUED_REQUESTS: usize = 32;
+
+/// Request received from the network, waiting to be delivered.
+pub struct IncomingRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+    /// Where to send the response. Destroying the sender produces a 503 response.
+    pub response: oneshot::Sender<ffi::Response>,
+}
+
+pub struct Routes {
+    routes: HashMap<u32, Route>,
+    next_route_id: u32,
+    /// Requests that have been assigned to a route but not answered yet.
+    pending: HashMap<u64, PendingRequest>,
+    next_request_id: u64,
+}
+
+struct Route {
+    prefix: String,
+    owner: Pid,
+    /// Requests not picked up yet.
+    queue: VecDeque<ffi::Request>,
+    /// [`ffi::HttpServerMessage::NextRequest`] message waiting for a request.
+    waiting: Option<MessageId>,
+}
+
+struct PendingRequest {
+    route_id: u32,
+    owner: Pid,
+    response: oneshot::Sender<ffi::Response>,
+}
+
+impl Routes {
+    pub fn new() -> Routes {
+        Routes {
+            routes: HashMap::new(),
+            next_route_id: 0,
+            pending: HashMap::new(),
+            next_request_id: 0,
+        }
+    }
+
+    /// Registers a new route. Returns an error if the prefix is invalid or already registered.
+    pub fn register(&mut self, owner: Pid, prefix: String) -> Result<u32, ErrorPayload> {
+        if !prefix.starts_with('/') {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("prefix must start with /"));
+        }
+        if self.routes.values().any(|r| r.prefix == prefix) {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("prefix already registered"));
+        }
+
+        let route_id = self.next_route_id;
+        self.next_route_id = self.next_route_id.wrapping_add(1);
+        self.routes.insert(
+            route_id,
+            Route {
+                prefix,
+                owner,
+                queue: VecDeque::new(),
+                waiting: None,
+            },
+        );
+        Ok(route_id)
+    }
+
+    /// Unregisters a route, if it belongs to `emitter`.
+    pub fn unregister(&mut self, emitter: Pid, route_id: u32) {
+        match self.routes.get(&route_id) {
+            Some(route) if route.owner == emitter => {}
+            _ => return,
+        }
+
+        let route = self.routes.remove(&route_id).unwrap();
+        if let Some(message_id) = route.waiting {
+            let response = ffi::NextRequestResponse {
+                result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+            };
+            redshirt_syscalls_interface::emit_answer(message_id, &response);
+        }
+        self.pending.retain(|_, p| p.route_id != route_id);
+    }
+
+    /// Processes a [`ffi::HttpServerMessage::NextRequest`].
+    pub fn next_request(&mut self, emitter: Pid, route_id: u32, message_id: MessageId) {
+        let result = match self.routes.get_mut(&route_id) {
+            Some(route) if route.owner == emitter => {
+                if route.waiting.is_some() {
+                    Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("a request is already being waited for"))
+                } else if let Some(request) = route.queue.pop_front() {
+                    Ok(request)
+                } else {
+                    route.waiting = Some(message_id);
+                    return;
+                }
+            }
+            _ => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+        };
+
+        let response = ffi::NextRequestResponse { result };
+        redshirt_syscalls_interface::emit_answer(message_id, &response);
+    }
+
+    /// Processes a [`ffi::HttpServerMessage::Respond`].
+    pub fn respond(&mut self, emitter: Pid, response: ffi::Response) {
+        match self.pending.get(&response.request_id) {
+            Some(pending) if pending.owner == emitter => {}
+            _ => return,
+        }
+
+        let pending = self.pending.remove(&response.request_id).unwrap();
+        let _ = pending.response.send(response);
+    }
+
+    /// Dispatches a request received from the network to the route with the longest matching
+    /// prefix.
+    pub fn incoming(&mut self, request: IncomingRequest) {
+        let route_id = self
+            .routes
+            .iter()
+            .filter(|(_, r)| request.path.starts_with(&r.prefix))
+            .max_by_key(|(_, r)| r.prefix.len())
+            .map(|(id, _)| *id);
+        let route_id = match route_id {
+            Some(id) => id,
+            None => {
+                let _ = request.response.send(ffi::Response {
+                    request_id: 0,
+                    status: 404,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                });
+                return;
+            }
+        };
+
+        let route = self.routes.get_mut(&route_id).unwrap();
+        if route.queue.len() >= MAX_QUEUED_REQUESTS {
+            // Dropping the sender produces a 503.
+            return;
+        }
+
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        self.pending.insert(
+            request_id,
+            PendingRequest {
+                route_id,
+                owner: route.owner,
+                response: request.response,
+            },
+        );
+
+        let request = ffi::Request {
+            request_id,
+            method: request.method,
+            path: request.path,
+            headers: request.headers,
+            body: request.body,
+        };
+
+        if let Some(message_id) = route.waiting.take() {
+            let response = ffi::NextRequestResponse {
+                result: Ok(request),
+            };
+            redshirt_syscalls_interface::emit_answer(message_id, &response);
+        } else {
+            route.queue.push_back(request);
+        }
+    }
+
+    /// Removes the routes and requests of a process that has terminated.
+    pub fn process_destroyed(&mut self, pid: Pid) {
+        self.routes.retain(|_, r| r.owner != pid);
+        self.pending.retain(|_, p| p.owner != pid);
+    }
+
+    /// Forgets about a [`ffi::HttpServerMessage::NextRequest`] that has been cancelled.
+    pub fn message_cancelled(&mut self, message_id: MessageId) {
+        for route in self.routes.values_mut() {
+            if route.waiting == Some(message_id) {
+                route.waiting = None;
+            }
+        }
+    }
+}
@@ -42,7 +42,7 @@ async fn async_main() {
         if device.vendor_id == 0x10ec && device.device_id == 0x8029 {
             let port_number = device.base_address_registers.iter().filter_map(|bar| {
                 match bar {
-                    redshirt_pci_interface::PciBaseAddressRegister::Io { base_address } if *base_address != 0 => Some(*base_address),
+                    Some(redshirt_pci_interface::PciBaseAddressRegister::Io { base_address, .. }) if *base_address != 0 => Some(*base_address),
                     _ => None
                 }
             }).next();
@@ -0,0 +1,154 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Starts the drivers of the network controllers, and registers their cards towards the network
+//! manager.
+//!
+//! This program subscribes to the events of the device manager. Each network controller on the
+//! PCI bus (class `0x2`) that isn't assigned to a driver yet is claimed and initialized by the
+//! driver that supports it, if any. The card is then registered through the Ethernet interface,
+//! handled by the network manager, and is unregistered when the device disappears.
+
+use futures::{channel::oneshot, prelude::*};
+use redshirt_device_manager_interface::{Bus, DeviceEvent};
+use redshirt_pci_interface::PciDeviceInfo;
+use std::{collections::HashMap, pin::Pin};
+
+/// Name of the driver, as reported to the device manager.
+const DRIVER_NAME: &str = "network-binder";
+
+/// PCI class of the network controllers.
+const NETWORK_CONTROLLER_CLASS: u8 = 0x2;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut events = redshirt_device_manager_interface::events();
+
+    // For each device being driven, a sender that stops the task of the device when destroyed.
+    let mut cards = HashMap::<u64, oneshot::Sender<()>>::new();
+    // Tasks driving the cards. Each task yields the identifier of its device when it ends.
+    let mut tasks = stream::FuturesUnordered::<Pin<Box<dyn Future<Output = u64>>>>::new();
+    // `FuturesUnordered` yields `None` when empty, which we don't want.
+    tasks.push(Box::pin(future::pending()));
+
+    loop {
+        let event = match future::select(events.next(), tasks.next()).await {
+            future::Either::Left((Some(event), _)) => event,
+            // The subscription has been interrupted.
+            future::Either::Left((None, _)) => return,
+            future::Either::Right((id, _)) => {
+                cards.remove(&id.unwrap());
+                continue;
+            }
+        };
+
+        match event {
+            DeviceEvent::Added(device) => {
+                if device.driver.is_some()
+                    || device.description.bus != Bus::Pci
+                    || device.description.class != NETWORK_CONTROLLER_CLASS
+                {
+                    continue;
+                }
+
+                let pci_device = match find_pci_device(&device.description.location).await {
+                    Some(d) => d,
+                    None => continue,
+                };
+                if !Card::is_supported(&pci_device) {
+                    continue;
+                }
+
+                let claim = redshirt_device_manager_interface::claim(device.id, DRIVER_NAME.into());
+                if claim.await.is_err() {
+                    continue;
+                }
+
+                let card = match Card::init(&pci_device).await {
+                    Some(c) => c,
+                    None => {
+                        redshirt_device_manager_interface::release(device.id);
+                        continue;
+                    }
+                };
+
+                let (stop_tx, stop_rx) = oneshot::channel();
+                cards.insert(device.id, stop_tx);
+                let id = device.id;
+                tasks.push(Box::pin(
+                    future::select(Box::pin(card.run()), stop_rx).map(move |_| id),
+                ));
+            }
+            DeviceEvent::Removed(device) => {
+                // Destroying the sender stops the task, which unregisters the card.
+                cards.remove(&device.id);
+            }
+            DeviceEvent::DriverChanged(_) => {}
+        }
+    }
+}
+
+/// Network card driven by one of the drivers.
+enum Card {
+    E1000(e1000::Device),
+    Realtek(realtek::Device),
+    VirtioNet(virtio_net::Device),
+}
+
+impl Card {
+    /// Returns true if one of the drivers supports the given PCI device.
+    fn is_supported(device: &PciDeviceInfo) -> bool {
+        e1000::is_supported(device)
+            || realtek::is_supported(device)
+            || virtio_net::is_supported(device)
+    }
+
+    /// Initializes the given PCI device with the driver that supports it.
+    async fn init(device: &PciDeviceInfo) -> Option<Card> {
+        if e1000::is_supported(device) {
+            e1000::init(device).await.map(Card::E1000)
+        } else if realtek::is_supported(device) {
+            realtek::init(device).await.map(Card::Realtek)
+        } else if virtio_net::is_supported(device) {
+            virtio_net::init(device).await.map(Card::VirtioNet)
+        } else {
+            None
+        }
+    }
+
+    /// Registers the card through the Ethernet interface and transfers frames between the card
+    /// and the network manager. Never finishes.
+    async fn run(self) {
+        match self {
+            Card::E1000(device) => e1000::run_device(device).await,
+            Card::Realtek(device) => realtek::run_device(device).await,
+            Card::VirtioNet(device) => virtio_net::run_device(device).await,
+        }
+    }
+}
+
+/// Returns the PCI device at the given location, in the format reported to the device manager.
+async fn find_pci_device(location: &str) -> Option<PciDeviceInfo> {
+    redshirt_pci_interface::get_pci_devices()
+        .await
+        .into_iter()
+        .find(|device| {
+            let l = &device.location;
+            format!("{:02x}:{:02x}.{}", l.bus, l.device, l.function) == location
+        })
+}
@@ -0,0 +1,107 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implementation of the smoltcp `Device` trait on top of queues of Ethernet frames.
+//!
+//! Frames received from the driver are pushed to the queue of received frames, and the frames
+//! generated by smoltcp are pushed to the queue of frames to send, which the driver pulls from.
+
+use smoltcp::phy::{self, DeviceCapabilities};
+use smoltcp::time::Instant;
+use std::collections::VecDeque;
+
+/// Maximum number of frames in each queue. Frames received while the queue of received frames
+/// is full are discarded, and smoltcp stops transmitting while the queue of frames to send is
+/// full.
+const MAX_QUEUED_FRAMES: usize = 64;
+
+/// Size of an Ethernet frame, including its header but not its CRC, with a payload of 1500
+/// bytes.
+const MAX_FRAME_SIZE: usize = 1514;
+
+/// Queues of Ethernet frames of a network interface.
+#[derive(Default)]
+pub struct FrameQueues {
+    received: VecDeque<Vec<u8>>,
+    to_send: VecDeque<Vec<u8>>,
+}
+
+impl FrameQueues {
+    /// Adds a frame received from the network. Returns `false` if the frame has been discarded.
+    pub fn push_received(&mut self, frame: Vec<u8>) -> bool {
+        if self.received.len() >= MAX_QUEUED_FRAMES {
+            return false;
+        }
+
+        self.received.push_back(frame);
+        true
+    }
+
+    /// Pops the next frame to send to the network.
+    pub fn pop_to_send(&mut self) -> Option<Vec<u8>> {
+        self.to_send.pop_front()
+    }
+}
+
+impl<'a> phy::Device<'a> for FrameQueues {
+    type RxToken = RxToken;
+    type TxToken = TxToken<'a>;
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let frame = self.received.pop_front()?;
+        Some((RxToken(frame), TxToken(&mut self.to_send)))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        if self.to_send.len() >= MAX_QUEUED_FRAMES {
+            return None;
+        }
+
+        Some(TxToken(&mut self.to_send))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.max_transmission_unit = MAX_FRAME_SIZE;
+        capabilities
+    }
+}
+
+/// Frame received from the network.
+pub struct RxToken(Vec<u8>);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, _: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        f(&mut self.0)
+    }
+}
+
+/// Permission to push a frame to the queue of frames to send.
+pub struct TxToken<'a>(&'a mut VecDeque<Vec<u8>>);
+
+impl<'a> phy::TxToken for TxToken<'a> {
+    fn consume<R, F>(self, _: Instant, len: usize, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        let mut frame = vec![0; len];
+        let outcome = f(&mut frame)?;
+        self.0.push_back(frame);
+        Ok(outcome)
+    }
+}
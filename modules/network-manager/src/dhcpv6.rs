@@ -0,0 +1,1041 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DHCPv6 client, as defined in RFC 8415.
+//!
+//! The [`Client`] doesn't perform any I/O by itself. The datagrams received on
+//! [`CLIENT_PORT`] must be passed to [`Client::on_datagram`], and the datagrams returned by
+//! [`Client::poll`] must be sent to [`SERVERS_ADDRESS`] on [`SERVER_PORT`].
+//!
+//! The client asks for addresses (`IA_NA`), for a delegated prefix (`IA_PD`), and for the list
+//! of DNS servers. The first server that advertises an address or a prefix is selected.
+
+use std::convert::TryFrom as _;
+
+/// UDP port the client listens on.
+pub const CLIENT_PORT: u16 = 546;
+
+/// UDP port the servers and relay agents listen on.
+pub const SERVER_PORT: u16 = 547;
+
+/// `All_DHCP_Relay_Agents_and_Servers` multicast address.
+pub const SERVERS_ADDRESS: [u16; 8] = [0xff02, 0, 0, 0, 0, 0, 1, 2];
+
+const SOLICIT: u8 = 1;
+const ADVERTISE: u8 = 2;
+const REQUEST: u8 = 3;
+const RENEW: u8 = 5;
+const REBIND: u8 = 6;
+const REPLY: u8 = 7;
+
+const OPTION_CLIENTID: u16 = 1;
+const OPTION_SERVERID: u16 = 2;
+const OPTION_IA_NA: u16 = 3;
+const OPTION_IAADDR: u16 = 5;
+const OPTION_ORO: u16 = 6;
+const OPTION_ELAPSED_TIME: u16 = 8;
+const OPTION_DNS_SERVERS: u16 = 23;
+const OPTION_IA_PD: u16 = 25;
+const OPTION_IAPREFIX: u16 = 26;
+
+/// Lifetime meaning that an address or a prefix never expires, in seconds.
+const INFINITY: u32 = 0xffff_ffff;
+
+/// Retransmission parameters of the messages, in milliseconds, as defined in section 7.6 of
+/// RFC 8415.
+const SOL_TIMEOUT: u64 = 1_000;
+const SOL_MAX_RT: u64 = 3_600_000;
+const REQ_TIMEOUT: u64 = 1_000;
+const REQ_MAX_RT: u64 = 30_000;
+const REQ_MAX_RC: u32 = 10;
+const REN_TIMEOUT: u64 = 10_000;
+const REN_MAX_RT: u64 = 600_000;
+const REB_TIMEOUT: u64 = 10_000;
+const REB_MAX_RT: u64 = 600_000;
+
+/// DHCPv6 message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub ty: u8,
+    /// Only the 24 lowest bits are transmitted.
+    pub transaction_id: u32,
+    pub options: Vec<DhcpOption>,
+}
+
+/// Option of a [`Message`] or of an [`IdentityAssociation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhcpOption {
+    ClientId(Vec<u8>),
+    ServerId(Vec<u8>),
+    IaNa(IdentityAssociation),
+    IaAddress(IaAddress),
+    OptionRequest(Vec<u16>),
+    /// Time since the start of the exchange, in hundredths of a second.
+    ElapsedTime(u16),
+    DnsServers(Vec<[u16; 8]>),
+    IaPd(IdentityAssociation),
+    IaPrefix(IaPrefix),
+    Other {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// Content of the `IA_NA` and `IA_PD` options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityAssociation {
+    pub iaid: u32,
+    /// Times after which the client contacts the server again, in seconds.
+    pub t1: u32,
+    pub t2: u32,
+    pub options: Vec<DhcpOption>,
+}
+
+/// Address assigned by a server. Sub-options are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IaAddress {
+    pub address: [u16; 8],
+    /// Lifetimes, in seconds.
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+}
+
+/// Prefix delegated by a server. Sub-options are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IaPrefix {
+    pub prefix: [u16; 8],
+    pub prefix_len: u8,
+    /// Lifetimes, in seconds.
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+}
+
+impl Message {
+    /// Decodes a message. Returns `None` if it is malformed.
+    pub fn decode(data: &[u8]) -> Option<Message> {
+        if data.len() < 4 {
+            return None;
+        }
+
+        Some(Message {
+            ty: data[0],
+            transaction_id: u32::from_be_bytes([0, data[1], data[2], data[3]]),
+            options: decode_options(&data[4..])?,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.ty];
+        out.extend_from_slice(&self.transaction_id.to_be_bytes()[1..]);
+        encode_options(&self.options, &mut out);
+        out
+    }
+
+    fn client_id(&self) -> Option<&[u8]> {
+        self.options.iter().find_map(|opt| match opt {
+            DhcpOption::ClientId(id) => Some(&id[..]),
+            _ => None,
+        })
+    }
+
+    fn server_id(&self) -> Option<&[u8]> {
+        self.options.iter().find_map(|opt| match opt {
+            DhcpOption::ServerId(id) => Some(&id[..]),
+            _ => None,
+        })
+    }
+
+    /// Returns the first `IA_NA` option with the given IAID.
+    fn ia_na(&self, iaid: u32) -> Option<&IdentityAssociation> {
+        self.options.iter().find_map(|opt| match opt {
+            DhcpOption::IaNa(ia) if ia.iaid == iaid => Some(ia),
+            _ => None,
+        })
+    }
+
+    /// Returns the first `IA_PD` option with the given IAID.
+    fn ia_pd(&self, iaid: u32) -> Option<&IdentityAssociation> {
+        self.options.iter().find_map(|opt| match opt {
+            DhcpOption::IaPd(ia) if ia.iaid == iaid => Some(ia),
+            _ => None,
+        })
+    }
+}
+
+impl IdentityAssociation {
+    /// Returns the addresses that haven't expired.
+    fn addresses(&self) -> impl Iterator<Item = &IaAddress> {
+        self.options.iter().filter_map(|opt| match opt {
+            DhcpOption::IaAddress(addr) if addr.valid_lifetime != 0 => Some(addr),
+            _ => None,
+        })
+    }
+
+    /// Returns the prefixes that haven't expired.
+    fn prefixes(&self) -> impl Iterator<Item = &IaPrefix> {
+        self.options.iter().filter_map(|opt| match opt {
+            DhcpOption::IaPrefix(prefix) if prefix.valid_lifetime != 0 => Some(prefix),
+            _ => None,
+        })
+    }
+}
+
+fn decode_options(mut data: &[u8]) -> Option<Vec<DhcpOption>> {
+    let mut options = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return None;
+        }
+        let code = u16::from_be_bytes([data[0], data[1]]);
+        let len = usize::from(u16::from_be_bytes([data[2], data[3]]));
+        if data.len() < 4 + len {
+            return None;
+        }
+        let body = &data[4..4 + len];
+        data = &data[4 + len..];
+
+        options.push(match code {
+            OPTION_CLIENTID => DhcpOption::ClientId(body.to_vec()),
+            OPTION_SERVERID => DhcpOption::ServerId(body.to_vec()),
+            OPTION_IA_NA => DhcpOption::IaNa(decode_identity_association(body)?),
+            OPTION_IA_PD => DhcpOption::IaPd(decode_identity_association(body)?),
+            OPTION_IAADDR if body.len() >= 24 => DhcpOption::IaAddress(IaAddress {
+                address: decode_address(&body[..16]),
+                preferred_lifetime: read_u32(&body[16..]),
+                valid_lifetime: read_u32(&body[20..]),
+            }),
+            OPTION_IAPREFIX if body.len() >= 25 => DhcpOption::IaPrefix(IaPrefix {
+                preferred_lifetime: read_u32(body),
+                valid_lifetime: read_u32(&body[4..]),
+                prefix_len: body[8],
+                prefix: decode_address(&body[9..25]),
+            }),
+            OPTION_IAADDR | OPTION_IAPREFIX => return None,
+            OPTION_ORO if body.len() % 2 == 0 => DhcpOption::OptionRequest(
+                body.chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect(),
+            ),
+            OPTION_ELAPSED_TIME if body.len() == 2 => {
+                DhcpOption::ElapsedTime(u16::from_be_bytes([body[0], body[1]]))
+            }
+            OPTION_DNS_SERVERS if body.len() % 16 == 0 => {
+                DhcpOption::DnsServers(body.chunks(16).map(decode_address).collect())
+            }
+            OPTION_ORO | OPTION_ELAPSED_TIME | OPTION_DNS_SERVERS => return None,
+            code => DhcpOption::Other {
+                code,
+                data: body.to_vec(),
+            },
+        });
+    }
+
+    Some(options)
+}
+
+fn decode_identity_association(body: &[u8]) -> Option<IdentityAssociation> {
+    if body.len() < 12 {
+        return None;
+    }
+
+    Some(IdentityAssociation {
+        iaid: read_u32(body),
+        t1: read_u32(&body[4..]),
+        t2: read_u32(&body[8..]),
+        options: decode_options(&body[12..])?,
+    })
+}
+
+fn encode_options(options: &[DhcpOption], out: &mut Vec<u8>) {
+    for option in options {
+        let mut body = Vec::new();
+        let code = match option {
+            DhcpOption::ClientId(id) => {
+                body.extend_from_slice(id);
+                OPTION_CLIENTID
+            }
+            DhcpOption::ServerId(id) => {
+                body.extend_from_slice(id);
+                OPTION_SERVERID
+            }
+            DhcpOption::IaNa(ia) => {
+                encode_identity_association(ia, &mut body);
+                OPTION_IA_NA
+            }
+            DhcpOption::IaAddress(addr) => {
+                encode_address(&addr.address, &mut body);
+                body.extend_from_slice(&addr.preferred_lifetime.to_be_bytes());
+                body.extend_from_slice(&addr.valid_lifetime.to_be_bytes());
+                OPTION_IAADDR
+            }
+            DhcpOption::OptionRequest(codes) => {
+                for code in codes {
+                    body.extend_from_slice(&code.to_be_bytes());
+                }
+                OPTION_ORO
+            }
+            DhcpOption::ElapsedTime(time) => {
+                body.extend_from_slice(&time.to_be_bytes());
+                OPTION_ELAPSED_TIME
+            }
+            DhcpOption::DnsServers(servers) => {
+                for server in servers {
+                    encode_address(server, &mut body);
+                }
+                OPTION_DNS_SERVERS
+            }
+            DhcpOption::IaPd(ia) => {
+                encode_identity_association(ia, &mut body);
+                OPTION_IA_PD
+            }
+            DhcpOption::IaPrefix(prefix) => {
+                body.extend_from_slice(&prefix.preferred_lifetime.to_be_bytes());
+                body.extend_from_slice(&prefix.valid_lifetime.to_be_bytes());
+                body.push(prefix.prefix_len);
+                encode_address(&prefix.prefix, &mut body);
+                OPTION_IAPREFIX
+            }
+            DhcpOption::Other { code, data } => {
+                body.extend_from_slice(data);
+                *code
+            }
+        };
+
+        out.extend_from_slice(&code.to_be_bytes());
+        out.extend_from_slice(&u16::try_from(body.len()).unwrap().to_be_bytes());
+        out.extend_from_slice(&body);
+    }
+}
+
+fn encode_identity_association(ia: &IdentityAssociation, out: &mut Vec<u8>) {
+    out.extend_from_slice(&ia.iaid.to_be_bytes());
+    out.extend_from_slice(&ia.t1.to_be_bytes());
+    out.extend_from_slice(&ia.t2.to_be_bytes());
+    encode_options(&ia.options, out);
+}
+
+fn decode_address(bytes: &[u8]) -> [u16; 8] {
+    let mut address = [0; 8];
+    for (segment, chunk) in address.iter_mut().zip(bytes.chunks(2)) {
+        *segment = u16::from_be_bytes([chunk[0], chunk[1]]);
+    }
+    address
+}
+
+fn encode_address(address: &[u16; 8], out: &mut Vec<u8>) {
+    for segment in address {
+        out.extend_from_slice(&segment.to_be_bytes());
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Configuration obtained from a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub addresses: Vec<IaAddress>,
+    pub prefixes: Vec<IaPrefix>,
+    pub dns_servers: Vec<[u16; 8]>,
+    /// Time when the lease has been obtained, in milliseconds.
+    obtained_ms: i64,
+    /// Times after which the lease is renewed, then rebound, in milliseconds since
+    /// `obtained_ms`.
+    t1_ms: u64,
+    t2_ms: u64,
+}
+
+impl Lease {
+    fn from_reply(reply: &Message, iaid: u32, now_ms: i64) -> Option<Lease> {
+        let ia_na = reply.ia_na(iaid);
+        let ia_pd = reply.ia_pd(iaid);
+        let addresses = ia_na
+            .into_iter()
+            .flat_map(|ia| ia.addresses())
+            .cloned()
+            .collect::<Vec<_>>();
+        let prefixes = ia_pd
+            .into_iter()
+            .flat_map(|ia| ia.prefixes())
+            .cloned()
+            .collect::<Vec<_>>();
+        if addresses.is_empty() && prefixes.is_empty() {
+            return None;
+        }
+
+        let dns_servers = reply
+            .options
+            .iter()
+            .find_map(|opt| match opt {
+                DhcpOption::DnsServers(servers) => Some(servers.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        // If the server leaves T1 or T2 to the client, they are set to 0.5 and 0.8 times the
+        // shortest preferred lifetime, as recommended in section 21.4 of RFC 8415.
+        let shortest_preferred = addresses
+            .iter()
+            .map(|a| a.preferred_lifetime)
+            .chain(prefixes.iter().map(|p| p.preferred_lifetime))
+            .min()
+            .unwrap();
+        let ias = ia_na
+            .into_iter()
+            .filter(|ia| ia.addresses().next().is_some())
+            .chain(
+                ia_pd
+                    .into_iter()
+                    .filter(|ia| ia.prefixes().next().is_some()),
+            );
+        let scale = |num: u32, den: u32| {
+            if shortest_preferred == INFINITY {
+                INFINITY
+            } else {
+                shortest_preferred / den * num
+            }
+        };
+        let (mut t1, mut t2) = (INFINITY, INFINITY);
+        for ia in ias {
+            t1 = t1.min(if ia.t1 != 0 { ia.t1 } else { scale(1, 2) });
+            t2 = t2.min(if ia.t2 != 0 { ia.t2 } else { scale(4, 5) });
+        }
+
+        Some(Lease {
+            addresses,
+            prefixes,
+            dns_servers,
+            obtained_ms: now_ms,
+            t1_ms: lifetime_ms(t1),
+            t2_ms: lifetime_ms(t2.max(t1)),
+        })
+    }
+
+    /// Removes the addresses and prefixes whose valid lifetime has elapsed. Returns true if
+    /// anything has been removed.
+    fn remove_expired(&mut self, now_ms: i64) -> bool {
+        let obtained_ms = self.obtained_ms;
+        let is_valid = |lifetime: u32| !has_elapsed(obtained_ms, lifetime_ms(lifetime), now_ms);
+        let before = self.addresses.len() + self.prefixes.len();
+        self.addresses.retain(|a| is_valid(a.valid_lifetime));
+        self.prefixes.retain(|p| is_valid(p.valid_lifetime));
+        self.addresses.len() + self.prefixes.len() != before
+    }
+
+    /// Returns the time when the last address or prefix expires, in milliseconds.
+    fn expiration_ms(&self) -> i64 {
+        let longest = self
+            .addresses
+            .iter()
+            .map(|a| a.valid_lifetime)
+            .chain(self.prefixes.iter().map(|p| p.valid_lifetime))
+            .max()
+            .unwrap_or(0);
+        add_ms(self.obtained_ms, lifetime_ms(longest))
+    }
+}
+
+/// Converts a lifetime in seconds into milliseconds. [`INFINITY`] is turned into
+/// `u64::max_value()`.
+fn lifetime_ms(lifetime: u32) -> u64 {
+    if lifetime == INFINITY {
+        u64::max_value()
+    } else {
+        u64::from(lifetime) * 1000
+    }
+}
+
+fn add_ms(time_ms: i64, delay_ms: u64) -> i64 {
+    time_ms.saturating_add(i64::try_from(delay_ms).unwrap_or(i64::max_value()))
+}
+
+fn has_elapsed(start_ms: i64, delay_ms: u64, now_ms: i64) -> bool {
+    now_ms >= add_ms(start_ms, delay_ms)
+}
+
+/// State of the exchange with the servers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Looking for a server.
+    Soliciting,
+    /// Asking the selected server for the addresses and prefixes that it has advertised.
+    Requesting {
+        server_id: Vec<u8>,
+        advertised: Vec<DhcpOption>,
+    },
+    /// Lease obtained. Waiting for T1.
+    Bound { server_id: Vec<u8> },
+    /// Extending the lease with the server that has granted it.
+    Renewing { server_id: Vec<u8> },
+    /// Extending the lease with any server.
+    Rebinding,
+}
+
+/// DHCPv6 client of a network interface.
+pub struct Client {
+    /// DUID of type DUID-LL, derived from the MAC address.
+    duid: Vec<u8>,
+    /// Identifier of the `IA_NA` and `IA_PD` of the interface.
+    iaid: u32,
+    state: State,
+    lease: Option<Lease>,
+    /// True if [`Client::lease`] has changed since the last call to
+    /// [`Client::take_lease_changed`].
+    lease_changed: bool,
+    /// Transaction identifier of the current exchange.
+    transaction_id: u32,
+    /// Number of messages sent in the current exchange.
+    transmissions: u32,
+    /// Time when the first message of the current exchange has been sent, in milliseconds.
+    exchange_start_ms: i64,
+    /// Retransmission timeout of the last message sent, in milliseconds.
+    retransmit_timeout_ms: u64,
+    /// Time when the next message must be sent, in milliseconds.
+    next_transmit_ms: i64,
+    /// State of the xorshift generator used for the transaction identifiers and the jitter of
+    /// the retransmissions.
+    rng: u64,
+}
+
+impl Client {
+    /// Initializes a client for the interface with the given MAC address. The first message
+    /// is sent at the next call to [`Client::poll`].
+    pub fn new(mac_address: [u8; 6]) -> Client {
+        let mut duid = vec![0, 3, 0, 1];
+        duid.extend_from_slice(&mac_address);
+
+        let iaid = u32::from_be_bytes([
+            mac_address[2],
+            mac_address[3],
+            mac_address[4],
+            mac_address[5],
+        ]);
+
+        let mut seed = [0; 8];
+        seed[2..].copy_from_slice(&mac_address);
+
+        Client {
+            duid,
+            iaid,
+            state: State::Soliciting,
+            lease: None,
+            lease_changed: false,
+            transaction_id: 0,
+            transmissions: 0,
+            exchange_start_ms: 0,
+            retransmit_timeout_ms: 0,
+            next_transmit_ms: i64::min_value(),
+            rng: u64::from_be_bytes(seed) | 1,
+        }
+    }
+
+    /// Returns the configuration obtained from the server, if any.
+    pub fn lease(&self) -> Option<&Lease> {
+        self.lease.as_ref()
+    }
+
+    /// Returns true if [`Client::lease`] has changed since the last time this method has been
+    /// called.
+    pub fn take_lease_changed(&mut self) -> bool {
+        let changed = self.lease_changed;
+        self.lease_changed = false;
+        changed
+    }
+
+    /// Returns the time when [`Client::poll`] must be called again, in milliseconds.
+    pub fn next_poll_ms(&self) -> i64 {
+        let mut next = self.next_transmit_ms;
+        if let Some(lease) = &self.lease {
+            next = next.min(lease.expiration_ms());
+        }
+        next
+    }
+
+    /// Updates the state of the client, and returns the datagram to send, if any.
+    pub fn poll(&mut self, now_ms: i64) -> Option<Vec<u8>> {
+        self.update_lease(now_ms);
+
+        if now_ms < self.next_transmit_ms {
+            return None;
+        }
+
+        match &self.state {
+            State::Bound { server_id } => {
+                let server_id = server_id.clone();
+                self.start_exchange(State::Renewing { server_id });
+            }
+            State::Renewing { .. } => {
+                let lease = self.lease.as_ref().unwrap();
+                if has_elapsed(lease.obtained_ms, lease.t2_ms, now_ms) {
+                    self.start_exchange(State::Rebinding);
+                }
+            }
+            State::Requesting { .. } if self.transmissions >= REQ_MAX_RC => {
+                self.start_exchange(State::Soliciting);
+            }
+            _ => {}
+        }
+
+        if self.transmissions == 0 {
+            self.exchange_start_ms = now_ms;
+            // The clock is mixed in so that the identifiers differ between restarts.
+            self.transaction_id = (self.random() ^ now_ms as u64) as u32 & 0xff_ffff;
+        }
+
+        let (initial, max) = match self.state {
+            State::Soliciting => (SOL_TIMEOUT, SOL_MAX_RT),
+            State::Requesting { .. } => (REQ_TIMEOUT, REQ_MAX_RT),
+            State::Renewing { .. } => (REN_TIMEOUT, REN_MAX_RT),
+            State::Rebinding => (REB_TIMEOUT, REB_MAX_RT),
+            State::Bound { .. } => unreachable!(),
+        };
+        self.retransmit_timeout_ms = if self.transmissions == 0 {
+            self.jitter(initial)
+        } else {
+            let doubled = self.retransmit_timeout_ms.saturating_mul(2);
+            self.jitter(doubled).min(self.jitter(max))
+        };
+        self.transmissions += 1;
+        self.next_transmit_ms = add_ms(now_ms, self.retransmit_timeout_ms);
+
+        // Renew messages are no longer sent once T2 is reached, and Rebind messages once the
+        // lease has expired.
+        if let Some(lease) = &self.lease {
+            let deadline = match self.state {
+                State::Renewing { .. } => add_ms(lease.obtained_ms, lease.t2_ms),
+                _ => lease.expiration_ms(),
+            };
+            self.next_transmit_ms = self.next_transmit_ms.min(deadline);
+        }
+
+        Some(self.build_message(now_ms).encode())
+    }
+
+    /// Processes a datagram received on [`CLIENT_PORT`].
+    pub fn on_datagram(&mut self, now_ms: i64, data: &[u8]) {
+        let message = match Message::decode(data) {
+            Some(m) => m,
+            None => return,
+        };
+
+        if self.transmissions == 0
+            || message.transaction_id != self.transaction_id
+            || message.client_id() != Some(&self.duid[..])
+        {
+            return;
+        }
+        let server_id = match message.server_id() {
+            Some(id) => id.to_vec(),
+            None => return,
+        };
+
+        match (&self.state, message.ty) {
+            (State::Soliciting, ADVERTISE) => {
+                let mut advertised = Vec::new();
+                if let Some(ia) = message.ia_na(self.iaid) {
+                    if ia.addresses().next().is_some() {
+                        advertised.push(DhcpOption::IaNa(ia.clone()));
+                    }
+                }
+                if let Some(ia) = message.ia_pd(self.iaid) {
+                    if ia.prefixes().next().is_some() {
+                        advertised.push(DhcpOption::IaPd(ia.clone()));
+                    }
+                }
+
+                // Servers that have nothing to offer are ignored.
+                if !advertised.is_empty() {
+                    self.start_exchange(State::Requesting {
+                        server_id,
+                        advertised,
+                    });
+                }
+            }
+            (State::Requesting { .. }, REPLY)
+            | (State::Renewing { .. }, REPLY)
+            | (State::Rebinding, REPLY) => match Lease::from_reply(&message, self.iaid, now_ms) {
+                Some(lease) => {
+                    self.next_transmit_ms = add_ms(now_ms, lease.t1_ms);
+                    self.lease = Some(lease);
+                    self.lease_changed = true;
+                    self.state = State::Bound { server_id };
+                    self.transmissions = 0;
+                }
+                None => {
+                    // Replies to renewals that contain nothing are ignored, and the lease is
+                    // kept until it expires.
+                    if let State::Requesting { .. } = self.state {
+                        self.start_exchange(State::Soliciting);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Removes what has expired from the lease, and goes back to soliciting if the lease is
+    /// empty.
+    fn update_lease(&mut self, now_ms: i64) {
+        let lease = match &mut self.lease {
+            Some(l) => l,
+            None => return,
+        };
+
+        if lease.remove_expired(now_ms) {
+            self.lease_changed = true;
+        }
+
+        if lease.addresses.is_empty() && lease.prefixes.is_empty() {
+            self.lease = None;
+            self.start_exchange(State::Soliciting);
+        }
+    }
+
+    /// Switches to the given state, and sends its first message at the next call to
+    /// [`Client::poll`].
+    fn start_exchange(&mut self, state: State) {
+        self.state = state;
+        self.transmissions = 0;
+        self.next_transmit_ms = i64::min_value();
+    }
+
+    fn build_message(&self, now_ms: i64) -> Message {
+        let elapsed = if self.transmissions <= 1 {
+            0
+        } else {
+            let centiseconds = now_ms.saturating_sub(self.exchange_start_ms) / 10;
+            u16::try_from(centiseconds).unwrap_or(u16::max_value())
+        };
+
+        let mut options = vec![
+            DhcpOption::ClientId(self.duid.clone()),
+            DhcpOption::ElapsedTime(elapsed),
+            DhcpOption::OptionRequest(vec![OPTION_DNS_SERVERS]),
+        ];
+
+        // The addresses and prefixes that the client wants to keep are included in the
+        // identity associations, with lifetimes of 0 as the servers ignore them.
+        let current_ias = |lease: &Lease| {
+            let mut ias = Vec::new();
+            if !lease.addresses.is_empty() {
+                ias.push(DhcpOption::IaNa(IdentityAssociation {
+                    iaid: self.iaid,
+                    t1: 0,
+                    t2: 0,
+                    options: lease
+                        .addresses
+                        .iter()
+                        .map(|a| {
+                            DhcpOption::IaAddress(IaAddress {
+                                address: a.address,
+                                preferred_lifetime: 0,
+                                valid_lifetime: 0,
+                            })
+                        })
+                        .collect(),
+                }));
+            }
+            if !lease.prefixes.is_empty() {
+                ias.push(DhcpOption::IaPd(IdentityAssociation {
+                    iaid: self.iaid,
+                    t1: 0,
+                    t2: 0,
+                    options: lease
+                        .prefixes
+                        .iter()
+                        .map(|p| {
+                            DhcpOption::IaPrefix(IaPrefix {
+                                prefix: p.prefix,
+                                prefix_len: p.prefix_len,
+                                preferred_lifetime: 0,
+                                valid_lifetime: 0,
+                            })
+                        })
+                        .collect(),
+                }));
+            }
+            ias
+        };
+
+        let ty = match &self.state {
+            State::Soliciting => {
+                let empty = IdentityAssociation {
+                    iaid: self.iaid,
+                    t1: 0,
+                    t2: 0,
+                    options: Vec::new(),
+                };
+                options.push(DhcpOption::IaNa(empty.clone()));
+                options.push(DhcpOption::IaPd(empty));
+                SOLICIT
+            }
+            State::Requesting {
+                server_id,
+                advertised,
+            } => {
+                options.push(DhcpOption::ServerId(server_id.clone()));
+                options.extend(advertised.iter().cloned());
+                REQUEST
+            }
+            State::Renewing { server_id } => {
+                options.push(DhcpOption::ServerId(server_id.clone()));
+                options.extend(current_ias(self.lease.as_ref().unwrap()));
+                RENEW
+            }
+            State::Rebinding => {
+                options.extend(current_ias(self.lease.as_ref().unwrap()));
+                REBIND
+            }
+            State::Bound { .. } => unreachable!(),
+        };
+
+        Message {
+            ty,
+            transaction_id: self.transaction_id,
+            options,
+        }
+    }
+
+    /// Randomizes a retransmission timeout by ±10%, as required by section 15 of RFC 8415.
+    fn jitter(&mut self, timeout_ms: u64) -> u64 {
+        let factor = 900 + self.random() % 201;
+        timeout_ms.saturating_mul(factor) / 1000
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Client, DhcpOption, IaAddress, IaPrefix, IdentityAssociation, Message, ADVERTISE, REBIND,
+        RENEW, REPLY, REQUEST, SOLICIT,
+    };
+
+    const MAC: [u8; 6] = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+    const ADDRESS: [u16; 8] = [0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x10];
+    const PREFIX: [u16; 8] = [0x2001, 0xdb8, 0x100, 0, 0, 0, 0, 0];
+    const DNS_SERVER: [u16; 8] = [0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x53];
+
+    #[test]
+    fn decode_solicit() {
+        let data = [
+            1, 0x12, 0x34, 0x56, // Solicit, transaction ID
+            0, 1, 0, 10, 0, 3, 0, 1, 0x52, 0x54, 0, 0x12, 0x34, 0x56, // Client ID
+            0, 8, 0, 2, 0, 0, // Elapsed time
+            0, 6, 0, 2, 0, 23, // Option request
+            0, 3, 0, 12, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, // IA_NA
+            0, 99, 0, 1, 0xaa, // Unknown option
+        ];
+
+        let message = Message::decode(&data).unwrap();
+        assert_eq!(
+            message,
+            Message {
+                ty: SOLICIT,
+                transaction_id: 0x12_3456,
+                options: vec![
+                    DhcpOption::ClientId(vec![0, 3, 0, 1, 0x52, 0x54, 0, 0x12, 0x34, 0x56]),
+                    DhcpOption::ElapsedTime(0),
+                    DhcpOption::OptionRequest(vec![23]),
+                    DhcpOption::IaNa(IdentityAssociation {
+                        iaid: 1,
+                        t1: 0,
+                        t2: 0,
+                        options: Vec::new(),
+                    }),
+                    DhcpOption::Other {
+                        code: 99,
+                        data: vec![0xaa],
+                    },
+                ],
+            }
+        );
+        assert_eq!(message.encode(), &data[..]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message {
+            ty: REPLY,
+            transaction_id: 0xab_cdef,
+            options: vec![
+                DhcpOption::ServerId(vec![0, 1, 2, 3]),
+                DhcpOption::IaNa(IdentityAssociation {
+                    iaid: 7,
+                    t1: 100,
+                    t2: 160,
+                    options: vec![DhcpOption::IaAddress(IaAddress {
+                        address: ADDRESS,
+                        preferred_lifetime: 200,
+                        valid_lifetime: 300,
+                    })],
+                }),
+                DhcpOption::IaPd(IdentityAssociation {
+                    iaid: 7,
+                    t1: 0,
+                    t2: 0,
+                    options: vec![DhcpOption::IaPrefix(IaPrefix {
+                        prefix: PREFIX,
+                        prefix_len: 56,
+                        preferred_lifetime: 200,
+                        valid_lifetime: 0xffff_ffff,
+                    })],
+                }),
+                DhcpOption::DnsServers(vec![DNS_SERVER, ADDRESS]),
+            ],
+        };
+
+        assert_eq!(Message::decode(&message.encode()), Some(message));
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(Message::decode(&[1, 0, 0]), None);
+        // Option longer than the message.
+        assert_eq!(Message::decode(&[7, 0, 0, 1, 0, 2, 0, 4, 1, 2]), None);
+        // Truncated option header.
+        assert_eq!(Message::decode(&[7, 0, 0, 1, 0, 2]), None);
+        // DNS servers whose length isn't a multiple of 16.
+        assert_eq!(Message::decode(&[7, 0, 0, 1, 0, 23, 0, 1, 0]), None);
+        // IA_NA shorter than its fixed fields.
+        assert_eq!(Message::decode(&[7, 0, 0, 1, 0, 3, 0, 4, 0, 0, 0, 1]), None);
+    }
+
+    /// Builds the answer of a server to a message sent by the client.
+    fn answer(request: &[u8], ty: u8, t1: u32, valid_lifetime: u32) -> Vec<u8> {
+        let request = Message::decode(request).unwrap();
+        let iaid = request
+            .options
+            .iter()
+            .find_map(|opt| match opt {
+                DhcpOption::IaNa(ia) => Some(ia.iaid),
+                _ => None,
+            })
+            .unwrap();
+
+        Message {
+            ty,
+            transaction_id: request.transaction_id,
+            options: vec![
+                DhcpOption::ClientId(request.client_id().unwrap().to_vec()),
+                DhcpOption::ServerId(vec![0, 3, 0, 1, 1, 2, 3, 4, 5, 6]),
+                DhcpOption::IaNa(IdentityAssociation {
+                    iaid,
+                    t1,
+                    t2: 0,
+                    options: vec![DhcpOption::IaAddress(IaAddress {
+                        address: ADDRESS,
+                        preferred_lifetime: valid_lifetime,
+                        valid_lifetime,
+                    })],
+                }),
+                DhcpOption::IaPd(IdentityAssociation {
+                    iaid,
+                    t1,
+                    t2: 0,
+                    options: vec![DhcpOption::IaPrefix(IaPrefix {
+                        prefix: PREFIX,
+                        prefix_len: 56,
+                        preferred_lifetime: valid_lifetime,
+                        valid_lifetime,
+                    })],
+                }),
+                DhcpOption::DnsServers(vec![DNS_SERVER]),
+            ],
+        }
+        .encode()
+    }
+
+    #[test]
+    fn obtains_lease() {
+        let mut client = Client::new(MAC);
+
+        let solicit = client.poll(0).unwrap();
+        assert_eq!(Message::decode(&solicit).unwrap().ty, SOLICIT);
+        assert!(client.poll(0).is_none());
+
+        // Answers from another transaction are ignored.
+        let mut wrong = answer(&solicit, ADVERTISE, 0, 1000);
+        wrong[3] ^= 1;
+        client.on_datagram(10, &wrong);
+        assert!(client.poll(10).is_none());
+
+        client.on_datagram(10, &answer(&solicit, ADVERTISE, 0, 1000));
+        let request = client.poll(10).unwrap();
+        let decoded = Message::decode(&request).unwrap();
+        assert_eq!(decoded.ty, REQUEST);
+        assert!(decoded.server_id().is_some());
+        assert!(client.lease().is_none());
+
+        client.on_datagram(20, &answer(&request, REPLY, 0, 1000));
+        assert!(client.take_lease_changed());
+        assert!(!client.take_lease_changed());
+        let lease = client.lease().unwrap();
+        assert_eq!(lease.addresses[0].address, ADDRESS);
+        assert_eq!(lease.prefixes[0].prefix, PREFIX);
+        assert_eq!(lease.prefixes[0].prefix_len, 56);
+        assert_eq!(lease.dns_servers, vec![DNS_SERVER]);
+
+        // T1 defaults to half of the preferred lifetime.
+        assert_eq!(client.next_poll_ms(), 20 + 500_000);
+        assert!(client.poll(20 + 499_999).is_none());
+    }
+
+    #[test]
+    fn renews_then_rebinds_then_expires() {
+        let mut client = Client::new(MAC);
+        let solicit = client.poll(0).unwrap();
+        client.on_datagram(0, &answer(&solicit, ADVERTISE, 0, 100));
+        let request = client.poll(0).unwrap();
+        client.on_datagram(0, &answer(&request, REPLY, 50, 100));
+        assert!(client.take_lease_changed());
+
+        let renew = client.poll(50_000).unwrap();
+        let decoded = Message::decode(&renew).unwrap();
+        assert_eq!(decoded.ty, RENEW);
+        assert!(decoded.server_id().is_some());
+        // The address to renew is sent back with lifetimes of 0.
+        assert!(decoded.options.iter().any(|opt| match opt {
+            DhcpOption::IaNa(ia) => {
+                ia.options
+                    == vec![DhcpOption::IaAddress(IaAddress {
+                        address: ADDRESS,
+                        preferred_lifetime: 0,
+                        valid_lifetime: 0,
+                    })]
+            }
+            _ => false,
+        }));
+
+        // T2 defaults to 0.8 times the preferred lifetime.
+        let rebind = client.poll(80_000).unwrap();
+        let decoded = Message::decode(&rebind).unwrap();
+        assert_eq!(decoded.ty, REBIND);
+        assert!(decoded.server_id().is_none());
+
+        let solicit = client.poll(100_000).unwrap();
+        assert_eq!(Message::decode(&solicit).unwrap().ty, SOLICIT);
+        assert!(client.take_lease_changed());
+        assert!(client.lease().is_none());
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Filtering of the packets exchanged between the drivers and the TCP/IP stack, according to
+//! the rules set through the firewall interface.
+
+use redshirt_firewall_interface::ffi::{
+    Action, AddressRange, Counters, Direction, GetRulesResponse, PortRange, Protocol, Rule, Ruleset,
+};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, Ipv6Packet, TcpPacket, UdpPacket,
+};
+
+/// Rules and their counters.
+pub struct Firewall {
+    ruleset: Ruleset,
+    counters: Counters,
+}
+
+/// Information about a packet that the rules can match.
+#[derive(Debug)]
+struct PacketInfo {
+    /// `None` if the protocol isn't one of those of [`Protocol`].
+    protocol: Option<Protocol>,
+    /// IPv4 addresses are represented as IPv4-mapped IPv6 addresses.
+    remote_address: u128,
+    /// `None` if the protocol doesn't have ports.
+    ports: Option<Ports>,
+}
+
+#[derive(Debug)]
+struct Ports {
+    local: u16,
+    remote: u16,
+}
+
+impl Firewall {
+    /// Initializes a firewall without any rule, allowing all packets.
+    pub fn new() -> Self {
+        Firewall::with_rules(Ruleset {
+            rules: Vec::new(),
+            default_action: Action::Allow,
+        })
+    }
+
+    /// Initializes a firewall with the given rules.
+    pub fn with_rules(ruleset: Ruleset) -> Self {
+        let counters = Counters {
+            rules: vec![0; ruleset.rules.len()],
+            default_action: 0,
+        };
+
+        Firewall { ruleset, counters }
+    }
+
+    /// Replaces the rules and resets the counters.
+    pub fn set_rules(&mut self, ruleset: Ruleset) {
+        *self = Firewall::with_rules(ruleset);
+    }
+
+    /// Returns the rules and their counters.
+    pub fn rules(&self) -> GetRulesResponse {
+        GetRulesResponse {
+            ruleset: self.ruleset.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+
+    /// Returns true if the given Ethernet frame, going through the network interface with the
+    /// given MAC address, must be let through. Updates the counters.
+    ///
+    /// Frames that don't contain an IP packet are always let through and aren't counted.
+    pub fn filter(&mut self, interface: [u8; 6], direction: Direction, frame: &[u8]) -> bool {
+        let packet = match PacketInfo::parse(direction, frame) {
+            Some(p) => p,
+            None => return true,
+        };
+
+        let matching = self
+            .ruleset
+            .rules
+            .iter()
+            .position(|rule| rule_matches(rule, interface, direction, &packet));
+
+        let action = match matching {
+            Some(index) => {
+                self.counters.rules[index] += 1;
+                self.ruleset.rules[index].action
+            }
+            None => {
+                self.counters.default_action += 1;
+                self.ruleset.default_action
+            }
+        };
+
+        action == Action::Allow
+    }
+}
+
+impl PacketInfo {
+    /// Extracts the information from an Ethernet frame. Returns `None` if the frame doesn't
+    /// contain an IP packet or is malformed.
+    fn parse(direction: Direction, frame: &[u8]) -> Option<PacketInfo> {
+        let frame = EthernetFrame::new_checked(frame).ok()?;
+
+        let (protocol, source, destination, payload) = match frame.ethertype() {
+            EthernetProtocol::Ipv4 => {
+                let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+                let protocol = match packet.protocol() {
+                    IpProtocol::Icmp => Some(Protocol::Icmp),
+                    IpProtocol::Tcp => Some(Protocol::Tcp),
+                    IpProtocol::Udp => Some(Protocol::Udp),
+                    _ => None,
+                };
+                let source = ipv4_mapped(packet.src_addr().0);
+                let destination = ipv4_mapped(packet.dst_addr().0);
+                (protocol, source, destination, packet.payload())
+            }
+            EthernetProtocol::Ipv6 => {
+                // Extension headers aren't supported, and packets that have some are considered
+                // as being of an unknown protocol.
+                let packet = Ipv6Packet::new_checked(frame.payload()).ok()?;
+                let protocol = match packet.next_header() {
+                    IpProtocol::Icmpv6 => Some(Protocol::Icmp),
+                    IpProtocol::Tcp => Some(Protocol::Tcp),
+                    IpProtocol::Udp => Some(Protocol::Udp),
+                    _ => None,
+                };
+                let source = u128::from_be_bytes(packet.src_addr().0);
+                let destination = u128::from_be_bytes(packet.dst_addr().0);
+                (protocol, source, destination, packet.payload())
+            }
+            _ => return None,
+        };
+
+        let source_and_destination_ports = match protocol {
+            Some(Protocol::Tcp) => {
+                let segment = TcpPacket::new_checked(payload).ok()?;
+                Some((segment.src_port(), segment.dst_port()))
+            }
+            Some(Protocol::Udp) => {
+                let datagram = UdpPacket::new_checked(payload).ok()?;
+                Some((datagram.src_port(), datagram.dst_port()))
+            }
+            Some(Protocol::Icmp) | None => None,
+        };
+
+        let (remote_address, ports) = match direction {
+            Direction::Inbound => (
+                source,
+                source_and_destination_ports.map(|(src, dst)| Ports {
+                    local: dst,
+                    remote: src,
+                }),
+            ),
+            Direction::Outbound => (
+                destination,
+                source_and_destination_ports.map(|(src, dst)| Ports {
+                    local: src,
+                    remote: dst,
+                }),
+            ),
+        };
+
+        Some(PacketInfo {
+            protocol,
+            remote_address,
+            ports,
+        })
+    }
+}
+
+/// Returns true if all the criteria of the rule match the packet.
+fn rule_matches(
+    rule: &Rule,
+    interface: [u8; 6],
+    direction: Direction,
+    packet: &PacketInfo,
+) -> bool {
+    if rule.direction.map_or(false, |d| d != direction) {
+        return false;
+    }
+    if rule.interface.map_or(false, |i| i != interface) {
+        return false;
+    }
+    if rule.protocol.map_or(false, |p| Some(p) != packet.protocol) {
+        return false;
+    }
+    if let Some(range) = &rule.local_port {
+        match &packet.ports {
+            Some(ports) if port_in_range(ports.local, range) => {}
+            _ => return false,
+        }
+    }
+    if let Some(range) = &rule.remote_port {
+        match &packet.ports {
+            Some(ports) if port_in_range(ports.remote, range) => {}
+            _ => return false,
+        }
+    }
+    if let Some(range) = &rule.remote_address {
+        if !address_in_range(packet.remote_address, range) {
+            return false;
+        }
+    }
+    true
+}
+
+fn port_in_range(port: u16, range: &PortRange) -> bool {
+    port >= range.first && port <= range.last
+}
+
+fn address_in_range(address: u128, range: &AddressRange) -> bool {
+    let mut network = 0u128;
+    for segment in range.ip.iter() {
+        network = (network << 16) | u128::from(*segment);
+    }
+
+    let mask = match u32::from(range.prefix_len.min(128)) {
+        0 => 0,
+        len => !0u128 << (128 - len),
+    };
+
+    address & mask == network & mask
+}
+
+/// Turns an IPv4 address into an IPv4-mapped IPv6 address.
+fn ipv4_mapped(address: [u8; 4]) -> u128 {
+    (0xffff << 32) | u128::from(u32::from_be_bytes(address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Firewall;
+    use redshirt_firewall_interface::ffi::{
+        Action, AddressRange, Direction, PortRange, Protocol, Rule, Ruleset,
+    };
+
+    const MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+    const OTHER_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x57];
+
+    /// Builds an Ethernet frame containing an IPv4 packet of the given protocol. `ports`
+    /// contains the source and destination ports of TCP and UDP packets.
+    fn ipv4_frame(protocol: u8, src: [u8; 4], dst: [u8; 4], ports: Option<(u16, u16)>) -> Vec<u8> {
+        // TCP headers are 20 bytes long, UDP and ICMP headers are 8 bytes long.
+        let transport_len = if protocol == 6 { 20 } else { 8 };
+
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&MAC);
+        frame.extend_from_slice(&OTHER_MAC);
+        frame.extend_from_slice(&[0x08, 0x00]);
+
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(20 + transport_len as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, protocol, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+
+        let mut transport = vec![0; transport_len];
+        if let Some((src_port, dst_port)) = ports {
+            transport[0..2].copy_from_slice(&src_port.to_be_bytes());
+            transport[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        }
+        match protocol {
+            // Data offset of the TCP header, in 32 bits words.
+            6 => transport[12] = 5 << 4,
+            // Length of the UDP datagram.
+            17 => transport[4..6].copy_from_slice(&8u16.to_be_bytes()),
+            _ => {}
+        }
+        frame.extend_from_slice(&transport);
+        frame
+    }
+
+    fn rule(action: Action) -> Rule {
+        Rule {
+            action,
+            direction: None,
+            interface: None,
+            protocol: None,
+            local_port: None,
+            remote_port: None,
+            remote_address: None,
+        }
+    }
+
+    #[test]
+    fn allows_everything_by_default() {
+        let mut firewall = Firewall::new();
+        let frame = ipv4_frame(6, [10, 0, 2, 2], [10, 0, 2, 15], Some((1234, 22)));
+        assert!(firewall.filter(MAC, Direction::Inbound, &frame));
+        assert_eq!(firewall.rules().counters.default_action, 1);
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let mut firewall = Firewall::with_rules(Ruleset {
+            rules: vec![
+                Rule {
+                    direction: Some(Direction::Inbound),
+                    protocol: Some(Protocol::Tcp),
+                    local_port: Some(PortRange {
+                        first: 22,
+                        last: 22,
+                    }),
+                    remote_address: Some(AddressRange {
+                        ip: [0, 0, 0, 0, 0, 0xffff, 0x0a00, 0x0200],
+                        prefix_len: 120,
+                    }),
+                    ..rule(Action::Allow)
+                },
+                Rule {
+                    direction: Some(Direction::Inbound),
+                    ..rule(Action::Deny)
+                },
+            ],
+            default_action: Action::Allow,
+        });
+
+        // SSH from the local network.
+        let frame = ipv4_frame(6, [10, 0, 2, 2], [10, 0, 2, 15], Some((1234, 22)));
+        assert!(firewall.filter(MAC, Direction::Inbound, &frame));
+        // SSH from another network.
+        let frame = ipv4_frame(6, [192, 168, 1, 1], [10, 0, 2, 15], Some((1234, 22)));
+        assert!(!firewall.filter(MAC, Direction::Inbound, &frame));
+        // UDP on the same port.
+        let frame = ipv4_frame(17, [10, 0, 2, 2], [10, 0, 2, 15], Some((1234, 22)));
+        assert!(!firewall.filter(MAC, Direction::Inbound, &frame));
+        // Outbound traffic isn't concerned by the rules. The local port is the source port.
+        let frame = ipv4_frame(6, [10, 0, 2, 15], [10, 0, 2, 2], Some((22, 1234)));
+        assert!(firewall.filter(MAC, Direction::Outbound, &frame));
+
+        let counters = firewall.rules().counters;
+        assert_eq!(counters.rules, [1, 2]);
+        assert_eq!(counters.default_action, 1);
+    }
+
+    #[test]
+    fn interface_and_ports() {
+        let mut firewall = Firewall::with_rules(Ruleset {
+            rules: vec![
+                Rule {
+                    interface: Some(OTHER_MAC),
+                    ..rule(Action::Allow)
+                },
+                Rule {
+                    remote_port: Some(PortRange {
+                        first: 53,
+                        last: 53,
+                    }),
+                    ..rule(Action::Allow)
+                },
+            ],
+            default_action: Action::Deny,
+        });
+
+        let dns = ipv4_frame(17, [10, 0, 2, 15], [10, 0, 2, 3], Some((50000, 53)));
+        assert!(firewall.filter(MAC, Direction::Outbound, &dns));
+        let ping = ipv4_frame(1, [10, 0, 2, 15], [10, 0, 2, 3], None);
+        assert!(!firewall.filter(MAC, Direction::Outbound, &ping));
+        assert!(firewall.filter(OTHER_MAC, Direction::Outbound, &ping));
+
+        let counters = firewall.rules().counters;
+        assert_eq!(counters.rules, [1, 1]);
+        assert_eq!(counters.default_action, 1);
+    }
+
+    #[test]
+    fn non_ip_frames_not_filtered() {
+        let mut firewall = Firewall::with_rules(Ruleset {
+            rules: Vec::new(),
+            default_action: Action::Deny,
+        });
+
+        let mut arp = ipv4_frame(6, [10, 0, 2, 2], [10, 0, 2, 15], Some((1, 2)));
+        arp[12..14].copy_from_slice(&[0x08, 0x06]);
+        assert!(firewall.filter(MAC, Direction::Inbound, &arp));
+        assert_eq!(firewall.rules().counters.default_action, 0);
+    }
+
+    #[test]
+    fn set_rules_resets_counters() {
+        let mut firewall = Firewall::new();
+        let frame = ipv4_frame(17, [10, 0, 2, 2], [10, 0, 2, 15], Some((53, 50000)));
+        assert!(firewall.filter(MAC, Direction::Inbound, &frame));
+
+        firewall.set_rules(Ruleset {
+            rules: vec![rule(Action::Deny)],
+            default_action: Action::Allow,
+        });
+        assert_eq!(firewall.rules().counters.default_action, 0);
+        assert!(!firewall.filter(MAC, Direction::Inbound, &frame));
+        assert_eq!(firewall.rules().counters.rules, [1]);
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Network manager.
+//!
+//! Registers the Ethernet, TCP and UDP interfaces. Network card drivers register their cards
+//! through the Ethernet interface, and the frames they exchange with the network are processed
+//! by a TCP/IP stack ([smoltcp](https://github.com/m-labs/smoltcp)). Other programs open TCP and
+//! UDP sockets on top of this stack through the TCP and UDP interfaces.
+//!
+//! The frames exchanged between the drivers and the stack are filtered according to the rules
+//! set through the firewall interface, which is also registered.
+//!
+//! The IPv4 configuration of the network interfaces is currently the static configuration of the
+//! user-mode network stack of QEMU. The IPv6 addresses, DNS servers and delegated prefixes are
+//! obtained through DHCPv6.
+
+mod device;
+mod dhcpv6;
+mod firewall;
+mod manager;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use std::{convert::TryFrom as _, time::Duration};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(redshirt_ethernet_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+    redshirt_interface_interface::register_interface(redshirt_tcp_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+    redshirt_interface_interface::register_interface(redshirt_udp_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+    redshirt_interface_interface::register_interface(redshirt_firewall_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut manager = manager::NetworkManager::new();
+    // Number of milliseconds after which the manager must be polled again, if any.
+    let mut next_poll: Option<u64> = None;
+
+    loop {
+        let event = {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            let timer = match next_poll {
+                Some(delay) => Box::pin(redshirt_time_interface::monotonic_wait(
+                    Duration::from_millis(delay),
+                ))
+                .left_future(),
+                None => future::pending().right_future(),
+            };
+            match future::select(next_message, timer).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right(_) => None,
+            }
+        };
+
+        match event {
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(msg)) => {
+                if msg.interface == redshirt_ethernet_interface::ffi::INTERFACE {
+                    match DecodeAll::decode_all(&msg.actual_data) {
+                        Ok(message) => {
+                            manager.ethernet_message(msg.emitter_pid, msg.message_id, message)
+                        }
+                        Err(_) => answer_error(msg.message_id),
+                    }
+                } else if msg.interface == redshirt_tcp_interface::ffi::INTERFACE {
+                    match DecodeAll::decode_all(&msg.actual_data) {
+                        Ok(message) => {
+                            manager.tcp_message(msg.emitter_pid, msg.message_id, message)
+                        }
+                        Err(_) => answer_error(msg.message_id),
+                    }
+                } else if msg.interface == redshirt_udp_interface::ffi::INTERFACE {
+                    match DecodeAll::decode_all(&msg.actual_data) {
+                        Ok(message) => {
+                            manager.udp_message(msg.emitter_pid, msg.message_id, message)
+                        }
+                        Err(_) => answer_error(msg.message_id),
+                    }
+                } else if msg.interface == redshirt_firewall_interface::ffi::INTERFACE {
+                    match DecodeAll::decode_all(&msg.actual_data) {
+                        Ok(message) => manager.firewall_message(msg.message_id, message),
+                        Err(_) => answer_error(msg.message_id),
+                    }
+                } else {
+                    answer_error(msg.message_id);
+                }
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                manager.process_destroyed(msg.pid)
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                manager.message_cancelled(msg.message_id)
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_)) => {}
+            // Timer fired.
+            None => {}
+        }
+
+        let now = redshirt_time_interface::monotonic_clock().await;
+        let now_ms = i64::try_from(now / 1_000_000).unwrap_or(i64::max_value());
+        next_poll = manager.poll(now_ms);
+    }
+}
+
+/// Answers a message that couldn't be decoded.
+fn answer_error(message_id: Option<redshirt_syscalls_interface::MessageId>) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_message_error(message_id);
+    }
+}
@@ -0,0 +1,1057 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State of the network manager: the network interfaces registered by the drivers, and the
+//! sockets opened by the other programs.
+
+use crate::device::FrameQueues;
+use crate::dhcpv6;
+use crate::firewall::Firewall;
+
+use redshirt_ethernet_interface::ffi::NetworkMessage;
+use redshirt_firewall_interface::ffi::{self as firewall, Direction};
+use redshirt_log_interface::Level;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId, Pid};
+use redshirt_tcp_interface::ffi as tcp;
+use redshirt_udp_interface::ffi as udp;
+use smoltcp::iface::{EthernetInterface, EthernetInterfaceBuilder, NeighborCache, Routes};
+use smoltcp::socket::{
+    SocketHandle, SocketSet, TcpSocket, TcpSocketBuffer, TcpState, UdpPacketMetadata, UdpSocket,
+    UdpSocketBuffer,
+};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv6Address};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom as _;
+
+/// Address assigned to the network interfaces. This is the address that the user-mode network
+/// stack of QEMU attributes to the guest.
+// TODO: use DHCP instead
+const IPV4_ADDRESS: [u8; 4] = [10, 0, 2, 15];
+
+/// Length of the prefix of the network of [`IPV4_ADDRESS`].
+const IPV4_PREFIX_LEN: u8 = 24;
+
+/// Gateway to send the packets destined to other networks to.
+const IPV4_GATEWAY: [u8; 4] = [10, 0, 2, 2];
+
+/// Size of the receive and send buffers of each TCP socket.
+const TCP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of datagrams that can be buffered in each direction for each UDP socket.
+const UDP_BUFFER_DATAGRAMS: usize = 32;
+
+/// Size of the buffers of datagrams in each direction for each UDP socket.
+const UDP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// First port attributed when a program asks for port 0.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// Target of the log messages.
+const LOG_TARGET: &str = "network-manager";
+
+/// Network interfaces and sockets.
+pub struct NetworkManager {
+    /// Network interfaces registered by the drivers, by PID of the driver and identifier chosen
+    /// by the driver.
+    interfaces: HashMap<(Pid, u64), Interface>,
+    /// Sockets of smoltcp, shared by all the interfaces.
+    sockets: SocketSet<'static, 'static, 'static>,
+    /// TCP sockets, by identifier.
+    tcp_sockets: HashMap<u32, TcpEntry>,
+    /// UDP sockets, by identifier.
+    udp_sockets: HashMap<u32, UdpEntry>,
+    /// Identifier to attribute to the next socket.
+    next_socket_id: u32,
+    /// Port to try to attribute to the next socket bound to port 0.
+    next_ephemeral_port: u16,
+    /// Filters the frames exchanged between the drivers and the interfaces.
+    firewall: Firewall,
+}
+
+struct Interface {
+    inner: EthernetInterface<'static, 'static, 'static, FrameQueues>,
+    /// MAC address of the network card.
+    mac_address: [u8; 6],
+    /// [`NetworkMessage::InterfaceWaitData`] message waiting for a frame to send.
+    pending_wait: Option<MessageId>,
+    /// Obtains the IPv6 addresses of the interface.
+    dhcpv6: dhcpv6::Client,
+    /// UDP socket of [`Interface::dhcpv6`], bound to the link-local address of the interface.
+    dhcpv6_socket: SocketHandle,
+}
+
+struct TcpEntry {
+    /// Process that has opened the socket.
+    owner: Pid,
+    /// Socket in [`NetworkManager::sockets`].
+    handle: SocketHandle,
+    /// Local port of the socket.
+    local_port: u16,
+    /// If true, the socket is listening and [`TcpEntry::handle`] is the socket waiting for the
+    /// next incoming connection.
+    listener: bool,
+    /// If true, the owner has closed the socket and it is removed once closed.
+    closed: bool,
+    /// [`tcp::TcpMessage::Open`] message to answer once the connection is established.
+    pending_open: Option<MessageId>,
+    /// [`tcp::TcpMessage::Accept`] message to answer once a connection comes in.
+    pending_accept: Option<MessageId>,
+    /// [`tcp::TcpMessage::Read`] message to answer once data is available.
+    pending_read: Option<MessageId>,
+    /// [`tcp::TcpMessage::Write`] message to answer once the data has been written, and data
+    /// that remains to be written.
+    pending_write: Option<(MessageId, Vec<u8>)>,
+}
+
+struct UdpEntry {
+    /// Process that has bound the socket.
+    owner: Pid,
+    /// Socket in [`NetworkManager::sockets`].
+    handle: SocketHandle,
+    /// Local port of the socket.
+    local_port: u16,
+    /// [`udp::UdpMessage::Receive`] message to send the received datagrams to.
+    subscription: Option<MessageId>,
+}
+
+impl NetworkManager {
+    /// Initializes a network manager without any interface or socket.
+    pub fn new() -> Self {
+        NetworkManager {
+            interfaces: HashMap::new(),
+            sockets: SocketSet::new(Vec::new()),
+            tcp_sockets: HashMap::new(),
+            udp_sockets: HashMap::new(),
+            next_socket_id: 0,
+            next_ephemeral_port: FIRST_EPHEMERAL_PORT,
+            firewall: Firewall::new(),
+        }
+    }
+
+    /// Handles a message on the Ethernet interface.
+    pub fn ethernet_message(
+        &mut self,
+        emitter: Pid,
+        message_id: Option<MessageId>,
+        message: NetworkMessage,
+    ) {
+        match message {
+            NetworkMessage::RegisterInterface { id, mac_address } => {
+                if self.interfaces.contains_key(&(emitter, id)) {
+                    return;
+                }
+
+                let mut routes = Routes::new(BTreeMap::new());
+                routes
+                    .add_default_ipv4_route(Ipv4Address(IPV4_GATEWAY))
+                    .unwrap();
+                let inner = EthernetInterfaceBuilder::new(FrameQueues::default())
+                    .ethernet_addr(EthernetAddress(mac_address))
+                    .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                    .ip_addrs(interface_addresses(mac_address, None))
+                    .routes(routes)
+                    .finalize();
+
+                let dhcpv6_socket = self.sockets.add(new_udp_socket());
+                self.sockets
+                    .get::<UdpSocket>(dhcpv6_socket)
+                    .bind(IpEndpoint::new(
+                        IpAddress::Ipv6(link_local_address(mac_address)),
+                        dhcpv6::CLIENT_PORT,
+                    ))
+                    .unwrap();
+
+                self.interfaces.insert(
+                    (emitter, id),
+                    Interface {
+                        inner,
+                        mac_address,
+                        pending_wait: None,
+                        dhcpv6: dhcpv6::Client::new(mac_address),
+                        dhcpv6_socket,
+                    },
+                );
+            }
+            NetworkMessage::UnregisterInterface(id) => {
+                if let Some(interface) = self.interfaces.remove(&(emitter, id)) {
+                    if let Some(message_id) = interface.pending_wait {
+                        redshirt_syscalls_interface::emit_message_error(message_id);
+                    }
+                    self.sockets.remove(interface.dhcpv6_socket);
+                }
+            }
+            NetworkMessage::InterfaceOnData { id, data } => {
+                if let Some(interface) = self.interfaces.get_mut(&(emitter, id)) {
+                    if !self
+                        .firewall
+                        .filter(interface.mac_address, Direction::Inbound, &data)
+                    {
+                        return;
+                    }
+
+                    // Frames that don't fit are lost, as if the network had dropped them.
+                    let _ = interface.inner.device_mut().push_received(data);
+                }
+            }
+            NetworkMessage::InterfaceWaitData(id) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                match self.interfaces.get_mut(&(emitter, id)) {
+                    Some(interface) if interface.pending_wait.is_none() => {
+                        interface.pending_wait = Some(message_id);
+                    }
+                    _ => redshirt_syscalls_interface::emit_message_error(message_id),
+                }
+            }
+        }
+    }
+
+    /// Handles a message on the TCP interface.
+    pub fn tcp_message(
+        &mut self,
+        emitter: Pid,
+        message_id: Option<MessageId>,
+        message: tcp::TcpMessage,
+    ) {
+        match message {
+            tcp::TcpMessage::Listen(listen) => {
+                let result = self.tcp_listen(emitter, listen.port);
+                if let Some(message_id) = message_id {
+                    redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &tcp::TcpListenResponse { result },
+                    );
+                }
+            }
+            tcp::TcpMessage::Accept(accept) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                match self.tcp_sockets.get_mut(&accept.socket_id) {
+                    Some(entry)
+                        if entry.owner == emitter
+                            && entry.listener
+                            && !entry.closed
+                            && entry.pending_accept.is_none() =>
+                    {
+                        entry.pending_accept = Some(message_id);
+                    }
+                    _ => redshirt_syscalls_interface::emit_message_error(message_id),
+                }
+            }
+            tcp::TcpMessage::Open(open) => {
+                let remote = IpEndpoint::new(to_ip_address(open.ip), open.port);
+                match (self.tcp_open(emitter, remote), message_id) {
+                    (Ok(socket_id), Some(message_id)) => {
+                        self.tcp_sockets.get_mut(&socket_id).unwrap().pending_open =
+                            Some(message_id);
+                    }
+                    (Ok(socket_id), None) => self.tcp_close(socket_id),
+                    (Err(()), Some(message_id)) => {
+                        redshirt_syscalls_interface::emit_answer(
+                            message_id,
+                            &tcp::TcpOpenResponse { result: Err(()) },
+                        );
+                    }
+                    (Err(()), None) => {}
+                }
+            }
+            tcp::TcpMessage::Close(close) => {
+                if self
+                    .tcp_sockets
+                    .get(&close.socket_id)
+                    .map_or(false, |e| e.owner == emitter)
+                {
+                    self.tcp_close(close.socket_id);
+                }
+            }
+            tcp::TcpMessage::Read(read) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                match self.tcp_sockets.get_mut(&read.socket_id) {
+                    Some(entry)
+                        if entry.owner == emitter
+                            && !entry.listener
+                            && !entry.closed
+                            && entry.pending_read.is_none() =>
+                    {
+                        entry.pending_read = Some(message_id);
+                    }
+                    _ => redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &tcp::TcpReadResponse { result: Err(()) },
+                    ),
+                }
+            }
+            tcp::TcpMessage::Write(write) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                match self.tcp_sockets.get_mut(&write.socket_id) {
+                    Some(entry)
+                        if entry.owner == emitter
+                            && !entry.listener
+                            && !entry.closed
+                            && entry.pending_write.is_none() =>
+                    {
+                        entry.pending_write = Some((message_id, write.data));
+                    }
+                    _ => redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &tcp::TcpWriteResponse { result: Err(()) },
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Handles a message on the UDP interface.
+    pub fn udp_message(
+        &mut self,
+        emitter: Pid,
+        message_id: Option<MessageId>,
+        message: udp::UdpMessage,
+    ) {
+        match message {
+            udp::UdpMessage::Bind(bind) => {
+                let result = self.udp_bind(emitter, bind.port);
+                if let Some(message_id) = message_id {
+                    redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &udp::UdpBindResponse { result },
+                    );
+                }
+            }
+            udp::UdpMessage::SendTo(send_to) => {
+                let result =
+                    match self.udp_sockets.get(&send_to.socket_id) {
+                        Some(entry) if entry.owner == emitter => {
+                            let remote = IpEndpoint::new(to_ip_address(send_to.ip), send_to.port);
+                            let mut socket = self.sockets.get::<UdpSocket>(entry.handle);
+                            match socket.send_slice(&send_to.data, remote) {
+                                Ok(()) => Ok(()),
+                                Err(smoltcp::Error::Exhausted) => {
+                                    Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                                        .with_message("send buffer full"))
+                                }
+                                Err(smoltcp::Error::Truncated) => {
+                                    Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                                        .with_message("datagram too large"))
+                                }
+                                Err(err) => Err(ErrorPayload::new(ErrorClass::OTHER)
+                                    .with_message(err.to_string())),
+                            }
+                        }
+                        _ => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                    };
+
+                if let Some(message_id) = message_id {
+                    redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &udp::UdpSendToResponse { result },
+                    );
+                }
+            }
+            udp::UdpMessage::Receive(socket_id) => {
+                let message_id = match message_id {
+                    Some(m) => m,
+                    None => return,
+                };
+
+                match self.udp_sockets.get_mut(&socket_id) {
+                    Some(entry) if entry.owner == emitter && entry.subscription.is_none() => {
+                        entry.subscription = Some(message_id);
+                    }
+                    _ => redshirt_syscalls_interface::emit_message_error(message_id),
+                }
+            }
+            udp::UdpMessage::Close(socket_id) => {
+                if self
+                    .udp_sockets
+                    .get(&socket_id)
+                    .map_or(false, |e| e.owner == emitter)
+                {
+                    self.udp_close(socket_id);
+                }
+            }
+        }
+    }
+
+    /// Handles a message on the firewall interface.
+    // TODO: any process can change the rules; add a proper permissions system
+    pub fn firewall_message(
+        &mut self,
+        message_id: Option<MessageId>,
+        message: firewall::FirewallMessage,
+    ) {
+        match message {
+            firewall::FirewallMessage::SetRules(ruleset) => self.firewall.set_rules(ruleset),
+            firewall::FirewallMessage::GetRules => {
+                if let Some(message_id) = message_id {
+                    redshirt_syscalls_interface::emit_answer(message_id, &self.firewall.rules());
+                }
+            }
+        }
+    }
+
+    /// Removes the interfaces and sockets of a process that has terminated.
+    pub fn process_destroyed(&mut self, pid: Pid) {
+        let interfaces = self
+            .interfaces
+            .keys()
+            .filter(|(driver, _)| *driver == pid)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in interfaces {
+            let interface = self.interfaces.remove(&key).unwrap();
+            self.sockets.remove(interface.dhcpv6_socket);
+        }
+
+        let tcp_sockets = self
+            .tcp_sockets
+            .iter()
+            .filter(|(_, e)| e.owner == pid)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for socket_id in tcp_sockets {
+            let entry = self.tcp_sockets.remove(&socket_id).unwrap();
+            self.sockets.get::<TcpSocket>(entry.handle).abort();
+            // The reset is sent the next time the interfaces are polled.
+            self.tcp_sockets.insert(
+                socket_id,
+                TcpEntry {
+                    closed: true,
+                    listener: false,
+                    pending_open: None,
+                    pending_accept: None,
+                    pending_read: None,
+                    pending_write: None,
+                    ..entry
+                },
+            );
+        }
+
+        let udp_sockets = self
+            .udp_sockets
+            .iter()
+            .filter(|(_, e)| e.owner == pid)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for socket_id in udp_sockets {
+            let entry = self.udp_sockets.remove(&socket_id).unwrap();
+            self.sockets.remove(entry.handle);
+        }
+    }
+
+    /// Forgets about a message that its emitter has cancelled.
+    pub fn message_cancelled(&mut self, message_id: MessageId) {
+        for interface in self.interfaces.values_mut() {
+            if interface.pending_wait == Some(message_id) {
+                interface.pending_wait = None;
+            }
+        }
+
+        for entry in self.tcp_sockets.values_mut() {
+            if entry.pending_accept == Some(message_id) {
+                entry.pending_accept = None;
+            }
+            if entry.pending_read == Some(message_id) {
+                entry.pending_read = None;
+            }
+            if entry.pending_write.as_ref().map(|(m, _)| *m) == Some(message_id) {
+                entry.pending_write = None;
+            }
+        }
+
+        let cancelled_open = self
+            .tcp_sockets
+            .iter()
+            .find(|(_, e)| e.pending_open == Some(message_id))
+            .map(|(id, _)| *id);
+        if let Some(socket_id) = cancelled_open {
+            self.tcp_sockets.get_mut(&socket_id).unwrap().pending_open = None;
+            self.tcp_close(socket_id);
+        }
+
+        for entry in self.udp_sockets.values_mut() {
+            if entry.subscription == Some(message_id) {
+                entry.subscription = None;
+            }
+        }
+    }
+
+    /// Processes the frames received by the interfaces, updates the sockets, answers the
+    /// messages that can be answered, and hands the frames to send to the drivers.
+    ///
+    /// Returns the number of milliseconds after which this method must be called again if
+    /// nothing else happens in the meanwhile, or `None` if there is no need to.
+    pub fn poll(&mut self, now_ms: i64) -> Option<u64> {
+        let timestamp = Instant::from_millis(now_ms);
+
+        for interface in self.interfaces.values_mut() {
+            // Errors concern individual packets, and are handled the same way as a lost packet.
+            let _ = interface.inner.poll(&mut self.sockets, timestamp);
+        }
+
+        self.update_tcp_sockets();
+        self.update_udp_sockets();
+
+        // Send out what the sockets have queued while being updated.
+        for interface in self.interfaces.values_mut() {
+            // The DHCPv6 datagrams are queued right before the interface is polled, as the
+            // sockets are shared by all the interfaces and the first one to be polled sends
+            // them.
+            update_dhcpv6(interface, &mut self.sockets, now_ms);
+            let _ = interface.inner.poll(&mut self.sockets, timestamp);
+
+            if let Some(message_id) = interface.pending_wait {
+                while let Some(frame) = interface.inner.device_mut().pop_to_send() {
+                    // Denied frames are lost, as if the network had dropped them.
+                    if self
+                        .firewall
+                        .filter(interface.mac_address, Direction::Outbound, &frame)
+                    {
+                        redshirt_syscalls_interface::emit_answer(message_id, &frame);
+                        interface.pending_wait = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let sockets = &self.sockets;
+        let dhcpv6_delays = self.interfaces.values().map(|interface| {
+            let delay = interface.dhcpv6.next_poll_ms().saturating_sub(now_ms);
+            u64::try_from(delay).unwrap_or(0)
+        });
+        self.interfaces
+            .values()
+            .filter_map(|interface| interface.inner.poll_delay(sockets, timestamp))
+            .map(|delay| delay.total_millis())
+            .chain(dhcpv6_delays)
+            .min()
+    }
+
+    /// Creates a new TCP socket listening on the given port.
+    fn tcp_listen(&mut self, owner: Pid, port: u16) -> Result<(u32, u16), ()> {
+        let port = match port {
+            0 => self.ephemeral_port(),
+            p if self.is_port_used(p) => return Err(()),
+            p => p,
+        };
+
+        let handle = self.sockets.add(new_tcp_socket());
+        self.sockets
+            .get::<TcpSocket>(handle)
+            .listen(port)
+            .map_err(|_| ())?;
+
+        let socket_id = self.allocate_socket_id();
+        self.tcp_sockets.insert(
+            socket_id,
+            TcpEntry {
+                owner,
+                handle,
+                local_port: port,
+                listener: true,
+                closed: false,
+                pending_open: None,
+                pending_accept: None,
+                pending_read: None,
+                pending_write: None,
+            },
+        );
+        Ok((socket_id, port))
+    }
+
+    /// Creates a new TCP socket connecting to the given remote.
+    fn tcp_open(&mut self, owner: Pid, remote: IpEndpoint) -> Result<u32, ()> {
+        let local_port = self.ephemeral_port();
+        let handle = self.sockets.add(new_tcp_socket());
+        if self
+            .sockets
+            .get::<TcpSocket>(handle)
+            .connect(remote, local_port)
+            .is_err()
+        {
+            self.sockets.remove(handle);
+            return Err(());
+        }
+
+        let socket_id = self.allocate_socket_id();
+        self.tcp_sockets.insert(
+            socket_id,
+            TcpEntry {
+                owner,
+                handle,
+                local_port,
+                listener: false,
+                closed: false,
+                pending_open: None,
+                pending_accept: None,
+                pending_read: None,
+                pending_write: None,
+            },
+        );
+        Ok(socket_id)
+    }
+
+    /// Closes a TCP socket. The socket is removed once the connection is closed.
+    fn tcp_close(&mut self, socket_id: u32) {
+        let entry = match self.tcp_sockets.get_mut(&socket_id) {
+            Some(e) => e,
+            None => return,
+        };
+
+        if let Some(message_id) = entry.pending_open.take() {
+            redshirt_syscalls_interface::emit_answer(
+                message_id,
+                &tcp::TcpOpenResponse { result: Err(()) },
+            );
+        }
+        if let Some(message_id) = entry.pending_accept.take() {
+            redshirt_syscalls_interface::emit_message_error(message_id);
+        }
+        if let Some(message_id) = entry.pending_read.take() {
+            redshirt_syscalls_interface::emit_answer(
+                message_id,
+                &tcp::TcpReadResponse { result: Err(()) },
+            );
+        }
+        if let Some((message_id, _)) = entry.pending_write.take() {
+            redshirt_syscalls_interface::emit_answer(
+                message_id,
+                &tcp::TcpWriteResponse { result: Err(()) },
+            );
+        }
+
+        let mut socket = self.sockets.get::<TcpSocket>(entry.handle);
+        if entry.listener {
+            socket.abort();
+        } else {
+            socket.close();
+        }
+        entry.listener = false;
+        entry.closed = true;
+    }
+
+    /// Answers the pending TCP messages that can be answered.
+    fn update_tcp_sockets(&mut self) {
+        let mut accepted = Vec::new();
+        let mut removed = Vec::new();
+
+        for (socket_id, entry) in self.tcp_sockets.iter_mut() {
+            let mut socket = self.sockets.get::<TcpSocket>(entry.handle);
+
+            if entry.closed {
+                if !socket.is_open() {
+                    removed.push(*socket_id);
+                }
+                continue;
+            }
+
+            if entry.listener {
+                if socket.is_listening() {
+                    continue;
+                }
+                if socket.state() == TcpState::Closed {
+                    // The incoming connection has been reset before being accepted.
+                    let _ = socket.listen(entry.local_port);
+                    continue;
+                }
+                if let Some(message_id) = entry.pending_accept.take() {
+                    accepted.push((*socket_id, message_id, socket.remote_endpoint()));
+                }
+                continue;
+            }
+
+            if let Some(message_id) = entry.pending_open {
+                match socket.state() {
+                    TcpState::SynSent | TcpState::SynReceived => continue,
+                    TcpState::Closed => {
+                        redshirt_syscalls_interface::emit_answer(
+                            message_id,
+                            &tcp::TcpOpenResponse { result: Err(()) },
+                        );
+                        entry.pending_open = None;
+                        entry.closed = true;
+                        continue;
+                    }
+                    _ => {
+                        redshirt_syscalls_interface::emit_answer(
+                            message_id,
+                            &tcp::TcpOpenResponse {
+                                result: Ok(*socket_id),
+                            },
+                        );
+                        entry.pending_open = None;
+                    }
+                }
+            }
+
+            if let Some(message_id) = entry.pending_read {
+                let result = if socket.can_recv() {
+                    Some(
+                        socket
+                            .recv(|data| (data.len(), data.to_vec()))
+                            .map_err(|_| ()),
+                    )
+                } else if !socket.may_recv() {
+                    // An empty buffer indicates that the remote has closed its side.
+                    Some(Ok(Vec::new()))
+                } else {
+                    None
+                };
+
+                if let Some(result) = result {
+                    redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &tcp::TcpReadResponse { result },
+                    );
+                    entry.pending_read = None;
+                }
+            }
+
+            if let Some((message_id, mut data)) = entry.pending_write.take() {
+                let result = if !socket.may_send() {
+                    Some(Err(()))
+                } else if let Ok(written) = socket.send_slice(&data) {
+                    data.drain(..written);
+                    if data.is_empty() {
+                        Some(Ok(()))
+                    } else {
+                        None
+                    }
+                } else {
+                    Some(Err(()))
+                };
+
+                match result {
+                    Some(result) => redshirt_syscalls_interface::emit_answer(
+                        message_id,
+                        &tcp::TcpWriteResponse { result },
+                    ),
+                    None => entry.pending_write = Some((message_id, data)),
+                }
+            }
+        }
+
+        for socket_id in removed {
+            let entry = self.tcp_sockets.remove(&socket_id).unwrap();
+            self.sockets.remove(entry.handle);
+        }
+
+        // The socket of the listener becomes the accepted socket, and a new socket is put in
+        // its place.
+        for (listener_id, message_id, remote) in accepted {
+            let accepted_socket_id = self.allocate_socket_id();
+            let new_handle = self.sockets.add(new_tcp_socket());
+            let listener = self.tcp_sockets.get_mut(&listener_id).unwrap();
+            let _ = self
+                .sockets
+                .get::<TcpSocket>(new_handle)
+                .listen(listener.local_port);
+            let handle = std::mem::replace(&mut listener.handle, new_handle);
+            let (owner, local_port) = (listener.owner, listener.local_port);
+
+            self.tcp_sockets.insert(
+                accepted_socket_id,
+                TcpEntry {
+                    owner,
+                    handle,
+                    local_port,
+                    listener: false,
+                    closed: false,
+                    pending_open: None,
+                    pending_accept: None,
+                    pending_read: None,
+                    pending_write: None,
+                },
+            );
+
+            redshirt_syscalls_interface::emit_answer(
+                message_id,
+                &tcp::TcpAcceptResponse {
+                    accepted_socket_id,
+                    remote_ip: from_ip_address(remote.addr),
+                    remote_port: remote.port,
+                },
+            );
+        }
+    }
+
+    /// Creates a new UDP socket bound to the given port.
+    fn udp_bind(&mut self, owner: Pid, port: u16) -> Result<(u32, u16), ErrorPayload> {
+        let port = match port {
+            0 => self.ephemeral_port(),
+            p if p == dhcpv6::CLIENT_PORT || self.is_port_used(p) => {
+                return Err(
+                    ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("port already in use")
+                )
+            }
+            p => p,
+        };
+
+        let handle = self.sockets.add(new_udp_socket());
+        if let Err(err) = self.sockets.get::<UdpSocket>(handle).bind(port) {
+            self.sockets.remove(handle);
+            return Err(ErrorPayload::new(ErrorClass::OTHER).with_message(err.to_string()));
+        }
+
+        let socket_id = self.allocate_socket_id();
+        self.udp_sockets.insert(
+            socket_id,
+            UdpEntry {
+                owner,
+                handle,
+                local_port: port,
+                subscription: None,
+            },
+        );
+        Ok((socket_id, port))
+    }
+
+    /// Closes a UDP socket and ends its subscription, if any.
+    fn udp_close(&mut self, socket_id: u32) {
+        if let Some(entry) = self.udp_sockets.remove(&socket_id) {
+            if let Some(message_id) = entry.subscription {
+                redshirt_syscalls_interface::emit_message_error(message_id);
+            }
+            self.sockets.remove(entry.handle);
+        }
+    }
+
+    /// Sends the received datagrams to the subscribers.
+    fn update_udp_sockets(&mut self) {
+        for entry in self.udp_sockets.values() {
+            let mut socket = self.sockets.get::<UdpSocket>(entry.handle);
+            while let Ok((data, remote)) = socket.recv() {
+                // Datagrams received while there is no subscription are discarded.
+                if let Some(message_id) = entry.subscription {
+                    let datagram = udp::UdpDatagram {
+                        ip: from_ip_address(remote.addr),
+                        port: remote.port,
+                        data: data.to_vec(),
+                    };
+                    redshirt_syscalls_interface::emit_answer_partial(message_id, &datagram);
+                }
+            }
+        }
+    }
+
+    /// Returns true if a TCP or UDP socket uses the given local port.
+    fn is_port_used(&self, port: u16) -> bool {
+        self.tcp_sockets.values().any(|e| e.local_port == port)
+            || self.udp_sockets.values().any(|e| e.local_port == port)
+    }
+
+    /// Picks a port that isn't used by any socket.
+    fn ephemeral_port(&mut self) -> u16 {
+        loop {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = self
+                .next_ephemeral_port
+                .checked_add(1)
+                .unwrap_or(FIRST_EPHEMERAL_PORT);
+            if !self.is_port_used(port) {
+                break port;
+            }
+        }
+    }
+
+    /// Returns a socket identifier that isn't used by any socket.
+    fn allocate_socket_id(&mut self) -> u32 {
+        loop {
+            let id = self.next_socket_id;
+            self.next_socket_id = self.next_socket_id.wrapping_add(1);
+            if !self.tcp_sockets.contains_key(&id) && !self.udp_sockets.contains_key(&id) {
+                break id;
+            }
+        }
+    }
+}
+
+fn new_tcp_socket() -> TcpSocket<'static> {
+    TcpSocket::new(
+        TcpSocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        TcpSocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+    )
+}
+
+fn new_udp_socket() -> UdpSocket<'static, 'static> {
+    UdpSocket::new(
+        UdpSocketBuffer::new(
+            vec![UdpPacketMetadata::EMPTY; UDP_BUFFER_DATAGRAMS],
+            vec![0; UDP_BUFFER_SIZE],
+        ),
+        UdpSocketBuffer::new(
+            vec![UdpPacketMetadata::EMPTY; UDP_BUFFER_DATAGRAMS],
+            vec![0; UDP_BUFFER_SIZE],
+        ),
+    )
+}
+
+/// Passes the received DHCPv6 datagrams to the client of the interface, queues the datagram
+/// it wants to send, if any, and applies the lease if it has changed.
+fn update_dhcpv6(
+    interface: &mut Interface,
+    sockets: &mut SocketSet<'static, 'static, 'static>,
+    now_ms: i64,
+) {
+    let mut socket = sockets.get::<UdpSocket>(interface.dhcpv6_socket);
+    while let Ok((data, remote)) = socket.recv() {
+        if remote.port == dhcpv6::SERVER_PORT {
+            interface.dhcpv6.on_datagram(now_ms, data);
+        }
+    }
+
+    if let Some(datagram) = interface.dhcpv6.poll(now_ms) {
+        let servers = IpEndpoint::new(to_ip_address(dhcpv6::SERVERS_ADDRESS), dhcpv6::SERVER_PORT);
+        // The datagram is sent again later if the buffer is full.
+        let _ = socket.send_slice(&datagram, servers);
+    }
+
+    if !interface.dhcpv6.take_lease_changed() {
+        return;
+    }
+
+    let lease = interface.dhcpv6.lease();
+    let addresses = interface_addresses(interface.mac_address, lease);
+    interface
+        .inner
+        .update_ip_addrs(|addrs| *addrs = addresses.into());
+
+    if let Some(lease) = lease {
+        // Delegated prefixes aren't routed anywhere for now, and are only reported.
+        let message = format!(
+            "DHCPv6 lease updated; addresses: {:?}; delegated prefixes: {:?}",
+            lease
+                .addresses
+                .iter()
+                .map(|a| to_ip_address(a.address).to_string())
+                .collect::<Vec<_>>(),
+            lease
+                .prefixes
+                .iter()
+                .map(|p| format!("{}/{}", to_ip_address(p.prefix), p.prefix_len))
+                .collect::<Vec<_>>(),
+        );
+        redshirt_log_interface::log(Level::Info, LOG_TARGET, &message);
+
+        if !lease.dns_servers.is_empty() {
+            let servers = lease
+                .dns_servers
+                .iter()
+                .map(|ip| redshirt_dns_interface::Server { ip: *ip, port: 53 })
+                .collect();
+            redshirt_dns_interface::set_servers(servers);
+        }
+    } else {
+        redshirt_log_interface::log(Level::Info, LOG_TARGET, "DHCPv6 lease expired");
+    }
+}
+
+/// Returns the link-local IPv6 address of an interface, derived from its MAC address as defined
+/// in RFC 4291.
+fn link_local_address(mac: [u8; 6]) -> Ipv6Address {
+    Ipv6Address([
+        0xfe,
+        0x80,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xff,
+        0xfe,
+        mac[3],
+        mac[4],
+        mac[5],
+    ])
+}
+
+/// Returns the addresses of an interface: the static IPv4 address, the link-local IPv6
+/// address, and the addresses of the DHCPv6 lease, if any.
+fn interface_addresses(mac: [u8; 6], lease: Option<&dhcpv6::Lease>) -> Vec<IpCidr> {
+    let mut addresses = vec![
+        IpCidr::new(IpAddress::Ipv4(Ipv4Address(IPV4_ADDRESS)), IPV4_PREFIX_LEN),
+        IpCidr::new(IpAddress::Ipv6(link_local_address(mac)), 64),
+    ];
+    if let Some(lease) = lease {
+        // The addresses obtained through DHCPv6 don't come with a prefix length.
+        addresses.extend(
+            lease
+                .addresses
+                .iter()
+                .map(|a| IpCidr::new(to_ip_address(a.address), 128)),
+        );
+    }
+    addresses
+}
+
+/// Converts an address of the TCP and UDP interfaces into an address of smoltcp. IPv4-mapped
+/// IPv6 addresses are turned into IPv4 addresses.
+fn to_ip_address(ip: [u16; 8]) -> IpAddress {
+    if ip[..5] == [0; 5] && ip[5] == 0xffff {
+        let [a, b] = ip[6].to_be_bytes();
+        let [c, d] = ip[7].to_be_bytes();
+        IpAddress::Ipv4(Ipv4Address([a, b, c, d]))
+    } else {
+        let mut bytes = [0; 16];
+        for (chunk, segment) in bytes.chunks_mut(2).zip(ip.iter()) {
+            chunk.copy_from_slice(&segment.to_be_bytes());
+        }
+        IpAddress::Ipv6(Ipv6Address(bytes))
+    }
+}
+
+/// Opposite of [`to_ip_address`].
+fn from_ip_address(ip: IpAddress) -> [u16; 8] {
+    match ip {
+        IpAddress::Ipv4(Ipv4Address([a, b, c, d])) => [
+            0,
+            0,
+            0,
+            0,
+            0,
+            0xffff,
+            u16::from_be_bytes([a, b]),
+            u16::from_be_bytes([c, d]),
+        ],
+        IpAddress::Ipv6(Ipv6Address(bytes)) => {
+            let mut ip = [0; 8];
+            for (segment, chunk) in ip.iter_mut().zip(bytes.chunks(2)) {
+                *segment = u16::from_be_bytes([chunk[0], chunk[1]]);
+            }
+            ip
+        }
+        _ => [0; 8],
+    }
+}
@@ -0,0 +1,526 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Initialization of an NVMe controller and submission of I/O commands.
+//!
+//! The controller is configured with several pairs of I/O queues, each of them with a fixed
+//! number of slots. A slot owns a data buffer and can hold one command at a time. Commands in
+//! different slots are processed by the controller in parallel.
+//!
+//! Completions are detected by polling the completion queues. A command that the controller
+//! hasn't completed after [`COMMAND_TIMEOUT_NS`] is reported as timed out.
+
+use crate::queue::{Command, QueuePair};
+use redshirt_hardware_interface::HardwareOperationsBuilder;
+
+const REG_CAP: u64 = 0x00;
+const REG_CC: u64 = 0x14;
+const REG_CSTS: u64 = 0x1c;
+const REG_AQA: u64 = 0x24;
+const REG_ASQ: u64 = 0x28;
+const REG_ACQ: u64 = 0x30;
+const DOORBELLS: u64 = 0x1000;
+
+const ADMIN_CREATE_IO_SQ: u8 = 0x01;
+const ADMIN_CREATE_IO_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+const ADMIN_SET_FEATURES: u8 = 0x09;
+
+const IO_FLUSH: u8 = 0x00;
+const IO_WRITE: u8 = 0x01;
+const IO_READ: u8 = 0x02;
+
+/// Size of a memory page, as configured in the controller.
+pub const PAGE_SIZE: u64 = 4096;
+/// Maximum number of bytes transferred by a single command.
+const MAX_TRANSFER_LEN: u64 = 8 * PAGE_SIZE;
+/// Maximum number of pairs of I/O queues to create.
+const MAX_IO_QUEUES: u16 = 4;
+/// Number of commands that can be in flight at the same time in each I/O queue.
+const SLOTS_PER_QUEUE: u16 = 8;
+/// Time after which a command that hasn't completed is reported as timed out, in nanoseconds.
+const COMMAND_TIMEOUT_NS: u128 = 5_000_000_000;
+
+/// Namespace of the controller, exposed as a block device.
+#[derive(Debug, Clone)]
+pub struct Namespace {
+    /// Identifier of the namespace within the controller.
+    pub id: u32,
+    /// Size of a logical block, in bytes.
+    pub sector_size: u32,
+    /// Number of logical blocks in the namespace.
+    pub num_sectors: u64,
+}
+
+/// Reason why a command has failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// The controller has completed the command with the given non-zero status code.
+    Status(u16),
+    /// The controller hasn't completed the command in time.
+    TimedOut,
+}
+
+/// Slot of an I/O queue. See the module-level documentation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SlotId {
+    queue: usize,
+    slot: u16,
+}
+
+struct Slot {
+    /// Physical address of the data buffer, made of contiguous pages.
+    data: u64,
+    /// Physical address of a list of pointers to the second page of `data` and onwards.
+    prp_list: u64,
+    /// True if a command is in flight.
+    busy: bool,
+    /// Value of the monotonic clock when [`Controller::next_completion`] started waiting for
+    /// the command in flight.
+    waiting_since: Option<u128>,
+    /// True if the command in flight has been reported as timed out. The slot stays busy until
+    /// the controller completes the command, as the controller might still access the buffer.
+    timed_out: bool,
+}
+
+/// Initialized NVMe controller.
+pub struct Controller {
+    admin: QueuePair,
+    io_queues: Vec<(QueuePair, Vec<Slot>)>,
+    namespaces: Vec<Namespace>,
+    /// Queue where to look first for a free slot. Rotates in order to spread the load.
+    next_queue: usize,
+}
+
+impl Controller {
+    /// Resets and initializes the controller whose registers are at the given physical address.
+    pub async unsafe fn init(registers: u64) -> Result<Controller, ()> {
+        let cap = read_u64(registers + REG_CAP).await;
+        let max_queue_entries = (cap & 0xffff) as u16 + 1;
+        let doorbell_stride = 4u64 << ((cap >> 32) & 0xf);
+
+        // Maximum time the controller takes to become ready, in units of 500ms.
+        let ready_timeout = u128::from((cap >> 24) & 0xff) * 500_000_000;
+
+        // Disable the controller before configuring the admin queues.
+        redshirt_hardware_interface::write_one_u32(registers + REG_CC, 0);
+        let start = redshirt_time_interface::monotonic_clock().await;
+        while read_u32(registers + REG_CSTS).await & 0x1 != 0 {
+            if redshirt_time_interface::monotonic_clock().await - start > ready_timeout {
+                return Err(());
+            }
+        }
+
+        let admin_size = max_queue_entries.min(32);
+        let admin = QueuePair::new(0, admin_size, registers + DOORBELLS, doorbell_stride).await;
+        let queue_sizes = u32::from(admin_size - 1) | (u32::from(admin_size - 1) << 16);
+        redshirt_hardware_interface::write_one_u32(registers + REG_AQA, queue_sizes);
+        write_u64(registers + REG_ASQ, admin.submission_address());
+        write_u64(registers + REG_ACQ, admin.completion_address());
+
+        // Enable the controller, with 64 bytes submission entries, 16 bytes completion entries,
+        // and 4kiB pages.
+        redshirt_hardware_interface::write_one_u32(registers + REG_CC, 1 | (6 << 16) | (4 << 20));
+        let start = redshirt_time_interface::monotonic_clock().await;
+        loop {
+            let csts = read_u32(registers + REG_CSTS).await;
+            if csts & 0x2 != 0 {
+                // Controller fatal status.
+                return Err(());
+            }
+            if csts & 0x1 != 0 {
+                break;
+            }
+            if redshirt_time_interface::monotonic_clock().await - start > ready_timeout {
+                return Err(());
+            }
+        }
+
+        let mut controller = Controller {
+            admin,
+            io_queues: Vec::new(),
+            namespaces: Vec::new(),
+            next_queue: 0,
+        };
+
+        let identify_buffer = crate::malloc_page_aligned(PAGE_SIZE).await;
+
+        // Identify the list of active namespaces.
+        let mut command = Command {
+            opcode: ADMIN_IDENTIFY,
+            prp1: identify_buffer,
+            ..Default::default()
+        };
+        command.cdw[0] = 0x2;
+        controller.admin_command(&command).await?;
+        let namespace_ids = read_buffer(identify_buffer, PAGE_SIZE as usize).await;
+        for id in namespace_ids.chunks(4) {
+            let id = u32::from_le_bytes([id[0], id[1], id[2], id[3]]);
+            if id == 0 {
+                break;
+            }
+
+            let mut command = Command {
+                opcode: ADMIN_IDENTIFY,
+                namespace: id,
+                prp1: identify_buffer,
+                ..Default::default()
+            };
+            command.cdw[0] = 0x0;
+            controller.admin_command(&command).await?;
+            let identify = read_buffer(identify_buffer, PAGE_SIZE as usize).await;
+
+            let mut num_sectors = [0; 8];
+            num_sectors.copy_from_slice(&identify[0..8]);
+            // The low 4 bits of `FLBAS` indicate which of the LBA formats, starting at offset
+            // 128, is in use.
+            let format = 128 + 4 * usize::from(identify[26] & 0xf);
+            let sector_size_log2 = identify[format + 2];
+            if sector_size_log2 < 9 || u64::from(sector_size_log2) > 12 {
+                continue;
+            }
+
+            controller.namespaces.push(Namespace {
+                id,
+                sector_size: 1 << sector_size_log2,
+                num_sectors: u64::from_le_bytes(num_sectors),
+            });
+        }
+
+        // Ask for the number of I/O queues. The controller answers with the number it allocated,
+        // which can be different.
+        let mut command = Command {
+            opcode: ADMIN_SET_FEATURES,
+            ..Default::default()
+        };
+        command.cdw[0] = 0x7;
+        command.cdw[1] = u32::from(MAX_IO_QUEUES - 1) | (u32::from(MAX_IO_QUEUES - 1) << 16);
+        let allocated = controller.admin_command(&command).await?;
+        let num_io_queues = MAX_IO_QUEUES
+            .min((allocated & 0xffff) as u16 + 1)
+            .min((allocated >> 16) as u16 + 1);
+
+        // One more entry than the number of slots, as a queue is full when its tail is right
+        // before its head.
+        let io_queue_size = max_queue_entries.min(SLOTS_PER_QUEUE + 1);
+        for id in 1..=num_io_queues {
+            let queue =
+                QueuePair::new(id, io_queue_size, registers + DOORBELLS, doorbell_stride).await;
+
+            let mut command = Command {
+                opcode: ADMIN_CREATE_IO_CQ,
+                prp1: queue.completion_address(),
+                ..Default::default()
+            };
+            command.cdw[0] = u32::from(id) | (u32::from(io_queue_size - 1) << 16);
+            // Physically contiguous, and interrupts disabled.
+            command.cdw[1] = 0x1;
+            controller.admin_command(&command).await?;
+
+            let mut command = Command {
+                opcode: ADMIN_CREATE_IO_SQ,
+                prp1: queue.submission_address(),
+                ..Default::default()
+            };
+            command.cdw[0] = u32::from(id) | (u32::from(io_queue_size - 1) << 16);
+            command.cdw[1] = 0x1 | (u32::from(id) << 16);
+            controller.admin_command(&command).await?;
+
+            let mut slots = Vec::with_capacity(usize::from(io_queue_size - 1));
+            for _ in 0..(io_queue_size - 1) {
+                slots.push(Slot::new().await);
+            }
+            controller.io_queues.push((queue, slots));
+        }
+
+        Ok(controller)
+    }
+
+    /// Returns the list of active namespaces.
+    pub fn namespaces(&self) -> &[Namespace] {
+        &self.namespaces
+    }
+
+    /// Returns the maximum number of sectors that a single command can transfer.
+    pub fn max_sectors_per_command(&self, namespace: &Namespace) -> u32 {
+        (MAX_TRANSFER_LEN / u64::from(namespace.sector_size)) as u32
+    }
+
+    /// Finds a slot without any command in flight and reserves it.
+    pub fn reserve_slot(&mut self) -> Option<SlotId> {
+        for n in 0..self.io_queues.len() {
+            let queue = (self.next_queue + n) % self.io_queues.len();
+            let slots = &mut self.io_queues[queue].1;
+            if let Some(slot) = slots.iter().position(|s| !s.busy) {
+                slots[slot].busy = true;
+                self.next_queue = (queue + 1) % self.io_queues.len();
+                return Some(SlotId {
+                    queue,
+                    slot: slot as u16,
+                });
+            }
+        }
+        None
+    }
+
+    /// Marks a slot as available again.
+    ///
+    /// If the command of the slot has timed out, the slot only becomes available once the
+    /// controller completes the command.
+    pub fn release_slot(&mut self, slot: SlotId) {
+        let slot = &mut self.io_queues[slot.queue].1[usize::from(slot.slot)];
+        if !slot.timed_out {
+            slot.busy = false;
+        }
+    }
+
+    /// Returns true if at least one slot has a command in flight that hasn't timed out.
+    pub fn has_commands_in_flight(&self) -> bool {
+        self.io_queues
+            .iter()
+            .any(|(_, slots)| slots.iter().any(|s| s.busy && !s.timed_out))
+    }
+
+    /// Starts reading sectors into the data buffer of the slot.
+    pub unsafe fn submit_read(
+        &mut self,
+        slot: SlotId,
+        namespace: &Namespace,
+        first_sector: u64,
+        num_sectors: u32,
+    ) {
+        self.submit_transfer(IO_READ, slot, namespace, first_sector, num_sectors);
+    }
+
+    /// Copies `data` into the data buffer of the slot and starts writing it.
+    pub unsafe fn submit_write(
+        &mut self,
+        slot: SlotId,
+        namespace: &Namespace,
+        first_sector: u64,
+        data: Vec<u8>,
+    ) {
+        debug_assert!(data.len() as u64 <= MAX_TRANSFER_LEN);
+        let num_sectors = (data.len() / namespace.sector_size as usize) as u32;
+        let buffer = self.io_queues[slot.queue].1[usize::from(slot.slot)].data;
+        redshirt_hardware_interface::write(buffer, data);
+        self.submit_transfer(IO_WRITE, slot, namespace, first_sector, num_sectors);
+    }
+
+    /// Starts flushing the volatile write cache of the namespace.
+    pub unsafe fn submit_flush(&mut self, slot: SlotId, namespace: &Namespace) {
+        let command = Command {
+            opcode: IO_FLUSH,
+            namespace: namespace.id,
+            ..Default::default()
+        };
+        let (queue, slots) = &mut self.io_queues[slot.queue];
+        slots[usize::from(slot.slot)].waiting_since = None;
+        queue.submit(slot.slot, &command);
+    }
+
+    unsafe fn submit_transfer(
+        &mut self,
+        opcode: u8,
+        slot: SlotId,
+        namespace: &Namespace,
+        first_sector: u64,
+        num_sectors: u32,
+    ) {
+        debug_assert!(num_sectors >= 1);
+        debug_assert!(num_sectors <= self.max_sectors_per_command(namespace));
+
+        let (queue, slots) = &mut self.io_queues[slot.queue];
+        let buffer = &mut slots[usize::from(slot.slot)];
+        buffer.waiting_since = None;
+        let len = u64::from(num_sectors) * u64::from(namespace.sector_size);
+
+        // If the transfer spans two pages, the second pointer is the second page. If it spans
+        // more, it is the list of pointers to the pages after the first one.
+        let prp2 = if len <= PAGE_SIZE {
+            0
+        } else if len <= 2 * PAGE_SIZE {
+            buffer.data + PAGE_SIZE
+        } else {
+            buffer.prp_list
+        };
+
+        let mut command = Command {
+            opcode,
+            namespace: namespace.id,
+            prp1: buffer.data,
+            prp2,
+            ..Default::default()
+        };
+        command.cdw[0] = first_sector as u32;
+        command.cdw[1] = (first_sector >> 32) as u32;
+        command.cdw[2] = num_sectors - 1;
+        queue.submit(slot.slot, &command);
+    }
+
+    /// Waits until a command completes or times out, and returns its slot and outcome.
+    ///
+    /// A command times out if it hasn't completed [`COMMAND_TIMEOUT_NS`] after this method has
+    /// started waiting for it. Its slot then stays busy until the controller completes the
+    /// command, at which point the completion is discarded and the slot becomes available.
+    ///
+    /// Must only be called if [`Controller::has_commands_in_flight`] returns true.
+    // TODO: wait for the interrupts of the completion queues instead of polling
+    pub async unsafe fn next_completion(&mut self) -> (SlotId, Result<(), CommandError>) {
+        loop {
+            // Read the entries at the head of all the completion queues at once.
+            let mut status_dwords = vec![[0u32; 1]; self.io_queues.len()];
+            let mut ops = HardwareOperationsBuilder::new();
+            for ((queue, _), out) in self.io_queues.iter().zip(status_dwords.iter_mut()) {
+                ops.read_u32(queue.head_status_address(), out);
+            }
+            ops.send().await;
+
+            let ready = self
+                .io_queues
+                .iter()
+                .zip(status_dwords.iter())
+                .position(|((queue, _), status)| queue.is_new_entry(status[0]));
+
+            if let Some(queue_index) = ready {
+                if let Some(completion) = self.io_queues[queue_index].0.poll().await {
+                    let slots = &mut self.io_queues[queue_index].1;
+                    let slot = match slots.get_mut(usize::from(completion.command_id)) {
+                        Some(slot) => slot,
+                        None => continue,
+                    };
+                    slot.waiting_since = None;
+
+                    // The command has already been reported as timed out.
+                    if slot.timed_out {
+                        slot.timed_out = false;
+                        slot.busy = false;
+                        continue;
+                    }
+
+                    let slot = SlotId {
+                        queue: queue_index,
+                        slot: completion.command_id,
+                    };
+                    let result = if completion.status == 0 {
+                        Ok(())
+                    } else {
+                        Err(CommandError::Status(completion.status))
+                    };
+                    return (slot, result);
+                }
+            }
+
+            let now = redshirt_time_interface::monotonic_clock().await;
+            for (queue_index, (_, slots)) in self.io_queues.iter_mut().enumerate() {
+                for (slot_index, slot) in slots.iter_mut().enumerate() {
+                    if !slot.busy || slot.timed_out {
+                        continue;
+                    }
+
+                    let waiting_since = *slot.waiting_since.get_or_insert(now);
+                    if now - waiting_since > COMMAND_TIMEOUT_NS {
+                        slot.timed_out = true;
+                        let slot = SlotId {
+                            queue: queue_index,
+                            slot: slot_index as u16,
+                        };
+                        return (slot, Err(CommandError::TimedOut));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the first `len` bytes of the data buffer of a slot.
+    pub async unsafe fn slot_data(&self, slot: SlotId, len: usize) -> Vec<u8> {
+        let buffer = self.io_queues[slot.queue].1[usize::from(slot.slot)].data;
+        read_buffer(buffer, len).await
+    }
+
+    /// Submits a command to the admin queue and waits for its completion. Returns the
+    /// command-specific result.
+    ///
+    /// Returns an error if the command fails or doesn't complete after [`COMMAND_TIMEOUT_NS`].
+    async unsafe fn admin_command(&mut self, command: &Command) -> Result<u32, ()> {
+        // Admin commands are sent one by one, so the identifier doesn't matter.
+        self.admin.submit(0, command);
+        let start = redshirt_time_interface::monotonic_clock().await;
+        loop {
+            if let Some(completion) = self.admin.poll().await {
+                return if completion.status == 0 {
+                    Ok(completion.result)
+                } else {
+                    Err(())
+                };
+            }
+            if redshirt_time_interface::monotonic_clock().await - start > COMMAND_TIMEOUT_NS {
+                return Err(());
+            }
+        }
+    }
+}
+
+impl Slot {
+    async unsafe fn new() -> Slot {
+        let data = crate::malloc_page_aligned(MAX_TRANSFER_LEN).await;
+        let prp_list = crate::malloc_page_aligned(PAGE_SIZE).await;
+
+        let mut list = Vec::new();
+        let mut page = data + PAGE_SIZE;
+        while page < data + MAX_TRANSFER_LEN {
+            list.extend_from_slice(&page.to_le_bytes());
+            page += PAGE_SIZE;
+        }
+        redshirt_hardware_interface::write(prp_list, list);
+
+        Slot {
+            data,
+            prp_list,
+            busy: false,
+            waiting_since: None,
+            timed_out: false,
+        }
+    }
+}
+
+async unsafe fn read_buffer(address: u64, len: usize) -> Vec<u8> {
+    let mut out = vec![0; len];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read(address, &mut out);
+    ops.send().await;
+    out
+}
+
+async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
+
+async unsafe fn read_u64(address: u64) -> u64 {
+    let mut out = [0u32; 2];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    u64::from(out[0]) | (u64::from(out[1]) << 32)
+}
+
+unsafe fn write_u64(address: u64, value: u64) {
+    redshirt_hardware_interface::write_one_u32(address, value as u32);
+    redshirt_hardware_interface::write_one_u32(address + 4, (value >> 32) as u32);
+}
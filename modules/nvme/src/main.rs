@@ -0,0 +1,399 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for NVMe controllers.
+//!
+//! This program scans the PCI space for NVMe controllers, and exposes each of their namespaces
+//! through the block device interface.
+//!
+//! Requests are spread over multiple I/O queues, and multiple requests can be in progress at the
+//! same time. Requests that are too large for a single command are split into multiple commands
+//! that are executed one after the other.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/NVMe
+//! - https://nvmexpress.org/wp-content/uploads/NVM-Express-1_4-2019.06.10-Ratified.pdf
+//!
+
+mod controller;
+mod queue;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_block_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom as _,
+};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    // TODO: support more than one controller
+    let mut controller = None;
+
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    for device in pci_devices {
+        // Class code for mass storage controllers, subclass for non-volatile memory, and
+        // programming interface for NVMe.
+        if device.class_code != 0x1 || device.subclass != 0x8 || device.prog_if != 0x2 {
+            continue;
+        }
+
+        let registers = match redshirt_pci_interface::map_bar(device.location, 0).await {
+            Ok(redshirt_pci_interface::PciBaseAddressRegister::Memory { base_address, .. }) => {
+                base_address
+            }
+            _ => continue,
+        };
+        redshirt_pci_interface::enable_bus_mastering(device.location);
+
+        if let Ok(c) = unsafe { controller::Controller::init(registers).await } {
+            controller = Some(c);
+            break;
+        }
+    }
+
+    let mut controller = match controller {
+        Some(c) if !c.namespaces().is_empty() => c,
+        _ => return,
+    };
+
+    // TODO: only one program can handle the block device interface at a time
+    if redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    // Requests waiting for a slot to be available.
+    let mut queued = VecDeque::<Request>::new();
+    // Requests being processed, and the number of sectors of the command currently in flight.
+    let mut in_flight = HashMap::<controller::SlotId, (Request, u32)>::new();
+
+    loop {
+        while !queued.is_empty() {
+            let slot = match controller.reserve_slot() {
+                Some(s) => s,
+                None => break,
+            };
+            let request = queued.pop_front().unwrap();
+            let num_sectors = unsafe { submit_next(&mut controller, slot, &request) };
+            in_flight.insert(slot, (request, num_sectors));
+        }
+
+        let event = if controller.has_commands_in_flight() {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            let next_completion = unsafe { controller.next_completion() };
+            futures::pin_mut!(next_completion);
+            match future::select(next_message, next_completion).await {
+                future::Either::Left((msg, _)) => future::Either::Left(msg),
+                future::Either::Right((completion, _)) => future::Either::Right(completion),
+            }
+        } else {
+            future::Either::Left(redshirt_syscalls_interface::next_interface_message().await)
+        };
+
+        match event {
+            future::Either::Left(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(
+                msg,
+            )) => {
+                assert_eq!(msg.interface, ffi::INTERFACE);
+                let message: ffi::BlockMessage = match DecodeAll::decode_all(&msg.actual_data) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+                if let Some(request) = handle_message(&controller, msg.message_id, message) {
+                    queued.push_back(request);
+                }
+            }
+            future::Either::Left(_) => {}
+            future::Either::Right((slot, result)) => {
+                let (mut request, num_sectors) = in_flight.remove(&slot).unwrap();
+                let namespace = controller.namespaces()[request.namespace].clone();
+
+                if let Err(error) = result {
+                    controller.release_slot(slot);
+                    let error = match error {
+                        controller::CommandError::Status(status) => {
+                            ErrorPayload::new(ErrorClass::IO).with_detail(u32::from(status))
+                        }
+                        controller::CommandError::TimedOut => {
+                            ErrorPayload::new(ErrorClass::TIMED_OUT)
+                        }
+                    };
+                    request.finish(Err(error));
+                    continue;
+                }
+
+                let finished = match &mut request.operation {
+                    Operation::Read {
+                        next_sector,
+                        remaining,
+                        data,
+                    } => {
+                        let len = num_sectors as usize * namespace.sector_size as usize;
+                        data.extend(unsafe { controller.slot_data(slot, len).await });
+                        *next_sector += u64::from(num_sectors);
+                        *remaining -= num_sectors;
+                        *remaining == 0
+                    }
+                    Operation::Write {
+                        next_sector,
+                        data,
+                        offset,
+                    } => {
+                        *offset += num_sectors as usize * namespace.sector_size as usize;
+                        *next_sector += u64::from(num_sectors);
+                        *offset == data.len()
+                    }
+                    Operation::Flush => true,
+                };
+
+                if finished {
+                    controller.release_slot(slot);
+                    request.finish(Ok(()));
+                } else {
+                    let num_sectors = unsafe { submit_next(&mut controller, slot, &request) };
+                    in_flight.insert(slot, (request, num_sectors));
+                }
+            }
+        }
+    }
+}
+
+/// Request in progress.
+struct Request {
+    message_id: Option<MessageId>,
+    /// Index of the namespace within [`controller::Controller::namespaces`].
+    namespace: usize,
+    operation: Operation,
+}
+
+enum Operation {
+    Read {
+        /// Next sector to read.
+        next_sector: u64,
+        /// Number of sectors left to read.
+        remaining: u32,
+        /// Data read so far.
+        data: Vec<u8>,
+    },
+    Write {
+        /// Sector where to write `data[offset..]`.
+        next_sector: u64,
+        data: Vec<u8>,
+        /// Number of bytes of `data` already written.
+        offset: usize,
+    },
+    Flush,
+}
+
+impl Request {
+    /// Sends back the answer to the request.
+    fn finish(self, result: Result<(), ErrorPayload>) {
+        match self.operation {
+            Operation::Read { data, .. } => {
+                let result = result.map(|()| data);
+                answer(self.message_id, ffi::ReadResponse { result });
+            }
+            Operation::Write { .. } => answer(self.message_id, ffi::WriteResponse { result }),
+            Operation::Flush => answer(self.message_id, ffi::FlushResponse { result }),
+        }
+    }
+}
+
+/// Processes a message. Answers it immediately if possible, otherwise returns the request to
+/// queue.
+fn handle_message(
+    controller: &controller::Controller,
+    message_id: Option<MessageId>,
+    message: ffi::BlockMessage,
+) -> Option<Request> {
+    let namespace = |device: u32| {
+        usize::try_from(device)
+            .ok()
+            .filter(|index| *index < controller.namespaces().len())
+            .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))
+    };
+
+    match message {
+        ffi::BlockMessage::ListDevices => {
+            let devices = (0..u32::try_from(controller.namespaces().len()).unwrap()).collect();
+            answer(message_id, ffi::ListDevicesResponse { devices });
+            None
+        }
+        ffi::BlockMessage::Geometry(geometry) => {
+            let result = namespace(geometry.device).map(|index| {
+                let namespace = &controller.namespaces()[index];
+                ffi::DeviceGeometry {
+                    sector_size: namespace.sector_size,
+                    num_sectors: namespace.num_sectors,
+                    read_only: false,
+                }
+            });
+            answer(message_id, ffi::GeometryResponse { result });
+            None
+        }
+        ffi::BlockMessage::Read(read) => {
+            let result = namespace(read.device).and_then(|index| {
+                check_range(
+                    &controller.namespaces()[index],
+                    read.first_sector,
+                    u64::from(read.num_sectors),
+                )?;
+                Ok(index)
+            });
+            match result {
+                Ok(_) if read.num_sectors == 0 => {
+                    answer(
+                        message_id,
+                        ffi::ReadResponse {
+                            result: Ok(Vec::new()),
+                        },
+                    );
+                    None
+                }
+                Ok(index) => Some(Request {
+                    message_id,
+                    namespace: index,
+                    operation: Operation::Read {
+                        next_sector: read.first_sector,
+                        remaining: read.num_sectors,
+                        data: Vec::new(),
+                    },
+                }),
+                Err(err) => {
+                    answer(message_id, ffi::ReadResponse { result: Err(err) });
+                    None
+                }
+            }
+        }
+        ffi::BlockMessage::Write(write) => {
+            let result = namespace(write.device).and_then(|index| {
+                let namespace = &controller.namespaces()[index];
+                if write.data.len() % namespace.sector_size as usize != 0 {
+                    return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("length isn't a multiple of the sector size"));
+                }
+                let num_sectors =
+                    u64::try_from(write.data.len()).unwrap() / u64::from(namespace.sector_size);
+                check_range(namespace, write.first_sector, num_sectors)?;
+                Ok(index)
+            });
+            match result {
+                Ok(_) if write.data.is_empty() => {
+                    answer(message_id, ffi::WriteResponse { result: Ok(()) });
+                    None
+                }
+                Ok(index) => Some(Request {
+                    message_id,
+                    namespace: index,
+                    operation: Operation::Write {
+                        next_sector: write.first_sector,
+                        data: write.data,
+                        offset: 0,
+                    },
+                }),
+                Err(err) => {
+                    answer(message_id, ffi::WriteResponse { result: Err(err) });
+                    None
+                }
+            }
+        }
+        ffi::BlockMessage::Flush(flush) => match namespace(flush.device) {
+            Ok(index) => Some(Request {
+                message_id,
+                namespace: index,
+                operation: Operation::Flush,
+            }),
+            Err(err) => {
+                answer(message_id, ffi::FlushResponse { result: Err(err) });
+                None
+            }
+        },
+    }
+}
+
+/// Submits the next command of a request in the given slot. Returns the number of sectors
+/// that the command transfers.
+unsafe fn submit_next(
+    controller: &mut controller::Controller,
+    slot: controller::SlotId,
+    request: &Request,
+) -> u32 {
+    let namespace = controller.namespaces()[request.namespace].clone();
+    let max_sectors = controller.max_sectors_per_command(&namespace);
+
+    match &request.operation {
+        Operation::Read {
+            next_sector,
+            remaining,
+            ..
+        } => {
+            let num_sectors = (*remaining).min(max_sectors);
+            controller.submit_read(slot, &namespace, *next_sector, num_sectors);
+            num_sectors
+        }
+        Operation::Write {
+            next_sector,
+            data,
+            offset,
+        } => {
+            let max_len = max_sectors as usize * namespace.sector_size as usize;
+            let chunk = &data[*offset..(*offset + max_len).min(data.len())];
+            let num_sectors = (chunk.len() / namespace.sector_size as usize) as u32;
+            controller.submit_write(slot, &namespace, *next_sector, chunk.to_vec());
+            num_sectors
+        }
+        Operation::Flush => {
+            controller.submit_flush(slot, &namespace);
+            0
+        }
+    }
+}
+
+/// Checks that the given range of sectors is within the namespace.
+fn check_range(
+    namespace: &controller::Namespace,
+    first_sector: u64,
+    num_sectors: u64,
+) -> Result<(), ErrorPayload> {
+    match first_sector.checked_add(num_sectors) {
+        Some(end) if end <= namespace.num_sectors => Ok(()),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("range of sectors out of the device")),
+    }
+}
+
+/// Allocates physical memory aligned to the size of a page.
+// TODO: the hardware interface doesn't support alignments larger than 128, so we over-allocate
+//       and waste the padding
+async fn malloc_page_aligned(size: u64) -> u64 {
+    let page_size = controller::PAGE_SIZE;
+    let ptr = redshirt_hardware_interface::malloc::malloc(size + page_size, 128).await;
+    (ptr + page_size - 1) / page_size * page_size
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
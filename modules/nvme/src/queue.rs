@@ -0,0 +1,183 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Submission and completion queues.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+/// Size of an entry of a submission queue.
+const SUBMISSION_ENTRY_LEN: u64 = 64;
+/// Size of an entry of a completion queue.
+const COMPLETION_ENTRY_LEN: u64 = 16;
+
+/// Command to put in a submission queue.
+#[derive(Debug, Default)]
+pub struct Command {
+    pub opcode: u8,
+    pub namespace: u32,
+    pub prp1: u64,
+    pub prp2: u64,
+    /// Command-specific dwords 10 to 15.
+    pub cdw: [u32; 6],
+}
+
+impl Command {
+    fn encode(&self, command_id: u16) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SUBMISSION_ENTRY_LEN as usize);
+        out.push(self.opcode);
+        out.push(0);
+        out.extend_from_slice(&command_id.to_le_bytes());
+        out.extend_from_slice(&self.namespace.to_le_bytes());
+        // Reserved dwords 2 and 3, and metadata pointer.
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&self.prp1.to_le_bytes());
+        out.extend_from_slice(&self.prp2.to_le_bytes());
+        for dword in &self.cdw {
+            out.extend_from_slice(&dword.to_le_bytes());
+        }
+        debug_assert_eq!(out.len() as u64, SUBMISSION_ENTRY_LEN);
+        out
+    }
+}
+
+/// Entry found in a completion queue.
+#[derive(Debug)]
+pub struct Completion {
+    /// Identifier passed when submitting the command.
+    pub command_id: u16,
+    /// Status code of the command. `0` on success.
+    pub status: u16,
+    /// Command-specific result.
+    pub result: u32,
+}
+
+/// Pair of a submission queue and the completion queue it reports to.
+pub struct QueuePair {
+    /// Number of entries in each of the two queues.
+    size: u16,
+    /// Physical address of the submission queue.
+    submission: u64,
+    /// Physical address of the completion queue.
+    completion: u64,
+    /// Physical address of the submission queue tail doorbell.
+    submission_doorbell: u64,
+    /// Physical address of the completion queue head doorbell.
+    completion_doorbell: u64,
+    submission_tail: u16,
+    completion_head: u16,
+    /// Value of the phase bit that indicates a new entry in the completion queue.
+    phase: bool,
+}
+
+impl QueuePair {
+    /// Allocates a new pair of queues with the given identifier.
+    ///
+    /// `doorbells` is the physical address of the first doorbell register, and `stride` the
+    /// distance between two doorbells.
+    pub async fn new(id: u16, size: u16, doorbells: u64, stride: u64) -> QueuePair {
+        let submission_len = u64::from(size) * SUBMISSION_ENTRY_LEN;
+        let completion_len = u64::from(size) * COMPLETION_ENTRY_LEN;
+        let submission = crate::malloc_page_aligned(submission_len).await;
+        let completion = crate::malloc_page_aligned(completion_len).await;
+
+        unsafe {
+            let mut ops = HardwareWriteOperationsBuilder::new();
+            ops.write(submission, vec![0; submission_len as usize]);
+            ops.write(completion, vec![0; completion_len as usize]);
+            ops.send();
+        }
+
+        QueuePair {
+            size,
+            submission,
+            completion,
+            submission_doorbell: doorbells + 2 * u64::from(id) * stride,
+            completion_doorbell: doorbells + (2 * u64::from(id) + 1) * stride,
+            submission_tail: 0,
+            completion_head: 0,
+            phase: true,
+        }
+    }
+
+    /// Returns the number of entries in each of the two queues.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Returns the physical address of the submission queue.
+    pub fn submission_address(&self) -> u64 {
+        self.submission
+    }
+
+    /// Returns the physical address of the completion queue.
+    pub fn completion_address(&self) -> u64 {
+        self.completion
+    }
+
+    /// Puts a command in the submission queue and notifies the controller.
+    ///
+    /// The caller must make sure that there are less than `size - 1` commands in flight.
+    pub unsafe fn submit(&mut self, command_id: u16, command: &Command) {
+        let address = self.submission + u64::from(self.submission_tail) * SUBMISSION_ENTRY_LEN;
+        self.submission_tail = (self.submission_tail + 1) % self.size;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(address, command.encode(command_id));
+        ops.write_one_u32(self.submission_doorbell, u32::from(self.submission_tail));
+        ops.send();
+    }
+
+    /// Returns the physical address of the status dword of the entry at the head of the
+    /// completion queue.
+    pub fn head_status_address(&self) -> u64 {
+        self.completion + u64::from(self.completion_head) * COMPLETION_ENTRY_LEN + 12
+    }
+
+    /// Given the status dword of the entry at the head of the completion queue, as read from
+    /// [`QueuePair::head_status_address`], returns true if it is a new entry.
+    pub fn is_new_entry(&self, status_dword: u32) -> bool {
+        ((status_dword >> 16) & 0x1 != 0) == self.phase
+    }
+
+    /// Pops the entry at the head of the completion queue, if any.
+    pub async unsafe fn poll(&mut self) -> Option<Completion> {
+        let mut entry = [0u32; 4];
+        let address = self.completion + u64::from(self.completion_head) * COMPLETION_ENTRY_LEN;
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read_u32(address, &mut entry);
+        ops.send().await;
+
+        if !self.is_new_entry(entry[3]) {
+            return None;
+        }
+
+        self.completion_head += 1;
+        if self.completion_head == self.size {
+            self.completion_head = 0;
+            self.phase = !self.phase;
+        }
+        redshirt_hardware_interface::write_one_u32(
+            self.completion_doorbell,
+            u32::from(self.completion_head),
+        );
+
+        Some(Completion {
+            command_id: (entry[3] & 0xffff) as u16,
+            // Bits 17 to 31 of the dword contain the status field.
+            status: (entry[3] >> 17) as u16,
+            result: entry[0],
+        })
+    }
+}
@@ -18,7 +18,7 @@ mod tcp_transport;
 use futures::prelude::*;
 use libp2p_core::transport::{boxed::Boxed, Transport};
 use libp2p_core::{identity, muxing::StreamMuxerBox, nodes::node::Substream, upgrade, PeerId};
-use libp2p_kad::{record::store::MemoryStore, record::Key, Kademlia, Quorum};
+use libp2p_kad::{record::store::MemoryStore, record::Key, Kademlia, Quorum, Record};
 use libp2p_mplex::MplexConfig;
 use libp2p_plaintext::PlainText2Config;
 use libp2p_swarm::Swarm;
@@ -92,6 +92,15 @@ impl<T> Network<T> {
         self.active_fetches.push((*hash, user_data));
     }
 
+    /// Stores a value on the network, so that it can later be fetched with
+    /// [`Network::start_fetch`]. `hash` must be the hash of `data`.
+    pub fn store(&mut self, hash: &[u8; 32], data: Vec<u8>) {
+        // TODO: the record is only kept in memory, and is lost when the program restarts
+        let _ = self
+            .swarm
+            .put_record(Record::new(Key::new(hash), data), Quorum::One);
+    }
+
     /// Returns a future that returns the next event that happens on the network.
     pub async fn next_event(&mut self) -> NetworkEvent<T> {
         loop {
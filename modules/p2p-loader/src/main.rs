@@ -16,6 +16,7 @@
 use futures::prelude::*;
 use p2p_loader::{Network, NetworkEvent};
 use parity_scale_codec::DecodeAll;
+use sha2::Digest as _;
 use std::time::Duration;
 
 fn main() {
@@ -43,6 +44,14 @@ async fn async_main() {
                 redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_),
                 _,
             )) => continue,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_),
+                _,
+            )) => continue,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_),
+                _,
+            )) => continue,
             future::Either::Right((NetworkEvent::FetchSuccess { data, user_data }, _)) => {
                 let rp = redshirt_loader_interface::ffi::LoadResponse { result: Ok(data) };
                 redshirt_syscalls_interface::emit_answer(user_data, &rp);
@@ -58,7 +67,18 @@ async fn async_main() {
         assert_eq!(msg.interface, redshirt_loader_interface::ffi::INTERFACE);
         let msg_data =
             redshirt_loader_interface::ffi::LoaderMessage::decode_all(&msg.actual_data).unwrap();
-        let redshirt_loader_interface::ffi::LoaderMessage::Load(hash_to_load) = msg_data;
-        network.start_fetch(&hash_to_load, msg.message_id.unwrap());
+        match msg_data {
+            redshirt_loader_interface::ffi::LoaderMessage::Load(hash_to_load) => {
+                network.start_fetch(&hash_to_load, msg.message_id.unwrap());
+            }
+            redshirt_loader_interface::ffi::LoaderMessage::Store(data) => {
+                let hash: [u8; 32] = sha2::Sha256::digest(&data).into();
+                network.store(&hash, data);
+                if let Some(message_id) = msg.message_id {
+                    let rp = redshirt_loader_interface::ffi::StoreResponse { result: Ok(hash) };
+                    redshirt_syscalls_interface::emit_answer(message_id, &rp);
+                }
+            }
+        }
     }
 }
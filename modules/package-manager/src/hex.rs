@@ -0,0 +1,50 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hexadecimal encoding.
+
+/// Encodes bytes as lowercase hexadecimal.
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hexadecimal, in lowercase or uppercase. Returns `None` if the string isn't valid
+/// hexadecimal.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = char::from(pair[0]).to_digit(16)?;
+            let low = char::from(pair[1]).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+/// Decodes exactly 32 bytes of hexadecimal.
+pub fn decode_32(text: &str) -> Option<[u8; 32]> {
+    let bytes = decode(text)?;
+    if bytes.len() != 32 {
+        return None;
+    }
+
+    let mut out = [0; 32];
+    out.copy_from_slice(&bytes);
+    Some(out)
+}
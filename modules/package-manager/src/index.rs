@@ -0,0 +1,190 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! List of installed packages.
+//!
+//! The list is stored in the file at [`INDEX_PATH`]. Each line of the file describes a package:
+//! its name, its version, the hash of its module, and the public key of its publisher, separated
+//! with spaces.
+
+use crate::{hex, manifest::Version};
+use redshirt_filesystem_interface::{ErrorPayload, File};
+
+/// Directory containing the index.
+const INDEX_DIR: &str = "/packages";
+/// Path of the file containing the index.
+pub const INDEX_PATH: &str = "/packages/index";
+
+/// List of installed packages.
+pub struct Index {
+    entries: Vec<Entry>,
+}
+
+/// Installed package.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub version: Version,
+    pub module_hash: [u8; 32],
+    /// Key that has signed the manifest of the package. Upgrades must be signed with the same
+    /// key.
+    pub public_key: [u8; 32],
+}
+
+impl Index {
+    /// Loads the index from the filesystem. Returns an empty index if the file doesn't exist.
+    /// Lines that can't be parsed are ignored.
+    pub async fn load() -> Index {
+        let content = match read_file(INDEX_PATH).await {
+            Ok(content) => content,
+            Err(_) => {
+                return Index {
+                    entries: Vec::new(),
+                }
+            }
+        };
+
+        let entries = String::from_utf8_lossy(&content)
+            .lines()
+            .filter_map(Entry::parse)
+            .collect();
+
+        Index { entries }
+    }
+
+    /// Writes the index to the filesystem.
+    pub async fn save(&self) -> Result<(), ErrorPayload> {
+        // An error here most likely means that the directory already exists. If not, creating
+        // the file below will fail as well.
+        let _ = redshirt_filesystem_interface::create_dir(INDEX_DIR).await;
+
+        let content = self
+            .entries
+            .iter()
+            .map(|entry| entry.to_line() + "\n")
+            .collect::<String>();
+
+        let mut file = File::create(INDEX_PATH).await?;
+        file.write(content.into_bytes()).await
+    }
+
+    /// Returns the installed package with the given name.
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Adds a package to the index, replacing the package with the same name if any.
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries.retain(|e| e.name != entry.name);
+        self.entries.push(entry);
+    }
+
+    /// Removes a package from the index. Returns `None` if it wasn't installed.
+    pub fn remove(&mut self, name: &str) -> Option<Entry> {
+        let position = self.entries.iter().position(|e| e.name == name)?;
+        Some(self.entries.remove(position))
+    }
+
+    /// Returns the list of installed packages.
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+}
+
+impl Entry {
+    /// Returns the description of the package, as sent on the package interface.
+    pub fn to_package(&self) -> redshirt_package_interface::Package {
+        redshirt_package_interface::Package {
+            name: self.name.clone(),
+            version: self.version.to_string(),
+            module_hash: self.module_hash,
+        }
+    }
+
+    fn parse(line: &str) -> Option<Entry> {
+        let mut fields = line.split_whitespace();
+        let entry = Entry {
+            name: fields.next()?.to_owned(),
+            version: Version::parse(fields.next()?)?,
+            module_hash: hex::decode_32(fields.next()?)?,
+            public_key: hex::decode_32(fields.next()?)?,
+        };
+
+        if fields.next().is_some() {
+            return None;
+        }
+
+        Some(entry)
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.name,
+            self.version,
+            hex::encode(&self.module_hash),
+            hex::encode(&self.public_key)
+        )
+    }
+}
+
+/// Reads the entire content of a file.
+async fn read_file(path: &str) -> Result<Vec<u8>, ErrorPayload> {
+    let mut file = File::open(path).await?;
+    let mut content = Vec::new();
+    loop {
+        let data = file.read(4096).await?;
+        if data.is_empty() {
+            break Ok(content);
+        }
+        content.extend_from_slice(&data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Entry;
+    use crate::manifest::Version;
+
+    const HASH: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const KEY: &str = "00000000000000000000000000000000000000000000000000000000000000ff";
+
+    #[test]
+    fn line_round_trip() {
+        let line = format!("hello-world 1.2.0 {} {}", HASH, KEY);
+        let entry = Entry::parse(&line).unwrap();
+        assert_eq!(entry.name, "hello-world");
+        assert_eq!(entry.version, Version::parse("1.2.0").unwrap());
+        assert_eq!(entry.module_hash[31], 1);
+        assert_eq!(entry.public_key[31], 0xff);
+        assert_eq!(entry.to_line(), line);
+    }
+
+    #[test]
+    fn extra_spaces_accepted() {
+        let line = format!("  hello-world   1.2.0\t{} {}  ", HASH, KEY);
+        assert!(Entry::parse(&line).is_some());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(Entry::parse("").is_none());
+        assert!(Entry::parse(&format!("hello-world 1.2.0 {}", HASH)).is_none());
+        assert!(Entry::parse(&format!("hello-world 1.2.0 {} {} extra", HASH, KEY)).is_none());
+        assert!(Entry::parse(&format!("hello-world 1.x {} {}", HASH, KEY)).is_none());
+        assert!(Entry::parse(&format!("hello-world 1.2.0 {} {}", &HASH[2..], KEY)).is_none());
+        assert!(Entry::parse(&format!("hello-world 1.2.0 {} zz{}", HASH, &KEY[2..])).is_none());
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Package manager.
+//!
+//! Registers the package interface. Packages are fetched over HTTP from a repository whose URL
+//! is passed through the `PACKAGES_REPOSITORY` environment variable. The manifest of a package
+//! named `foo` is found at `<repository>/foo/manifest`. See the [`manifest`] module for its
+//! format.
+//!
+//! Manifests must be signed by one of the publisher keys passed, in hexadecimal and separated
+//! with commas, through the `PACKAGES_TRUSTED_KEYS` environment variable. Nothing can be
+//! installed if the variable isn't set. The key that has signed a package is remembered when the
+//! package is installed, and upgrades must be signed with the same key.
+//!
+//! Modules are downloaded, checked against the hash found in the manifest, then stored through
+//! the loader, after which they can be loaded by their hash. The list of installed packages is
+//! kept in a file. See the [`index`] module.
+
+mod hex;
+mod index;
+mod manifest;
+
+use parity_scale_codec::DecodeAll;
+use redshirt_package_interface::{ffi, Package};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use sha2::Digest as _;
+
+/// Repository used if the `PACKAGES_REPOSITORY` environment variable isn't set.
+const DEFAULT_REPOSITORY: &str = "http://127.0.0.1:8000";
+/// Maximum size of a manifest, in bytes.
+const MAX_MANIFEST_SIZE: usize = 64 * 1024;
+/// Maximum size of a module, in bytes.
+const MAX_MODULE_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let repository = redshirt_arguments_interface::var("PACKAGES_REPOSITORY")
+        .await
+        .unwrap_or_else(|| DEFAULT_REPOSITORY.to_owned());
+    let trusted_keys = redshirt_arguments_interface::var("PACKAGES_TRUSTED_KEYS")
+        .await
+        .map_or(Some(Vec::new()), |keys| parse_trusted_keys(&keys));
+    let mut index = index::Index::load().await;
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+
+        let message_id = match msg.message_id {
+            Some(m) => m,
+            None => continue,
+        };
+
+        // TODO: messages are processed one at a time, and a slow download blocks all the other
+        //       requests
+        match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(ffi::PackageMessage::List) => {
+                let packages = index.iter().map(index::Entry::to_package).collect();
+                let response = ffi::ListResponse { packages };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+            }
+            Ok(ffi::PackageMessage::Install(name)) => {
+                let result = install(&repository, &trusted_keys, &mut index, &name).await;
+                let response = ffi::InstallResponse { result };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+            }
+            Ok(ffi::PackageMessage::Upgrade(name)) => {
+                let result = upgrade(&repository, &trusted_keys, &mut index, &name).await;
+                let response = ffi::UpgradeResponse { result };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+            }
+            Ok(ffi::PackageMessage::Remove(name)) => {
+                // TODO: the module stays in the loader, as the loader can't remove modules
+                let result = match index.remove(&name) {
+                    Some(_) => index.save().await,
+                    None => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                };
+                let response = ffi::RemoveResponse { result };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+            }
+            Err(_) => redshirt_syscalls_interface::emit_message_error(message_id),
+        }
+    }
+}
+
+/// Parses the value of the `PACKAGES_TRUSTED_KEYS` environment variable. Returns `None` if it
+/// is invalid.
+fn parse_trusted_keys(keys: &str) -> Option<Vec<[u8; 32]>> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(hex::decode_32)
+        .collect()
+}
+
+/// Installs the latest version of a package.
+async fn install(
+    repository: &str,
+    trusted_keys: &Option<Vec<[u8; 32]>>,
+    index: &mut index::Index,
+    name: &str,
+) -> Result<Package, ErrorPayload> {
+    let manifest = fetch_manifest(repository, trusted_keys, name).await?;
+
+    if let Some(installed) = index.get(name) {
+        if installed.public_key != manifest.public_key {
+            return Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+                .with_message("Manifest signed by a different key than the installed package"));
+        }
+    }
+
+    install_manifest(index, manifest).await
+}
+
+/// Installs the latest version of an installed package, if it is more recent.
+// TODO: processes running the previous version of the package are left untouched
+async fn upgrade(
+    repository: &str,
+    trusted_keys: &Option<Vec<[u8; 32]>>,
+    index: &mut index::Index,
+    name: &str,
+) -> Result<Option<Package>, ErrorPayload> {
+    let installed = match index.get(name) {
+        Some(entry) => entry.clone(),
+        None => return Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+    };
+
+    let manifest = fetch_manifest(repository, trusted_keys, name).await?;
+    if manifest.public_key != installed.public_key {
+        return Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+            .with_message("Manifest signed by a different key than the installed package"));
+    }
+
+    if manifest.version <= installed.version {
+        return Ok(None);
+    }
+
+    install_manifest(index, manifest).await.map(Some)
+}
+
+/// Downloads the manifest of the package with the given name and verifies that it has been
+/// signed by one of the trusted keys.
+async fn fetch_manifest(
+    repository: &str,
+    trusted_keys: &Option<Vec<[u8; 32]>>,
+    name: &str,
+) -> Result<manifest::Manifest, ErrorPayload> {
+    let trusted_keys = match trusted_keys {
+        Some(keys) if !keys.is_empty() => keys,
+        Some(_) => {
+            return Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+                .with_message("No trusted publisher key configured"))
+        }
+        None => {
+            return Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+                .with_message("Invalid PACKAGES_TRUSTED_KEYS"))
+        }
+    };
+
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(
+            ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("Invalid package name")
+        );
+    }
+
+    let url = format!("{}/{}/manifest", repository.trim_end_matches('/'), name);
+    let data = download(&url, MAX_MANIFEST_SIZE).await?;
+
+    let manifest = manifest::parse(&data, trusted_keys).map_err(|err| {
+        ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message(err.to_string())
+    })?;
+    if manifest.name != name {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("Manifest is for a different package"));
+    }
+
+    Ok(manifest)
+}
+
+/// Downloads the module of a manifest, stores it through the loader, and adds the package to
+/// the index.
+async fn install_manifest(
+    index: &mut index::Index,
+    manifest: manifest::Manifest,
+) -> Result<Package, ErrorPayload> {
+    let module = download(&manifest.module_url, MAX_MODULE_SIZE).await?;
+
+    let hash: [u8; 32] = sha2::Sha256::digest(&module).into();
+    if hash != manifest.module_hash {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("Module doesn't match the hash of the manifest"));
+    }
+
+    let stored_hash = redshirt_loader_interface::store(module)
+        .await
+        .map_err(|()| {
+            ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("Failed to store the module")
+        })?;
+    if stored_hash != hash {
+        return Err(
+            ErrorPayload::new(ErrorClass::OTHER).with_message("Loader returned an unexpected hash")
+        );
+    }
+
+    let entry = index::Entry {
+        name: manifest.name,
+        version: manifest.version,
+        module_hash: hash,
+        public_key: manifest.public_key,
+    };
+    let package = entry.to_package();
+    index.insert(entry);
+    index.save().await?;
+    Ok(package)
+}
+
+/// Downloads the resource at the given URL. Fails if it is larger than `max_size` bytes.
+async fn download(url: &str, max_size: usize) -> Result<Vec<u8>, ErrorPayload> {
+    let mut response = redshirt_http_client_interface::get(url).await?;
+    match response.status() {
+        200 => {}
+        404 => return Err(ErrorPayload::new(ErrorClass::NOT_FOUND).with_message(url.to_owned())),
+        status => {
+            return Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                .with_message(format!("HTTP status {} for {}", status, url)))
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        let chunk = response.read_body().await?;
+        if chunk.is_empty() {
+            break Ok(data);
+        }
+
+        data.extend_from_slice(&chunk);
+        if data.len() > max_size {
+            break Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message(format!("{} is too large", url)));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Manifests of the packages of a repository.
+//!
+//! A manifest is a text file made of `key: value` lines, the last of which contains the
+//! signature of all the bytes that precede it:
+//!
+//! ```text
+//! name: hello-world
+//! version: 1.2.0
+//! module: https://example.com/hello-world-1.2.0.wasm
+//! hash: <SHA-256 of the module, in hexadecimal>
+//! signature: <ed25519 signature, in hexadecimal>
+//! ```
+//!
+//! The signature must have been produced by one of the publisher keys trusted by the package
+//! manager. Unknown keys are ignored, in order to leave room for future additions, which
+//! includes the `public-key` key of the older manifests.
+
+use crate::hex;
+use std::{cmp::Ordering, fmt, str};
+
+/// Decoded and verified manifest.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+    /// URL where to download the module from.
+    pub module_url: String,
+    /// SHA-256 hash of the module.
+    pub module_hash: [u8; 32],
+    /// Trusted key that has signed the manifest.
+    pub public_key: [u8; 32],
+}
+
+/// Version of a package, made of numbers separated with dots.
+#[derive(Debug, Clone)]
+pub struct Version(Vec<u32>);
+
+/// Error that can happen when parsing a manifest.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The manifest isn't valid UTF-8.
+    NotUtf8,
+    /// A line isn't in the `key: value` format.
+    MalformedLine,
+    /// The manifest doesn't end with a signature.
+    MissingSignature,
+    /// A required key is missing.
+    MissingField(&'static str),
+    /// The value of a key is invalid.
+    InvalidField(&'static str),
+    /// The signature doesn't match the content of the manifest and any of the trusted keys.
+    BadSignature,
+}
+
+/// Parses a manifest and verifies that it has been signed by one of the given trusted keys.
+pub fn parse(data: &[u8], trusted_keys: &[[u8; 32]]) -> Result<Manifest, ParseError> {
+    let text = str::from_utf8(data).map_err(|_| ParseError::NotUtf8)?;
+
+    let signature_start = if text.starts_with("signature:") {
+        0
+    } else {
+        text.rfind("\nsignature:")
+            .map(|pos| pos + 1)
+            .ok_or(ParseError::MissingSignature)?
+    };
+    let (signed, signature_line) = text.split_at(signature_start);
+    let signature = hex::decode(signature_line["signature:".len()..].trim())
+        .ok_or(ParseError::InvalidField("signature"))?;
+
+    let mut name = None;
+    let mut version = None;
+    let mut module_url = None;
+    let mut module_hash = None;
+
+    for line in signed.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut split = line.splitn(2, ':');
+        let key = split.next().unwrap().trim();
+        let value = split.next().ok_or(ParseError::MalformedLine)?.trim();
+
+        match key {
+            "name" => name = Some(value.to_owned()),
+            "version" => {
+                version = Some(Version::parse(value).ok_or(ParseError::InvalidField("version"))?)
+            }
+            "module" => module_url = Some(value.to_owned()),
+            "hash" => {
+                module_hash = Some(hex::decode_32(value).ok_or(ParseError::InvalidField("hash"))?)
+            }
+            _ => {}
+        }
+    }
+
+    let public_key = *trusted_keys
+        .iter()
+        .find(|key| {
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &key[..])
+                .verify(signed.as_bytes(), &signature)
+                .is_ok()
+        })
+        .ok_or(ParseError::BadSignature)?;
+
+    Ok(Manifest {
+        name: name.ok_or(ParseError::MissingField("name"))?,
+        version: version.ok_or(ParseError::MissingField("version"))?,
+        module_url: module_url.ok_or(ParseError::MissingField("module"))?,
+        module_hash: module_hash.ok_or(ParseError::MissingField("hash"))?,
+        public_key,
+    })
+}
+
+impl Version {
+    /// Parses a version such as `1.2.0`. Returns `None` if the string is empty or contains
+    /// something else than numbers and dots.
+    pub fn parse(text: &str) -> Option<Version> {
+        let numbers = text
+            .split('.')
+            .map(|n| n.parse().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        Some(Version(numbers))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        // Missing numbers are considered to be 0, so that `1.2` and `1.2.0` are equal.
+        let len = self.0.len().max(other.0.len());
+        (0..len)
+            .map(|n| {
+                let a = self.0.get(n).cloned().unwrap_or(0);
+                let b = other.0.get(n).cloned().unwrap_or(0);
+                a.cmp(&b)
+            })
+            .find(|ord| *ord != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, number) in self.0.iter().enumerate() {
+            if n != 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", number)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::NotUtf8 => write!(f, "Manifest isn't valid UTF-8"),
+            ParseError::MalformedLine => write!(f, "Malformed line"),
+            ParseError::MissingSignature => write!(f, "Missing signature"),
+            ParseError::MissingField(key) => write!(f, "Missing field: {}", key),
+            ParseError::InvalidField(key) => write!(f, "Invalid field: {}", key),
+            ParseError::BadSignature => write!(f, "Bad signature"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, ParseError, Version};
+    use crate::hex;
+    use ring::signature::{Ed25519KeyPair, KeyPair as _};
+
+    const CONTENT: &str = "name: hello-world\n\
+                           version: 1.2.0\n\
+                           module: https://example.com/hello-world.wasm\n\
+                           hash: 0000000000000000000000000000000000000000000000000000000000000001\n";
+
+    /// Returns the public key derived from the given seed.
+    fn public_key(seed: u8) -> [u8; 32] {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(key_pair.public_key().as_ref());
+        public_key
+    }
+
+    /// Appends to `content` its signature with the key derived from the given seed.
+    fn sign(seed: u8, content: &str) -> Vec<u8> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+        let signature = key_pair.sign(content.as_bytes());
+        format!(
+            "{}signature: {}\n",
+            content,
+            hex::encode(signature.as_ref())
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn valid_manifest() {
+        let manifest = parse(&sign(1, CONTENT), &[public_key(2), public_key(1)]).unwrap();
+        assert_eq!(manifest.name, "hello-world");
+        assert_eq!(manifest.version, Version::parse("1.2").unwrap());
+        assert_eq!(manifest.module_url, "https://example.com/hello-world.wasm");
+        assert_eq!(manifest.module_hash[31], 1);
+        assert_eq!(manifest.public_key, public_key(1));
+    }
+
+    #[test]
+    fn embedded_public_key_not_trusted() {
+        // Claiming to be signed by a trusted key doesn't help.
+        let content = format!("{}public-key: {}\n", CONTENT, hex::encode(&public_key(2)));
+        let manifest = sign(1, &content);
+        assert_eq!(
+            parse(&manifest, &[public_key(2)]).unwrap_err(),
+            ParseError::BadSignature
+        );
+        assert_eq!(parse(&manifest, &[]).unwrap_err(), ParseError::BadSignature);
+    }
+
+    #[test]
+    fn tampered_manifest() {
+        let mut manifest = sign(1, CONTENT);
+        manifest[CONTENT.find("1.2.0").unwrap()] = b'9';
+        assert_eq!(
+            parse(&manifest, &[public_key(1)]).unwrap_err(),
+            ParseError::BadSignature
+        );
+    }
+
+    #[test]
+    fn malformed_manifests() {
+        let keys = [public_key(1)];
+        assert_eq!(
+            parse(CONTENT.as_bytes(), &keys).unwrap_err(),
+            ParseError::MissingSignature
+        );
+        assert_eq!(
+            parse(b"name: \xff\nsignature: 00\n", &keys).unwrap_err(),
+            ParseError::NotUtf8
+        );
+        assert_eq!(
+            parse(b"signature: zz\n", &keys).unwrap_err(),
+            ParseError::InvalidField("signature")
+        );
+        assert_eq!(
+            parse(&sign(1, "name: hello-world\nversion\n"), &keys).unwrap_err(),
+            ParseError::MalformedLine
+        );
+        assert_eq!(
+            parse(&sign(1, "name: hello-world\nversion: 1.x\n"), &keys).unwrap_err(),
+            ParseError::InvalidField("version")
+        );
+        assert_eq!(
+            parse(&sign(1, "name: hello-world\nversion: 1.0\n"), &keys).unwrap_err(),
+            ParseError::MissingField("module")
+        );
+    }
+
+    #[test]
+    fn version_parse() {
+        assert_eq!(Version::parse("1.2.0").unwrap().to_string(), "1.2.0");
+        assert!(Version::parse("").is_none());
+        assert!(Version::parse("1..2").is_none());
+        assert!(Version::parse("1.2-beta").is_none());
+    }
+
+    #[test]
+    fn version_ordering() {
+        let v = |s| Version::parse(s).unwrap();
+        assert_eq!(v("1.2"), v("1.2.0"));
+        assert!(v("1.2.1") > v("1.2"));
+        assert!(v("1.10") > v("1.9"));
+        assert!(v("2") > v("1.99.99"));
+    }
+}
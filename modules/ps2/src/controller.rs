@@ -0,0 +1,248 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Intel 8042 PS/2 controller.
+//!
+//! The controller is accessed through two I/O ports: a data port, used to exchange bytes with
+//! the controller and the devices, and a status/command port. Bytes sent by the devices are
+//! queued by the controller and retrieved one by one through the data port.
+
+const DATA_PORT: u32 = 0x60;
+const STATUS_PORT: u32 = 0x64;
+const COMMAND_PORT: u32 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+const STATUS_AUX: u8 = 1 << 5;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_AUX: u8 = 0xa7;
+const CMD_ENABLE_AUX: u8 = 0xa8;
+const CMD_TEST_AUX: u8 = 0xa9;
+const CMD_SELF_TEST: u8 = 0xaa;
+const CMD_TEST_KEYBOARD: u8 = 0xab;
+const CMD_DISABLE_KEYBOARD: u8 = 0xad;
+const CMD_ENABLE_KEYBOARD: u8 = 0xae;
+const CMD_WRITE_AUX: u8 = 0xd4;
+
+const CONFIG_KEYBOARD_IRQ: u8 = 1 << 0;
+const CONFIG_AUX_IRQ: u8 = 1 << 1;
+const CONFIG_AUX_CLOCK_DISABLED: u8 = 1 << 5;
+
+/// Acknowledgement sent by the devices after each byte they receive.
+const DEVICE_ACK: u8 = 0xfa;
+/// Sent by the devices if the byte they have received must be sent again.
+const DEVICE_RESEND: u8 = 0xfe;
+
+/// Number of nanoseconds after which we consider that the controller or a device isn't
+/// responding.
+const TIMEOUT_NS: u128 = 500_000_000;
+
+/// One of the two devices connected to the controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Port {
+    /// First port, normally connected to a keyboard.
+    Keyboard,
+    /// Second port, normally connected to a mouse.
+    Aux,
+}
+
+/// Initialized PS/2 controller. Both ports are enabled, but interrupts are disabled.
+pub struct Controller {
+    /// True if the controller has a second port.
+    has_aux: bool,
+}
+
+impl Controller {
+    /// Initializes the controller.
+    ///
+    /// Returns an error if there is no controller or if it doesn't respond properly.
+    pub async unsafe fn init() -> Result<Controller, ()> {
+        write_command(CMD_DISABLE_KEYBOARD).await?;
+        write_command(CMD_DISABLE_AUX).await?;
+        flush_output().await;
+
+        // Disable the interrupts while we initialize the devices. We keep the translation of
+        // the scan codes to the set 1 enabled.
+        let mut config = read_config().await?;
+        let may_have_aux = config & CONFIG_AUX_CLOCK_DISABLED != 0;
+        config &= !(CONFIG_KEYBOARD_IRQ | CONFIG_AUX_IRQ);
+        write_config(config).await?;
+
+        write_command(CMD_SELF_TEST).await?;
+        if read_data().await? != 0x55 {
+            return Err(());
+        }
+        // The self test might reset the controller on some hardware.
+        write_config(config).await?;
+
+        // If the second port exists, enabling it clears the bit that disables its clock.
+        let has_aux = if may_have_aux {
+            write_command(CMD_ENABLE_AUX).await?;
+            let has_aux = read_config().await? & CONFIG_AUX_CLOCK_DISABLED == 0;
+            write_command(CMD_DISABLE_AUX).await?;
+            has_aux
+        } else {
+            false
+        };
+
+        write_command(CMD_TEST_KEYBOARD).await?;
+        if read_data().await? != 0 {
+            return Err(());
+        }
+        let has_aux = if has_aux {
+            write_command(CMD_TEST_AUX).await?;
+            read_data().await? == 0
+        } else {
+            false
+        };
+
+        write_command(CMD_ENABLE_KEYBOARD).await?;
+        if has_aux {
+            write_command(CMD_ENABLE_AUX).await?;
+        }
+
+        Ok(Controller { has_aux })
+    }
+
+    /// Returns true if the controller has a second port.
+    pub fn has_aux(&self) -> bool {
+        self.has_aux
+    }
+
+    /// Enables the interrupts of the controller. Bytes sent by the devices then trigger IRQ 1
+    /// for the keyboard and IRQ 12 for the auxiliary device.
+    pub async unsafe fn enable_interrupts(&self) -> Result<(), ()> {
+        let mut config = read_config().await?;
+        config |= CONFIG_KEYBOARD_IRQ;
+        if self.has_aux {
+            config |= CONFIG_AUX_IRQ;
+        }
+        write_config(config).await
+    }
+
+    /// Sends a byte to a device and waits for it to be acknowledged.
+    pub async unsafe fn send(&self, port: Port, byte: u8) -> Result<(), ()> {
+        debug_assert!(port == Port::Keyboard || self.has_aux);
+
+        for _ in 0..3 {
+            if port == Port::Aux {
+                write_command(CMD_WRITE_AUX).await?;
+            }
+            write_data(byte).await?;
+
+            match self.receive(port).await? {
+                DEVICE_ACK => return Ok(()),
+                DEVICE_RESEND => continue,
+                _ => return Err(()),
+            }
+        }
+
+        Err(())
+    }
+
+    /// Waits for a byte to be sent by the given device. Bytes sent by the other device in the
+    /// meanwhile are discarded.
+    pub async unsafe fn receive(&self, port: Port) -> Result<u8, ()> {
+        let start = redshirt_time_interface::monotonic_clock().await;
+        loop {
+            if let Some((p, byte)) = self.try_read().await {
+                if p == port {
+                    return Ok(byte);
+                }
+                continue;
+            }
+
+            if redshirt_time_interface::monotonic_clock().await - start > TIMEOUT_NS {
+                return Err(());
+            }
+        }
+    }
+
+    /// Returns the next byte sent by one of the devices, if any.
+    pub async unsafe fn try_read(&self) -> Option<(Port, u8)> {
+        let status = read_status().await;
+        if status & STATUS_OUTPUT_FULL == 0 {
+            return None;
+        }
+
+        let port = if self.has_aux && status & STATUS_AUX != 0 {
+            Port::Aux
+        } else {
+            Port::Keyboard
+        };
+        let byte = redshirt_hardware_interface::port_read_u8(DATA_PORT).await;
+        Some((port, byte))
+    }
+}
+
+async unsafe fn read_config() -> Result<u8, ()> {
+    write_command(CMD_READ_CONFIG).await?;
+    read_data().await
+}
+
+async unsafe fn write_config(config: u8) -> Result<(), ()> {
+    write_command(CMD_WRITE_CONFIG).await?;
+    write_data(config).await
+}
+
+/// Discards the bytes waiting to be read.
+async unsafe fn flush_output() {
+    // Limit the number of iterations in case the controller misbehaves.
+    for _ in 0..64 {
+        if read_status().await & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        redshirt_hardware_interface::port_read_u8(DATA_PORT).await;
+    }
+}
+
+async unsafe fn write_command(command: u8) -> Result<(), ()> {
+    wait_input_empty().await?;
+    redshirt_hardware_interface::port_write_u8(COMMAND_PORT, command);
+    Ok(())
+}
+
+async unsafe fn write_data(data: u8) -> Result<(), ()> {
+    wait_input_empty().await?;
+    redshirt_hardware_interface::port_write_u8(DATA_PORT, data);
+    Ok(())
+}
+
+/// Waits for a byte to be available, and reads it.
+async unsafe fn read_data() -> Result<u8, ()> {
+    let start = redshirt_time_interface::monotonic_clock().await;
+    while read_status().await & STATUS_OUTPUT_FULL == 0 {
+        if redshirt_time_interface::monotonic_clock().await - start > TIMEOUT_NS {
+            return Err(());
+        }
+    }
+    Ok(redshirt_hardware_interface::port_read_u8(DATA_PORT).await)
+}
+
+/// Waits for the controller to be ready to accept a byte.
+async unsafe fn wait_input_empty() -> Result<(), ()> {
+    let start = redshirt_time_interface::monotonic_clock().await;
+    while read_status().await & STATUS_INPUT_FULL != 0 {
+        if redshirt_time_interface::monotonic_clock().await - start > TIMEOUT_NS {
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+async unsafe fn read_status() -> u8 {
+    redshirt_hardware_interface::port_read_u8(STATUS_PORT).await
+}
@@ -0,0 +1,140 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! PS/2 keyboards.
+//!
+//! The controller translates the scan codes sent by the keyboard to the scan code set 1, which
+//! we then convert to USB HID usage IDs.
+
+use crate::controller::{Controller, Port};
+use redshirt_keyboard_interface::KeyEvent;
+
+const CMD_ENABLE_SCANNING: u8 = 0xf4;
+const CMD_RESET: u8 = 0xff;
+
+/// Sent by the keyboard after a successful reset.
+const SELF_TEST_PASSED: u8 = 0xaa;
+
+/// Resets the keyboard connected to the first port of the controller.
+pub async unsafe fn init(controller: &Controller) -> Result<(), ()> {
+    controller.send(Port::Keyboard, CMD_RESET).await?;
+    if controller.receive(Port::Keyboard).await? != SELF_TEST_PASSED {
+        return Err(());
+    }
+    controller.send(Port::Keyboard, CMD_ENABLE_SCANNING).await
+}
+
+/// Turns the bytes sent by the keyboard into key events.
+#[derive(Default)]
+pub struct Decoder {
+    /// True if the previous byte was the `0xe0` prefix of the extended keys.
+    extended: bool,
+    /// Number of bytes of the Pause key sequence that remain to be received.
+    pause_remaining: u8,
+}
+
+impl Decoder {
+    /// Processes a byte sent by the keyboard.
+    pub fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        // The Pause key sends `e1 1d 45 e1 9d c5` when pressed, and nothing when released.
+        if self.pause_remaining != 0 {
+            self.pause_remaining -= 1;
+            if self.pause_remaining == 0 {
+                return Some(KeyEvent {
+                    usage: USAGE_PAUSE,
+                    pressed: false,
+                });
+            }
+            return None;
+        }
+
+        match byte {
+            0xe0 => {
+                self.extended = true;
+                return None;
+            }
+            0xe1 => {
+                self.pause_remaining = 5;
+                return Some(KeyEvent {
+                    usage: USAGE_PAUSE,
+                    pressed: true,
+                });
+            }
+            _ => {}
+        }
+
+        let extended = self.extended;
+        self.extended = false;
+
+        let pressed = byte & 0x80 == 0;
+        let code = usize::from(byte & 0x7f);
+        let usage = if extended {
+            EXTENDED_SET1_TO_USAGE
+                .iter()
+                .find(|(c, _)| usize::from(*c) == code)
+                .map(|(_, u)| *u)?
+        } else {
+            match SET1_TO_USAGE.get(code) {
+                Some(0) | None => return None,
+                Some(u) => *u,
+            }
+        };
+
+        Some(KeyEvent { usage, pressed })
+    }
+}
+
+const USAGE_PAUSE: u16 = 0x48;
+
+/// USB HID usage ID of each scan code of the set 1, or 0 if the scan code is unused.
+#[rustfmt::skip]
+const SET1_TO_USAGE: [u16; 0x59] = [
+    // 0x00
+    0x00, 0x29, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x2d, 0x2e, 0x2a, 0x2b,
+    // 0x10
+    0x14, 0x1a, 0x08, 0x15, 0x17, 0x1c, 0x18, 0x0c, 0x12, 0x13, 0x2f, 0x30, 0x28, 0xe0, 0x04, 0x16,
+    // 0x20
+    0x07, 0x09, 0x0a, 0x0b, 0x0d, 0x0e, 0x0f, 0x33, 0x34, 0x35, 0xe1, 0x31, 0x1d, 0x1b, 0x06, 0x19,
+    // 0x30
+    0x05, 0x11, 0x10, 0x36, 0x37, 0x38, 0xe5, 0x55, 0xe2, 0x2c, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e,
+    // 0x40
+    0x3f, 0x40, 0x41, 0x42, 0x43, 0x53, 0x47, 0x5f, 0x60, 0x61, 0x56, 0x5c, 0x5d, 0x5e, 0x57, 0x59,
+    // 0x50
+    0x5a, 0x5b, 0x62, 0x63, 0x00, 0x00, 0x64, 0x44, 0x45,
+];
+
+/// USB HID usage ID of the scan codes of the set 1 that are preceded with `0xe0`.
+///
+/// The fake shift presses and releases that some keys, such as Print Screen, send alongside
+/// their own scan code aren't in this list and are therefore ignored.
+const EXTENDED_SET1_TO_USAGE: &[(u8, u16)] = &[
+    (0x1c, 0x58), // Keypad Enter
+    (0x1d, 0xe4), // Right Control
+    (0x35, 0x54), // Keypad /
+    (0x37, 0x46), // Print Screen
+    (0x38, 0xe6), // Right Alt
+    (0x47, 0x4a), // Home
+    (0x48, 0x52), // Up
+    (0x49, 0x4b), // Page Up
+    (0x4b, 0x50), // Left
+    (0x4d, 0x4f), // Right
+    (0x4f, 0x4d), // End
+    (0x50, 0x51), // Down
+    (0x51, 0x4e), // Page Down
+    (0x52, 0x49), // Insert
+    (0x53, 0x4c), // Delete
+    (0x5b, 0xe3), // Left GUI
+    (0x5c, 0xe7), // Right GUI
+    (0x5d, 0x65), // Application
+];
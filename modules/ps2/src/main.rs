@@ -0,0 +1,186 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Driver for the PS/2 controller, and the keyboard and mouse connected to it.
+//!
+//! Key presses are reported through the keyboard interface, and mouse movements through the
+//! pointer interface.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/%228042%22_PS/2_Controller
+//! - https://wiki.osdev.org/PS/2_Mouse
+//! - https://www.usb.org/document-library/hid-usage-tables-112
+//!
+
+mod controller;
+mod keyboard;
+mod mouse;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_interrupts_interface::{Interrupt, InterruptSource};
+use redshirt_keyboard_interface::ffi as keyboard_ffi;
+use redshirt_pointer_interface::ffi as pointer_ffi;
+use redshirt_syscalls_interface::{InterfaceOrDestroyed, MessageId, Pid};
+use std::pin::Pin;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let controller = match unsafe { controller::Controller::init().await } {
+        Ok(c) => c,
+        Err(()) => return,
+    };
+
+    let mut keyboard = match unsafe { keyboard::init(&controller).await } {
+        Ok(()) => Some(keyboard::Decoder::default()),
+        Err(()) => None,
+    };
+    let mut mouse = if controller.has_aux() {
+        unsafe { mouse::init(&controller).await.ok() }
+    } else {
+        None
+    };
+
+    if keyboard.is_none() && mouse.is_none() {
+        return;
+    }
+
+    // TODO: only one program can handle each interface at a time
+    if keyboard.is_some()
+        && redshirt_interface_interface::register_interface(keyboard_ffi::INTERFACE)
+            .await
+            .is_err()
+    {
+        keyboard = None;
+    }
+    if mouse.is_some()
+        && redshirt_interface_interface::register_interface(pointer_ffi::INTERFACE)
+            .await
+            .is_err()
+    {
+        mouse = None;
+    }
+
+    let keyboard_irq = Interrupt::claim(InterruptSource::IsaIrq(1)).await.ok();
+    let mouse_irq = Interrupt::claim(InterruptSource::IsaIrq(12)).await.ok();
+    if unsafe { controller.enable_interrupts().await }.is_err() {
+        return;
+    }
+
+    let mut keyboard_wait = wait_irq(&keyboard_irq);
+    let mut mouse_wait = wait_irq(&mouse_irq);
+
+    let mut keyboard_subscribers = Vec::<(Pid, MessageId)>::new();
+    let mut pointer_subscribers = Vec::<(Pid, MessageId)>::new();
+
+    loop {
+        let event = {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            let irq = future::select(&mut keyboard_wait, &mut mouse_wait);
+            match future::select(next_message, irq).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right((future::Either::Left(_), _)) => {
+                    keyboard_wait = wait_irq(&keyboard_irq);
+                    None
+                }
+                future::Either::Right((future::Either::Right(_), _)) => {
+                    mouse_wait = wait_irq(&mouse_irq);
+                    None
+                }
+            }
+        };
+
+        let msg = match event {
+            Some(InterfaceOrDestroyed::Interface(m)) => m,
+            Some(InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                keyboard_subscribers.retain(|(pid, _)| *pid != msg.pid);
+                pointer_subscribers.retain(|(pid, _)| *pid != msg.pid);
+                continue;
+            }
+            Some(InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                keyboard_subscribers.retain(|(_, id)| *id != msg.message_id);
+                pointer_subscribers.retain(|(_, id)| *id != msg.message_id);
+                continue;
+            }
+            Some(InterfaceOrDestroyed::Shutdown(_)) => continue,
+            None => {
+                // Both interrupts are handled the same way, as the controller indicates which
+                // device each byte comes from.
+                while let Some((port, byte)) = unsafe { controller.try_read().await } {
+                    match (port, &mut keyboard, &mut mouse) {
+                        (controller::Port::Keyboard, Some(decoder), _) => {
+                            if let Some(event) = decoder.feed(byte) {
+                                notify(&keyboard_subscribers, &event);
+                            }
+                        }
+                        (controller::Port::Aux, _, Some(decoder)) => {
+                            for event in decoder.feed(byte) {
+                                notify(&pointer_subscribers, &event);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                for irq in keyboard_irq.iter().chain(mouse_irq.iter()) {
+                    irq.ack();
+                }
+                continue;
+            }
+        };
+
+        let message_id = match msg.message_id {
+            Some(id) => id,
+            None => continue,
+        };
+
+        if msg.interface == keyboard_ffi::INTERFACE {
+            match DecodeAll::decode_all(&msg.actual_data) {
+                Ok(keyboard_ffi::KeyboardMessage::Subscribe) => {
+                    keyboard_subscribers.push((msg.emitter_pid, message_id));
+                }
+                Err(_) => redshirt_syscalls_interface::emit_message_error(message_id),
+            }
+        } else if msg.interface == pointer_ffi::INTERFACE {
+            match DecodeAll::decode_all(&msg.actual_data) {
+                Ok(pointer_ffi::PointerMessage::Subscribe) => {
+                    pointer_subscribers.push((msg.emitter_pid, message_id));
+                }
+                Err(_) => redshirt_syscalls_interface::emit_message_error(message_id),
+            }
+        } else {
+            unreachable!()
+        }
+    }
+}
+
+/// Returns a `Future` that is ready when the interrupt is triggered, or never if the interrupt
+/// couldn't be claimed.
+fn wait_irq(irq: &Option<Interrupt>) -> Pin<Box<dyn Future<Output = ()>>> {
+    match irq {
+        Some(irq) => Box::pin(irq.wait().map(|_| ())),
+        None => Box::pin(future::pending()),
+    }
+}
+
+/// Sends an event to all the subscribers.
+fn notify(subscribers: &[(Pid, MessageId)], event: &impl parity_scale_codec::Encode) {
+    for (_, message_id) in subscribers {
+        redshirt_syscalls_interface::emit_answer_partial(*message_id, event);
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! PS/2 mice.
+//!
+//! Mice send packets of three bytes containing the state of the buttons and the movement since
+//! the previous packet. Mice that support the IntelliMouse extension, which we try to enable,
+//! send a fourth byte containing the movement of the wheel.
+
+use crate::controller::{Controller, Port};
+use redshirt_pointer_interface::{Button, PointerEvent};
+
+const CMD_SET_SAMPLE_RATE: u8 = 0xf3;
+const CMD_GET_ID: u8 = 0xf2;
+const CMD_ENABLE_REPORTING: u8 = 0xf4;
+const CMD_RESET: u8 = 0xff;
+
+/// Sent by the mouse after a successful reset.
+const SELF_TEST_PASSED: u8 = 0xaa;
+/// Identifier returned by mice whose IntelliMouse extension is enabled.
+const INTELLIMOUSE_ID: u8 = 3;
+
+/// Resets the mouse connected to the second port of the controller and enables it.
+pub async unsafe fn init(controller: &Controller) -> Result<Decoder, ()> {
+    controller.send(Port::Aux, CMD_RESET).await?;
+    if controller.receive(Port::Aux).await? != SELF_TEST_PASSED {
+        return Err(());
+    }
+    // The self-test result is followed with the identifier of the device.
+    controller.receive(Port::Aux).await?;
+
+    // Setting the sample rate to 200, 100, then 80 enables the IntelliMouse extension on mice
+    // that support it.
+    for rate in &[200, 100, 80] {
+        controller.send(Port::Aux, CMD_SET_SAMPLE_RATE).await?;
+        controller.send(Port::Aux, *rate).await?;
+    }
+    controller.send(Port::Aux, CMD_GET_ID).await?;
+    let has_wheel = controller.receive(Port::Aux).await? == INTELLIMOUSE_ID;
+
+    controller.send(Port::Aux, CMD_ENABLE_REPORTING).await?;
+
+    Ok(Decoder {
+        packet: [0; 4],
+        received: 0,
+        packet_len: if has_wheel { 4 } else { 3 },
+        buttons: 0,
+    })
+}
+
+/// Turns the bytes sent by the mouse into pointer events.
+pub struct Decoder {
+    /// Bytes of the packet being received.
+    packet: [u8; 4],
+    /// Number of bytes of `packet` received so far.
+    received: usize,
+    /// Either 3, or 4 if the mouse reports the wheel.
+    packet_len: usize,
+    /// State of the buttons in the last packet.
+    buttons: u8,
+}
+
+impl Decoder {
+    /// Processes a byte sent by the mouse.
+    pub fn feed(&mut self, byte: u8) -> Vec<PointerEvent> {
+        // Bit 3 of the first byte of a packet is always set. If it isn't, we've lost track of
+        // the packet boundaries and discard bytes until we find a plausible first byte.
+        if self.received == 0 && byte & (1 << 3) == 0 {
+            return Vec::new();
+        }
+
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_len {
+            return Vec::new();
+        }
+        self.received = 0;
+
+        let mut events = Vec::new();
+        let flags = self.packet[0];
+
+        for (bit, button) in &[(0, Button::Left), (1, Button::Right), (2, Button::Middle)] {
+            let mask = 1 << bit;
+            if (flags ^ self.buttons) & mask != 0 {
+                events.push(PointerEvent::Button {
+                    button: *button,
+                    pressed: flags & mask != 0,
+                });
+            }
+        }
+        self.buttons = flags & 0x7;
+
+        // The movement is a 9 bits two's complement number, whose sign bit is in the flags.
+        // Packets whose movement has overflowed are ignored.
+        if flags & 0xc0 == 0 {
+            let dx = i32::from(self.packet[1]) - (i32::from(flags & 0x10) << 4);
+            let dy = i32::from(self.packet[2]) - (i32::from(flags & 0x20) << 3);
+            if dx != 0 || dy != 0 {
+                // The Y axis of the mouse goes up, while ours goes down.
+                events.push(PointerEvent::Motion { dx, dy: -dy });
+            }
+        }
+
+        if self.packet_len == 4 && self.packet[3] != 0 {
+            events.push(PointerEvent::Wheel {
+                delta: i32::from(self.packet[3] as i8),
+            });
+        }
+
+        events
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Provides a filesystem mounted at `/mem` that stores everything in memory.
+//!
+//! The content of the filesystem is lost when the module stops.
+
+use parity_scale_codec::DecodeAll;
+use redshirt_filesystem_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::{collections::BTreeMap, collections::HashMap, convert::TryFrom as _};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() -> ! {
+    redshirt_filesystem_interface::mount("/mem").await.unwrap();
+
+    let mut fs = Ramfs::new();
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                fs.process_destroyed(msg.pid);
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(
+            msg.interface,
+            redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE
+        );
+
+        let message: ffi::FilesystemMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        let emitter = msg.emitter_pid;
+
+        match message {
+            ffi::FilesystemMessage::Open(open) => answer(
+                msg.message_id,
+                ffi::OpenResponse {
+                    result: fs.open(emitter, open),
+                },
+            ),
+            ffi::FilesystemMessage::Read(read) => answer(
+                msg.message_id,
+                ffi::ReadResponse {
+                    result: fs.read(emitter, read.handle, read.len),
+                },
+            ),
+            ffi::FilesystemMessage::Write(write) => answer(
+                msg.message_id,
+                ffi::WriteResponse {
+                    result: fs.write(emitter, write.handle, write.data),
+                },
+            ),
+            ffi::FilesystemMessage::Seek(seek) => answer(
+                msg.message_id,
+                ffi::SeekResponse {
+                    result: fs.seek(emitter, seek.handle, seek.position),
+                },
+            ),
+            ffi::FilesystemMessage::Close(close) => fs.close(emitter, close.handle),
+            ffi::FilesystemMessage::CreateDir(create) => answer(
+                msg.message_id,
+                ffi::CreateDirResponse {
+                    result: fs.create_dir(&create.path),
+                },
+            ),
+            ffi::FilesystemMessage::ReadDir(read_dir) => answer(
+                msg.message_id,
+                ffi::ReadDirResponse {
+                    result: fs.read_dir(&read_dir.path),
+                },
+            ),
+            ffi::FilesystemMessage::Metadata(request) => answer(
+                msg.message_id,
+                ffi::MetadataResponse {
+                    result: fs.metadata(&request.path),
+                },
+            ),
+            ffi::FilesystemMessage::Mount(_) => answer(
+                msg.message_id,
+                ffi::MountResponse {
+                    result: Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)),
+                },
+            ),
+        }
+    }
+}
+
+/// Sends back `response` if the emitter of the message expects an answer.
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
+
+/// In-memory filesystem.
+struct Ramfs {
+    /// All the files and directories, indexed by their normalized path. The root directory has
+    /// an empty path and is always present.
+    nodes: BTreeMap<String, Node>,
+    /// Files currently open.
+    handles: HashMap<u64, OpenFile>,
+    /// Handle to assign to the next opened file.
+    next_handle: u64,
+}
+
+enum Node {
+    File(Vec<u8>),
+    Directory,
+}
+
+struct OpenFile {
+    /// Process that has opened the file. Other processes can't use the handle.
+    owner: Pid,
+    /// Normalized path of the file.
+    path: String,
+    /// Current position within the file.
+    position: u64,
+}
+
+impl Ramfs {
+    fn new() -> Ramfs {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(String::new(), Node::Directory);
+
+        Ramfs {
+            nodes,
+            handles: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    fn open(&mut self, emitter: Pid, open: ffi::Open) -> Result<u64, ErrorPayload> {
+        let path = normalize(&open.path)?;
+
+        match self.nodes.get_mut(&path) {
+            Some(Node::File(content)) => {
+                if open.truncate {
+                    content.clear();
+                }
+            }
+            Some(Node::Directory) => {
+                return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                    .with_message("path is a directory"))
+            }
+            None if open.create => {
+                self.check_parent_is_dir(&path)?;
+                self.nodes.insert(path.clone(), Node::File(Vec::new()));
+            }
+            None => return Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(
+            handle,
+            OpenFile {
+                owner: emitter,
+                path,
+                position: 0,
+            },
+        );
+        Ok(handle)
+    }
+
+    fn read(&mut self, emitter: Pid, handle: u64, len: u32) -> Result<Vec<u8>, ErrorPayload> {
+        let open_file = handle_mut(&mut self.handles, emitter, handle)?;
+        let content = match self.nodes.get(&open_file.path) {
+            Some(Node::File(content)) => content,
+            _ => unreachable!(),
+        };
+
+        let start = usize::try_from(open_file.position)
+            .unwrap_or(usize::max_value())
+            .min(content.len());
+        let end = start
+            .saturating_add(usize::try_from(len).unwrap())
+            .min(content.len());
+        open_file.position += u64::try_from(end - start).unwrap();
+        Ok(content[start..end].to_vec())
+    }
+
+    fn write(&mut self, emitter: Pid, handle: u64, data: Vec<u8>) -> Result<(), ErrorPayload> {
+        let open_file = handle_mut(&mut self.handles, emitter, handle)?;
+        let content = match self.nodes.get_mut(&open_file.path) {
+            Some(Node::File(content)) => content,
+            _ => unreachable!(),
+        };
+
+        let start = usize::try_from(open_file.position)
+            .map_err(|_| ErrorPayload::new(ErrorClass::INVALID_REQUEST))?;
+        let end = start
+            .checked_add(data.len())
+            .ok_or_else(|| ErrorPayload::new(ErrorClass::INVALID_REQUEST))?;
+        if content.len() < end {
+            content.resize(end, 0);
+        }
+        content[start..end].copy_from_slice(&data);
+        open_file.position = u64::try_from(end).unwrap();
+        Ok(())
+    }
+
+    fn seek(&mut self, emitter: Pid, handle: u64, position: u64) -> Result<(), ErrorPayload> {
+        handle_mut(&mut self.handles, emitter, handle)?.position = position;
+        Ok(())
+    }
+
+    fn close(&mut self, emitter: Pid, handle: u64) {
+        if handle_mut(&mut self.handles, emitter, handle).is_ok() {
+            self.handles.remove(&handle);
+        }
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), ErrorPayload> {
+        let path = normalize(path)?;
+        if self.nodes.contains_key(&path) {
+            return Err(
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("path already exists")
+            );
+        }
+        self.check_parent_is_dir(&path)?;
+        self.nodes.insert(path, Node::Directory);
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<ffi::DirEntry>, ErrorPayload> {
+        let path = normalize(path)?;
+        match self.nodes.get(&path) {
+            Some(Node::Directory) => {}
+            Some(Node::File(_)) => {
+                return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                    .with_message("path is not a directory"))
+            }
+            None => return Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+        }
+
+        let prefix = format!("{}/", path);
+        Ok(self
+            .nodes
+            .range(prefix.clone()..)
+            .take_while(|(p, _)| p.starts_with(&prefix))
+            .filter(|(p, _)| !p[prefix.len()..].contains('/'))
+            .map(|(p, node)| ffi::DirEntry {
+                name: p[prefix.len()..].to_owned(),
+                kind: node.kind(),
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &str) -> Result<ffi::Metadata, ErrorPayload> {
+        let path = normalize(path)?;
+        let node = self
+            .nodes
+            .get(&path)
+            .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))?;
+        Ok(ffi::Metadata {
+            kind: node.kind(),
+            len: match node {
+                Node::File(content) => u64::try_from(content.len()).unwrap(),
+                Node::Directory => 0,
+            },
+        })
+    }
+
+    /// Closes all the files opened by the given process.
+    fn process_destroyed(&mut self, pid: Pid) {
+        self.handles.retain(|_, f| f.owner != pid);
+    }
+
+    fn check_parent_is_dir(&self, path: &str) -> Result<(), ErrorPayload> {
+        let parent = &path[..path.rfind('/').unwrap_or(0)];
+        match self.nodes.get(parent) {
+            Some(Node::Directory) => Ok(()),
+            _ => Err(ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("parent not found")),
+        }
+    }
+}
+
+impl Node {
+    fn kind(&self) -> ffi::EntryKind {
+        match self {
+            Node::File(_) => ffi::EntryKind::File,
+            Node::Directory => ffi::EntryKind::Directory,
+        }
+    }
+}
+
+/// Returns the open file corresponding to `handle`, if it belongs to `emitter`.
+fn handle_mut(
+    handles: &mut HashMap<u64, OpenFile>,
+    emitter: Pid,
+    handle: u64,
+) -> Result<&mut OpenFile, ErrorPayload> {
+    match handles.get_mut(&handle) {
+        Some(f) if f.owner == emitter => Ok(f),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid handle")),
+    }
+}
+
+/// Turns a path into the format used as key in [`Ramfs::nodes`]: a `/` before each component,
+/// and no trailing `/`. Empty components and `.` are removed, and `..` is resolved.
+// TODO: this simple scheme means that a path ending with `/` can designate a file
+fn normalize(path: &str) -> Result<String, ErrorPayload> {
+    if !path.starts_with('/') {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("relative path"));
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            c => components.push(c),
+        }
+    }
+
+    let mut out = String::with_capacity(path.len());
+    for component in components {
+        out.push('/');
+        out.push_str(component);
+    }
+    Ok(out)
+}
@@ -0,0 +1,173 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for the Realtek RTL8139 and RTL8168 network cards.
+//!
+//! Each card is registered through the Ethernet interface, after which the frames received from
+//! the network are forwarded to the handler of that interface and the frames it provides are
+//! sent out.
+//!
+//! This library is used by the `realtek` program, which drives all the cards found on the PCI
+//! bus, and by the programs that start drivers as cards appear.
+//!
+//! Bibliography:
+//!
+//! - https://wiki.osdev.org/RTL8139
+//! - https://wiki.osdev.org/RTL8169
+//!
+
+use futures::{lock::Mutex, prelude::*};
+use redshirt_pci_interface::PciDeviceInfo;
+use std::time::Duration;
+
+mod rtl8139;
+mod rtl8168;
+
+/// Interval at which the devices are checked for incoming frames.
+// TODO: use interrupts instead
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Number of times sending a frame is attempted before it is discarded.
+const SEND_ATTEMPTS: u32 = 16;
+
+/// One of the supported cards.
+pub enum Device {
+    Rtl8139(rtl8139::Device),
+    Rtl8168(rtl8168::Device),
+}
+
+impl Device {
+    pub fn mac_address(&self) -> [u8; 6] {
+        match self {
+            Device::Rtl8139(d) => d.mac_address(),
+            Device::Rtl8168(d) => d.mac_address(),
+        }
+    }
+
+    async unsafe fn read_one_incoming(&mut self) -> Option<Vec<u8>> {
+        match self {
+            Device::Rtl8139(d) => d.read_one_incoming().await,
+            Device::Rtl8168(d) => d.read_one_incoming().await,
+        }
+    }
+
+    async unsafe fn send_packet(&mut self, frame: &[u8]) -> Result<(), ()> {
+        match self {
+            Device::Rtl8139(d) => d.send_packet(frame).await,
+            Device::Rtl8168(d) => d.send_packet(frame).await,
+        }
+    }
+}
+
+/// Returns true if the given PCI device is supported by this driver.
+pub fn is_supported(device: &PciDeviceInfo) -> bool {
+    device.vendor_id == 0x10ec
+        && (device.device_id == 0x8139 || device.device_id == 0x8168 || device.device_id == 0x8169)
+}
+
+/// Resets and initializes the given PCI device. Returns `None` if the device isn't supported or
+/// its registers can't be accessed.
+pub async fn init(device: &PciDeviceInfo) -> Option<Device> {
+    if !is_supported(device) {
+        return None;
+    }
+
+    let port_number =
+        device
+            .base_address_registers
+            .iter()
+            .filter_map(|bar| match bar {
+                Some(redshirt_pci_interface::PciBaseAddressRegister::Io {
+                    base_address, ..
+                }) if *base_address != 0 => Some(*base_address),
+                _ => None,
+            })
+            .next()?;
+
+    // Enables the I/O ports.
+    let bar_index = device
+        .base_address_registers
+        .iter()
+        .position(|bar| match bar {
+            Some(redshirt_pci_interface::PciBaseAddressRegister::Io { base_address, .. }) => {
+                *base_address == port_number
+            }
+            _ => false,
+        })
+        .unwrap();
+    if redshirt_pci_interface::map_bar(device.location, bar_index as u8)
+        .await
+        .is_err()
+    {
+        return None;
+    }
+    redshirt_pci_interface::enable_bus_mastering(device.location);
+
+    let device = unsafe {
+        if device.device_id == 0x8139 {
+            Device::Rtl8139(rtl8139::Device::reset(port_number).await)
+        } else {
+            Device::Rtl8168(rtl8168::Device::reset(port_number).await)
+        }
+    };
+
+    let mac = device.mac_address();
+    redshirt_stdout_interface::stdout(format!(
+        "Initialized Realtek card with MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    ));
+    Some(device)
+}
+
+/// Registers the device through the Ethernet interface and transfers frames between the device
+/// and the handler of that interface.
+///
+/// Never finishes. The interface is unregistered when the future is destroyed.
+pub async fn run_device(device: Device) {
+    let registration = redshirt_ethernet_interface::register_interface(device.mac_address()).await;
+    let device = Mutex::new(device);
+
+    let receive = async {
+        loop {
+            let frame = unsafe { device.lock().await.read_one_incoming().await };
+            match frame {
+                Some(frame) => registration.packet_from_network(frame),
+                None => redshirt_time_interface::monotonic_wait(POLL_INTERVAL).await,
+            }
+        }
+    };
+
+    let send = async {
+        loop {
+            let frame = registration.packet_to_send().await;
+            for _ in 0..SEND_ATTEMPTS {
+                if unsafe { device.lock().await.send_packet(&frame).await }.is_ok() {
+                    break;
+                }
+                redshirt_time_interface::monotonic_wait(POLL_INTERVAL).await;
+            }
+        }
+    };
+
+    future::join(receive, send).await;
+}
+
+async unsafe fn port_read_u32(port: u32) -> u32 {
+    let mut out = 0;
+    let mut ops = redshirt_hardware_interface::HardwareOperationsBuilder::new();
+    ops.port_read_u32(port, &mut out);
+    ops.send().await;
+    out
+}
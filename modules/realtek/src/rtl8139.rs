@@ -0,0 +1,194 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! RTL8139 family of network cards.
+
+use crate::port_read_u32;
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+// Offsets of the registers, relative to the base I/O port.
+const REG_IDR0: u32 = 0x00;
+const REG_TSD0: u32 = 0x10;
+const REG_TSAD0: u32 = 0x20;
+const REG_RBSTART: u32 = 0x30;
+const REG_CR: u32 = 0x37;
+const REG_CAPR: u32 = 0x38;
+const REG_IMR: u32 = 0x3c;
+const REG_TCR: u32 = 0x40;
+const REG_RCR: u32 = 0x44;
+const REG_CONFIG1: u32 = 0x52;
+
+const CR_BUFE: u8 = 1 << 0;
+const CR_TE: u8 = 1 << 2;
+const CR_RE: u8 = 1 << 3;
+const CR_RST: u8 = 1 << 4;
+
+const RCR_APM: u32 = 1 << 1;
+const RCR_AM: u32 = 1 << 2;
+const RCR_AB: u32 = 1 << 3;
+const RCR_WRAP: u32 = 1 << 7;
+
+/// Bit of a transmit status register indicating that the card has finished reading the buffer.
+const TSD_OWN: u32 = 1 << 13;
+
+/// Bit of the header of a received packet indicating that it has been received correctly.
+const RX_STATUS_ROK: u16 = 1 << 0;
+
+/// Size of the receive ring buffer, as configured in `RCR`.
+const RX_RING_LEN: u32 = 8192;
+/// Maximum size of an Ethernet frame, without the CRC.
+const MAX_FRAME_LEN: usize = 1514;
+
+/// State of a device.
+//
+// # Device overview
+//
+// Received packets are written by the card one after the other in a ring buffer in physical
+// memory, each prefixed with a 4 bytes header. Because we set the `WRAP` bit, a packet that
+// overflows the end of the ring is written past the end instead of wrapping around, which is why
+// the buffer is larger than the ring.
+//
+// Packets are transmitted through four fixed descriptors used in a round-robin fashion.
+//
+pub struct Device {
+    /// Base I/O port where to write commands to. All ports are derived from this one.
+    base_port: u32,
+    /// Physical address of the receive buffer.
+    rx_buffer: u64,
+    /// Offset within the receive ring of the next packet to read.
+    rx_offset: u32,
+    /// Physical address of the buffer of each of the four transmit descriptors.
+    tx_buffers: [u64; 4],
+    /// Index of the next transmit descriptor to use.
+    next_tx: u32,
+    /// MAC address of the device.
+    mac_address: [u8; 6],
+}
+
+impl Device {
+    /// Assumes that an RTL8139 device is mapped starting at `base_port` and reinitializes it
+    /// to a starting state.
+    pub async unsafe fn reset(base_port: u32) -> Self {
+        // Power on, then software reset.
+        redshirt_hardware_interface::port_write_u8(base_port + REG_CONFIG1, 0x0);
+        redshirt_hardware_interface::port_write_u8(base_port + REG_CR, CR_RST);
+        while redshirt_hardware_interface::port_read_u8(base_port + REG_CR).await & CR_RST != 0 {}
+
+        let mut mac_address = [0; 6];
+        for (n, byte) in mac_address.iter_mut().enumerate() {
+            *byte =
+                redshirt_hardware_interface::port_read_u8(base_port + REG_IDR0 + n as u32).await;
+        }
+
+        // The card only supports 32 bits addresses.
+        // TODO: the hardware interface doesn't let us ask for memory below 4GiB
+        let rx_buffer =
+            redshirt_hardware_interface::malloc::malloc(u64::from(RX_RING_LEN) + 16 + 1500, 4)
+                .await;
+        let mut tx_buffers = [0; 4];
+        for buffer in tx_buffers.iter_mut() {
+            *buffer = redshirt_hardware_interface::malloc::malloc(MAX_FRAME_LEN as u64, 4).await;
+        }
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.port_write_u32(base_port + REG_RBSTART, rx_buffer as u32);
+        for (n, buffer) in tx_buffers.iter().enumerate() {
+            ops.port_write_u32(base_port + REG_TSAD0 + 4 * n as u32, *buffer as u32);
+        }
+        ops.port_write_u16(base_port + REG_IMR, 0);
+        ops.port_write_u8(base_port + REG_CR, CR_RE | CR_TE);
+        ops.port_write_u32(base_port + REG_RCR, RCR_APM | RCR_AM | RCR_AB | RCR_WRAP);
+        // Maximum DMA burst size.
+        ops.port_write_u32(base_port + REG_TCR, 0x7 << 8);
+        ops.send();
+
+        Device {
+            base_port,
+            rx_buffer,
+            rx_offset: 0,
+            tx_buffers,
+            next_tx: 0,
+            mac_address,
+        }
+    }
+
+    /// Returns the MAC address of the device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// Reads one Ethernet frame received by the device.
+    ///
+    /// Returns `None` if there's no frame available.
+    pub async unsafe fn read_one_incoming(&mut self) -> Option<Vec<u8>> {
+        let cr = redshirt_hardware_interface::port_read_u8(self.base_port + REG_CR).await;
+        if cr & CR_BUFE != 0 {
+            return None;
+        }
+
+        let mut header = [0u8; 4];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.rx_buffer + u64::from(self.rx_offset), &mut header);
+        ops.send().await;
+        let status = u16::from_le_bytes([header[0], header[1]]);
+        // The length includes the CRC.
+        let len = u16::from_le_bytes([header[2], header[3]]);
+
+        let packet = if status & RX_STATUS_ROK != 0 && len >= 4 {
+            let mut data = vec![0; usize::from(len - 4)];
+            let mut ops = HardwareOperationsBuilder::new();
+            ops.read(self.rx_buffer + u64::from(self.rx_offset) + 4, &mut data);
+            ops.send().await;
+            Some(data)
+        } else {
+            None
+        };
+
+        // Entries are aligned on 4 bytes.
+        self.rx_offset = ((self.rx_offset + u32::from(len) + 4 + 3) & !3) % RX_RING_LEN;
+        // For some reason, the value written to `CAPR` must be 16 bytes before the actual offset.
+        let capr = (self.rx_offset as u16).wrapping_sub(16);
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.port_write_u16(self.base_port + REG_CAPR, capr);
+        ops.send();
+
+        packet
+    }
+
+    /// Queues an Ethernet frame, without its CRC, for sending.
+    ///
+    /// Returns an error if the frame is too large or if all the transmit descriptors are busy.
+    pub async unsafe fn send_packet(&mut self, frame: &[u8]) -> Result<(), ()> {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(());
+        }
+
+        let tsd = self.base_port + REG_TSD0 + 4 * self.next_tx;
+        // The `OWN` bit is set by the card when it no longer needs the buffer. It is also set
+        // after a reset.
+        if port_read_u32(tsd).await & TSD_OWN == 0 {
+            return Err(());
+        }
+
+        redshirt_hardware_interface::write(self.tx_buffers[self.next_tx as usize], frame.to_vec());
+        // Writing the size clears the `OWN` bit and starts the transmission.
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.port_write_u32(tsd, frame.len() as u32);
+        ops.send();
+
+        self.next_tx = (self.next_tx + 1) % 4;
+        Ok(())
+    }
+}
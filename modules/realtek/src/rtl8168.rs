@@ -0,0 +1,257 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! RTL8168 and RTL8169 family of network cards.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+// Offsets of the registers, relative to the base I/O port.
+const REG_IDR0: u32 = 0x00;
+const REG_TNPDS: u32 = 0x20;
+const REG_CR: u32 = 0x37;
+const REG_TPPOLL: u32 = 0x38;
+const REG_IMR: u32 = 0x3c;
+const REG_TCR: u32 = 0x40;
+const REG_RCR: u32 = 0x44;
+const REG_9346CR: u32 = 0x50;
+const REG_RMS: u32 = 0xda;
+const REG_RDSAR: u32 = 0xe4;
+const REG_MTPS: u32 = 0xec;
+
+const CR_TE: u8 = 1 << 2;
+const CR_RE: u8 = 1 << 3;
+const CR_RST: u8 = 1 << 4;
+
+const RCR_APM: u32 = 1 << 1;
+const RCR_AM: u32 = 1 << 2;
+const RCR_AB: u32 = 1 << 3;
+
+/// Value of `TPPOLL` that asks the card to look for new normal-priority packets to send.
+const TPPOLL_NPQ: u8 = 1 << 6;
+
+// Bits of the first dword of a descriptor.
+const DESC_OWN: u32 = 1 << 31;
+const DESC_EOR: u32 = 1 << 30;
+const DESC_FS: u32 = 1 << 29;
+const DESC_LS: u32 = 1 << 28;
+/// Bit of a receive descriptor indicating an error.
+const DESC_RX_RES: u32 = 1 << 21;
+
+/// Number of descriptors in each ring.
+const NUM_DESCRIPTORS: u32 = 32;
+/// Size of the buffer of each descriptor.
+const BUFFER_LEN: u32 = 2048;
+/// Maximum size of an Ethernet frame, without the CRC.
+const MAX_FRAME_LEN: usize = 1514;
+
+/// State of a device.
+//
+// # Device overview
+//
+// The card uses a ring of descriptors for receiving and another one for transmitting. The `OWN`
+// bit of a descriptor indicates whether it belongs to the card or to us. Each descriptor has its
+// own buffer, allocated during initialization and never freed.
+//
+pub struct Device {
+    /// Base I/O port where to write commands to. All ports are derived from this one.
+    base_port: u32,
+    /// Physical address of the receive descriptors ring.
+    rx_ring: u64,
+    /// Physical address of the transmit descriptors ring.
+    tx_ring: u64,
+    /// Physical address of the buffer of each receive descriptor.
+    rx_buffers: Vec<u64>,
+    /// Physical address of the buffer of each transmit descriptor.
+    tx_buffers: Vec<u64>,
+    /// Index of the next receive descriptor that the card will fill.
+    next_rx: u32,
+    /// Index of the next transmit descriptor to fill.
+    next_tx: u32,
+    /// MAC address of the device.
+    mac_address: [u8; 6],
+}
+
+impl Device {
+    /// Assumes that an RTL8168 device is mapped starting at `base_port` and reinitializes it
+    /// to a starting state.
+    pub async unsafe fn reset(base_port: u32) -> Self {
+        redshirt_hardware_interface::port_write_u8(base_port + REG_CR, CR_RST);
+        while redshirt_hardware_interface::port_read_u8(base_port + REG_CR).await & CR_RST != 0 {}
+
+        let mut mac_address = [0; 6];
+        for (n, byte) in mac_address.iter_mut().enumerate() {
+            *byte =
+                redshirt_hardware_interface::port_read_u8(base_port + REG_IDR0 + n as u32).await;
+        }
+
+        // The rings must be aligned on 256 bytes.
+        let ring_len = u64::from(NUM_DESCRIPTORS) * 16;
+        let rx_ring = malloc_aligned_256(ring_len).await;
+        let tx_ring = malloc_aligned_256(ring_len).await;
+
+        let mut rx_buffers = Vec::with_capacity(NUM_DESCRIPTORS as usize);
+        let mut tx_buffers = Vec::with_capacity(NUM_DESCRIPTORS as usize);
+        for _ in 0..NUM_DESCRIPTORS {
+            rx_buffers
+                .push(redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 8).await);
+            tx_buffers
+                .push(redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 8).await);
+        }
+
+        let mut rx_descriptors = Vec::with_capacity(ring_len as usize);
+        for (n, buffer) in rx_buffers.iter().enumerate() {
+            let mut flags = DESC_OWN | BUFFER_LEN;
+            if n as u32 == NUM_DESCRIPTORS - 1 {
+                flags |= DESC_EOR;
+            }
+            rx_descriptors.extend_from_slice(&flags.to_le_bytes());
+            rx_descriptors.extend_from_slice(&[0; 4]);
+            rx_descriptors.extend_from_slice(&buffer.to_le_bytes());
+        }
+
+        let mut tx_descriptors = Vec::with_capacity(ring_len as usize);
+        for (n, buffer) in tx_buffers.iter().enumerate() {
+            let flags = if n as u32 == NUM_DESCRIPTORS - 1 {
+                DESC_EOR
+            } else {
+                0
+            };
+            tx_descriptors.extend_from_slice(&flags.to_le_bytes());
+            tx_descriptors.extend_from_slice(&[0; 4]);
+            tx_descriptors.extend_from_slice(&buffer.to_le_bytes());
+        }
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(rx_ring, rx_descriptors);
+        ops.write(tx_ring, tx_descriptors);
+        // Unlock the configuration registers.
+        ops.port_write_u8(base_port + REG_9346CR, 0xc0);
+        ops.port_write_u16(base_port + REG_IMR, 0);
+        // Maximum DMA burst size and no receive threshold.
+        ops.port_write_u32(
+            base_port + REG_RCR,
+            RCR_APM | RCR_AM | RCR_AB | (0x7 << 8) | (0x7 << 13),
+        );
+        // Standard inter-frame gap and maximum DMA burst size.
+        ops.port_write_u32(base_port + REG_TCR, (0x3 << 24) | (0x7 << 8));
+        ops.port_write_u16(base_port + REG_RMS, BUFFER_LEN as u16);
+        // In units of 128 bytes.
+        ops.port_write_u8(base_port + REG_MTPS, (BUFFER_LEN / 128) as u8);
+        ops.port_write_u32(base_port + REG_TNPDS, tx_ring as u32);
+        ops.port_write_u32(base_port + REG_TNPDS + 4, (tx_ring >> 32) as u32);
+        ops.port_write_u32(base_port + REG_RDSAR, rx_ring as u32);
+        ops.port_write_u32(base_port + REG_RDSAR + 4, (rx_ring >> 32) as u32);
+        ops.port_write_u8(base_port + REG_CR, CR_RE | CR_TE);
+        ops.port_write_u8(base_port + REG_9346CR, 0x00);
+        ops.send();
+
+        Device {
+            base_port,
+            rx_ring,
+            tx_ring,
+            rx_buffers,
+            tx_buffers,
+            next_rx: 0,
+            next_tx: 0,
+            mac_address,
+        }
+    }
+
+    /// Returns the MAC address of the device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// Reads one Ethernet frame received by the device.
+    ///
+    /// Returns `None` if there's no frame available.
+    pub async unsafe fn read_one_incoming(&mut self) -> Option<Vec<u8>> {
+        let descriptor = self.rx_ring + u64::from(self.next_rx) * 16;
+        let flags = read_u32(descriptor).await;
+        if flags & DESC_OWN != 0 {
+            return None;
+        }
+
+        // The length includes the CRC. Frames spanning multiple descriptors are discarded, but
+        // can't happen as our buffers are larger than the maximum size of a frame.
+        let len = (flags & 0x3fff) as usize;
+        let complete =
+            flags & DESC_FS != 0 && flags & DESC_LS != 0 && flags & DESC_RX_RES == 0 && len >= 4;
+
+        let packet = if complete {
+            let mut data = vec![0; len - 4];
+            let mut ops = HardwareOperationsBuilder::new();
+            ops.read(self.rx_buffers[self.next_rx as usize], &mut data);
+            ops.send().await;
+            Some(data)
+        } else {
+            None
+        };
+
+        // Give the descriptor back to the card.
+        let mut new_flags = DESC_OWN | BUFFER_LEN;
+        if self.next_rx == NUM_DESCRIPTORS - 1 {
+            new_flags |= DESC_EOR;
+        }
+        redshirt_hardware_interface::write_one_u32(descriptor, new_flags);
+        self.next_rx = (self.next_rx + 1) % NUM_DESCRIPTORS;
+
+        packet
+    }
+
+    /// Queues an Ethernet frame, without its CRC, for sending.
+    ///
+    /// Returns an error if the frame is too large or if the transmit ring is full.
+    pub async unsafe fn send_packet(&mut self, frame: &[u8]) -> Result<(), ()> {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(());
+        }
+
+        let descriptor = self.tx_ring + u64::from(self.next_tx) * 16;
+        if read_u32(descriptor).await & DESC_OWN != 0 {
+            return Err(());
+        }
+
+        let mut flags = DESC_OWN | DESC_FS | DESC_LS | frame.len() as u32;
+        if self.next_tx == NUM_DESCRIPTORS - 1 {
+            flags |= DESC_EOR;
+        }
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(self.tx_buffers[self.next_tx as usize], frame.to_vec());
+        ops.write_one_u32(descriptor, flags);
+        ops.port_write_u8(self.base_port + REG_TPPOLL, TPPOLL_NPQ);
+        ops.send();
+
+        self.next_tx = (self.next_tx + 1) % NUM_DESCRIPTORS;
+        Ok(())
+    }
+}
+
+async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
+
+/// Allocates physical memory aligned on 256 bytes, which is more than the maximum alignment
+/// supported by the hardware interface.
+// TODO: the padding is wasted
+async fn malloc_aligned_256(size: u64) -> u64 {
+    let ptr = redshirt_hardware_interface::malloc::malloc(size + 256, 128).await;
+    (ptr + 255) / 256 * 256
+}
@@ -0,0 +1,300 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Exposes the console interface over TCP, as a lightweight way to interact with a machine
+//! running in an emulator or in a lab.
+//!
+//! Listens on the address passed through the `REMOTE_CONSOLE_ADDRESS` environment variable, or
+//! `0.0.0.0:2323` by default. Only one client can be connected at a time, and other connections
+//! are closed immediately. Text written while no client is connected is discarded.
+//!
+//! By default, clients are expected to speak the telnet protocol, and the characters that they
+//! type are echoed back by this program. If the `REMOTE_CONSOLE_MODE` environment variable is
+//! `raw`, bytes are transmitted as they are, which suits clients such as `netcat` that echo
+//! and buffer lines themselves.
+//!
+//! Only one program can handle the console interface at a time. This program replaces the
+//! console of the machine.
+
+mod telnet;
+
+use futures::{io::WriteHalf, prelude::*};
+use parity_scale_codec::DecodeAll;
+use redshirt_console_interface::ffi;
+use redshirt_syscalls_interface::{InterfaceOrDestroyed, MessageId, Pid};
+use redshirt_tcp_interface::{TcpListener, TcpStream};
+use std::{collections::VecDeque, pin::Pin};
+
+/// Address to listen on if the `REMOTE_CONSOLE_ADDRESS` environment variable isn't set.
+const DEFAULT_ADDRESS: &str = "0.0.0.0:2323";
+/// Size reported if the client doesn't tell us the size of its window.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+/// Maximum length of a line, in bytes. Additional characters are ignored.
+const MAX_LINE_LEN: usize = 4096;
+/// Maximum number of lines typed by the user and waiting to be read. Older lines are
+/// discarded.
+const MAX_QUEUED_LINES: usize = 64;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let address = redshirt_arguments_interface::var("REMOTE_CONSOLE_ADDRESS")
+        .await
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+    let address = match address.parse() {
+        Ok(a) => a,
+        Err(_) => return,
+    };
+    let telnet = redshirt_arguments_interface::var("REMOTE_CONSOLE_MODE")
+        .await
+        .map_or(true, |mode| mode != "raw");
+
+    let listener = match TcpListener::bind(&address).await {
+        Ok(l) => l,
+        Err(()) => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut connections = Box::pin(stream::unfold(listener, |mut l| async move {
+        let (connection, _) = l.accept().await;
+        Some((connection, l))
+    }));
+
+    let mut session: Option<Session> = None;
+    // Lines typed by the user that nobody has asked for yet.
+    let mut lines = VecDeque::<String>::new();
+    // `ReadLine` messages waiting for the user to type a line.
+    let mut pending_reads = VecDeque::<(Pid, MessageId)>::new();
+
+    loop {
+        let event = {
+            let message = redshirt_syscalls_interface::next_interface_message().map(Event::Message);
+            let connection = connections.next().map(|c| Event::Connection(c.unwrap()));
+            let input = async {
+                match &mut session {
+                    Some(s) => Event::Input(s.input.next().await),
+                    None => future::pending().await,
+                }
+            };
+            futures::pin_mut!(input);
+
+            match future::select(future::select(message, connection), input).await {
+                future::Either::Left((future::Either::Left((event, _)), _)) => event,
+                future::Either::Left((future::Either::Right((event, _)), _)) => event,
+                future::Either::Right((event, _)) => event,
+            }
+        };
+
+        let msg = match event {
+            Event::Connection(connection) => {
+                // Dropping the connection closes it.
+                if session.is_none() {
+                    session = Some(Session::new(connection, telnet).await);
+                }
+                continue;
+            }
+            Event::Input(None) => {
+                session = None;
+                continue;
+            }
+            Event::Input(Some(data)) => {
+                let typed = session.as_mut().unwrap().process_input(&data).await;
+                for line in typed {
+                    if let Some((_, message_id)) = pending_reads.pop_front() {
+                        answer_line(message_id, line);
+                    } else {
+                        if lines.len() >= MAX_QUEUED_LINES {
+                            lines.pop_front();
+                        }
+                        lines.push_back(line);
+                    }
+                }
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::Interface(m)) => m,
+            Event::Message(InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                pending_reads.retain(|(pid, _)| *pid != msg.pid);
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                pending_reads.retain(|(_, id)| *id != msg.message_id);
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::Shutdown(_)) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(ffi::ConsoleMessage::Write(text)) => {
+                if let Some(session) = &mut session {
+                    session.write_text(&text).await;
+                }
+            }
+            Ok(ffi::ConsoleMessage::ReadLine) => {
+                if let Some(message_id) = msg.message_id {
+                    if let Some(line) = lines.pop_front() {
+                        answer_line(message_id, line);
+                    } else {
+                        pending_reads.push_back((msg.emitter_pid, message_id));
+                    }
+                }
+            }
+            Ok(ffi::ConsoleMessage::Size) => {
+                if let Some(message_id) = msg.message_id {
+                    let (columns, rows) = session.as_ref().map_or(DEFAULT_SIZE, |s| s.size);
+                    let response = ffi::SizeResponse { columns, rows };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+            }
+        }
+    }
+}
+
+/// Event that the main loop waits for.
+enum Event {
+    Message(InterfaceOrDestroyed),
+    Connection(TcpStream),
+    /// Data sent by the client, or `None` if the connection has been closed.
+    Input(Option<Vec<u8>>),
+}
+
+/// Answers a `ReadLine` message.
+fn answer_line(message_id: MessageId, line: String) {
+    let response = ffi::ReadLineResponse { result: Ok(line) };
+    redshirt_syscalls_interface::emit_answer(message_id, &response);
+}
+
+/// Connection with the client.
+struct Session {
+    /// Data sent by the client.
+    input: Pin<Box<dyn Stream<Item = Vec<u8>>>>,
+    output: WriteHalf<TcpStream>,
+    /// If false, we don't negotiate anything and don't echo the input.
+    telnet: bool,
+    /// Also used in raw mode, in order to normalize the line breaks.
+    decoder: telnet::Decoder,
+    /// Line being typed by the user.
+    line: Vec<u8>,
+    /// Size of the window of the client, as columns and rows.
+    size: (u16, u16),
+}
+
+impl Session {
+    async fn new(connection: TcpStream, telnet: bool) -> Self {
+        let (reader, output) = connection.split();
+        let input = Box::pin(stream::unfold(reader, |mut reader| async move {
+            let mut buffer = [0; 512];
+            match reader.read(&mut buffer).await {
+                Ok(0) | Err(_) => None,
+                Ok(n) => Some((buffer[..n].to_vec(), reader)),
+            }
+        }));
+
+        let mut session = Session {
+            input,
+            output,
+            telnet,
+            decoder: telnet::Decoder::new(),
+            line: Vec::new(),
+            size: DEFAULT_SIZE,
+        };
+
+        if telnet {
+            session.send(telnet::NEGOTIATION).await;
+        }
+
+        session
+    }
+
+    /// Sends text written on the console to the client.
+    async fn write_text(&mut self, text: &str) {
+        if self.telnet {
+            // Telnet requires line breaks to be a carriage return followed with a line feed.
+            let text = text.replace('\n', "\r\n");
+            self.send(text.as_bytes()).await;
+        } else {
+            self.send(text.as_bytes()).await;
+        }
+    }
+
+    /// Processes data sent by the client, and returns the lines that have been completed.
+    async fn process_input(&mut self, data: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut to_send = Vec::new();
+
+        for byte in data {
+            match self.decoder.feed(*byte) {
+                None => {}
+                Some(telnet::Event::Reply(reply)) => to_send.extend_from_slice(&reply),
+                Some(telnet::Event::WindowSize { columns, rows }) => {
+                    if columns != 0 && rows != 0 {
+                        self.size = (columns, rows);
+                    }
+                }
+                Some(telnet::Event::Data(b'\r')) => {
+                    lines.push(String::from_utf8_lossy(&self.line).into_owned());
+                    self.line.clear();
+                    if self.telnet {
+                        to_send.extend_from_slice(b"\r\n");
+                    }
+                }
+                Some(telnet::Event::Data(0x7f)) | Some(telnet::Event::Data(0x08)) => {
+                    // Remove the last character, which can span multiple bytes.
+                    let mut removed = false;
+                    while let Some(byte) = self.line.pop() {
+                        removed = true;
+                        if byte & 0xc0 != 0x80 {
+                            break;
+                        }
+                    }
+                    if removed && self.telnet {
+                        to_send.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                Some(telnet::Event::Data(byte)) => {
+                    // Control characters are ignored.
+                    if (byte >= 0x20 || byte == b'\t') && self.line.len() < MAX_LINE_LEN {
+                        self.line.push(byte);
+                        if self.telnet {
+                            to_send.push(byte);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !to_send.is_empty() {
+            self.send(&to_send).await;
+        }
+
+        lines
+    }
+
+    /// Sends data to the client. Errors are ignored, as a closed connection is detected when
+    /// reading.
+    async fn send(&mut self, data: &[u8]) {
+        let _ = self.output.write_all(data).await;
+    }
+}
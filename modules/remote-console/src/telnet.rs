@@ -0,0 +1,244 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Telnet protocol, as defined in RFC 854.
+//!
+//! We ask the client to let us echo the characters and to send them one at a time as they are
+//! typed (RFC 857 and RFC 858), and to report the size of its window (RFC 1073). All the other
+//! options are refused.
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const OPTION_ECHO: u8 = 1;
+const OPTION_SUPPRESS_GO_AHEAD: u8 = 3;
+const OPTION_NAWS: u8 = 31;
+
+/// Bytes to send to the client at the start of the connection.
+pub const NEGOTIATION: &[u8] = &[
+    IAC,
+    WILL,
+    OPTION_ECHO,
+    IAC,
+    WILL,
+    OPTION_SUPPRESS_GO_AHEAD,
+    IAC,
+    DO,
+    OPTION_NAWS,
+];
+
+/// Event decoded from the bytes sent by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A byte of data. Line breaks are always reported as `\r`.
+    Data(u8),
+    /// The client has reported the size of its window.
+    WindowSize { columns: u16, rows: u16 },
+    /// Bytes that must be sent back to the client.
+    Reply([u8; 3]),
+}
+
+/// Separates the data from the commands in the bytes sent by the client.
+#[derive(Debug)]
+pub struct Decoder {
+    state: State,
+    /// Content of the sub-negotiation being received.
+    subnegotiation: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Data,
+    /// Received a carriage return, which might be followed with a line feed or a NUL byte.
+    CarriageReturn,
+    /// Received `IAC`.
+    Command,
+    /// Received `IAC` followed with `WILL`, `WONT`, `DO` or `DONT`.
+    Negotiation(u8),
+    /// Inside of a sub-negotiation.
+    Subnegotiation,
+    /// Received `IAC` inside of a sub-negotiation.
+    SubnegotiationCommand,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            state: State::Data,
+            subnegotiation: Vec::new(),
+        }
+    }
+
+    /// Processes a byte sent by the client.
+    pub fn feed(&mut self, byte: u8) -> Option<Event> {
+        match (self.state, byte) {
+            (State::CarriageReturn, b'\n') | (State::CarriageReturn, 0) => {
+                self.state = State::Data;
+                None
+            }
+            (State::Data, IAC) | (State::CarriageReturn, IAC) => {
+                self.state = State::Command;
+                None
+            }
+            (State::Data, b'\r') | (State::CarriageReturn, b'\r') | (State::Data, b'\n') => {
+                self.state = State::CarriageReturn;
+                Some(Event::Data(b'\r'))
+            }
+            (State::Data, byte) | (State::CarriageReturn, byte) => {
+                self.state = State::Data;
+                Some(Event::Data(byte))
+            }
+            (State::Command, IAC) => {
+                // Escaped `0xff` byte.
+                self.state = State::Data;
+                Some(Event::Data(IAC))
+            }
+            (State::Command, WILL)
+            | (State::Command, WONT)
+            | (State::Command, DO)
+            | (State::Command, DONT) => {
+                self.state = State::Negotiation(byte);
+                None
+            }
+            (State::Command, SB) => {
+                self.state = State::Subnegotiation;
+                self.subnegotiation.clear();
+                None
+            }
+            (State::Command, _) => {
+                // Other commands, such as `NOP` or `GA`, are ignored.
+                self.state = State::Data;
+                None
+            }
+            (State::Negotiation(command), option) => {
+                self.state = State::Data;
+                match (command, option) {
+                    // Answers to our own requests.
+                    (DO, OPTION_ECHO) | (DO, OPTION_SUPPRESS_GO_AHEAD) | (WILL, OPTION_NAWS) => {
+                        None
+                    }
+                    (DO, option) => Some(Event::Reply([IAC, WONT, option])),
+                    (WILL, option) => Some(Event::Reply([IAC, DONT, option])),
+                    _ => None,
+                }
+            }
+            (State::Subnegotiation, IAC) => {
+                self.state = State::SubnegotiationCommand;
+                None
+            }
+            (State::Subnegotiation, byte) => {
+                // Sub-negotiations that we care about are small.
+                if self.subnegotiation.len() < 64 {
+                    self.subnegotiation.push(byte);
+                }
+                None
+            }
+            (State::SubnegotiationCommand, SE) => {
+                self.state = State::Data;
+                let sub = &self.subnegotiation;
+                if sub.len() == 5 && sub[0] == OPTION_NAWS {
+                    Some(Event::WindowSize {
+                        columns: u16::from_be_bytes([sub[1], sub[2]]),
+                        rows: u16::from_be_bytes([sub[3], sub[4]]),
+                    })
+                } else {
+                    None
+                }
+            }
+            (State::SubnegotiationCommand, byte) => {
+                // `IAC IAC` is an escaped `0xff` byte.
+                self.state = State::Subnegotiation;
+                if self.subnegotiation.len() < 64 {
+                    self.subnegotiation.push(byte);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Event, DO, DONT, IAC, OPTION_ECHO, OPTION_NAWS, SB, SE, WILL, WONT};
+
+    fn decode(bytes: &[u8]) -> Vec<Event> {
+        let mut decoder = Decoder::new();
+        bytes.iter().filter_map(|b| decoder.feed(*b)).collect()
+    }
+
+    #[test]
+    fn plain_data() {
+        assert_eq!(decode(b"ab"), [Event::Data(b'a'), Event::Data(b'b')]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let expected = [Event::Data(b'a'), Event::Data(b'\r'), Event::Data(b'b')];
+        assert_eq!(decode(b"a\r\nb"), expected);
+        assert_eq!(decode(b"a\r\0b"), expected);
+        assert_eq!(decode(b"a\nb"), expected);
+        assert_eq!(decode(b"\r\r"), [Event::Data(b'\r'), Event::Data(b'\r')]);
+    }
+
+    #[test]
+    fn escaped_iac() {
+        assert_eq!(
+            decode(&[IAC, IAC, b'a']),
+            [Event::Data(IAC), Event::Data(b'a')]
+        );
+    }
+
+    #[test]
+    fn negotiation() {
+        // Answers to our own requests are accepted silently.
+        assert!(decode(&[IAC, DO, OPTION_ECHO, IAC, WILL, OPTION_NAWS]).is_empty());
+        // Other options are refused.
+        assert_eq!(decode(&[IAC, DO, 24]), [Event::Reply([IAC, WONT, 24])]);
+        assert_eq!(decode(&[IAC, WILL, 24]), [Event::Reply([IAC, DONT, 24])]);
+        assert_eq!(decode(&[IAC, WONT, OPTION_NAWS, b'a']), [Event::Data(b'a')]);
+    }
+
+    #[test]
+    fn window_size() {
+        assert_eq!(
+            decode(&[IAC, SB, OPTION_NAWS, 0, 80, 0, 24, IAC, SE]),
+            [Event::WindowSize {
+                columns: 80,
+                rows: 24
+            }]
+        );
+        // A size of 255 is escaped.
+        assert_eq!(
+            decode(&[IAC, SB, OPTION_NAWS, 0, IAC, IAC, 0, 24, IAC, SE]),
+            [Event::WindowSize {
+                columns: 255,
+                rows: 24
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_subnegotiation_ignored() {
+        assert_eq!(
+            decode(&[IAC, SB, 24, 1, IAC, SE, b'a']),
+            [Event::Data(b'a')]
+        );
+    }
+}
@@ -0,0 +1,471 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SD Host Controller and SD memory card initialization, as defined in the SD Host Controller
+//! Simplified Specification and the SD Physical Layer Simplified Specification.
+//!
+//! Data is transferred one block at a time through the buffer data port register. DMA isn't
+//! used, as the controllers of some boards implement it incorrectly.
+//!
+//! All registers are accessed 32 bits at a time, as some controllers, such as the one of the
+//! Raspberry Pi, don't support narrower accesses.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+
+/// Size of a block. SD cards are always accessed in units of 512 bytes.
+pub const BLOCK_SIZE: u32 = 512;
+
+// Offsets of the registers.
+const REG_BLOCK_SIZE_COUNT: u64 = 0x04;
+const REG_ARGUMENT: u64 = 0x08;
+const REG_TRANSFER_MODE_COMMAND: u64 = 0x0c;
+const REG_RESPONSE: u64 = 0x10;
+const REG_BUFFER_DATA_PORT: u64 = 0x20;
+const REG_PRESENT_STATE: u64 = 0x24;
+const REG_HOST_CONTROL: u64 = 0x28;
+const REG_CLOCK_CONTROL: u64 = 0x2c;
+const REG_INT_STATUS: u64 = 0x30;
+const REG_INT_STATUS_ENABLE: u64 = 0x34;
+const REG_INT_SIGNAL_ENABLE: u64 = 0x38;
+const REG_CAPABILITIES: u64 = 0x40;
+const REG_HOST_VERSION: u64 = 0xfc;
+
+const PRESENT_STATE_CMD_INHIBIT: u32 = 1 << 0;
+const PRESENT_STATE_DAT_INHIBIT: u32 = 1 << 1;
+
+/// Host control register: 4 bits data transfer width.
+const HOST_CONTROL_4BIT: u32 = 1 << 1;
+/// Power control register, which is the second byte of the host control register: bus power
+/// on, at 3.3V.
+const HOST_CONTROL_POWER_3_3V: u32 = (1 << 8) | (0b111 << 9);
+
+const CLOCK_INTERNAL_ENABLE: u32 = 1 << 0;
+const CLOCK_INTERNAL_STABLE: u32 = 1 << 1;
+const CLOCK_SD_ENABLE: u32 = 1 << 2;
+/// Maximum data timeout, which is the third byte of the clock control register.
+const CLOCK_DATA_TIMEOUT_MAX: u32 = 0xe << 16;
+/// Software reset bits, in the fourth byte of the clock control register.
+const RESET_ALL: u32 = 1 << 24;
+const RESET_CMD: u32 = 1 << 25;
+const RESET_DAT: u32 = 1 << 26;
+
+const INT_COMMAND_COMPLETE: u32 = 1 << 0;
+const INT_TRANSFER_COMPLETE: u32 = 1 << 1;
+const INT_BUFFER_WRITE_READY: u32 = 1 << 4;
+const INT_BUFFER_READ_READY: u32 = 1 << 5;
+const INT_ERROR: u32 = 1 << 15;
+/// Bits of the status enable register corresponding to the statuses above, plus all the
+/// errors.
+const INT_ENABLED: u32 = 0xffff_0000
+    | INT_COMMAND_COMPLETE
+    | INT_TRANSFER_COMPLETE
+    | INT_BUFFER_WRITE_READY
+    | INT_BUFFER_READ_READY;
+
+// Bits of the command register, which is the upper half of `REG_TRANSFER_MODE_COMMAND`.
+const RESPONSE_NONE: u32 = 0b00 << 16;
+const RESPONSE_136: u32 = 0b01 << 16;
+const RESPONSE_48: u32 = 0b10 << 16;
+const RESPONSE_48_BUSY: u32 = 0b11 << 16;
+const RESPONSE_MASK: u32 = 0b11 << 16;
+const CMD_CRC_CHECK: u32 = 1 << 19;
+const CMD_INDEX_CHECK: u32 = 1 << 20;
+const CMD_DATA_PRESENT: u32 = 1 << 21;
+// Bits of the transfer mode register, which is the lower half of `REG_TRANSFER_MODE_COMMAND`.
+const TRANSFER_READ: u32 = 1 << 4;
+
+// Response types, as defined by the SD specifications.
+const R1: u32 = RESPONSE_48 | CMD_CRC_CHECK | CMD_INDEX_CHECK;
+const R1B: u32 = RESPONSE_48_BUSY | CMD_CRC_CHECK | CMD_INDEX_CHECK;
+const R2: u32 = RESPONSE_136 | CMD_CRC_CHECK;
+const R3: u32 = RESPONSE_48;
+const R6: u32 = RESPONSE_48 | CMD_CRC_CHECK | CMD_INDEX_CHECK;
+const R7: u32 = RESPONSE_48 | CMD_CRC_CHECK | CMD_INDEX_CHECK;
+
+// Commands. Those prefixed with `ACMD` must be preceded with `CMD55`.
+const CMD0_GO_IDLE_STATE: u8 = 0;
+const CMD2_ALL_SEND_CID: u8 = 2;
+const CMD3_SEND_RELATIVE_ADDR: u8 = 3;
+const CMD7_SELECT_CARD: u8 = 7;
+const CMD8_SEND_IF_COND: u8 = 8;
+const CMD9_SEND_CSD: u8 = 9;
+const CMD16_SET_BLOCKLEN: u8 = 16;
+const CMD17_READ_SINGLE_BLOCK: u8 = 17;
+const CMD24_WRITE_BLOCK: u8 = 24;
+const CMD55_APP_CMD: u8 = 55;
+const ACMD6_SET_BUS_WIDTH: u8 = 6;
+const ACMD41_SD_SEND_OP_COND: u8 = 41;
+
+/// Argument of `CMD8`: 2.7-3.6V, and a check pattern that the card echoes back.
+const IF_COND_ARGUMENT: u32 = 0x1aa;
+/// Bit of the OCR indicating that the card has finished powering up.
+const OCR_READY: u32 = 1 << 31;
+/// Bit of the OCR indicating a high capacity card, which is addressed in blocks rather than
+/// bytes.
+const OCR_HIGH_CAPACITY: u32 = 1 << 30;
+/// Voltage window of the OCR: 2.7-3.6V.
+const OCR_VOLTAGE_WINDOW: u32 = 0x00ff_8000;
+
+/// Frequency of the clock during the card identification.
+const IDENTIFICATION_CLOCK_HZ: u32 = 400_000;
+/// Frequency of the clock during data transfers, in default speed mode.
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+
+/// Frequency of the base clock if the controller doesn't report it. Overestimating it is safe,
+/// as it only makes the bus slower than intended.
+// TODO: the Raspberry Pi reports 0; ask the firmware through the mailbox instead
+const FALLBACK_BASE_CLOCK_HZ: u32 = 250_000_000;
+
+/// Timeout for commands and for each block of data, in nanoseconds.
+const TIMEOUT_NS: u128 = 500_000_000;
+/// Timeout for the card to finish powering up, in nanoseconds.
+const POWER_UP_TIMEOUT_NS: u128 = 1_000_000_000;
+
+/// Initialized SD card behind a host controller.
+pub struct Card {
+    /// Physical address of the registers of the controller.
+    base: u64,
+    /// Relative card address, assigned by the card during the identification.
+    rca: u32,
+    /// If true, the card is addressed in blocks. Otherwise, in bytes.
+    high_capacity: bool,
+    /// Total number of blocks of the card.
+    num_blocks: u64,
+}
+
+impl Card {
+    /// Initializes the controller whose registers are at the given physical address, then
+    /// identifies the card plugged into it and selects it.
+    pub async unsafe fn init(base: u64) -> Result<Card, ()> {
+        let mut card = Card {
+            base,
+            rca: 0,
+            high_capacity: false,
+            num_blocks: 0,
+        };
+
+        // Reset the controller.
+        write_u32(base + REG_CLOCK_CONTROL, RESET_ALL);
+        card.wait_clear(REG_CLOCK_CONTROL, RESET_ALL, TIMEOUT_NS)
+            .await?;
+
+        write_u32(base + REG_HOST_CONTROL, HOST_CONTROL_POWER_3_3V);
+        card.set_clock(IDENTIFICATION_CLOCK_HZ).await?;
+
+        // We poll the status register instead of relying on interrupts.
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u32(base + REG_INT_STATUS_ENABLE, INT_ENABLED);
+        ops.write_one_u32(base + REG_INT_SIGNAL_ENABLE, 0);
+        ops.write_one_u32(base + REG_INT_STATUS, 0xffff_ffff);
+        ops.send();
+
+        card.command(CMD0_GO_IDLE_STATE, 0, RESPONSE_NONE).await?;
+
+        // Cards that follow version 2.0 or later of the specifications echo the argument of
+        // `CMD8`. Older cards don't answer.
+        let v2 = match card.command(CMD8_SEND_IF_COND, IF_COND_ARGUMENT, R7).await {
+            Ok(response) if response[0] & 0xfff == IF_COND_ARGUMENT => true,
+            Ok(_) => return Err(()),
+            Err(()) => false,
+        };
+
+        // Wait for the card to be powered up.
+        let ocr_argument = if v2 {
+            OCR_VOLTAGE_WINDOW | OCR_HIGH_CAPACITY
+        } else {
+            OCR_VOLTAGE_WINDOW
+        };
+        let start = redshirt_time_interface::monotonic_clock().await;
+        let ocr = loop {
+            card.command(CMD55_APP_CMD, 0, R1).await?;
+            let ocr = card
+                .command(ACMD41_SD_SEND_OP_COND, ocr_argument, R3)
+                .await?[0];
+            if ocr & OCR_READY != 0 {
+                break ocr;
+            }
+            if redshirt_time_interface::monotonic_clock().await - start > POWER_UP_TIMEOUT_NS {
+                return Err(());
+            }
+        };
+        card.high_capacity = ocr & OCR_HIGH_CAPACITY != 0;
+
+        card.command(CMD2_ALL_SEND_CID, 0, R2).await?;
+        card.rca = card.command(CMD3_SEND_RELATIVE_ADDR, 0, R6).await?[0] >> 16;
+        let csd = card.command(CMD9_SEND_CSD, card.rca << 16, R2).await?;
+        card.num_blocks = num_blocks_from_csd(&csd).ok_or(())?;
+        card.command(CMD7_SELECT_CARD, card.rca << 16, R1B).await?;
+
+        if !card.high_capacity {
+            card.command(CMD16_SET_BLOCKLEN, BLOCK_SIZE, R1).await?;
+        }
+
+        // Switch to a 4 bits bus. All SD memory cards support it.
+        card.command(CMD55_APP_CMD, card.rca << 16, R1).await?;
+        card.command(ACMD6_SET_BUS_WIDTH, 0b10, R1).await?;
+        write_u32(
+            base + REG_HOST_CONTROL,
+            HOST_CONTROL_POWER_3_3V | HOST_CONTROL_4BIT,
+        );
+
+        card.set_clock(TRANSFER_CLOCK_HZ).await?;
+        Ok(card)
+    }
+
+    /// Returns the total number of blocks of the card.
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Reads the block with the given index.
+    pub async unsafe fn read_block(&mut self, block: u64) -> Result<Vec<u8>, ()> {
+        write_u32(self.base + REG_BLOCK_SIZE_COUNT, (1 << 16) | BLOCK_SIZE);
+        self.command(
+            CMD17_READ_SINGLE_BLOCK,
+            self.address_argument(block)?,
+            R1 | CMD_DATA_PRESENT | TRANSFER_READ,
+        )
+        .await?;
+
+        self.wait_interrupt(INT_BUFFER_READ_READY).await?;
+
+        // The buffer data port is read repeatedly. Each read pops 4 bytes.
+        let mut words = vec![[0u32]; (BLOCK_SIZE / 4) as usize];
+        let mut ops = HardwareOperationsBuilder::with_capacity(words.len());
+        for word in words.iter_mut() {
+            ops.read_u32(self.base + REG_BUFFER_DATA_PORT, word);
+        }
+        ops.send().await;
+
+        self.wait_interrupt(INT_TRANSFER_COMPLETE).await?;
+
+        let mut out = Vec::with_capacity(BLOCK_SIZE as usize);
+        for [word] in words {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    /// Writes the block with the given index. `data` must be [`BLOCK_SIZE`] bytes long.
+    pub async unsafe fn write_block(&mut self, block: u64, data: &[u8]) -> Result<(), ()> {
+        debug_assert_eq!(data.len(), BLOCK_SIZE as usize);
+
+        write_u32(self.base + REG_BLOCK_SIZE_COUNT, (1 << 16) | BLOCK_SIZE);
+        self.command(
+            CMD24_WRITE_BLOCK,
+            self.address_argument(block)?,
+            R1 | CMD_DATA_PRESENT,
+        )
+        .await?;
+
+        self.wait_interrupt(INT_BUFFER_WRITE_READY).await?;
+
+        let mut ops = HardwareWriteOperationsBuilder::with_capacity(data.len() / 4);
+        for word in data.chunks(4) {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            ops.write_one_u32(self.base + REG_BUFFER_DATA_PORT, word);
+        }
+        ops.send();
+
+        // The transfer is complete once the card has finished programming the block.
+        self.wait_interrupt(INT_TRANSFER_COMPLETE).await
+    }
+
+    /// Returns the argument to pass to the read and write commands for the given block.
+    fn address_argument(&self, block: u64) -> Result<u32, ()> {
+        let address = if self.high_capacity {
+            block
+        } else {
+            block * u64::from(BLOCK_SIZE)
+        };
+
+        if address > u64::from(u32::max_value()) {
+            return Err(());
+        }
+        Ok(address as u32)
+    }
+
+    /// Sends a command to the card and waits for its response. `flags` contains the upper half
+    /// of the command register (response type and checks) and the transfer mode.
+    async unsafe fn command(
+        &mut self,
+        index: u8,
+        argument: u32,
+        flags: u32,
+    ) -> Result<[u32; 4], ()> {
+        let busy = flags & RESPONSE_MASK == RESPONSE_48_BUSY;
+        let mut inhibit = PRESENT_STATE_CMD_INHIBIT;
+        if busy || flags & CMD_DATA_PRESENT != 0 {
+            inhibit |= PRESENT_STATE_DAT_INHIBIT;
+        }
+        self.wait_clear(REG_PRESENT_STATE, inhibit, TIMEOUT_NS)
+            .await?;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u32(self.base + REG_INT_STATUS, 0xffff_ffff);
+        ops.write_one_u32(self.base + REG_ARGUMENT, argument);
+        ops.write_one_u32(
+            self.base + REG_TRANSFER_MODE_COMMAND,
+            flags | (u32::from(index) << 24),
+        );
+        ops.send();
+
+        self.wait_interrupt(INT_COMMAND_COMPLETE).await?;
+
+        let mut response = [0u32; 4];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read_u32(self.base + REG_RESPONSE, &mut response);
+        ops.send().await;
+
+        // Commands with a busy signal complete once the card releases the data line.
+        if busy && flags & CMD_DATA_PRESENT == 0 {
+            self.wait_interrupt(INT_TRANSFER_COMPLETE).await?;
+        }
+
+        Ok(response)
+    }
+
+    /// Waits for the given bit of the interrupt status register to be set, then clears it.
+    ///
+    /// On error or timeout, resets the command and data lines of the controller and returns an
+    /// error.
+    async unsafe fn wait_interrupt(&mut self, bit: u32) -> Result<(), ()> {
+        let start = redshirt_time_interface::monotonic_clock().await;
+        loop {
+            let status = read_u32(self.base + REG_INT_STATUS).await;
+            if status & INT_ERROR != 0 {
+                break;
+            }
+            if status & bit != 0 {
+                write_u32(self.base + REG_INT_STATUS, bit);
+                return Ok(());
+            }
+            if redshirt_time_interface::monotonic_clock().await - start > TIMEOUT_NS {
+                break;
+            }
+        }
+
+        let clock = read_u32(self.base + REG_CLOCK_CONTROL).await;
+        write_u32(self.base + REG_CLOCK_CONTROL, clock | RESET_CMD | RESET_DAT);
+        self.wait_clear(REG_CLOCK_CONTROL, RESET_CMD | RESET_DAT, TIMEOUT_NS)
+            .await?;
+        write_u32(self.base + REG_INT_STATUS, 0xffff_ffff);
+        Err(())
+    }
+
+    /// Waits for the given bits of a register to be all cleared.
+    async unsafe fn wait_clear(&self, register: u64, bits: u32, timeout: u128) -> Result<(), ()> {
+        let start = redshirt_time_interface::monotonic_clock().await;
+        while read_u32(self.base + register).await & bits != 0 {
+            if redshirt_time_interface::monotonic_clock().await - start > timeout {
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the frequency of the SD clock to at most `target_hz`.
+    async unsafe fn set_clock(&mut self, target_hz: u32) -> Result<(), ()> {
+        let capabilities = read_u32(self.base + REG_CAPABILITIES).await;
+        let spec_version = (read_u32(self.base + REG_HOST_VERSION).await >> 16) & 0xff;
+
+        // Starting from version 3.0 of the specifications, the base clock field is 8 bits and
+        // the divider is 10 bits. Before, they are respectively 6 and 8 bits, and the divider
+        // must be a power of two.
+        let v3 = spec_version >= 2;
+        let base_clock_mhz = if v3 {
+            (capabilities >> 8) & 0xff
+        } else {
+            (capabilities >> 8) & 0x3f
+        };
+        let base_clock_hz = if base_clock_mhz != 0 {
+            base_clock_mhz * 1_000_000
+        } else {
+            FALLBACK_BASE_CLOCK_HZ
+        };
+
+        // The SD clock is the base clock divided by `2 * divider`, or the base clock itself if
+        // the divider is 0.
+        let mut divider = if base_clock_hz <= target_hz {
+            0
+        } else {
+            (base_clock_hz + 2 * target_hz - 1) / (2 * target_hz)
+        };
+        let divider_bits = if v3 {
+            divider = divider.min(0x3ff);
+            ((divider & 0xff) << 8) | ((divider >> 8) << 6)
+        } else {
+            if divider != 0 {
+                divider = divider.next_power_of_two().min(0x80);
+            }
+            divider << 8
+        };
+
+        // The SD clock must be disabled while its frequency is changed.
+        let clock = CLOCK_DATA_TIMEOUT_MAX | divider_bits | CLOCK_INTERNAL_ENABLE;
+        write_u32(self.base + REG_CLOCK_CONTROL, 0);
+        write_u32(self.base + REG_CLOCK_CONTROL, clock);
+
+        let start = redshirt_time_interface::monotonic_clock().await;
+        while read_u32(self.base + REG_CLOCK_CONTROL).await & CLOCK_INTERNAL_STABLE == 0 {
+            if redshirt_time_interface::monotonic_clock().await - start > TIMEOUT_NS {
+                return Err(());
+            }
+        }
+
+        write_u32(self.base + REG_CLOCK_CONTROL, clock | CLOCK_SD_ENABLE);
+        Ok(())
+    }
+}
+
+/// Extracts the capacity of the card, in blocks, from the response to `CMD9`.
+fn num_blocks_from_csd(csd: &[u32; 4]) -> Option<u64> {
+    // The controller strips the CRC of the response, meaning that bit `n` of the CSD is bit
+    // `n - 8` of the response.
+    let bits = |high: usize, low: usize| -> u64 {
+        let mut value = 0;
+        for bit in (low..=high).rev() {
+            let bit = bit - 8;
+            value = (value << 1) | u64::from((csd[bit / 32] >> (bit % 32)) & 1);
+        }
+        value
+    };
+
+    match bits(127, 126) {
+        // Standard capacity cards.
+        0 => {
+            let c_size = bits(73, 62);
+            let c_size_mult = bits(49, 47);
+            let read_bl_len = bits(83, 80);
+            let bytes = (c_size + 1) << (c_size_mult + 2 + read_bl_len);
+            Some(bytes / u64::from(BLOCK_SIZE))
+        }
+        // High and extended capacity cards, whose capacity is in units of 512kiB.
+        1 => Some((bits(69, 48) + 1) * 1024),
+        _ => None,
+    }
+}
+
+async unsafe fn read_u32(address: u64) -> u32 {
+    let mut out = [0u32];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u32(address, &mut out);
+    ops.send().await;
+    out[0]
+}
+
+unsafe fn write_u32(address: u64, value: u32) {
+    redshirt_hardware_interface::write_one_u32(address, value);
+}
@@ -0,0 +1,172 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for SD Host Controllers.
+//!
+//! This program initializes the SD card plugged into the controller of the board, and exposes
+//! it through the block device interface.
+//!
+//! Bibliography:
+//!
+//! - https://www.sdcard.org/downloads/pls/ (SD Host Controller Simplified Specification and
+//!   Physical Layer Simplified Specification)
+//!
+
+mod card;
+
+use parity_scale_codec::DecodeAll;
+use redshirt_block_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId};
+use std::convert::TryFrom as _;
+
+/// Physical address of the registers of the controller of the Raspberry Pi 2 and 3.
+// TODO: we assume that we're on a Raspberry Pi; also look for controllers on the PCI bus
+const CONTROLLER_BASE: u64 = 0x3f30_0000;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut card = match unsafe { card::Card::init(CONTROLLER_BASE).await } {
+        Ok(c) => c,
+        Err(()) => return,
+    };
+
+    // TODO: only one program can handle the block device interface at a time
+    if redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::BlockMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match message {
+            ffi::BlockMessage::ListDevices => {
+                answer(
+                    msg.message_id,
+                    ffi::ListDevicesResponse { devices: vec![0] },
+                );
+            }
+            ffi::BlockMessage::Geometry(geometry) => {
+                let result = check_device(geometry.device).map(|()| ffi::DeviceGeometry {
+                    sector_size: card::BLOCK_SIZE,
+                    num_sectors: card.num_blocks(),
+                    read_only: false,
+                });
+                answer(msg.message_id, ffi::GeometryResponse { result });
+            }
+            ffi::BlockMessage::Read(read) => {
+                let result = match check_device(read.device)
+                    .and_then(|()| check_range(&card, read.first_sector, read.num_sectors.into()))
+                {
+                    Ok(()) => read_blocks(&mut card, read.first_sector, read.num_sectors).await,
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadResponse { result });
+            }
+            ffi::BlockMessage::Write(write) => {
+                let result = if write.data.len() % card::BLOCK_SIZE as usize != 0 {
+                    Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("length isn't a multiple of the sector size"))
+                } else {
+                    let num_sectors =
+                        u64::try_from(write.data.len() / card::BLOCK_SIZE as usize).unwrap();
+                    match check_device(write.device)
+                        .and_then(|()| check_range(&card, write.first_sector, num_sectors))
+                    {
+                        Ok(()) => write_blocks(&mut card, write.first_sector, &write.data).await,
+                        Err(err) => Err(err),
+                    }
+                };
+                answer(msg.message_id, ffi::WriteResponse { result });
+            }
+            ffi::BlockMessage::Flush(flush) => {
+                // Writes only complete once the card has programmed the data, so there is
+                // nothing to flush.
+                let result = check_device(flush.device);
+                answer(msg.message_id, ffi::FlushResponse { result });
+            }
+        }
+    }
+}
+
+/// Reads a range of blocks, one at a time.
+async fn read_blocks(
+    card: &mut card::Card,
+    first_block: u64,
+    num_blocks: u32,
+) -> Result<Vec<u8>, ErrorPayload> {
+    let mut out = Vec::with_capacity(num_blocks as usize * card::BLOCK_SIZE as usize);
+    for block in first_block..first_block + u64::from(num_blocks) {
+        let data = unsafe { card.read_block(block).await }.map_err(|()| io_error())?;
+        out.extend_from_slice(&data);
+    }
+    Ok(out)
+}
+
+/// Writes a range of blocks, one at a time.
+async fn write_blocks(
+    card: &mut card::Card,
+    first_block: u64,
+    data: &[u8],
+) -> Result<(), ErrorPayload> {
+    for (block, chunk) in (first_block..).zip(data.chunks(card::BLOCK_SIZE as usize)) {
+        unsafe { card.write_block(block, chunk).await }.map_err(|()| io_error())?;
+    }
+    Ok(())
+}
+
+/// We only expose one device, whose identifier is 0.
+fn check_device(device: u32) -> Result<(), ErrorPayload> {
+    if device == 0 {
+        Ok(())
+    } else {
+        Err(ErrorPayload::new(ErrorClass::NOT_FOUND))
+    }
+}
+
+/// Checks that the given range of sectors is within the card.
+fn check_range(card: &card::Card, first_sector: u64, num_sectors: u64) -> Result<(), ErrorPayload> {
+    match first_sector.checked_add(num_sectors) {
+        Some(end) if end <= card.num_blocks() => Ok(()),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("range of sectors out of the device")),
+    }
+}
+
+fn io_error() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO)
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
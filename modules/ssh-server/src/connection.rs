@@ -0,0 +1,1074 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State machine of a connection with a client.
+//!
+//! Implements the transport layer (RFC 4253), the public key authentication (RFC 4252) and
+//! a single session channel (RFC 4254). The [`Connection`] doesn't perform any I/O by itself:
+//! the data received from the client is passed to [`Connection::feed`], and the data to send
+//! back is retrieved with [`Connection::take_output`].
+
+use crate::{
+    kex,
+    transport::{PacketCodec, PacketError},
+    wire::{Reader, Writer},
+};
+use rand_chacha::ChaCha20Rng;
+use rand_core::{RngCore as _, SeedableRng as _};
+use ring::signature::{self, Ed25519KeyPair, KeyPair as _};
+use std::{cmp, convert::TryFrom, mem, rc::Rc};
+
+const MSG_DISCONNECT: u8 = 1;
+const MSG_IGNORE: u8 = 2;
+const MSG_UNIMPLEMENTED: u8 = 3;
+const MSG_DEBUG: u8 = 4;
+const MSG_SERVICE_REQUEST: u8 = 5;
+const MSG_SERVICE_ACCEPT: u8 = 6;
+const MSG_USERAUTH_REQUEST: u8 = 50;
+const MSG_USERAUTH_FAILURE: u8 = 51;
+const MSG_USERAUTH_SUCCESS: u8 = 52;
+const MSG_USERAUTH_PK_OK: u8 = 60;
+const MSG_GLOBAL_REQUEST: u8 = 80;
+const MSG_REQUEST_FAILURE: u8 = 82;
+const MSG_CHANNEL_OPEN: u8 = 90;
+const MSG_CHANNEL_OPEN_CONFIRMATION: u8 = 91;
+const MSG_CHANNEL_OPEN_FAILURE: u8 = 92;
+const MSG_CHANNEL_WINDOW_ADJUST: u8 = 93;
+const MSG_CHANNEL_DATA: u8 = 94;
+const MSG_CHANNEL_EXTENDED_DATA: u8 = 95;
+const MSG_CHANNEL_EOF: u8 = 96;
+const MSG_CHANNEL_CLOSE: u8 = 97;
+const MSG_CHANNEL_REQUEST: u8 = 98;
+const MSG_CHANNEL_SUCCESS: u8 = 99;
+const MSG_CHANNEL_FAILURE: u8 = 100;
+
+const DISCONNECT_PROTOCOL_ERROR: u32 = 2;
+const DISCONNECT_KEY_EXCHANGE_FAILED: u32 = 3;
+const DISCONNECT_MAC_ERROR: u32 = 5;
+const DISCONNECT_SERVICE_NOT_AVAILABLE: u32 = 7;
+const DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE: u32 = 14;
+
+const OPEN_UNKNOWN_CHANNEL_TYPE: u32 = 3;
+const OPEN_RESOURCE_SHORTAGE: u32 = 4;
+
+/// Identification string that we send to the client.
+pub const SERVER_VERSION: &[u8] = b"SSH-2.0-redshirt";
+/// Maximum length of the identification string of the client, including the line break.
+const MAX_VERSION_LEN: usize = 255;
+/// Number of failed authentication attempts after which we disconnect the client.
+const MAX_AUTH_ATTEMPTS: u32 = 10;
+/// Number of bytes that the client can send on the channel before we acknowledge them.
+const LOCAL_WINDOW: u32 = 256 * 1024;
+/// Maximum size of the data of a `SSH_MSG_CHANNEL_DATA` message, in both directions.
+const MAX_CHANNEL_PACKET: u32 = 32 * 1024;
+/// Maximum number of bytes waiting for the client to enlarge its window. Additional data is
+/// discarded.
+const MAX_PENDING_OUTPUT: usize = 256 * 1024;
+/// Identifier that we give to the channel. We only support one at a time.
+const CHANNEL_ID: u32 = 0;
+
+const MALFORMED: Error = Error::Protocol("malformed message");
+
+/// Configuration shared by all the connections.
+pub struct Config {
+    /// Key that identifies the server.
+    pub host_key: Ed25519KeyPair,
+    /// Ed25519 public keys of the clients that are allowed to log in.
+    pub authorized_keys: Vec<[u8; 32]>,
+}
+
+/// Event that happened on the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The client has requested a shell. From now on, the data passed to
+    /// [`Connection::write`] is sent to it. If `pty` is true, the client has also requested a
+    /// terminal, and expects the characters that it types to be echoed back.
+    ShellStarted { pty: bool },
+    /// Data typed by the user.
+    Data(Vec<u8>),
+    /// The client has reported the size of its window.
+    WindowSize { columns: u16, rows: u16 },
+    /// The client has closed the session.
+    Closed,
+}
+
+/// Error that terminates the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The client hasn't sent a valid SSH 2.0 identification string.
+    BadVersion,
+    /// Error in the binary packet protocol.
+    Packet(PacketError),
+    /// No algorithm of this category is supported by both sides.
+    NoCommonAlgorithm(&'static str),
+    /// The client has requested a service that we don't provide.
+    ServiceNotAvailable,
+    /// The client has failed to authenticate too many times.
+    TooManyAuthFailures,
+    /// The client has sent an unexpected or malformed message.
+    Protocol(&'static str),
+    /// The client has closed the connection.
+    Disconnected,
+}
+
+impl Error {
+    /// Returns the reason code and the description of the `SSH_MSG_DISCONNECT` message to send
+    /// to the client, if any.
+    fn disconnect_message(&self) -> Option<(u32, &'static str)> {
+        match self {
+            Error::BadVersion | Error::Disconnected => None,
+            Error::Packet(PacketError::BadTag) => Some((DISCONNECT_MAC_ERROR, "corrupted packet")),
+            Error::Packet(_) => Some((DISCONNECT_PROTOCOL_ERROR, "invalid packet")),
+            Error::NoCommonAlgorithm(_) => {
+                Some((DISCONNECT_KEY_EXCHANGE_FAILED, "no common algorithm"))
+            }
+            Error::ServiceNotAvailable => {
+                Some((DISCONNECT_SERVICE_NOT_AVAILABLE, "service not available"))
+            }
+            Error::TooManyAuthFailures => Some((
+                DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE,
+                "too many authentication failures",
+            )),
+            Error::Protocol(description) => Some((DISCONNECT_PROTOCOL_ERROR, description)),
+        }
+    }
+}
+
+/// Connection with a client.
+pub struct Connection {
+    config: Rc<Config>,
+    /// Generates the ephemeral keys of the key exchanges.
+    rng: ChaCha20Rng,
+    /// Identification string of the client, without the line break, once received.
+    client_version: Option<Vec<u8>>,
+    /// Data received before the identification string of the client is complete.
+    version_buffer: Vec<u8>,
+    codec: PacketCodec,
+    /// Key exchange in progress, if any.
+    kex: Option<Kex>,
+    /// If true, we have sent `SSH_MSG_KEXINIT` but not `SSH_MSG_NEWKEYS` yet, and only the
+    /// messages of the key exchange can be sent.
+    outbound_paused: bool,
+    /// Messages to send once `outbound_paused` becomes false.
+    queued: Vec<Vec<u8>>,
+    /// Exchange hash of the first key exchange, or `None` if it isn't finished.
+    session_id: Option<[u8; 32]>,
+    /// True if both sides support the strict key exchange.
+    strict_kex: bool,
+    /// True if the client has requested the `ssh-userauth` service.
+    userauth_started: bool,
+    authenticated: bool,
+    auth_failures: u32,
+    channel: Option<Channel>,
+    /// Data to send to the client.
+    output: Vec<u8>,
+}
+
+struct Kex {
+    /// Our `SSH_MSG_KEXINIT` message.
+    server_kexinit: Vec<u8>,
+    step: KexStep,
+}
+
+enum KexStep {
+    /// Waiting for the `SSH_MSG_KEXINIT` of the client.
+    KexInit,
+    /// Waiting for `SSH_MSG_KEX_ECDH_INIT`.
+    EcdhInit {
+        client_kexinit: Vec<u8>,
+        /// If true, the next packet must be ignored.
+        ignore_next_packet: bool,
+    },
+    /// Waiting for `SSH_MSG_NEWKEYS`, after which the packets of the client are decrypted with
+    /// this key.
+    NewKeys { inbound_key: [u8; 64] },
+}
+
+struct Channel {
+    /// Identifier that the client has given to the channel.
+    remote_id: u32,
+    /// Number of bytes that the client is still willing to accept.
+    remote_window: u32,
+    /// Maximum size of the data of a message sent to the client.
+    remote_max_packet: u32,
+    /// Number of bytes that we are still willing to accept.
+    local_window: u32,
+    /// True if the client has requested a terminal.
+    pty: bool,
+    /// True if the client has requested a shell.
+    shell: bool,
+    /// Data waiting for the client to enlarge its window.
+    pending: Vec<u8>,
+}
+
+impl Connection {
+    /// Starts a connection. The `seed` is used to generate the ephemeral keys, and must be
+    /// random.
+    pub fn new(config: Rc<Config>, seed: [u8; 32]) -> Self {
+        let mut connection = Connection {
+            config,
+            rng: ChaCha20Rng::from_seed(seed),
+            client_version: None,
+            version_buffer: Vec::new(),
+            codec: PacketCodec::new(),
+            kex: None,
+            outbound_paused: false,
+            queued: Vec::new(),
+            session_id: None,
+            strict_kex: false,
+            userauth_started: false,
+            authenticated: false,
+            auth_failures: 0,
+            channel: None,
+            output: Vec::new(),
+        };
+
+        connection.output.extend_from_slice(SERVER_VERSION);
+        connection.output.extend_from_slice(b"\r\n");
+        connection.start_kex();
+        connection
+    }
+
+    /// Processes data sent by the client.
+    ///
+    /// On error, the connection must be closed after sending the data returned by
+    /// [`take_output`](Connection::take_output), which might contain a message explaining the
+    /// reason to the client.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Event>, Error> {
+        let mut events = Vec::new();
+        match self.feed_inner(data, &mut events) {
+            Ok(()) => Ok(events),
+            Err(err) => {
+                if let Some((reason, description)) = err.disconnect_message() {
+                    let message = Writer::message(MSG_DISCONNECT)
+                        .uint32(reason)
+                        .string(description.as_bytes())
+                        .string(b"")
+                        .into_bytes();
+                    self.send(message);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Sends data to the client through the shell session. Does nothing if the client hasn't
+    /// requested a shell.
+    pub fn write(&mut self, data: &[u8]) {
+        let channel = match &mut self.channel {
+            Some(channel) if channel.shell => channel,
+            _ => return,
+        };
+
+        let room = MAX_PENDING_OUTPUT.saturating_sub(channel.pending.len());
+        channel
+            .pending
+            .extend_from_slice(&data[..cmp::min(room, data.len())]);
+        self.flush_channel();
+    }
+
+    /// Returns the data to send to the client.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.output, Vec::new())
+    }
+
+    fn feed_inner(&mut self, data: &[u8], events: &mut Vec<Event>) -> Result<(), Error> {
+        if self.client_version.is_some() {
+            self.codec.push(data);
+        } else {
+            self.version_buffer.extend_from_slice(data);
+            let line_end = match self.version_buffer.iter().position(|b| *b == b'\n') {
+                Some(pos) if pos < MAX_VERSION_LEN => pos,
+                Some(_) => return Err(Error::BadVersion),
+                None if self.version_buffer.len() >= MAX_VERSION_LEN => {
+                    return Err(Error::BadVersion)
+                }
+                None => return Ok(()),
+            };
+
+            let rest = self.version_buffer.split_off(line_end + 1);
+            let mut version = mem::replace(&mut self.version_buffer, Vec::new());
+            version.pop();
+            if version.last() == Some(&b'\r') {
+                version.pop();
+            }
+            if !version.starts_with(b"SSH-2.0-") && !version.starts_with(b"SSH-1.99-") {
+                return Err(Error::BadVersion);
+            }
+
+            self.client_version = Some(version);
+            self.codec.push(&rest);
+        }
+
+        while let Some(payload) = self.codec.next_packet().map_err(Error::Packet)? {
+            self.handle_packet(&payload, events)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, payload: &[u8], events: &mut Vec<Event>) -> Result<(), Error> {
+        if let Some(Kex {
+            step: KexStep::EcdhInit {
+                ignore_next_packet, ..
+            },
+            ..
+        }) = &mut self.kex
+        {
+            if *ignore_next_packet {
+                *ignore_next_packet = false;
+                return Ok(());
+            }
+        }
+
+        let message_type = *payload.first().ok_or(MALFORMED)?;
+        match message_type {
+            kex::MSG_KEXINIT => self.on_kexinit(payload),
+            kex::MSG_KEX_ECDH_INIT => self.on_ecdh_init(payload),
+            kex::MSG_NEWKEYS => self.on_newkeys(),
+            MSG_DISCONNECT => Err(Error::Disconnected),
+            _ if self.kex.is_some() => {
+                // Only the generic messages can be interleaved with a key exchange, and not even
+                // them during the first one if it is strict.
+                let strict = self.strict_kex && self.session_id.is_none();
+                match message_type {
+                    MSG_IGNORE | MSG_UNIMPLEMENTED | MSG_DEBUG if !strict => Ok(()),
+                    _ => Err(Error::Protocol("unexpected message during key exchange")),
+                }
+            }
+            MSG_IGNORE | MSG_UNIMPLEMENTED | MSG_DEBUG => Ok(()),
+            MSG_SERVICE_REQUEST => self.on_service_request(payload),
+            MSG_USERAUTH_REQUEST => self.on_userauth_request(payload),
+            MSG_GLOBAL_REQUEST if self.authenticated => {
+                let mut reader = Reader::new(&payload[1..]);
+                reader.string().ok_or(MALFORMED)?;
+                if reader.boolean().ok_or(MALFORMED)? {
+                    self.send(Writer::message(MSG_REQUEST_FAILURE).into_bytes());
+                }
+                Ok(())
+            }
+            MSG_CHANNEL_OPEN if self.authenticated => self.on_channel_open(payload),
+            MSG_CHANNEL_WINDOW_ADJUST..=MSG_CHANNEL_FAILURE if self.authenticated => {
+                self.on_channel_message(payload, events)
+            }
+            _ => {
+                let sequence = self.codec.last_inbound_sequence();
+                let message = Writer::message(MSG_UNIMPLEMENTED)
+                    .uint32(sequence)
+                    .into_bytes();
+                self.send(message);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends our `SSH_MSG_KEXINIT` message.
+    fn start_kex(&mut self) {
+        let mut cookie = [0; 16];
+        self.rng.fill_bytes(&mut cookie);
+        let server_kexinit = kex::build_kexinit(cookie);
+        self.send(server_kexinit.clone());
+        self.outbound_paused = true;
+        self.kex = Some(Kex {
+            server_kexinit,
+            step: KexStep::KexInit,
+        });
+    }
+
+    fn on_kexinit(&mut self, payload: &[u8]) -> Result<(), Error> {
+        // A `SSH_MSG_KEXINIT` received outside of a key exchange starts a new one.
+        if self.kex.is_none() {
+            self.start_kex();
+        }
+
+        let negotiated = kex::negotiate(payload).map_err(|err| match err {
+            kex::NegotiationError::Malformed => MALFORMED,
+            kex::NegotiationError::NoCommonAlgorithm(category) => {
+                Error::NoCommonAlgorithm(category)
+            }
+        })?;
+
+        if self.session_id.is_none() {
+            self.strict_kex = negotiated.strict;
+            if self.strict_kex && self.codec.last_inbound_sequence() != 0 {
+                return Err(Error::Protocol("SSH_MSG_KEXINIT isn't the first message"));
+            }
+        }
+
+        let kex = self.kex.as_mut().unwrap();
+        match kex.step {
+            KexStep::KexInit => {}
+            _ => return Err(Error::Protocol("unexpected SSH_MSG_KEXINIT")),
+        }
+        kex.step = KexStep::EcdhInit {
+            client_kexinit: payload.to_vec(),
+            ignore_next_packet: negotiated.ignore_next_packet,
+        };
+        Ok(())
+    }
+
+    fn on_ecdh_init(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let (server_kexinit, client_kexinit) = match &self.kex {
+            Some(Kex {
+                server_kexinit,
+                step: KexStep::EcdhInit { client_kexinit, .. },
+            }) => (server_kexinit.clone(), client_kexinit.clone()),
+            _ => return Err(Error::Protocol("unexpected SSH_MSG_KEX_ECDH_INIT")),
+        };
+
+        let mut reader = Reader::new(&payload[1..]);
+        let client_ephemeral = reader.string().ok_or(MALFORMED)?;
+        if client_ephemeral.len() != 32 || !reader.is_finished() {
+            return Err(MALFORMED);
+        }
+        let client_ephemeral = {
+            let mut key = [0; 32];
+            key.copy_from_slice(client_ephemeral);
+            x25519_dalek::PublicKey::from(key)
+        };
+
+        let server_secret = x25519_dalek::EphemeralSecret::new(&mut self.rng);
+        let server_ephemeral = x25519_dalek::PublicKey::from(&server_secret);
+        let shared_secret = server_secret.diffie_hellman(&client_ephemeral);
+        let shared_secret = shared_secret.as_bytes();
+        // Section 3.1 of RFC 8731 requires rejecting keys that lead to an all-zero secret.
+        if shared_secret.iter().all(|b| *b == 0) {
+            return Err(Error::Protocol("invalid ephemeral key"));
+        }
+
+        let host_key = kex::ed25519_public_key_blob(self.config.host_key.public_key().as_ref());
+        let exchange_hash = kex::exchange_hash(&kex::ExchangeHashInputs {
+            client_version: self.client_version.as_ref().unwrap(),
+            server_version: SERVER_VERSION,
+            client_kexinit: &client_kexinit,
+            server_kexinit: &server_kexinit,
+            host_key: &host_key,
+            client_ephemeral: client_ephemeral.as_bytes(),
+            server_ephemeral: server_ephemeral.as_bytes(),
+            shared_secret,
+        });
+        let session_id = *self.session_id.get_or_insert(exchange_hash);
+
+        let signature = self.config.host_key.sign(&exchange_hash);
+        let reply = Writer::message(kex::MSG_KEX_ECDH_REPLY)
+            .string(&host_key)
+            .string(server_ephemeral.as_bytes())
+            .string(&kex::ed25519_signature_blob(signature.as_ref()))
+            .into_bytes();
+        self.send(reply);
+        self.send(Writer::message(kex::MSG_NEWKEYS).into_bytes());
+
+        let outbound_key = kex::derive_key(shared_secret, &exchange_hash, b'D', &session_id);
+        let inbound_key = kex::derive_key(shared_secret, &exchange_hash, b'C', &session_id);
+        self.codec.set_outbound_key(&outbound_key, self.strict_kex);
+        self.kex.as_mut().unwrap().step = KexStep::NewKeys { inbound_key };
+
+        self.outbound_paused = false;
+        for message in mem::replace(&mut self.queued, Vec::new()) {
+            self.send(message);
+        }
+        self.flush_channel();
+        Ok(())
+    }
+
+    fn on_newkeys(&mut self) -> Result<(), Error> {
+        match self.kex.take() {
+            Some(Kex {
+                step: KexStep::NewKeys { inbound_key },
+                ..
+            }) => {
+                self.codec.set_inbound_key(&inbound_key, self.strict_kex);
+                Ok(())
+            }
+            _ => Err(Error::Protocol("unexpected SSH_MSG_NEWKEYS")),
+        }
+    }
+
+    fn on_service_request(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader::new(&payload[1..]);
+        let service = reader.string().ok_or(MALFORMED)?;
+        if service != b"ssh-userauth" || self.userauth_started {
+            return Err(Error::ServiceNotAvailable);
+        }
+
+        self.userauth_started = true;
+        let message = Writer::message(MSG_SERVICE_ACCEPT)
+            .string(service)
+            .into_bytes();
+        self.send(message);
+        Ok(())
+    }
+
+    fn on_userauth_request(&mut self, payload: &[u8]) -> Result<(), Error> {
+        if !self.userauth_started {
+            return Err(Error::Protocol(
+                "authentication requested before the service",
+            ));
+        }
+        // Requests sent after a successful authentication are ignored, as required by
+        // section 5.1 of RFC 4252.
+        if self.authenticated {
+            return Ok(());
+        }
+
+        let mut reader = Reader::new(&payload[1..]);
+        let user = reader.string().ok_or(MALFORMED)?;
+        let service = reader.string().ok_or(MALFORMED)?;
+        let method = reader.string().ok_or(MALFORMED)?;
+
+        if service == b"ssh-connection" && method == b"publickey" {
+            let has_signature = reader.boolean().ok_or(MALFORMED)?;
+            let algorithm = reader.string().ok_or(MALFORMED)?;
+            let key_blob = reader.string().ok_or(MALFORMED)?;
+
+            let key = kex::parse_ed25519_public_key_blob(key_blob).filter(|key| {
+                algorithm == kex::HOST_KEY_ALGORITHM.as_bytes()
+                    && self.config.authorized_keys.contains(key)
+            });
+
+            if let Some(key) = key {
+                if !has_signature {
+                    // The client asks whether this key is acceptable before signing with it.
+                    let message = Writer::message(MSG_USERAUTH_PK_OK)
+                        .string(algorithm)
+                        .string(key_blob)
+                        .into_bytes();
+                    self.send(message);
+                    return Ok(());
+                }
+
+                let signature_blob = reader.string().ok_or(MALFORMED)?;
+                let signed = Writer::default()
+                    .string(&self.session_id.unwrap())
+                    .byte(MSG_USERAUTH_REQUEST)
+                    .string(user)
+                    .string(service)
+                    .string(method)
+                    .boolean(true)
+                    .string(algorithm)
+                    .string(key_blob)
+                    .into_bytes();
+                let valid =
+                    kex::parse_ed25519_signature_blob(signature_blob).map_or(false, |signature| {
+                        signature::UnparsedPublicKey::new(&signature::ED25519, &key[..])
+                            .verify(&signed, signature)
+                            .is_ok()
+                    });
+
+                if valid {
+                    self.authenticated = true;
+                    self.send(Writer::message(MSG_USERAUTH_SUCCESS).into_bytes());
+                    return Ok(());
+                }
+            }
+        }
+
+        self.auth_failures += 1;
+        if self.auth_failures > MAX_AUTH_ATTEMPTS {
+            return Err(Error::TooManyAuthFailures);
+        }
+
+        let message = Writer::message(MSG_USERAUTH_FAILURE)
+            .name_list(&["publickey"])
+            .boolean(false)
+            .into_bytes();
+        self.send(message);
+        Ok(())
+    }
+
+    fn on_channel_open(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader::new(&payload[1..]);
+        let channel_type = reader.string().ok_or(MALFORMED)?;
+        let remote_id = reader.uint32().ok_or(MALFORMED)?;
+        let remote_window = reader.uint32().ok_or(MALFORMED)?;
+        let remote_max_packet = reader.uint32().ok_or(MALFORMED)?;
+
+        let failure = if channel_type != b"session" {
+            Some((OPEN_UNKNOWN_CHANNEL_TYPE, "unknown channel type"))
+        } else if self.channel.is_some() {
+            Some((
+                OPEN_RESOURCE_SHORTAGE,
+                "only one session at a time is supported",
+            ))
+        } else {
+            None
+        };
+
+        if let Some((reason, description)) = failure {
+            let message = Writer::message(MSG_CHANNEL_OPEN_FAILURE)
+                .uint32(remote_id)
+                .uint32(reason)
+                .string(description.as_bytes())
+                .string(b"")
+                .into_bytes();
+            self.send(message);
+            return Ok(());
+        }
+
+        self.channel = Some(Channel {
+            remote_id,
+            remote_window,
+            remote_max_packet,
+            local_window: LOCAL_WINDOW,
+            pty: false,
+            shell: false,
+            pending: Vec::new(),
+        });
+
+        let message = Writer::message(MSG_CHANNEL_OPEN_CONFIRMATION)
+            .uint32(remote_id)
+            .uint32(CHANNEL_ID)
+            .uint32(LOCAL_WINDOW)
+            .uint32(MAX_CHANNEL_PACKET)
+            .into_bytes();
+        self.send(message);
+        Ok(())
+    }
+
+    fn on_channel_message(&mut self, payload: &[u8], events: &mut Vec<Event>) -> Result<(), Error> {
+        let mut reader = Reader::new(&payload[1..]);
+        if reader.uint32().ok_or(MALFORMED)? != CHANNEL_ID {
+            return Err(Error::Protocol("message for an unknown channel"));
+        }
+        let channel = self
+            .channel
+            .as_mut()
+            .ok_or(Error::Protocol("message for an unknown channel"))?;
+        let remote_id = channel.remote_id;
+
+        match payload[0] {
+            MSG_CHANNEL_WINDOW_ADJUST => {
+                let increase = reader.uint32().ok_or(MALFORMED)?;
+                channel.remote_window = channel
+                    .remote_window
+                    .checked_add(increase)
+                    .ok_or(Error::Protocol("window too large"))?;
+                self.flush_channel();
+            }
+            MSG_CHANNEL_DATA | MSG_CHANNEL_EXTENDED_DATA => {
+                if payload[0] == MSG_CHANNEL_EXTENDED_DATA {
+                    reader.uint32().ok_or(MALFORMED)?;
+                }
+                let data = reader.string().ok_or(MALFORMED)?;
+
+                let len = u32::try_from(data.len()).unwrap();
+                if len > channel.local_window {
+                    return Err(Error::Protocol("channel window exceeded"));
+                }
+                channel.local_window -= len;
+
+                // Clients don't normally send extended data, which we ignore.
+                if payload[0] == MSG_CHANNEL_DATA && channel.shell && !data.is_empty() {
+                    events.push(Event::Data(data.to_vec()));
+                }
+
+                // The data is processed immediately, so we can acknowledge it right away.
+                if channel.local_window < LOCAL_WINDOW / 2 {
+                    let increase = LOCAL_WINDOW - channel.local_window;
+                    channel.local_window = LOCAL_WINDOW;
+                    let message = Writer::message(MSG_CHANNEL_WINDOW_ADJUST)
+                        .uint32(remote_id)
+                        .uint32(increase)
+                        .into_bytes();
+                    self.send(message);
+                }
+            }
+            MSG_CHANNEL_EOF => {}
+            MSG_CHANNEL_CLOSE => {
+                self.channel = None;
+                let message = Writer::message(MSG_CHANNEL_CLOSE)
+                    .uint32(remote_id)
+                    .into_bytes();
+                self.send(message);
+                events.push(Event::Closed);
+            }
+            MSG_CHANNEL_REQUEST => {
+                let request = reader.string().ok_or(MALFORMED)?;
+                let want_reply = reader.boolean().ok_or(MALFORMED)?;
+
+                let accepted = match request {
+                    b"pty-req" if !channel.shell => {
+                        reader.string().ok_or(MALFORMED)?;
+                        let columns = reader.uint32().ok_or(MALFORMED)?;
+                        let rows = reader.uint32().ok_or(MALFORMED)?;
+                        channel.pty = true;
+                        events.extend(window_size_event(columns, rows));
+                        true
+                    }
+                    b"window-change" => {
+                        let columns = reader.uint32().ok_or(MALFORMED)?;
+                        let rows = reader.uint32().ok_or(MALFORMED)?;
+                        events.extend(window_size_event(columns, rows));
+                        true
+                    }
+                    b"shell" if !channel.shell => {
+                        channel.shell = true;
+                        events.push(Event::ShellStarted { pty: channel.pty });
+                        true
+                    }
+                    // Executing commands, setting environment variables, subsystems and
+                    // forwardings aren't supported.
+                    _ => false,
+                };
+
+                if want_reply {
+                    let reply = if accepted {
+                        MSG_CHANNEL_SUCCESS
+                    } else {
+                        MSG_CHANNEL_FAILURE
+                    };
+                    self.send(Writer::message(reply).uint32(remote_id).into_bytes());
+                }
+            }
+            MSG_CHANNEL_SUCCESS | MSG_CHANNEL_FAILURE => {
+                // We never send channel requests.
+                return Err(Error::Protocol("unexpected channel request reply"));
+            }
+            _ => return Err(Error::Protocol("unexpected channel message")),
+        }
+
+        Ok(())
+    }
+
+    /// Sends as much of the pending data of the channel as the window of the client allows.
+    fn flush_channel(&mut self) {
+        if self.outbound_paused {
+            return;
+        }
+
+        loop {
+            let channel = match &mut self.channel {
+                Some(channel) => channel,
+                None => return,
+            };
+
+            let max_len = cmp::min(
+                channel.remote_window,
+                cmp::min(channel.remote_max_packet, MAX_CHANNEL_PACKET),
+            );
+            let len = cmp::min(usize::try_from(max_len).unwrap(), channel.pending.len());
+            if len == 0 {
+                return;
+            }
+
+            channel.remote_window -= u32::try_from(len).unwrap();
+            let message = Writer::message(MSG_CHANNEL_DATA)
+                .uint32(channel.remote_id)
+                .string(&channel.pending[..len])
+                .into_bytes();
+            channel.pending.drain(..len);
+            self.send(message);
+        }
+    }
+
+    /// Sends a message to the client, or queues it if a key exchange is in progress.
+    fn send(&mut self, payload: Vec<u8>) {
+        let is_kex_message = match payload[0] {
+            MSG_DISCONNECT..=MSG_DEBUG => true,
+            kex::MSG_KEXINIT..=49 => true,
+            _ => false,
+        };
+
+        if self.outbound_paused && !is_kex_message {
+            self.queued.push(payload);
+            return;
+        }
+
+        let packet = self.codec.encode(&payload);
+        self.output.extend_from_slice(&packet);
+    }
+}
+
+/// Builds the event reporting the size of the window of the client. Sizes of 0 mean that the
+/// client doesn't know the size.
+fn window_size_event(columns: u32, rows: u32) -> Option<Event> {
+    if columns == 0 || rows == 0 {
+        return None;
+    }
+
+    Some(Event::WindowSize {
+        columns: u16::try_from(columns).unwrap_or(u16::max_value()),
+        rows: u16::try_from(rows).unwrap_or(u16::max_value()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Connection, Error, Event, SERVER_VERSION};
+    use crate::{kex, transport::PacketCodec, wire::Reader, wire::Writer};
+    use ring::signature::{self, Ed25519KeyPair, KeyPair as _};
+    use std::rc::Rc;
+
+    /// Client side of a connection, with the messages that the server has sent.
+    struct Client {
+        connection: Connection,
+        codec: PacketCodec,
+        received: Vec<Vec<u8>>,
+    }
+
+    impl Client {
+        fn send(&mut self, payload: Vec<u8>) -> Result<Vec<Event>, Error> {
+            let packet = self.codec.encode(&payload);
+            self.connection.feed(&packet)
+        }
+
+        /// Decodes the messages sent by the server, and returns their types.
+        fn receive(&mut self) -> Vec<u8> {
+            self.codec.push(&self.connection.take_output());
+            let mut types = Vec::new();
+            while let Some(payload) = self.codec.next_packet().unwrap() {
+                types.push(payload[0]);
+                self.received.push(payload);
+            }
+            types
+        }
+    }
+
+    fn user_key() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[2; 32]).unwrap()
+    }
+
+    /// Performs the key exchange, and checks the signature of the server.
+    fn connect() -> Client {
+        let config = Rc::new(Config {
+            host_key: Ed25519KeyPair::from_seed_unchecked(&[1; 32]).unwrap(),
+            authorized_keys: {
+                let mut key = [0; 32];
+                key.copy_from_slice(user_key().public_key().as_ref());
+                vec![key]
+            },
+        });
+        let host_public_key = config.host_key.public_key().as_ref().to_vec();
+
+        let mut connection = Connection::new(config, [0; 32]);
+        let output = connection.take_output();
+        assert!(output.starts_with(b"SSH-2.0-redshirt\r\n"));
+        let mut codec = PacketCodec::new();
+        codec.push(&output[SERVER_VERSION.len() + 2..]);
+        let server_kexinit = codec.next_packet().unwrap().unwrap();
+
+        let mut client_kexinit = Writer::message(kex::MSG_KEXINIT);
+        for _ in 0..16 {
+            client_kexinit = client_kexinit.byte(0);
+        }
+        let client_kexinit = client_kexinit
+            .name_list(&["curve25519-sha256", "kex-strict-c-v00@openssh.com"])
+            .name_list(&["ssh-ed25519"])
+            .name_list(&["chacha20-poly1305@openssh.com"])
+            .name_list(&["chacha20-poly1305@openssh.com"])
+            .name_list(&[])
+            .name_list(&[])
+            .name_list(&["none"])
+            .name_list(&["none"])
+            .name_list(&[])
+            .name_list(&[])
+            .boolean(false)
+            .uint32(0)
+            .into_bytes();
+        let client_secret = x25519_dalek::StaticSecret::from([3; 32]);
+        let client_ephemeral = x25519_dalek::PublicKey::from(&client_secret);
+
+        let mut data = b"SSH-2.0-test\r\n".to_vec();
+        data.extend(codec.encode(&client_kexinit));
+        let ecdh_init = Writer::message(kex::MSG_KEX_ECDH_INIT)
+            .string(client_ephemeral.as_bytes())
+            .into_bytes();
+        data.extend(codec.encode(&ecdh_init));
+        assert_eq!(connection.feed(&data), Ok(Vec::new()));
+
+        codec.push(&connection.take_output());
+        let reply = codec.next_packet().unwrap().unwrap();
+        let mut reader = Reader::new(&reply);
+        assert_eq!(reader.byte(), Some(kex::MSG_KEX_ECDH_REPLY));
+        let host_key = reader.string().unwrap().to_vec();
+        assert_eq!(
+            kex::parse_ed25519_public_key_blob(&host_key).unwrap()[..],
+            host_public_key[..]
+        );
+        let mut server_ephemeral = [0; 32];
+        server_ephemeral.copy_from_slice(reader.string().unwrap());
+        let signature = kex::parse_ed25519_signature_blob(reader.string().unwrap())
+            .unwrap()
+            .to_vec();
+        assert_eq!(codec.next_packet().unwrap().unwrap(), [kex::MSG_NEWKEYS]);
+
+        let shared_secret =
+            client_secret.diffie_hellman(&x25519_dalek::PublicKey::from(server_ephemeral));
+        let exchange_hash = kex::exchange_hash(&kex::ExchangeHashInputs {
+            client_version: b"SSH-2.0-test",
+            server_version: SERVER_VERSION,
+            client_kexinit: &client_kexinit,
+            server_kexinit: &server_kexinit,
+            host_key: &host_key,
+            client_ephemeral: client_ephemeral.as_bytes(),
+            server_ephemeral: &server_ephemeral,
+            shared_secret: shared_secret.as_bytes(),
+        });
+        signature::UnparsedPublicKey::new(&signature::ED25519, &host_public_key)
+            .verify(&exchange_hash, &signature)
+            .unwrap();
+
+        // The strict key exchange resets the sequence numbers.
+        let secret = shared_secret.as_bytes();
+        codec.set_inbound_key(
+            &kex::derive_key(secret, &exchange_hash, b'D', &exchange_hash),
+            true,
+        );
+        let newkeys = codec.encode(&[kex::MSG_NEWKEYS]);
+        assert_eq!(connection.feed(&newkeys), Ok(Vec::new()));
+        codec.set_outbound_key(
+            &kex::derive_key(secret, &exchange_hash, b'C', &exchange_hash),
+            true,
+        );
+
+        Client {
+            connection,
+            codec,
+            received: Vec::new(),
+        }
+    }
+
+    /// Builds a public key authentication request signed by `key`.
+    fn userauth_request(client: &Client, key: &Ed25519KeyPair) -> Vec<u8> {
+        let key_blob = kex::ed25519_public_key_blob(key.public_key().as_ref());
+        let session_id = client.connection.session_id.unwrap();
+        let signed = Writer::default()
+            .string(&session_id)
+            .byte(50)
+            .string(b"root")
+            .string(b"ssh-connection")
+            .string(b"publickey")
+            .boolean(true)
+            .string(b"ssh-ed25519")
+            .string(&key_blob)
+            .into_bytes();
+        let signature = kex::ed25519_signature_blob(key.sign(&signed).as_ref());
+
+        Writer::message(50)
+            .string(b"root")
+            .string(b"ssh-connection")
+            .string(b"publickey")
+            .boolean(true)
+            .string(b"ssh-ed25519")
+            .string(&key_blob)
+            .string(&signature)
+            .into_bytes()
+    }
+
+    #[test]
+    fn shell_session() {
+        let mut client = connect();
+
+        let events = client.send(Writer::message(5).string(b"ssh-userauth").into_bytes());
+        assert_eq!(events, Ok(Vec::new()));
+        let request = userauth_request(&client, &user_key());
+        assert_eq!(client.send(request), Ok(Vec::new()));
+        assert_eq!(client.receive(), [6, 52]);
+
+        let open = Writer::message(90)
+            .string(b"session")
+            .uint32(7)
+            .uint32(1024)
+            .uint32(16)
+            .into_bytes();
+        assert_eq!(client.send(open), Ok(Vec::new()));
+        let pty = Writer::message(98)
+            .uint32(0)
+            .string(b"pty-req")
+            .boolean(true)
+            .string(b"xterm")
+            .uint32(100)
+            .uint32(30)
+            .uint32(0)
+            .uint32(0)
+            .string(b"")
+            .into_bytes();
+        assert_eq!(
+            client.send(pty),
+            Ok(vec![Event::WindowSize {
+                columns: 100,
+                rows: 30
+            }])
+        );
+        let shell = Writer::message(98)
+            .uint32(0)
+            .string(b"shell")
+            .boolean(true)
+            .into_bytes();
+        assert_eq!(
+            client.send(shell),
+            Ok(vec![Event::ShellStarted { pty: true }])
+        );
+        let data = Writer::message(94).uint32(0).string(b"ls\r").into_bytes();
+        assert_eq!(client.send(data), Ok(vec![Event::Data(b"ls\r".to_vec())]));
+        assert_eq!(client.receive(), [91, 99, 99]);
+
+        // The data is split according to the maximum packet size of the client.
+        client.connection.write(b"hello world, hello world");
+        assert_eq!(client.receive(), [94, 94]);
+        let mut reader = Reader::new(&client.received[5][1..]);
+        assert_eq!(reader.uint32(), Some(7));
+        assert_eq!(reader.string(), Some(&b"hello world, hel"[..]));
+
+        let close = Writer::message(97).uint32(0).into_bytes();
+        assert_eq!(client.send(close), Ok(vec![Event::Closed]));
+        assert_eq!(client.receive(), [97]);
+    }
+
+    #[test]
+    fn unauthorized_key() {
+        let mut client = connect();
+        client
+            .send(Writer::message(5).string(b"ssh-userauth").into_bytes())
+            .unwrap();
+        let other_key = Ed25519KeyPair::from_seed_unchecked(&[4; 32]).unwrap();
+        let request = userauth_request(&client, &other_key);
+        assert_eq!(client.send(request), Ok(Vec::new()));
+        assert_eq!(client.receive(), [6, 51]);
+
+        // Channels can't be opened without authenticating.
+        let open = Writer::message(90)
+            .string(b"session")
+            .uint32(0)
+            .uint32(1024)
+            .uint32(1024)
+            .into_bytes();
+        assert_eq!(client.send(open), Ok(Vec::new()));
+        assert_eq!(client.receive(), [3]);
+    }
+
+    #[test]
+    fn bad_signature() {
+        let mut client = connect();
+        client
+            .send(Writer::message(5).string(b"ssh-userauth").into_bytes())
+            .unwrap();
+        let mut request = userauth_request(&client, &user_key());
+        let last = request.len() - 1;
+        request[last] ^= 1;
+        assert_eq!(client.send(request), Ok(Vec::new()));
+        assert_eq!(client.receive(), [6, 51]);
+    }
+
+    #[test]
+    fn bad_version() {
+        let config = Rc::new(Config {
+            host_key: Ed25519KeyPair::from_seed_unchecked(&[1; 32]).unwrap(),
+            authorized_keys: Vec::new(),
+        });
+        let mut connection = Connection::new(config, [0; 32]);
+        assert_eq!(connection.feed(b"SSH-1.5-old\r\n"), Err(Error::BadVersion));
+    }
+}
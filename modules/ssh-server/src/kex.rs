@@ -0,0 +1,342 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Algorithm negotiation and key exchange, as defined in section 7 of RFC 4253.
+//!
+//! We only support `curve25519-sha256` (RFC 8731) for the key exchange, `ssh-ed25519`
+//! (RFC 8709) for the host key, and `chacha20-poly1305@openssh.com` for the encryption. We also
+//! support the "strict key exchange" extension of OpenSSH, which protects the first key
+//! exchange against an attacker that would insert or remove packets.
+
+use crate::wire::{Reader, Writer};
+use ring::digest;
+
+pub const MSG_KEXINIT: u8 = 20;
+pub const MSG_NEWKEYS: u8 = 21;
+pub const MSG_KEX_ECDH_INIT: u8 = 30;
+pub const MSG_KEX_ECDH_REPLY: u8 = 31;
+
+const KEX_ALGORITHMS: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const STRICT_KEX_SERVER: &str = "kex-strict-s-v00@openssh.com";
+const STRICT_KEX_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+pub const HOST_KEY_ALGORITHM: &str = "ssh-ed25519";
+const CIPHER: &str = "chacha20-poly1305@openssh.com";
+/// The cipher authenticates the packets by itself, and the negotiated MAC is never used. We
+/// advertise one anyway, as some clients refuse an empty list.
+const MAC: &str = "hmac-sha2-256";
+const COMPRESSION: &str = "none";
+
+/// Outcome of a successful negotiation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    /// True if both sides support the strict key exchange.
+    pub strict: bool,
+    /// True if the client has sent a key exchange packet based on a wrong guess of the
+    /// algorithms. This packet must be ignored.
+    pub ignore_next_packet: bool,
+}
+
+/// Error while negotiating the algorithms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NegotiationError {
+    /// The `SSH_MSG_KEXINIT` message is malformed.
+    Malformed,
+    /// No algorithm of this category is supported by both sides.
+    NoCommonAlgorithm(&'static str),
+}
+
+/// Builds our `SSH_MSG_KEXINIT` message.
+pub fn build_kexinit(cookie: [u8; 16]) -> Vec<u8> {
+    let mut kex_algorithms = KEX_ALGORITHMS.to_vec();
+    kex_algorithms.push(STRICT_KEX_SERVER);
+
+    let mut writer = Writer::message(MSG_KEXINIT);
+    for byte in cookie.iter() {
+        writer = writer.byte(*byte);
+    }
+    writer
+        .name_list(&kex_algorithms)
+        .name_list(&[HOST_KEY_ALGORITHM])
+        .name_list(&[CIPHER])
+        .name_list(&[CIPHER])
+        .name_list(&[MAC])
+        .name_list(&[MAC])
+        .name_list(&[COMPRESSION])
+        .name_list(&[COMPRESSION])
+        .name_list(&[])
+        .name_list(&[])
+        .boolean(false)
+        .uint32(0)
+        .into_bytes()
+}
+
+/// Negotiates the algorithms based on the `SSH_MSG_KEXINIT` message of the client.
+pub fn negotiate(client_kexinit: &[u8]) -> Result<Negotiated, NegotiationError> {
+    let mut reader = Reader::new(client_kexinit);
+    if reader.byte() != Some(MSG_KEXINIT) {
+        return Err(NegotiationError::Malformed);
+    }
+    reader.bytes(16).ok_or(NegotiationError::Malformed)?;
+
+    let mut lists = Vec::with_capacity(10);
+    for _ in 0..10 {
+        lists.push(reader.name_list().ok_or(NegotiationError::Malformed)?);
+    }
+    let first_kex_packet_follows = reader.boolean().ok_or(NegotiationError::Malformed)?;
+    reader.uint32().ok_or(NegotiationError::Malformed)?;
+
+    // The algorithm chosen in each category is the first one of the client that we support.
+    let kex = lists[0]
+        .iter()
+        .find(|name| KEX_ALGORITHMS.contains(*name))
+        .ok_or(NegotiationError::NoCommonAlgorithm("key exchange"))?;
+    if !lists[1].contains(&HOST_KEY_ALGORITHM) {
+        return Err(NegotiationError::NoCommonAlgorithm("host key"));
+    }
+    if !lists[2].contains(&CIPHER) || !lists[3].contains(&CIPHER) {
+        return Err(NegotiationError::NoCommonAlgorithm("cipher"));
+    }
+    if !lists[6].contains(&COMPRESSION) || !lists[7].contains(&COMPRESSION) {
+        return Err(NegotiationError::NoCommonAlgorithm("compression"));
+    }
+
+    Ok(Negotiated {
+        strict: lists[0].contains(&STRICT_KEX_CLIENT),
+        ignore_next_packet: first_kex_packet_follows
+            && (lists[0].first() != Some(kex) || lists[1].first() != Some(&HOST_KEY_ALGORITHM)),
+    })
+}
+
+/// Inputs of the exchange hash, in the order in which they are hashed.
+pub struct ExchangeHashInputs<'a> {
+    /// Identification string of the client, without the line break.
+    pub client_version: &'a [u8],
+    /// Identification string of the server, without the line break.
+    pub server_version: &'a [u8],
+    pub client_kexinit: &'a [u8],
+    pub server_kexinit: &'a [u8],
+    /// Public host key, as encoded by [`ed25519_public_key_blob`].
+    pub host_key: &'a [u8],
+    pub client_ephemeral: &'a [u8],
+    pub server_ephemeral: &'a [u8],
+    pub shared_secret: &'a [u8],
+}
+
+/// Calculates the exchange hash, which the server signs and which the keys are derived from.
+pub fn exchange_hash(inputs: &ExchangeHashInputs) -> [u8; 32] {
+    let data = Writer::default()
+        .string(inputs.client_version)
+        .string(inputs.server_version)
+        .string(inputs.client_kexinit)
+        .string(inputs.server_kexinit)
+        .string(inputs.host_key)
+        .string(inputs.client_ephemeral)
+        .string(inputs.server_ephemeral)
+        .mpint(inputs.shared_secret)
+        .into_bytes();
+    sha256(&[&data])
+}
+
+/// Derives a key of the cipher from the result of the key exchange. `letter` is `C` for the
+/// key of the packets sent by the client, and `D` for the packets sent by the server.
+pub fn derive_key(
+    shared_secret: &[u8],
+    exchange_hash: &[u8; 32],
+    letter: u8,
+    session_id: &[u8; 32],
+) -> [u8; 64] {
+    let secret = Writer::default().mpint(shared_secret).into_bytes();
+    let first = sha256(&[&secret, exchange_hash, &[letter], session_id]);
+    let second = sha256(&[&secret, exchange_hash, &first]);
+
+    let mut key = [0; 64];
+    key[..32].copy_from_slice(&first);
+    key[32..].copy_from_slice(&second);
+    key
+}
+
+/// Encodes an Ed25519 public key as in section 4 of RFC 8709.
+pub fn ed25519_public_key_blob(public_key: &[u8]) -> Vec<u8> {
+    Writer::default()
+        .string(HOST_KEY_ALGORITHM.as_bytes())
+        .string(public_key)
+        .into_bytes()
+}
+
+/// Decodes an Ed25519 public key encoded as in section 4 of RFC 8709.
+pub fn parse_ed25519_public_key_blob(blob: &[u8]) -> Option<[u8; 32]> {
+    let mut reader = Reader::new(blob);
+    if reader.string()? != HOST_KEY_ALGORITHM.as_bytes() {
+        return None;
+    }
+    let key = reader.string()?;
+    if key.len() != 32 || !reader.is_finished() {
+        return None;
+    }
+
+    let mut out = [0; 32];
+    out.copy_from_slice(key);
+    Some(out)
+}
+
+/// Encodes an Ed25519 signature as in section 6 of RFC 8709.
+pub fn ed25519_signature_blob(signature: &[u8]) -> Vec<u8> {
+    Writer::default()
+        .string(HOST_KEY_ALGORITHM.as_bytes())
+        .string(signature)
+        .into_bytes()
+}
+
+/// Decodes an Ed25519 signature encoded as in section 6 of RFC 8709.
+pub fn parse_ed25519_signature_blob(blob: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(blob);
+    if reader.string()? != HOST_KEY_ALGORITHM.as_bytes() {
+        return None;
+    }
+    let signature = reader.string()?;
+    if signature.len() != 64 || !reader.is_finished() {
+        return None;
+    }
+    Some(signature)
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut context = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        context.update(part);
+    }
+
+    let mut out = [0; 32];
+    out.copy_from_slice(context.finish().as_ref());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_kexinit, ed25519_public_key_blob, negotiate, parse_ed25519_public_key_blob,
+        parse_ed25519_signature_blob, Negotiated, NegotiationError, MSG_KEXINIT,
+    };
+    use crate::wire::Writer;
+
+    /// Builds a `SSH_MSG_KEXINIT` message with the given key exchange and cipher algorithms.
+    fn client_kexinit(kex: &[&str], ciphers: &[&str], first_kex_packet_follows: bool) -> Vec<u8> {
+        let mut writer = Writer::message(MSG_KEXINIT);
+        for _ in 0..16 {
+            writer = writer.byte(0);
+        }
+        writer
+            .name_list(kex)
+            .name_list(&["rsa-sha2-512", "ssh-ed25519"])
+            .name_list(ciphers)
+            .name_list(ciphers)
+            .name_list(&["umac-64@openssh.com"])
+            .name_list(&["umac-64@openssh.com"])
+            .name_list(&["none", "zlib@openssh.com"])
+            .name_list(&["none", "zlib@openssh.com"])
+            .name_list(&[])
+            .name_list(&[])
+            .boolean(first_kex_packet_follows)
+            .uint32(0)
+            .into_bytes()
+    }
+
+    #[test]
+    fn negotiate_openssh() {
+        let kexinit = client_kexinit(
+            &["sntrup761x25519-sha512", "curve25519-sha256", "ext-info-c"],
+            &["aes128-ctr", "chacha20-poly1305@openssh.com"],
+            false,
+        );
+        assert_eq!(
+            negotiate(&kexinit),
+            Ok(Negotiated {
+                strict: false,
+                ignore_next_packet: false,
+            })
+        );
+    }
+
+    #[test]
+    fn negotiate_strict() {
+        let kexinit = client_kexinit(
+            &["curve25519-sha256", "kex-strict-c-v00@openssh.com"],
+            &["chacha20-poly1305@openssh.com"],
+            false,
+        );
+        assert!(negotiate(&kexinit).unwrap().strict);
+    }
+
+    #[test]
+    fn negotiate_wrong_guess() {
+        let kexinit = client_kexinit(
+            &["curve25519-sha256@libssh.org"],
+            &["chacha20-poly1305@openssh.com"],
+            true,
+        );
+        // The first host key algorithm of the client isn't ours.
+        assert!(negotiate(&kexinit).unwrap().ignore_next_packet);
+    }
+
+    #[test]
+    fn negotiate_failures() {
+        let kexinit = client_kexinit(
+            &["diffie-hellman-group14-sha256"],
+            &["chacha20-poly1305@openssh.com"],
+            false,
+        );
+        assert_eq!(
+            negotiate(&kexinit),
+            Err(NegotiationError::NoCommonAlgorithm("key exchange"))
+        );
+
+        let kexinit = client_kexinit(&["curve25519-sha256"], &["aes128-ctr"], false);
+        assert_eq!(
+            negotiate(&kexinit),
+            Err(NegotiationError::NoCommonAlgorithm("cipher"))
+        );
+
+        let kexinit = client_kexinit(&["curve25519-sha256"], &["aes128-ctr"], false);
+        assert_eq!(
+            negotiate(&kexinit[..kexinit.len() - 1]),
+            Err(NegotiationError::Malformed)
+        );
+    }
+
+    #[test]
+    fn negotiate_with_ourselves() {
+        let kexinit = build_kexinit([0; 16]);
+        assert_eq!(
+            negotiate(&kexinit),
+            Ok(Negotiated {
+                strict: false,
+                ignore_next_packet: false,
+            })
+        );
+    }
+
+    #[test]
+    fn key_blobs() {
+        let blob = ed25519_public_key_blob(&[3; 32]);
+        assert_eq!(&blob[..15], b"\0\0\0\x0bssh-ed25519");
+        assert_eq!(parse_ed25519_public_key_blob(&blob), Some([3; 32]));
+        assert_eq!(parse_ed25519_public_key_blob(&blob[..blob.len() - 1]), None);
+        assert_eq!(parse_ed25519_signature_blob(&blob), None);
+
+        let mut trailing = blob.clone();
+        trailing.push(0);
+        assert_eq!(parse_ed25519_public_key_blob(&trailing), None);
+    }
+}
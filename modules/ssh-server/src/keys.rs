@@ -0,0 +1,105 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parsing and formatting of the keys passed through the environment variables.
+
+use crate::kex;
+
+/// Parses a list of keys in the format of the `authorized_keys` files of OpenSSH, separated
+/// with line breaks or commas. Returns `None` if a key is invalid or isn't an Ed25519 key.
+///
+/// Empty lines and lines starting with `#` are ignored. Options in front of the keys aren't
+/// supported.
+pub fn parse_authorized_keys(text: &str) -> Option<Vec<[u8; 32]>> {
+    text.split(|c| c == '\n' || c == ',')
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()? != kex::HOST_KEY_ALGORITHM {
+                return None;
+            }
+            let blob = base64::decode(fields.next()?).ok()?;
+            kex::parse_ed25519_public_key_blob(&blob)
+        })
+        .collect()
+}
+
+/// Formats a public key as in the `authorized_keys` and `known_hosts` files of OpenSSH.
+pub fn format_public_key(public_key: &[u8]) -> String {
+    let blob = kex::ed25519_public_key_blob(public_key);
+    format!("{} {}", kex::HOST_KEY_ALGORITHM, base64::encode(&blob))
+}
+
+/// Decodes the 32 bytes seed of an Ed25519 key, encoded in hexadecimal.
+pub fn parse_seed(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim().as_bytes();
+    if text.len() != 64 {
+        return None;
+    }
+
+    let mut out = [0; 32];
+    for (byte, pair) in out.iter_mut().zip(text.chunks(2)) {
+        let high = char::from(pair[0]).to_digit(16)?;
+        let low = char::from(pair[1]).to_digit(16)?;
+        *byte = (high * 16 + low) as u8;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_public_key, parse_authorized_keys, parse_seed};
+
+    #[test]
+    fn authorized_keys_round_trip() {
+        let line = format_public_key(&[5; 32]);
+        assert!(line.starts_with("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI"));
+
+        let text = format!(
+            "# admin\n{} user@host\n\n{}",
+            line,
+            format_public_key(&[6; 32])
+        );
+        assert_eq!(parse_authorized_keys(&text), Some(vec![[5; 32], [6; 32]]));
+        let text = format!("{},{}", line, line);
+        assert_eq!(parse_authorized_keys(&text), Some(vec![[5; 32], [5; 32]]));
+        assert_eq!(parse_authorized_keys(""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn authorized_keys_invalid() {
+        assert_eq!(parse_authorized_keys("ssh-rsa AAAAB3NzaC1yc2E"), None);
+        assert_eq!(parse_authorized_keys("ssh-ed25519 !!!"), None);
+        assert_eq!(parse_authorized_keys("ssh-ed25519"), None);
+        // The blob contains an RSA key.
+        assert_eq!(
+            parse_authorized_keys("ssh-ed25519 AAAAB3NzaC1yc2EAAAADAQAB"),
+            None
+        );
+    }
+
+    #[test]
+    fn seed() {
+        let text = "000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F";
+        let mut expected = [0; 32];
+        for (n, byte) in expected.iter_mut().enumerate() {
+            *byte = n as u8;
+        }
+        assert_eq!(parse_seed(text), Some(expected));
+        assert_eq!(parse_seed(&text[2..]), None);
+        assert_eq!(parse_seed(&text.replace('A', "g")), None);
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SSH server that exposes the console interface, so that a machine can be administered
+//! remotely.
+//!
+//! The shell session of the client is attached to the console: the text that programs write on
+//! the console is sent to the client, and the lines typed by the client are returned to the
+//! programs that read from the console, such as a shell. Only one program can handle the
+//! console interface at a time, and this program replaces the console of the machine. Only one
+//! session can be open at a time, and other connections are closed immediately. Text written
+//! while no session is open is discarded.
+//!
+//! Listens on the address passed through the `SSH_SERVER_ADDRESS` environment variable, or
+//! `0.0.0.0:22` by default.
+//!
+//! Clients authenticate with one of the Ed25519 public keys listed in the
+//! `SSH_SERVER_AUTHORIZED_KEYS` environment variable, in the format of the `authorized_keys`
+//! files of OpenSSH, separated with line breaks or commas. The server doesn't start if no key
+//! is authorized. Passwords aren't supported.
+//!
+//! The host key is the Ed25519 seed passed in hexadecimal through the `SSH_SERVER_HOST_KEY`
+//! environment variable. If it isn't set, a host key is generated at startup and its public key
+//! is written to the logs, so that clients can verify it.
+
+mod connection;
+mod kex;
+mod keys;
+mod transport;
+mod wire;
+
+use futures::{io::WriteHalf, prelude::*};
+use parity_scale_codec::DecodeAll;
+use redshirt_console_interface::ffi;
+use redshirt_log_interface::Level;
+use redshirt_syscalls_interface::{InterfaceOrDestroyed, MessageId, Pid};
+use redshirt_tcp_interface::{TcpListener, TcpStream};
+use ring::signature::{Ed25519KeyPair, KeyPair as _};
+use std::{collections::VecDeque, mem, pin::Pin, rc::Rc};
+
+/// Address to listen on if the `SSH_SERVER_ADDRESS` environment variable isn't set.
+const DEFAULT_ADDRESS: &str = "0.0.0.0:22";
+/// Size reported if the client doesn't tell us the size of its window.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+/// Maximum length of a line, in bytes. Additional characters are ignored.
+const MAX_LINE_LEN: usize = 4096;
+/// Maximum number of lines typed by the user and waiting to be read. Older lines are
+/// discarded.
+const MAX_QUEUED_LINES: usize = 64;
+/// Target of the log entries.
+const LOG_TARGET: &str = "ssh-server";
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let address = redshirt_arguments_interface::var("SSH_SERVER_ADDRESS")
+        .await
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+    let address = match address.parse() {
+        Ok(a) => a,
+        Err(_) => return,
+    };
+
+    let authorized_keys = redshirt_arguments_interface::var("SSH_SERVER_AUTHORIZED_KEYS")
+        .await
+        .and_then(|keys| keys::parse_authorized_keys(&keys));
+    let authorized_keys = match authorized_keys {
+        Some(keys) if !keys.is_empty() => keys,
+        _ => {
+            let message = "SSH_SERVER_AUTHORIZED_KEYS is missing or invalid";
+            redshirt_log_interface::log(Level::Error, LOG_TARGET, message);
+            return;
+        }
+    };
+
+    let host_key = match redshirt_arguments_interface::var("SSH_SERVER_HOST_KEY").await {
+        Some(seed) => match keys::parse_seed(&seed) {
+            Some(seed) => Ed25519KeyPair::from_seed_unchecked(&seed).unwrap(),
+            None => {
+                let message = "SSH_SERVER_HOST_KEY isn't a 32 bytes hexadecimal seed";
+                redshirt_log_interface::log(Level::Error, LOG_TARGET, message);
+                return;
+            }
+        },
+        None => {
+            let seed = redshirt_random_interface::generate(32).await;
+            let host_key = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+            let message = format!(
+                "generated host key {}",
+                keys::format_public_key(host_key.public_key().as_ref())
+            );
+            redshirt_log_interface::log(Level::Info, LOG_TARGET, &message);
+            host_key
+        }
+    };
+
+    let config = Rc::new(connection::Config {
+        host_key,
+        authorized_keys,
+    });
+
+    let listener = match TcpListener::bind(&address).await {
+        Ok(l) => l,
+        Err(()) => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut connections = Box::pin(stream::unfold(listener, |mut l| async move {
+        let (connection, _) = l.accept().await;
+        Some((connection, l))
+    }));
+
+    let mut session: Option<Session> = None;
+    // Lines typed by the user that nobody has asked for yet.
+    let mut lines = VecDeque::<String>::new();
+    // `ReadLine` messages waiting for the user to type a line.
+    let mut pending_reads = VecDeque::<(Pid, MessageId)>::new();
+
+    loop {
+        let event = {
+            let message = redshirt_syscalls_interface::next_interface_message().map(Event::Message);
+            let connection = connections.next().map(|c| Event::Connection(c.unwrap()));
+            let input = async {
+                match &mut session {
+                    Some(s) => Event::Input(s.input.next().await),
+                    None => future::pending().await,
+                }
+            };
+            futures::pin_mut!(input);
+
+            match future::select(future::select(message, connection), input).await {
+                future::Either::Left((future::Either::Left((event, _)), _)) => event,
+                future::Either::Left((future::Either::Right((event, _)), _)) => event,
+                future::Either::Right((event, _)) => event,
+            }
+        };
+
+        let msg = match event {
+            Event::Connection(connection) => {
+                // Dropping the connection closes it.
+                if session.is_none() {
+                    session = Some(Session::new(connection, config.clone()).await);
+                }
+                continue;
+            }
+            Event::Input(None) => {
+                session = None;
+                continue;
+            }
+            Event::Input(Some(data)) => {
+                match session.as_mut().unwrap().process_input(&data).await {
+                    Ok(typed) => {
+                        for line in typed {
+                            if let Some((_, message_id)) = pending_reads.pop_front() {
+                                answer_line(message_id, line);
+                            } else {
+                                if lines.len() >= MAX_QUEUED_LINES {
+                                    lines.pop_front();
+                                }
+                                lines.push_back(line);
+                            }
+                        }
+                    }
+                    Err(()) => session = None,
+                }
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::Interface(m)) => m,
+            Event::Message(InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                pending_reads.retain(|(pid, _)| *pid != msg.pid);
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                pending_reads.retain(|(_, id)| *id != msg.message_id);
+                continue;
+            }
+            Event::Message(InterfaceOrDestroyed::Shutdown(_)) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(ffi::ConsoleMessage::Write(text)) => {
+                if let Some(session) = &mut session {
+                    session.write_text(&text).await;
+                }
+            }
+            Ok(ffi::ConsoleMessage::ReadLine) => {
+                if let Some(message_id) = msg.message_id {
+                    if let Some(line) = lines.pop_front() {
+                        answer_line(message_id, line);
+                    } else {
+                        pending_reads.push_back((msg.emitter_pid, message_id));
+                    }
+                }
+            }
+            Ok(ffi::ConsoleMessage::Size) => {
+                if let Some(message_id) = msg.message_id {
+                    let (columns, rows) = session.as_ref().map_or(DEFAULT_SIZE, |s| s.size);
+                    let response = ffi::SizeResponse { columns, rows };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+            }
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+            }
+        }
+    }
+}
+
+/// Event that the main loop waits for.
+enum Event {
+    Message(InterfaceOrDestroyed),
+    Connection(TcpStream),
+    /// Data sent by the client, or `None` if the connection has been closed.
+    Input(Option<Vec<u8>>),
+}
+
+/// Answers a `ReadLine` message.
+fn answer_line(message_id: MessageId, line: String) {
+    let response = ffi::ReadLineResponse { result: Ok(line) };
+    redshirt_syscalls_interface::emit_answer(message_id, &response);
+}
+
+/// Connection with the client.
+struct Session {
+    /// Data sent by the client.
+    input: Pin<Box<dyn Stream<Item = Vec<u8>>>>,
+    output: WriteHalf<TcpStream>,
+    connection: connection::Connection,
+    /// If true, the client has requested a terminal. We echo the characters that it types, and
+    /// line breaks must be sent as a carriage return followed with a line feed.
+    pty: bool,
+    /// Line being typed by the user.
+    line: Vec<u8>,
+    /// True if the last byte typed by the user is a carriage return.
+    after_carriage_return: bool,
+    /// Size of the window of the client, as columns and rows.
+    size: (u16, u16),
+}
+
+impl Session {
+    async fn new(socket: TcpStream, config: Rc<connection::Config>) -> Self {
+        let (reader, output) = socket.split();
+        let input = Box::pin(stream::unfold(reader, |mut reader| async move {
+            let mut buffer = [0; 4096];
+            match reader.read(&mut buffer).await {
+                Ok(0) | Err(_) => None,
+                Ok(n) => Some((buffer[..n].to_vec(), reader)),
+            }
+        }));
+
+        let mut seed = [0; 32];
+        redshirt_random_interface::generate_in(&mut seed).await;
+
+        let mut session = Session {
+            input,
+            output,
+            connection: connection::Connection::new(config, seed),
+            pty: false,
+            line: Vec::new(),
+            after_carriage_return: false,
+            size: DEFAULT_SIZE,
+        };
+
+        session.flush().await;
+        session
+    }
+
+    /// Sends text written on the console to the client.
+    async fn write_text(&mut self, text: &str) {
+        if self.pty {
+            let text = text.replace('\n', "\r\n");
+            self.connection.write(text.as_bytes());
+        } else {
+            self.connection.write(text.as_bytes());
+        }
+        self.flush().await;
+    }
+
+    /// Processes data sent by the client, and returns the lines that have been completed.
+    /// Returns an error if the connection must be closed.
+    async fn process_input(&mut self, data: &[u8]) -> Result<Vec<String>, ()> {
+        let events = match self.connection.feed(data) {
+            Ok(events) => events,
+            Err(_) => {
+                self.flush().await;
+                return Err(());
+            }
+        };
+
+        let mut lines = Vec::new();
+        let mut to_echo = Vec::new();
+
+        for event in events {
+            match event {
+                connection::Event::ShellStarted { pty } => self.pty = pty,
+                connection::Event::WindowSize { columns, rows } => self.size = (columns, rows),
+                connection::Event::Closed => {
+                    self.flush().await;
+                    return Err(());
+                }
+                connection::Event::Data(data) => {
+                    for byte in data {
+                        self.process_byte(byte, &mut lines, &mut to_echo);
+                    }
+                }
+            }
+        }
+
+        if !to_echo.is_empty() {
+            self.connection.write(&to_echo);
+        }
+        self.flush().await;
+        Ok(lines)
+    }
+
+    /// Processes a byte typed by the user. Completed lines are pushed to `lines`, and the bytes
+    /// to echo back to `to_echo`.
+    fn process_byte(&mut self, byte: u8, lines: &mut Vec<String>, to_echo: &mut Vec<u8>) {
+        // Terminals send a carriage return, while clients without a terminal send line feeds. A
+        // line feed that directly follows a carriage return doesn't start a new line.
+        if byte == b'\n' && mem::replace(&mut self.after_carriage_return, false) {
+            return;
+        }
+        self.after_carriage_return = byte == b'\r';
+
+        match byte {
+            b'\r' | b'\n' => {
+                lines.push(String::from_utf8_lossy(&self.line).into_owned());
+                self.line.clear();
+                if self.pty {
+                    to_echo.extend_from_slice(b"\r\n");
+                }
+            }
+            0x7f | 0x08 => {
+                // Remove the last character, which can span multiple bytes.
+                let mut removed = false;
+                while let Some(byte) = self.line.pop() {
+                    removed = true;
+                    if byte & 0xc0 != 0x80 {
+                        break;
+                    }
+                }
+                if removed && self.pty {
+                    to_echo.extend_from_slice(b"\x08 \x08");
+                }
+            }
+            byte => {
+                // Control characters are ignored.
+                if (byte >= 0x20 || byte == b'\t') && self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte);
+                    if self.pty {
+                        to_echo.push(byte);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the pending data to the client. Errors are ignored, as a closed connection is
+    /// detected when reading.
+    async fn flush(&mut self) {
+        let data = self.connection.take_output();
+        if !data.is_empty() {
+            let _ = self.output.write_all(&data).await;
+        }
+    }
+}
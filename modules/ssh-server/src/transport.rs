@@ -0,0 +1,280 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Binary packet protocol, as defined in section 6 of RFC 4253.
+//!
+//! Before the first key exchange, packets are sent in plain text. Afterwards, they are encrypted
+//! with `chacha20-poly1305@openssh.com`, which is the only cipher that we support.
+
+use ring::aead::chacha20_poly1305_openssh::{OpeningKey, SealingKey, KEY_LEN, TAG_LEN};
+use std::convert::TryFrom;
+
+/// Maximum size of a packet, not including the length prefix and the authentication tag.
+pub const MAX_PACKET_LEN: usize = 256 * 1024;
+
+/// Size of the blocks that the packets must be aligned to.
+const BLOCK_SIZE: usize = 8;
+
+/// Error while decoding a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketError {
+    /// The length of the packet is too large or not aligned to the block size.
+    BadLength,
+    /// The length of the padding is invalid.
+    BadPadding,
+    /// The authentication tag of the packet doesn't match its content.
+    BadTag,
+}
+
+/// Splits the data received from the client into packets, and builds the packets sent to it.
+pub struct PacketCodec {
+    /// Data received from the client and not decoded yet.
+    buffer: Vec<u8>,
+    /// Key used to decrypt the packets sent by the client, or `None` before the first key
+    /// exchange.
+    inbound: Option<OpeningKey>,
+    /// Key used to encrypt the packets sent to the client, or `None` before the first key
+    /// exchange.
+    outbound: Option<SealingKey>,
+    /// Number of packets received, modulo 2^32. Used as the nonce of the cipher.
+    inbound_sequence: u32,
+    /// Number of packets sent, modulo 2^32. Used as the nonce of the cipher.
+    outbound_sequence: u32,
+}
+
+impl PacketCodec {
+    pub fn new() -> Self {
+        PacketCodec {
+            buffer: Vec::new(),
+            inbound: None,
+            outbound: None,
+            inbound_sequence: 0,
+            outbound_sequence: 0,
+        }
+    }
+
+    /// Adds data received from the client.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the sequence number of the last packet returned by
+    /// [`next_packet`](PacketCodec::next_packet).
+    pub fn last_inbound_sequence(&self) -> u32 {
+        self.inbound_sequence.wrapping_sub(1)
+    }
+
+    /// Decodes the next packet from the data that has been pushed, and returns its payload.
+    /// Returns `Ok(None)` if more data is needed.
+    pub fn next_packet(&mut self) -> Result<Option<Vec<u8>>, PacketError> {
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+
+        let mut length_bytes = [0; 4];
+        length_bytes.copy_from_slice(&self.buffer[..4]);
+        if let Some(key) = &self.inbound {
+            length_bytes = key.decrypt_packet_length(self.inbound_sequence, length_bytes);
+        }
+        let packet_len = usize::try_from(u32::from_be_bytes(length_bytes))
+            .map_err(|_| PacketError::BadLength)?;
+
+        // The length prefix is only part of the aligned data if it's not encrypted.
+        let aligned_len = if self.inbound.is_some() {
+            packet_len
+        } else {
+            packet_len + 4
+        };
+        if packet_len > MAX_PACKET_LEN || packet_len < 5 || aligned_len % BLOCK_SIZE != 0 {
+            return Err(PacketError::BadLength);
+        }
+
+        let tag_len = if self.inbound.is_some() { TAG_LEN } else { 0 };
+        if self.buffer.len() < 4 + packet_len + tag_len {
+            return Ok(None);
+        }
+
+        let packet = match &self.inbound {
+            Some(key) => {
+                let mut tag = [0; TAG_LEN];
+                tag.copy_from_slice(&self.buffer[4 + packet_len..4 + packet_len + TAG_LEN]);
+                key.open_in_place(
+                    self.inbound_sequence,
+                    &mut self.buffer[..4 + packet_len],
+                    &tag,
+                )
+                .map_err(|_| PacketError::BadTag)?
+            }
+            None => &self.buffer[4..4 + packet_len],
+        };
+
+        let padding_len = usize::from(packet[0]);
+        if padding_len < 4 || padding_len + 1 > packet_len {
+            return Err(PacketError::BadPadding);
+        }
+        let payload = packet[1..packet_len - padding_len].to_vec();
+
+        self.buffer.drain(..4 + packet_len + tag_len);
+        self.inbound_sequence = self.inbound_sequence.wrapping_add(1);
+        Ok(Some(payload))
+    }
+
+    /// Builds a packet containing the given payload, ready to be sent to the client.
+    pub fn encode(&mut self, payload: &[u8]) -> Vec<u8> {
+        // The length prefix is only part of the aligned data if it's not encrypted.
+        let unpadded_len = if self.outbound.is_some() {
+            1 + payload.len()
+        } else {
+            5 + payload.len()
+        };
+        let mut padding_len = BLOCK_SIZE - unpadded_len % BLOCK_SIZE;
+        if padding_len < 4 {
+            padding_len += BLOCK_SIZE;
+        }
+
+        let packet_len = 1 + payload.len() + padding_len;
+        let mut packet = Vec::with_capacity(4 + packet_len + TAG_LEN);
+        packet.extend_from_slice(&u32::try_from(packet_len).unwrap().to_be_bytes());
+        packet.push(u8::try_from(padding_len).unwrap());
+        packet.extend_from_slice(payload);
+        // The padding is encrypted after the first key exchange, so its content doesn't matter.
+        packet.resize(4 + packet_len, 0);
+
+        if let Some(key) = &self.outbound {
+            let mut tag = [0; TAG_LEN];
+            key.seal_in_place(self.outbound_sequence, &mut packet, &mut tag);
+            packet.extend_from_slice(&tag);
+        }
+
+        self.outbound_sequence = self.outbound_sequence.wrapping_add(1);
+        packet
+    }
+
+    /// Starts decrypting the packets sent by the client with the given key. If `reset_sequence`
+    /// is true, the sequence number of the next packet is 0.
+    pub fn set_inbound_key(&mut self, key: &[u8; KEY_LEN], reset_sequence: bool) {
+        self.inbound = Some(OpeningKey::new(key));
+        if reset_sequence {
+            self.inbound_sequence = 0;
+        }
+    }
+
+    /// Starts encrypting the packets sent to the client with the given key. If `reset_sequence`
+    /// is true, the sequence number of the next packet is 0.
+    pub fn set_outbound_key(&mut self, key: &[u8; KEY_LEN], reset_sequence: bool) {
+        self.outbound = Some(SealingKey::new(key));
+        if reset_sequence {
+            self.outbound_sequence = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PacketCodec, PacketError};
+
+    /// Passes the packets built by `sender` to `receiver`.
+    fn transfer(sender: &mut PacketCodec, receiver: &mut PacketCodec, payload: &[u8]) {
+        let packet = sender.encode(payload);
+        receiver.push(&packet);
+        assert_eq!(receiver.next_packet(), Ok(Some(payload.to_vec())));
+        assert_eq!(receiver.next_packet(), Ok(None));
+    }
+
+    #[test]
+    fn plain_text() {
+        let mut sender = PacketCodec::new();
+        let mut receiver = PacketCodec::new();
+        for len in 0..20 {
+            let packet = sender.encode(&vec![0xaa; len]);
+            assert_eq!(packet.len() % 8, 0);
+            assert!(usize::from(packet[4]) >= 4);
+            receiver.push(&packet);
+            assert_eq!(receiver.next_packet(), Ok(Some(vec![0xaa; len])));
+        }
+        assert_eq!(receiver.last_inbound_sequence(), 19);
+    }
+
+    #[test]
+    fn partial_data() {
+        let mut sender = PacketCodec::new();
+        let mut receiver = PacketCodec::new();
+        let packet = sender.encode(b"hello");
+        receiver.push(&packet[..7]);
+        assert_eq!(receiver.next_packet(), Ok(None));
+        receiver.push(&packet[7..]);
+        assert_eq!(receiver.next_packet(), Ok(Some(b"hello".to_vec())));
+    }
+
+    #[test]
+    fn encrypted() {
+        let key = [7; 64];
+        let mut sender = PacketCodec::new();
+        let mut receiver = PacketCodec::new();
+        transfer(&mut sender, &mut receiver, b"before");
+        sender.set_outbound_key(&key, false);
+        receiver.set_inbound_key(&key, false);
+        transfer(&mut sender, &mut receiver, b"after");
+        transfer(&mut sender, &mut receiver, &[0x55; 1000]);
+
+        let packet = sender.encode(b"after");
+        assert_eq!((packet.len() - 4 - 16) % 8, 0);
+        assert!(!packet.windows(5).any(|w| w == b"after"));
+    }
+
+    #[test]
+    fn reset_sequence() {
+        let key = [7; 64];
+        let mut sender = PacketCodec::new();
+        let mut receiver = PacketCodec::new();
+        transfer(&mut sender, &mut receiver, b"before");
+        transfer(&mut sender, &mut receiver, b"before");
+        sender.set_outbound_key(&key, true);
+        receiver.set_inbound_key(&key, true);
+        transfer(&mut sender, &mut receiver, b"after");
+        assert_eq!(receiver.last_inbound_sequence(), 0);
+    }
+
+    #[test]
+    fn tampered() {
+        let key = [7; 64];
+        let mut sender = PacketCodec::new();
+        let mut receiver = PacketCodec::new();
+        sender.set_outbound_key(&key, false);
+        receiver.set_inbound_key(&key, false);
+        let mut packet = sender.encode(b"hello");
+        packet[6] ^= 1;
+        receiver.push(&packet);
+        assert_eq!(receiver.next_packet(), Err(PacketError::BadTag));
+    }
+
+    #[test]
+    fn bad_length() {
+        let mut receiver = PacketCodec::new();
+        receiver.push(&[0xff, 0xff, 0xff, 0xf4]);
+        assert_eq!(receiver.next_packet(), Err(PacketError::BadLength));
+
+        let mut receiver = PacketCodec::new();
+        receiver.push(&[0, 0, 0, 13]);
+        assert_eq!(receiver.next_packet(), Err(PacketError::BadLength));
+    }
+
+    #[test]
+    fn bad_padding() {
+        let mut receiver = PacketCodec::new();
+        receiver.push(&[0, 0, 0, 12, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(receiver.next_packet(), Err(PacketError::BadPadding));
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Encoding of the data types used in SSH messages, as defined in section 5 of RFC 4251.
+
+use std::convert::TryFrom;
+
+/// Builds the payload of a message.
+#[derive(Debug, Default)]
+pub struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    /// Starts a message of the given type.
+    pub fn message(ty: u8) -> Self {
+        Writer { buffer: vec![ty] }
+    }
+
+    pub fn byte(mut self, value: u8) -> Self {
+        self.buffer.push(value);
+        self
+    }
+
+    pub fn boolean(self, value: bool) -> Self {
+        self.byte(if value { 1 } else { 0 })
+    }
+
+    pub fn uint32(mut self, value: u32) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Writes bytes, prefixed with their length.
+    pub fn string(self, value: &[u8]) -> Self {
+        let len = u32::try_from(value.len()).unwrap();
+        let mut this = self.uint32(len);
+        this.buffer.extend_from_slice(value);
+        this
+    }
+
+    /// Writes a comma-separated list of names.
+    pub fn name_list(self, names: &[&str]) -> Self {
+        self.string(names.join(",").as_bytes())
+    }
+
+    /// Writes an unsigned integer given in big endian.
+    pub fn mpint(self, value: &[u8]) -> Self {
+        let value = match value.iter().position(|b| *b != 0) {
+            Some(first) => &value[first..],
+            None => &[],
+        };
+
+        // A leading zero is added if the most significant bit is set, as the number would
+        // otherwise be negative.
+        if value.first().map_or(false, |b| *b & 0x80 != 0) {
+            let mut padded = Vec::with_capacity(value.len() + 1);
+            padded.push(0);
+            padded.extend_from_slice(value);
+            self.string(&padded)
+        } else {
+            self.string(value)
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Parses the payload of a message. All the methods return `None` if the payload is too short
+/// or malformed.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        Reader { remaining: payload }
+    }
+
+    pub fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.remaining.split_first()?;
+        self.remaining = rest;
+        Some(*first)
+    }
+
+    pub fn boolean(&mut self) -> Option<bool> {
+        Some(self.byte()? != 0)
+    }
+
+    pub fn uint32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads bytes prefixed with their length.
+    pub fn string(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.uint32()?).ok()?;
+        self.bytes(len)
+    }
+
+    /// Reads a comma-separated list of names.
+    pub fn name_list(&mut self) -> Option<Vec<&'a str>> {
+        let list = std::str::from_utf8(self.string()?).ok()?;
+        if list.is_empty() {
+            Some(Vec::new())
+        } else {
+            Some(list.split(',').collect())
+        }
+    }
+
+    /// Reads `len` bytes.
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.remaining.len() < len {
+            return None;
+        }
+
+        let (bytes, rest) = self.remaining.split_at(len);
+        self.remaining = rest;
+        Some(bytes)
+    }
+
+    /// Returns true if the whole payload has been read.
+    pub fn is_finished(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Reader, Writer};
+
+    #[test]
+    fn mpint_examples() {
+        // Examples from section 5 of RFC 4251.
+        let encode = |value: &[u8]| Writer::default().mpint(value).into_bytes();
+        assert_eq!(encode(&[]), [0, 0, 0, 0]);
+        assert_eq!(encode(&[0, 0]), [0, 0, 0, 0]);
+        assert_eq!(
+            encode(&[0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7]),
+            [0, 0, 0, 8, 0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7]
+        );
+        assert_eq!(encode(&[0x80]), [0, 0, 0, 2, 0, 0x80]);
+        assert_eq!(encode(&[0, 0, 0x80]), [0, 0, 0, 2, 0, 0x80]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let payload = Writer::message(5)
+            .boolean(true)
+            .uint32(0xdead_beef)
+            .string(b"hello")
+            .name_list(&["a", "b"])
+            .name_list(&[])
+            .into_bytes();
+
+        let mut reader = Reader::new(&payload);
+        assert_eq!(reader.byte(), Some(5));
+        assert_eq!(reader.boolean(), Some(true));
+        assert_eq!(reader.uint32(), Some(0xdead_beef));
+        assert_eq!(reader.string(), Some(&b"hello"[..]));
+        assert_eq!(reader.name_list(), Some(vec!["a", "b"]));
+        assert_eq!(reader.name_list(), Some(Vec::new()));
+        assert!(reader.is_finished());
+        assert_eq!(reader.byte(), None);
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(Reader::new(&[0, 0, 0]).uint32(), None);
+        assert_eq!(Reader::new(&[0, 0, 0, 4, 1, 2, 3]).string(), None);
+        assert_eq!(Reader::new(&[0xff, 0xff, 0xff, 0xff]).string(), None);
+        assert_eq!(Reader::new(&[0, 0, 0, 1, 0xff]).name_list(), None);
+    }
+}
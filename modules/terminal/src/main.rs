@@ -0,0 +1,99 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Terminal emulator drawing on the framebuffer.
+//!
+//! Implements the stdout and console interfaces by drawing characters on the framebuffer with a
+//! built-in bitmap font. Messages on the console interface can contain VT100 escape sequences,
+//! which are interpreted.
+//!
+//! This is an alternative to the VGA text mode console of the standalone kernel for machines
+//! where a framebuffer is available. Only one of them can run at a time, as they register the
+//! same interfaces.
+
+mod font;
+mod screen;
+mod terminal;
+
+use parity_scale_codec::DecodeAll;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let info = redshirt_framebuffer_interface::info().await;
+    let mut screen = screen::Screen::new(info);
+    let (columns, rows) = screen.size_in_cells();
+    let mut terminal = terminal::Terminal::new(columns, rows);
+
+    redshirt_interface_interface::register_interface(redshirt_stdout_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+    redshirt_interface_interface::register_interface(redshirt_console_interface::ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    // Clears the screen.
+    let _ = screen.update(&mut terminal).await;
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+
+        if msg.interface == redshirt_stdout_interface::ffi::INTERFACE {
+            match DecodeAll::decode_all(&msg.actual_data) {
+                Ok(redshirt_stdout_interface::ffi::StdoutMessage::Message(message)) => {
+                    terminal.write_raw(&message);
+                }
+                Err(_) => continue,
+            }
+        } else if msg.interface == redshirt_console_interface::ffi::INTERFACE {
+            match DecodeAll::decode_all(&msg.actual_data) {
+                Ok(redshirt_console_interface::ffi::ConsoleMessage::Write(message)) => {
+                    terminal.write(&message);
+                }
+                Ok(redshirt_console_interface::ffi::ConsoleMessage::ReadLine) => {
+                    // TODO: no keyboard driver yet
+                    if let Some(message_id) = msg.message_id {
+                        let response = redshirt_console_interface::ffi::ReadLineResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+                Ok(redshirt_console_interface::ffi::ConsoleMessage::Size) => {
+                    if let Some(message_id) = msg.message_id {
+                        let response = redshirt_console_interface::ffi::SizeResponse {
+                            columns: terminal.columns(),
+                            rows: terminal.rows(),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+                Err(_) => continue,
+            }
+        }
+
+        // TODO: messages are not processed while the screen is being updated; should instead
+        //       coalesce the writes that arrive in the meantime
+        let _ = screen.update(&mut terminal).await;
+    }
+}
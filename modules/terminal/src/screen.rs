@@ -0,0 +1,176 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Drawing of the terminal on the framebuffer.
+
+use crate::{font, terminal::Terminal};
+use futures::prelude::*;
+use redshirt_framebuffer_interface::{
+    ffi::{FramebufferInfo, PixelFormat, Rect},
+    ErrorPayload,
+};
+
+/// Width of a character on the screen, in pixels.
+const CELL_WIDTH: u32 = font::GLYPH_WIDTH;
+/// Height of a character on the screen, in pixels. Each line of the glyphs is drawn twice in
+/// order to obtain the proportions of a text mode console.
+const CELL_HEIGHT: u32 = font::GLYPH_HEIGHT * 2;
+
+/// Red, green and blue components of the 16 colors, in the same order as the VGA palette:
+/// black, red, green, yellow, blue, magenta, cyan, white, then their bright versions.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xaa, 0x00, 0x00),
+    (0x00, 0xaa, 0x00),
+    (0xaa, 0x55, 0x00),
+    (0x00, 0x00, 0xaa),
+    (0xaa, 0x00, 0xaa),
+    (0x00, 0xaa, 0xaa),
+    (0xaa, 0xaa, 0xaa),
+    (0x55, 0x55, 0x55),
+    (0xff, 0x55, 0x55),
+    (0x55, 0xff, 0x55),
+    (0xff, 0xff, 0x55),
+    (0x55, 0x55, 0xff),
+    (0xff, 0x55, 0xff),
+    (0x55, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// Copy of the content of the framebuffer.
+pub struct Screen {
+    info: FramebufferInfo,
+    /// Pixels of the whole screen, line by line, in the format of the framebuffer.
+    buffer: Vec<u8>,
+    /// Colors of [`PALETTE`] in the format of the framebuffer.
+    palette: [[u8; 4]; 16],
+}
+
+impl Screen {
+    /// Initializes a new black screen.
+    pub fn new(info: FramebufferInfo) -> Screen {
+        let bpp = info.format.bytes_per_pixel() as usize;
+        let buffer = vec![0; info.width as usize * info.height as usize * bpp];
+
+        let mut palette = [[0; 4]; 16];
+        for (encoded, color) in palette.iter_mut().zip(PALETTE.iter()) {
+            *encoded = encode_pixel(info.format, *color);
+        }
+
+        Screen {
+            info,
+            buffer,
+            palette,
+        }
+    }
+
+    /// Returns the number of columns and rows of characters that fit on the screen.
+    pub fn size_in_cells(&self) -> (u16, u16) {
+        let columns = (self.info.width / CELL_WIDTH)
+            .max(1)
+            .min(u32::from(u16::max_value()));
+        let rows = (self.info.height / CELL_HEIGHT)
+            .max(1)
+            .min(u32::from(u16::max_value()));
+        (columns as u16, rows as u16)
+    }
+
+    /// Draws the lines of `terminal` that have changed, and sends them to the framebuffer.
+    pub fn update(
+        &mut self,
+        terminal: &mut Terminal,
+    ) -> impl Future<Output = Result<(), ErrorPayload>> {
+        let (columns, rows) = self.size_in_cells();
+        let columns = columns.min(terminal.columns());
+        let rows = rows.min(terminal.rows());
+        let cursor = terminal.cursor();
+
+        let mut damage = Vec::new();
+        for y in terminal.take_dirty_lines() {
+            if y >= rows {
+                continue;
+            }
+
+            for x in 0..columns {
+                let inverted = cursor == Some((x, y));
+                self.draw_cell(terminal, x, y, inverted);
+            }
+
+            damage.push(Rect {
+                x: 0,
+                y: u32::from(y) * CELL_HEIGHT,
+                width: u32::from(columns) * CELL_WIDTH,
+                height: CELL_HEIGHT,
+            });
+        }
+
+        if damage.is_empty() {
+            return future::Either::Left(future::ok(()));
+        }
+
+        future::Either::Right(redshirt_framebuffer_interface::present_from_buffer(
+            &self.info,
+            &self.buffer,
+            &damage,
+        ))
+    }
+
+    /// Draws the character at the given position in the buffer. If `inverted` is true, the
+    /// foreground and background colors are swapped, which is how the cursor is displayed.
+    fn draw_cell(&mut self, terminal: &Terminal, x: u16, y: u16, inverted: bool) {
+        let cell = terminal.cell(x, y);
+        let (foreground, background) = if inverted {
+            (cell.background, cell.foreground)
+        } else {
+            (cell.foreground, cell.background)
+        };
+        let foreground = self.palette[usize::from(foreground & 0xf)];
+        let background = self.palette[usize::from(background & 0xf)];
+
+        let glyph = font::glyph(cell.chr);
+        let bpp = self.info.format.bytes_per_pixel() as usize;
+        let stride = self.info.width as usize * bpp;
+
+        for py in 0..CELL_HEIGHT {
+            let bits = glyph[(py * font::GLYPH_HEIGHT / CELL_HEIGHT) as usize];
+            let line_start = (u32::from(y) * CELL_HEIGHT + py) as usize * stride
+                + (u32::from(x) * CELL_WIDTH) as usize * bpp;
+
+            for px in 0..CELL_WIDTH {
+                let color = if bits & (1 << px) != 0 {
+                    &foreground
+                } else {
+                    &background
+                };
+                let start = line_start + px as usize * bpp;
+                self.buffer[start..start + bpp].copy_from_slice(&color[..bpp]);
+            }
+        }
+    }
+}
+
+/// Turns a color into the bytes of a pixel in the given format. Only the first
+/// [`PixelFormat::bytes_per_pixel`] bytes are meaningful.
+fn encode_pixel(format: PixelFormat, (r, g, b): (u8, u8, u8)) -> [u8; 4] {
+    match format {
+        PixelFormat::B8G8R8X8 => [b, g, r, 0],
+        PixelFormat::R8G8B8X8 => [r, g, b, 0],
+        PixelFormat::R5G6B5 => {
+            let value = (u16::from(r >> 3) << 11) | (u16::from(g >> 2) << 5) | u16::from(b >> 3);
+            let [low, high] = value.to_le_bytes();
+            [low, high, 0, 0]
+        }
+    }
+}
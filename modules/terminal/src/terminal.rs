@@ -0,0 +1,444 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State machine of the terminal.
+//!
+//! The [`Terminal`] holds a grid of characters and interprets the text written on it, including
+//! a subset of the VT100 escape sequences. It doesn't know anything about pixels; the grid is
+//! drawn by the [`Screen`](crate::screen::Screen).
+
+use std::{convert::TryFrom as _, mem};
+
+/// Color of the text when no color has been set. Light gray.
+pub const DEFAULT_FOREGROUND: u8 = 7;
+/// Color of the background when no color has been set. Black.
+pub const DEFAULT_BACKGROUND: u8 = 0;
+
+/// Number of columns between two tab stops.
+const TAB_WIDTH: u16 = 8;
+
+/// Character on the grid, with its colors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub chr: char,
+    /// Index of the color of the character within the 16 colors palette.
+    pub foreground: u8,
+    /// Index of the color of the background within the 16 colors palette.
+    pub background: u8,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            chr: ' ',
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+        }
+    }
+}
+
+/// Grid of characters and cursor.
+pub struct Terminal {
+    columns: u16,
+    rows: u16,
+    /// Content of the grid, line by line. Always contains `columns * rows` elements.
+    cells: Vec<Cell>,
+    cursor_x: u16,
+    cursor_y: u16,
+    /// Position saved with `ESC 7` or `CSI s`.
+    saved_cursor: (u16, u16),
+    /// If false, the cursor must not be drawn.
+    cursor_visible: bool,
+    /// Cursor as it was when [`Terminal::take_dirty_lines`] was last called.
+    drawn_cursor: Option<(u16, u16)>,
+    foreground: u8,
+    background: u8,
+    /// If true, the foreground color is made brighter.
+    bold: bool,
+    /// If true, the foreground and background colors are swapped.
+    inverse: bool,
+    /// For each line, whether it has been modified since the last call to
+    /// [`Terminal::take_dirty_lines`].
+    dirty: Vec<bool>,
+    /// Escape sequence currently being parsed.
+    escape: Escape,
+}
+
+/// State of the parsing of escape sequences.
+enum Escape {
+    /// Not in an escape sequence.
+    None,
+    /// After an `ESC` character.
+    Escape,
+    /// After `ESC [`.
+    Csi {
+        /// True if the sequence starts with `?`, indicating a private mode.
+        private: bool,
+        /// Parameters parsed so far. Contains at least one element, where `0` means that the
+        /// parameter was omitted.
+        params: Vec<u16>,
+    },
+}
+
+impl Terminal {
+    /// Initializes a new terminal with the given size. The grid is empty and the cursor is at
+    /// the top left.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `columns` or `rows` is 0.
+    ///
+    pub fn new(columns: u16, rows: u16) -> Terminal {
+        assert!(columns >= 1);
+        assert!(rows >= 1);
+
+        Terminal {
+            columns,
+            rows,
+            cells: vec![Cell::default(); usize::from(columns) * usize::from(rows)],
+            cursor_x: 0,
+            cursor_y: 0,
+            saved_cursor: (0, 0),
+            cursor_visible: true,
+            drawn_cursor: None,
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bold: false,
+            inverse: false,
+            dirty: vec![true; usize::from(rows)],
+            escape: Escape::None,
+        }
+    }
+
+    /// Returns the number of characters per line.
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    /// Returns the number of lines.
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// Returns the character at the given position.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the position is out of the grid.
+    ///
+    pub fn cell(&self, x: u16, y: u16) -> Cell {
+        assert!(x < self.columns);
+        assert!(y < self.rows);
+        self.cells[self.index(x, y)]
+    }
+
+    /// Returns the position of the cursor, or `None` if it is hidden.
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        if self.cursor_visible {
+            Some((self.cursor_x, self.cursor_y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the list of lines that have been modified since the last time this method has
+    /// been called, including the lines where the cursor has appeared or disappeared.
+    pub fn take_dirty_lines(&mut self) -> Vec<u16> {
+        let cursor = self.cursor();
+        if cursor != self.drawn_cursor {
+            for (_, y) in self.drawn_cursor.iter().chain(cursor.iter()) {
+                self.dirty[usize::from(*y)] = true;
+            }
+            self.drawn_cursor = cursor;
+        }
+
+        let dirty = mem::replace(&mut self.dirty, vec![false; usize::from(self.rows)]);
+        dirty
+            .into_iter()
+            .enumerate()
+            .filter(|(_, dirty)| *dirty)
+            .map(|(y, _)| u16::try_from(y).unwrap())
+            .collect()
+    }
+
+    /// Writes text on the terminal, ignoring escape sequences.
+    pub fn write_raw(&mut self, text: &str) {
+        for chr in text.chars() {
+            self.put_char(chr);
+        }
+    }
+
+    /// Writes text on the terminal, interpreting escape sequences.
+    pub fn write(&mut self, text: &str) {
+        for chr in text.chars() {
+            match (&mut self.escape, chr) {
+                (Escape::None, '\x1b') => self.escape = Escape::Escape,
+                (Escape::None, chr) => self.put_char(chr),
+                (Escape::Escape, '[') => {
+                    self.escape = Escape::Csi {
+                        private: false,
+                        params: vec![0],
+                    }
+                }
+                (Escape::Escape, '7') => {
+                    self.escape = Escape::None;
+                    self.saved_cursor = (self.cursor_x, self.cursor_y);
+                }
+                (Escape::Escape, '8') => {
+                    self.escape = Escape::None;
+                    self.restore_cursor();
+                }
+                (Escape::Escape, 'c') => {
+                    self.escape = Escape::None;
+                    self.reset();
+                }
+                // Other sequences are not supported.
+                (Escape::Escape, _) => self.escape = Escape::None,
+                (Escape::Csi { private, params }, '?') if *params == [0] => *private = true,
+                (Escape::Csi { params, .. }, ';') => params.push(0),
+                (Escape::Csi { params, .. }, '0'..='9') => {
+                    let last = params.last_mut().unwrap();
+                    let digit = chr.to_digit(10).unwrap() as u16;
+                    *last = last.saturating_mul(10).saturating_add(digit);
+                }
+                (Escape::Csi { .. }, chr) => {
+                    let (private, params) = match mem::replace(&mut self.escape, Escape::None) {
+                        Escape::Csi { private, params } => (private, params),
+                        _ => unreachable!(),
+                    };
+                    if private {
+                        self.apply_private_csi(&params, chr);
+                    } else {
+                        self.apply_csi(&params, chr);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies a CSI escape sequence. `params` always contains at least one element, where `0`
+    /// means that the parameter was omitted.
+    fn apply_csi(&mut self, params: &[u16], command: char) {
+        let count = params[0].max(1);
+
+        match command {
+            'A' => self.cursor_y = self.cursor_y.saturating_sub(count),
+            'B' => self.cursor_y = self.cursor_y.saturating_add(count).min(self.rows - 1),
+            'C' => self.cursor_x = self.cursor_x.saturating_add(count).min(self.columns - 1),
+            'D' => self.cursor_x = self.cursor_x.saturating_sub(count),
+            'E' => {
+                self.cursor_x = 0;
+                self.cursor_y = self.cursor_y.saturating_add(count).min(self.rows - 1);
+            }
+            'F' => {
+                self.cursor_x = 0;
+                self.cursor_y = self.cursor_y.saturating_sub(count);
+            }
+            'G' => self.cursor_x = count.min(self.columns) - 1,
+            'd' => self.cursor_y = count.min(self.rows) - 1,
+            'H' | 'f' => {
+                let row = params[0].max(1).min(self.rows) - 1;
+                let col = params.get(1).cloned().unwrap_or(0).max(1).min(self.columns) - 1;
+                self.cursor_y = row;
+                self.cursor_x = col;
+            }
+            'J' => match params[0] {
+                0 => {
+                    self.erase(self.cursor_y, self.cursor_x, self.columns);
+                    for y in self.cursor_y + 1..self.rows {
+                        self.erase(y, 0, self.columns);
+                    }
+                }
+                1 => {
+                    for y in 0..self.cursor_y {
+                        self.erase(y, 0, self.columns);
+                    }
+                    self.erase(self.cursor_y, 0, self.cursor_x + 1);
+                }
+                2 | 3 => {
+                    for y in 0..self.rows {
+                        self.erase(y, 0, self.columns);
+                    }
+                }
+                _ => {}
+            },
+            'K' => match params[0] {
+                0 => self.erase(self.cursor_y, self.cursor_x, self.columns),
+                1 => self.erase(self.cursor_y, 0, self.cursor_x + 1),
+                2 => self.erase(self.cursor_y, 0, self.columns),
+                _ => {}
+            },
+            'S' => {
+                for _ in 0..count.min(self.rows) {
+                    self.scroll_up();
+                }
+            }
+            's' => self.saved_cursor = (self.cursor_x, self.cursor_y),
+            'u' => self.restore_cursor(),
+            'm' => {
+                for param in params {
+                    match *param {
+                        0 => {
+                            self.foreground = DEFAULT_FOREGROUND;
+                            self.background = DEFAULT_BACKGROUND;
+                            self.bold = false;
+                            self.inverse = false;
+                        }
+                        1 => self.bold = true,
+                        7 => self.inverse = true,
+                        22 => self.bold = false,
+                        27 => self.inverse = false,
+                        n @ 30..=37 => self.foreground = (n - 30) as u8,
+                        39 => self.foreground = DEFAULT_FOREGROUND,
+                        n @ 40..=47 => self.background = (n - 40) as u8,
+                        49 => self.background = DEFAULT_BACKGROUND,
+                        n @ 90..=97 => self.foreground = (n - 90) as u8 | 0x8,
+                        n @ 100..=107 => self.background = (n - 100) as u8 | 0x8,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies a CSI escape sequence starting with `?`.
+    fn apply_private_csi(&mut self, params: &[u16], command: char) {
+        match (params[0], command) {
+            (25, 'h') => self.cursor_visible = true,
+            (25, 'l') => self.cursor_visible = false,
+            _ => {}
+        }
+    }
+
+    /// Moves the cursor to the position saved with `ESC 7` or `CSI s`.
+    fn restore_cursor(&mut self) {
+        let (x, y) = self.saved_cursor;
+        self.cursor_x = x.min(self.columns - 1);
+        self.cursor_y = y.min(self.rows - 1);
+    }
+
+    /// Puts the terminal back in its initial state and clears the grid.
+    fn reset(&mut self) {
+        *self = Terminal::new(self.columns, self.rows);
+    }
+
+    /// Replaces the characters between `x_start` (inclusive) and `x_end` (exclusive) on line `y`
+    /// with spaces.
+    fn erase(&mut self, y: u16, x_start: u16, x_end: u16) {
+        let x_end = x_end.min(self.columns);
+        if x_start >= x_end {
+            return;
+        }
+
+        let blank = Cell {
+            chr: ' ',
+            foreground: self.foreground,
+            background: self.background,
+        };
+
+        let start = self.index(x_start, y);
+        let end = self.index(x_end - 1, y) + 1;
+        for cell in &mut self.cells[start..end] {
+            *cell = blank;
+        }
+        self.dirty[usize::from(y)] = true;
+    }
+
+    /// Writes a single character at the cursor position and advances the cursor.
+    fn put_char(&mut self, chr: char) {
+        match chr {
+            '\n' => {
+                self.cursor_x = 0;
+                self.line_feed();
+                return;
+            }
+            '\r' => {
+                self.cursor_x = 0;
+                return;
+            }
+            '\t' => {
+                let next_stop = (self.cursor_x / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor_x = next_stop.min(self.columns - 1);
+                return;
+            }
+            '\x08' => {
+                self.cursor_x = self.cursor_x.saturating_sub(1);
+                return;
+            }
+            chr if chr.is_control() => return,
+            _ => {}
+        }
+
+        let (foreground, background) = {
+            let foreground = if self.bold {
+                self.foreground | 0x8
+            } else {
+                self.foreground
+            };
+            if self.inverse {
+                (self.background, foreground)
+            } else {
+                (foreground, self.background)
+            }
+        };
+
+        let index = self.index(self.cursor_x, self.cursor_y);
+        self.cells[index] = Cell {
+            chr,
+            foreground,
+            background,
+        };
+        self.dirty[usize::from(self.cursor_y)] = true;
+
+        self.cursor_x += 1;
+        if self.cursor_x == self.columns {
+            self.cursor_x = 0;
+            self.line_feed();
+        }
+    }
+
+    /// Moves the cursor one line down, scrolling the grid if it is on the last line.
+    fn line_feed(&mut self) {
+        if self.cursor_y + 1 == self.rows {
+            self.scroll_up();
+        } else {
+            self.cursor_y += 1;
+        }
+    }
+
+    /// Moves all the lines one line up. The first line disappears and the last line is cleared.
+    fn scroll_up(&mut self) {
+        // TODO: the whole screen is then redrawn; the framebuffer interface could support
+        //       copying regions instead
+        let columns = usize::from(self.columns);
+        self.cells.drain(..columns);
+        let blank = Cell {
+            chr: ' ',
+            foreground: self.foreground,
+            background: self.background,
+        };
+        self.cells.extend((0..columns).map(|_| blank));
+        for dirty in &mut self.dirty {
+            *dirty = true;
+        }
+    }
+
+    fn index(&self, x: u16, y: u16) -> usize {
+        debug_assert!(x < self.columns);
+        debug_assert!(y < self.rows);
+        usize::from(y) * usize::from(self.columns) + usize::from(x)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implements the loader interface by downloading modules from a TFTP server, as found on
+//! networks set up for PXE booting.
+//!
+//! Modules are looked for on the server at the address passed through the `TFTP_SERVER`
+//! environment variable, or `10.0.2.2:69` by default, which is where QEMU's user-mode
+//! networking exposes its built-in TFTP server. A module whose hash is `h` is fetched from the
+//! file named `<h>.wasm`, where `<h>` is written in lowercase hexadecimal. The content of the
+//! file is checked against the hash before being returned.
+//!
+//! Modules passed through `Store` messages are kept in memory and are served without asking
+//! the server.
+
+mod tftp;
+
+use futures::{prelude::*, stream::FuturesUnordered};
+use parity_scale_codec::DecodeAll;
+use redshirt_loader_interface::ffi;
+use redshirt_syscalls_interface::InterfaceOrDestroyed;
+use sha2::Digest as _;
+use std::{collections::HashMap, fmt::Write as _, net::SocketAddr};
+
+/// Address of the server if the `TFTP_SERVER` environment variable isn't set.
+const DEFAULT_SERVER: &str = "10.0.2.2:69";
+/// Maximum size of a module, in bytes. Larger files are rejected.
+const MAX_MODULE_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let server = redshirt_arguments_interface::var("TFTP_SERVER")
+        .await
+        .unwrap_or_else(|| DEFAULT_SERVER.to_owned());
+    let server: SocketAddr = match server.parse() {
+        Ok(a) => a,
+        Err(_) => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut stored = HashMap::<[u8; 32], Vec<u8>>::new();
+    let mut fetches = FuturesUnordered::new();
+
+    loop {
+        let msg = {
+            let next_interface = redshirt_syscalls_interface::next_interface_message();
+            let next_fetch = async {
+                if fetches.is_empty() {
+                    future::pending().await
+                } else {
+                    fetches.next().await.unwrap()
+                }
+            };
+            futures::pin_mut!(next_fetch);
+            match future::select(next_interface, next_fetch).await {
+                future::Either::Left((InterfaceOrDestroyed::Interface(m), _)) => m,
+                future::Either::Left((_, _)) => continue,
+                future::Either::Right(((message_id, result), _)) => {
+                    let rp = ffi::LoadResponse { result };
+                    redshirt_syscalls_interface::emit_answer(message_id, &rp);
+                    continue;
+                }
+            }
+        };
+
+        assert_eq!(msg.interface, ffi::INTERFACE);
+        let msg_data = match ffi::LoaderMessage::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match msg_data {
+            ffi::LoaderMessage::Load(hash) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                if let Some(data) = stored.get(&hash) {
+                    let rp = ffi::LoadResponse {
+                        result: Ok(data.clone()),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &rp);
+                    continue;
+                }
+
+                fetches.push(async move { (message_id, load(server, &hash).await) });
+            }
+            ffi::LoaderMessage::Store(data) => {
+                let hash: [u8; 32] = sha2::Sha256::digest(&data).into();
+                stored.insert(hash, data);
+                if let Some(message_id) = msg.message_id {
+                    let rp = ffi::StoreResponse { result: Ok(hash) };
+                    redshirt_syscalls_interface::emit_answer(message_id, &rp);
+                }
+            }
+        }
+    }
+}
+
+/// Downloads the module with the given hash from the server and verifies its content.
+async fn load(server: SocketAddr, hash: &[u8; 32]) -> Result<Vec<u8>, ()> {
+    let mut file_name = String::with_capacity(hash.len() * 2 + 5);
+    for byte in hash.iter() {
+        write!(file_name, "{:02x}", byte).unwrap();
+    }
+    file_name.push_str(".wasm");
+
+    let data = tftp::fetch(server, &file_name, MAX_MODULE_SIZE).await?;
+    let actual_hash: [u8; 32] = sha2::Sha256::digest(&data).into();
+    if actual_hash != *hash {
+        return Err(());
+    }
+
+    Ok(data)
+}
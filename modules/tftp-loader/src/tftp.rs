@@ -0,0 +1,222 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Client side of the Trivial File Transfer Protocol, as defined in RFC 1350, with the block
+//! size option of RFC 2348.
+
+use futures::prelude::*;
+use redshirt_udp_interface::UdpSocket;
+use std::{net::SocketAddr, time::Duration};
+
+const OPCODE_RRQ: u16 = 1;
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ACK: u16 = 4;
+const OPCODE_ERROR: u16 = 5;
+const OPCODE_OACK: u16 = 6;
+
+/// Error code sent to a peer that isn't the one we are transferring with.
+const ERROR_UNKNOWN_TID: u16 = 5;
+
+/// Size of the blocks if the server doesn't support the block size option.
+const DEFAULT_BLOCK_SIZE: usize = 512;
+/// Size of the blocks that we ask for. Chosen so that a block fits in an Ethernet frame.
+const REQUESTED_BLOCK_SIZE: usize = 1428;
+
+/// Time after which the last packet is sent again if the server hasn't answered.
+const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Number of retransmissions after which the transfer is abandoned.
+const MAX_RETRANSMITS: u32 = 5;
+
+/// Downloads the file with the given name from the server at the given address.
+///
+/// Fails if the file is larger than `max_len` bytes.
+pub async fn fetch(server: SocketAddr, file_name: &str, max_len: usize) -> Result<Vec<u8>, ()> {
+    let local_addr: SocketAddr = match server {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    };
+    let socket = UdpSocket::bind(&local_addr).await.map_err(|_| ())?;
+    let mut incoming = socket.incoming();
+
+    let requested_block_size = REQUESTED_BLOCK_SIZE.to_string();
+    let mut request = Vec::new();
+    request.extend_from_slice(&OPCODE_RRQ.to_be_bytes());
+    for field in &[file_name, "octet", "blksize", requested_block_size.as_str()] {
+        request.extend_from_slice(field.as_bytes());
+        request.push(0);
+    }
+
+    // The server answers from a new port, which identifies the transfer.
+    let mut peer: Option<SocketAddr> = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut next_block: u16 = 1;
+    let mut data = Vec::new();
+
+    let mut last_sent = request;
+    socket
+        .send_to(last_sent.clone(), &server)
+        .await
+        .map_err(|_| ())?;
+    let mut retransmits = 0;
+
+    loop {
+        let datagram = {
+            let timeout = Box::pin(redshirt_time_interface::monotonic_wait(RETRANSMIT_TIMEOUT));
+            match future::select(incoming.next(), timeout).await {
+                future::Either::Left((Some(datagram), _)) => datagram,
+                future::Either::Left((None, _)) => return Err(()),
+                future::Either::Right(((), _)) => {
+                    retransmits += 1;
+                    if retransmits > MAX_RETRANSMITS {
+                        return Err(());
+                    }
+                    let target = peer.unwrap_or(server);
+                    socket
+                        .send_to(last_sent.clone(), &target)
+                        .await
+                        .map_err(|_| ())?;
+                    continue;
+                }
+            }
+        };
+
+        let (from, packet) = datagram;
+        if packet.len() < 4 {
+            continue;
+        }
+
+        match peer {
+            Some(peer) if peer != from => {
+                let _ = socket.send_to(error_packet(ERROR_UNKNOWN_TID), &from).await;
+                continue;
+            }
+            Some(_) => {}
+            None if from.ip() == server.ip() => peer = Some(from),
+            None => continue,
+        }
+
+        let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+        match opcode {
+            OPCODE_OACK if next_block == 1 => {
+                block_size = parse_block_size(&packet[2..]).ok_or(())?;
+                last_sent = ack_packet(0);
+            }
+            OPCODE_DATA => {
+                let block = u16::from_be_bytes([packet[2], packet[3]]);
+                let payload = &packet[4..];
+                if block == next_block {
+                    if data.len() + payload.len() > max_len {
+                        return Err(());
+                    }
+                    data.extend_from_slice(payload);
+                    last_sent = ack_packet(block);
+                    next_block = next_block.wrapping_add(1);
+
+                    // A block smaller than the block size indicates the end of the file.
+                    if payload.len() < block_size {
+                        let _ = socket.send_to(last_sent, &from).await;
+                        return Ok(data);
+                    }
+                } else if block != next_block.wrapping_sub(1) {
+                    continue;
+                }
+                // If the block is the previous one, our acknowledgement has been lost and we
+                // send it again.
+            }
+            OPCODE_ERROR => return Err(()),
+            _ => continue,
+        }
+
+        retransmits = 0;
+        socket
+            .send_to(last_sent.clone(), &from)
+            .await
+            .map_err(|_| ())?;
+    }
+}
+
+/// Extracts the block size from the options of an OACK packet.
+///
+/// Returns `None` if the server has acknowledged a block size that we haven't asked for.
+fn parse_block_size(options: &[u8]) -> Option<usize> {
+    let mut fields = options
+        .split(|b| *b == 0)
+        .filter(|f| !f.is_empty())
+        .map(|f| std::str::from_utf8(f).ok());
+
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+        if name?.eq_ignore_ascii_case("blksize") {
+            block_size = value?.parse().ok()?;
+            if block_size == 0 || block_size > REQUESTED_BLOCK_SIZE {
+                return None;
+            }
+        }
+    }
+
+    Some(block_size)
+}
+
+fn ack_packet(block: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4);
+    packet.extend_from_slice(&OPCODE_ACK.to_be_bytes());
+    packet.extend_from_slice(&block.to_be_bytes());
+    packet
+}
+
+fn error_packet(code: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5);
+    packet.extend_from_slice(&OPCODE_ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.push(0);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ack_packet, error_packet, parse_block_size, DEFAULT_BLOCK_SIZE};
+
+    #[test]
+    fn block_size_acknowledged() {
+        assert_eq!(parse_block_size(b"blksize\x001024\x00"), Some(1024));
+        assert_eq!(
+            parse_block_size(b"tsize\x00500\x00BLKSIZE\x001428\x00"),
+            Some(1428)
+        );
+    }
+
+    #[test]
+    fn block_size_missing() {
+        assert_eq!(parse_block_size(b""), Some(DEFAULT_BLOCK_SIZE));
+        assert_eq!(
+            parse_block_size(b"tsize\x00500\x00"),
+            Some(DEFAULT_BLOCK_SIZE)
+        );
+    }
+
+    #[test]
+    fn block_size_invalid() {
+        assert_eq!(parse_block_size(b"blksize\x000\x00"), None);
+        assert_eq!(parse_block_size(b"blksize\x001429\x00"), None);
+        assert_eq!(parse_block_size(b"blksize\x00abc\x00"), None);
+        assert_eq!(parse_block_size(b"blksize\x00\xff\x00"), None);
+    }
+
+    #[test]
+    fn packets() {
+        assert_eq!(ack_packet(0x1234), [0, 4, 0x12, 0x34]);
+        assert_eq!(error_packet(5), [0, 5, 0, 5, 0]);
+    }
+}
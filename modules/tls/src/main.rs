@@ -0,0 +1,161 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! TLS client sessions, based on `rustls`.
+//!
+//! Registers the TLS interface. Each session is driven by a separate task that owns the TCP
+//! socket it has been given, and that receives the messages concerning the session through a
+//! channel. Certificates are verified against the root certificates of the `webpki-roots`
+//! crate.
+
+mod session;
+mod verifier;
+
+use futures::{channel::mpsc, prelude::*};
+use parity_scale_codec::DecodeAll;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, Pid};
+use redshirt_tls_interface::ffi;
+use std::{collections::HashMap, pin::Pin};
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    // Open sessions, with the process that has opened them and the channel to their task.
+    // Destroying the sender closes the session.
+    let mut sessions: HashMap<u32, (Pid, mpsc::UnboundedSender<session::Command>)> = HashMap::new();
+    let mut next_session_id: u32 = 0;
+
+    // Tasks driving the sessions. Each task yields the identifier of its session when it ends.
+    let mut tasks = stream::FuturesUnordered::<Pin<Box<dyn Future<Output = u32>>>>::new();
+    // `FuturesUnordered` yields `None` when empty, which we don't want.
+    tasks.push(Box::pin(future::pending()));
+
+    loop {
+        let msg = match future::select(
+            redshirt_syscalls_interface::next_interface_message(),
+            tasks.next(),
+        )
+        .await
+        {
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m),
+                _,
+            )) => m,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg),
+                _,
+            )) => {
+                sessions.retain(|_, (owner, _)| *owner != msg.pid);
+                continue;
+            }
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg),
+                _,
+            )) => {
+                for (_, commands) in sessions.values() {
+                    let _ = commands.unbounded_send(session::Command::Cancelled(msg.message_id));
+                }
+                continue;
+            }
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_),
+                _,
+            )) => continue,
+            future::Either::Right((session_id, _)) => {
+                sessions.remove(&session_id.unwrap());
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::TlsMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        // Returns the channel to the task of a session, if it belongs to the emitter.
+        let session_commands = |session_id: u32| {
+            sessions
+                .get(&session_id)
+                .filter(|(owner, _)| *owner == msg.emitter_pid)
+                .map(|(_, commands)| commands.clone())
+        };
+
+        match message {
+            ffi::TlsMessage::Connect(connect) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                let session_id = next_session_id;
+                next_session_id = next_session_id.wrapping_add(1);
+                let (tx, rx) = mpsc::unbounded();
+                sessions.insert(session_id, (msg.emitter_pid, tx));
+                tasks.push(Box::pin(
+                    session::run(session_id, connect, message_id, rx).map(move |()| session_id),
+                ));
+            }
+            ffi::TlsMessage::Read(session_id) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                match session_commands(session_id) {
+                    Some(commands) => {
+                        let _ = commands.unbounded_send(session::Command::Read(message_id));
+                    }
+                    None => {
+                        let response = ffi::TlsReadResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+            }
+            ffi::TlsMessage::Write(write) => match session_commands(write.session_id) {
+                Some(commands) => {
+                    let command = session::Command::Write(msg.message_id, write.data);
+                    let _ = commands.unbounded_send(command);
+                }
+                None => {
+                    if let Some(message_id) = msg.message_id {
+                        let response = ffi::TlsWriteResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+            },
+            ffi::TlsMessage::Close(session_id) => {
+                if session_commands(session_id).is_some() {
+                    sessions.remove(&session_id);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Task driving a single TLS session.
+
+use crate::verifier::Verifier;
+
+use futures::{channel::mpsc, prelude::*};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId};
+use redshirt_tcp_interface::ffi as tcp_ffi;
+use redshirt_tls_interface::ffi;
+use rustls::Session as _;
+use std::{convert::TryFrom as _, io::Read as _, io::Write as _, pin::Pin, sync::Arc};
+
+/// Message concerning a session, sent by the main task.
+pub enum Command {
+    /// A [`ffi::TlsMessage::Read`] has been received.
+    Read(MessageId),
+    /// A [`ffi::TlsMessage::Write`] has been received.
+    Write(Option<MessageId>, Vec<u8>),
+    /// The given message has been cancelled by its emitter.
+    Cancelled(MessageId),
+}
+
+/// Performs the handshake, answers `connect_message`, then processes the commands until the
+/// sender of `commands` is destroyed or the connection fails.
+pub async fn run(
+    session_id: u32,
+    connect: ffi::TlsConnect,
+    connect_message: MessageId,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    let tcp_socket = connect.tcp_socket;
+    let mut connection = match Connection::handshake(connect).await {
+        Ok(c) => {
+            let response = ffi::TlsConnectResponse {
+                result: Ok(session_id),
+            };
+            redshirt_syscalls_interface::emit_answer(connect_message, &response);
+            c
+        }
+        Err(err) => {
+            let response = ffi::TlsConnectResponse { result: Err(err) };
+            redshirt_syscalls_interface::emit_answer(connect_message, &response);
+            tcp_close(tcp_socket);
+            return;
+        }
+    };
+
+    // Read message waiting for data.
+    let mut pending_read: Option<MessageId> = None;
+    // Read of the TCP socket in progress. Only started when a read message is pending.
+    let mut tcp_read_in_progress: Option<
+        Pin<Box<dyn Future<Output = Result<Vec<u8>, ErrorPayload>>>>,
+    > = None;
+
+    loop {
+        if let Some(message_id) = pending_read {
+            let data = connection.plaintext();
+            if !data.is_empty() || connection.remote_closed {
+                let response = ffi::TlsReadResponse { result: Ok(data) };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+                pending_read = None;
+            } else if tcp_read_in_progress.is_none() {
+                tcp_read_in_progress = Some(Box::pin(tcp_read(tcp_socket)));
+            }
+        }
+
+        let event = match tcp_read_in_progress.as_mut() {
+            Some(tcp_read) => match future::select(commands.next(), tcp_read).await {
+                future::Either::Left((command, _)) => future::Either::Left(command),
+                future::Either::Right((data, _)) => future::Either::Right(data),
+            },
+            None => future::Either::Left(commands.next().await),
+        };
+
+        match event {
+            // The session has been closed.
+            future::Either::Left(None) => break,
+            future::Either::Left(Some(Command::Read(message_id))) => {
+                if pending_read.is_some() {
+                    let response = ffi::TlsReadResponse {
+                        result: Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("a read is already in progress")),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                } else {
+                    pending_read = Some(message_id);
+                }
+            }
+            future::Either::Left(Some(Command::Write(message_id, data))) => {
+                let result = connection.write(&data).await;
+                let failed = result.is_err();
+                if let Some(message_id) = message_id {
+                    let response = ffi::TlsWriteResponse { result };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+                if failed {
+                    break;
+                }
+            }
+            future::Either::Left(Some(Command::Cancelled(message_id))) => {
+                if pending_read == Some(message_id) {
+                    pending_read = None;
+                }
+            }
+            future::Either::Right(data) => {
+                tcp_read_in_progress = None;
+                let result = match data {
+                    Ok(data) => connection.process(&data),
+                    Err(err) => Err(err),
+                };
+                let result = match result {
+                    Ok(()) => connection.flush().await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    if let Some(message_id) = pending_read.take() {
+                        let response = ffi::TlsReadResponse { result: Err(err) };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    connection.session.send_close_notify();
+    let _ = connection.flush().await;
+    tcp_close(tcp_socket);
+}
+
+/// TLS session over a TCP socket.
+struct Connection {
+    session: rustls::ClientSession,
+    tcp_socket: u32,
+    /// True if the remote has closed the TCP connection.
+    remote_closed: bool,
+}
+
+impl Connection {
+    /// Performs a client handshake.
+    async fn handshake(connect: ffi::TlsConnect) -> Result<Connection, ErrorPayload> {
+        let dns_name =
+            webpki::DNSNameRef::try_from_ascii_str(&connect.server_name).map_err(|_| {
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid server name")
+            })?;
+
+        let now = redshirt_time_interface::system_clock().await;
+        let mut config = rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(Verifier::new(
+                u64::try_from(now / 1_000_000_000).unwrap(),
+            )));
+
+        let mut connection = Connection {
+            session: rustls::ClientSession::new(&Arc::new(config), dns_name),
+            tcp_socket: connect.tcp_socket,
+            remote_closed: false,
+        };
+
+        while connection.session.is_handshaking() {
+            connection.flush().await?;
+            if !connection.session.is_handshaking() {
+                break;
+            }
+
+            let data = tcp_read(connection.tcp_socket).await?;
+            if data.is_empty() {
+                return Err(ErrorPayload::new(ErrorClass::IO)
+                    .with_message("connection closed during the handshake"));
+            }
+            connection.process(&data)?;
+        }
+
+        connection.flush().await?;
+        Ok(connection)
+    }
+
+    /// Encrypts and sends data.
+    async fn write(&mut self, data: &[u8]) -> Result<(), ErrorPayload> {
+        self.session
+            .write_all(data)
+            .map_err(|err| ErrorPayload::new(ErrorClass::IO).with_message(err.to_string()))?;
+        self.flush().await
+    }
+
+    /// Sends the data that `rustls` wants to send over the TCP connection.
+    async fn flush(&mut self) -> Result<(), ErrorPayload> {
+        while self.session.wants_write() {
+            let mut buffer = Vec::new();
+            self.session.write_tls(&mut buffer).unwrap();
+            tcp_write(self.tcp_socket, buffer).await?;
+        }
+        Ok(())
+    }
+
+    /// Processes data received on the TCP connection.
+    fn process(&mut self, mut data: &[u8]) -> Result<(), ErrorPayload> {
+        if data.is_empty() {
+            self.remote_closed = true;
+            return Ok(());
+        }
+
+        while !data.is_empty() {
+            self.session.read_tls(&mut data).unwrap();
+            self.session.process_new_packets().map_err(tls_error)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the decrypted data that hasn't been read yet.
+    fn plaintext(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        // An error is returned if the remote has sent a closure alert, in which case the data
+        // read before the alert is still in `out`.
+        if self.session.read_to_end(&mut out).is_err() {
+            self.remote_closed = true;
+        }
+        out
+    }
+}
+
+/// Converts an error returned by `rustls`.
+fn tls_error(err: rustls::TLSError) -> ErrorPayload {
+    let class = match err {
+        rustls::TLSError::WebPKIError(_) | rustls::TLSError::NoCertificatesPresented => {
+            ErrorClass::PERMISSION_DENIED
+        }
+        _ => ErrorClass::OTHER,
+    };
+    ErrorPayload::new(class).with_message(err.to_string())
+}
+
+fn tcp_read(socket_id: u32) -> impl Future<Output = Result<Vec<u8>, ErrorPayload>> {
+    let response = unsafe {
+        let msg = tcp_ffi::TcpMessage::Read(tcp_ffi::TcpRead { socket_id });
+        redshirt_syscalls_interface::emit_message_with_response(&tcp_ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: tcp_ffi::TcpReadResponse| {
+        rep.result
+            .map_err(|()| ErrorPayload::new(ErrorClass::IO).with_message("TCP read failed"))
+    })
+}
+
+fn tcp_write(socket_id: u32, data: Vec<u8>) -> impl Future<Output = Result<(), ErrorPayload>> {
+    let response = unsafe {
+        let msg = tcp_ffi::TcpMessage::Write(tcp_ffi::TcpWrite { socket_id, data });
+        redshirt_syscalls_interface::emit_message_with_response(&tcp_ffi::INTERFACE, msg).unwrap()
+    };
+    response.map(|rep: tcp_ffi::TcpWriteResponse| {
+        rep.result
+            .map_err(|()| ErrorPayload::new(ErrorClass::IO).with_message("TCP write failed"))
+    })
+}
+
+fn tcp_close(socket_id: u32) {
+    unsafe {
+        let msg = tcp_ffi::TcpMessage::Close(tcp_ffi::TcpClose { socket_id });
+        let _ =
+            redshirt_syscalls_interface::emit_message_without_response(&tcp_ffi::INTERFACE, msg);
+    }
+}
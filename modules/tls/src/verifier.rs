@@ -0,0 +1,87 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Verification of the certificates of servers.
+
+use rustls::{Certificate, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError};
+
+/// Signature algorithms accepted in certificates. Same list as the default verifier of
+/// `rustls`.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Verifies certificates the same way as the default verifier of `rustls`, except that the
+/// current time is passed explicitly. The default verifier uses the system clock of the
+/// standard library, which isn't available.
+pub struct Verifier {
+    now: webpki::Time,
+}
+
+impl Verifier {
+    /// Builds a verifier that considers that the current time is the given number of seconds
+    /// since the Epoch.
+    pub fn new(seconds_since_epoch: u64) -> Self {
+        Verifier {
+            now: webpki::Time::from_seconds_since_unix_epoch(seconds_since_epoch),
+        }
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let (end_entity, intermediates) = presented_certs
+            .split_first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+        let cert = webpki::EndEntityCert::from(&end_entity.0).map_err(TLSError::WebPKIError)?;
+        let intermediates = intermediates
+            .iter()
+            .map(|c| c.0.as_ref())
+            .collect::<Vec<_>>();
+        let trust_anchors = roots
+            .roots
+            .iter()
+            .map(|r| r.to_trust_anchor())
+            .collect::<Vec<_>>();
+
+        cert.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TLSServerTrustAnchors(&trust_anchors),
+            &intermediates,
+            self.now,
+        )
+        .map_err(TLSError::WebPKIError)?;
+        cert.verify_is_valid_for_dns_name(dns_name)
+            .map_err(TLSError::WebPKIError)?;
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Virtual filesystem.
+//!
+//! Implements the filesystem interface by routing requests to the providers that have mounted
+//! themselves at a prefix of the requested path. When mount points are nested, the longest
+//! prefix wins.
+
+use parity_scale_codec::DecodeAll;
+use redshirt_filesystem_interface::ffi;
+use redshirt_syscalls_interface::{Decode, Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::collections::HashMap;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() -> ! {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    let mut vfs = Vfs {
+        mounts: Vec::new(),
+        handles: HashMap::new(),
+        next_handle: 1,
+    };
+
+    // TODO: requests are processed one by one, and a slow provider delays everyone
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                vfs.process_destroyed(msg.pid);
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::FilesystemMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        vfs.handle(msg.emitter_pid, msg.message_id, message).await;
+    }
+}
+
+struct Vfs {
+    /// List of mount points. Prefixes are normalized, as returned by [`normalize`].
+    mounts: Vec<(String, Pid)>,
+    /// Files currently open, indexed by the handle given to the process that opened them.
+    handles: HashMap<u64, OpenFile>,
+    /// Handle to assign to the next opened file.
+    next_handle: u64,
+}
+
+struct OpenFile {
+    /// Process that has opened the file. Other processes can't use the handle.
+    owner: Pid,
+    /// Provider that holds the file.
+    provider: Pid,
+    /// Handle of the file, as assigned by the provider.
+    provider_handle: u64,
+}
+
+impl Vfs {
+    async fn handle(
+        &mut self,
+        emitter: Pid,
+        message_id: Option<MessageId>,
+        message: ffi::FilesystemMessage,
+    ) {
+        match message {
+            ffi::FilesystemMessage::Open(open) => {
+                let result = match self.resolve(&open.path) {
+                    Ok((provider, path)) => {
+                        let request = ffi::FilesystemMessage::Open(ffi::Open { path, ..open });
+                        let rep: Result<ffi::OpenResponse, _> = forward(provider, request).await;
+                        match rep.and_then(|rep| rep.result) {
+                            Ok(provider_handle) => {
+                                let handle = self.next_handle;
+                                self.next_handle += 1;
+                                self.handles.insert(
+                                    handle,
+                                    OpenFile {
+                                        owner: emitter,
+                                        provider,
+                                        provider_handle,
+                                    },
+                                );
+                                Ok(handle)
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(message_id, ffi::OpenResponse { result });
+            }
+            ffi::FilesystemMessage::Read(read) => {
+                let result = match self.open_file(emitter, read.handle) {
+                    Ok((provider, handle)) => {
+                        let request = ffi::FilesystemMessage::Read(ffi::Read { handle, ..read });
+                        forward(provider, request)
+                            .await
+                            .and_then(|rep: ffi::ReadResponse| rep.result)
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(message_id, ffi::ReadResponse { result });
+            }
+            ffi::FilesystemMessage::Write(write) => {
+                let result = match self.open_file(emitter, write.handle) {
+                    Ok((provider, handle)) => {
+                        let request = ffi::FilesystemMessage::Write(ffi::Write { handle, ..write });
+                        forward(provider, request)
+                            .await
+                            .and_then(|rep: ffi::WriteResponse| rep.result)
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(message_id, ffi::WriteResponse { result });
+            }
+            ffi::FilesystemMessage::Seek(seek) => {
+                let result = match self.open_file(emitter, seek.handle) {
+                    Ok((provider, handle)) => {
+                        let request = ffi::FilesystemMessage::Seek(ffi::Seek { handle, ..seek });
+                        forward(provider, request)
+                            .await
+                            .and_then(|rep: ffi::SeekResponse| rep.result)
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(message_id, ffi::SeekResponse { result });
+            }
+            ffi::FilesystemMessage::Close(close) => {
+                if self.open_file(emitter, close.handle).is_ok() {
+                    let file = self.handles.remove(&close.handle).unwrap();
+                    close_in_provider(&file);
+                }
+            }
+            ffi::FilesystemMessage::CreateDir(create) => {
+                let result = match self.resolve(&create.path) {
+                    Ok((provider, path)) => {
+                        let request = ffi::FilesystemMessage::CreateDir(ffi::CreateDir { path });
+                        forward(provider, request)
+                            .await
+                            .and_then(|rep: ffi::CreateDirResponse| rep.result)
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(message_id, ffi::CreateDirResponse { result });
+            }
+            ffi::FilesystemMessage::ReadDir(read_dir) => {
+                let result = self.read_dir(&read_dir.path).await;
+                answer(message_id, ffi::ReadDirResponse { result });
+            }
+            ffi::FilesystemMessage::Metadata(request) => {
+                let result = self.metadata(&request.path).await;
+                answer(message_id, ffi::MetadataResponse { result });
+            }
+            ffi::FilesystemMessage::Mount(mount) => {
+                let result = self.mount(emitter, &mount.prefix);
+                answer(message_id, ffi::MountResponse { result });
+            }
+        }
+    }
+
+    fn mount(&mut self, provider: Pid, prefix: &str) -> Result<(), ErrorPayload> {
+        let prefix = normalize(prefix)?;
+        if self.mounts.iter().any(|(p, _)| *p == prefix) {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("prefix already mounted"));
+        }
+        self.mounts.push((prefix, provider));
+        Ok(())
+    }
+
+    /// Lists a directory, including the mount points that are direct children of it.
+    async fn read_dir(&self, path: &str) -> Result<Vec<ffi::DirEntry>, ErrorPayload> {
+        let normalized = normalize(path)?;
+
+        let mut entries = match self.resolve(path) {
+            Ok((provider, path)) => {
+                let request = ffi::FilesystemMessage::ReadDir(ffi::ReadDir { path });
+                forward(provider, request)
+                    .await
+                    .and_then(|rep: ffi::ReadDirResponse| rep.result)
+            }
+            Err(err) => Err(err),
+        };
+
+        let mount_points = self
+            .mounts
+            .iter()
+            .filter_map(|(prefix, _)| {
+                let pos = prefix.rfind('/')?;
+                if prefix[..pos] == normalized && pos + 1 < prefix.len() {
+                    Some(prefix[pos + 1..].to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if mount_points.is_empty() {
+            return entries;
+        }
+        if entries.is_err() {
+            entries = Ok(Vec::new());
+        }
+        if let Ok(entries) = &mut entries {
+            for name in mount_points {
+                entries.retain(|e| e.name != name);
+                entries.push(ffi::DirEntry {
+                    name,
+                    kind: ffi::EntryKind::Directory,
+                });
+            }
+        }
+        entries
+    }
+
+    async fn metadata(&self, path: &str) -> Result<ffi::Metadata, ErrorPayload> {
+        let normalized = normalize(path)?;
+        let result = match self.resolve(path) {
+            Ok((provider, path)) => {
+                let request = ffi::FilesystemMessage::Metadata(ffi::MetadataRequest { path });
+                forward(provider, request)
+                    .await
+                    .and_then(|rep: ffi::MetadataResponse| rep.result)
+            }
+            Err(err) => Err(err),
+        };
+
+        // Directories that contain mount points exist even if no provider knows about them.
+        if result.is_err()
+            && self
+                .mounts
+                .iter()
+                .any(|(prefix, _)| is_under(prefix, &normalized))
+        {
+            return Ok(ffi::Metadata {
+                kind: ffi::EntryKind::Directory,
+                len: 0,
+            });
+        }
+
+        result
+    }
+
+    /// Finds the provider in charge of the given path, and returns the path relative to its
+    /// mount point.
+    fn resolve(&self, path: &str) -> Result<(Pid, String), ErrorPayload> {
+        let path = normalize(path)?;
+        let (prefix, provider) = self
+            .mounts
+            .iter()
+            .filter(|(prefix, _)| is_under(&path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))?;
+
+        let relative = &path[prefix.len()..];
+        let relative = if relative.is_empty() {
+            "/".to_owned()
+        } else {
+            relative.to_owned()
+        };
+        Ok((*provider, relative))
+    }
+
+    /// Returns the provider and provider handle of an open file, if it belongs to `emitter`.
+    fn open_file(&self, emitter: Pid, handle: u64) -> Result<(Pid, u64), ErrorPayload> {
+        match self.handles.get(&handle) {
+            Some(f) if f.owner == emitter => Ok((f.provider, f.provider_handle)),
+            _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid handle")),
+        }
+    }
+
+    fn process_destroyed(&mut self, pid: Pid) {
+        // The process might be a provider.
+        self.mounts.retain(|(_, provider)| *provider != pid);
+        self.handles.retain(|_, f| f.provider != pid);
+
+        // The process might have open files.
+        for file in self.handles.values().filter(|f| f.owner == pid) {
+            close_in_provider(file);
+        }
+        self.handles.retain(|_, f| f.owner != pid);
+    }
+}
+
+/// Sends a request to a provider and waits for its answer.
+async fn forward<T: Decode>(
+    provider: Pid,
+    request: ffi::FilesystemMessage,
+) -> Result<T, ErrorPayload> {
+    let response = unsafe {
+        redshirt_syscalls_interface::emit_directed_message_with_response(provider, request)
+    };
+    match response {
+        Ok(response) => Ok(response.await),
+        Err(_) => Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)),
+    }
+}
+
+fn close_in_provider(file: &OpenFile) {
+    let request = ffi::FilesystemMessage::Close(ffi::Close {
+        handle: file.provider_handle,
+    });
+    unsafe {
+        let _ = redshirt_syscalls_interface::emit_directed_message_without_response(
+            file.provider,
+            request,
+        );
+    }
+}
+
+/// Sends back `response` if the emitter of the message expects an answer.
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
+
+/// Returns true if `path` is `prefix` or is inside of `prefix`. Both must be normalized.
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.starts_with(prefix)
+        && (path.len() == prefix.len() || path.as_bytes()[prefix.len()] == b'/')
+}
+
+/// Turns a path into a canonical format: a `/` before each component, and no trailing `/`.
+/// The root is an empty string. Empty components and `.` are removed, and `..` is resolved.
+fn normalize(path: &str) -> Result<String, ErrorPayload> {
+    if !path.starts_with('/') {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("relative path"));
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            c => components.push(c),
+        }
+    }
+
+    let mut out = String::with_capacity(path.len());
+    for component in components {
+        out.push('/');
+        out.push_str(component);
+    }
+    Ok(out)
+}
@@ -0,0 +1,556 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! 9P2000.L client talking to a virtio 9p transport, as defined in section 5.11 of the virtio
+//! specifications.
+//!
+//! Requests are sent one at a time. Each request consists of a buffer containing the
+//! T-message, followed with a buffer where the device writes the R-message.
+
+use redshirt_hardware_interface::HardwareOperationsBuilder;
+use redshirt_pci_interface::PciDeviceLocation;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use std::convert::TryFrom as _;
+use virtio::{Buffer, VirtioDevice, Virtqueue};
+
+/// Maximum size of a message that we propose to the server.
+const MSIZE: u32 = 16 * 1024;
+/// Version of the protocol that we speak.
+const VERSION: &str = "9P2000.L";
+
+/// Tag used for the version negotiation.
+const NOTAG: u16 = 0xffff;
+/// Fid passed when there is no authentication fid.
+const NOFID: u32 = 0xffff_ffff;
+/// Tag of all the other requests. Since we only have one request in flight at a time, they can
+/// all use the same tag.
+const TAG: u16 = 0;
+
+/// Size of the header of all messages: `size[4] type[1] tag[2]`.
+const HEADER_LEN: u32 = 7;
+
+// Types of messages.
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TREADDIR: u8 = 40;
+const TMKDIR: u8 = 72;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+/// Maximum number of path components in a single walk request.
+const MAXWELEM: usize = 16;
+
+/// Flags of `Tlopen` and `Tlcreate`. Same values as on Linux.
+pub const O_RDONLY: u32 = 0o0;
+pub const O_RDWR: u32 = 0o2;
+pub const O_TRUNC: u32 = 0o1000;
+
+/// Bit of [`Qid::ty`] set for directories.
+const QTDIR: u8 = 0x80;
+/// Value of the `request_mask` of `Tgetattr` asking for the basic attributes, which include
+/// the size.
+const GETATTR_BASIC: u64 = 0x7ff;
+
+/// Identifier of a file on the server side, chosen by the client.
+pub type Fid = u32;
+
+/// Unique identifier of a file on the server side.
+#[derive(Debug, Clone)]
+pub struct Qid {
+    pub ty: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+impl Qid {
+    pub fn is_directory(&self) -> bool {
+        self.ty & QTDIR != 0
+    }
+}
+
+/// Entry returned by [`Client::read_dir`].
+#[derive(Debug)]
+pub struct DirEntry {
+    pub qid: Qid,
+    /// Offset to pass to [`Client::read_dir`] in order to read the entries after this one.
+    pub offset: u64,
+    pub name: String,
+}
+
+/// Connection to a 9p server through a virtio device.
+pub struct Client {
+    requestq: Virtqueue,
+    /// Physical address of the buffer containing the T-message.
+    request: u64,
+    /// Physical address of the buffer where the device writes the R-message.
+    response: u64,
+    /// Maximum size of a message, negotiated with the server.
+    msize: u32,
+    /// Fid of the root of the exported directory.
+    root: Fid,
+    /// Fids that have been clunked and can be reused.
+    free_fids: Vec<Fid>,
+    /// Next fid to allocate if `free_fids` is empty.
+    next_fid: Fid,
+}
+
+impl Client {
+    /// Initializes the device at the given location and attaches to the root of the exported
+    /// directory.
+    pub async unsafe fn init(location: PciDeviceLocation) -> Result<Client, ErrorPayload> {
+        let device = VirtioDevice::from_pci(location)
+            .await
+            .ok_or_else(protocol_error)?;
+        // We don't negotiate `VIRTIO_9P_MOUNT_TAG`, as we don't use the name of the export.
+        device.init(0).await.map_err(|()| protocol_error())?;
+        let requestq = device.setup_queue(0, 4).await.ok_or_else(protocol_error)?;
+        device.driver_ok().await;
+
+        let mut client = Client {
+            requestq,
+            request: redshirt_hardware_interface::malloc::malloc(u64::from(MSIZE), 8).await,
+            response: redshirt_hardware_interface::malloc::malloc(u64::from(MSIZE), 8).await,
+            msize: MSIZE,
+            root: 0,
+            free_fids: Vec::new(),
+            next_fid: 1,
+        };
+
+        // Version negotiation. The server answers with a `msize` that is lower or equal to ours.
+        let mut msg = MessageBuilder::new(TVERSION, NOTAG);
+        msg.u32(MSIZE);
+        msg.string(VERSION);
+        let response = client.request(msg).await?;
+        let mut response = Reader(&response);
+        let msize = response.u32()?;
+        if response.string()? != VERSION {
+            return Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)
+                .with_message("server doesn't support 9P2000.L"));
+        }
+        if msize <= HEADER_LEN + 16 {
+            return Err(protocol_error());
+        }
+        client.msize = msize.min(MSIZE);
+
+        let mut msg = MessageBuilder::new(TATTACH, TAG);
+        msg.u32(client.root);
+        msg.u32(NOFID);
+        msg.string("");
+        msg.string("");
+        msg.u32(0);
+        client.request(msg).await?;
+
+        Ok(client)
+    }
+
+    /// Maximum number of bytes that [`Client::read`] can return.
+    pub fn max_read_len(&self) -> u32 {
+        // Header plus `count[4]`.
+        self.msize - HEADER_LEN - 4
+    }
+
+    /// Maximum number of bytes that [`Client::write`] can accept.
+    pub fn max_write_len(&self) -> u32 {
+        // Header plus `fid[4] offset[8] count[4]`.
+        self.msize - HEADER_LEN - 16
+    }
+
+    /// Walks from the root to the given path, and returns a new fid pointing to it. The fid
+    /// must later be released with [`Client::clunk`].
+    pub async unsafe fn walk(&mut self, components: &[&str]) -> Result<(Fid, Qid), ErrorPayload> {
+        let fid = self.alloc_fid();
+        let mut qid = None;
+        let mut from = self.root;
+
+        // A walk with zero components simply clones the fid, which is what we want for the
+        // root. Otherwise, the path is walked in chunks of `MAXWELEM` components.
+        let mut chunks = components.chunks(MAXWELEM).collect::<Vec<_>>();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+
+        for chunk in chunks {
+            let mut msg = MessageBuilder::new(TWALK, TAG);
+            msg.u32(from);
+            msg.u32(fid);
+            msg.u16(chunk.len() as u16);
+            for component in chunk {
+                msg.string(component);
+            }
+
+            let result = match self.request(msg).await {
+                Ok(response) => {
+                    let mut response = Reader(&response);
+                    let nwqid = usize::from(response.u16()?);
+                    let mut last_qid = None;
+                    for _ in 0..nwqid {
+                        last_qid = Some(response.qid()?);
+                    }
+                    // If only some of the components could be walked, the walk has failed.
+                    if nwqid == chunk.len() {
+                        Ok(last_qid)
+                    } else {
+                        Err(ErrorPayload::new(ErrorClass::NOT_FOUND))
+                    }
+                }
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(last_qid) => {
+                    if last_qid.is_some() {
+                        qid = last_qid;
+                    }
+                }
+                Err(err) => {
+                    // The fid only exists if a previous chunk has been walked.
+                    if from == fid {
+                        self.clunk(fid).await;
+                    } else {
+                        self.free_fids.push(fid);
+                    }
+                    return Err(err);
+                }
+            }
+
+            from = fid;
+        }
+
+        let qid = match qid {
+            Some(qid) => qid,
+            None => self.get_attr(fid).await?.0,
+        };
+
+        Ok((fid, qid))
+    }
+
+    /// Opens the file that `fid` points to.
+    pub async unsafe fn open(&mut self, fid: Fid, flags: u32) -> Result<Qid, ErrorPayload> {
+        let mut msg = MessageBuilder::new(TLOPEN, TAG);
+        msg.u32(fid);
+        msg.u32(flags);
+        let response = self.request(msg).await?;
+        Reader(&response).qid()
+    }
+
+    /// Creates a file named `name` in the directory that `fid` points to, and opens it. `fid`
+    /// then points to the new file.
+    pub async unsafe fn create(
+        &mut self,
+        fid: Fid,
+        name: &str,
+        flags: u32,
+        mode: u32,
+    ) -> Result<Qid, ErrorPayload> {
+        let mut msg = MessageBuilder::new(TLCREATE, TAG);
+        msg.u32(fid);
+        msg.string(name);
+        msg.u32(flags);
+        msg.u32(mode);
+        msg.u32(0);
+        let response = self.request(msg).await?;
+        Reader(&response).qid()
+    }
+
+    /// Creates a directory named `name` in the directory that `fid` points to.
+    pub async unsafe fn create_dir(
+        &mut self,
+        fid: Fid,
+        name: &str,
+        mode: u32,
+    ) -> Result<Qid, ErrorPayload> {
+        let mut msg = MessageBuilder::new(TMKDIR, TAG);
+        msg.u32(fid);
+        msg.string(name);
+        msg.u32(mode);
+        msg.u32(0);
+        let response = self.request(msg).await?;
+        Reader(&response).qid()
+    }
+
+    /// Reads at most `len` bytes at the given offset of an open file. `len` must be lower or
+    /// equal to [`Client::max_read_len`].
+    pub async unsafe fn read(
+        &mut self,
+        fid: Fid,
+        offset: u64,
+        len: u32,
+    ) -> Result<Vec<u8>, ErrorPayload> {
+        debug_assert!(len <= self.max_read_len());
+        let mut msg = MessageBuilder::new(TREAD, TAG);
+        msg.u32(fid);
+        msg.u64(offset);
+        msg.u32(len);
+        let response = self.request(msg).await?;
+        let mut response = Reader(&response);
+        let count = response.u32()?;
+        Ok(response.bytes(usize::try_from(count).unwrap())?.to_vec())
+    }
+
+    /// Writes data at the given offset of an open file, and returns the number of bytes
+    /// written. The length of `data` must be lower or equal to [`Client::max_write_len`].
+    pub async unsafe fn write(
+        &mut self,
+        fid: Fid,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<u32, ErrorPayload> {
+        debug_assert!(data.len() <= self.max_write_len() as usize);
+        let mut msg = MessageBuilder::new(TWRITE, TAG);
+        msg.u32(fid);
+        msg.u64(offset);
+        msg.u32(data.len() as u32);
+        msg.bytes(data);
+        let response = self.request(msg).await?;
+        Reader(&response).u32()
+    }
+
+    /// Reads entries of an open directory, starting after the entry whose
+    /// [`DirEntry::offset`] is `offset`, or at the start if `offset` is 0. Returns an empty
+    /// list once the end has been reached.
+    pub async unsafe fn read_dir(
+        &mut self,
+        fid: Fid,
+        offset: u64,
+    ) -> Result<Vec<DirEntry>, ErrorPayload> {
+        let mut msg = MessageBuilder::new(TREADDIR, TAG);
+        msg.u32(fid);
+        msg.u64(offset);
+        msg.u32(self.max_read_len());
+        let response = self.request(msg).await?;
+        let mut response = Reader(&response);
+        let count = response.u32()?;
+        let mut data = Reader(response.bytes(usize::try_from(count).unwrap())?);
+
+        let mut entries = Vec::new();
+        while !data.0.is_empty() {
+            let qid = data.qid()?;
+            let offset = data.u64()?;
+            let _ty = data.u8()?;
+            let name = data.string()?;
+            entries.push(DirEntry { qid, offset, name });
+        }
+        Ok(entries)
+    }
+
+    /// Returns the qid and the size of the file that `fid` points to.
+    pub async unsafe fn get_attr(&mut self, fid: Fid) -> Result<(Qid, u64), ErrorPayload> {
+        let mut msg = MessageBuilder::new(TGETATTR, TAG);
+        msg.u32(fid);
+        msg.u64(GETATTR_BASIC);
+        let response = self.request(msg).await?;
+        let mut response = Reader(&response);
+        let _valid = response.u64()?;
+        let qid = response.qid()?;
+        // Skip `mode[4] uid[4] gid[4] nlink[8] rdev[8]`.
+        response.bytes(28)?;
+        let size = response.u64()?;
+        Ok((qid, size))
+    }
+
+    /// Releases a fid. Open files are closed.
+    pub async unsafe fn clunk(&mut self, fid: Fid) {
+        let mut msg = MessageBuilder::new(TCLUNK, TAG);
+        msg.u32(fid);
+        // According to the protocol, the fid is released even if the server returns an error.
+        let _ = self.request(msg).await;
+        self.free_fids.push(fid);
+    }
+
+    fn alloc_fid(&mut self) -> Fid {
+        if let Some(fid) = self.free_fids.pop() {
+            return fid;
+        }
+
+        let fid = self.next_fid;
+        self.next_fid += 1;
+        fid
+    }
+
+    /// Sends a T-message to the server and waits for the R-message. Returns the body of the
+    /// R-message, without its header.
+    async unsafe fn request(&mut self, msg: MessageBuilder) -> Result<Vec<u8>, ErrorPayload> {
+        let ty = msg.ty();
+        let msg = msg.finish();
+        if msg.len() > self.msize as usize {
+            return Err(
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("request too large")
+            );
+        }
+        let msg_len = msg.len() as u32;
+        redshirt_hardware_interface::write(self.request, msg);
+
+        let written = self
+            .requestq
+            .submit_and_wait(&[
+                Buffer {
+                    address: self.request,
+                    len: msg_len,
+                    device_writable: false,
+                },
+                Buffer {
+                    address: self.response,
+                    len: self.msize,
+                    device_writable: true,
+                },
+            ])
+            .await;
+
+        let mut response = vec![0; written.min(self.msize) as usize];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.response, &mut response);
+        ops.send().await;
+
+        let mut reader = Reader(&response);
+        let size = reader.u32()?;
+        let response_ty = reader.u8()?;
+        let _tag = reader.u16()?;
+        if usize::try_from(size).unwrap() != response.len() {
+            return Err(protocol_error());
+        }
+
+        if response_ty == RLERROR {
+            return Err(errno_to_error(reader.u32()?));
+        }
+
+        // The type of an R-message is always the type of the T-message plus one.
+        if response_ty != ty + 1 {
+            return Err(protocol_error());
+        }
+
+        Ok(response[HEADER_LEN as usize..].to_vec())
+    }
+}
+
+/// Builds a T-message.
+struct MessageBuilder(Vec<u8>);
+
+impl MessageBuilder {
+    fn new(ty: u8, tag: u16) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        // The size is filled in `finish`.
+        buf.extend_from_slice(&[0; 4]);
+        buf.push(ty);
+        buf.extend_from_slice(&tag.to_le_bytes());
+        MessageBuilder(buf)
+    }
+
+    fn ty(&self) -> u8 {
+        self.0[4]
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u16(u16::try_from(value.len()).unwrap());
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let size = u32::try_from(self.0.len()).unwrap();
+        self.0[..4].copy_from_slice(&size.to_le_bytes());
+        self.0
+    }
+}
+
+/// Decodes the body of an R-message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ErrorPayload> {
+        if self.0.len() < len {
+            return Err(protocol_error());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ErrorPayload> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ErrorPayload> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, ErrorPayload> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, ErrorPayload> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn string(&mut self) -> Result<String, ErrorPayload> {
+        let len = usize::from(self.u16()?);
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| protocol_error())
+    }
+
+    fn qid(&mut self) -> Result<Qid, ErrorPayload> {
+        Ok(Qid {
+            ty: self.u8()?,
+            version: self.u32()?,
+            path: self.u64()?,
+        })
+    }
+}
+
+/// Turns a Linux error number, as found in `Rlerror` messages, into an [`ErrorPayload`].
+fn errno_to_error(errno: u32) -> ErrorPayload {
+    let class = match errno {
+        // `ENOENT`
+        2 => ErrorClass::NOT_FOUND,
+        // `EPERM`, `EACCES`, `EROFS`
+        1 | 13 | 30 => ErrorClass::PERMISSION_DENIED,
+        // `EEXIST`, `ENOTDIR`, `EISDIR`, `EINVAL`, `ENAMETOOLONG`, `ENOTEMPTY`
+        17 | 20 | 21 | 22 | 36 | 39 => ErrorClass::INVALID_REQUEST,
+        // `ENOSYS`, `EOPNOTSUPP`
+        38 | 95 => ErrorClass::UNSUPPORTED,
+        _ => ErrorClass::IO,
+    };
+
+    ErrorPayload::new(class).with_detail(errno)
+}
+
+fn protocol_error() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message("9p protocol error")
+}
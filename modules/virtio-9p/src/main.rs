@@ -0,0 +1,372 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for virtio 9p transports, which QEMU uses to share a directory of the host with the
+//! guest (`-virtfs`).
+//!
+//! This program scans the PCI space for a virtio 9p device, and mounts the directory shared by
+//! the host at `/host` through the filesystem interface. Only the first device is used.
+//!
+//! Bibliography:
+//!
+//! - https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html (section 5.11)
+//! - https://github.com/chaos/diod/blob/master/protocol.md
+//!
+
+mod client;
+
+use client::{Client, Fid, O_RDONLY, O_RDWR, O_TRUNC};
+use parity_scale_codec::DecodeAll;
+use redshirt_filesystem_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+use std::{collections::HashMap, convert::TryFrom as _};
+
+/// Permissions of the files created through this program.
+const FILE_MODE: u32 = 0o644;
+/// Permissions of the directories created through this program.
+const DIR_MODE: u32 = 0o755;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    let device = pci_devices.into_iter().find(|device| {
+        // `0x1009` is the identifier of transitional devices, which also support the modern
+        // interface.
+        device.vendor_id == 0x1af4 && (device.device_id == 0x1009 || device.device_id == 0x1049)
+    });
+
+    let location = match device {
+        Some(d) => d.location,
+        None => return,
+    };
+
+    let mut client = match unsafe { Client::init(location).await } {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    if redshirt_filesystem_interface::mount("/host").await.is_err() {
+        return;
+    }
+
+    let mut handles = HashMap::<u64, OpenFile>::new();
+    let mut next_handle = 1;
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg) => {
+                let closed = handles
+                    .iter()
+                    .filter(|(_, f)| f.owner == msg.pid)
+                    .map(|(h, _)| *h)
+                    .collect::<Vec<_>>();
+                for handle in closed {
+                    let file = handles.remove(&handle).unwrap();
+                    unsafe { client.clunk(file.fid).await };
+                }
+                continue;
+            }
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(
+            msg.interface,
+            redshirt_syscalls_interface::DIRECTED_MESSAGE_INTERFACE
+        );
+
+        let message: ffi::FilesystemMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        let emitter = msg.emitter_pid;
+
+        match message {
+            ffi::FilesystemMessage::Open(open) => {
+                let result = match unsafe { open_file(&mut client, &open).await } {
+                    Ok(fid) => {
+                        let handle = next_handle;
+                        next_handle += 1;
+                        handles.insert(
+                            handle,
+                            OpenFile {
+                                owner: emitter,
+                                fid,
+                                position: 0,
+                            },
+                        );
+                        Ok(handle)
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::OpenResponse { result });
+            }
+            ffi::FilesystemMessage::Read(read) => {
+                let result = match handle_mut(&mut handles, emitter, read.handle) {
+                    Ok(file) => {
+                        let len = read.len.min(client.max_read_len());
+                        let data = unsafe { client.read(file.fid, file.position, len).await };
+                        if let Ok(data) = &data {
+                            file.position += u64::try_from(data.len()).unwrap();
+                        }
+                        data
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadResponse { result });
+            }
+            ffi::FilesystemMessage::Write(write) => {
+                let result = match handle_mut(&mut handles, emitter, write.handle) {
+                    Ok(file) => unsafe { write_file(&mut client, file, &write.data).await },
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::WriteResponse { result });
+            }
+            ffi::FilesystemMessage::Seek(seek) => {
+                let result = handle_mut(&mut handles, emitter, seek.handle).map(|file| {
+                    file.position = seek.position;
+                });
+                answer(msg.message_id, ffi::SeekResponse { result });
+            }
+            ffi::FilesystemMessage::Close(close) => {
+                if handle_mut(&mut handles, emitter, close.handle).is_ok() {
+                    let file = handles.remove(&close.handle).unwrap();
+                    unsafe { client.clunk(file.fid).await };
+                }
+            }
+            ffi::FilesystemMessage::CreateDir(create_dir) => {
+                let result = unsafe { create_dir_at(&mut client, &create_dir.path).await };
+                answer(msg.message_id, ffi::CreateDirResponse { result });
+            }
+            ffi::FilesystemMessage::ReadDir(read_dir) => {
+                let result = unsafe { list_dir(&mut client, &read_dir.path).await };
+                answer(msg.message_id, ffi::ReadDirResponse { result });
+            }
+            ffi::FilesystemMessage::Metadata(request) => {
+                let result = unsafe { metadata(&mut client, &request.path).await };
+                answer(msg.message_id, ffi::MetadataResponse { result });
+            }
+            ffi::FilesystemMessage::Mount(_) => {
+                answer(
+                    msg.message_id,
+                    ffi::MountResponse {
+                        result: Err(ErrorPayload::new(ErrorClass::UNSUPPORTED)),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Sends back `response` if the emitter of the message expects an answer.
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
+
+struct OpenFile {
+    /// Process that has opened the file. Other processes can't use the handle.
+    owner: Pid,
+    /// Fid of the open file on the server.
+    fid: Fid,
+    /// Current position within the file.
+    position: u64,
+}
+
+/// Returns the open file corresponding to `handle`, if it belongs to `emitter`.
+fn handle_mut(
+    handles: &mut HashMap<u64, OpenFile>,
+    emitter: Pid,
+    handle: u64,
+) -> Result<&mut OpenFile, ErrorPayload> {
+    match handles.get_mut(&handle) {
+        Some(f) if f.owner == emitter => Ok(f),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid handle")),
+    }
+}
+
+/// Splits an absolute path into its components.
+fn components(path: &str) -> Result<Vec<&str>, ErrorPayload> {
+    if !path.starts_with('/') {
+        return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("relative path"));
+    }
+
+    Ok(path.split('/').filter(|c| !c.is_empty()).collect())
+}
+
+/// Opens or creates the file designated by `open`, and returns its fid.
+async unsafe fn open_file(client: &mut Client, open: &ffi::Open) -> Result<Fid, ErrorPayload> {
+    let components = components(&open.path)?;
+
+    let (fid, qid) = match client.walk(&components).await {
+        Ok(v) => v,
+        Err(ref err) if err.class == ErrorClass::NOT_FOUND && open.create => {
+            let (name, parent) = components
+                .split_last()
+                .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))?;
+            let (fid, _) = client.walk(parent).await?;
+            return match client.create(fid, name, O_RDWR, FILE_MODE).await {
+                Ok(_) => Ok(fid),
+                Err(err) => {
+                    client.clunk(fid).await;
+                    Err(err)
+                }
+            };
+        }
+        Err(err) => return Err(err),
+    };
+
+    if qid.is_directory() {
+        client.clunk(fid).await;
+        return Err(
+            ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("path is a directory")
+        );
+    }
+
+    let mut result = if open.truncate {
+        client.open(fid, O_RDWR | O_TRUNC).await
+    } else {
+        client.open(fid, O_RDWR).await
+    };
+
+    // The host might not let us write to the file, in which case we open it read-only. Writes
+    // will then fail.
+    if result.is_err() && !open.truncate {
+        result = client.open(fid, O_RDONLY).await;
+    }
+
+    match result {
+        Ok(_) => Ok(fid),
+        Err(err) => {
+            client.clunk(fid).await;
+            Err(err)
+        }
+    }
+}
+
+/// Writes all of `data` at the current position of `file`, splitting it in multiple requests
+/// if necessary.
+async unsafe fn write_file(
+    client: &mut Client,
+    file: &mut OpenFile,
+    mut data: &[u8],
+) -> Result<(), ErrorPayload> {
+    while !data.is_empty() {
+        let chunk_len = data.len().min(client.max_write_len() as usize);
+        let written = client
+            .write(file.fid, file.position, &data[..chunk_len])
+            .await?;
+        if written == 0 {
+            return Err(ErrorPayload::new(ErrorClass::IO));
+        }
+        let written = (written as usize).min(chunk_len);
+        file.position += u64::try_from(written).unwrap();
+        data = &data[written..];
+    }
+
+    Ok(())
+}
+
+/// Creates the directory at the given path.
+async unsafe fn create_dir_at(client: &mut Client, path: &str) -> Result<(), ErrorPayload> {
+    let components = components(path)?;
+    let (name, parent) = components.split_last().ok_or_else(|| {
+        ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("directory already exists")
+    })?;
+
+    let (fid, _) = client.walk(parent).await?;
+    let result = client.create_dir(fid, name, DIR_MODE).await.map(|_| ());
+    client.clunk(fid).await;
+    result
+}
+
+/// Lists the content of the directory at the given path.
+async unsafe fn list_dir(
+    client: &mut Client,
+    path: &str,
+) -> Result<Vec<ffi::DirEntry>, ErrorPayload> {
+    let (fid, qid) = client.walk(&components(path)?).await?;
+    if !qid.is_directory() {
+        client.clunk(fid).await;
+        return Err(
+            ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("path is not a directory")
+        );
+    }
+
+    let result = match client.open(fid, O_RDONLY).await {
+        Ok(_) => read_dir_entries(client, fid).await,
+        Err(err) => Err(err),
+    };
+
+    client.clunk(fid).await;
+    result
+}
+
+/// Reads all the entries of an open directory, except for `.` and `..`.
+async unsafe fn read_dir_entries(
+    client: &mut Client,
+    fid: Fid,
+) -> Result<Vec<ffi::DirEntry>, ErrorPayload> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let chunk = client.read_dir(fid, offset).await?;
+        offset = match chunk.last() {
+            Some(e) => e.offset,
+            None => return Ok(entries),
+        };
+
+        for entry in chunk {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            entries.push(ffi::DirEntry {
+                kind: if entry.qid.is_directory() {
+                    ffi::EntryKind::Directory
+                } else {
+                    ffi::EntryKind::File
+                },
+                name: entry.name,
+            });
+        }
+    }
+}
+
+/// Queries information about the file or directory at the given path.
+async unsafe fn metadata(client: &mut Client, path: &str) -> Result<ffi::Metadata, ErrorPayload> {
+    let (fid, _) = client.walk(&components(path)?).await?;
+    let result = client.get_attr(fid).await.map(|(qid, size)| {
+        if qid.is_directory() {
+            ffi::Metadata {
+                kind: ffi::EntryKind::Directory,
+                len: 0,
+            }
+        } else {
+            ffi::Metadata {
+                kind: ffi::EntryKind::File,
+                len: size,
+            }
+        }
+    });
+    client.clunk(fid).await;
+    result
+}
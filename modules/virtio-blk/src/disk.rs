@@ -0,0 +1,170 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Requests to a virtio block device, as defined in section 5.2 of the virtio specifications.
+
+use redshirt_hardware_interface::HardwareOperationsBuilder;
+use redshirt_pci_interface::PciDeviceLocation;
+use virtio::{Buffer, VirtioDevice, Virtqueue};
+
+/// Size of a sector. Virtio block devices always address their content in units of 512 bytes.
+pub const SECTOR_SIZE: u32 = 512;
+/// Maximum number of sectors transferred by a single request.
+pub const MAX_SECTORS_PER_REQUEST: u32 = 128;
+
+const VIRTIO_BLK_F_RO: u32 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH: u32 = 1 << 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// Initialized virtio block device.
+pub struct Disk {
+    requestq: Virtqueue,
+    /// Physical address of the buffer containing the header of the request.
+    header: u64,
+    /// Physical address of the buffer used for data transfers.
+    data: u64,
+    /// Physical address of the byte where the device writes the status of the request.
+    status: u64,
+    /// Total number of sectors of the device.
+    num_sectors: u64,
+    read_only: bool,
+    /// If false, the device doesn't have a write cache and flushing is a no-op.
+    supports_flush: bool,
+}
+
+impl Disk {
+    /// Initializes the device at the given location.
+    pub async unsafe fn init(location: PciDeviceLocation) -> Result<Disk, ()> {
+        let device = VirtioDevice::from_pci(location).await.ok_or(())?;
+        let features = device.init(VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH).await?;
+        let requestq = device.setup_queue(0, 16).await.ok_or(())?;
+        device.driver_ok().await;
+
+        // The first field of the configuration structure is the capacity, in sectors.
+        let capacity_low = device.read_device_config_u32(0).await;
+        let capacity_high = device.read_device_config_u32(4).await;
+
+        Ok(Disk {
+            requestq,
+            header: redshirt_hardware_interface::malloc::malloc(16, 16).await,
+            data: redshirt_hardware_interface::malloc::malloc(
+                u64::from(MAX_SECTORS_PER_REQUEST) * u64::from(SECTOR_SIZE),
+                16,
+            )
+            .await,
+            status: redshirt_hardware_interface::malloc::malloc(1, 1).await,
+            num_sectors: u64::from(capacity_low) | (u64::from(capacity_high) << 32),
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+            supports_flush: features & VIRTIO_BLK_F_FLUSH != 0,
+        })
+    }
+
+    /// Returns the total number of sectors of the device.
+    pub fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+
+    /// Returns true if the device refuses writes.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Reads `num_sectors` sectors starting at `first_sector`.
+    ///
+    /// The range must be within the device, and `num_sectors` no larger than
+    /// [`MAX_SECTORS_PER_REQUEST`].
+    pub async unsafe fn read(
+        &mut self,
+        first_sector: u64,
+        num_sectors: u32,
+    ) -> Result<Vec<u8>, ()> {
+        debug_assert!(num_sectors <= MAX_SECTORS_PER_REQUEST);
+        let len = num_sectors * SECTOR_SIZE;
+        self.request(VIRTIO_BLK_T_IN, first_sector, len).await?;
+
+        let mut out = vec![0; len as usize];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.data, &mut out);
+        ops.send().await;
+        Ok(out)
+    }
+
+    /// Writes `data` starting at `first_sector`.
+    ///
+    /// The length of `data` must be a multiple of the sector size, the range must be within the
+    /// device, and the number of sectors no larger than [`MAX_SECTORS_PER_REQUEST`].
+    pub async unsafe fn write(&mut self, first_sector: u64, data: Vec<u8>) -> Result<(), ()> {
+        debug_assert_eq!(data.len() % SECTOR_SIZE as usize, 0);
+        debug_assert!(data.len() <= (MAX_SECTORS_PER_REQUEST * SECTOR_SIZE) as usize);
+        let len = data.len() as u32;
+        redshirt_hardware_interface::write(self.data, data);
+        self.request(VIRTIO_BLK_T_OUT, first_sector, len).await
+    }
+
+    /// Makes sure that the previous writes are persisted.
+    pub async unsafe fn flush(&mut self) -> Result<(), ()> {
+        if !self.supports_flush {
+            return Ok(());
+        }
+        self.request(VIRTIO_BLK_T_FLUSH, 0, 0).await
+    }
+
+    /// Sends a request to the device and waits for its completion. `data_len` bytes of the data
+    /// buffer are transferred.
+    async unsafe fn request(&mut self, ty: u32, sector: u64, data_len: u32) -> Result<(), ()> {
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&ty.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&sector.to_le_bytes());
+        redshirt_hardware_interface::write(self.header, header);
+        redshirt_hardware_interface::write(self.status, vec![0xff]);
+
+        let mut buffers = Vec::with_capacity(3);
+        buffers.push(Buffer {
+            address: self.header,
+            len: 16,
+            device_writable: false,
+        });
+        if data_len != 0 {
+            buffers.push(Buffer {
+                address: self.data,
+                len: data_len,
+                device_writable: ty == VIRTIO_BLK_T_IN,
+            });
+        }
+        buffers.push(Buffer {
+            address: self.status,
+            len: 1,
+            device_writable: true,
+        });
+        self.requestq.submit_and_wait(&buffers).await;
+
+        let mut status = [0u8];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.status, &mut status);
+        ops.send().await;
+
+        if status[0] == VIRTIO_BLK_S_OK {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
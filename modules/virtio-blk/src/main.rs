@@ -0,0 +1,196 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for virtio block devices.
+//!
+//! This program scans the PCI space for virtio block devices, and exposes them through the
+//! block device interface.
+//!
+//! Bibliography:
+//!
+//! - https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html
+//!
+
+mod disk;
+
+use parity_scale_codec::DecodeAll;
+use redshirt_block_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId};
+use std::convert::TryFrom as _;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let mut disks = Vec::new();
+
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    for device in pci_devices {
+        // `0x1001` is the identifier of transitional devices, which also support the modern
+        // interface.
+        if device.vendor_id != 0x1af4 || (device.device_id != 0x1001 && device.device_id != 0x1042)
+        {
+            continue;
+        }
+
+        if let Ok(disk) = unsafe { disk::Disk::init(device.location).await } {
+            disks.push(disk);
+        }
+    }
+
+    if disks.is_empty() {
+        return;
+    }
+
+    // TODO: only one program can handle the block device interface at a time
+    if redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let msg = match redshirt_syscalls_interface::next_interface_message().await {
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_) => continue,
+            redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_) => continue,
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::BlockMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match message {
+            ffi::BlockMessage::ListDevices => {
+                let devices = (0..u32::try_from(disks.len()).unwrap()).collect();
+                answer(msg.message_id, ffi::ListDevicesResponse { devices });
+            }
+            ffi::BlockMessage::Geometry(geometry) => {
+                let result = disk(&mut disks, geometry.device).map(|disk| ffi::DeviceGeometry {
+                    sector_size: disk::SECTOR_SIZE,
+                    num_sectors: disk.num_sectors(),
+                    read_only: disk.read_only(),
+                });
+                answer(msg.message_id, ffi::GeometryResponse { result });
+            }
+            ffi::BlockMessage::Read(read) => {
+                let result = match disk(&mut disks, read.device) {
+                    Ok(disk) => {
+                        match check_range(disk, read.first_sector, u64::from(read.num_sectors)) {
+                            Ok(()) => read_sectors(disk, read.first_sector, read.num_sectors).await,
+                            Err(err) => Err(err),
+                        }
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::ReadResponse { result });
+            }
+            ffi::BlockMessage::Write(write) => {
+                let result = match disk(&mut disks, write.device) {
+                    Ok(disk) if disk.read_only() => {
+                        Err(ErrorPayload::new(ErrorClass::PERMISSION_DENIED)
+                            .with_message("device is read-only"))
+                    }
+                    Ok(_) if write.data.len() % disk::SECTOR_SIZE as usize != 0 => {
+                        Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("length isn't a multiple of the sector size"))
+                    }
+                    Ok(disk) => {
+                        let num_sectors =
+                            u64::try_from(write.data.len() / disk::SECTOR_SIZE as usize).unwrap();
+                        match check_range(disk, write.first_sector, num_sectors) {
+                            Ok(()) => write_sectors(disk, write.first_sector, write.data).await,
+                            Err(err) => Err(err),
+                        }
+                    }
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::WriteResponse { result });
+            }
+            ffi::BlockMessage::Flush(flush) => {
+                let result = match disk(&mut disks, flush.device) {
+                    Ok(disk) => unsafe { disk.flush().await }.map_err(|()| io_error()),
+                    Err(err) => Err(err),
+                };
+                answer(msg.message_id, ffi::FlushResponse { result });
+            }
+        }
+    }
+}
+
+/// Reads a range of sectors, splitting it in multiple requests if necessary.
+async fn read_sectors(
+    disk: &mut disk::Disk,
+    first_sector: u64,
+    num_sectors: u32,
+) -> Result<Vec<u8>, ErrorPayload> {
+    let mut out = Vec::with_capacity(num_sectors as usize * disk::SECTOR_SIZE as usize);
+    let mut sector = first_sector;
+    let mut remaining = num_sectors;
+    while remaining != 0 {
+        let chunk = remaining.min(disk::MAX_SECTORS_PER_REQUEST);
+        let data = unsafe { disk.read(sector, chunk).await }.map_err(|()| io_error())?;
+        out.extend_from_slice(&data);
+        sector += u64::from(chunk);
+        remaining -= chunk;
+    }
+    Ok(out)
+}
+
+/// Writes a range of sectors, splitting it in multiple requests if necessary.
+async fn write_sectors(
+    disk: &mut disk::Disk,
+    first_sector: u64,
+    data: Vec<u8>,
+) -> Result<(), ErrorPayload> {
+    let chunk_len = (disk::MAX_SECTORS_PER_REQUEST * disk::SECTOR_SIZE) as usize;
+    let mut sector = first_sector;
+    for chunk in data.chunks(chunk_len) {
+        unsafe { disk.write(sector, chunk.to_vec()).await }.map_err(|()| io_error())?;
+        sector += u64::try_from(chunk.len()).unwrap() / u64::from(disk::SECTOR_SIZE);
+    }
+    Ok(())
+}
+
+fn disk(disks: &mut [disk::Disk], device: u32) -> Result<&mut disk::Disk, ErrorPayload> {
+    usize::try_from(device)
+        .ok()
+        .and_then(move |index| disks.get_mut(index))
+        .ok_or_else(|| ErrorPayload::new(ErrorClass::NOT_FOUND))
+}
+
+/// Checks that the given range of sectors is within the device.
+fn check_range(disk: &disk::Disk, first_sector: u64, num_sectors: u64) -> Result<(), ErrorPayload> {
+    match first_sector.checked_add(num_sectors) {
+        Some(end) if end <= disk.num_sectors() => Ok(()),
+        _ => Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+            .with_message("range of sectors out of the device")),
+    }
+}
+
+fn io_error() -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO)
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Commands of the virtio GPU device, as defined in section 5.7 of the virtio specifications.
+//!
+//! Only the 2D commands are used. The display is backed by a single resource, whose backing
+//! storage is a buffer of physical memory that we update before asking the host to transfer it.
+
+use redshirt_framebuffer_interface::ffi::Rect;
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+use redshirt_pci_interface::PciDeviceLocation;
+use virtio::{Buffer, VirtioDevice, Virtqueue};
+
+const VIRTIO_GPU_CMD_GET_DISPLAY_INFO: u32 = 0x100;
+const VIRTIO_GPU_CMD_RESOURCE_CREATE_2D: u32 = 0x101;
+const VIRTIO_GPU_CMD_SET_SCANOUT: u32 = 0x103;
+const VIRTIO_GPU_CMD_RESOURCE_FLUSH: u32 = 0x104;
+const VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D: u32 = 0x105;
+const VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING: u32 = 0x106;
+
+const VIRTIO_GPU_RESP_OK_NODATA: u32 = 0x1100;
+const VIRTIO_GPU_RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+const VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM: u32 = 2;
+
+/// Size of the header common to all requests and responses.
+const HEADER_LEN: u32 = 24;
+/// Size of the buffers used for requests and responses. Large enough for every command we send.
+const BUFFER_LEN: u32 = 512;
+/// Identifier of the resource that we display. Chosen by us.
+const RESOURCE_ID: u32 = 1;
+
+/// Resolution used if the device doesn't report any enabled scanout.
+const DEFAULT_RESOLUTION: (u32, u32) = (1024, 768);
+
+/// Virtio GPU whose first scanout displays the content of a buffer in physical memory.
+pub struct Display {
+    controlq: Virtqueue,
+    /// Physical address of the buffer where requests are written.
+    request_buffer: u64,
+    /// Physical address of the buffer where the device writes responses.
+    response_buffer: u64,
+    /// Physical address of the backing storage of the displayed resource.
+    backing: u64,
+    width: u32,
+    height: u32,
+}
+
+impl Display {
+    /// Initializes the device at the given location and sets up its first scanout.
+    pub async unsafe fn init(location: PciDeviceLocation) -> Result<Display, ()> {
+        let device = VirtioDevice::from_pci(location).await.ok_or(())?;
+        device.init(0).await?;
+        let controlq = device.setup_queue(0, 16).await.ok_or(())?;
+        device.driver_ok().await;
+
+        // Offset 8 of the configuration structure is `num_scanouts`.
+        if device.read_device_config_u32(8).await == 0 {
+            return Err(());
+        }
+
+        let request_buffer =
+            redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 64).await;
+        let response_buffer =
+            redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 64).await;
+
+        let mut display = Display {
+            controlq,
+            request_buffer,
+            response_buffer,
+            backing: 0,
+            width: 0,
+            height: 0,
+        };
+
+        let (width, height) = display.display_info().await?.unwrap_or(DEFAULT_RESOLUTION);
+        let backing_len = u64::from(width) * u64::from(height) * 4;
+        display.backing = redshirt_hardware_interface::malloc::malloc(backing_len, 64).await;
+        display.width = width;
+        display.height = height;
+        redshirt_hardware_interface::write(display.backing, vec![0; backing_len as usize]);
+
+        let mut request = header(VIRTIO_GPU_CMD_RESOURCE_CREATE_2D);
+        push_u32(&mut request, RESOURCE_ID);
+        push_u32(&mut request, VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM);
+        push_u32(&mut request, width);
+        push_u32(&mut request, height);
+        display.command_nodata(request).await?;
+
+        let mut request = header(VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING);
+        push_u32(&mut request, RESOURCE_ID);
+        push_u32(&mut request, 1);
+        request.extend_from_slice(&display.backing.to_le_bytes());
+        push_u32(&mut request, backing_len as u32);
+        push_u32(&mut request, 0);
+        display.command_nodata(request).await?;
+
+        let full_screen = display.full_screen();
+        let mut request = header(VIRTIO_GPU_CMD_SET_SCANOUT);
+        push_rect(&mut request, &full_screen);
+        push_u32(&mut request, 0);
+        push_u32(&mut request, RESOURCE_ID);
+        display.command_nodata(request).await?;
+
+        display.flush(&full_screen).await?;
+        Ok(display)
+    }
+
+    /// Returns the width of the display, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the display, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Copies `pixels` to the given area of the screen and waits for the host to display them.
+    ///
+    /// `pixels` must contain exactly `rect.width * rect.height` pixels in the `B8G8R8X8` format,
+    /// and `rect` must be within the screen.
+    pub async unsafe fn update(&mut self, rect: &Rect, pixels: &[u8]) -> Result<(), ()> {
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+
+        let line_len = rect.width as usize * 4;
+        debug_assert_eq!(pixels.len(), line_len * rect.height as usize);
+
+        let mut ops = HardwareWriteOperationsBuilder::with_capacity(rect.height as usize);
+        for (row, line) in pixels.chunks(line_len).enumerate() {
+            let offset =
+                (u64::from(rect.y) + row as u64) * u64::from(self.width) + u64::from(rect.x);
+            ops.write(self.backing + offset * 4, line.to_vec());
+        }
+        ops.send();
+
+        self.flush(rect).await
+    }
+
+    /// Transfers the given area of the backing storage to the host, then asks the host to
+    /// refresh that area on the screen.
+    async unsafe fn flush(&mut self, rect: &Rect) -> Result<(), ()> {
+        let offset = (u64::from(rect.y) * u64::from(self.width) + u64::from(rect.x)) * 4;
+        let mut request = header(VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D);
+        push_rect(&mut request, rect);
+        request.extend_from_slice(&offset.to_le_bytes());
+        push_u32(&mut request, RESOURCE_ID);
+        push_u32(&mut request, 0);
+        self.command_nodata(request).await?;
+
+        let mut request = header(VIRTIO_GPU_CMD_RESOURCE_FLUSH);
+        push_rect(&mut request, rect);
+        push_u32(&mut request, RESOURCE_ID);
+        push_u32(&mut request, 0);
+        self.command_nodata(request).await
+    }
+
+    /// Returns the resolution of the first scanout, or `None` if it isn't enabled.
+    async unsafe fn display_info(&mut self) -> Result<Option<(u32, u32)>, ()> {
+        // The response contains 16 entries of 24 bytes each.
+        let response = self
+            .command(
+                header(VIRTIO_GPU_CMD_GET_DISPLAY_INFO),
+                HEADER_LEN + 16 * 24,
+            )
+            .await;
+        if read_u32(&response, 0) != VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
+            return Err(());
+        }
+
+        let width = read_u32(&response, 32);
+        let height = read_u32(&response, 36);
+        let enabled = read_u32(&response, 40);
+        if enabled == 0 || width == 0 || height == 0 {
+            return Ok(None);
+        }
+        Ok(Some((width, height)))
+    }
+
+    fn full_screen(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Sends a command whose successful response doesn't contain any data.
+    async unsafe fn command_nodata(&mut self, request: Vec<u8>) -> Result<(), ()> {
+        let response = self.command(request, HEADER_LEN).await;
+        if read_u32(&response, 0) == VIRTIO_GPU_RESP_OK_NODATA {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Sends a command on the control queue and returns the response.
+    async unsafe fn command(&mut self, request: Vec<u8>, response_len: u32) -> Vec<u8> {
+        debug_assert!(request.len() <= BUFFER_LEN as usize);
+        debug_assert!(response_len <= BUFFER_LEN);
+
+        let request_len = request.len() as u32;
+        redshirt_hardware_interface::write(self.request_buffer, request);
+        self.controlq
+            .submit_and_wait(&[
+                Buffer {
+                    address: self.request_buffer,
+                    len: request_len,
+                    device_writable: false,
+                },
+                Buffer {
+                    address: self.response_buffer,
+                    len: response_len,
+                    device_writable: true,
+                },
+            ])
+            .await;
+
+        let mut response = vec![0; response_len as usize];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(self.response_buffer, &mut response);
+        ops.send().await;
+        response
+    }
+}
+
+/// Builds the header of a request of the given type.
+fn header(ty: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(BUFFER_LEN as usize);
+    push_u32(&mut out, ty);
+    // `flags`, `fence_id`, `ctx_id` and padding.
+    out.extend_from_slice(&[0; 20]);
+    out
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_rect(out: &mut Vec<u8>, rect: &Rect) {
+    push_u32(out, rect.x);
+    push_u32(out, rect.y);
+    push_u32(out, rect.width);
+    push_u32(out, rect.height);
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
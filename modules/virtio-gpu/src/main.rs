@@ -0,0 +1,176 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driver for virtio GPUs.
+//!
+//! This program scans the PCI space for a virtio GPU. If it finds one, it configures its first
+//! scanout and registers the framebuffer interface.
+//!
+//! Virtio GPUs don't report vertical blanks. Vsync events are instead emulated with a 60Hz timer.
+//!
+//! Bibliography:
+//!
+//! - https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html
+//!
+
+mod gpu;
+
+use futures::prelude::*;
+use parity_scale_codec::DecodeAll;
+use redshirt_framebuffer_interface::ffi;
+use redshirt_syscalls_interface::{Encode, ErrorClass, ErrorPayload, MessageId, Pid};
+
+/// Period of the emulated vertical blanks, in nanoseconds.
+const VSYNC_PERIOD: u128 = 1_000_000_000 / 60;
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    let location = match pci_devices
+        .iter()
+        .find(|device| device.vendor_id == 0x1af4 && device.device_id == 0x1050)
+    {
+        Some(device) => device.location,
+        None => return,
+    };
+
+    let mut display = match unsafe { gpu::Display::init(location).await } {
+        Ok(display) => display,
+        Err(()) => return,
+    };
+
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    // Processes waiting for vsync events, and the message to answer.
+    let mut vsync_subscribers: Vec<(Pid, MessageId)> = Vec::new();
+    // Timer emulating vertical blanks. `None` if there is no subscriber.
+    let mut vsync_timer: Option<redshirt_time_interface::Interval> = None;
+    let mut frame: u64 = 0;
+
+    loop {
+        let event = if let Some(timer) = vsync_timer.as_mut() {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            match future::select(next_message, timer.next()).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right(_) => None,
+            }
+        } else {
+            Some(redshirt_syscalls_interface::next_interface_message().await)
+        };
+
+        let msg = match event {
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m)) => m,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg)) => {
+                vsync_subscribers.retain(|(pid, _)| *pid != msg.pid);
+                if vsync_subscribers.is_empty() {
+                    vsync_timer = None;
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg)) => {
+                vsync_subscribers.retain(|(_, id)| *id != msg.message_id);
+                if vsync_subscribers.is_empty() {
+                    vsync_timer = None;
+                }
+                continue;
+            }
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_)) => continue,
+            None => {
+                frame = frame.wrapping_add(1);
+                for (_, message_id) in &vsync_subscribers {
+                    redshirt_syscalls_interface::emit_answer_partial(
+                        *message_id,
+                        ffi::VsyncEvent { frame },
+                    );
+                }
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::FramebufferMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+
+        match message {
+            ffi::FramebufferMessage::Info => {
+                let info = ffi::FramebufferInfo {
+                    width: display.width(),
+                    height: display.height(),
+                    format: ffi::PixelFormat::B8G8R8X8,
+                };
+                answer(msg.message_id, info);
+            }
+            ffi::FramebufferMessage::Present(present) => {
+                let result = if present.damage.iter().all(|d| is_valid_damage(&display, d)) {
+                    let mut result = Ok(());
+                    for damage in &present.damage {
+                        if unsafe { display.update(&damage.rect, &damage.pixels).await }.is_err() {
+                            result = Err(ErrorPayload::new(ErrorClass::IO));
+                            break;
+                        }
+                    }
+                    result
+                } else {
+                    Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                        .with_message("damage outside of the screen or of the wrong size"))
+                };
+
+                answer(msg.message_id, ffi::PresentResponse { result });
+            }
+            ffi::FramebufferMessage::SubscribeVsync => {
+                let message_id = match msg.message_id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                vsync_subscribers.push((msg.emitter_pid, message_id));
+                if vsync_timer.is_none() {
+                    let now = redshirt_time_interface::monotonic_clock().await;
+                    vsync_timer = Some(redshirt_time_interface::monotonic_interval(
+                        now + VSYNC_PERIOD,
+                        VSYNC_PERIOD,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if the damage is within the screen and contains the right number of bytes.
+fn is_valid_damage(display: &gpu::Display, damage: &ffi::Damage) -> bool {
+    let rect = &damage.rect;
+    let fits_horizontally = rect
+        .x
+        .checked_add(rect.width)
+        .map_or(false, |end| end <= display.width());
+    let fits_vertically = rect
+        .y
+        .checked_add(rect.height)
+        .map_or(false, |end| end <= display.height());
+    let expected_len = u64::from(rect.width) * u64::from(rect.height) * 4;
+    fits_horizontally && fits_vertically && damage.pixels.len() as u64 == expected_len
+}
+
+fn answer(message_id: Option<MessageId>, response: impl Encode) {
+    if let Some(message_id) = message_id {
+        redshirt_syscalls_interface::emit_answer(message_id, response);
+    }
+}
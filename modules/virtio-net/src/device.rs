@@ -0,0 +1,166 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use redshirt_hardware_interface::HardwareOperationsBuilder;
+use redshirt_pci_interface::PciDeviceLocation;
+use std::collections::HashMap;
+use virtio::{Buffer, VirtioDevice, Virtqueue};
+
+/// Feature bit indicating that the configuration structure contains the MAC address.
+const VIRTIO_NET_F_MAC: u32 = 1 << 5;
+
+/// Size of the header that precedes each packet in the buffers.
+const HEADER_LEN: usize = 12;
+/// Size of the buffers. Large enough for the header and an Ethernet frame without the CRC.
+const BUFFER_LEN: u32 = 2048;
+/// Maximum size of an Ethernet frame, without the CRC.
+const MAX_FRAME_LEN: usize = 1514;
+
+/// State of a device.
+//
+// # Implementation note
+//
+// Each buffer contains exactly one packet, and is made of a single descriptor. All the
+// descriptors of the receive queue are filled with empty buffers during initialization, and each
+// buffer is given back to the device after its content has been read.
+//
+// Buffers of the transmit queue are allocated on demand and reused once the device has sent
+// their content.
+//
+pub struct Device {
+    receiveq: Virtqueue,
+    transmitq: Virtqueue,
+    /// Physical addresses of the buffers available to the device for receiving, indexed by the
+    /// identifier of the chain.
+    receive_buffers: HashMap<u16, u64>,
+    /// Physical addresses of the buffers being transmitted, indexed by the identifier of the
+    /// chain.
+    transmit_buffers: HashMap<u16, u64>,
+    /// Physical addresses of transmit buffers that aren't in use.
+    free_transmit_buffers: Vec<u64>,
+    /// MAC address of the device.
+    mac_address: [u8; 6],
+}
+
+impl Device {
+    /// Initializes the virtio network device at the given location.
+    pub async unsafe fn init(location: PciDeviceLocation) -> Result<Self, ()> {
+        let device = VirtioDevice::from_pci(location).await.ok_or(())?;
+        let features = device.init(VIRTIO_NET_F_MAC).await?;
+        let mut receiveq = device.setup_queue(0, 64).await.ok_or(())?;
+        let transmitq = device.setup_queue(1, 64).await.ok_or(())?;
+
+        // TODO: generate a random MAC address if the device doesn't provide one
+        let mut mac_address = [0; 6];
+        if features & VIRTIO_NET_F_MAC != 0 {
+            let low = device.read_device_config_u32(0).await.to_le_bytes();
+            let high = device.read_device_config_u32(4).await.to_le_bytes();
+            mac_address[..4].copy_from_slice(&low);
+            mac_address[4..].copy_from_slice(&high[..2]);
+        }
+
+        let mut receive_buffers = HashMap::new();
+        while receiveq.num_free_descriptors() != 0 {
+            let buffer =
+                redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 8).await;
+            let chain = receiveq.push(&[Buffer {
+                address: buffer,
+                len: BUFFER_LEN,
+                device_writable: true,
+            }]);
+            receive_buffers.insert(chain, buffer);
+        }
+
+        device.driver_ok().await;
+
+        Ok(Device {
+            receiveq,
+            transmitq,
+            receive_buffers,
+            transmit_buffers: HashMap::new(),
+            free_transmit_buffers: Vec::new(),
+            mac_address,
+        })
+    }
+
+    /// Returns the MAC address of the device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    /// Reads one Ethernet frame received by the device.
+    ///
+    /// Returns `None` if there's no frame available.
+    pub async unsafe fn read_one_incoming(&mut self) -> Option<Vec<u8>> {
+        let (chain, len) = self.receiveq.pop_used().await?;
+        let buffer = self.receive_buffers.remove(&chain).unwrap();
+
+        let mut data = vec![0; len as usize];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(buffer, &mut data);
+        ops.send().await;
+
+        // Give the buffer back to the device.
+        let chain = self.receiveq.push(&[Buffer {
+            address: buffer,
+            len: BUFFER_LEN,
+            device_writable: true,
+        }]);
+        self.receive_buffers.insert(chain, buffer);
+
+        if data.len() < HEADER_LEN {
+            return None;
+        }
+        Some(data.split_off(HEADER_LEN))
+    }
+
+    /// Queues an Ethernet frame, without its CRC, for sending.
+    ///
+    /// Returns an error if the frame is too large or if the transmit queue is full.
+    pub async unsafe fn send_packet(&mut self, frame: &[u8]) -> Result<(), ()> {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(());
+        }
+
+        while let Some((chain, _)) = self.transmitq.pop_used().await {
+            let buffer = self.transmit_buffers.remove(&chain).unwrap();
+            self.free_transmit_buffers.push(buffer);
+        }
+
+        if self.transmitq.num_free_descriptors() == 0 {
+            return Err(());
+        }
+
+        let buffer = match self.free_transmit_buffers.pop() {
+            Some(b) => b,
+            None => redshirt_hardware_interface::malloc::malloc(u64::from(BUFFER_LEN), 8).await,
+        };
+
+        // All the fields of the header are 0, as we don't use any offloading feature.
+        let mut data = Vec::with_capacity(HEADER_LEN + frame.len());
+        data.extend_from_slice(&[0; HEADER_LEN]);
+        data.extend_from_slice(frame);
+        let len = data.len() as u32;
+        redshirt_hardware_interface::write(buffer, data);
+
+        let chain = self.transmitq.push(&[Buffer {
+            address: buffer,
+            len,
+            device_writable: false,
+        }]);
+        self.transmit_buffers.insert(chain, buffer);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Driver for virtio entropy devices.
+//!
+//! This program scans the PCI space for a virtio entropy device, periodically reads random
+//! data from it, and passes this data to the random interface in order to reseed the
+//! generator of the handler. This makes the generated numbers trustworthy on platforms where
+//! the kernel can't rely on the CPU to provide entropy.
+//!
+//! Bibliography:
+//!
+//! - https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html (section 5.4)
+//!
+
+use redshirt_hardware_interface::HardwareOperationsBuilder;
+use std::time::Duration;
+use virtio::{Buffer, VirtioDevice};
+
+/// Number of bytes requested from the device at each refill.
+const ENTROPY_LEN: u32 = 64;
+/// Time to wait between two refills.
+const REFILL_PERIOD: Duration = Duration::from_secs(30);
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    let pci_devices = redshirt_pci_interface::get_pci_devices().await;
+    let device = pci_devices.into_iter().find(|device| {
+        // `0x1005` is the identifier of transitional devices, which also support the modern
+        // interface.
+        device.vendor_id == 0x1af4 && (device.device_id == 0x1005 || device.device_id == 0x1044)
+    });
+
+    let location = match device {
+        Some(d) => d.location,
+        None => return,
+    };
+
+    unsafe {
+        let device = match VirtioDevice::from_pci(location).await {
+            Some(d) => d,
+            None => return,
+        };
+        // The entropy device doesn't define any feature bit.
+        if device.init(0).await.is_err() {
+            return;
+        }
+        let mut requestq = match device.setup_queue(0, 4).await {
+            Some(q) => q,
+            None => return,
+        };
+        device.driver_ok().await;
+
+        let buffer = redshirt_hardware_interface::malloc::malloc(u64::from(ENTROPY_LEN), 1).await;
+
+        loop {
+            let written = requestq
+                .submit_and_wait(&[Buffer {
+                    address: buffer,
+                    len: ENTROPY_LEN,
+                    device_writable: true,
+                }])
+                .await;
+
+            // The device is allowed to write fewer bytes than requested.
+            if written != 0 {
+                let mut entropy = vec![0; written.min(ENTROPY_LEN) as usize];
+                let mut ops = HardwareOperationsBuilder::new();
+                ops.read(buffer, &mut entropy);
+                ops.send().await;
+                redshirt_random_interface::add_entropy(entropy);
+            }
+
+            redshirt_time_interface::monotonic_wait(REFILL_PERIOD).await;
+        }
+    }
+}
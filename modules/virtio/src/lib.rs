@@ -0,0 +1,443 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Virtio devices accessed through PCI, as defined in version 1.0 of the virtio specifications.
+//!
+//! Only the "modern" interface is supported. Legacy devices, which expose their registers
+//! through I/O ports, are ignored.
+//!
+//! This library is shared between the drivers of the various virtio devices.
+
+use redshirt_hardware_interface::{HardwareOperationsBuilder, HardwareWriteOperationsBuilder};
+use redshirt_pci_interface::{PciBaseAddressRegister, PciDeviceLocation};
+use std::{collections::VecDeque, mem};
+
+/// Feature bit indicating compliance with version 1.0 of the specifications.
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 128;
+
+// Offsets of the fields within the common configuration structure.
+const COMMON_DEVICE_FEATURE_SELECT: u64 = 0x0;
+const COMMON_DEVICE_FEATURE: u64 = 0x4;
+const COMMON_DRIVER_FEATURE_SELECT: u64 = 0x8;
+const COMMON_DRIVER_FEATURE: u64 = 0xc;
+const COMMON_DEVICE_STATUS: u64 = 0x14;
+const COMMON_QUEUE_SELECT: u64 = 0x16;
+const COMMON_QUEUE_SIZE: u64 = 0x18;
+const COMMON_QUEUE_ENABLE: u64 = 0x1c;
+const COMMON_QUEUE_NOTIFY_OFF: u64 = 0x1e;
+const COMMON_QUEUE_DESC: u64 = 0x20;
+const COMMON_QUEUE_AVAIL: u64 = 0x28;
+const COMMON_QUEUE_USED: u64 = 0x30;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// Virtio device whose configuration structures have been located.
+pub struct VirtioDevice {
+    /// Physical address of the common configuration structure.
+    common_cfg: u64,
+    /// Physical address of the notification area.
+    notify_base: u64,
+    /// Value to multiply the queue notification offsets with.
+    notify_off_multiplier: u32,
+    /// Physical address of the device-specific configuration structure.
+    device_cfg: u64,
+}
+
+impl VirtioDevice {
+    /// Enables the device on the PCI bus and locates its configuration structures.
+    ///
+    /// Returns `None` if the device doesn't expose the structures required by the modern
+    /// interface.
+    pub async unsafe fn from_pci(location: PciDeviceLocation) -> Option<VirtioDevice> {
+        let status = redshirt_pci_interface::read_config_u32(location, 0x4).await >> 16;
+        if status & 0x10 == 0 {
+            // Device has no capabilities list.
+            return None;
+        }
+
+        redshirt_pci_interface::enable_bus_mastering(location);
+
+        let mut common_cfg = None;
+        let mut notify = None;
+        let mut device_cfg = None;
+
+        let mut cap_ptr =
+            (redshirt_pci_interface::read_config_u32(location, 0x34).await & 0xfc) as u8;
+        while cap_ptr != 0 {
+            let header = redshirt_pci_interface::read_config_u32(location, cap_ptr).await;
+            let cap_id = header & 0xff;
+            let next = ((header >> 8) & 0xfc) as u8;
+
+            // `0x9` is the identifier of vendor-specific capabilities, which virtio uses.
+            if cap_id == 0x9 {
+                let cfg_type = (header >> 24) & 0xff;
+                let bar =
+                    redshirt_pci_interface::read_config_u32(location, cap_ptr + 4).await & 0xff;
+                let offset = redshirt_pci_interface::read_config_u32(location, cap_ptr + 8).await;
+                let address = match redshirt_pci_interface::map_bar(location, bar as u8).await {
+                    Ok(PciBaseAddressRegister::Memory { base_address, .. }) => {
+                        base_address + u64::from(offset)
+                    }
+                    _ => {
+                        cap_ptr = next;
+                        continue;
+                    }
+                };
+
+                match cfg_type {
+                    1 if common_cfg.is_none() => common_cfg = Some(address),
+                    2 if notify.is_none() => {
+                        let multiplier =
+                            redshirt_pci_interface::read_config_u32(location, cap_ptr + 16).await;
+                        notify = Some((address, multiplier));
+                    }
+                    4 if device_cfg.is_none() => device_cfg = Some(address),
+                    _ => {}
+                }
+            }
+
+            cap_ptr = next;
+        }
+
+        let (notify_base, notify_off_multiplier) = notify?;
+        Some(VirtioDevice {
+            common_cfg: common_cfg?,
+            notify_base,
+            notify_off_multiplier,
+            device_cfg: device_cfg?,
+        })
+    }
+
+    /// Resets the device and negotiates the given device-specific features. On success, returns
+    /// the features among `features` that the device supports.
+    ///
+    /// On success, queues must then be set up with [`VirtioDevice::setup_queue`], after which
+    /// [`VirtioDevice::driver_ok`] must be called.
+    pub async unsafe fn init(&self, features: u32) -> Result<u32, ()> {
+        self.write_status(0);
+        while self.read_u8(self.common_cfg + COMMON_DEVICE_STATUS).await != 0 {}
+
+        let mut status = STATUS_ACKNOWLEDGE;
+        self.write_status(status);
+        status |= STATUS_DRIVER;
+        self.write_status(status);
+
+        let device_features = {
+            let mut ops = HardwareWriteOperationsBuilder::new();
+            ops.write_one_u32(self.common_cfg + COMMON_DEVICE_FEATURE_SELECT, 0);
+            ops.send();
+            let low = self.read_u32(self.common_cfg + COMMON_DEVICE_FEATURE).await;
+            let mut ops = HardwareWriteOperationsBuilder::new();
+            ops.write_one_u32(self.common_cfg + COMMON_DEVICE_FEATURE_SELECT, 1);
+            ops.send();
+            let high = self.read_u32(self.common_cfg + COMMON_DEVICE_FEATURE).await;
+            u64::from(low) | (u64::from(high) << 32)
+        };
+
+        if device_features & VIRTIO_F_VERSION_1 == 0 {
+            self.write_status(status | STATUS_FAILED);
+            return Err(());
+        }
+
+        let driver_features = (u64::from(features) & device_features) | VIRTIO_F_VERSION_1;
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u32(self.common_cfg + COMMON_DRIVER_FEATURE_SELECT, 0);
+        ops.write_one_u32(
+            self.common_cfg + COMMON_DRIVER_FEATURE,
+            driver_features as u32,
+        );
+        ops.write_one_u32(self.common_cfg + COMMON_DRIVER_FEATURE_SELECT, 1);
+        ops.write_one_u32(
+            self.common_cfg + COMMON_DRIVER_FEATURE,
+            (driver_features >> 32) as u32,
+        );
+        ops.send();
+
+        status |= STATUS_FEATURES_OK;
+        self.write_status(status);
+        if self.read_u8(self.common_cfg + COMMON_DEVICE_STATUS).await & STATUS_FEATURES_OK == 0 {
+            self.write_status(status | STATUS_FAILED);
+            return Err(());
+        }
+
+        Ok(driver_features as u32)
+    }
+
+    /// Allocates and enables the queue with the given index. The number of entries in the queue
+    /// is capped to `max_size`, which must be a power of two.
+    ///
+    /// Returns `None` if the device doesn't have this queue.
+    pub async unsafe fn setup_queue(&self, index: u16, max_size: u16) -> Option<Virtqueue> {
+        debug_assert!(max_size.is_power_of_two());
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write_one_u16(self.common_cfg + COMMON_QUEUE_SELECT, index);
+        ops.send();
+
+        let size = self.read_u16(self.common_cfg + COMMON_QUEUE_SIZE).await;
+        if size == 0 {
+            return None;
+        }
+        let size = size.min(max_size);
+
+        let desc_len = 16 * u64::from(size);
+        let avail_len = 6 + 2 * u64::from(size);
+        let used_len = 6 + 8 * u64::from(size);
+        let desc = redshirt_hardware_interface::malloc::malloc(desc_len, 16).await;
+        let avail = redshirt_hardware_interface::malloc::malloc(avail_len, 2).await;
+        let used = redshirt_hardware_interface::malloc::malloc(used_len, 4).await;
+
+        let notify_off = self
+            .read_u16(self.common_cfg + COMMON_QUEUE_NOTIFY_OFF)
+            .await;
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        ops.write(desc, vec![0; desc_len as usize]);
+        ops.write(avail, vec![0; avail_len as usize]);
+        ops.write(used, vec![0; used_len as usize]);
+        ops.write_one_u16(self.common_cfg + COMMON_QUEUE_SIZE, size);
+        for (offset, address) in &[
+            (COMMON_QUEUE_DESC, desc),
+            (COMMON_QUEUE_AVAIL, avail),
+            (COMMON_QUEUE_USED, used),
+        ] {
+            ops.write_one_u32(self.common_cfg + offset, *address as u32);
+            ops.write_one_u32(self.common_cfg + offset + 4, (*address >> 32) as u32);
+        }
+        ops.write_one_u16(self.common_cfg + COMMON_QUEUE_ENABLE, 1);
+        ops.send();
+
+        Some(Virtqueue {
+            index,
+            size,
+            desc,
+            avail,
+            used,
+            notify_address: self.notify_base
+                + u64::from(notify_off) * u64::from(self.notify_off_multiplier),
+            next_avail: 0,
+            last_used: 0,
+            free_descriptors: (0..size).rev().collect(),
+            chains: (0..size).map(|_| Vec::new()).collect(),
+            pending_used: VecDeque::new(),
+        })
+    }
+
+    /// Indicates to the device that the driver is ready.
+    pub async unsafe fn driver_ok(&self) {
+        let status = self.read_u8(self.common_cfg + COMMON_DEVICE_STATUS).await;
+        self.write_status(status | STATUS_DRIVER_OK);
+    }
+
+    /// Reads a `u32` in the device-specific configuration structure.
+    pub async unsafe fn read_device_config_u32(&self, offset: u64) -> u32 {
+        self.read_u32(self.device_cfg + offset).await
+    }
+
+    unsafe fn write_status(&self, status: u8) {
+        redshirt_hardware_interface::write(self.common_cfg + COMMON_DEVICE_STATUS, vec![status]);
+    }
+
+    async unsafe fn read_u8(&self, address: u64) -> u8 {
+        let mut out = [0u8];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read(address, &mut out);
+        ops.send().await;
+        out[0]
+    }
+
+    async unsafe fn read_u16(&self, address: u64) -> u16 {
+        read_u16(address).await
+    }
+
+    async unsafe fn read_u32(&self, address: u64) -> u32 {
+        let mut out = [0u32];
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read_u32(address, &mut out);
+        ops.send().await;
+        out[0]
+    }
+}
+
+/// Split virtqueue, as described in section 2.4 of the specifications.
+pub struct Virtqueue {
+    /// Index of the queue within the device.
+    index: u16,
+    /// Number of entries in the queue.
+    size: u16,
+    /// Physical address of the descriptors table.
+    desc: u64,
+    /// Physical address of the available ring.
+    avail: u64,
+    /// Physical address of the used ring.
+    used: u64,
+    /// Physical address where to write in order to notify the device.
+    notify_address: u64,
+    /// Value of the `idx` field of the available ring.
+    next_avail: u16,
+    /// Value of the `idx` field of the used ring the last time we processed it.
+    last_used: u16,
+    /// Descriptors that aren't part of any chain.
+    free_descriptors: Vec<u16>,
+    /// For each descriptor that is the head of a chain in flight, the descriptors of the chain.
+    chains: Vec<Vec<u16>>,
+    /// Elements of the used ring that [`Virtqueue::submit_and_wait`] has read while waiting for
+    /// another chain, and that [`Virtqueue::pop_used`] hasn't returned yet. The descriptors of
+    /// these chains are only freed once returned, so that their heads can't be reused before.
+    pending_used: VecDeque<(u16, u32)>,
+}
+
+/// Buffer within a chain of descriptors.
+pub struct Buffer {
+    /// Physical address of the buffer.
+    pub address: u64,
+    /// Length in bytes of the buffer.
+    pub len: u32,
+    /// If true, the device writes to the buffer. Otherwise, the device reads from it.
+    pub device_writable: bool,
+}
+
+impl Virtqueue {
+    /// Returns the number of descriptors that aren't used by any chain in flight.
+    pub fn num_free_descriptors(&self) -> usize {
+        self.free_descriptors.len()
+    }
+
+    /// Makes a chain of buffers available to the device. Returns the identifier of the chain,
+    /// which [`Virtqueue::pop_used`] returns once the device has processed it.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `buffers` is empty or if there are more buffers than free descriptors.
+    ///
+    pub unsafe fn push(&mut self, buffers: &[Buffer]) -> u16 {
+        assert!(!buffers.is_empty());
+        assert!(buffers.len() <= self.free_descriptors.len());
+
+        let split_point = self.free_descriptors.len() - buffers.len();
+        let chain = self.free_descriptors.split_off(split_point);
+
+        let mut ops = HardwareWriteOperationsBuilder::new();
+        for (index, (buffer, descriptor)) in buffers.iter().zip(chain.iter()).enumerate() {
+            let mut flags = 0;
+            let mut next = 0;
+            if let Some(next_descriptor) = chain.get(index + 1) {
+                flags |= VIRTQ_DESC_F_NEXT;
+                next = *next_descriptor;
+            }
+            if buffer.device_writable {
+                flags |= VIRTQ_DESC_F_WRITE;
+            }
+
+            let mut encoded = Vec::with_capacity(16);
+            encoded.extend_from_slice(&buffer.address.to_le_bytes());
+            encoded.extend_from_slice(&buffer.len.to_le_bytes());
+            encoded.extend_from_slice(&flags.to_le_bytes());
+            encoded.extend_from_slice(&next.to_le_bytes());
+            ops.write(self.desc + 16 * u64::from(*descriptor), encoded);
+        }
+
+        let head = chain[0];
+        let ring_slot = self.next_avail % self.size;
+        self.next_avail = self.next_avail.wrapping_add(1);
+        self.chains[usize::from(head)] = chain;
+
+        ops.write_one_u16(self.avail + 4 + 2 * u64::from(ring_slot), head);
+        ops.write_one_u16(self.avail + 2, self.next_avail);
+        ops.write_one_u16(self.notify_address, self.index);
+        ops.send();
+
+        head
+    }
+
+    /// Returns the next chain that the device has finished processing, if any, and the number
+    /// of bytes that the device has written to it. The descriptors of the chain are freed.
+    ///
+    /// Chains that [`Virtqueue::submit_and_wait`] has seen completing while waiting for another
+    /// chain are returned first.
+    pub async unsafe fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let (head, written) = match self.pending_used.pop_front() {
+            Some(element) => element,
+            None => self.read_used().await?,
+        };
+
+        self.free_chain(head);
+        Some((head, written))
+    }
+
+    /// Submits a chain of buffers to the device and waits for the device to have processed it.
+    /// Returns the number of bytes that the device has written to the chain.
+    ///
+    /// The other chains that the device finishes processing in the meantime are kept and
+    /// later returned by [`Virtqueue::pop_used`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `buffers` is empty or if there are more buffers than free descriptors.
+    ///
+    // TODO: wait for the interrupt of the queue instead of polling the used ring
+    pub async unsafe fn submit_and_wait(&mut self, buffers: &[Buffer]) -> u32 {
+        let head = self.push(buffers);
+        loop {
+            if let Some((used, written)) = self.read_used().await {
+                if used == head {
+                    self.free_chain(head);
+                    break written;
+                }
+
+                self.pending_used.push_back((used, written));
+            }
+        }
+    }
+
+    /// Reads the next element of the used ring, if any, without freeing the descriptors of the
+    /// chain.
+    async unsafe fn read_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = read_u16(self.used + 2).await;
+        if used_idx == self.last_used {
+            return None;
+        }
+
+        let mut element = [0u32; 2];
+        let ring_slot = self.last_used % self.size;
+        let mut ops = HardwareOperationsBuilder::new();
+        ops.read_u32(self.used + 4 + 8 * u64::from(ring_slot), &mut element);
+        ops.send().await;
+        self.last_used = self.last_used.wrapping_add(1);
+
+        Some((element[0] as u16, element[1]))
+    }
+
+    /// Frees the descriptors of the chain whose head is `head`.
+    fn free_chain(&mut self, head: u16) {
+        let chain = mem::replace(&mut self.chains[usize::from(head)], Vec::new());
+        self.free_descriptors.extend(chain);
+    }
+}
+
+async unsafe fn read_u16(address: u64) -> u16 {
+    let mut out = [0u16];
+    let mut ops = HardwareOperationsBuilder::new();
+    ops.read_u16(address, &mut out);
+    ops.send().await;
+    out[0]
+}
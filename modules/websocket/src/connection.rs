@@ -0,0 +1,304 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! WebSocket connection whose handshake is finished.
+
+use crate::{frame, handshake::Handshake, transport::Transport};
+use futures::{channel::mpsc, prelude::*};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId};
+use redshirt_websocket_interface::ffi;
+use std::pin::Pin;
+
+/// Maximum size of a message, after reassembling its fragments.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Message concerning a connection, sent by the main task.
+pub enum Command {
+    /// A [`ffi::WebSocketMessage::Send`] has been received.
+    Send(Option<MessageId>, ffi::Message),
+    /// A [`ffi::WebSocketMessage::Receive`] has been received.
+    Receive(MessageId),
+    /// The given message has been cancelled by its emitter.
+    Cancelled(MessageId),
+}
+
+/// Which side of the connection we are. Determines whether frames are masked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// Performs the client handshake, answers `connect_message`, then processes the commands
+/// until the sender of `commands` is destroyed or the connection fails.
+pub async fn connect_and_run(
+    connection_id: u32,
+    connect: ffi::Connect,
+    connect_message: MessageId,
+    commands: mpsc::UnboundedReceiver<Command>,
+) {
+    match crate::handshake::client(&connect).await {
+        Ok(handshake) => {
+            let response = ffi::ConnectResponse {
+                result: Ok(connection_id),
+            };
+            redshirt_syscalls_interface::emit_answer(connect_message, &response);
+            run(Connection::new(handshake, Role::Client), commands).await
+        }
+        Err(err) => {
+            let response = ffi::ConnectResponse { result: Err(err) };
+            redshirt_syscalls_interface::emit_answer(connect_message, &response);
+        }
+    }
+}
+
+/// Processes the commands until the sender of `commands` is destroyed or the connection fails,
+/// then sends a close frame.
+pub async fn run(mut connection: Connection, mut commands: mpsc::UnboundedReceiver<Command>) {
+    // Receive message waiting for a message.
+    let mut pending_receive: Option<MessageId> = None;
+    // Read of the transport in progress. Only started when a receive message is pending.
+    let mut read_in_progress: Option<Pin<Box<dyn Future<Output = Result<Vec<u8>, ErrorPayload>>>>> =
+        None;
+
+    loop {
+        if let Some(message_id) = pending_receive {
+            match connection.next_message().await {
+                Ok(Some(message)) => {
+                    let response = ffi::ReceiveResponse {
+                        result: Ok(message),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    pending_receive = None;
+                }
+                Ok(None) => {
+                    if read_in_progress.is_none() {
+                        read_in_progress = Some(connection.transport.read());
+                    }
+                }
+                Err(err) => {
+                    let response = ffi::ReceiveResponse { result: Err(err) };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    break;
+                }
+            }
+        }
+
+        let event = match read_in_progress.as_mut() {
+            Some(read) => match future::select(commands.next(), read).await {
+                future::Either::Left((command, _)) => future::Either::Left(command),
+                future::Either::Right((data, _)) => future::Either::Right(data),
+            },
+            None => future::Either::Left(commands.next().await),
+        };
+
+        match event {
+            // The connection has been closed.
+            future::Either::Left(None) => break,
+            future::Either::Left(Some(Command::Receive(message_id))) => {
+                if pending_receive.is_some() {
+                    let response = ffi::ReceiveResponse {
+                        result: Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                            .with_message("a receive is already in progress")),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                } else {
+                    pending_receive = Some(message_id);
+                }
+            }
+            future::Either::Left(Some(Command::Send(message_id, message))) => {
+                let result = connection.send(message).await;
+                let failed = result.is_err();
+                if let Some(message_id) = message_id {
+                    let response = ffi::SendResponse { result };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+                if failed {
+                    break;
+                }
+            }
+            future::Either::Left(Some(Command::Cancelled(message_id))) => {
+                if pending_receive == Some(message_id) {
+                    pending_receive = None;
+                }
+            }
+            future::Either::Right(data) => {
+                read_in_progress = None;
+                match data {
+                    Ok(data) => connection.on_data(data),
+                    Err(err) => {
+                        if let Some(message_id) = pending_receive.take() {
+                            let response = ffi::ReceiveResponse { result: Err(err) };
+                            redshirt_syscalls_interface::emit_answer(message_id, &response);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let _ = connection.close().await;
+}
+
+/// State of a connection.
+pub struct Connection {
+    transport: Transport,
+    role: Role,
+    /// Data received and not decoded yet.
+    buffer: Vec<u8>,
+    /// Opcode and data of the fragments of a message received so far.
+    fragments: Option<(u8, Vec<u8>)>,
+    /// True if we have received a close frame.
+    close_received: bool,
+    /// True if we have sent a close frame.
+    close_sent: bool,
+    /// True if the remote has closed the TCP connection.
+    transport_closed: bool,
+}
+
+impl Connection {
+    pub fn new(handshake: Handshake, role: Role) -> Connection {
+        Connection {
+            transport: handshake.transport,
+            role,
+            buffer: handshake.leftover,
+            fragments: None,
+            close_received: false,
+            close_sent: false,
+            transport_closed: false,
+        }
+    }
+
+    /// Sends a message.
+    async fn send(&mut self, message: ffi::Message) -> Result<(), ErrorPayload> {
+        if self.close_sent {
+            return Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                .with_message("the connection is closing"));
+        }
+
+        match message {
+            ffi::Message::Text(text) => self.send_frame(frame::OPCODE_TEXT, text.as_bytes()).await,
+            ffi::Message::Binary(data) => self.send_frame(frame::OPCODE_BINARY, &data).await,
+        }
+    }
+
+    /// Sends a close frame with the "normal closure" status code, if not done yet.
+    async fn close(&mut self) -> Result<(), ErrorPayload> {
+        if self.close_sent || self.transport_closed {
+            return Ok(());
+        }
+        self.close_sent = true;
+        self.send_frame(frame::OPCODE_CLOSE, &1000u16.to_be_bytes())
+            .await
+    }
+
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), ErrorPayload> {
+        let mask = match self.role {
+            Role::Client => {
+                let mut mask = [0; 4];
+                redshirt_random_interface::generate_in(&mut mask).await;
+                Some(mask)
+            }
+            Role::Server => None,
+        };
+
+        self.transport
+            .write(frame::encode(opcode, payload, mask))
+            .await
+    }
+
+    /// Processes data read from the transport.
+    fn on_data(&mut self, data: Vec<u8>) {
+        if data.is_empty() {
+            self.transport_closed = true;
+        } else {
+            self.buffer.extend_from_slice(&data);
+        }
+    }
+
+    /// Decodes the frames in the buffer, answering pings and close frames, until a full
+    /// message is available. Returns `Ok(None)` if more data is needed, and `Ok(Some(None))` if
+    /// the remote has closed the connection.
+    async fn next_message(&mut self) -> Result<Option<Option<ffi::Message>>, ErrorPayload> {
+        loop {
+            if self.close_received {
+                return Ok(Some(None));
+            }
+
+            let frame = match frame::decode(&mut self.buffer, self.role == Role::Server)
+                .map_err(protocol_error)?
+            {
+                Some(f) => f,
+                None if self.transport_closed => {
+                    return Err(protocol_error("connection closed without a close frame"))
+                }
+                None => return Ok(None),
+            };
+
+            match frame.opcode {
+                frame::OPCODE_PING => self.send_frame(frame::OPCODE_PONG, &frame.payload).await?,
+                frame::OPCODE_PONG => {}
+                frame::OPCODE_CLOSE => {
+                    self.close_received = true;
+                    if !self.close_sent {
+                        // Echo the status code, as recommended.
+                        self.close_sent = true;
+                        let code = frame.payload.get(..2).unwrap_or(&[]).to_vec();
+                        self.send_frame(frame::OPCODE_CLOSE, &code).await?;
+                    }
+                }
+                frame::OPCODE_TEXT | frame::OPCODE_BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(protocol_error("unexpected data frame"));
+                    }
+                    if frame.fin {
+                        return Ok(Some(Some(to_message(frame.opcode, frame.payload)?)));
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                frame::OPCODE_CONTINUATION => {
+                    let (opcode, mut data) = self
+                        .fragments
+                        .take()
+                        .ok_or_else(|| protocol_error("unexpected continuation frame"))?;
+                    if data.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                        return Err(protocol_error("message too large"));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return Ok(Some(Some(to_message(opcode, data)?)));
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+                _ => return Err(protocol_error("unknown opcode")),
+            }
+        }
+    }
+}
+
+fn to_message(opcode: u8, data: Vec<u8>) -> Result<ffi::Message, ErrorPayload> {
+    if opcode == frame::OPCODE_TEXT {
+        String::from_utf8(data)
+            .map(ffi::Message::Text)
+            .map_err(|_| protocol_error("invalid UTF-8 in text message"))
+    } else {
+        Ok(ffi::Message::Binary(data))
+    }
+}
+
+fn protocol_error(msg: &str) -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message(format!("protocol error: {}", msg))
+}
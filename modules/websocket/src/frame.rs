@@ -0,0 +1,243 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Encoding and decoding of WebSocket frames.
+
+use std::convert::TryFrom as _;
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_BINARY: u8 = 0x2;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xa;
+
+/// Maximum size of the payload of a frame.
+pub const MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Decoded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// True if this is the last frame of a message.
+    pub fin: bool,
+    pub opcode: u8,
+    /// Payload, already unmasked.
+    pub payload: Vec<u8>,
+}
+
+/// Extracts a frame from the beginning of `buffer`. Returns `Ok(None)` if `buffer` doesn't
+/// contain a full frame yet.
+///
+/// Frames sent by clients are always masked, and frames sent by servers never are.
+pub fn decode(buffer: &mut Vec<u8>, expect_masked: bool) -> Result<Option<Frame>, &'static str> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    if buffer[0] & 0x70 != 0 {
+        return Err("reserved bits set");
+    }
+    let fin = buffer[0] & 0x80 != 0;
+    let opcode = buffer[0] & 0xf;
+    let masked = buffer[1] & 0x80 != 0;
+    if masked != expect_masked {
+        return Err("invalid masking");
+    }
+
+    let (len, mut offset) = match buffer[1] & 0x7f {
+        126 => {
+            if buffer.len() < 4 {
+                return Ok(None);
+            }
+            (u64::from(u16::from_be_bytes([buffer[2], buffer[3]])), 4)
+        }
+        127 => {
+            if buffer.len() < 10 {
+                return Ok(None);
+            }
+            let mut len = [0; 8];
+            len.copy_from_slice(&buffer[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (u64::from(len), 2),
+    };
+
+    if opcode >= OPCODE_CLOSE && (!fin || len > 125) {
+        return Err("invalid control frame");
+    }
+    if len > MAX_FRAME_SIZE {
+        return Err("frame too large");
+    }
+    let len = usize::try_from(len).unwrap();
+
+    let mask = if masked {
+        if buffer.len() < offset + 4 {
+            return Ok(None);
+        }
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&buffer[offset..offset + 4]);
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+
+    if buffer.len() < offset + len {
+        return Ok(None);
+    }
+
+    let mut payload = buffer[offset..offset + len].to_vec();
+    buffer.drain(..offset + len);
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+/// Encodes a frame with the `fin` bit set. Clients must pass a random mask.
+pub fn encode(opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 14);
+    out.push(0x80 | opcode);
+
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    if let Ok(len @ 0..=125) = u8::try_from(payload.len()) {
+        out.push(mask_bit | len);
+    } else if let Ok(len) = u16::try_from(payload.len()) {
+        out.push(mask_bit | 126);
+        out.extend_from_slice(&len.to_be_bytes());
+    } else {
+        out.push(mask_bit | 127);
+        out.extend_from_slice(&u64::try_from(payload.len()).unwrap().to_be_bytes());
+    }
+
+    let start = out.len();
+    if let Some(mask) = mask {
+        out.extend_from_slice(&mask);
+    }
+    out.extend_from_slice(payload);
+    if let Some(mask) = mask {
+        apply_mask(&mut out[start + 4..], mask);
+    }
+    out
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (n, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[n % 4];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Frame, MAX_FRAME_SIZE, OPCODE_BINARY, OPCODE_PING, OPCODE_TEXT};
+
+    fn round_trip(payload_len: usize, mask: Option<[u8; 4]>) {
+        let payload = (0..payload_len).map(|n| n as u8).collect::<Vec<_>>();
+        let mut buffer = encode(OPCODE_BINARY, &payload, mask);
+        buffer.extend_from_slice(b"next");
+
+        let frame = decode(&mut buffer, mask.is_some()).unwrap().unwrap();
+        assert_eq!(
+            frame,
+            Frame {
+                fin: true,
+                opcode: OPCODE_BINARY,
+                payload,
+            }
+        );
+        assert_eq!(buffer, b"next");
+    }
+
+    #[test]
+    fn length_7_bits() {
+        assert_eq!(encode(OPCODE_TEXT, b"hello", None)[..2], [0x81, 5]);
+        round_trip(0, None);
+        round_trip(125, None);
+    }
+
+    #[test]
+    fn length_16_bits() {
+        assert_eq!(
+            encode(OPCODE_BINARY, &[0; 126], None)[..4],
+            [0x82, 126, 0, 126]
+        );
+        round_trip(126, None);
+        round_trip(65535, None);
+    }
+
+    #[test]
+    fn length_64_bits() {
+        assert_eq!(
+            encode(OPCODE_BINARY, &[0; 65536], None)[..10],
+            [0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]
+        );
+        round_trip(65536, None);
+    }
+
+    #[test]
+    fn masking() {
+        // Example from RFC 6455, section 5.7.
+        let encoded = encode(OPCODE_TEXT, b"Hello", Some([0x37, 0xfa, 0x21, 0x3d]));
+        assert_eq!(
+            encoded,
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+        round_trip(300, Some([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn unexpected_masking_rejected() {
+        let mut buffer = encode(OPCODE_TEXT, b"Hello", Some([1, 2, 3, 4]));
+        assert!(decode(&mut buffer, false).is_err());
+        let mut buffer = encode(OPCODE_TEXT, b"Hello", None);
+        assert!(decode(&mut buffer, true).is_err());
+    }
+
+    #[test]
+    fn incomplete_frame() {
+        let encoded = encode(OPCODE_BINARY, &[0; 200], Some([1, 2, 3, 4]));
+        for len in 0..encoded.len() {
+            let mut buffer = encoded[..len].to_vec();
+            assert_eq!(decode(&mut buffer, true), Ok(None));
+            assert_eq!(buffer.len(), len);
+        }
+    }
+
+    #[test]
+    fn too_large_rejected() {
+        let mut buffer = vec![0x82, 127];
+        buffer.extend_from_slice(&(MAX_FRAME_SIZE + 1).to_be_bytes());
+        assert_eq!(decode(&mut buffer, false), Err("frame too large"));
+    }
+
+    #[test]
+    fn invalid_control_frames_rejected() {
+        let mut buffer = encode(OPCODE_PING, &[0; 126], None);
+        assert_eq!(decode(&mut buffer, false), Err("invalid control frame"));
+        let mut buffer = vec![OPCODE_PING, 0];
+        assert_eq!(decode(&mut buffer, false), Err("invalid control frame"));
+    }
+
+    #[test]
+    fn reserved_bits_rejected() {
+        let mut buffer = vec![0xc2, 0];
+        assert_eq!(decode(&mut buffer, false), Err("reserved bits set"));
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Opening handshake, performed over HTTP/1.1.
+
+use crate::transport::Transport;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use redshirt_websocket_interface::ffi;
+
+/// Value appended to the key of the client before hashing it.
+const KEY_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Maximum size of the head of a handshake request or response.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Maximum number of headers in a handshake request or response.
+const MAX_HEADERS: usize = 64;
+
+/// Connection whose handshake has succeeded.
+pub struct Handshake {
+    pub transport: Transport,
+    /// Data received after the handshake, containing the beginning of the first frames.
+    pub leftover: Vec<u8>,
+    /// Path and query of the handshake request.
+    pub path: String,
+    /// Headers of the handshake request, for the server, or response, for the client.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+/// Connects to a server and performs the client side of the handshake.
+pub async fn client(connect: &ffi::Connect) -> Result<Handshake, ErrorPayload> {
+    let url = parse_url(&connect.url)
+        .map_err(|()| ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid URL"))?;
+
+    let mut key = [0; 16];
+    redshirt_random_interface::generate_in(&mut key).await;
+    let key = base64::encode(&key);
+
+    let mut request = Vec::with_capacity(256);
+    request.extend_from_slice(b"GET ");
+    request.extend_from_slice(url.path.as_bytes());
+    request.extend_from_slice(b" HTTP/1.1\r\nHost: ");
+    request.extend_from_slice(url.authority.as_bytes());
+    request.extend_from_slice(b"\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n");
+    request.extend_from_slice(b"Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: ");
+    request.extend_from_slice(key.as_bytes());
+    request.extend_from_slice(b"\r\n");
+    for (name, value) in &connect.headers {
+        if name.is_empty()
+            || name.bytes().any(|b| !b.is_ascii_graphic() || b == b':')
+            || value.iter().any(|b| *b == b'\r' || *b == b'\n')
+        {
+            return Err(
+                ErrorPayload::new(ErrorClass::INVALID_REQUEST).with_message("invalid header")
+            );
+        }
+        request.extend_from_slice(name.as_bytes());
+        request.extend_from_slice(b": ");
+        request.extend_from_slice(value);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.extend_from_slice(b"\r\n");
+
+    let mut transport = Transport::connect(&url.host, url.port, url.tls).await?;
+    transport.write(request).await?;
+
+    let mut buffer = Vec::new();
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        match response.parse(&buffer) {
+            Ok(httparse::Status::Complete(len)) => {
+                if response.code != Some(101) {
+                    return Err(
+                        ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message(format!(
+                            "server answered with status {}",
+                            response.code.unwrap()
+                        )),
+                    );
+                }
+                let expected = accept_key(key.as_bytes());
+                if header(response.headers, "Sec-WebSocket-Accept") != Some(expected.as_bytes()) {
+                    return Err(handshake_error("invalid Sec-WebSocket-Accept"));
+                }
+
+                let headers = to_owned_headers(response.headers);
+                buffer.drain(..len);
+                return Ok(Handshake {
+                    transport,
+                    leftover: buffer,
+                    path: url.path,
+                    headers,
+                });
+            }
+            Ok(httparse::Status::Partial) => {}
+            Err(err) => return Err(handshake_error(&err.to_string())),
+        }
+
+        read_more(&mut transport, &mut buffer).await?;
+    }
+}
+
+/// Performs the server side of the handshake on a connection that has just been accepted.
+pub async fn server(mut transport: Transport) -> Result<Handshake, ErrorPayload> {
+    let mut buffer = Vec::new();
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        match request.parse(&buffer) {
+            Ok(httparse::Status::Complete(len)) => {
+                let key = header(request.headers, "Sec-WebSocket-Key");
+                let valid = request.method == Some("GET")
+                    && header_contains(request.headers, "Upgrade", "websocket")
+                    && header_contains(request.headers, "Connection", "upgrade")
+                    && header(request.headers, "Sec-WebSocket-Version") == Some(b"13");
+                let key = match key {
+                    Some(key) if valid => key,
+                    _ => {
+                        let response = b"HTTP/1.1 400 Bad Request\r\n\
+                            Sec-WebSocket-Version: 13\r\nContent-Length: 0\r\n\r\n";
+                        let _ = transport.write(response.to_vec()).await;
+                        return Err(handshake_error("invalid handshake request"));
+                    }
+                };
+
+                let response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    accept_key(key)
+                );
+                let path = request.path.unwrap().to_owned();
+                let headers = to_owned_headers(request.headers);
+                buffer.drain(..len);
+
+                transport.write(response.into_bytes()).await?;
+                return Ok(Handshake {
+                    transport,
+                    leftover: buffer,
+                    path,
+                    headers,
+                });
+            }
+            Ok(httparse::Status::Partial) => {}
+            Err(err) => return Err(handshake_error(&err.to_string())),
+        }
+
+        read_more(&mut transport, &mut buffer).await?;
+    }
+}
+
+/// Reads data from the transport into `buffer`.
+async fn read_more(transport: &mut Transport, buffer: &mut Vec<u8>) -> Result<(), ErrorPayload> {
+    if buffer.len() > MAX_HEAD_SIZE {
+        return Err(handshake_error("head too large"));
+    }
+    let data = transport.read().await?;
+    if data.is_empty() {
+        return Err(handshake_error("connection closed during the handshake"));
+    }
+    buffer.extend_from_slice(&data);
+    Ok(())
+}
+
+/// Calculates the value of the `Sec-WebSocket-Accept` header corresponding to a key.
+fn accept_key(key: &[u8]) -> String {
+    let mut hasher = sha1::Sha1::new();
+    hasher.update(key);
+    hasher.update(KEY_GUID.as_bytes());
+    base64::encode(&hasher.digest().bytes())
+}
+
+/// Returns the value of the first header with the given name.
+fn header<'a>(headers: &[httparse::Header<'a>], name: &str) -> Option<&'a [u8]> {
+    headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value)
+}
+
+/// Returns true if the header with the given name contains `token` in its comma-separated list
+/// of values, case-insensitive.
+fn header_contains(headers: &[httparse::Header], name: &str, token: &str) -> bool {
+    headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(name))
+        .flat_map(|h| h.value.split(|b| *b == b','))
+        .any(|v| {
+            String::from_utf8_lossy(v)
+                .trim()
+                .eq_ignore_ascii_case(token)
+        })
+}
+
+fn to_owned_headers(headers: &[httparse::Header]) -> Vec<(String, Vec<u8>)> {
+    headers
+        .iter()
+        .map(|h| (h.name.to_owned(), h.value.to_owned()))
+        .collect()
+}
+
+fn handshake_error(msg: &str) -> ErrorPayload {
+    ErrorPayload::new(ErrorClass::IO).with_message(format!("handshake failed: {}", msg))
+}
+
+/// Components of a `ws` or `wss` URL.
+struct Url {
+    tls: bool,
+    host: String,
+    port: u16,
+    /// Host and port as found in the URL, to put in the `Host` header.
+    authority: String,
+    /// Path and query, starting with `/`.
+    path: String,
+}
+
+fn parse_url(url: &str) -> Result<Url, ()> {
+    let (tls, rest) = if url.len() >= 5 && url[..5].eq_ignore_ascii_case("ws://") {
+        (false, &url[5..])
+    } else if url.len() >= 6 && url[..6].eq_ignore_ascii_case("wss://") {
+        (true, &url[6..])
+    } else {
+        return Err(());
+    };
+
+    // Fragments aren't allowed in WebSocket URLs.
+    if rest.contains('#') {
+        return Err(());
+    }
+
+    let (authority, path) = match rest.find(|c| c == '/' || c == '?') {
+        Some(pos) if rest[pos..].starts_with('/') => (&rest[..pos], rest[pos..].to_owned()),
+        Some(pos) => (&rest[..pos], format!("/{}", &rest[pos..])),
+        None => (rest, "/".to_owned()),
+    };
+
+    let (host, port) = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(())?;
+        (&authority[1..end], &authority[end + 1..])
+    } else {
+        match authority.rfind(':') {
+            Some(pos) => (&authority[..pos], &authority[pos..]),
+            None => (authority, ""),
+        }
+    };
+
+    let port = if port.is_empty() {
+        if tls {
+            443
+        } else {
+            80
+        }
+    } else if port.starts_with(':') {
+        port[1..].parse().map_err(|_| ())?
+    } else {
+        return Err(());
+    };
+
+    if host.is_empty() || host.contains('@') {
+        return Err(());
+    }
+
+    Ok(Url {
+        tls,
+        host: host.to_owned(),
+        port,
+        authority: authority.to_owned(),
+        path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_key, parse_url};
+
+    #[test]
+    fn accept_key_rfc_example() {
+        // Example from RFC 6455, section 1.3.
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn url_default_ports() {
+        let url = parse_url("ws://example.com").unwrap();
+        assert!(!url.tls);
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.port, 80);
+        assert_eq!(url.path, "/");
+
+        let url = parse_url("WSS://example.com/chat?room=1").unwrap();
+        assert!(url.tls);
+        assert_eq!(url.port, 443);
+        assert_eq!(url.path, "/chat?room=1");
+    }
+
+    #[test]
+    fn url_explicit_port_and_ipv6() {
+        let url = parse_url("ws://[::1]:8080?a=b").unwrap();
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.authority, "[::1]:8080");
+        assert_eq!(url.path, "/?a=b");
+    }
+
+    #[test]
+    fn url_invalid() {
+        assert!(parse_url("http://example.com").is_err());
+        assert!(parse_url("ws://example.com/#fragment").is_err());
+        assert!(parse_url("ws://user@example.com").is_err());
+        assert!(parse_url("ws://:80").is_err());
+        assert!(parse_url("ws://example.com:port").is_err());
+    }
+}
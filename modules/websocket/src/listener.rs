@@ -0,0 +1,94 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Accepting connections on a TCP port.
+
+use crate::{handshake, transport::Transport};
+use futures::{channel::mpsc, prelude::*};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId};
+use redshirt_tcp_interface::TcpListener;
+use redshirt_websocket_interface::ffi;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    pin::Pin,
+};
+
+/// Connection accepted by a listener, sent to the main task.
+pub struct Event {
+    pub listener_id: u32,
+    pub handshake: handshake::Handshake,
+}
+
+/// Binds the TCP listener, answers `listen_message`, then accepts connections and performs
+/// their handshake. Runs until the receiver of `events` is destroyed.
+pub async fn run(
+    listener_id: u32,
+    listen: ffi::Listen,
+    listen_message: MessageId,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), listen.port);
+    let tcp_listener = match TcpListener::bind(&addr).await {
+        Ok(l) => {
+            let response = ffi::ListenResponse {
+                result: Ok((listener_id, l.local_addr().port())),
+            };
+            redshirt_syscalls_interface::emit_answer(listen_message, &response);
+            l
+        }
+        Err(()) => {
+            let response = ffi::ListenResponse {
+                result: Err(ErrorPayload::new(ErrorClass::UNAVAILABLE)
+                    .with_message("failed to bind TCP listener")),
+            };
+            redshirt_syscalls_interface::emit_answer(listen_message, &response);
+            return;
+        }
+    };
+
+    let mut incoming = Box::pin(stream::unfold(tcp_listener, |mut l| async move {
+        let connec = l.accept().await.0;
+        Some((connec, l))
+    }));
+
+    // Handshakes in progress.
+    let mut handshakes = stream::FuturesUnordered::<
+        Pin<Box<dyn Future<Output = Result<handshake::Handshake, ErrorPayload>>>>,
+    >::new();
+    // `FuturesUnordered` yields `None` when empty, which we don't want.
+    handshakes.push(Box::pin(future::pending()));
+
+    loop {
+        match future::select(incoming.next(), handshakes.next()).await {
+            future::Either::Left((Some(tcp), _)) => {
+                let transport = Transport::Tcp(tcp.into_raw_handle());
+                handshakes.push(Box::pin(handshake::server(transport)));
+            }
+            future::Either::Left((None, _)) => break,
+            future::Either::Right((Some(Ok(handshake)), _)) => {
+                let event = Event {
+                    listener_id,
+                    handshake,
+                };
+                if events.unbounded_send(event).is_err() {
+                    break;
+                }
+            }
+            // Failed handshakes are silently discarded.
+            future::Either::Right((Some(Err(_)), _)) => {}
+            future::Either::Right((None, _)) => unreachable!(),
+        }
+    }
+}
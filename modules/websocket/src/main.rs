@@ -0,0 +1,319 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! WebSocket client and server.
+//!
+//! Registers the WebSocket interface. Each connection is driven by a separate task that
+//! receives the messages concerning it through a channel. Connections are established through
+//! the TCP interface, and through the TLS interface for `wss` URLs.
+//!
+//! Listeners bind their own TCP port.
+// TODO: allow serving WebSocket routes through the HTTP server module; this requires the HTTP
+// server interface to hand over upgraded connections
+
+mod connection;
+mod frame;
+mod handshake;
+mod listener;
+mod transport;
+
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+};
+use parity_scale_codec::DecodeAll;
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload, MessageId, Pid};
+use redshirt_websocket_interface::ffi;
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+};
+
+/// Maximum number of connections waiting to be accepted on a listener. Additional
+/// connections are closed.
+const MAX_QUEUED_CONNECTIONS: usize = 16;
+
+/// Value produced by a task when it ends.
+enum TaskEnd {
+    Connection(u32),
+    Listener(u32),
+}
+
+struct Listener {
+    owner: Pid,
+    /// [`ffi::WebSocketMessage::Accept`] message waiting for a connection.
+    waiting: Option<MessageId>,
+    /// Connections accepted but not reported yet.
+    queue: VecDeque<ffi::Accepted>,
+    /// Destroying this sender stops the task of the listener.
+    _abort: oneshot::Sender<()>,
+}
+
+fn main() {
+    redshirt_syscalls_interface::block_on(async_main());
+}
+
+async fn async_main() {
+    redshirt_interface_interface::register_interface(ffi::INTERFACE)
+        .await
+        .unwrap();
+
+    // Open connections, with the process that owns them and the channel to their task.
+    // Destroying the sender closes the connection.
+    let mut connections: HashMap<u32, (Pid, mpsc::UnboundedSender<connection::Command>)> =
+        HashMap::new();
+    let mut next_connection_id: u32 = 0;
+    let mut listeners: HashMap<u32, Listener> = HashMap::new();
+    let mut next_listener_id: u32 = 0;
+
+    // Connections accepted by the listener tasks.
+    let (events_tx, mut events_rx) = mpsc::unbounded::<listener::Event>();
+
+    // Tasks driving the connections and listeners.
+    let mut tasks = stream::FuturesUnordered::<Pin<Box<dyn Future<Output = TaskEnd>>>>::new();
+    // `FuturesUnordered` yields `None` when empty, which we don't want.
+    tasks.push(Box::pin(future::pending()));
+
+    loop {
+        let internal_event = future::select(tasks.next(), events_rx.next());
+        let msg = match future::select(
+            redshirt_syscalls_interface::next_interface_message(),
+            internal_event,
+        )
+        .await
+        {
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m),
+                _,
+            )) => m,
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(msg),
+                _,
+            )) => {
+                connections.retain(|_, (owner, _)| *owner != msg.pid);
+                listeners.retain(|_, l| l.owner != msg.pid);
+                continue;
+            }
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(msg),
+                _,
+            )) => {
+                for (_, commands) in connections.values() {
+                    let command = connection::Command::Cancelled(msg.message_id);
+                    let _ = commands.unbounded_send(command);
+                }
+                for listener in listeners.values_mut() {
+                    if listener.waiting == Some(msg.message_id) {
+                        listener.waiting = None;
+                    }
+                }
+                continue;
+            }
+            future::Either::Left((
+                redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_),
+                _,
+            )) => continue,
+            future::Either::Right((future::Either::Left((task_end, _)), _)) => {
+                match task_end.unwrap() {
+                    TaskEnd::Connection(id) => {
+                        connections.remove(&id);
+                    }
+                    TaskEnd::Listener(id) => {
+                        listeners.remove(&id);
+                    }
+                }
+                continue;
+            }
+            future::Either::Right((future::Either::Right((event, _)), _)) => {
+                // `events_tx` is never destroyed.
+                let event = event.unwrap();
+                let listener = match listeners.get_mut(&event.listener_id) {
+                    Some(l) => l,
+                    None => continue,
+                };
+                if listener.queue.len() >= MAX_QUEUED_CONNECTIONS {
+                    continue;
+                }
+
+                let connection_id = next_connection_id;
+                next_connection_id = next_connection_id.wrapping_add(1);
+                let accepted = ffi::Accepted {
+                    connection_id,
+                    path: event.handshake.path.clone(),
+                    headers: event.handshake.headers.clone(),
+                };
+
+                let (tx, rx) = mpsc::unbounded();
+                connections.insert(connection_id, (listener.owner, tx));
+                let connection =
+                    connection::Connection::new(event.handshake, connection::Role::Server);
+                tasks.push(Box::pin(
+                    connection::run(connection, rx)
+                        .map(move |()| TaskEnd::Connection(connection_id)),
+                ));
+
+                if let Some(message_id) = listener.waiting.take() {
+                    let response = ffi::AcceptResponse {
+                        result: Ok(accepted),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                } else {
+                    listener.queue.push_back(accepted);
+                }
+                continue;
+            }
+        };
+        assert_eq!(msg.interface, ffi::INTERFACE);
+
+        let message: ffi::WebSocketMessage = match DecodeAll::decode_all(&msg.actual_data) {
+            Ok(m) => m,
+            Err(_) => {
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_message_error(message_id);
+                }
+                continue;
+            }
+        };
+
+        // Returns the channel to the task of a connection, if it belongs to the emitter.
+        let connection_commands = |connection_id: u32| {
+            connections
+                .get(&connection_id)
+                .filter(|(owner, _)| *owner == msg.emitter_pid)
+                .map(|(_, commands)| commands.clone())
+        };
+
+        match message {
+            ffi::WebSocketMessage::Connect(connect) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                let connection_id = next_connection_id;
+                next_connection_id = next_connection_id.wrapping_add(1);
+                let (tx, rx) = mpsc::unbounded();
+                connections.insert(connection_id, (msg.emitter_pid, tx));
+                tasks.push(Box::pin(
+                    connection::connect_and_run(connection_id, connect, message_id, rx)
+                        .map(move |()| TaskEnd::Connection(connection_id)),
+                ));
+            }
+            ffi::WebSocketMessage::Listen(listen) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                let listener_id = next_listener_id;
+                next_listener_id = next_listener_id.wrapping_add(1);
+                let (abort_tx, abort_rx) = oneshot::channel();
+                listeners.insert(
+                    listener_id,
+                    Listener {
+                        owner: msg.emitter_pid,
+                        waiting: None,
+                        queue: VecDeque::new(),
+                        _abort: abort_tx,
+                    },
+                );
+                let task = listener::run(listener_id, listen, message_id, events_tx.clone());
+                tasks.push(Box::pin(
+                    future::select(abort_rx, Box::pin(task))
+                        .map(move |_| TaskEnd::Listener(listener_id)),
+                ));
+            }
+            ffi::WebSocketMessage::Accept(listener_id) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                let result = match listeners.get_mut(&listener_id) {
+                    Some(l) if l.owner == msg.emitter_pid => {
+                        if l.waiting.is_some() {
+                            Err(ErrorPayload::new(ErrorClass::INVALID_REQUEST)
+                                .with_message("an accept is already in progress"))
+                        } else if let Some(accepted) = l.queue.pop_front() {
+                            Ok(accepted)
+                        } else {
+                            l.waiting = Some(message_id);
+                            continue;
+                        }
+                    }
+                    _ => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                };
+
+                let response = ffi::AcceptResponse { result };
+                redshirt_syscalls_interface::emit_answer(message_id, &response);
+            }
+            ffi::WebSocketMessage::StopListening(listener_id) => {
+                match listeners.get(&listener_id) {
+                    Some(l) if l.owner == msg.emitter_pid => {}
+                    _ => continue,
+                }
+
+                let listener = listeners.remove(&listener_id).unwrap();
+                if let Some(message_id) = listener.waiting {
+                    let response = ffi::AcceptResponse {
+                        result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                    };
+                    redshirt_syscalls_interface::emit_answer(message_id, &response);
+                }
+                // Connections that have never been reported are closed.
+                for accepted in listener.queue {
+                    connections.remove(&accepted.connection_id);
+                }
+            }
+            ffi::WebSocketMessage::Send(send) => match connection_commands(send.connection_id) {
+                Some(commands) => {
+                    let command = connection::Command::Send(msg.message_id, send.message);
+                    let _ = commands.unbounded_send(command);
+                }
+                None => {
+                    if let Some(message_id) = msg.message_id {
+                        let response = ffi::SendResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+            },
+            ffi::WebSocketMessage::Receive(connection_id) => {
+                let message_id = match msg.message_id {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                match connection_commands(connection_id) {
+                    Some(commands) => {
+                        let _ = commands.unbounded_send(connection::Command::Receive(message_id));
+                    }
+                    None => {
+                        let response = ffi::ReceiveResponse {
+                            result: Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                        };
+                        redshirt_syscalls_interface::emit_answer(message_id, &response);
+                    }
+                }
+            }
+            ffi::WebSocketMessage::Close(connection_id) => {
+                if connection_commands(connection_id).is_some() {
+                    connections.remove(&connection_id);
+                }
+            }
+        }
+    }
+}
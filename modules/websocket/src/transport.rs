@@ -0,0 +1,145 @@
+// Copyright (C) 2019  Pierre Krieger
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Stream of bytes over which WebSocket frames are exchanged.
+
+use futures::prelude::*;
+use redshirt_dns_interface::ffi::{RecordData, RecordType};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use redshirt_tcp_interface::{ffi as tcp_ffi, TcpStream};
+use redshirt_tls_interface::TlsStream;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+};
+
+/// TCP connection, optionally encrypted. The TCP socket is closed when destroyed.
+pub enum Transport {
+    /// Identifier of a TCP socket.
+    Tcp(u32),
+    Tls(TlsStream),
+}
+
+impl Transport {
+    /// Connects to the given host, resolving it through the DNS interface if necessary.
+    pub async fn connect(host: &str, port: u16, tls: bool) -> Result<Transport, ErrorPayload> {
+        let ip = resolve(host).await?;
+        let tcp = TcpStream::connect(&SocketAddr::new(ip, port))
+            .await
+            .map_err(|()| {
+                ErrorPayload::new(ErrorClass::UNAVAILABLE).with_message("connection failed")
+            })?;
+
+        if tls {
+            Ok(Transport::Tls(TlsStream::connect(tcp, host).await?))
+        } else {
+            Ok(Transport::Tcp(tcp.into_raw_handle()))
+        }
+    }
+
+    /// Reads some data. Produces an empty `Vec` if the remote has closed the connection.
+    ///
+    /// The returned future doesn't borrow the transport, so that writing is possible while a
+    /// read is in progress.
+    pub fn read(&mut self) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, ErrorPayload>>>> {
+        match self {
+            Transport::Tcp(socket_id) => {
+                let response = unsafe {
+                    let msg = tcp_ffi::TcpMessage::Read(tcp_ffi::TcpRead {
+                        socket_id: *socket_id,
+                    });
+                    redshirt_syscalls_interface::emit_message_with_response(
+                        &tcp_ffi::INTERFACE,
+                        msg,
+                    )
+                    .unwrap()
+                };
+                Box::pin(response.map(|rep: tcp_ffi::TcpReadResponse| {
+                    rep.result.map_err(|()| {
+                        ErrorPayload::new(ErrorClass::IO).with_message("TCP read failed")
+                    })
+                }))
+            }
+            Transport::Tls(tls) => Box::pin(tls.read()),
+        }
+    }
+
+    /// Writes data.
+    pub fn write(
+        &mut self,
+        data: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), ErrorPayload>>>> {
+        match self {
+            Transport::Tcp(socket_id) => {
+                let response = unsafe {
+                    let msg = tcp_ffi::TcpMessage::Write(tcp_ffi::TcpWrite {
+                        socket_id: *socket_id,
+                        data,
+                    });
+                    redshirt_syscalls_interface::emit_message_with_response(
+                        &tcp_ffi::INTERFACE,
+                        msg,
+                    )
+                    .unwrap()
+                };
+                Box::pin(response.map(|rep: tcp_ffi::TcpWriteResponse| {
+                    rep.result.map_err(|()| {
+                        ErrorPayload::new(ErrorClass::IO).with_message("TCP write failed")
+                    })
+                }))
+            }
+            Transport::Tls(tls) => Box::pin(tls.write(data)),
+        }
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        if let Transport::Tcp(socket_id) = self {
+            unsafe {
+                let msg = tcp_ffi::TcpMessage::Close(tcp_ffi::TcpClose {
+                    socket_id: *socket_id,
+                });
+                let _ = redshirt_syscalls_interface::emit_message_without_response(
+                    &tcp_ffi::INTERFACE,
+                    msg,
+                );
+            }
+        }
+    }
+}
+
+/// Turns a host into an IP address, asking the DNS interface if necessary.
+async fn resolve(host: &str) -> Result<IpAddr, ErrorPayload> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+
+    for record_type in &[RecordType::A, RecordType::Aaaa] {
+        let records = match redshirt_dns_interface::resolve(host, *record_type).await {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        for record in records {
+            match record.data {
+                RecordData::A(ip) => return Ok(IpAddr::from(Ipv4Addr::from(ip))),
+                RecordData::Aaaa(ip) => return Ok(IpAddr::from(Ipv6Addr::from(ip))),
+                _ => {}
+            }
+        }
+    }
+
+    Err(ErrorPayload::new(ErrorClass::NOT_FOUND).with_message("failed to resolve host"))
+}
@@ -15,15 +15,24 @@
 
 //! Implements the PCI interface.
 //!
+//! The devices found are reported to the device manager. The bus is then periodically scanned
+//! again in order to detect devices that are plugged or unplugged.
+//!
 //! See https://en.wikipedia.org/wiki/PCI_configuration_space
 
 // TODO: support Enhanced Configuration Access Mechanism (ECAM)
+// TODO: use the hot-plug controller interrupts instead of periodically scanning
 
+use futures::prelude::*;
 use parity_scale_codec::DecodeAll;
-use std::{borrow::Cow, convert::TryFrom as _};
+use redshirt_syscalls_interface::{ErrorClass, ErrorPayload};
+use std::{borrow::Cow, convert::TryFrom as _, time::Duration};
 
 include!(concat!(env!("OUT_DIR"), "/build-pci.rs"));
 
+/// Duration between two scans of the bus.
+const RESCAN_PERIOD: Duration = Duration::from_secs(2);
+
 fn main() {
     redshirt_syscalls_interface::block_on(async_main());
 }
@@ -32,21 +41,110 @@ async fn async_main() {
     redshirt_interface_interface::register_interface(redshirt_pci_interface::ffi::INTERFACE)
         .await.unwrap();
 
-    let devices = unsafe {
+    let mut devices = unsafe {
         read_pci_devices().await
     };
+    for device in &devices {
+        redshirt_device_manager_interface::report(device_description(device));
+    }
+
+    let mut rescan_timer = Box::pin(redshirt_time_interface::monotonic_wait(RESCAN_PERIOD));
 
     loop {
-        let msg = match redshirt_syscalls_interface::next_interface_message().await {
-            redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m) => m,
-            redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_) => continue,
+        let event = {
+            let next_message = redshirt_syscalls_interface::next_interface_message();
+            match future::select(next_message, &mut rescan_timer).await {
+                future::Either::Left((event, _)) => Some(event),
+                future::Either::Right(_) => None,
+            }
+        };
+
+        let msg = match event {
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Interface(m)) => m,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::ProcessDestroyed(_)) => continue,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::Shutdown(_)) => continue,
+            Some(redshirt_syscalls_interface::InterfaceOrDestroyed::MessageCancelled(_)) => continue,
+            None => {
+                rescan_timer = Box::pin(redshirt_time_interface::monotonic_wait(RESCAN_PERIOD));
+                unsafe { rescan(0, &mut devices).await; }
+                continue;
+            }
         };
         assert_eq!(msg.interface, redshirt_pci_interface::ffi::INTERFACE);
-        let redshirt_pci_interface::ffi::PciMessage::GetDevicesList =
-            DecodeAll::decode_all(&msg.actual_data).unwrap();       // TODO: don't unwrap
-        redshirt_syscalls_interface::emit_answer(msg.message_id.unwrap(), &redshirt_pci_interface::ffi::GetDevicesListResponse {
-            devices: devices.clone(),
-        });
+        match DecodeAll::decode_all(&msg.actual_data).unwrap() {       // TODO: don't unwrap
+            redshirt_pci_interface::ffi::PciMessage::GetDevicesList => {
+                redshirt_syscalls_interface::emit_answer(msg.message_id.unwrap(), &redshirt_pci_interface::ffi::GetDevicesListResponse {
+                    devices: devices.clone(),
+                });
+            }
+            redshirt_pci_interface::ffi::PciMessage::ReadConfigU32 { location, offset } => {
+                // TODO: check that the location and offset are valid instead of panicking
+                let value = unsafe {
+                    pci_cfg_read_u32(location.bus, location.device, location.function, offset).await
+                };
+                redshirt_syscalls_interface::emit_answer(msg.message_id.unwrap(), &value);
+            }
+            redshirt_pci_interface::ffi::PciMessage::WriteConfigU32 { location, offset, value } => {
+                // TODO: check that the location and offset are valid instead of panicking
+                unsafe {
+                    pci_cfg_write_u32(location.bus, location.device, location.function, offset, value);
+                }
+            }
+            redshirt_pci_interface::ffi::PciMessage::MapBar { location, bar } => {
+                let bar = devices.iter()
+                    .find(|d| d.location == location)
+                    .and_then(|d| d.base_address_registers.get(usize::from(bar)))
+                    .and_then(|bar| bar.clone());
+
+                let result = match bar {
+                    Some(bar) => {
+                        let command_bit = match bar {
+                            redshirt_pci_interface::PciBaseAddressRegister::Io { .. } => 0x1,
+                            redshirt_pci_interface::PciBaseAddressRegister::Memory { .. } => 0x2,
+                        };
+                        unsafe { pci_set_command_bits(location, command_bit).await; }
+                        // Sandbox the driver to the hardware of its device.
+                        let range = match bar {
+                            redshirt_pci_interface::PciBaseAddressRegister::Io {
+                                base_address,
+                                size,
+                            } => redshirt_hardware_interface::ffi::AccessRange::Ports {
+                                start: base_address,
+                                len: size,
+                            },
+                            redshirt_pci_interface::PciBaseAddressRegister::Memory {
+                                base_address,
+                                size,
+                                ..
+                            } => redshirt_hardware_interface::ffi::AccessRange::Memory {
+                                start: base_address,
+                                len: size,
+                            },
+                        };
+                        // The driver also needs the interrupts of its device.
+                        let interrupts = redshirt_hardware_interface::ffi::AccessRange::Interrupts;
+                        match redshirt_hardware_interface::grant(msg.emitter_pid, range).await {
+                            Ok(()) => redshirt_hardware_interface::grant(msg.emitter_pid, interrupts)
+                                .await
+                                .map(|()| bar),
+                            Err(err) => Err(err),
+                        }
+                    }
+                    None => Err(ErrorPayload::new(ErrorClass::NOT_FOUND)),
+                };
+
+                if let Some(message_id) = msg.message_id {
+                    redshirt_syscalls_interface::emit_answer(message_id, &redshirt_pci_interface::ffi::MapBarResponse {
+                        result,
+                    });
+                }
+            }
+            redshirt_pci_interface::ffi::PciMessage::EnableBusMastering { location } => {
+                if devices.iter().any(|d| d.location == location) {
+                    unsafe { pci_set_command_bits(location, 0x4).await; }
+                }
+            }
+        }
     }
 }
 
@@ -65,65 +163,210 @@ async unsafe fn read_bus_pci_devices(bus_idx: u8) -> Vec<redshirt_pci_interface:
 
     for device_idx in 0 .. 32 {
         for func_idx in 0 .. 8 {    // TODO: check function 0 only first
-            let (vendor_id, device_id) = {
-                let vendor_device = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0).await;
-                let vendor_id = u16::try_from(vendor_device & 0xffff).unwrap();
-                let device_id = u16::try_from(vendor_device >> 16).unwrap();
-                (vendor_id, device_id)
+            if let Some(device) = read_pci_device(bus_idx, device_idx, func_idx).await {
+                out.push(device);
+            }
+
+            // TODO: wrong; need to enumerate other PCI buses
+        }
+    }
+
+    out
+}
+
+/// Reads the information about the device at the given location, if any.
+async unsafe fn read_pci_device(bus_idx: u8, device_idx: u8, func_idx: u8) -> Option<redshirt_pci_interface::PciDeviceInfo> {
+    let (vendor_id, device_id) = read_vendor_device_ids(bus_idx, device_idx, func_idx).await;
+    if vendor_id == 0xffff {
+        return None;
+    }
+
+    let (_bist, header_ty, latency, cache_line) = {
+        let val = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0xc).await;
+        let bytes = val.to_be_bytes();
+        (bytes[0], bytes[1], bytes[2], bytes[3])
+    };
+
+    let (vendor_name, device_name) = match PCI_DEVICES.get(&(vendor_id, device_id)) {
+        Some((v, d)) => (Cow::Borrowed(*v), Cow::Borrowed(*d)),
+        None => (
+            Cow::Owned(format!("Unknown <0x{:x}>", vendor_id)),
+            Cow::Owned(format!("Unknown <0x{:x}>", device_id))
+        ),
+    };
+
+    let [class_code, subclass, prog_if, revision_id] = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0x8).await.to_be_bytes();
+
+    // Only the general device header has 6 base address registers. PCI-to-PCI bridges
+    // have 2, and the other header types none.
+    let num_bars = match header_ty & 0x7f {
+        0x0 => 6,
+        0x1 => 2,
+        _ => 0,
+    };
+
+    redshirt_stdout_interface::stdout(format!("PCI device: {} - {}\n", vendor_name, device_name));
+
+    Some(redshirt_pci_interface::PciDeviceInfo {
+        location: redshirt_pci_interface::PciDeviceLocation {
+            bus: bus_idx,
+            device: device_idx,
+            function: func_idx,
+        },
+        vendor_id,
+        device_id,
+        class_code,
+        subclass,
+        prog_if,
+        revision_id,
+        base_address_registers: read_bars(bus_idx, device_idx, func_idx, num_bars).await,
+    })
+}
+
+async unsafe fn read_vendor_device_ids(bus_idx: u8, device_idx: u8, func_idx: u8) -> (u16, u16) {
+    let vendor_device = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0).await;
+    let vendor_id = u16::try_from(vendor_device & 0xffff).unwrap();
+    let device_id = u16::try_from(vendor_device >> 16).unwrap();
+    (vendor_id, device_id)
+}
+
+/// Scans the bus again, updates `devices`, and reports the devices that have been plugged or
+/// unplugged to the device manager.
+///
+/// Only the vendor and device IDs of the devices already known are read, as sizing their base
+/// address registers would disrupt their drivers.
+async unsafe fn rescan(bus_idx: u8, devices: &mut Vec<redshirt_pci_interface::PciDeviceInfo>) {
+    for device_idx in 0 .. 32 {
+        for func_idx in 0 .. 8 {
+            let location = redshirt_pci_interface::PciDeviceLocation {
+                bus: bus_idx,
+                device: device_idx,
+                function: func_idx,
             };
 
-            if vendor_id == 0xffff {
-                continue;
+            let ids = read_vendor_device_ids(bus_idx, device_idx, func_idx).await;
+            let known = devices.iter().position(|d| d.location == location);
+
+            // A device whose IDs have changed has been swapped for a different one.
+            if let Some(known) = known {
+                if (devices[known].vendor_id, devices[known].device_id) == ids {
+                    continue;
+                }
+                let removed = devices.remove(known);
+                redshirt_device_manager_interface::unreport(
+                    redshirt_device_manager_interface::Bus::Pci,
+                    device_description(&removed).location
+                );
             }
 
-            let (_bist, header_ty, latency, cache_line) = {
-                let val = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0xc).await;
-                let bytes = val.to_be_bytes();
-                (bytes[0], bytes[1], bytes[2], bytes[3])
-            };
+            if let Some(device) = read_pci_device(bus_idx, device_idx, func_idx).await {
+                redshirt_device_manager_interface::report(device_description(&device));
+                devices.push(device);
+            }
+        }
+    }
+}
+
+/// Builds the description of a device passed to the device manager.
+fn device_description(device: &redshirt_pci_interface::PciDeviceInfo) -> redshirt_device_manager_interface::DeviceDescription {
+    redshirt_device_manager_interface::DeviceDescription {
+        bus: redshirt_device_manager_interface::Bus::Pci,
+        location: format!("{:02x}:{:02x}.{}", device.location.bus, device.location.device, device.location.function),
+        vendor_id: device.vendor_id,
+        product_id: device.device_id,
+        class: device.class_code,
+        subclass: device.subclass,
+        protocol: device.prog_if,
+    }
+}
 
-            let (vendor_name, device_name) = match PCI_DEVICES.get(&(vendor_id, device_id)) {
-                Some((v, d)) => (Cow::Borrowed(*v), Cow::Borrowed(*d)),
-                None => (
-                    Cow::Owned(format!("Unknown <0x{:x}>", vendor_id)),
-                    Cow::Owned(format!("Unknown <0x{:x}>", device_id))
-                ),
+/// Reads the base address registers of a device and determines their sizes.
+async unsafe fn read_bars(bus: u8, slot: u8, func: u8, num_bars: u8) -> Vec<Option<redshirt_pci_interface::PciBaseAddressRegister>> {
+    let mut list = Vec::with_capacity(6);
+
+    // Decoding must be disabled while we write to the registers in order to determine the
+    // sizes, otherwise the device might respond to accesses to random addresses.
+    let command = pci_cfg_read_u32(bus, slot, func, 0x4).await;
+    pci_cfg_write_u32(bus, slot, func, 0x4, command & 0xfffc);
+
+    let mut bar_n = 0;
+    while bar_n < 6 {
+        if bar_n >= num_bars {
+            list.push(None);
+            bar_n += 1;
+            continue;
+        }
+
+        let offset = 0x10 + bar_n * 0x4;
+        let bar = pci_cfg_read_u32(bus, slot, func, offset).await;
+        let mask = pci_cfg_size_mask(bus, slot, func, offset, bar).await;
+
+        if (bar & 0x1) == 0 {
+            let prefetchable = (bar & (1 << 3)) != 0;
+            let is_64bits = ((bar >> 1) & 0b11) == 0b10 && bar_n + 1 < num_bars;
+
+            let (base_address, size_mask) = if is_64bits {
+                let high = pci_cfg_read_u32(bus, slot, func, offset + 4).await;
+                let high_mask = pci_cfg_size_mask(bus, slot, func, offset + 4, high).await;
+                (
+                    u64::from(bar & !0b1111) | (u64::from(high) << 32),
+                    u64::from(mask & !0b1111) | (u64::from(high_mask) << 32),
+                )
+            } else {
+                (u64::from(bar & !0b1111), u64::from(mask & !0b1111) | 0xffffffff_00000000)
             };
 
-            let class_code = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0x8).await;
-
-            out.push(redshirt_pci_interface::PciDeviceInfo {
-                vendor_id,
-                device_id,
-                base_address_registers: {
-                    let mut list = Vec::with_capacity(6);
-                    for bar_n in 0..6 {
-                        let bar = pci_cfg_read_u32(bus_idx, device_idx, func_idx, 0x10 + bar_n * 0x4).await;
-                        list.push(if (bar & 0x1) == 0 {
-                            let prefetchable = (bar & (1 << 3)) != 0;
-                            let base_address = bar & !0b1111;
-                            redshirt_pci_interface::PciBaseAddressRegister::Memory {
-                                base_address,
-                                prefetchable,
-                            }
-                        } else {
-                            let base_address = bar & !0b11;
-                            redshirt_pci_interface::PciBaseAddressRegister::Io {
-                                base_address,
-                            }
-                        });
-                    }
-                    list
-                },
-            });
+            if (is_64bits && size_mask == 0) || (!is_64bits && (mask & !0b1111) == 0) {
+                list.push(None);
+            } else {
+                list.push(Some(redshirt_pci_interface::PciBaseAddressRegister::Memory {
+                    base_address,
+                    size: (!size_mask).wrapping_add(1),
+                    prefetchable,
+                }));
+            }
 
-            redshirt_stdout_interface::stdout(format!("PCI device: {} - {}\n", vendor_name, device_name));
+            if is_64bits {
+                list.push(None);
+                bar_n += 2;
+            } else {
+                bar_n += 1;
+            }
 
-            // TODO: wrong; need to enumerate other PCI buses
+        } else {
+            if (mask & !0b11) == 0 {
+                list.push(None);
+            } else {
+                // The upper 16 bits aren't necessarily implemented.
+                list.push(Some(redshirt_pci_interface::PciBaseAddressRegister::Io {
+                    base_address: bar & !0b11,
+                    size: (!((mask & !0b11) | 0xffff0000)).wrapping_add(1),
+                }));
+            }
+            bar_n += 1;
         }
     }
 
-    out
+    pci_cfg_write_u32(bus, slot, func, 0x4, command & 0xffff);
+    list
+}
+
+/// Writes all ones to the base address register at the given offset, reads back the value,
+/// then restores the register to `original`.
+async unsafe fn pci_cfg_size_mask(bus: u8, slot: u8, func: u8, offset: u8, original: u32) -> u32 {
+    pci_cfg_write_u32(bus, slot, func, offset, 0xffffffff);
+    let mask = pci_cfg_read_u32(bus, slot, func, offset).await;
+    pci_cfg_write_u32(bus, slot, func, offset, original);
+    mask
+}
+
+/// Sets bits in the command register of a device. The status register, which shares the same
+/// `u32`, is left untouched.
+async unsafe fn pci_set_command_bits(location: redshirt_pci_interface::PciDeviceLocation, bits: u16) {
+    let value = pci_cfg_read_u32(location.bus, location.device, location.function, 0x4).await;
+    // Bits of the status register are cleared by writing 1, so we write 0s.
+    let new_value = (value & 0xffff) | u32::from(bits);
+    pci_cfg_write_u32(location.bus, location.device, location.function, 0x4, new_value);
 }
 
 // TODO: ensure endianess? PCI is always little endian, but what if we're on a BE platform?
@@ -148,3 +391,20 @@ async unsafe fn pci_cfg_read_u32(bus: u8, slot: u8, func: u8, offset: u8) -> u32
     operations_builder.send().await;
     out
 }
+
+unsafe fn pci_cfg_write_u32(bus: u8, slot: u8, func: u8, offset: u8, value: u32) {
+    assert!(slot < 32);
+    assert!(func < 8);
+    assert_eq!(offset & 3, 0);
+
+    let addr: u32 = 0x80000000 |
+        (u32::from(bus) << 16) |
+        (u32::from(slot) << 11) |
+        (u32::from(func) << 8) |
+        u32::from(offset);
+
+    let mut operations_builder = redshirt_hardware_interface::HardwareWriteOperationsBuilder::new();
+    operations_builder.port_write_u32(0xcf8, addr);
+    operations_builder.port_write_u32(0xcfc, value);
+    operations_builder.send();
+}